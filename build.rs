@@ -0,0 +1,79 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// Static assets that get content-hashed URLs: (const prefix, file name, stem, extension)
+const ASSETS: &[(&str, &str, &str, &str)] = &[
+    ("APP_JS", "app.js", "app", "js"),
+    ("STYLES_CSS", "styles.css", "styles", "css"),
+    ("LOGS_JS", "logs.js", "logs", "js"),
+    ("LOGS_CSS", "logs.css", "logs", "css"),
+    ("MAPPINGS_JS", "mappings.js", "mappings", "js"),
+];
+
+/// HTML pages only contribute to the overall UI version
+const PAGES: &[&str] = &["index.html", "logs.html", "mappings.html"];
+
+/// FNV-1a 64-bit; stable across compiler versions unlike DefaultHasher
+fn fnv1a(data: &[u8], mut hash: u64) -> u64 {
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+fn main() {
+    let static_dir = Path::new("src/static");
+    println!("cargo:rerun-if-changed=src/static");
+
+    let mut generated = String::new();
+    let mut ui_hash = FNV_OFFSET;
+
+    for (name, file, stem, ext) in ASSETS {
+        let content = fs::read(static_dir.join(file)).expect("failed to read static asset");
+        let hash = format!("{:016x}", fnv1a(&content, FNV_OFFSET));
+        ui_hash = fnv1a(&content, ui_hash);
+
+        generated.push_str(&format!(
+            "pub const {}_PATH: &str = \"/{}.{}.{}\";\n",
+            name, stem, &hash[..12], ext
+        ));
+    }
+
+    for file in PAGES {
+        let content = fs::read(static_dir.join(file)).expect("failed to read static page");
+        ui_hash = fnv1a(&content, ui_hash);
+    }
+
+    generated.push_str(&format!(
+        "pub const UI_VERSION: &str = \"{:016x}\";\n",
+        ui_hash
+    ));
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("asset_hashes.rs");
+    fs::write(out, generated).expect("failed to write asset_hashes.rs");
+
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+/// gRPC service; protoc is vendored so builds don't need it installed, but an
+/// installed one named by PROTOC wins, for hosts without a vendored binary
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    println!("cargo:rerun-if-changed=proto/dhcpmon.proto");
+    println!("cargo:rerun-if-env-changed=PROTOC");
+    if env::var_os("PROTOC").is_none() {
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect(
+            "no vendored protoc for this platform; set PROTOC to an installed protoc or build with --no-default-features",
+        );
+        env::set_var("PROTOC", protoc);
+    }
+    tonic_build::configure()
+        .build_client(false)
+        .compile_protos(&["proto/dhcpmon.proto"], &["proto"])
+        .expect("failed to compile proto/dhcpmon.proto");
+}
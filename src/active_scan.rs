@@ -0,0 +1,237 @@
+//! Active DHCP server discovery, off unless `[active_scan]` enables it.
+//!
+//! Passive capture only sees the servers clients happen to talk to. In active
+//! mode the monitor periodically broadcasts a DISCOVER or INFORM of its own and
+//! records every server that answers together with the options it hands out, so
+//! an audit finds all servers on the segment, including ones nobody configured.
+
+use crate::alerts::Severity;
+use crate::dhcp::{DhcpOption, DhcpPacket, DhcpPacketBuilder};
+use crate::mac::Mac;
+use crate::web::state::AppState;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tracing::{debug, info, warn};
+
+const CLIENT_PORT: u16 = 68;
+const SERVER_PORT: u16 = 67;
+
+/// Options asked for in probes, so servers show as much of their configuration as they will
+const PARAMETER_REQUEST_LIST: &[u8] = &[1, 3, 6, 12, 15, 28, 42, 43, 44, 51, 54, 58, 59, 66, 67, 119, 121, 150, 252];
+
+/// Which message the monitor sends
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProbeKind {
+    /// Every server with a free address answers with an OFFER, and holds that
+    /// address until the offer times out
+    Discover,
+    /// Servers answer with an ACK carrying configuration only; nothing is reserved,
+    /// but servers that ignore INFORM stay invisible
+    Inform,
+}
+
+impl ProbeKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "discover" => Some(Self::Discover),
+            "inform" => Some(Self::Inform),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Discover => "discover",
+            Self::Inform => "inform",
+        }
+    }
+
+    fn message_type(&self) -> u8 {
+        match self {
+            Self::Discover => 1,
+            Self::Inform => 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ActiveScanConfig {
+    pub probe: ProbeKind,
+    pub interval: Duration,
+    /// How long answers are collected after each probe
+    pub wait: Duration,
+    /// chaddr of the probes; the capture filter drops them so they aren't logged as clients
+    pub client_mac: Mac,
+    /// ciaddr of INFORM probes, the monitor's own address
+    pub client_ip: Ipv4Addr,
+    /// Send on this interface only
+    pub interface: Option<String>,
+    /// When non-empty, any other server that answers raises a rogue_server alert
+    pub expected_servers: Vec<Ipv4Addr>,
+}
+
+/// A server's answer to a probe
+#[derive(Debug, Clone)]
+pub struct ServerAnswer {
+    /// Option 54, or the packet's source when the server leaves it out
+    pub server_id: Ipv4Addr,
+    pub source_ip: Ipv4Addr,
+    /// "OFFER", "ACK" or "NAK"
+    pub message_type: &'static str,
+    /// yiaddr of an OFFER
+    pub offered_ip: Option<Ipv4Addr>,
+    pub options: Vec<DhcpOption>,
+}
+
+/// A BOOTREQUEST asking every server on the segment to answer
+pub fn build_probe(kind: ProbeKind, xid: u32, mac: Mac, client_ip: Ipv4Addr) -> Vec<u8> {
+    let mut client_id = vec![1];
+    client_id.extend_from_slice(&mac.octets());
+    let builder = DhcpPacketBuilder::message(kind.message_type()).mac(mac.octets()).xid(xid);
+    let builder = match kind {
+        // Without an address the answer has to be broadcast back
+        ProbeKind::Discover => builder.broadcast(),
+        ProbeKind::Inform => builder.ciaddr(client_ip),
+    };
+    builder.option(61, client_id).option(55, PARAMETER_REQUEST_LIST).build()
+}
+
+/// The server answer in `data`, if it replies to the probe with `xid`
+pub fn parse_answer(data: &[u8], source: Ipv4Addr, xid: u32) -> Option<ServerAnswer> {
+    let packet = DhcpPacket::parse(data).ok()?;
+    if packet.op != 2 || packet.xid != xid {
+        return None;
+    }
+    let message_type = match packet.get_message_type()? {
+        2 => "OFFER",
+        5 => "ACK",
+        6 => "NAK",
+        _ => return None,
+    };
+    Some(ServerAnswer {
+        server_id: packet.get_server_id().and_then(|id| id.parse().ok()).unwrap_or(source),
+        source_ip: source,
+        message_type,
+        offered_ip: Some(packet.yiaddr).filter(|ip| !ip.is_unspecified()),
+        options: packet.options,
+    })
+}
+
+fn open_socket(config: &ActiveScanConfig) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    // The host's own DHCP client may hold the port as well
+    socket.set_reuse_address(true)?;
+    socket.set_broadcast(true)?;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if let Some(interface) = &config.interface {
+        socket.bind_device(Some(interface.as_bytes()))?;
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    if config.interface.is_some() {
+        warn!("active_scan interface is only supported on Linux, sending on all interfaces");
+    }
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, CLIENT_PORT)).into())?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// Broadcast one probe and collect answers for `config.wait`, the first from each server
+pub async fn scan(config: &ActiveScanConfig) -> std::io::Result<Vec<ServerAnswer>> {
+    let socket = open_socket(config)?;
+    let xid: u32 = rand::random();
+    let probe = build_probe(config.probe, xid, config.client_mac, config.client_ip);
+    socket.send_to(&probe, (Ipv4Addr::BROADCAST, SERVER_PORT)).await?;
+
+    let mut answers: Vec<ServerAnswer> = Vec::new();
+    let mut buffer = vec![0u8; 4096];
+    let deadline = tokio::time::Instant::now() + config.wait;
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buffer)).await {
+        let (len, source) = received?;
+        let IpAddr::V4(source) = source.ip() else {
+            continue;
+        };
+        if let Some(answer) = parse_answer(&buffer[..len], source, xid) {
+            if !answers.iter().any(|seen| seen.server_id == answer.server_id) {
+                answers.push(answer);
+            }
+        }
+    }
+    Ok(answers)
+}
+
+/// Scan every interval while this node is active, storing answers and alerting on unexpected servers
+pub fn spawn(state: Arc<AppState>) {
+    let Some(config) = state.active_scan.clone() else {
+        return;
+    };
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.interval);
+        loop {
+            interval.tick().await;
+            if !state.is_active() {
+                continue;
+            }
+            match scan(&config).await {
+                Ok(answers) => {
+                    debug!("Active {} scan: {} servers answered", config.probe.as_str(), answers.len());
+                    record(&config, &state, answers).await;
+                }
+                Err(e) => warn!("Active DHCP scan failed: {}", e),
+            }
+        }
+    });
+}
+
+async fn record(config: &ActiveScanConfig, state: &AppState, answers: Vec<ServerAnswer>) {
+    let scanned_at = chrono::Utc::now().to_rfc3339();
+    for answer in answers {
+        let stored = crate::db::dhcp_servers::record_answer(&state.db_pool, config.probe, &answer, &scanned_at).await;
+        match stored {
+            Ok(true) => info!("Active scan found DHCP server {} ({})", answer.server_id, answer.message_type),
+            Ok(false) => {}
+            Err(e) => warn!("Failed to store active scan answer from {}: {}", answer.server_id, e),
+        }
+
+        if !config.expected_servers.is_empty() && !config.expected_servers.contains(&answer.server_id) {
+            let message = format!(
+                "Unexpected DHCP server {} answered an active scan ({} from {})",
+                answer.server_id, answer.message_type, answer.source_ip
+            );
+            state.raise_alert("rogue_server", &answer.server_id.to_string(), Severity::Critical, message).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_and_answer() {
+        let mac: Mac = "02:00:00:00:dc:01".parse().unwrap();
+        let probe = DhcpPacket::parse(&build_probe(ProbeKind::Discover, 0x1234, mac, Ipv4Addr::UNSPECIFIED)).unwrap();
+        assert_eq!((probe.op, probe.xid, probe.flags), (1, 0x1234, 0x8000));
+        assert_eq!(probe.get_message_type(), Some(1));
+        assert_eq!(probe.get_mac_address(), "02:00:00:00:dc:01");
+
+        let inform = DhcpPacket::parse(&build_probe(ProbeKind::Inform, 1, mac, Ipv4Addr::new(10, 0, 0, 2))).unwrap();
+        assert_eq!((inform.get_message_type(), inform.ciaddr), (Some(8), Ipv4Addr::new(10, 0, 0, 2)));
+
+        // An OFFER for the probe, without option 54
+        let mut offer = build_probe(ProbeKind::Discover, 0x1234, mac, Ipv4Addr::UNSPECIFIED);
+        offer[0] = 2;
+        offer[16..20].copy_from_slice(&[10, 0, 0, 50]);
+        offer[242] = 2;
+        let source = Ipv4Addr::new(10, 0, 0, 1);
+        let answer = parse_answer(&offer, source, 0x1234).unwrap();
+        assert_eq!((answer.server_id, answer.message_type), (source, "OFFER"));
+        assert_eq!(answer.offered_ip, Some(Ipv4Addr::new(10, 0, 0, 50)));
+
+        // Other transactions and our own broadcast probe are not answers
+        assert!(parse_answer(&offer, source, 0x9999).is_none());
+        assert!(parse_answer(&build_probe(ProbeKind::Discover, 0x1234, mac, source), source, 0x1234).is_none());
+    }
+}
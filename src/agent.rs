@@ -0,0 +1,299 @@
+//! Multi-site deployments: a lightweight agent at each branch captures DHCP and forwards
+//! the parsed requests over HTTPS to a central instance, which stores them tagged with
+//! the site the agent's token belongs to. Agents keep no database and serve no UI; while
+//! the central instance is unreachable they buffer requests, dropping the oldest.
+
+use crate::dhcp::DhcpRequest;
+use anyhow::{bail, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Where agents post their batches, relative to the central instance's URL
+pub const INGEST_PATH: &str = "/api/ingest";
+/// Most requests accepted in one batch
+pub const MAX_INGEST_BATCH: usize = 1000;
+/// Longest wait between retries while the central instance is unreachable
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// The address a client holds or is being given, and the packet field it came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct AssignedIp {
+    pub ip: String,
+    /// "yiaddr", "ciaddr" or "requested_ip"
+    pub source: String,
+}
+
+/// A request as forwarded by an agent, with the fields DhcpRequest keeps out of its JSON
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct ForwardedRequest {
+    #[serde(flatten)]
+    pub request: DhcpRequest,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assigned_ip: Option<AssignedIp>,
+    /// The UDP payload, base64-encoded, for pcap export
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_packet: Option<String>,
+}
+
+impl ForwardedRequest {
+    pub fn new(request: &DhcpRequest) -> Self {
+        Self {
+            assigned_ip: request.assigned_ip.as_ref().map(|(ip, source)| AssignedIp {
+                ip: ip.clone(),
+                source: source.to_string(),
+            }),
+            raw_packet: request
+                .raw_packet
+                .as_ref()
+                .map(|data| base64::engine::general_purpose::STANDARD.encode(data)),
+            request: request.clone(),
+        }
+    }
+
+    /// The request as captured at `site`; ids and sites claimed by the agent are ignored
+    pub fn into_request(self, site: &str) -> DhcpRequest {
+        let source = |name: &str| -> Option<&'static str> {
+            ["yiaddr", "ciaddr", "requested_ip"].into_iter().find(|known| *known == name)
+        };
+        DhcpRequest {
+            id: None,
+            site_id: Some(site.to_string()),
+            assigned_ip: self
+                .assigned_ip
+                .and_then(|assigned| Some((assigned.ip, source(&assigned.source)?))),
+            raw_packet: self
+                .raw_packet
+                .and_then(|data| base64::engine::general_purpose::STANDARD.decode(data).ok()),
+            ..self.request
+        }
+    }
+}
+
+/// The agents allowed to forward requests: each bearer token belongs to one site
+#[derive(Debug, Default)]
+pub struct AgentRegistry {
+    /// (site, token)
+    agents: Vec<(String, String)>,
+}
+
+impl AgentRegistry {
+    pub fn new(agents: Vec<(String, String)>) -> Self {
+        Self { agents }
+    }
+
+    pub fn len(&self) -> usize {
+        self.agents.len()
+    }
+
+    /// The site `token` belongs to; every token is compared, in constant time
+    pub fn authenticate(&self, token: &str) -> Option<&str> {
+        self.agents
+            .iter()
+            .fold(None, |found, (site, expected)| {
+                if constant_time_eq(token.as_bytes(), expected.as_bytes()) {
+                    Some(site.as_str())
+                } else {
+                    found
+                }
+            })
+    }
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[derive(Debug, Clone)]
+pub struct AgentConfig {
+    /// Base URL of the central instance, e.g. "https://dhcpmon.example.com"
+    pub central_url: String,
+    pub token: String,
+    pub batch_size: usize,
+    /// Send what is buffered at least this often
+    pub flush_interval: Duration,
+    /// Requests held while the central instance is unreachable
+    pub max_buffered: usize,
+    pub timeout: Duration,
+}
+
+/// Buffers requests and posts them to the central instance in batches
+pub struct Forwarder {
+    config: AgentConfig,
+    client: reqwest::Client,
+    queue: Mutex<VecDeque<ForwardedRequest>>,
+    ready: Notify,
+    dropped: AtomicU64,
+}
+
+impl Forwarder {
+    pub fn new(config: AgentConfig) -> Result<Self> {
+        let client = reqwest::Client::builder().timeout(config.timeout).build()?;
+        Ok(Self {
+            config,
+            client,
+            queue: Mutex::new(VecDeque::new()),
+            ready: Notify::new(),
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    /// Queue a request for the next batch
+    pub fn push(&self, request: &DhcpRequest) {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() >= self.config.max_buffered {
+            queue.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        queue.push_back(ForwardedRequest::new(request));
+        if queue.len() >= self.config.batch_size {
+            self.ready.notify_one();
+        }
+    }
+
+    fn take_batch(&self) -> Vec<ForwardedRequest> {
+        let mut queue = self.queue.lock().unwrap();
+        let len = queue.len().min(self.config.batch_size);
+        queue.drain(..len).collect()
+    }
+
+    // Put a batch that failed to send back in front, dropping its oldest requests if
+    // newer ones have since filled the buffer
+    fn requeue(&self, batch: Vec<ForwardedRequest>) {
+        let mut queue = self.queue.lock().unwrap();
+        for request in batch.into_iter().rev() {
+            if queue.len() >= self.config.max_buffered {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            } else {
+                queue.push_front(request);
+            }
+        }
+    }
+
+    async fn send(&self, batch: &[ForwardedRequest]) -> Result<()> {
+        let url = format!("{}{}", self.config.central_url.trim_end_matches('/'), INGEST_PATH);
+        let response = self
+            .client
+            .post(&url)
+            .bearer_auth(&self.config.token)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(batch)?)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            bail!("{} returned {}", url, response.status());
+        }
+        Ok(())
+    }
+
+    // Send everything buffered, stopping at the first batch that fails
+    async fn flush(&self) -> Result<()> {
+        loop {
+            let batch = self.take_batch();
+            if batch.is_empty() {
+                return Ok(());
+            }
+            if let Err(e) = self.send(&batch).await {
+                self.requeue(batch);
+                return Err(e);
+            }
+            tracing::debug!("Forwarded {} requests", batch.len());
+            let dropped = self.dropped.swap(0, Ordering::Relaxed);
+            if dropped > 0 {
+                tracing::warn!("Dropped {} requests while the central instance was unreachable", dropped);
+            }
+        }
+    }
+
+    /// Flush batches in the background, backing off while the central instance is unreachable
+    pub fn spawn(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut backoff: Option<Duration> = None;
+            loop {
+                match backoff {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => {
+                        let _ = tokio::time::timeout(self.config.flush_interval, self.ready.notified()).await;
+                    }
+                }
+                match self.flush().await {
+                    Ok(()) => backoff = None,
+                    Err(e) => {
+                        let delay = backoff.map_or(Duration::from_secs(1), |delay| (delay * 2).min(MAX_BACKOFF));
+                        tracing::warn!("Failed to forward requests, retrying in {}s: {}", delay.as_secs(), e);
+                        backoff = Some(delay);
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forwarded_request_round_trip() {
+        let request = DhcpRequest {
+            id: Some(7),
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            message_type: "REQUEST".to_string(),
+            site_id: Some("spoofed".to_string()),
+            assigned_ip: Some(("10.0.0.5".to_string(), "ciaddr")),
+            raw_packet: Some(vec![1, 2, 3]),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&ForwardedRequest::new(&request)).unwrap();
+        let received: ForwardedRequest = serde_json::from_str(&json).unwrap();
+        let stored = received.into_request("branch-1");
+
+        assert_eq!(stored.id, None);
+        assert_eq!(stored.mac_address, "aa:bb:cc:dd:ee:ff");
+        assert_eq!(stored.site_id.as_deref(), Some("branch-1"));
+        assert_eq!(stored.assigned_ip, Some(("10.0.0.5".to_string(), "ciaddr")));
+        assert_eq!(stored.raw_packet, Some(vec![1, 2, 3]));
+
+        let registry = AgentRegistry::new(vec![
+            ("branch-1".to_string(), "secret-1".to_string()),
+            ("branch-2".to_string(), "secret-2".to_string()),
+        ]);
+        assert_eq!(registry.authenticate("secret-2"), Some("branch-2"));
+        assert_eq!(registry.authenticate("secret"), None);
+    }
+
+    #[test]
+    fn test_buffer_drops_oldest() {
+        let forwarder = Forwarder::new(AgentConfig {
+            central_url: "https://central".to_string(),
+            token: "secret".to_string(),
+            batch_size: 2,
+            flush_interval: Duration::from_secs(5),
+            max_buffered: 3,
+            timeout: Duration::from_secs(5),
+        })
+        .unwrap();
+        let request = |xid: &str| DhcpRequest {
+            xid: xid.to_string(),
+            ..Default::default()
+        };
+        let xids = |batch: &[ForwardedRequest]| batch.iter().map(|r| r.request.xid.clone()).collect::<Vec<_>>();
+
+        for xid in ["1", "2", "3", "4"] {
+            forwarder.push(&request(xid));
+        }
+        let batch = forwarder.take_batch();
+        assert_eq!(xids(&batch), ["2", "3"]);
+
+        // The failed batch goes back in front of what arrived meanwhile, minus what no longer fits
+        forwarder.push(&request("5"));
+        forwarder.requeue(batch);
+        assert_eq!(xids(&forwarder.take_batch()), ["3", "4"]);
+        assert_eq!(xids(&forwarder.take_batch()), ["5"]);
+        assert_eq!(forwarder.dropped.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,280 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// Alert severity levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "info" => Some(Severity::Info),
+            "warning" => Some(Severity::Warning),
+            "critical" => Some(Severity::Critical),
+            _ => None,
+        }
+    }
+}
+
+/// An alert as delivered to notification channels
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub alert_type: String,
+    /// Deduplication key within the alert type (e.g. a MAC or server IP)
+    pub key: String,
+    pub severity: Severity,
+    pub message: String,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// Times the condition was seen while this alert was open
+    pub occurrences: u64,
+    /// The condition keeps clearing and re-triggering
+    pub flapping: bool,
+}
+
+/// Tuning for alert deduplication and flap suppression
+#[derive(Debug, Clone)]
+pub struct AlertConfig {
+    /// Minimum time between repeat notifications for the same open alert
+    pub cooldown_secs: u64,
+    /// Window in which re-triggers are counted towards flapping
+    pub flap_window_secs: u64,
+    /// Re-triggers within the window that mark an alert as flapping
+    pub flap_threshold: usize,
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        Self {
+            cooldown_secs: 900,
+            flap_window_secs: 3600,
+            flap_threshold: 3,
+        }
+    }
+}
+
+struct AlertState {
+    alert: Alert,
+    open: bool,
+    last_notified: DateTime<Utc>,
+    reopened_at: VecDeque<DateTime<Utc>>,
+}
+
+/// Collapses repeated alert conditions into a single open alert per dedup key
+pub struct AlertManager {
+    config: AlertConfig,
+    states: Mutex<HashMap<(String, String), AlertState>>,
+}
+
+impl AlertManager {
+    pub fn new(config: AlertConfig) -> Self {
+        Self {
+            config,
+            states: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record an occurrence of a condition. Returns the alert when channels should
+    /// be notified, or None when the occurrence was folded into an open alert.
+    pub fn raise(&self, alert_type: &str, key: &str, severity: Severity, message: String) -> Option<Alert> {
+        self.raise_at(alert_type, key, severity, message, Utc::now())
+    }
+
+    fn raise_at(
+        &self,
+        alert_type: &str,
+        key: &str,
+        severity: Severity,
+        message: String,
+        now: DateTime<Utc>,
+    ) -> Option<Alert> {
+        let cooldown = Duration::seconds(self.config.cooldown_secs as i64);
+        let flap_window = Duration::seconds(self.config.flap_window_secs as i64);
+
+        let mut states = self.states.lock().unwrap();
+        let state = match states.entry((alert_type.to_string(), key.to_string())) {
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                let alert = Alert {
+                    alert_type: alert_type.to_string(),
+                    key: key.to_string(),
+                    severity,
+                    message,
+                    first_seen: now,
+                    last_seen: now,
+                    occurrences: 1,
+                    flapping: false,
+                };
+                entry.insert(AlertState {
+                    alert: alert.clone(),
+                    open: true,
+                    last_notified: now,
+                    reopened_at: VecDeque::new(),
+                });
+                return Some(alert);
+            }
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+        };
+
+        state.alert.last_seen = now;
+        state.alert.message = message;
+        state.alert.severity = state.alert.severity.max(severity);
+
+        let notify = if state.open {
+            state.alert.occurrences += 1;
+            now - state.last_notified >= cooldown
+        } else {
+            // Condition came back after being resolved
+            state.open = true;
+            state.reopened_at.push_back(now);
+            while state.reopened_at.front().is_some_and(|t| now - *t > flap_window) {
+                state.reopened_at.pop_front();
+            }
+            state.alert.flapping = state.reopened_at.len() >= self.config.flap_threshold;
+
+            if state.alert.flapping {
+                // Keep counting on the existing alert; only remind once per cooldown
+                state.alert.occurrences += 1;
+                now - state.last_notified >= cooldown
+            } else {
+                state.alert.first_seen = now;
+                state.alert.occurrences = 1;
+                true
+            }
+        };
+
+        if notify {
+            state.last_notified = now;
+            Some(state.alert.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Mark a condition as cleared; the next raise starts a new episode
+    pub fn resolve(&self, alert_type: &str, key: &str) {
+        let mut states = self.states.lock().unwrap();
+        if let Some(state) = states.get_mut(&(alert_type.to_string(), key.to_string())) {
+            state.open = false;
+        }
+    }
+
+    /// Currently open alerts, most severe first
+    #[cfg(test)]
+    pub fn open_alerts(&self) -> Vec<Alert> {
+        let states = self.states.lock().unwrap();
+        let mut alerts: Vec<Alert> = states
+            .values()
+            .filter(|state| state.open)
+            .map(|state| state.alert.clone())
+            .collect();
+        alerts.sort_by(|a, b| b.severity.cmp(&a.severity).then(b.last_seen.cmp(&a.last_seen)));
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager() -> AlertManager {
+        AlertManager::new(AlertConfig {
+            cooldown_secs: 60,
+            flap_window_secs: 600,
+            flap_threshold: 3,
+        })
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn test_repeats_are_deduplicated_within_cooldown() {
+        let m = manager();
+        assert!(m.raise_at("rogue_server", "10.0.0.9", Severity::Critical, "x".into(), at(0)).is_some());
+        for i in 1..50 {
+            assert!(m.raise_at("rogue_server", "10.0.0.9", Severity::Critical, "x".into(), at(i)).is_none());
+        }
+
+        // After the cool-down a reminder carries the occurrence count
+        let reminder = m.raise_at("rogue_server", "10.0.0.9", Severity::Critical, "x".into(), at(61)).unwrap();
+        assert_eq!(reminder.occurrences, 51);
+        assert_eq!(reminder.first_seen, at(0));
+    }
+
+    #[test]
+    fn test_keys_are_independent() {
+        let m = manager();
+        assert!(m.raise_at("rogue_server", "10.0.0.9", Severity::Critical, "x".into(), at(0)).is_some());
+        assert!(m.raise_at("rogue_server", "10.0.0.10", Severity::Critical, "x".into(), at(1)).is_some());
+        assert!(m.raise_at("nak_flood", "10.0.0.9", Severity::Warning, "x".into(), at(2)).is_some());
+        assert_eq!(m.open_alerts().len(), 3);
+    }
+
+    #[test]
+    fn test_resolved_alert_notifies_again() {
+        let m = manager();
+        m.raise_at("db", "write", Severity::Critical, "x".into(), at(0));
+        m.resolve("db", "write");
+        assert!(m.open_alerts().is_empty());
+
+        let alert = m.raise_at("db", "write", Severity::Critical, "x".into(), at(5)).unwrap();
+        assert_eq!(alert.occurrences, 1);
+        assert!(!alert.flapping);
+    }
+
+    #[test]
+    fn test_flapping_is_suppressed() {
+        let m = manager();
+        m.raise_at("db", "write", Severity::Critical, "x".into(), at(0));
+
+        let mut notifications = 0;
+        for i in 1..=20 {
+            m.resolve("db", "write");
+            if m.raise_at("db", "write", Severity::Critical, "x".into(), at(i)).is_some() {
+                notifications += 1;
+            }
+        }
+        // Two re-opens notify before the flap threshold is reached, then silence
+        assert_eq!(notifications, 2);
+
+        let alert = &m.open_alerts()[0];
+        assert!(alert.flapping);
+        assert_eq!(alert.occurrences, 19);
+
+        // A flapping alert still reminds once per cool-down
+        m.resolve("db", "write");
+        let reminder = m.raise_at("db", "write", Severity::Critical, "x".into(), at(100)).unwrap();
+        assert!(reminder.flapping);
+    }
+
+    #[test]
+    fn test_flapping_clears_after_window() {
+        let m = manager();
+        m.raise_at("db", "write", Severity::Critical, "x".into(), at(0));
+        for i in 1..=3 {
+            m.resolve("db", "write");
+            m.raise_at("db", "write", Severity::Critical, "x".into(), at(i));
+        }
+        assert!(m.open_alerts()[0].flapping);
+
+        m.resolve("db", "write");
+        let alert = m.raise_at("db", "write", Severity::Critical, "x".into(), at(2000)).unwrap();
+        assert!(!alert.flapping);
+    }
+}
@@ -0,0 +1,717 @@
+use crate::alerts::Severity;
+use crate::dhcp::DhcpRequest;
+use crate::mac::Mac;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Upper bound on MACs remembered for first-sighting and fingerprint-change
+/// detection, so a starvation attack with random MACs can't grow the maps without limit
+const MAX_TRACKED_MACS: usize = 100_000;
+
+/// Fingerprints and vendor classes remembered per MAC (dual-boot machines alternate)
+const MAX_IDENTITIES_PER_MAC: usize = 4;
+
+/// Thresholds for the anomaly detectors
+#[derive(Debug, Clone)]
+pub struct AnomalyConfig {
+    /// Previously-unseen MACs sending DISCOVER within the window that indicate starvation
+    pub starvation_macs: usize,
+    pub starvation_window: Duration,
+    /// Lower threshold applied when the new MACs are sequential or locally
+    /// administered, as generated by starvation tools
+    pub starvation_patterned_macs: usize,
+    /// DECLINEs within the window (clients finding their address already in use)
+    pub decline_threshold: usize,
+    pub decline_window: Duration,
+    /// NAKs within the window
+    pub nak_threshold: usize,
+    pub nak_window: Duration,
+    /// Alert when a known MAC presents an option 55 fingerprint or vendor class
+    /// it has not used before
+    pub fingerprint_changes: bool,
+    /// Alert when two MACs hold the same address within the window, or a client
+    /// declines an address as already in use
+    pub ip_conflicts: bool,
+    pub conflict_window: Duration,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            starvation_macs: 100,
+            starvation_window: Duration::from_secs(60),
+            starvation_patterned_macs: 30,
+            decline_threshold: 10,
+            decline_window: Duration::from_secs(300),
+            nak_threshold: 20,
+            nak_window: Duration::from_secs(60),
+            fingerprint_changes: true,
+            ip_conflicts: true,
+            conflict_window: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// A suspicious pattern, keyed for alert deduplication
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anomaly {
+    pub kind: &'static str,
+    pub key: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Outcome of observing one request
+#[derive(Debug, Default, PartialEq)]
+pub struct AnomalyReport {
+    pub raised: Vec<Anomaly>,
+    /// (kind, key) of conditions that have subsided
+    pub cleared: Vec<(&'static str, String)>,
+}
+
+/// Counts events in a sliding window
+struct RateWindow {
+    window: Duration,
+    events: VecDeque<Instant>,
+    active: bool,
+}
+
+impl RateWindow {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            events: VecDeque::new(),
+            active: false,
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while self.events.front().is_some_and(|t| now.duration_since(*t) > self.window) {
+            self.events.pop_front();
+        }
+    }
+
+    fn record(&mut self, now: Instant) -> usize {
+        self.expire(now);
+        self.events.push_back(now);
+        self.events.len()
+    }
+}
+
+/// Sliding window of DISCOVERs from new MACs, counting distinct MACs
+struct DiscoverWindow {
+    window: Duration,
+    events: VecDeque<(Instant, String)>,
+    per_mac: HashMap<String, usize>,
+    /// The MACs in per_mac as numbers, kept sorted so neighbors are cheap to find
+    values: BTreeSet<u64>,
+    /// Counts reported by `patterns`, updated as MACs enter and leave the window
+    patterns: (usize, usize, usize),
+    active: bool,
+}
+
+impl DiscoverWindow {
+    fn expire(&mut self, now: Instant) {
+        while let Some((t, _)) = self.events.front() {
+            if now.duration_since(*t) <= self.window {
+                break;
+            }
+            let (_, mac) = self.events.pop_front().unwrap();
+            if let Some(count) = self.per_mac.get_mut(&mac) {
+                *count -= 1;
+                if *count == 0 {
+                    self.per_mac.remove(&mac);
+                    if let Ok(mac) = mac.parse::<Mac>() {
+                        self.update_value(mac_value(&mac), false);
+                    }
+                }
+            }
+        }
+    }
+
+    fn record(&mut self, now: Instant, mac: &str) -> usize {
+        self.expire(now);
+        self.events.push_back((now, mac.to_string()));
+        let count = self.per_mac.entry(mac.to_string()).or_insert(0);
+        *count += 1;
+        if *count == 1 {
+            if let Ok(mac) = mac.parse::<Mac>() {
+                self.update_value(mac_value(&mac), true);
+            }
+        }
+        self.per_mac.len()
+    }
+
+    fn count(&mut self, now: Instant) -> usize {
+        self.expire(now);
+        self.per_mac.len()
+    }
+
+    /// MACs in the window that look generated: (sequential, locally administered, either)
+    fn patterns(&self) -> (usize, usize, usize) {
+        self.patterns
+    }
+
+    /// What one MAC in the window adds to the pattern counts
+    fn pattern_of(&self, value: u64) -> (usize, usize, usize) {
+        if !self.values.contains(&value) {
+            return (0, 0, 0);
+        }
+        // Part of a run of consecutive addresses
+        let sequential = value.checked_sub(1).is_some_and(|prev| self.values.contains(&prev))
+            || self.values.contains(&(value + 1));
+        // The locally administered bit is set on randomized MACs
+        let random = (value >> 40) & 0x02 != 0;
+        (sequential as usize, random as usize, (sequential || random) as usize)
+    }
+
+    /// Add or remove a MAC; only it and its two neighbors can change pattern
+    fn update_value(&mut self, value: u64, present: bool) {
+        let affected = [value.checked_sub(1), Some(value), Some(value + 1)];
+        for value in affected.into_iter().flatten() {
+            let (seq, rnd, either) = self.pattern_of(value);
+            self.patterns = (self.patterns.0 - seq, self.patterns.1 - rnd, self.patterns.2 - either);
+        }
+        match present {
+            true => self.values.insert(value),
+            false => self.values.remove(&value),
+        };
+        for value in affected.into_iter().flatten() {
+            let (seq, rnd, either) = self.pattern_of(value);
+            self.patterns = (self.patterns.0 + seq, self.patterns.1 + rnd, self.patterns.2 + either);
+        }
+    }
+}
+
+fn mac_value(mac: &Mac) -> u64 {
+    mac.octets().iter().fold(0, |value, octet| (value << 8) | *octet as u64)
+}
+
+/// Fingerprints and vendor classes a MAC has presented, most recent last
+#[derive(Default)]
+struct IdentityHistory {
+    fingerprints: Vec<String>,
+    vendor_classes: Vec<String>,
+}
+
+// Move the value to the end of the list, returning the previous latest if the value is
+// new to a MAC that already had one
+fn remember(values: &mut Vec<String>, value: &str) -> Option<String> {
+    let previous = match values.iter().position(|v| v == value) {
+        Some(i) => {
+            values.remove(i);
+            None
+        }
+        None => values.last().cloned(),
+    };
+    values.push(value.to_string());
+    if values.len() > MAX_IDENTITIES_PER_MAC {
+        values.remove(0);
+    }
+    previous
+}
+
+/// Last device seen holding an address
+struct AddressHolder {
+    mac: String,
+    /// Field the address came from ("yiaddr", "ciaddr" or "requested_ip")
+    source: &'static str,
+    timestamp: String,
+    seen: Instant,
+}
+
+struct DetectorState {
+    discovers: DiscoverWindow,
+    declines: RateWindow,
+    naks: RateWindow,
+    identities: HashMap<String, IdentityHistory>,
+    /// When each MAC was first seen, to tell new clients from returning ones;
+    /// None for MACs already known before this run
+    first_seen: HashMap<String, Option<Instant>>,
+    /// Keyed by IPv4 address
+    holders: HashMap<String, AddressHolder>,
+}
+
+/// Flags starvation, DECLINE/NAK floods and fingerprint changes
+pub struct AnomalyDetector {
+    config: AnomalyConfig,
+    state: Mutex<DetectorState>,
+}
+
+// A window condition clears once the rate falls to half the threshold
+fn window_transition(
+    active: &mut bool,
+    count: usize,
+    threshold: usize,
+    kind: &'static str,
+    report: &mut AnomalyReport,
+    anomaly: impl FnOnce() -> Anomaly,
+) {
+    if count >= threshold {
+        *active = true;
+        report.raised.push(anomaly());
+    } else if *active && count <= threshold / 2 {
+        *active = false;
+        report.cleared.push((kind, "network".to_string()));
+    }
+}
+
+impl AnomalyDetector {
+    pub fn new(config: AnomalyConfig) -> Self {
+        let state = DetectorState {
+            discovers: DiscoverWindow {
+                window: config.starvation_window,
+                events: VecDeque::new(),
+                per_mac: HashMap::new(),
+                values: BTreeSet::new(),
+                patterns: (0, 0, 0),
+                active: false,
+            },
+            declines: RateWindow::new(config.decline_window),
+            naks: RateWindow::new(config.nak_window),
+            identities: HashMap::new(),
+            first_seen: HashMap::new(),
+            holders: HashMap::new(),
+        };
+
+        Self {
+            config,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Remember a fingerprint or vendor class a MAC used before this run, so a
+    /// restart doesn't forget what each device looks like
+    pub fn seed_identity(&self, mac: &str, fingerprint: &str, vendor_class: Option<&str>) {
+        let mut state = self.state.lock().unwrap();
+        if state.identities.len() >= MAX_TRACKED_MACS && !state.identities.contains_key(mac) {
+            return;
+        }
+        let history = state.identities.entry(mac.to_string()).or_default();
+        if !fingerprint.is_empty() {
+            remember(&mut history.fingerprints, fingerprint);
+        }
+        if let Some(vendor_class) = vendor_class.filter(|v| !v.is_empty()) {
+            remember(&mut history.vendor_classes, vendor_class);
+        }
+    }
+
+    /// Remember a MAC seen before this run, so its DISCOVERs never count towards
+    /// starvation after a restart
+    pub fn seed_known(&self, mac: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state.first_seen.len() < MAX_TRACKED_MACS {
+            state.first_seen.insert(mac.to_string(), None);
+        }
+    }
+
+    pub fn observe(&self, request: &DhcpRequest) -> AnomalyReport {
+        self.observe_at(request, Instant::now())
+    }
+
+    fn observe_at(&self, request: &DhcpRequest, now: Instant) -> AnomalyReport {
+        let config = &self.config;
+        let mut state = self.state.lock().unwrap();
+        let mut report = AnomalyReport::default();
+
+        // A MAC counts as new until it has been around for a full starvation window
+        let first_seen = match state.first_seen.get(&request.mac_address) {
+            Some(first_seen) => *first_seen,
+            None => {
+                if state.first_seen.len() < MAX_TRACKED_MACS {
+                    state.first_seen.insert(request.mac_address.clone(), Some(now));
+                }
+                Some(now)
+            }
+        };
+        let unseen = first_seen.is_some_and(|first_seen| now.duration_since(first_seen) <= config.starvation_window);
+
+        match request.message_type.as_str() {
+            "DISCOVER" => {
+                let distinct = match unseen {
+                    true => state.discovers.record(now, &request.mac_address),
+                    false => state.discovers.count(now),
+                };
+                let (sequential, random, patterned) = state.discovers.patterns();
+                let threshold = match patterned >= config.starvation_patterned_macs {
+                    true => config.starvation_patterned_macs,
+                    false => config.starvation_macs,
+                };
+                let discovers = &mut state.discovers;
+                window_transition(&mut discovers.active, distinct, threshold, "dhcp_starvation", &mut report, || Anomaly {
+                    kind: "dhcp_starvation",
+                    key: "network".to_string(),
+                    severity: Severity::Critical,
+                    message: format!(
+                        "Possible DHCP starvation: DISCOVERs from {} previously-unseen MACs in {}s \
+                         ({} sequential, {} locally administered); a rogue DHCP server often follows",
+                        distinct,
+                        config.starvation_window.as_secs(),
+                        sequential,
+                        random
+                    ),
+                });
+            }
+            "DECLINE" => {
+                let count = state.declines.record(now);
+                window_transition(&mut state.declines.active, count, config.decline_threshold, "excessive_declines", &mut report, || Anomaly {
+                    kind: "excessive_declines",
+                    key: "network".to_string(),
+                    severity: Severity::Warning,
+                    message: format!(
+                        "{} DHCPDECLINEs in {}s (address conflicts?), latest from {}",
+                        count,
+                        config.decline_window.as_secs(),
+                        request.mac_address
+                    ),
+                });
+            }
+            "NAK" => {
+                let count = state.naks.record(now);
+                window_transition(&mut state.naks.active, count, config.nak_threshold, "nak_flood", &mut report, || Anomaly {
+                    kind: "nak_flood",
+                    key: "network".to_string(),
+                    severity: Severity::Warning,
+                    message: format!("{} DHCPNAKs in {}s", count, config.nak_window.as_secs()),
+                });
+            }
+            _ => {}
+        }
+
+        if config.ip_conflicts {
+            if let Some(conflict) = Self::check_address(config, &mut state.holders, request, now) {
+                report.raised.push(conflict);
+            }
+        }
+
+        if config.fingerprint_changes {
+            if let Some(changed) = Self::check_identity(&mut state.identities, request) {
+                report.raised.push(changed);
+            }
+        }
+
+        report
+    }
+
+    // Compare the fingerprint and vendor class with everything the MAC presented before;
+    // requests without option 55 or 60 say nothing about a change
+    fn check_identity(identities: &mut HashMap<String, IdentityHistory>, request: &DhcpRequest) -> Option<Anomaly> {
+        let vendor_class = request.vendor_class.as_deref().filter(|v| !v.is_empty());
+        if request.fingerprint.is_empty() && vendor_class.is_none() {
+            return None;
+        }
+        if identities.len() >= MAX_TRACKED_MACS && !identities.contains_key(&request.mac_address) {
+            return None;
+        }
+        let history = identities.entry(request.mac_address.clone()).or_default();
+
+        let mut changes = Vec::new();
+        if !request.fingerprint.is_empty() {
+            if let Some(previous) = remember(&mut history.fingerprints, &request.fingerprint) {
+                changes.push(format!("DHCP fingerprint from {} to {}", previous, request.fingerprint));
+            }
+        }
+        if let Some(vendor_class) = vendor_class {
+            if let Some(previous) = remember(&mut history.vendor_classes, vendor_class) {
+                changes.push(format!("vendor class from \"{}\" to \"{}\"", previous, vendor_class));
+            }
+        }
+        if changes.is_empty() {
+            return None;
+        }
+
+        Some(Anomaly {
+            kind: "fingerprint_changed",
+            key: request.mac_address.clone(),
+            severity: Severity::Warning,
+            message: format!(
+                "{} changed {} (MAC spoofing or OS reinstall?)",
+                request.mac_address,
+                changes.join(" and ")
+            ),
+        })
+    }
+
+    // Track who holds which address and flag a second MAC on it within the window,
+    // or a DECLINE (the client found the address already in use)
+    fn check_address(
+        config: &AnomalyConfig,
+        holders: &mut HashMap<String, AddressHolder>,
+        request: &DhcpRequest,
+        now: Instant,
+    ) -> Option<Anomaly> {
+        let mac = &request.mac_address;
+        let recent = |holder: &&AddressHolder| holder.mac != *mac && now.duration_since(holder.seen) <= config.conflict_window;
+        // Sites are separate networks that may well reuse the same private ranges
+        let key = |ip: &str| match &request.site_id {
+            Some(site) => format!("{}@{}", ip, site),
+            None => ip.to_string(),
+        };
+
+        match request.message_type.as_str() {
+            // Released addresses may be handed to someone else straight away
+            "RELEASE" => {
+                holders.retain(|_, holder| holder.mac != *mac);
+                None
+            }
+            "DECLINE" => {
+                let ip = request.requested_ip.as_ref()?;
+                let key = key(ip);
+                let message = match holders.get(&key).filter(recent) {
+                    Some(holder) => format!(
+                        "Address conflict on {}: {} declined it at {} while {} held it ({}) since {}",
+                        ip, mac, request.timestamp, holder.mac, holder.source, holder.timestamp
+                    ),
+                    None => format!(
+                        "Address conflict on {}: {} declined it at {} as already in use by another host",
+                        ip, mac, request.timestamp
+                    ),
+                };
+                Some(Anomaly {
+                    kind: "ip_conflict",
+                    key,
+                    severity: Severity::Warning,
+                    message,
+                })
+            }
+            _ => {
+                let (ip, source) = request.assigned_ip.as_ref()?;
+                let key = key(ip);
+                let conflict = holders.get(&key).filter(recent).map(|holder| Anomaly {
+                    kind: "ip_conflict",
+                    key: key.clone(),
+                    severity: Severity::Warning,
+                    message: format!(
+                        "Duplicate address {}: {} ({}) at {} and {} ({}) at {}",
+                        ip, holder.mac, holder.source, holder.timestamp, mac, source, request.timestamp
+                    ),
+                });
+                if holders.len() < MAX_TRACKED_MACS || holders.contains_key(&key) {
+                    holders.insert(
+                        key,
+                        AddressHolder {
+                            mac: mac.clone(),
+                            source,
+                            timestamp: request.timestamp.clone(),
+                            seen: now,
+                        },
+                    );
+                }
+                conflict
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(message_type: &str, mac: &str, fingerprint: &str) -> DhcpRequest {
+        DhcpRequest {
+            message_type: message_type.to_string(),
+            mac_address: mac.to_string(),
+            fingerprint: fingerprint.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn detector() -> AnomalyDetector {
+        AnomalyDetector::new(AnomalyConfig {
+            starvation_macs: 10,
+            decline_threshold: 3,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_starvation_from_random_macs() {
+        let d = detector();
+        let start = Instant::now();
+
+        // One chatty client is not starvation
+        for _ in 0..50 {
+            assert!(d.observe_at(&request("DISCOVER", "00:11:22:33:44:55", ""), start).raised.is_empty());
+        }
+
+        // The tenth distinct MAC crosses the threshold
+        for i in 0..8 {
+            let mac = format!("02:00:00:00:00:{:02x}", i);
+            assert!(d.observe_at(&request("DISCOVER", &mac, ""), start).raised.is_empty());
+        }
+        let raised = d.observe_at(&request("DISCOVER", "02:00:00:00:00:ff", ""), start).raised;
+        assert_eq!(raised.len(), 1);
+        assert_eq!(raised[0].kind, "dhcp_starvation");
+        assert_eq!(raised[0].severity, Severity::Critical);
+        assert!(raised[0].message.contains("10 previously-unseen MACs"));
+        assert!(raised[0].message.contains("9 locally administered"));
+
+        // Clears once the window has passed
+        let later = start + Duration::from_secs(120);
+        let report = d.observe_at(&request("DISCOVER", "00:11:22:33:44:55", ""), later);
+        assert_eq!(report.cleared, vec![("dhcp_starvation", "network".to_string())]);
+    }
+
+    #[test]
+    fn test_starvation_ignores_known_macs_and_spots_sequences() {
+        let d = detector();
+        let start = Instant::now();
+        let known: Vec<String> = (0..20).map(|i| format!("00:11:22:33:{:02x}:01", i * 7)).collect();
+        for mac in &known {
+            d.observe_at(&request("REQUEST", mac, ""), start);
+        }
+
+        // Returning clients rediscovering together (e.g. after a switch reboot) are fine
+        let later = start + Duration::from_secs(120);
+        for mac in &known {
+            assert!(d.observe_at(&request("DISCOVER", mac, ""), later).raised.is_empty());
+        }
+
+        // Sequential new MACs trip the lower patterned threshold
+        let d = AnomalyDetector::new(AnomalyConfig {
+            starvation_macs: 100,
+            starvation_patterned_macs: 5,
+            ..Default::default()
+        });
+        for i in 0..4 {
+            let mac = format!("00:0c:29:00:00:{:02x}", i);
+            assert!(d.observe_at(&request("DISCOVER", &mac, ""), start).raised.is_empty());
+        }
+        let raised = d.observe_at(&request("DISCOVER", "00:0c:29:00:00:04", ""), start).raised;
+        assert_eq!(raised.len(), 1);
+        assert!(raised[0].message.contains("(5 sequential, 0 locally administered)"));
+    }
+
+    #[test]
+    fn test_seeded_macs_are_not_new() {
+        let d = detector();
+        let now = Instant::now();
+        for i in 0..20 {
+            d.seed_known(&format!("02:00:00:00:01:{:02x}", i));
+        }
+        // A restart followed by everyone rediscovering at once
+        for i in 0..20 {
+            let mac = format!("02:00:00:00:01:{:02x}", i);
+            assert!(d.observe_at(&request("DISCOVER", &mac, ""), now).raised.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_pattern_counts_follow_the_window() {
+        let mut window = DiscoverWindow {
+            window: Duration::from_secs(60),
+            events: VecDeque::new(),
+            per_mac: HashMap::new(),
+            values: BTreeSet::new(),
+            patterns: (0, 0, 0),
+            active: false,
+        };
+        let start = Instant::now();
+        window.record(start, "00:0c:29:00:00:01");
+        window.record(start, "00:0c:29:00:00:03");
+        assert_eq!(window.patterns(), (0, 0, 0));
+        // Fills the gap, making a run of three
+        window.record(start + Duration::from_secs(30), "00:0c:29:00:00:02");
+        window.record(start + Duration::from_secs(30), "02:00:00:00:00:09");
+        assert_eq!(window.patterns(), (3, 1, 4));
+        // The first two expire, leaving the middle one without neighbors
+        assert_eq!(window.count(start + Duration::from_secs(61)), 2);
+        assert_eq!(window.patterns(), (0, 1, 1));
+    }
+
+    #[test]
+    fn test_decline_threshold() {
+        let d = detector();
+        let now = Instant::now();
+        assert!(d.observe_at(&request("DECLINE", "aa", ""), now).raised.is_empty());
+        assert!(d.observe_at(&request("DECLINE", "bb", ""), now).raised.is_empty());
+        let report = d.observe_at(&request("DECLINE", "cc", ""), now);
+        assert_eq!(report.raised[0].kind, "excessive_declines");
+    }
+
+    #[test]
+    fn test_fingerprint_change() {
+        let d = detector();
+        let now = Instant::now();
+        assert!(d.observe_at(&request("REQUEST", "aa:bb", "1,3,6"), now).raised.is_empty());
+        assert!(d.observe_at(&request("REQUEST", "aa:bb", "1,3,6"), now).raised.is_empty());
+        // Requests without option 55 don't count as a change
+        assert!(d.observe_at(&request("REQUEST", "aa:bb", ""), now).raised.is_empty());
+
+        let report = d.observe_at(&request("REQUEST", "aa:bb", "1,3,6,15"), now);
+        assert_eq!(report.raised.len(), 1);
+        assert_eq!(report.raised[0].kind, "fingerprint_changed");
+        assert_eq!(report.raised[0].key, "aa:bb");
+
+        // Going back to a fingerprint seen before (dual boot) is not news
+        assert!(d.observe_at(&request("REQUEST", "aa:bb", "1,3,6"), now).raised.is_empty());
+    }
+
+    #[test]
+    fn test_vendor_class_change_after_restart() {
+        let d = detector();
+        let now = Instant::now();
+        d.seed_identity("aa:bb", "1,3,6", Some("MSFT 5.0"));
+        let with_vendor = |fingerprint: &str, vendor: &str| DhcpRequest {
+            vendor_class: Some(vendor.to_string()),
+            ..request("REQUEST", "aa:bb", fingerprint)
+        };
+
+        assert!(d.observe_at(&with_vendor("1,3,6", "MSFT 5.0"), now).raised.is_empty());
+        let raised = d.observe_at(&with_vendor("1,3,6", "android-dhcp-14"), now).raised;
+        assert_eq!(raised.len(), 1);
+        assert_eq!(
+            raised[0].message,
+            "aa:bb changed vendor class from \"MSFT 5.0\" to \"android-dhcp-14\" (MAC spoofing or OS reinstall?)"
+        );
+    }
+
+    #[test]
+    fn test_ip_conflicts() {
+        let d = detector();
+        let start = Instant::now();
+        let holding = |mac: &str, ip: &str, source: &'static str, timestamp: &str| DhcpRequest {
+            timestamp: timestamp.to_string(),
+            assigned_ip: Some((ip.to_string(), source)),
+            ..request("REQUEST", mac, "")
+        };
+
+        let first = holding("aa:aa:aa:aa:aa:aa", "10.0.0.5", "yiaddr", "2025-01-01T10:00:00Z");
+        assert!(d.observe_at(&first, start).raised.is_empty());
+        // Renewals by the same device are fine
+        assert!(d.observe_at(&holding("aa:aa:aa:aa:aa:aa", "10.0.0.5", "ciaddr", "2025-01-01T10:30:00Z"), start).raised.is_empty());
+
+        let second = holding("bb:bb:bb:bb:bb:bb", "10.0.0.5", "yiaddr", "2025-01-01T10:40:00Z");
+        let raised = d.observe_at(&second, start + Duration::from_secs(600)).raised;
+        assert_eq!(raised.len(), 1);
+        assert_eq!((raised[0].kind, raised[0].key.as_str()), ("ip_conflict", "10.0.0.5"));
+        assert!(raised[0].message.contains("aa:aa:aa:aa:aa:aa (ciaddr) at 2025-01-01T10:30:00Z"));
+        assert!(raised[0].message.contains("bb:bb:bb:bb:bb:bb (yiaddr) at 2025-01-01T10:40:00Z"));
+
+        // The same address at an agent site is a different network
+        let remote = DhcpRequest {
+            site_id: Some("branch-1".to_string()),
+            ..holding("ff:ff:ff:ff:ff:ff", "10.0.0.5", "yiaddr", "t")
+        };
+        assert!(d.observe_at(&remote, start + Duration::from_secs(650)).raised.is_empty());
+
+        // After a RELEASE, or outside the window, the address may change hands
+        let release = request("RELEASE", "bb:bb:bb:bb:bb:bb", "");
+        d.observe_at(&release, start + Duration::from_secs(700));
+        assert!(d.observe_at(&holding("cc:cc:cc:cc:cc:cc", "10.0.0.5", "yiaddr", "t"), start + Duration::from_secs(800)).raised.is_empty());
+        let later = start + Duration::from_secs(800 + 3601);
+        assert!(d.observe_at(&holding("dd:dd:dd:dd:dd:dd", "10.0.0.5", "yiaddr", "t"), later).raised.is_empty());
+
+        // A DECLINE names the device it collided with
+        let decline = DhcpRequest {
+            requested_ip: Some("10.0.0.5".to_string()),
+            timestamp: "2025-01-01T12:00:00Z".to_string(),
+            ..request("DECLINE", "ee:ee:ee:ee:ee:ee", "")
+        };
+        let raised = d.observe_at(&decline, later).raised;
+        let conflict = raised.iter().find(|anomaly| anomaly.kind == "ip_conflict").unwrap();
+        assert!(conflict.message.contains("ee:ee:ee:ee:ee:ee declined it"));
+        assert!(conflict.message.contains("dd:dd:dd:dd:dd:dd held it"));
+    }
+}
@@ -0,0 +1,216 @@
+//! ARP table correlation: devices keep answering ARP long after their last DHCP
+//! renewal, so the host's neighbor table (/proc/net/arp) tells us which ones are
+//! still on the network and whether they still use the address DHCP gave them.
+
+use crate::db::queries::DeviceSummary;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// ATF_COM: the entry has a resolved hardware address
+const FLAG_COMPLETE: u32 = 0x2;
+
+/// Where and how often to read the neighbor table
+#[derive(Debug, Clone)]
+pub struct NeighborConfig {
+    pub path: PathBuf,
+    pub interval: Duration,
+    /// A device counts as active while it was in the table this recently
+    pub active_window: Duration,
+}
+
+/// A resolved entry of the neighbor table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Neighbor {
+    pub ip: String,
+    pub mac: String,
+    pub interface: String,
+}
+
+#[derive(Debug, Clone)]
+struct Sighting {
+    ip: String,
+    interface: String,
+    last_seen: DateTime<Utc>,
+}
+
+/// What the neighbor table says about a device
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Presence {
+    /// In the neighbor table within the active window
+    pub active: bool,
+    pub arp_ip: Option<String>,
+    pub interface: Option<String>,
+    pub arp_seen: Option<DateTime<Utc>>,
+    /// Whether the ARP address matches the device's DHCP address (None when either is unknown)
+    pub ip_confirmed: Option<bool>,
+}
+
+/// Parse /proc/net/arp, keeping only resolved entries
+pub fn parse_proc_net_arp(content: &str) -> Vec<Neighbor> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [ip, _hw_type, flags, mac, _mask, interface] = fields[..] else {
+                return None;
+            };
+            let flags = u32::from_str_radix(flags.trim_start_matches("0x"), 16).ok()?;
+            let mac: crate::mac::Mac = mac.parse().ok()?;
+            if flags & FLAG_COMPLETE == 0 || mac.octets() == [0; 6] {
+                return None;
+            }
+            Some(Neighbor {
+                ip: ip.to_string(),
+                mac: mac.to_string(),
+                interface: interface.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The latest neighbor table sighting of each MAC
+pub struct NeighborTable {
+    config: NeighborConfig,
+    sightings: RwLock<HashMap<String, Sighting>>,
+}
+
+impl NeighborTable {
+    pub fn new(config: NeighborConfig) -> Self {
+        Self {
+            config,
+            sightings: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn update(&self, neighbors: Vec<Neighbor>, now: DateTime<Utc>) {
+        let mut sightings = self.sightings.write().unwrap();
+        for neighbor in neighbors {
+            sightings.insert(
+                neighbor.mac,
+                Sighting {
+                    ip: neighbor.ip,
+                    interface: neighbor.interface,
+                    last_seen: now,
+                },
+            );
+        }
+        // Forget devices long gone so the map doesn't grow without limit
+        let keep = self
+            .config
+            .active_window
+            .checked_mul(10)
+            .and_then(|keep| chrono::Duration::from_std(keep).ok())
+            .unwrap_or(chrono::Duration::MAX);
+        sightings.retain(|_, sighting| now - sighting.last_seen <= keep);
+    }
+
+    pub fn presence(&self, mac: &str, dhcp_ip: Option<&str>, now: DateTime<Utc>) -> Presence {
+        let sightings = self.sightings.read().unwrap();
+        let Some(sighting) = sightings.get(mac) else {
+            return Presence {
+                active: false,
+                arp_ip: None,
+                interface: None,
+                arp_seen: None,
+                ip_confirmed: None,
+            };
+        };
+        let window = chrono::Duration::from_std(self.config.active_window).unwrap_or(chrono::Duration::MAX);
+        Presence {
+            active: now - sighting.last_seen <= window,
+            arp_ip: Some(sighting.ip.clone()),
+            interface: Some(sighting.interface.clone()),
+            arp_seen: Some(sighting.last_seen),
+            ip_confirmed: dhcp_ip.map(|ip| ip == sighting.ip),
+        }
+    }
+
+    /// Fill in the presence of each device in the inventory
+    pub fn annotate(&self, devices: &mut [DeviceSummary]) {
+        let now = Utc::now();
+        for device in devices {
+            let dhcp_ip = device.assigned_ip.as_deref().or(device.last_ip.as_deref());
+            device.presence = Some(self.presence(&device.mac_address, dhcp_ip, now));
+        }
+    }
+
+    // Read the table on every tick
+    async fn poll(&self) {
+        match tokio::fs::read_to_string(&self.config.path).await {
+            Ok(content) => {
+                let neighbors = parse_proc_net_arp(&content);
+                debug!("Read {} neighbors from {}", neighbors.len(), self.config.path.display());
+                self.update(neighbors, Utc::now());
+            }
+            Err(e) => warn!("Failed to read neighbor table {}: {}", self.config.path.display(), e),
+        }
+    }
+}
+
+/// Poll the neighbor table in the background
+pub fn spawn(table: Arc<NeighborTable>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(table.config.interval);
+        loop {
+            interval.tick().await;
+            table.poll().await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROC_NET_ARP: &str = "\
+IP address       HW type     Flags       HW address            Mask     Device
+192.168.1.1      0x1         0x2         AA:BB:CC:00:00:01     *        eth0
+192.168.1.20     0x1         0x0         00:00:00:00:00:00     *        eth0
+192.168.1.30     0x1         0x6         aa:bb:cc:00:00:02     *        eth0.10
+";
+
+    #[test]
+    fn test_parse_and_presence() {
+        let neighbors = parse_proc_net_arp(PROC_NET_ARP);
+        assert_eq!(neighbors.len(), 2);
+        assert_eq!(neighbors[0].mac, "aa:bb:cc:00:00:01");
+        assert_eq!(neighbors[1].interface, "eth0.10");
+
+        let table = NeighborTable::new(NeighborConfig {
+            path: PathBuf::new(),
+            interval: Duration::from_secs(60),
+            active_window: Duration::from_secs(300),
+        });
+        let now = Utc::now();
+        table.update(neighbors, now);
+
+        let presence = table.presence("aa:bb:cc:00:00:01", Some("192.168.1.1"), now);
+        assert!(presence.active);
+        assert_eq!(presence.ip_confirmed, Some(true));
+        // The device moved to another address without telling DHCP
+        assert_eq!(table.presence("aa:bb:cc:00:00:02", Some("192.168.1.99"), now).ip_confirmed, Some(false));
+
+        let later = now + chrono::Duration::seconds(301);
+        assert!(!table.presence("aa:bb:cc:00:00:01", None, later).active);
+        assert!(!table.presence("aa:bb:cc:00:00:03", None, now).active);
+    }
+
+    #[test]
+    fn test_huge_active_window() {
+        let table = NeighborTable::new(NeighborConfig {
+            path: PathBuf::new(),
+            interval: Duration::from_secs(60),
+            active_window: Duration::MAX,
+        });
+        let now = Utc::now();
+        table.update(parse_proc_net_arp(PROC_NET_ARP), now);
+        table.update(Vec::new(), now + chrono::Duration::days(365));
+        assert!(table.presence("aa:bb:cc:00:00:01", None, now).active);
+    }
+}
@@ -0,0 +1,105 @@
+//! Which captured packets are recorded.
+//!
+//! On large networks much of the traffic is noise: the monitor host's own DHCP
+//! client, lab equipment, relays from sites nobody is watching. Packets matching
+//! these filters are dropped before they are logged, stored or broadcast.
+
+use crate::dhcp::DhcpPacket;
+use crate::smb_policy::Subnet;
+use regex::Regex;
+use std::net::Ipv4Addr;
+
+#[derive(Debug, Clone, Default)]
+pub struct CaptureFilter {
+    /// Lower-case, colon-separated MAC prefixes ("00:15:5d")
+    pub ignore_mac_prefixes: Vec<String>,
+    /// Vendor classes (option 60) to drop
+    pub ignore_vendor_classes: Vec<Regex>,
+    /// When non-empty, only clients in these subnets are kept
+    pub only_subnets: Vec<Subnet>,
+}
+
+/// The address that places a client in a subnet: the link address relays
+/// report (see `DhcpPacket::get_link_address`), otherwise the client's own,
+/// falling back to the address it asks for (option 50) while it has none
+pub fn subnet_address(packet: &DhcpPacket, source: Ipv4Addr) -> Option<Ipv4Addr> {
+    packet
+        .get_link_address()
+        .into_iter()
+        .chain([packet.ciaddr, source])
+        .find(|addr| !addr.is_unspecified() && !addr.is_broadcast())
+        .or_else(|| packet.get_requested_ip()?.parse().ok())
+}
+
+impl CaptureFilter {
+    pub fn is_empty(&self) -> bool {
+        self.ignore_mac_prefixes.is_empty() && self.ignore_vendor_classes.is_empty() && self.only_subnets.is_empty()
+    }
+
+    /// Why the packet should be dropped, or None to keep it
+    pub fn reject(&self, packet: &DhcpPacket, source: Ipv4Addr) -> Option<String> {
+        let mac = packet.get_mac_address();
+        if let Some(prefix) = self.ignore_mac_prefixes.iter().find(|prefix| mac.starts_with(prefix.as_str())) {
+            return Some(format!("MAC matches ignored prefix {}", prefix));
+        }
+
+        if let Some(vendor_class) = packet.get_vendor_class() {
+            if let Some(pattern) = self.ignore_vendor_classes.iter().find(|pattern| pattern.is_match(&vendor_class)) {
+                return Some(format!("vendor class matches ignored pattern {}", pattern));
+            }
+        }
+
+        if !self.only_subnets.is_empty() {
+            let in_scope = subnet_address(packet, source)
+                .is_some_and(|addr| self.only_subnets.iter().any(|subnet| subnet.contains(addr)));
+            if !in_scope {
+                return Some("client is outside the captured subnets".to_string());
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dhcp::DhcpPacketBuilder;
+
+    fn packet(mac: [u8; 6], giaddr: [u8; 4], options: &[(u8, &[u8])]) -> DhcpPacket {
+        let mut builder = DhcpPacketBuilder::discover().mac(mac).giaddr(giaddr.into());
+        for (code, value) in options {
+            builder = builder.option(*code, value);
+        }
+        DhcpPacket::parse(&builder.build()).unwrap()
+    }
+
+    #[test]
+    fn test_capture_filter() {
+        let filter = CaptureFilter {
+            ignore_mac_prefixes: vec![crate::mac::canonical_fragment("00-15-5D")],
+            ignore_vendor_classes: vec![Regex::new("^dhcpcd").unwrap()],
+            only_subnets: vec![Subnet::parse("10.1.0.0/16").unwrap()],
+        };
+        let unassigned = Ipv4Addr::UNSPECIFIED;
+
+        let hyperv = packet([0x00, 0x15, 0x5d, 1, 2, 3], [10, 1, 0, 1], &[]);
+        assert!(filter.reject(&hyperv, unassigned).is_some());
+
+        let monitor = packet([0xaa; 6], [10, 1, 0, 1], &[(60, b"dhcpcd-10.0.6:Linux")]);
+        assert!(filter.reject(&monitor, unassigned).is_some());
+
+        // Relayed from an in-scope subnet, or known by the address it requests
+        let relayed = packet([0xaa; 6], [10, 1, 0, 1], &[]);
+        assert_eq!(filter.reject(&relayed, unassigned), None);
+        let requesting = packet([0xaa; 6], [0; 4], &[(50, &[10, 1, 5, 9])]);
+        assert_eq!(filter.reject(&requesting, unassigned), None);
+
+        let other_site = packet([0xaa; 6], [10, 2, 0, 1], &[]);
+        assert!(filter.reject(&other_site, unassigned).is_some());
+        let unknown = packet([0xaa; 6], [0; 4], &[]);
+        assert!(filter.reject(&unknown, unassigned).is_some());
+
+        assert_eq!(CaptureFilter::default().reject(&other_site, unassigned), None);
+    }
+}
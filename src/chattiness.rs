@@ -0,0 +1,166 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::time::Duration;
+
+/// DISCOVERs from one device before a DISCOVER-dominated pattern counts as a loop
+const DISCOVER_LOOP_MIN: i64 = 10;
+
+/// Mean gap between non-DISCOVER requests below which a device is renewing too fast
+const RAPID_RENEWAL_SECS: f64 = 60.0;
+
+/// Per-device request counts over a window, as read from the database
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DeviceActivity {
+    pub mac_address: String,
+    pub requests: i64,
+    pub discovers: i64,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub os_name: Option<String>,
+    pub vendor_class: Option<String>,
+}
+
+/// Why a device stands out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoisyPattern {
+    /// Mostly DISCOVERs: the client never completes DORA (failing NIC, bad VLAN, no free leases)
+    DiscoverLoop,
+    /// Renewing every few seconds (roaming storms, broken lease handling)
+    RapidRenewal,
+    /// More requests than the renewal rate explains, without a clearer pattern
+    Elevated,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NoisyClient {
+    pub mac_address: String,
+    pub os_name: Option<String>,
+    pub vendor_class: Option<String>,
+    pub requests: i64,
+    pub discovers: i64,
+    /// Requests relative to what a well-behaved client would send in the window
+    pub score: f64,
+    /// Mean seconds between requests (None with a single request)
+    pub mean_interval_secs: Option<f64>,
+    pub pattern: NoisyPattern,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+/// Requests a well-behaved client sends in `window`: one DORA exchange plus a renewal every `renewal`
+pub fn expected_requests(window: Duration, renewal: Duration) -> f64 {
+    2.0 + window.as_secs_f64() / renewal.as_secs_f64().max(1.0)
+}
+
+fn mean_interval(activity: &DeviceActivity) -> Option<f64> {
+    if activity.requests < 2 {
+        return None;
+    }
+    let first = DateTime::parse_from_rfc3339(&activity.first_seen).ok()?;
+    let last = DateTime::parse_from_rfc3339(&activity.last_seen).ok()?;
+    let span = (last - first).num_milliseconds() as f64 / 1000.0;
+    Some(span / (activity.requests - 1) as f64)
+}
+
+fn classify(activity: &DeviceActivity, mean_interval_secs: Option<f64>) -> NoisyPattern {
+    if activity.discovers >= DISCOVER_LOOP_MIN && activity.discovers * 5 >= activity.requests * 4 {
+        NoisyPattern::DiscoverLoop
+    } else if mean_interval_secs.is_some_and(|secs| secs < RAPID_RENEWAL_SECS) {
+        NoisyPattern::RapidRenewal
+    } else {
+        NoisyPattern::Elevated
+    }
+}
+
+/// Score devices against the expected renewal rate, keeping those at or above `min_score`, noisiest first
+pub fn rank_noisy_clients(
+    activity: Vec<DeviceActivity>,
+    window: Duration,
+    renewal: Duration,
+    min_score: f64,
+) -> Vec<NoisyClient> {
+    let expected = expected_requests(window, renewal);
+
+    let mut noisy: Vec<NoisyClient> = activity
+        .into_iter()
+        .filter_map(|device| {
+            let score = device.requests as f64 / expected;
+            if score < min_score {
+                return None;
+            }
+            let mean_interval_secs = mean_interval(&device);
+            Some(NoisyClient {
+                pattern: classify(&device, mean_interval_secs),
+                mac_address: device.mac_address,
+                os_name: device.os_name,
+                vendor_class: device.vendor_class,
+                requests: device.requests,
+                discovers: device.discovers,
+                score,
+                mean_interval_secs,
+                first_seen: device.first_seen,
+                last_seen: device.last_seen,
+            })
+        })
+        .collect();
+
+    noisy.sort_by(|a, b| b.score.total_cmp(&a.score));
+    noisy
+}
+
+/// Start of a report window ending now
+pub fn window_start(window: Duration) -> String {
+    let window = chrono::Duration::from_std(window).unwrap_or(chrono::Duration::hours(1));
+    (Utc::now() - window).to_rfc3339()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activity(mac: &str, requests: i64, discovers: i64, span_secs: i64) -> DeviceActivity {
+        let first = DateTime::parse_from_rfc3339("2025-01-01T00:00:00+00:00").unwrap();
+        DeviceActivity {
+            mac_address: mac.to_string(),
+            requests,
+            discovers,
+            first_seen: first.to_rfc3339(),
+            last_seen: (first + chrono::Duration::seconds(span_secs)).to_rfc3339(),
+            os_name: None,
+            vendor_class: None,
+        }
+    }
+
+    #[test]
+    fn test_expected_requests() {
+        let hour = Duration::from_secs(3600);
+        assert_eq!(expected_requests(hour, Duration::from_secs(1800)), 4.0);
+    }
+
+    #[test]
+    fn test_rank_noisy_clients() {
+        let hour = Duration::from_secs(3600);
+        let renewal = Duration::from_secs(1800);
+        let devices = vec![
+            // Normal: DORA plus two renewals
+            activity("aa:aa:aa:aa:aa:01", 4, 1, 3600),
+            // Stuck in DISCOVER, every 10 seconds
+            activity("aa:aa:aa:aa:aa:02", 360, 355, 3590),
+            // Renewing every 20 seconds
+            activity("aa:aa:aa:aa:aa:03", 120, 1, 2380),
+            // Busier than expected but not fast
+            activity("aa:aa:aa:aa:aa:04", 30, 2, 3500),
+        ];
+
+        let noisy = rank_noisy_clients(devices, hour, renewal, 5.0);
+        let macs: Vec<&str> = noisy.iter().map(|n| n.mac_address.as_str()).collect();
+        assert_eq!(macs, vec!["aa:aa:aa:aa:aa:02", "aa:aa:aa:aa:aa:03", "aa:aa:aa:aa:aa:04"]);
+
+        assert_eq!(noisy[0].pattern, NoisyPattern::DiscoverLoop);
+        assert_eq!(noisy[0].score, 90.0);
+        assert_eq!(noisy[1].pattern, NoisyPattern::RapidRenewal);
+        assert_eq!(noisy[1].mean_interval_secs, Some(20.0));
+        assert_eq!(noisy[2].pattern, NoisyPattern::Elevated);
+    }
+}
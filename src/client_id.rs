@@ -0,0 +1,181 @@
+use crate::dhcp::{format_uuid, hex_colon};
+use serde::{Deserialize, Serialize};
+
+/// DHCP unique identifier (RFC 8415 section 11)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "duid_type", rename_all = "snake_case")]
+pub enum Duid {
+    /// Link-layer address plus time
+    Llt { hw_type: u16, time: u32, address: String },
+    /// Vendor-assigned, based on an enterprise number
+    En { enterprise: u32, identifier: String },
+    /// Link-layer address
+    Ll { hw_type: u16, address: String },
+    Uuid { uuid: String },
+    Unknown { code: u16, data: String },
+}
+
+impl Duid {
+    fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < 2 {
+            return None;
+        }
+        let code = u16::from_be_bytes([data[0], data[1]]);
+        let body = &data[2..];
+        let duid = match (code, body.len()) {
+            (1, 6..) => Duid::Llt {
+                hw_type: u16::from_be_bytes([body[0], body[1]]),
+                time: u32::from_be_bytes([body[2], body[3], body[4], body[5]]),
+                address: hex_colon(&body[6..]),
+            },
+            (2, 4..) => Duid::En {
+                enterprise: u32::from_be_bytes([body[0], body[1], body[2], body[3]]),
+                identifier: hex_colon(&body[4..]),
+            },
+            (3, 2..) => Duid::Ll {
+                hw_type: u16::from_be_bytes([body[0], body[1]]),
+                address: hex_colon(&body[2..]),
+            },
+            (4, 16) => Duid::Uuid {
+                uuid: format_uuid(body),
+            },
+            _ => Duid::Unknown {
+                code,
+                data: hex_colon(body),
+            },
+        };
+        Some(duid)
+    }
+}
+
+/// Decoded option 61 contents
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ClientIdentifier {
+    /// Hardware type (1 = Ethernet) and address
+    Hardware { hw_type: u8, address: String },
+    /// RFC 4361 node-specific identifier: IAID plus DUID, stable across MAC changes
+    Duid { iaid: u32, duid: Duid },
+    /// Type 0 and unassigned types: an opaque value, often a name
+    Opaque { id_type: u8, value: String },
+}
+
+/// Option 61 client identifier: the raw bytes as a stable key, plus their decoding
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientId {
+    /// Full option value as colon-separated hex; the canonical client key
+    pub key: String,
+    #[serde(flatten)]
+    pub decoded: ClientIdentifier,
+}
+
+impl ClientId {
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let (&id_type, rest) = data.split_first()?;
+        if rest.is_empty() {
+            return None;
+        }
+
+        let decoded = match id_type {
+            0xff if rest.len() > 4 => {
+                let iaid = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]);
+                match Duid::decode(&rest[4..]) {
+                    Some(duid) => ClientIdentifier::Duid { iaid, duid },
+                    None => return None,
+                }
+            }
+            1..=0xfe => ClientIdentifier::Hardware {
+                hw_type: id_type,
+                address: hex_colon(rest),
+            },
+            _ => {
+                let printable = rest.iter().all(|b| b.is_ascii_graphic() || *b == b' ');
+                ClientIdentifier::Opaque {
+                    id_type,
+                    value: if printable { String::from_utf8_lossy(rest).to_string() } else { hex_colon(rest) },
+                }
+            }
+        };
+
+        Some(ClientId {
+            key: hex_colon(data),
+            decoded,
+        })
+    }
+
+    /// Decode a stored key back into a client identifier
+    pub fn from_key(key: &str) -> Option<Self> {
+        let bytes = key
+            .split(':')
+            .map(|b| u8::from_str_radix(b, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        Self::decode(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hardware_identifier() {
+        let id = ClientId::decode(&[1, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]).unwrap();
+        assert_eq!(id.key, "01:aa:bb:cc:dd:ee:ff");
+        assert_eq!(
+            id.decoded,
+            ClientIdentifier::Hardware {
+                hw_type: 1,
+                address: "aa:bb:cc:dd:ee:ff".to_string()
+            }
+        );
+        assert_eq!(ClientId::from_key(&id.key), Some(id));
+    }
+
+    #[test]
+    fn test_duid_identifier() {
+        // systemd-networkd style: IAID + DUID-EN (enterprise 43793)
+        let mut data = vec![0xff, 0x12, 0x34, 0x56, 0x78, 0x00, 0x02, 0x00, 0x00, 0xab, 0x11];
+        data.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        let id = ClientId::decode(&data).unwrap();
+        assert_eq!(
+            id.decoded,
+            ClientIdentifier::Duid {
+                iaid: 0x12345678,
+                duid: Duid::En {
+                    enterprise: 43793,
+                    identifier: "de:ad:be:ef".to_string()
+                }
+            }
+        );
+
+        // DUID-LL
+        let id = ClientId::decode(&[0xff, 0, 0, 0, 1, 0, 3, 0, 1, 1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(
+            id.decoded,
+            ClientIdentifier::Duid {
+                iaid: 1,
+                duid: Duid::Ll {
+                    hw_type: 1,
+                    address: "01:02:03:04:05:06".to_string()
+                }
+            }
+        );
+
+        let json = serde_json::to_value(&id).unwrap();
+        assert_eq!(json["kind"], "duid");
+        assert_eq!(json["duid"]["duid_type"], "ll");
+    }
+
+    #[test]
+    fn test_opaque_identifier() {
+        let id = ClientId::decode(b"\x00printer-3").unwrap();
+        assert_eq!(
+            id.decoded,
+            ClientIdentifier::Opaque {
+                id_type: 0,
+                value: "printer-3".to_string()
+            }
+        );
+        assert_eq!(ClientId::decode(&[1]), None);
+    }
+}
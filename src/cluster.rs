@@ -0,0 +1,222 @@
+//! Leader election for an active and a standby instance sharing one SQLite file.
+//!
+//! The leader holds a lease row it renews every third of the TTL; the standby takes
+//! the row over once it lapses. SQLite locking only holds on local storage, so both
+//! instances run on one host, both capturing from `[receive] interface` (see dhcp_socket).
+//! Failover between hosts would need a shared PostgreSQL backend, which doesn't exist.
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Leader election settings for running several instances against one database
+#[derive(Debug, Clone)]
+pub struct ClusterConfig {
+    /// Unique name of this instance
+    pub node_id: String,
+    /// How long a leader keeps the lease without renewing it
+    pub lease_ttl: Duration,
+}
+
+impl ClusterConfig {
+    /// Renew well inside the TTL so one slow round doesn't cost the lease
+    fn renew_interval(&self) -> Duration {
+        self.lease_ttl / 3
+    }
+}
+
+/// Attempts per election round, so a briefly locked database doesn't cost a renewal
+const ACQUIRE_ATTEMPTS: u32 = 3;
+const ACQUIRE_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Current holder of the lease, for the status endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct ClusterStatus {
+    pub node_id: String,
+    pub is_leader: bool,
+    pub leader: Option<String>,
+    /// Lease expiry (Unix milliseconds)
+    pub lease_expires_at: Option<i64>,
+}
+
+/// Take or renew the lease; succeeds when it is free, expired or already ours
+pub async fn try_acquire(pool: &SqlitePool, node_id: &str, ttl: Duration, now_ms: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO cluster_leader (id, node_id, expires_at) VALUES (1, ?1, ?2)
+        ON CONFLICT(id) DO UPDATE SET node_id = excluded.node_id, expires_at = excluded.expires_at
+        WHERE cluster_leader.node_id = excluded.node_id OR cluster_leader.expires_at < ?3
+        "#
+    )
+    .bind(node_id)
+    .bind(now_ms + ttl.as_millis() as i64)
+    .bind(now_ms)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Holder and expiry of the lease, if it has ever been taken
+pub async fn current_leader(pool: &SqlitePool) -> Result<Option<(String, i64)>, sqlx::Error> {
+    sqlx::query_as("SELECT node_id, expires_at FROM cluster_leader WHERE id = 1")
+        .fetch_optional(pool)
+        .await
+}
+
+/// Keeps this instance's view of leadership up to date; only the leader captures and probes
+pub struct LeaderElection {
+    config: ClusterConfig,
+    pool: SqlitePool,
+    is_leader: AtomicBool,
+    /// Expiry of the lease as last renewed by this node (Unix milliseconds)
+    held_until: AtomicI64,
+}
+
+impl LeaderElection {
+    pub fn new(config: ClusterConfig, pool: SqlitePool) -> Self {
+        Self {
+            config,
+            pool,
+            is_leader: AtomicBool::new(false),
+            held_until: AtomicI64::new(0),
+        }
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.is_leader.load(Ordering::Relaxed)
+    }
+
+    /// One election round; returns whether this node is now leader
+    pub async fn tick(&self) -> bool {
+        let mut attempt = 1;
+        let result = loop {
+            let now_ms = chrono::Utc::now().timestamp_millis();
+            match try_acquire(&self.pool, &self.config.node_id, self.config.lease_ttl, now_ms).await {
+                Ok(acquired) => break Ok((acquired, now_ms)),
+                Err(e) if attempt < ACQUIRE_ATTEMPTS => {
+                    warn!("Leader lease renewal failed (attempt {}): {}", attempt, e);
+                    attempt += 1;
+                    tokio::time::sleep(ACQUIRE_RETRY_DELAY).await;
+                }
+                Err(e) => break Err(e),
+            }
+        };
+
+        let leader = match result {
+            Ok((acquired, now_ms)) => {
+                if acquired {
+                    self.held_until
+                        .store(now_ms + self.config.lease_ttl.as_millis() as i64, Ordering::Relaxed);
+                }
+                acquired
+            }
+            Err(e) => {
+                // No other node can take the lease before it expires, so a leader keeps
+                // leading until then and the next round tries again
+                let held = chrono::Utc::now().timestamp_millis() < self.held_until.load(Ordering::Relaxed);
+                warn!(
+                    "Leader lease renewal failed: {}{}",
+                    e,
+                    if held && self.is_leader() { ", keeping leadership until the lease expires" } else { "" }
+                );
+                held && self.is_leader()
+            }
+        };
+
+        let was_leader = self.is_leader.swap(leader, Ordering::Relaxed);
+        if leader && !was_leader {
+            info!("Node {} became leader, starting capture", self.config.node_id);
+        } else if !leader && was_leader {
+            warn!("Node {} lost leadership, standing by", self.config.node_id);
+        }
+        leader
+    }
+
+    /// Run election rounds in the background
+    pub fn spawn(self: &Arc<Self>) {
+        let election = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(election.config.renew_interval());
+            loop {
+                interval.tick().await;
+                election.tick().await;
+            }
+        });
+    }
+
+    pub async fn status(&self) -> Result<ClusterStatus, sqlx::Error> {
+        let leader = current_leader(&self.pool).await?;
+        Ok(ClusterStatus {
+            node_id: self.config.node_id.clone(),
+            is_leader: self.is_leader(),
+            leader: leader.as_ref().map(|(node, _)| node.clone()),
+            lease_expires_at: leader.map(|(_, expires)| expires),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lease_failover() {
+        let pool = crate::db::test_pool().await;
+        let ttl = Duration::from_secs(15);
+
+        assert!(try_acquire(&pool, "a", ttl, 0).await.unwrap());
+        // Held by a: b is refused, a renews
+        assert!(!try_acquire(&pool, "b", ttl, 5_000).await.unwrap());
+        assert!(try_acquire(&pool, "a", ttl, 5_000).await.unwrap());
+        assert_eq!(current_leader(&pool).await.unwrap(), Some(("a".to_string(), 20_000)));
+
+        // a stops renewing; b takes over once the lease expires
+        assert!(!try_acquire(&pool, "b", ttl, 19_000).await.unwrap());
+        assert!(try_acquire(&pool, "b", ttl, 20_001).await.unwrap());
+        assert!(!try_acquire(&pool, "a", ttl, 21_000).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_tick_tracks_leadership() {
+        let pool = crate::db::test_pool().await;
+        let config = |node: &str| ClusterConfig {
+            node_id: node.to_string(),
+            lease_ttl: Duration::from_secs(15),
+        };
+        let a = LeaderElection::new(config("a"), pool.clone());
+        let b = LeaderElection::new(config("b"), pool);
+
+        assert!(a.tick().await);
+        assert!(!b.tick().await);
+        assert!(a.is_leader());
+        assert!(!b.is_leader());
+
+        let status = b.status().await.unwrap();
+        assert_eq!(status.leader.as_deref(), Some("a"));
+        assert!(!status.is_leader);
+    }
+
+    #[tokio::test]
+    async fn test_leader_survives_database_errors_until_lease_expires() {
+        let pool = crate::db::test_pool().await;
+        let election = LeaderElection::new(
+            ClusterConfig {
+                node_id: "a".to_string(),
+                lease_ttl: Duration::from_millis(600),
+            },
+            pool.clone(),
+        );
+        assert!(election.tick().await);
+
+        // Every renewal now fails; the lease still protects the leader for a while
+        pool.close().await;
+        assert!(election.tick().await);
+        tokio::time::sleep(Duration::from_millis(700)).await;
+        assert!(!election.tick().await);
+        assert!(!election.is_leader());
+    }
+}
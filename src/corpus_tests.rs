@@ -0,0 +1,124 @@
+//! Golden-output tests over the packet corpus in testdata/packets.
+//!
+//! Each `<name>.hex` packet is parsed and classified, and the result is compared
+//! with `testdata/golden/<name>.json`. After an intentional parser or fingerprint
+//! change, regenerate the golden files and review the diff:
+//!
+//!     UPDATE_GOLDEN=1 cargo test corpus
+
+use crate::dhcp::{DhcpPacket, DhcpRequest};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const PACKET_DIR: &str = "testdata/packets";
+const GOLDEN_DIR: &str = "testdata/golden";
+
+/// Parse and detection output recorded for each corpus packet
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct GoldenOutput {
+    mac_address: String,
+    message_type: String,
+    xid: String,
+    fingerprint: String,
+    vendor_class: Option<String>,
+    os_name: Option<String>,
+    device_class: Option<String>,
+    option_codes: Vec<u8>,
+}
+
+impl GoldenOutput {
+    fn from_request(request: &DhcpRequest) -> Self {
+        Self {
+            mac_address: request.mac_address.clone(),
+            message_type: request.message_type.clone(),
+            xid: request.xid.clone(),
+            fingerprint: request.fingerprint.clone(),
+            vendor_class: request.vendor_class.clone(),
+            os_name: request.os_name.clone(),
+            device_class: request.device_class.clone(),
+            option_codes: request.raw_options.iter().map(|o| o.code).collect(),
+        }
+    }
+}
+
+/// Read a hex packet file; `#` starts a comment, whitespace is ignored
+fn read_hex_packet(path: &Path) -> Vec<u8> {
+    let text = fs::read_to_string(path).unwrap();
+    let hex: String = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(|line| line.split_whitespace())
+        .collect();
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn corpus_packets() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(PACKET_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "hex"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[test]
+fn corpus_matches_golden_output() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let packets = corpus_packets();
+    assert!(!packets.is_empty(), "no packets found in {}", PACKET_DIR);
+
+    let mut mismatches = Vec::new();
+
+    for path in packets {
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        let data = read_hex_packet(&path);
+        let packet = DhcpPacket::parse(&data)
+            .unwrap_or_else(|e| panic!("{}: failed to parse: {}", name, e));
+        let request = DhcpRequest::from_packet(&packet, "0.0.0.0".to_string(), 68);
+        let actual = GoldenOutput::from_request(&request);
+
+        let golden_path = Path::new(GOLDEN_DIR).join(format!("{}.json", name));
+        if update {
+            let json = serde_json::to_string_pretty(&actual).unwrap();
+            fs::write(&golden_path, json + "\n").unwrap();
+            continue;
+        }
+
+        let expected: GoldenOutput = match fs::read_to_string(&golden_path) {
+            Ok(content) => serde_json::from_str(&content).unwrap(),
+            Err(_) => {
+                mismatches.push(format!("{}: missing golden file {}", name, golden_path.display()));
+                continue;
+            }
+        };
+
+        if expected != actual {
+            mismatches.push(format!(
+                "{}:\n  expected: {:?}\n  actual:   {:?}",
+                name, expected, actual
+            ));
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "corpus output changed (rerun with UPDATE_GOLDEN=1 if intended):\n{}",
+        mismatches.join("\n")
+    );
+}
+
+#[test]
+fn read_hex_packet_ignores_comments() {
+    let dir = std::env::temp_dir().join("ks-dhcpmon-corpus-test");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("sample.hex");
+    fs::write(&path, "# header comment\n01 02 # trailing\n0a ff\n").unwrap();
+
+    assert_eq!(read_hex_packet(&path), vec![0x01, 0x02, 0x0a, 0xff]);
+}
@@ -0,0 +1,185 @@
+use crate::alerts::Alert;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, SqlitePool};
+
+/// A stored alert
+#[derive(Debug, Clone, FromRow, Serialize, utoipa::ToSchema)]
+pub struct StoredAlert {
+    pub id: i64,
+    pub alert_type: String,
+    pub alert_key: String,
+    pub severity: String,
+    pub message: String,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub occurrences: i64,
+    pub flapping: bool,
+    pub acknowledged: bool,
+    pub acknowledged_at: Option<String>,
+}
+
+/// Filters for listing alerts; unset fields match everything
+#[derive(Debug, Default, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AlertFilter {
+    pub severity: Option<String>,
+    pub alert_type: Option<String>,
+    /// RFC 3339 bounds on last_seen
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub acknowledged: Option<bool>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+const FILTER_CONDITIONS: &str = "(?1 IS NULL OR severity = ?1)
+    AND (?2 IS NULL OR alert_type = ?2)
+    AND (?3 IS NULL OR last_seen >= ?3)
+    AND (?4 IS NULL OR last_seen <= ?4)
+    AND (?5 IS NULL OR acknowledged = ?5)";
+
+/// Store a notified alert
+pub async fn insert_alert(pool: &SqlitePool, alert: &Alert) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO alerts (
+            alert_type, alert_key, severity, message,
+            first_seen, last_seen, occurrences, flapping
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&alert.alert_type)
+    .bind(&alert.key)
+    .bind(alert.severity.as_str())
+    .bind(&alert.message)
+    .bind(alert.first_seen.to_rfc3339())
+    .bind(alert.last_seen.to_rfc3339())
+    .bind(alert.occurrences as i64)
+    .bind(alert.flapping)
+    .execute(pool)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+/// Matching alerts, newest first
+pub async fn list_alerts(pool: &SqlitePool, filter: &AlertFilter) -> Result<Vec<StoredAlert>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT id, alert_type, alert_key, severity, message, first_seen, last_seen,
+                occurrences, flapping, acknowledged, acknowledged_at
+         FROM alerts WHERE {}
+         ORDER BY last_seen DESC, id DESC LIMIT ?6 OFFSET ?7",
+        FILTER_CONDITIONS
+    ))
+    .bind(&filter.severity)
+    .bind(&filter.alert_type)
+    .bind(&filter.since)
+    .bind(&filter.until)
+    .bind(filter.acknowledged)
+    .bind(filter.limit.unwrap_or(100).clamp(1, 1000))
+    .bind(filter.offset.unwrap_or(0).max(0))
+    .fetch_all(pool)
+    .await
+}
+
+/// Number of matching alerts (ignores limit/offset)
+pub async fn count_alerts(pool: &SqlitePool, filter: &AlertFilter) -> Result<i64, sqlx::Error> {
+    let (count,): (i64,) = sqlx::query_as(&format!("SELECT COUNT(*) FROM alerts WHERE {}", FILTER_CONDITIONS))
+        .bind(&filter.severity)
+        .bind(&filter.alert_type)
+        .bind(&filter.since)
+        .bind(&filter.until)
+        .bind(filter.acknowledged)
+        .fetch_one(pool)
+        .await?;
+    Ok(count)
+}
+
+/// Mark an alert as acknowledged; false if no such alert
+pub async fn acknowledge_alert(pool: &SqlitePool, id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE alerts SET acknowledged = 1, acknowledged_at = COALESCE(acknowledged_at, ?) WHERE id = ?",
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Acknowledge every open alert, returning how many were updated
+pub async fn acknowledge_all(pool: &SqlitePool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("UPDATE alerts SET acknowledged = 1, acknowledged_at = ? WHERE acknowledged = 0")
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// Delete an alert; false if no such alert
+pub async fn delete_alert(pool: &SqlitePool, id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM alerts WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::Severity;
+    use chrono::{TimeZone, Utc};
+
+    fn alert(alert_type: &str, severity: Severity, hour: u32) -> Alert {
+        let at = Utc.with_ymd_and_hms(2025, 1, 1, hour, 0, 0).unwrap();
+        Alert {
+            alert_type: alert_type.to_string(),
+            key: "network".to_string(),
+            severity,
+            message: format!("{} at {}", alert_type, hour),
+            first_seen: at,
+            last_seen: at,
+            occurrences: 1,
+            flapping: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_filter_acknowledge_delete() {
+        let pool = crate::db::test_pool().await;
+        let rogue = insert_alert(&pool, &alert("rogue_dhcp_server", Severity::Critical, 1)).await.unwrap();
+        insert_alert(&pool, &alert("nak_flood", Severity::Warning, 2)).await.unwrap();
+        insert_alert(&pool, &alert("nak_flood", Severity::Warning, 3)).await.unwrap();
+
+        let all = list_alerts(&pool, &AlertFilter::default()).await.unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].message, "nak_flood at 3");
+
+        let critical = AlertFilter {
+            severity: Some("critical".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(count_alerts(&pool, &critical).await.unwrap(), 1);
+
+        let recent = AlertFilter {
+            alert_type: Some("nak_flood".to_string()),
+            since: Some("2025-01-01T02:30:00+00:00".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(list_alerts(&pool, &recent).await.unwrap().len(), 1);
+
+        assert!(acknowledge_alert(&pool, rogue).await.unwrap());
+        let open = AlertFilter {
+            acknowledged: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(count_alerts(&pool, &open).await.unwrap(), 2);
+        assert_eq!(acknowledge_all(&pool).await.unwrap(), 2);
+        assert_eq!(count_alerts(&pool, &open).await.unwrap(), 0);
+
+        assert!(delete_alert(&pool, rogue).await.unwrap());
+        assert!(!delete_alert(&pool, rogue).await.unwrap());
+        assert!(!acknowledge_alert(&pool, rogue).await.unwrap());
+    }
+}
@@ -0,0 +1,124 @@
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+use std::collections::HashMap;
+
+/// Operator-supplied description of a device
+#[derive(Debug, Clone, Default, PartialEq, Serialize, utoipa::ToSchema)]
+pub struct DeviceMeta {
+    pub mac_address: String,
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub owner: Option<String>,
+    pub tags: Vec<String>,
+    pub updated_at: String,
+}
+
+impl DeviceMeta {
+    /// Nothing left to describe; stored as no row at all
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.location.is_none() && self.owner.is_none() && self.tags.is_empty()
+    }
+}
+
+#[derive(FromRow)]
+struct DbDeviceMeta {
+    mac_address: String,
+    name: Option<String>,
+    location: Option<String>,
+    owner: Option<String>,
+    /// JSON array of strings
+    tags: String,
+    updated_at: String,
+}
+
+impl From<DbDeviceMeta> for DeviceMeta {
+    fn from(row: DbDeviceMeta) -> Self {
+        DeviceMeta {
+            mac_address: row.mac_address,
+            name: row.name,
+            location: row.location,
+            owner: row.owner,
+            tags: serde_json::from_str(&row.tags).unwrap_or_default(),
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+pub async fn get_meta(pool: &SqlitePool, mac_address: &str) -> Result<Option<DeviceMeta>, sqlx::Error> {
+    let row: Option<DbDeviceMeta> = sqlx::query_as("SELECT * FROM device_meta WHERE mac_address = ?")
+        .bind(mac_address)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(Into::into))
+}
+
+/// Every described device keyed by MAC, for merging into listings
+pub async fn all_meta(pool: &SqlitePool) -> Result<HashMap<String, DeviceMeta>, sqlx::Error> {
+    let rows: Vec<DbDeviceMeta> = sqlx::query_as("SELECT * FROM device_meta").fetch_all(pool).await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.mac_address.clone(), row.into()))
+        .collect())
+}
+
+/// Replace a device's description; an empty one removes the row
+pub async fn put_meta(pool: &SqlitePool, meta: &DeviceMeta) -> Result<(), sqlx::Error> {
+    if meta.is_empty() {
+        sqlx::query("DELETE FROM device_meta WHERE mac_address = ?")
+            .bind(&meta.mac_address)
+            .execute(pool)
+            .await?;
+        return Ok(());
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO device_meta (mac_address, name, location, owner, tags, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?)
+        ON CONFLICT(mac_address) DO UPDATE SET
+            name = excluded.name,
+            location = excluded.location,
+            owner = excluded.owner,
+            tags = excluded.tags,
+            updated_at = excluded.updated_at
+        "#
+    )
+    .bind(&meta.mac_address)
+    .bind(&meta.name)
+    .bind(&meta.location)
+    .bind(&meta.owner)
+    .bind(serde_json::to_string(&meta.tags).unwrap_or_else(|_| "[]".to_string()))
+    .bind(&meta.updated_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_put_meta_replaces_and_clears() {
+        let pool = crate::db::test_pool().await;
+        let mut meta = DeviceMeta {
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            name: Some("Kitchen thermostat".to_string()),
+            location: Some("Kitchen".to_string()),
+            owner: None,
+            tags: vec!["iot".to_string(), "hvac".to_string()],
+            updated_at: "2025-01-01T00:00:00+00:00".to_string(),
+        };
+        put_meta(&pool, &meta).await.unwrap();
+        meta.location = None;
+        put_meta(&pool, &meta).await.unwrap();
+
+        assert_eq!(get_meta(&pool, "aa:bb:cc:dd:ee:ff").await.unwrap(), Some(meta.clone()));
+        assert_eq!(all_meta(&pool).await.unwrap()["aa:bb:cc:dd:ee:ff"].tags, ["iot", "hvac"]);
+
+        put_meta(&pool, &DeviceMeta { mac_address: meta.mac_address.clone(), ..Default::default() })
+            .await
+            .unwrap();
+        assert_eq!(get_meta(&pool, "aa:bb:cc:dd:ee:ff").await.unwrap(), None);
+    }
+}
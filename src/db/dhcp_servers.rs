@@ -0,0 +1,133 @@
+use crate::active_scan::{ProbeKind, ServerAnswer};
+use crate::dhcp::DhcpOption;
+use crate::options_catalog::DecodedOption;
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+/// A DHCP server found by active scanning, with its latest answer
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct DhcpServer {
+    pub server_id: String,
+    pub source_ip: String,
+    /// Probe it last answered: "discover" or "inform"
+    pub probe: String,
+    /// "OFFER", "ACK" or "NAK"
+    pub message_type: String,
+    pub offered_ip: Option<String>,
+    /// Options of the latest answer, decoded by the catalog
+    #[schema(value_type = Vec<Object>)]
+    pub options: Vec<DecodedOption>,
+    pub first_seen: String,
+    pub last_seen: String,
+    /// Scans the server answered
+    pub answers: i64,
+}
+
+/// Store a server's answer, replacing its previous one; true for a server not seen before
+pub async fn record_answer(
+    pool: &SqlitePool,
+    probe: ProbeKind,
+    answer: &ServerAnswer,
+    scanned_at: &str,
+) -> Result<bool, sqlx::Error> {
+    let options = serde_json::to_string(&answer.options).unwrap_or_else(|_| "[]".to_string());
+    let server_id = answer.server_id.to_string();
+    let source_ip = answer.source_ip.to_string();
+    let offered_ip = answer.offered_ip.map(|ip| ip.to_string());
+
+    let inserted = sqlx::query(
+        "INSERT OR IGNORE INTO dhcp_servers
+             (server_id, source_ip, probe, message_type, offered_ip, options, first_seen, last_seen, answers)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, 1)",
+    )
+    .bind(&server_id)
+    .bind(&source_ip)
+    .bind(probe.as_str())
+    .bind(answer.message_type)
+    .bind(&offered_ip)
+    .bind(&options)
+    .bind(scanned_at)
+    .bind(scanned_at)
+    .execute(pool)
+    .await?
+    .rows_affected()
+        > 0;
+    if inserted {
+        return Ok(true);
+    }
+
+    sqlx::query(
+        "UPDATE dhcp_servers SET source_ip = ?, probe = ?, message_type = ?, offered_ip = ?, options = ?,
+             last_seen = ?, answers = answers + 1
+         WHERE server_id = ?",
+    )
+    .bind(&source_ip)
+    .bind(probe.as_str())
+    .bind(answer.message_type)
+    .bind(&offered_ip)
+    .bind(&options)
+    .bind(scanned_at)
+    .bind(&server_id)
+    .execute(pool)
+    .await?;
+    Ok(false)
+}
+
+type ServerRow = (String, String, String, String, Option<String>, String, String, String, i64);
+
+/// Every server found so far, most recently seen first
+pub async fn list_servers(pool: &SqlitePool) -> Result<Vec<DhcpServer>, sqlx::Error> {
+    let rows: Vec<ServerRow> = sqlx::query_as(
+        "SELECT server_id, source_ip, probe, message_type, offered_ip, options, first_seen, last_seen, answers
+         FROM dhcp_servers ORDER BY last_seen DESC",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(server_id, source_ip, probe, message_type, offered_ip, options, first_seen, last_seen, answers)| {
+            let options: Vec<DhcpOption> = serde_json::from_str(&options).unwrap_or_default();
+            DhcpServer {
+                server_id,
+                source_ip,
+                probe,
+                message_type,
+                offered_ip,
+                options: options.iter().map(crate::options_catalog::decode).collect(),
+                first_seen,
+                last_seen,
+                answers,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[tokio::test]
+    async fn test_record_answers() {
+        let pool = crate::db::test_pool().await;
+        let mut answer = ServerAnswer {
+            server_id: Ipv4Addr::new(10, 0, 0, 1),
+            source_ip: Ipv4Addr::new(10, 0, 0, 1),
+            message_type: "OFFER",
+            offered_ip: Some(Ipv4Addr::new(10, 0, 0, 50)),
+            options: vec![DhcpOption { code: 3, data: vec![10, 0, 0, 254] }],
+        };
+        assert!(record_answer(&pool, ProbeKind::Discover, &answer, "2025-01-01T00:00:00+00:00").await.unwrap());
+        answer.message_type = "ACK";
+        answer.offered_ip = None;
+        assert!(!record_answer(&pool, ProbeKind::Inform, &answer, "2025-01-02T00:00:00+00:00").await.unwrap());
+
+        let servers = list_servers(&pool).await.unwrap();
+        assert_eq!(servers.len(), 1);
+        let server = &servers[0];
+        assert_eq!((server.probe.as_str(), server.message_type.as_str(), server.answers), ("inform", "ACK", 2));
+        assert_eq!(server.first_seen, "2025-01-01T00:00:00+00:00");
+        assert_eq!(server.options[0].display, "10.0.0.254");
+    }
+}
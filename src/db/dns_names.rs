@@ -0,0 +1,66 @@
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+
+/// A name found for one of a device's addresses by reverse lookup
+#[derive(Debug, Clone, PartialEq, FromRow, Serialize, utoipa::ToSchema)]
+pub struct DnsName {
+    pub ip_address: String,
+    pub name: String,
+    /// "dns" (PTR from the resolver) or "mdns" (asked the device itself)
+    pub source: String,
+    pub resolved_at: String,
+}
+
+/// Store the name resolved for the device's address, replacing an earlier one
+pub async fn record_name(
+    pool: &SqlitePool,
+    mac_address: &str,
+    name: &DnsName,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO dns_names (mac_address, ip_address, name, source, resolved_at) VALUES (?, ?, ?, ?, ?)
+         ON CONFLICT (mac_address, ip_address) DO UPDATE SET
+             name = excluded.name, source = excluded.source, resolved_at = excluded.resolved_at",
+    )
+    .bind(mac_address)
+    .bind(&name.ip_address)
+    .bind(&name.name)
+    .bind(&name.source)
+    .bind(&name.resolved_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Names resolved for a device, most recent first
+pub async fn names_for_mac(pool: &SqlitePool, mac_address: &str) -> Result<Vec<DnsName>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT ip_address, name, source, resolved_at FROM dns_names
+         WHERE mac_address = ? ORDER BY resolved_at DESC",
+    )
+    .bind(mac_address)
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_record_replaces_name() {
+        let pool = crate::db::test_pool().await;
+        let mut name = DnsName {
+            ip_address: "10.0.0.5".to_string(),
+            name: "printer.lan".to_string(),
+            source: "dns".to_string(),
+            resolved_at: "2025-01-01T00:00:00+00:00".to_string(),
+        };
+        record_name(&pool, "aa:bb:cc:dd:ee:ff", &name).await.unwrap();
+        name.name = "printer.local".to_string();
+        name.source = "mdns".to_string();
+        record_name(&pool, "aa:bb:cc:dd:ee:ff", &name).await.unwrap();
+
+        assert_eq!(names_for_mac(&pool, "aa:bb:cc:dd:ee:ff").await.unwrap(), vec![name]);
+    }
+}
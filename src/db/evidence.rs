@@ -0,0 +1,152 @@
+use crate::hybrid_detection::Evidence;
+use crate::smb::SmbServerInfo;
+use serde::Serialize;
+use sqlx::types::Json;
+use sqlx::{FromRow, SqlitePool};
+
+/// The latest verdict one detection source gave for a device
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct StoredEvidence {
+    pub source: String,
+    /// OS name, or the OS family for hints
+    pub value: Option<String>,
+    pub device_class: Option<String>,
+    pub vendor: Option<String>,
+    pub confidence: f64,
+    pub method: String,
+    /// SMB signing, capabilities and uptime, for SMB probe evidence
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smb_server: Option<Json<SmbServerInfo>>,
+    /// When the source started giving this value
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+/// Record each source's evidence for a device, one row per source.
+/// first_seen is kept while a source keeps giving the same value.
+pub async fn record_evidence(
+    pool: &SqlitePool,
+    mac_address: &str,
+    timestamp: &str,
+    evidence: &[Evidence],
+) -> Result<(), sqlx::Error> {
+    if evidence.is_empty() {
+        return Ok(());
+    }
+
+    let mut tx = pool.begin().await?;
+    for item in evidence {
+        sqlx::query(
+            r#"
+            INSERT INTO detection_evidence (
+                mac_address, source, value, device_class, vendor,
+                confidence, method, smb_server, first_seen, last_seen
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?9)
+            ON CONFLICT(mac_address, source) DO UPDATE SET
+                first_seen = CASE WHEN value IS excluded.value THEN first_seen ELSE excluded.first_seen END,
+                value = excluded.value,
+                device_class = excluded.device_class,
+                vendor = excluded.vendor,
+                confidence = excluded.confidence,
+                method = excluded.method,
+                smb_server = excluded.smb_server,
+                last_seen = excluded.last_seen
+            "#
+        )
+        .bind(mac_address)
+        .bind(item.source)
+        .bind(item.value())
+        .bind(&item.device_class)
+        .bind(&item.vendor)
+        .bind(item.confidence)
+        .bind(&item.method)
+        .bind(item.smb_server.as_ref().map(Json))
+        .bind(timestamp)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+/// Stored evidence for a device, most confident first
+pub async fn evidence_for_mac(pool: &SqlitePool, mac_address: &str) -> Result<Vec<StoredEvidence>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT source, value, device_class, vendor, confidence, method, smb_server, first_seen, last_seen
+         FROM detection_evidence WHERE mac_address = ?
+         ORDER BY confidence DESC, source"
+    )
+    .bind(mac_address)
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hybrid_detection::TtlFamily;
+
+    #[tokio::test]
+    async fn test_record_evidence() {
+        let pool = crate::db::test_pool().await;
+        let mac = "aa:bb:cc:dd:ee:ff";
+        let fingerprint = Evidence {
+            source: "dhcp_fingerprint",
+            os_name: Some("Windows 10/11".to_string()),
+            device_class: Some("Desktop".to_string()),
+            confidence: 0.95,
+            method: "DHCP fingerprint".to_string(),
+            ..Default::default()
+        };
+        let ttl = Evidence {
+            source: "icmp_ttl",
+            family: Some(TtlFamily::Windows),
+            confidence: 0.3,
+            method: "ICMP TTL".to_string(),
+            ..Default::default()
+        };
+
+        record_evidence(&pool, mac, "2025-01-01T00:00:00+00:00", &[fingerprint.clone(), ttl]).await.unwrap();
+        let stored = evidence_for_mac(&pool, mac).await.unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].source, "dhcp_fingerprint");
+        assert_eq!(stored[1].value.as_deref(), Some("Windows"));
+        assert!(stored[0].smb_server.is_none());
+
+        // Same value keeps first_seen; a new value restarts it
+        record_evidence(&pool, mac, "2025-01-02T00:00:00+00:00", std::slice::from_ref(&fingerprint)).await.unwrap();
+        let changed = Evidence { os_name: Some("Windows 7".to_string()), ..fingerprint };
+        let stored = evidence_for_mac(&pool, mac).await.unwrap();
+        assert_eq!(stored[0].first_seen, "2025-01-01T00:00:00+00:00");
+        assert_eq!(stored[0].last_seen, "2025-01-02T00:00:00+00:00");
+
+        record_evidence(&pool, mac, "2025-01-03T00:00:00+00:00", &[changed]).await.unwrap();
+        let stored = evidence_for_mac(&pool, mac).await.unwrap();
+        assert_eq!(stored[0].value.as_deref(), Some("Windows 7"));
+        assert_eq!(stored[0].first_seen, "2025-01-03T00:00:00+00:00");
+
+        // SMB server details come back as stored
+        let server = SmbServerInfo {
+            signing_enabled: true,
+            signing_required: true,
+            capabilities: vec!["dfs".to_string()],
+            server_guid: Some("00112233-4455-6677-8899-aabbccddeeff".to_string()),
+            system_time: None,
+            server_start_time: None,
+            uptime_secs: Some(3600),
+            native_os: None,
+            native_lan_manager: None,
+            primary_domain: Some("WORKGROUP".to_string()),
+        };
+        let smb = Evidence {
+            source: "smb",
+            os_name: Some("Windows 7/Server 2008 R2".to_string()),
+            confidence: 0.95,
+            smb_server: Some(server.clone()),
+            ..Default::default()
+        };
+        record_evidence(&pool, mac, "2025-01-04T00:00:00+00:00", &[smb]).await.unwrap();
+        let stored = evidence_for_mac(&pool, mac).await.unwrap();
+        let smb = stored.iter().find(|e| e.source == "smb").unwrap();
+        assert_eq!(smb.smb_server.as_ref().map(|json| &json.0), Some(&server));
+    }
+}
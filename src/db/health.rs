@@ -0,0 +1,244 @@
+use super::queries;
+use crate::dhcp::DhcpRequest;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Requests held in memory while the database cannot be written
+pub const DEGRADED_BUFFER_SIZE: usize = 50_000;
+
+/// How often a degraded database is retried
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+// Primary SQLite result codes (extended codes carry these in the low byte)
+const SQLITE_CORRUPT: i64 = 11;
+const SQLITE_FULL: i64 = 13;
+const SQLITE_NOTADB: i64 = 26;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DbCondition {
+    Ok,
+    DiskFull,
+    Corrupt,
+}
+
+impl DbCondition {
+    pub fn alert_type(&self) -> &'static str {
+        match self {
+            DbCondition::Ok => "db_ok",
+            DbCondition::DiskFull => "db_disk_full",
+            DbCondition::Corrupt => "db_corrupt",
+        }
+    }
+}
+
+/// Map a SQLite (extended) result code to a degraded condition
+pub fn condition_from_code(code: &str) -> Option<DbCondition> {
+    match code.parse::<i64>().ok()? & 0xff {
+        SQLITE_FULL => Some(DbCondition::DiskFull),
+        SQLITE_CORRUPT | SQLITE_NOTADB => Some(DbCondition::Corrupt),
+        _ => None,
+    }
+}
+
+/// Whether an error means the database is full or corrupt rather than a transient failure
+pub fn classify_error(error: &sqlx::Error) -> Option<DbCondition> {
+    match error {
+        sqlx::Error::Database(e) => e.code().and_then(|code| condition_from_code(&code)),
+        _ => None,
+    }
+}
+
+/// Snapshot of the database write path for /healthz
+#[derive(Debug, Clone, Serialize)]
+pub struct DbHealth {
+    pub condition: DbCondition,
+    pub buffered: usize,
+    pub dropped: u64,
+    pub last_error: Option<String>,
+}
+
+/// What happened to a request handed to the write guard
+pub enum WriteOutcome {
+    /// The row id, or None when the request was written along with buffered ones
+    Stored(Option<i64>),
+    /// The database is full or corrupt; the request is held in memory
+    Buffered(DbCondition, String),
+    Failed(sqlx::Error),
+}
+
+struct GuardState {
+    condition: DbCondition,
+    buffer: VecDeque<Arc<DhcpRequest>>,
+    dropped: u64,
+    last_error: Option<String>,
+    last_retry: Instant,
+}
+
+impl GuardState {
+    fn push(&mut self, request: Arc<DhcpRequest>) {
+        if self.buffer.len() >= DEGRADED_BUFFER_SIZE {
+            self.buffer.pop_front();
+            self.dropped += 1;
+        }
+        self.buffer.push_back(request);
+    }
+}
+
+/// Inserts requests, falling back to in-memory buffering when SQLite reports
+/// a full disk or corruption, and flushing the buffer once writes succeed again
+pub struct WriteGuard {
+    state: Mutex<GuardState>,
+}
+
+impl Default for WriteGuard {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(GuardState {
+                condition: DbCondition::Ok,
+                buffer: VecDeque::new(),
+                dropped: 0,
+                last_error: None,
+                last_retry: Instant::now(),
+            }),
+        }
+    }
+}
+
+impl WriteGuard {
+    pub async fn insert(&self, pool: &SqlitePool, request: Arc<DhcpRequest>) -> WriteOutcome {
+        // The lock only guards the degraded-mode state; healthy writes run concurrently
+        {
+            let mut state = self.state.lock().await;
+            if state.condition != DbCondition::Ok {
+                state.push(request);
+                if state.last_retry.elapsed() < RETRY_INTERVAL {
+                    return WriteOutcome::Buffered(state.condition, state.last_error.clone().unwrap_or_default());
+                }
+                return Self::flush(&mut state, pool).await;
+            }
+        }
+
+        match queries::insert_request(pool, &request).await {
+            Ok(id) => WriteOutcome::Stored(Some(id)),
+            Err(e) => match classify_error(&e) {
+                Some(condition) => {
+                    let mut state = self.state.lock().await;
+                    // Concurrent writes fail together; the first one switches modes
+                    if state.condition == DbCondition::Ok {
+                        tracing::error!("Database {:?}, buffering requests in memory: {}", condition, e);
+                        state.condition = condition;
+                        state.last_retry = Instant::now();
+                    }
+                    state.last_error = Some(e.to_string());
+                    state.push(request);
+                    WriteOutcome::Buffered(condition, e.to_string())
+                }
+                None => WriteOutcome::Failed(e),
+            },
+        }
+    }
+
+    // Write the buffer in one transaction; leave degraded mode only if it all lands
+    async fn flush(state: &mut GuardState, pool: &SqlitePool) -> WriteOutcome {
+        state.last_retry = Instant::now();
+
+        let result = async {
+            let mut tx = pool.begin().await?;
+            for request in &state.buffer {
+                queries::insert_request(&mut *tx, request).await?;
+            }
+            tx.commit().await
+        }
+        .await;
+
+        match result {
+            Ok(()) => {
+                tracing::info!(
+                    "Database writable again, flushed {} buffered requests ({} dropped)",
+                    state.buffer.len(),
+                    state.dropped
+                );
+                state.buffer.clear();
+                state.condition = DbCondition::Ok;
+                state.last_error = None;
+                WriteOutcome::Stored(None)
+            }
+            Err(e) => {
+                if let Some(condition) = classify_error(&e) {
+                    state.condition = condition;
+                }
+                state.last_error = Some(e.to_string());
+                WriteOutcome::Buffered(state.condition, e.to_string())
+            }
+        }
+    }
+
+    pub async fn health(&self) -> DbHealth {
+        let state = self.state.lock().await;
+        DbHealth {
+            condition: state.condition,
+            buffered: state.buffer.len(),
+            dropped: state.dropped,
+            last_error: state.last_error.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_condition_from_code() {
+        assert_eq!(condition_from_code("13"), Some(DbCondition::DiskFull));
+        assert_eq!(condition_from_code("11"), Some(DbCondition::Corrupt));
+        // SQLITE_CORRUPT_VTAB (extended)
+        assert_eq!(condition_from_code("267"), Some(DbCondition::Corrupt));
+        assert_eq!(condition_from_code("26"), Some(DbCondition::Corrupt));
+        // SQLITE_BUSY is transient
+        assert_eq!(condition_from_code("5"), None);
+        assert_eq!(condition_from_code("garbage"), None);
+    }
+
+    #[tokio::test]
+    async fn test_buffer_flushes_on_retry() {
+        let pool = crate::db::test_pool().await;
+        let guard = WriteGuard::default();
+        {
+            let mut state = guard.state.lock().await;
+            state.condition = DbCondition::DiskFull;
+            state.last_retry = Instant::now() - RETRY_INTERVAL;
+            state.push(Arc::new(DhcpRequest::default()));
+        }
+
+        assert!(matches!(
+            guard.insert(&pool, Arc::new(DhcpRequest::default())).await,
+            WriteOutcome::Stored(None)
+        ));
+        let health = guard.health().await;
+        assert_eq!(health.condition, DbCondition::Ok);
+        assert_eq!(health.buffered, 0);
+
+        let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM dhcp_requests")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count.0, 2);
+    }
+
+    #[tokio::test]
+    async fn test_buffer_is_bounded() {
+        let guard = WriteGuard::default();
+        let mut state = guard.state.lock().await;
+        for _ in 0..DEGRADED_BUFFER_SIZE + 5 {
+            state.push(Arc::new(DhcpRequest::default()));
+        }
+        assert_eq!(state.buffer.len(), DEGRADED_BUFFER_SIZE);
+        assert_eq!(state.dropped, 5);
+    }
+}
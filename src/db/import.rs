@@ -0,0 +1,208 @@
+use sqlx::SqlitePool;
+use crate::dhcp::DhcpRequest;
+use super::queries;
+
+/// Input formats accepted by the importer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// Line-delimited JSON as written by RequestLogger (request.json)
+    Ndjson,
+    /// JSON array as produced by /api/logs/export?format=json
+    Json,
+    /// CSV as produced by /api/logs/export?format=csv
+    Csv,
+}
+
+impl ImportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "ndjson" | "jsonl" => Some(Self::Ndjson),
+            "json" => Some(Self::Json),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+
+    /// Guess the format from the first non-whitespace content
+    pub fn detect(data: &str) -> Self {
+        let trimmed = data.trim_start();
+        if trimmed.starts_with('[') {
+            Self::Json
+        } else if trimmed.starts_with('{') {
+            Self::Ndjson
+        } else {
+            Self::Csv
+        }
+    }
+}
+
+/// Outcome of an import run
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ImportSummary {
+    pub imported: u64,
+    pub duplicates: u64,
+    pub errors: u64,
+}
+
+/// Parse the input into requests, counting lines that could not be parsed
+pub fn parse_requests(data: &str, format: ImportFormat) -> anyhow::Result<(Vec<DhcpRequest>, u64)> {
+    match format {
+        ImportFormat::Json => Ok((serde_json::from_str(data)?, 0)),
+        ImportFormat::Ndjson => {
+            let mut requests = Vec::new();
+            let mut errors = 0;
+            for line in data.lines().filter(|l| !l.trim().is_empty()) {
+                match serde_json::from_str::<DhcpRequest>(line) {
+                    Ok(request) => requests.push(request),
+                    Err(e) => {
+                        tracing::warn!("Skipping unparsable log line: {}", e);
+                        errors += 1;
+                    }
+                }
+            }
+            Ok((requests, errors))
+        }
+        ImportFormat::Csv => parse_csv(data),
+    }
+}
+
+fn parse_csv(data: &str) -> anyhow::Result<(Vec<DhcpRequest>, u64)> {
+    let mut lines = data.lines().filter(|l| !l.trim().is_empty());
+    let header = lines
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("CSV input is empty"))?;
+    let columns = split_csv_line(header);
+    let index = |name: &str| columns.iter().position(|c| c == name);
+
+    let (Some(ts), Some(mac), Some(xid)) = (index("timestamp"), index("mac_address"), index("xid")) else {
+        anyhow::bail!("CSV header must contain timestamp, mac_address and xid columns");
+    };
+    let source_ip = index("source_ip");
+    let source_port = index("source_port");
+    let message_type = index("message_type");
+    let fingerprint = index("fingerprint");
+    let vendor_class = index("vendor_class");
+    let vlan_id = index("vlan_id");
+
+    let mut requests = Vec::new();
+    let mut errors = 0;
+    for line in lines {
+        let fields = split_csv_line(line);
+        if fields.len() != columns.len() {
+            errors += 1;
+            continue;
+        }
+        let get = |i: Option<usize>| i.map(|i| fields[i].clone()).unwrap_or_default();
+
+        requests.push(DhcpRequest {
+            timestamp: fields[ts].clone(),
+            source_ip: get(source_ip),
+            source_port: get(source_port).parse().unwrap_or(68),
+            mac_address: crate::mac::canonical(&fields[mac]),
+            message_type: get(message_type),
+            xid: fields[xid].clone(),
+            fingerprint: get(fingerprint),
+            // The CSV exporter writes "-" for a missing vendor class
+            vendor_class: Some(get(vendor_class)).filter(|v| !v.is_empty() && v != "-"),
+            vlan_id: vlan_id.and_then(|i| fields[i].parse().ok()),
+            ..Default::default()
+        });
+    }
+
+    Ok((requests, errors))
+}
+
+/// Split a CSV line, honouring double-quoted fields with "" escapes
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Insert requests that are not already stored (keyed by timestamp + xid + MAC)
+pub async fn import_requests(
+    pool: &SqlitePool,
+    requests: &[DhcpRequest],
+) -> Result<ImportSummary, sqlx::Error> {
+    let mut summary = ImportSummary::default();
+
+    for request in requests {
+        let existing: Option<(i64,)> = sqlx::query_as(
+            "SELECT id FROM dhcp_requests WHERE timestamp = ? AND xid = ? AND mac_address = ? LIMIT 1"
+        )
+        .bind(&request.timestamp)
+        .bind(&request.xid)
+        .bind(&request.mac_address)
+        .fetch_optional(pool)
+        .await?;
+
+        if existing.is_some() {
+            summary.duplicates += 1;
+            continue;
+        }
+
+        queries::insert_request(pool, request).await?;
+        summary.imported += 1;
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(ImportFormat::detect("  [{\"a\":1}]"), ImportFormat::Json);
+        assert_eq!(ImportFormat::detect("{\"a\":1}\n{\"a\":2}"), ImportFormat::Ndjson);
+        assert_eq!(ImportFormat::detect("timestamp,xid\n"), ImportFormat::Csv);
+    }
+
+    #[test]
+    fn test_split_csv_line_quoted() {
+        let fields = split_csv_line(r#"a,"1,3,6","say ""hi""",-"#);
+        assert_eq!(fields, vec!["a", "1,3,6", "say \"hi\"", "-"]);
+    }
+
+    #[test]
+    fn test_parse_exported_csv() {
+        let csv = "timestamp,source_ip,source_port,mac_address,message_type,xid,fingerprint,vendor_class\n\
+                   2025-01-01T00:00:00Z,10.0.0.5,68,aa:bb:cc:dd:ee:ff,DISCOVER,0000abcd,\"1,3,6,15\",MSFT 5.0\n\
+                   2025-01-01T00:00:01Z,10.0.0.6,68,11-22-33-44-55-AA,REQUEST,0000abce,,-\n\
+                   broken,line\n";
+        let (requests, errors) = parse_requests(csv, ImportFormat::Csv).unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(errors, 1);
+        assert_eq!(requests[0].fingerprint, "1,3,6,15");
+        assert_eq!(requests[0].vendor_class.as_deref(), Some("MSFT 5.0"));
+        assert_eq!(requests[1].vendor_class, None);
+        // MACs are stored in canonical form whatever the export used
+        assert_eq!(requests[1].mac_address, "11:22:33:44:55:aa");
+    }
+
+    #[test]
+    fn test_parse_ndjson_skips_bad_lines() {
+        let line = r#"{"timestamp":"2025-01-01T00:00:00Z","source_ip":"0.0.0.0","source_port":68,"mac_address":"aa:bb:cc:dd:ee:ff","message_type":"DISCOVER","xid":"00000001","fingerprint":"1,3,6","vendor_class":null,"os_name":null,"device_class":null,"raw_options":[]}"#;
+        let data = format!("{}\nnot json\n\n{}\n", line, line);
+        let (requests, errors) = parse_requests(&data, ImportFormat::Ndjson).unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(errors, 1);
+        assert_eq!(requests[0].detection_method, None);
+    }
+}
@@ -0,0 +1,133 @@
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+
+/// A stretch of time a device held one address
+#[derive(Debug, Clone, PartialEq, FromRow, Serialize, utoipa::ToSchema)]
+pub struct IpAssignment {
+    pub ip_address: String,
+    /// Where the address was seen: "yiaddr", "ciaddr" or "requested_ip"
+    pub source: String,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+/// Note the device's address at `timestamp`. Extends the current assignment when the
+/// address is unchanged; otherwise starts a new one and returns the previous address.
+pub async fn record_ip(
+    pool: &SqlitePool,
+    mac_address: &str,
+    ip_address: &str,
+    source: &str,
+    timestamp: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let current: Option<(i64, String)> = sqlx::query_as(
+        "SELECT id, ip_address FROM ip_history WHERE mac_address = ? ORDER BY last_seen DESC, id DESC LIMIT 1",
+    )
+    .bind(mac_address)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let previous = match current {
+        Some((id, current_ip)) if current_ip == ip_address => {
+            sqlx::query("UPDATE ip_history SET last_seen = MAX(last_seen, ?) WHERE id = ?")
+                .bind(timestamp)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            return Ok(None);
+        }
+        Some((_, current_ip)) => Some(current_ip),
+        None => None,
+    };
+
+    sqlx::query(
+        "INSERT INTO ip_history (mac_address, ip_address, source, first_seen, last_seen) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(mac_address)
+    .bind(ip_address)
+    .bind(source)
+    .bind(timestamp)
+    .bind(timestamp)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(previous)
+}
+
+/// Addresses a device has held, most recent first
+pub async fn ip_history(pool: &SqlitePool, mac_address: &str) -> Result<Vec<IpAssignment>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT ip_address, source, first_seen, last_seen FROM ip_history
+        WHERE mac_address = ?
+        ORDER BY last_seen DESC, id DESC
+        "#
+    )
+    .bind(mac_address)
+    .fetch_all(pool)
+    .await
+}
+
+/// An address assignment together with the device that held it
+#[derive(Debug, Clone, PartialEq, FromRow, Serialize)]
+pub struct DeviceAssignment {
+    pub mac_address: String,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub assignment: IpAssignment,
+}
+
+/// Assignments across all devices, optionally for one address or still seen since
+/// `seen_since` (RFC 3339), most recent first
+pub async fn assignments(
+    pool: &SqlitePool,
+    ip_address: Option<&str>,
+    seen_since: Option<&str>,
+    limit: i64,
+) -> Result<Vec<DeviceAssignment>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT mac_address, ip_address, source, first_seen, last_seen FROM ip_history
+        WHERE (?1 IS NULL OR ip_address = ?1) AND (?2 IS NULL OR last_seen >= ?2)
+        ORDER BY last_seen DESC, id DESC
+        LIMIT ?3
+        "#
+    )
+    .bind(ip_address)
+    .bind(seen_since)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_record_ip_tracks_changes() {
+        let pool = crate::db::test_pool().await;
+        let mac = "aa:bb:cc:dd:ee:ff";
+
+        assert_eq!(record_ip(&pool, mac, "10.0.0.5", "requested_ip", "2025-01-01T00:00:00+00:00").await.unwrap(), None);
+        assert_eq!(record_ip(&pool, mac, "10.0.0.5", "ciaddr", "2025-01-01T01:00:00+00:00").await.unwrap(), None);
+        assert_eq!(
+            record_ip(&pool, mac, "10.0.0.9", "ciaddr", "2025-01-02T00:00:00+00:00").await.unwrap().as_deref(),
+            Some("10.0.0.5")
+        );
+
+        let history = ip_history(&pool, mac).await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].ip_address, "10.0.0.9");
+        assert_eq!(history[1].first_seen, "2025-01-01T00:00:00+00:00");
+        assert_eq!(history[1].last_seen, "2025-01-01T01:00:00+00:00");
+
+        record_ip(&pool, "11:22:33:44:55:66", "10.0.0.5", "yiaddr", "2025-01-03T00:00:00+00:00").await.unwrap();
+        let holders = assignments(&pool, Some("10.0.0.5"), None, 10).await.unwrap();
+        assert_eq!(holders.len(), 2);
+        assert_eq!(holders[0].mac_address, "11:22:33:44:55:66");
+        assert_eq!(assignments(&pool, None, Some("2025-01-02T00:00:00+00:00"), 10).await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,43 @@
+use sqlx::SqlitePool;
+
+/// When the MAC was first seen, if it ever was
+pub async fn first_seen(pool: &SqlitePool, mac_address: &str) -> Result<Option<String>, sqlx::Error> {
+    let row: Option<(String,)> = sqlx::query_as("SELECT first_seen FROM known_devices WHERE mac_address = ?")
+        .bind(mac_address)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(|(first_seen,)| first_seen))
+}
+
+/// Every MAC ever seen, newest first
+pub async fn all_known(pool: &SqlitePool) -> Result<Vec<String>, sqlx::Error> {
+    let rows: Vec<(String,)> = sqlx::query_as("SELECT mac_address FROM known_devices ORDER BY first_seen DESC")
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().map(|(mac,)| mac).collect())
+}
+
+/// Record a MAC's first sighting; false when it was already known
+pub async fn insert_known(pool: &SqlitePool, mac_address: &str, first_seen: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("INSERT OR IGNORE INTO known_devices (mac_address, first_seen) VALUES (?, ?)")
+        .bind(mac_address)
+        .bind(first_seen)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Timestamp of the MAC's last stored request before `before`
+pub async fn last_request_before(
+    pool: &SqlitePool,
+    mac_address: &str,
+    before: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let row: (Option<String>,) =
+        sqlx::query_as("SELECT MAX(timestamp) FROM dhcp_requests WHERE mac_address = ? AND timestamp < ?")
+            .bind(mac_address)
+            .bind(before)
+            .fetch_one(pool)
+            .await?;
+    Ok(row.0)
+}
@@ -1,5 +1,21 @@
+pub mod alerts;
+pub mod device_meta;
+pub mod dhcp_servers;
+pub mod dns_names;
+pub mod evidence;
+pub mod health;
+pub mod import;
+pub mod ip_history;
+pub mod known_devices;
 pub mod models;
+pub mod overrides;
+pub mod probe_cache;
 pub mod queries;
+pub mod recover;
+pub mod snapshot;
+pub mod stats;
+pub mod sweep;
+pub mod xlsx;
 
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
 use tracing::info;
@@ -23,6 +39,22 @@ CREATE TABLE IF NOT EXISTS dhcp_requests (
     confidence REAL,
     smb_dialect TEXT,
     smb_build INTEGER,
+    vlan_id INTEGER,
+    user_class TEXT,
+    client_arch INTEGER,
+    client_ndi TEXT,
+    client_uuid TEXT,
+    lint_issues TEXT,
+    client_id TEXT,
+    requested_ip TEXT,
+    server_id TEXT,
+    hostname TEXT,
+    client_fqdn TEXT,
+    vendor_options TEXT,
+    site_id TEXT,
+    subnet TEXT,
+    repeats INTEGER NOT NULL DEFAULT 0,
+    last_repeat_at TEXT,
     created_at DATETIME DEFAULT CURRENT_TIMESTAMP
 );
 
@@ -31,8 +63,290 @@ CREATE INDEX IF NOT EXISTS idx_mac_address ON dhcp_requests(mac_address);
 CREATE INDEX IF NOT EXISTS idx_message_type ON dhcp_requests(message_type);
 CREATE INDEX IF NOT EXISTS idx_created_at ON dhcp_requests(created_at);
 CREATE INDEX IF NOT EXISTS idx_os_name ON dhcp_requests(os_name);
+CREATE INDEX IF NOT EXISTS idx_dedup ON dhcp_requests(timestamp, xid, mac_address);
+CREATE INDEX IF NOT EXISTS idx_mac_timestamp ON dhcp_requests(mac_address, timestamp);
+
+CREATE TABLE IF NOT EXISTS alerts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    alert_type TEXT NOT NULL,
+    alert_key TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    message TEXT NOT NULL,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL,
+    occurrences INTEGER NOT NULL,
+    flapping INTEGER NOT NULL DEFAULT 0,
+    acknowledged INTEGER NOT NULL DEFAULT 0,
+    acknowledged_at TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_alerts_last_seen ON alerts(last_seen);
+CREATE INDEX IF NOT EXISTS idx_alerts_type ON alerts(alert_type);
+
+CREATE TABLE IF NOT EXISTS cluster_leader (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    node_id TEXT NOT NULL,
+    expires_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS stats_checkpoint (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    last_id INTEGER NOT NULL,
+    taken_at TEXT NOT NULL,
+    total_requests INTEGER NOT NULL,
+    request_types TEXT NOT NULL,
+    vendor_classes TEXT NOT NULL,
+    vlans TEXT NOT NULL,
+    subnets TEXT NOT NULL DEFAULT '{}'
+);
+
+CREATE TABLE IF NOT EXISTS mac_overrides (
+    mac_address TEXT PRIMARY KEY,
+    os_name TEXT NOT NULL,
+    device_class TEXT NOT NULL,
+    vendor TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS ip_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    mac_address TEXT NOT NULL,
+    ip_address TEXT NOT NULL,
+    source TEXT NOT NULL,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_ip_history_mac ON ip_history(mac_address, last_seen);
+
+CREATE TABLE IF NOT EXISTS known_devices (
+    mac_address TEXT PRIMARY KEY,
+    first_seen TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS device_meta (
+    mac_address TEXT PRIMARY KEY,
+    name TEXT,
+    location TEXT,
+    owner TEXT,
+    tags TEXT NOT NULL DEFAULT '[]',
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS detection_evidence (
+    mac_address TEXT NOT NULL,
+    source TEXT NOT NULL,
+    value TEXT,
+    device_class TEXT,
+    vendor TEXT,
+    confidence REAL NOT NULL,
+    method TEXT NOT NULL,
+    smb_server TEXT,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL,
+    PRIMARY KEY (mac_address, source)
+);
+
+CREATE TABLE IF NOT EXISTS probe_cache (
+    source TEXT NOT NULL,
+    mac_address TEXT NOT NULL,
+    ip_address TEXT NOT NULL,
+    result TEXT,
+    failures INTEGER NOT NULL DEFAULT 0,
+    expires_at INTEGER NOT NULL,
+    PRIMARY KEY (source, mac_address, ip_address)
+);
+
+CREATE TABLE IF NOT EXISTS dns_names (
+    mac_address TEXT NOT NULL,
+    ip_address TEXT NOT NULL,
+    name TEXT NOT NULL,
+    source TEXT NOT NULL,
+    resolved_at TEXT NOT NULL,
+    PRIMARY KEY (mac_address, ip_address)
+);
+
+CREATE TABLE IF NOT EXISTS dhcp_servers (
+    server_id TEXT PRIMARY KEY,
+    source_ip TEXT NOT NULL,
+    probe TEXT NOT NULL,
+    message_type TEXT NOT NULL,
+    offered_ip TEXT,
+    options TEXT NOT NULL,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL,
+    answers INTEGER NOT NULL DEFAULT 1
+);
+
+CREATE TABLE IF NOT EXISTS sweep_sightings (
+    mac_address TEXT PRIMARY KEY,
+    ip_address TEXT NOT NULL,
+    method TEXT NOT NULL,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL
+);
+"#;
+
+/// SQL for a 4-byte IPv4 option in raw_options (a JSON array of {code, data}), dotted
+macro_rules! ipv4_option {
+    ($code:literal) => {
+        concat!(
+            "(SELECT json_extract(value, '$.data[0]') || '.' || json_extract(value, '$.data[1]') || '.' ",
+            "|| json_extract(value, '$.data[2]') || '.' || json_extract(value, '$.data[3]') ",
+            "FROM json_each(raw_options) WHERE json_extract(value, '$.code') = ",
+            stringify!($code),
+            " AND json_array_length(value, '$.data') = 4)"
+        )
+    };
+}
+
+/// Columns added after the initial schema: (table, column, type)
+/// Databases created by older versions get these via ALTER TABLE on startup
+const COLUMN_MIGRATIONS: &[(&str, &str, &str)] = &[
+    ("dhcp_requests", "vlan_id", "INTEGER"),
+    ("dhcp_requests", "user_class", "TEXT"),
+    ("dhcp_requests", "client_arch", "INTEGER"),
+    ("dhcp_requests", "client_ndi", "TEXT"),
+    ("dhcp_requests", "client_uuid", "TEXT"),
+    ("dhcp_requests", "lint_issues", "TEXT"),
+    ("dhcp_requests", "client_id", "TEXT"),
+    ("dhcp_requests", "requested_ip", "TEXT"),
+    ("dhcp_requests", "server_id", "TEXT"),
+    ("dhcp_requests", "hostname", "TEXT"),
+    ("dhcp_requests", "repeats", "INTEGER NOT NULL DEFAULT 0"),
+    ("dhcp_requests", "last_repeat_at", "TEXT"),
+    ("dhcp_requests", "raw_packet", "BLOB"),
+    ("dhcp_requests", "client_fqdn", "TEXT"),
+    ("dhcp_requests", "site_id", "TEXT"),
+    ("dhcp_requests", "vendor_options", "TEXT"),
+    ("dhcp_requests", "subnet", "TEXT"),
+    ("dhcp_requests", "verified_at", "TEXT"),
+    ("alerts", "acknowledged", "INTEGER NOT NULL DEFAULT 0"),
+    ("alerts", "acknowledged_at", "TEXT"),
+    ("detection_evidence", "smb_server", "TEXT"),
+    ("stats_checkpoint", "subnets", "TEXT NOT NULL DEFAULT '{}'"),
+];
+
+/// Values for existing rows when a column is first added: (table, column, expression)
+const COLUMN_BACKFILLS: &[(&str, &str, &str)] = &[
+    ("dhcp_requests", "requested_ip", ipv4_option!(50)),
+    ("dhcp_requests", "server_id", ipv4_option!(54)),
+];
+
+/// Fill the hostname column from stored option 12 data. Done here rather than in
+/// COLUMN_BACKFILLS because SQL can't decode the bytes as UTF-8.
+async fn backfill_hostnames(pool: &SqlitePool) -> Result<u64, sqlx::Error> {
+    let options: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT dhcp_requests.id, json_extract(opt.value, '$.data') \
+         FROM dhcp_requests, json_each(dhcp_requests.raw_options) AS opt \
+         WHERE json_extract(opt.value, '$.code') = 12",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut tx = pool.begin().await?;
+    let mut updated = 0;
+    for (id, data) in options {
+        let Some(hostname) = serde_json::from_str::<Vec<u8>>(&data)
+            .ok()
+            .and_then(|data| crate::dhcp::decode_hostname(&data))
+        else {
+            continue;
+        };
+        sqlx::query("UPDATE dhcp_requests SET hostname = ? WHERE id = ?")
+            .bind(hostname)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        updated += 1;
+    }
+    tx.commit().await?;
+    Ok(updated)
+}
+
+/// Indexes on migrated columns, created once the columns exist
+const POST_MIGRATION_INDEXES: &str = r#"
+CREATE INDEX IF NOT EXISTS idx_vlan_id ON dhcp_requests(vlan_id);
+CREATE INDEX IF NOT EXISTS idx_client_id ON dhcp_requests(client_id);
+CREATE INDEX IF NOT EXISTS idx_requested_ip ON dhcp_requests(requested_ip);
+CREATE INDEX IF NOT EXISTS idx_server_id ON dhcp_requests(server_id);
+CREATE INDEX IF NOT EXISTS idx_hostname ON dhcp_requests(hostname);
+CREATE INDEX IF NOT EXISTS idx_site_id ON dhcp_requests(site_id);
+CREATE INDEX IF NOT EXISTS idx_subnet ON dhcp_requests(subnet);
+CREATE INDEX IF NOT EXISTS idx_alerts_acknowledged ON alerts(acknowledged);
+"#;
+
+/// Devices already in the request history aren't announced as new; only does
+/// work the first time, while known_devices is still empty
+const SEED_KNOWN_DEVICES: &str = r#"
+INSERT INTO known_devices (mac_address, first_seen)
+SELECT mac_address, MIN(timestamp) FROM dhcp_requests
+WHERE NOT EXISTS (SELECT 1 FROM known_devices)
+GROUP BY mac_address
 "#;
 
+/// Tables with a mac_address column. Rows written before MACs were canonicalized
+/// (imports, hand-made mappings) are rewritten on startup; the cheap GLOB test keeps
+/// this from evaluating the full expression on rows already in canonical form.
+const MAC_TABLES: &[&str] = &[
+    "dhcp_requests",
+    "ip_history",
+    "known_devices",
+    "device_meta",
+    "mac_overrides",
+    "detection_evidence",
+    "dns_names",
+];
+
+async fn normalize_macs(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let canonical = crate::mac::canonical_sql("mac_address");
+    for table in MAC_TABLES {
+        // OR IGNORE: a row whose canonical twin already exists is left alone
+        let updated = sqlx::query(&format!(
+            "UPDATE OR IGNORE {table} SET mac_address = {canonical} \
+             WHERE (mac_address GLOB '*[A-F.-]*' OR length(mac_address) = 12) AND mac_address != {canonical}",
+        ))
+        .execute(pool)
+        .await?;
+        if updated.rows_affected() > 0 {
+            info!("Normalized {} MAC addresses in {}", updated.rows_affected(), table);
+        }
+    }
+    Ok(())
+}
+
+async fn migrate_columns(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    for (table, column, column_type) in COLUMN_MIGRATIONS {
+        let existing: Vec<(String,)> =
+            sqlx::query_as(&format!("SELECT name FROM pragma_table_info('{}')", table))
+                .fetch_all(pool)
+                .await?;
+
+        if !existing.iter().any(|(name,)| name == column) {
+            info!("Adding column {}.{}", table, column);
+            sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, column_type))
+                .execute(pool)
+                .await?;
+
+            let backfill = COLUMN_BACKFILLS.iter().find(|(t, c, _)| t == table && c == column);
+            if let Some((_, _, expression)) = backfill {
+                let updated = sqlx::query(&format!("UPDATE {} SET {} = {}", table, column, expression))
+                    .execute(pool)
+                    .await?;
+                info!("Backfilled {}.{} for {} rows", table, column, updated.rows_affected());
+            }
+            if (*table, *column) == ("dhcp_requests", "hostname") {
+                let updated = backfill_hostnames(pool).await?;
+                info!("Backfilled {}.{} for {} rows", table, column, updated);
+            }
+        }
+    }
+
+    sqlx::query(POST_MIGRATION_INDEXES).execute(pool).await?;
+    Ok(())
+}
+
 pub async fn create_pool(database_url: &str) -> Result<SqlitePool, sqlx::Error> {
     info!("Initializing database at {}", database_url);
 
@@ -49,7 +363,53 @@ pub async fn create_pool(database_url: &str) -> Result<SqlitePool, sqlx::Error>
     // Run migrations (create table and indexes)
     info!("Running database migrations");
     sqlx::query(SCHEMA).execute(&pool).await?;
+    migrate_columns(&pool).await?;
+    normalize_macs(&pool).await?;
+    sqlx::query(SEED_KNOWN_DEVICES).execute(&pool).await?;
 
     info!("Database initialized successfully");
     Ok(pool)
 }
+
+/// Fresh database in a temporary file for tests
+#[cfg(test)]
+pub async fn test_pool() -> SqlitePool {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let path = std::env::temp_dir().join(format!(
+        "ks-dhcpmon-test-{}-{}.db",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    let _ = std::fs::remove_file(&path);
+    create_pool(&format!("sqlite:{}", path.display())).await.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_backfill_hostnames() {
+        let pool = test_pool().await;
+        let mut request = crate::dhcp::DhcpRequest {
+            timestamp: "2025-01-01T00:00:00Z".to_string(),
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            message_type: "DISCOVER".to_string(),
+            ..Default::default()
+        };
+        let mut name = "Zoë's-PC".as_bytes().to_vec();
+        name.push(0);
+        request.raw_options = vec![crate::dhcp::DhcpOption { code: 12, data: name }];
+        let id = queries::insert_request(&pool, &request).await.unwrap();
+
+        assert_eq!(backfill_hostnames(&pool).await.unwrap(), 1);
+        let (hostname,): (Option<String>,) = sqlx::query_as("SELECT hostname FROM dhcp_requests WHERE id = ?")
+            .bind(id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(hostname.as_deref(), Some("Zoë's-PC"));
+    }
+}
@@ -19,15 +19,52 @@ pub struct DbDhcpRequest {
     pub confidence: Option<f64>,
     pub smb_dialect: Option<String>,
     pub smb_build: Option<i64>,
-    pub created_at: String,
+    pub vlan_id: Option<i64>,
+    pub user_class: Option<String>,
+    pub client_arch: Option<i64>,
+    pub client_ndi: Option<String>,
+    pub client_uuid: Option<String>,
+    /// JSON array of lint codes, NULL for a clean packet
+    pub lint_issues: Option<String>,
+    /// Option 61 client identifier key (hex)
+    pub client_id: Option<String>,
+    pub requested_ip: Option<String>,
+    pub server_id: Option<String>,
+    pub hostname: Option<String>,
+    /// JSON-encoded ClientFqdn
+    pub client_fqdn: Option<String>,
+    /// JSON-encoded option 125 vendor blocks
+    pub vendor_options: Option<String>,
+    pub site_id: Option<String>,
+    pub subnet: Option<String>,
+    pub repeats: i64,
+    pub last_repeat_at: Option<String>,
+    pub raw_packet: Option<Vec<u8>>,
 }
 
 impl From<DbDhcpRequest> for DhcpRequest {
     fn from(db_req: DbDhcpRequest) -> Self {
         // Parse raw_options back from JSON
-        let raw_options = serde_json::from_str(&db_req.raw_options).unwrap_or_default();
+        let raw_options: Vec<crate::dhcp::DhcpOption> = serde_json::from_str(&db_req.raw_options).unwrap_or_default();
+        // Rows stored before client_fqdn was added still have option 81 in raw_options
+        let client_fqdn = db_req
+            .client_fqdn
+            .and_then(|fqdn| serde_json::from_str(&fqdn).ok())
+            .or_else(|| {
+                let option = raw_options.iter().find(|option| option.code == 81)?;
+                crate::dhcp::ClientFqdn::decode(&option.data)
+            });
+        let vendor_options = db_req
+            .vendor_options
+            .and_then(|blocks| serde_json::from_str(&blocks).ok())
+            .or_else(|| {
+                let option = raw_options.iter().find(|option| option.code == 125)?;
+                Some(crate::vendor_options::decode(&option.data))
+            })
+            .unwrap_or_default();
 
         DhcpRequest {
+            id: Some(db_req.id),
             timestamp: db_req.timestamp,
             source_ip: db_req.source_ip,
             source_port: db_req.source_port as u16,
@@ -36,6 +73,10 @@ impl From<DbDhcpRequest> for DhcpRequest {
             xid: db_req.xid,
             fingerprint: db_req.fingerprint,
             vendor_class: db_req.vendor_class,
+            hostname: db_req.hostname,
+            client_fqdn,
+            requested_ip: db_req.requested_ip,
+            server_id: db_req.server_id,
             os_name: db_req.os_name,
             device_class: db_req.device_class,
             raw_options,
@@ -43,6 +84,25 @@ impl From<DbDhcpRequest> for DhcpRequest {
             confidence: db_req.confidence.map(|c| c as f32),
             smb_dialect: db_req.smb_dialect,
             smb_build: db_req.smb_build.map(|b| b as u32),
+            vlan_id: db_req.vlan_id.map(|v| v as u16),
+            user_class: db_req.user_class,
+            client_arch: db_req.client_arch.map(|a| a as u16),
+            client_ndi: db_req.client_ndi,
+            client_uuid: db_req.client_uuid,
+            client_id: db_req.client_id.as_deref().and_then(crate::client_id::ClientId::from_key),
+            vendor_options,
+            lint_issues: db_req
+                .lint_issues
+                .and_then(|issues| serde_json::from_str(&issues).ok())
+                .unwrap_or_default(),
+            repeats: db_req.repeats as u32,
+            last_repeat_at: db_req.last_repeat_at,
+            site_id: db_req.site_id,
+            subnet: db_req.subnet,
+            // giaddr, from the payload when it was stored
+            relayed: db_req.raw_packet.as_deref().and_then(|packet| packet.get(24..28)).is_some_and(|giaddr| giaddr != [0; 4]),
+            assigned_ip: None,
+            raw_packet: db_req.raw_packet,
         }
     }
 }
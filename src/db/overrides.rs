@@ -0,0 +1,97 @@
+use crate::fingerprint::MacOsInfo;
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+use std::collections::HashMap;
+
+/// A device identity set by an administrator through the API
+#[derive(Debug, Clone, PartialEq, FromRow, Serialize, utoipa::ToSchema)]
+pub struct MacOverride {
+    pub mac_address: String,
+    pub os_name: String,
+    pub device_class: String,
+    pub vendor: String,
+    pub updated_at: String,
+}
+
+impl MacOverride {
+    pub fn info(&self) -> MacOsInfo {
+        MacOsInfo {
+            os_name: self.os_name.clone(),
+            device_class: self.device_class.clone(),
+            vendor: self.vendor.clone(),
+        }
+    }
+}
+
+pub async fn list_overrides(pool: &SqlitePool) -> Result<Vec<MacOverride>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM mac_overrides ORDER BY mac_address")
+        .fetch_all(pool)
+        .await
+}
+
+/// All overrides keyed by MAC, for the detection lookup table
+pub async fn load_overrides(pool: &SqlitePool) -> Result<HashMap<String, MacOsInfo>, sqlx::Error> {
+    Ok(list_overrides(pool)
+        .await?
+        .into_iter()
+        .map(|entry| (entry.mac_address.clone(), entry.info()))
+        .collect())
+}
+
+pub async fn upsert_override(pool: &SqlitePool, entry: &MacOverride) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO mac_overrides (mac_address, os_name, device_class, vendor, updated_at)
+        VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT(mac_address) DO UPDATE SET
+            os_name = excluded.os_name,
+            device_class = excluded.device_class,
+            vendor = excluded.vendor,
+            updated_at = excluded.updated_at
+        "#
+    )
+    .bind(&entry.mac_address)
+    .bind(&entry.os_name)
+    .bind(&entry.device_class)
+    .bind(&entry.vendor)
+    .bind(&entry.updated_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Remove an override; false when there was none
+pub async fn delete_override(pool: &SqlitePool, mac_address: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM mac_overrides WHERE mac_address = ?")
+        .bind(mac_address)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_override_crud() {
+        let pool = crate::db::test_pool().await;
+        let mut entry = MacOverride {
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            os_name: "Windows 10".to_string(),
+            device_class: "Desktop/Laptop".to_string(),
+            vendor: "Microsoft".to_string(),
+            updated_at: "2025-01-01T00:00:00+00:00".to_string(),
+        };
+        upsert_override(&pool, &entry).await.unwrap();
+        entry.os_name = "Windows 11".to_string();
+        upsert_override(&pool, &entry).await.unwrap();
+
+        assert_eq!(list_overrides(&pool).await.unwrap(), vec![entry.clone()]);
+        assert_eq!(load_overrides(&pool).await.unwrap()["aa:bb:cc:dd:ee:ff"].os_name, "Windows 11");
+
+        assert!(delete_override(&pool, "aa:bb:cc:dd:ee:ff").await.unwrap());
+        assert!(!delete_override(&pool, "aa:bb:cc:dd:ee:ff").await.unwrap());
+        assert!(list_overrides(&pool).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+use sqlx::{FromRow, SqlitePool};
+
+/// A detection source's cached probe result or failure, as stored across restarts
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct CachedProbe {
+    pub source: String,
+    pub mac_address: String,
+    pub ip_address: String,
+    /// JSON-encoded result; None for a failed probe waiting to be retried
+    pub result: Option<String>,
+    pub failures: u32,
+    /// Unix seconds
+    pub expires_at: i64,
+}
+
+/// Replace the stored cache with `entries`
+pub async fn save(pool: &SqlitePool, entries: &[CachedProbe]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM probe_cache").execute(&mut *tx).await?;
+    for entry in entries {
+        sqlx::query(
+            "INSERT INTO probe_cache (source, mac_address, ip_address, result, failures, expires_at)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&entry.source)
+        .bind(&entry.mac_address)
+        .bind(&entry.ip_address)
+        .bind(&entry.result)
+        .bind(entry.failures)
+        .bind(entry.expires_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+pub async fn load(pool: &SqlitePool) -> Result<Vec<CachedProbe>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT source, mac_address, ip_address, result, failures, expires_at FROM probe_cache
+         ORDER BY source, mac_address, ip_address",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_save_replaces_cache() {
+        let pool = crate::db::test_pool().await;
+        let entry = |mac: &str, result: Option<&str>| CachedProbe {
+            source: "smb".to_string(),
+            mac_address: mac.to_string(),
+            ip_address: "10.0.0.5".to_string(),
+            result: result.map(str::to_string),
+            failures: if result.is_some() { 0 } else { 2 },
+            expires_at: 1_700_000_000,
+        };
+
+        save(&pool, &[entry("aa:bb:cc:dd:ee:01", Some("{}")), entry("aa:bb:cc:dd:ee:02", None)]).await.unwrap();
+        assert_eq!(load(&pool).await.unwrap(), [entry("aa:bb:cc:dd:ee:01", Some("{}")), entry("aa:bb:cc:dd:ee:02", None)]);
+
+        save(&pool, &[entry("aa:bb:cc:dd:ee:02", None)]).await.unwrap();
+        assert_eq!(load(&pool).await.unwrap(), [entry("aa:bb:cc:dd:ee:02", None)]);
+    }
+}
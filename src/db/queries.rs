@@ -1,4 +1,6 @@
-use sqlx::SqlitePool;
+use futures::StreamExt;
+use sqlx::{QueryBuilder, Sqlite, SqliteExecutor, SqlitePool};
+use tokio::sync::mpsc;
 use crate::dhcp::DhcpRequest;
 use super::models::DbDhcpRequest;
 
@@ -10,6 +12,18 @@ pub struct QueryFilters {
     pub xid: Option<String>,
     pub start_date: Option<String>,
     pub end_date: Option<String>,
+    pub vlan_id: Option<u16>,
+    pub hostname: Option<String>,
+    pub requested_ip: Option<std::net::Ipv4Addr>,
+    pub server_id: Option<std::net::Ipv4Addr>,
+    /// Agent site; "local" matches requests captured by this instance
+    pub site_id: Option<String>,
+    /// Option 125 enterprise number
+    pub enterprise: Option<u32>,
+    /// Subnet in CIDR notation, as attributed on capture
+    pub subnet: Option<String>,
+    /// true: only packets with conformance warnings; false: only clean ones
+    pub malformed: Option<bool>,
     pub sort_by: String,
     pub sort_order: String,
     pub page: i64,
@@ -25,6 +39,14 @@ impl Default for QueryFilters {
             xid: None,
             start_date: None,
             end_date: None,
+            vlan_id: None,
+            hostname: None,
+            requested_ip: None,
+            server_id: None,
+            site_id: None,
+            enterprise: None,
+            subnet: None,
+            malformed: None,
             sort_by: "timestamp".to_string(),
             sort_order: "DESC".to_string(),
             page: 1,
@@ -33,18 +55,32 @@ impl Default for QueryFilters {
     }
 }
 
-pub async fn insert_request(pool: &SqlitePool, request: &DhcpRequest) -> Result<i64, sqlx::Error> {
+pub async fn insert_request<'e, E: SqliteExecutor<'e>>(
+    executor: E,
+    request: &DhcpRequest,
+) -> Result<i64, sqlx::Error> {
     // Serialize raw_options to JSON
     let raw_options_json = serde_json::to_string(&request.raw_options)
         .unwrap_or_else(|_| "[]".to_string());
+    let lint_json = (!request.lint_issues.is_empty())
+        .then(|| serde_json::to_string(&request.lint_issues).unwrap_or_default());
+    let fqdn_json = request
+        .client_fqdn
+        .as_ref()
+        .map(|fqdn| serde_json::to_string(fqdn).unwrap_or_default());
+    let vendor_json = (!request.vendor_options.is_empty())
+        .then(|| serde_json::to_string(&request.vendor_options).unwrap_or_default());
 
     let result = sqlx::query(
         r#"
         INSERT INTO dhcp_requests (
             timestamp, source_ip, source_port, mac_address, message_type,
             xid, fingerprint, vendor_class, os_name, device_class, raw_options,
-            detection_method, confidence, smb_dialect, smb_build
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            detection_method, confidence, smb_dialect, smb_build, vlan_id,
+            user_class, client_arch, client_ndi, client_uuid, lint_issues, client_id,
+            requested_ip, server_id, hostname, raw_packet, client_fqdn, site_id, vendor_options,
+            subnet
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#
     )
     .bind(&request.timestamp)
@@ -62,58 +98,114 @@ pub async fn insert_request(pool: &SqlitePool, request: &DhcpRequest) -> Result<
     .bind(request.confidence.map(|c| c as f64))
     .bind(&request.smb_dialect)
     .bind(request.smb_build.map(|b| b as i64))
-    .execute(pool)
+    .bind(request.vlan_id.map(|v| v as i64))
+    .bind(&request.user_class)
+    .bind(request.client_arch.map(|a| a as i64))
+    .bind(&request.client_ndi)
+    .bind(&request.client_uuid)
+    .bind(lint_json)
+    .bind(request.client_id.as_ref().map(|id| id.key.as_str()))
+    .bind(&request.requested_ip)
+    .bind(&request.server_id)
+    .bind(&request.hostname)
+    .bind(&request.raw_packet)
+    .bind(fqdn_json)
+    .bind(&request.site_id)
+    .bind(vendor_json)
+    .bind(&request.subnet)
+    .execute(executor)
     .await?;
 
     Ok(result.last_insert_rowid())
 }
 
-pub async fn query_requests(
-    pool: &SqlitePool,
-    filters: &QueryFilters,
-) -> Result<Vec<DhcpRequest>, sqlx::Error> {
-    let mut query = String::from("SELECT * FROM dhcp_requests WHERE 1=1");
-    let mut conditions = Vec::new();
+/// An RFC 3339 filter bound in the UTC form requests are stored with, so it compares
+/// correctly as text; None when it doesn't parse
+pub fn timestamp_bound(value: &str) -> Option<String> {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|time| time.with_timezone(&chrono::Utc).to_rfc3339())
+}
 
-    // Build WHERE clause
-    if filters.mac_address.is_some() {
-        conditions.push(format!(
-            "mac_address LIKE '%{}%'",
-            filters.mac_address.as_ref().unwrap()
-        ));
+/// Site filter value for requests captured by this instance rather than an agent
+pub const LOCAL_SITE: &str = "local";
+
+/// `column` contains `value` literally: `%` and `_` in it are escaped
+fn push_like_contains(query: &mut QueryBuilder<'static, Sqlite>, column: &str, value: &str) {
+    let escaped = value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    query.push(" AND ").push(column).push(" LIKE ");
+    query.push_bind(format!("%{}%", escaped));
+    query.push(" ESCAPE '\\'");
+}
+
+/// Append the WHERE conditions shared by query_requests, count_requests and the export;
+/// every caller-supplied value is bound, never spliced into the SQL
+fn push_conditions(query: &mut QueryBuilder<'static, Sqlite>, filters: &QueryFilters) {
+    query.push(" WHERE 1=1");
+
+    if let Some(mac_address) = &filters.mac_address {
+        push_like_contains(query, "mac_address", mac_address);
     }
-    if filters.vendor_class.is_some() {
-        conditions.push(format!(
-            "vendor_class LIKE '%{}%'",
-            filters.vendor_class.as_ref().unwrap()
-        ));
+    if let Some(vendor_class) = &filters.vendor_class {
+        push_like_contains(query, "vendor_class", vendor_class);
     }
-    if filters.message_type.is_some() {
-        conditions.push(format!(
-            "message_type = '{}'",
-            filters.message_type.as_ref().unwrap()
-        ));
+    if let Some(message_type) = &filters.message_type {
+        query.push(" AND message_type = ").push_bind(message_type.clone());
     }
-    if filters.xid.is_some() {
-        conditions.push(format!("xid LIKE '%{}%'", filters.xid.as_ref().unwrap()));
+    if let Some(xid) = &filters.xid {
+        push_like_contains(query, "xid", xid);
     }
-    if filters.start_date.is_some() {
-        conditions.push(format!(
-            "timestamp >= '{}'",
-            filters.start_date.as_ref().unwrap()
-        ));
+    if let Some(start_date) = &filters.start_date {
+        query.push(" AND timestamp >= ").push_bind(start_date.clone());
     }
-    if filters.end_date.is_some() {
-        conditions.push(format!(
-            "timestamp <= '{}'",
-            filters.end_date.as_ref().unwrap()
-        ));
+    if let Some(end_date) = &filters.end_date {
+        query.push(" AND timestamp <= ").push_bind(end_date.clone());
     }
-
-    for condition in conditions {
-        query.push_str(" AND ");
-        query.push_str(&condition);
+    if let Some(vlan_id) = filters.vlan_id {
+        query.push(" AND vlan_id = ").push_bind(vlan_id as i64);
+    }
+    if let Some(hostname) = &filters.hostname {
+        push_like_contains(query, "hostname", hostname);
     }
+    if let Some(requested_ip) = filters.requested_ip {
+        query.push(" AND requested_ip = ").push_bind(requested_ip.to_string());
+    }
+    if let Some(server_id) = filters.server_id {
+        query.push(" AND server_id = ").push_bind(server_id.to_string());
+    }
+    match filters.site_id.as_deref() {
+        None => {}
+        Some(LOCAL_SITE) => {
+            query.push(" AND site_id IS NULL");
+        }
+        Some(site_id) => {
+            query.push(" AND site_id = ").push_bind(site_id.to_string());
+        }
+    }
+    if let Some(enterprise) = filters.enterprise {
+        query
+            .push(" AND EXISTS (SELECT 1 FROM json_each(vendor_options) WHERE json_extract(value, '$.enterprise') = ")
+            .push_bind(enterprise as i64)
+            .push(")");
+    }
+    if let Some(subnet) = &filters.subnet {
+        query.push(" AND subnet = ").push_bind(subnet.clone());
+    }
+    match filters.malformed {
+        Some(true) => {
+            query.push(" AND lint_issues IS NOT NULL");
+        }
+        Some(false) => {
+            query.push(" AND lint_issues IS NULL");
+        }
+        None => {}
+    }
+}
+
+/// SELECT for the filtered, sorted requests, without pagination
+fn select_query(filters: &QueryFilters) -> QueryBuilder<'static, Sqlite> {
+    let mut query = QueryBuilder::new("SELECT * FROM dhcp_requests");
+    push_conditions(&mut query, filters);
 
     // Add ORDER BY
     let sort_by = sanitize_column_name(&filters.sort_by);
@@ -122,14 +214,22 @@ pub async fn query_requests(
     } else {
         "DESC"
     };
-    query.push_str(&format!(" ORDER BY {} {}", sort_by, sort_order));
+    query.push(format!(" ORDER BY {} {}", sort_by, sort_order));
+    query
+}
+
+pub async fn query_requests(
+    pool: &SqlitePool,
+    filters: &QueryFilters,
+) -> Result<Vec<DhcpRequest>, sqlx::Error> {
+    let mut query = select_query(filters);
 
     // Add LIMIT and OFFSET for pagination
     let offset = (filters.page - 1) * filters.page_size;
-    query.push_str(&format!(" LIMIT {} OFFSET {}", filters.page_size, offset));
+    query.push(" LIMIT ").push_bind(filters.page_size).push(" OFFSET ").push_bind(offset);
 
     // Execute query
-    let db_requests: Vec<DbDhcpRequest> = sqlx::query_as(&query).fetch_all(pool).await?;
+    let db_requests: Vec<DbDhcpRequest> = query.build_query_as().fetch_all(pool).await?;
 
     // Convert to DhcpRequest
     let requests: Vec<DhcpRequest> = db_requests.into_iter().map(|db_req| db_req.into()).collect();
@@ -141,99 +241,724 @@ pub async fn count_requests(
     pool: &SqlitePool,
     filters: &QueryFilters,
 ) -> Result<i64, sqlx::Error> {
-    let mut query = String::from("SELECT COUNT(*) as count FROM dhcp_requests WHERE 1=1");
-    let mut conditions = Vec::new();
+    let mut query = QueryBuilder::new("SELECT COUNT(*) as count FROM dhcp_requests");
+    push_conditions(&mut query, filters);
 
-    // Build WHERE clause (same as query_requests)
-    if filters.mac_address.is_some() {
-        conditions.push(format!(
-            "mac_address LIKE '%{}%'",
-            filters.mac_address.as_ref().unwrap()
-        ));
-    }
-    if filters.vendor_class.is_some() {
-        conditions.push(format!(
-            "vendor_class LIKE '%{}%'",
-            filters.vendor_class.as_ref().unwrap()
-        ));
+    // Execute count query
+    let result: (i64,) = query.build_query_as().fetch_one(pool).await?;
+
+    Ok(result.0)
+}
+
+/// Count a deduplicated repeat on the latest stored row for the same
+/// (MAC, message type, fingerprint); false when there is no such row yet
+pub async fn record_repeat(pool: &SqlitePool, request: &DhcpRequest) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE dhcp_requests SET repeats = repeats + 1, last_repeat_at = ?
+        WHERE id = (
+            SELECT id FROM dhcp_requests
+            WHERE mac_address = ? AND message_type = ? AND fingerprint = ?
+            ORDER BY id DESC LIMIT 1
+        )
+        "#,
+    )
+    .bind(&request.timestamp)
+    .bind(&request.mac_address)
+    .bind(&request.message_type)
+    .bind(&request.fingerprint)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Replace a stored request's detection with what later probes found
+pub async fn update_detection(pool: &SqlitePool, request: &DhcpRequest) -> Result<bool, sqlx::Error> {
+    let Some(id) = request.id else {
+        return Ok(false);
+    };
+    let result = sqlx::query(
+        "UPDATE dhcp_requests SET os_name = ?, device_class = ?, detection_method = ?, confidence = ?,
+             smb_dialect = ?, smb_build = ?
+         WHERE id = ?",
+    )
+    .bind(&request.os_name)
+    .bind(&request.device_class)
+    .bind(&request.detection_method)
+    .bind(request.confidence.map(|c| c as f64))
+    .bind(&request.smb_dialect)
+    .bind(request.smb_build.map(|b| b as i64))
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Replace a stored request's detection with a re-verification's and record when
+/// it was verified, which restarts the decay of its confidence
+pub async fn record_verification(pool: &SqlitePool, request: &DhcpRequest, verified_at: &str) -> Result<bool, sqlx::Error> {
+    let Some(id) = request.id else {
+        return Ok(false);
+    };
+    let result = sqlx::query(
+        "UPDATE dhcp_requests SET os_name = ?, device_class = ?, detection_method = ?, confidence = ?,
+             smb_dialect = ?, smb_build = ?, verified_at = ?
+         WHERE id = ?",
+    )
+    .bind(&request.os_name)
+    .bind(&request.device_class)
+    .bind(&request.detection_method)
+    .bind(request.confidence.map(|c| c as f64))
+    .bind(&request.smb_dialect)
+    .bind(request.smb_build.map(|b| b as i64))
+    .bind(verified_at)
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// The detection of a device's latest request and when it was last verified
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct StoredDetection {
+    pub mac_address: String,
+    pub confidence: f64,
+    /// The re-verification time, or the request's own timestamp
+    pub verified_at: String,
+}
+
+/// The latest detection of every device that has one
+pub async fn latest_detections(pool: &SqlitePool) -> Result<Vec<StoredDetection>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        WITH latest AS (
+            SELECT mac_address, confidence, COALESCE(verified_at, timestamp) AS verified_at,
+                   ROW_NUMBER() OVER (PARTITION BY mac_address ORDER BY timestamp DESC, id DESC) AS rn
+            FROM dhcp_requests
+        )
+        SELECT mac_address, confidence, verified_at FROM latest
+        WHERE rn = 1 AND confidence IS NOT NULL
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn request_by_id(pool: &SqlitePool, id: i64) -> Result<Option<DhcpRequest>, sqlx::Error> {
+    let request: Option<DbDhcpRequest> = sqlx::query_as("SELECT * FROM dhcp_requests WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(request.map(Into::into))
+}
+
+/// Most recent request from a device, and the last non-zero source IP it used
+pub async fn latest_for_mac(
+    pool: &SqlitePool,
+    mac_address: &str,
+) -> Result<Option<(DhcpRequest, Option<String>)>, sqlx::Error> {
+    let latest: Option<DbDhcpRequest> = sqlx::query_as(
+        "SELECT * FROM dhcp_requests WHERE mac_address = ? ORDER BY timestamp DESC LIMIT 1",
+    )
+    .bind(mac_address)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(latest) = latest else {
+        return Ok(None);
+    };
+
+    let last_ip: Option<(String,)> = sqlx::query_as(
+        "SELECT source_ip FROM dhcp_requests WHERE mac_address = ? AND source_ip != '0.0.0.0' ORDER BY timestamp DESC LIMIT 1",
+    )
+    .bind(mac_address)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(Some((latest.into(), last_ip.map(|(ip,)| ip))))
+}
+
+/// Lint results for one vendor class
+#[derive(Debug, serde::Serialize)]
+pub struct VendorQuality {
+    /// None for clients that don't send option 60
+    pub vendor_class: Option<String>,
+    pub packets: i64,
+    pub devices: i64,
+    /// Packets with at least one lint issue
+    pub flagged: i64,
+    /// Percentage of clean packets
+    pub quality: f64,
+    /// Issue code and number of packets it appeared in, most common first
+    pub issues: Vec<(String, i64)>,
+}
+
+/// Per-vendor client quality over requests since `since`, worst first; folded repeats
+/// count as the packets they were
+pub async fn lint_report(pool: &SqlitePool, since: Option<&str>) -> Result<Vec<VendorQuality>, sqlx::Error> {
+    let vendors: Vec<(Option<String>, i64, i64, i64)> = sqlx::query_as(
+        r#"
+        SELECT vendor_class, SUM(1 + repeats), COUNT(DISTINCT mac_address),
+               SUM(CASE WHEN lint_issues IS NOT NULL THEN 1 + repeats ELSE 0 END)
+        FROM dhcp_requests
+        WHERE ?1 IS NULL OR timestamp >= ?1
+        GROUP BY vendor_class
+        "#
+    )
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+
+    let issues: Vec<(Option<String>, String, i64)> = sqlx::query_as(
+        r#"
+        SELECT r.vendor_class, i.value AS code, SUM(1 + r.repeats) AS n
+        FROM dhcp_requests r, json_each(r.lint_issues) i
+        WHERE r.lint_issues IS NOT NULL AND (?1 IS NULL OR r.timestamp >= ?1)
+        GROUP BY r.vendor_class, code
+        ORDER BY n DESC, code
+        "#
+    )
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+
+    let mut report: Vec<VendorQuality> = vendors
+        .into_iter()
+        .map(|(vendor_class, packets, devices, flagged)| VendorQuality {
+            quality: if packets > 0 { (packets - flagged) as f64 * 100.0 / packets as f64 } else { 100.0 },
+            vendor_class,
+            packets,
+            devices,
+            flagged,
+            issues: Vec::new(),
+        })
+        .collect();
+
+    for (vendor_class, code, count) in issues {
+        if let Some(entry) = report.iter_mut().find(|v| v.vendor_class == vendor_class) {
+            entry.issues.push((code, count));
+        }
     }
-    if filters.message_type.is_some() {
-        conditions.push(format!(
-            "message_type = '{}'",
-            filters.message_type.as_ref().unwrap()
-        ));
+
+    report.sort_by(|a, b| a.quality.total_cmp(&b.quality).then(b.packets.cmp(&a.packets)));
+    Ok(report)
+}
+
+/// One row of the device inventory
+#[derive(Debug, Default, sqlx::FromRow, serde::Serialize)]
+pub struct DeviceSummary {
+    /// Option 61 client identifier when the device sends one, otherwise its MAC
+    pub client_key: String,
+    pub client_id: Option<String>,
+    /// MAC address of the most recent request
+    pub mac_address: String,
+    /// Distinct MACs seen for this client (more than one for MAC-rotating clients)
+    pub mac_count: i64,
+    /// Last non-0.0.0.0 source IP
+    pub last_ip: Option<String>,
+    pub os_name: Option<String>,
+    pub device_class: Option<String>,
+    pub vendor_class: Option<String>,
+    /// Confidence of the detection when it was made
+    pub confidence: Option<f64>,
+    /// When the detection was made or last re-verified
+    pub verified_at: Option<String>,
+    /// Confidence decayed since then (when re-verification is enabled)
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_confidence: Option<f64>,
+    pub vlan_id: Option<i64>,
+    pub requests: i64,
+    pub first_seen: String,
+    pub last_seen: String,
+    /// Address from the device's DHCP exchanges (see ip_history)
+    pub assigned_ip: Option<String>,
+    /// Times that address has changed; non-zero for a device expected to keep a
+    /// reservation means the reservation isn't being honoured
+    pub ip_changes: i64,
+    /// Latest reverse DNS (or mDNS) name of one of the device's addresses
+    pub dns_name: Option<String>,
+    /// Name, location, owner and tags set through the API
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<super::device_meta::DeviceMeta>,
+    /// Whether the host's ARP table shows the device (when ARP correlation is enabled)
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence: Option<crate::arp::Presence>,
+    /// Online, idle or offline (when the presence engine is enabled)
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_state: Option<crate::presence::PresenceStatus>,
+    /// Found by a subnet sweep but never seen asking DHCP: a static address
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub no_dhcp: bool,
+}
+
+/// Every client seen, keyed by client identifier (falling back to MAC) and
+/// described by its most recent request, most recently seen first. Hosts only
+/// found by subnet sweeps are included, marked `no_dhcp`.
+pub async fn device_inventory(pool: &SqlitePool) -> Result<Vec<DeviceSummary>, sqlx::Error> {
+    let mut devices: Vec<DeviceSummary> = sqlx::query_as(
+        r#"
+        WITH keyed AS (
+            SELECT *, COALESCE(client_id, mac_address) AS client_key FROM dhcp_requests
+        ),
+        latest AS (
+            SELECT *, ROW_NUMBER() OVER (PARTITION BY client_key ORDER BY timestamp DESC, id DESC) AS rn
+            FROM keyed
+        ),
+        totals AS (
+            SELECT client_key, COUNT(*) AS requests, COUNT(DISTINCT mac_address) AS mac_count,
+                   MIN(timestamp) AS first_seen, MAX(timestamp) AS last_seen
+            FROM keyed
+            GROUP BY client_key
+        )
+        SELECT l.client_key, l.client_id, l.mac_address, t.mac_count,
+               (SELECT source_ip FROM keyed k
+                WHERE k.client_key = l.client_key AND k.source_ip != '0.0.0.0'
+                ORDER BY k.timestamp DESC LIMIT 1) AS last_ip,
+               l.os_name, l.device_class, l.vendor_class, l.confidence,
+               CASE WHEN l.confidence IS NOT NULL THEN COALESCE(l.verified_at, l.timestamp) END AS verified_at,
+               l.vlan_id,
+               t.requests, t.first_seen, t.last_seen,
+               (SELECT h.ip_address FROM ip_history h
+                WHERE h.mac_address = l.mac_address
+                ORDER BY h.last_seen DESC, h.id DESC LIMIT 1) AS assigned_ip,
+               (SELECT MAX(COUNT(*) - 1, 0) FROM ip_history h
+                WHERE h.mac_address = l.mac_address) AS ip_changes,
+               (SELECT d.name FROM dns_names d
+                WHERE d.mac_address = l.mac_address
+                ORDER BY d.resolved_at DESC LIMIT 1) AS dns_name
+        FROM latest l JOIN totals t ON t.client_key = l.client_key
+        WHERE l.rn = 1
+        ORDER BY t.last_seen DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let swept = super::sweep::without_dhcp(pool).await?;
+    if !swept.is_empty() {
+        devices.extend(swept.into_iter().map(|host| DeviceSummary {
+            client_key: host.mac_address.clone(),
+            mac_address: host.mac_address,
+            mac_count: 1,
+            last_ip: Some(host.ip_address),
+            first_seen: host.first_seen,
+            last_seen: host.last_seen,
+            no_dhcp: true,
+            ..Default::default()
+        }));
+        devices.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
     }
-    if filters.xid.is_some() {
-        conditions.push(format!("xid LIKE '%{}%'", filters.xid.as_ref().unwrap()));
+
+    let mut meta = super::device_meta::all_meta(pool).await?;
+    for device in &mut devices {
+        device.meta = meta.remove(&device.mac_address);
     }
-    if filters.start_date.is_some() {
-        conditions.push(format!(
-            "timestamp >= '{}'",
-            filters.start_date.as_ref().unwrap()
-        ));
+    Ok(devices)
+}
+
+/// A value that differs from the device's previous request
+#[derive(Debug, PartialEq, sqlx::FromRow, serde::Serialize)]
+pub struct ValueChange {
+    pub timestamp: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// One page of a device's requests, oldest first
+pub async fn requests_for_mac(
+    pool: &SqlitePool,
+    mac_address: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<DhcpRequest>, sqlx::Error> {
+    let rows: Vec<DbDhcpRequest> = sqlx::query_as(
+        "SELECT * FROM dhcp_requests WHERE mac_address = ? ORDER BY timestamp ASC, id ASC LIMIT ? OFFSET ?",
+    )
+    .bind(mac_address)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(Into::into).collect())
+}
+
+/// Each distinct (MAC, fingerprint, vendor class) combination, most recently used last
+pub async fn device_identities(pool: &SqlitePool) -> Result<Vec<(String, String, Option<String>)>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT mac_address, fingerprint, vendor_class FROM dhcp_requests
+         GROUP BY mac_address, fingerprint, vendor_class
+         ORDER BY MAX(id)",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Requests and devices seen at one site
+#[derive(Debug, PartialEq, sqlx::FromRow, serde::Serialize, utoipa::ToSchema)]
+pub struct SiteSummary {
+    /// None for requests captured by this instance
+    pub site_id: Option<String>,
+    pub requests: i64,
+    pub devices: i64,
+    pub last_seen: String,
+}
+
+/// Every site requests were stored for, busiest first
+pub async fn site_summary(pool: &SqlitePool) -> Result<Vec<SiteSummary>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT site_id, SUM(1 + repeats) AS requests, COUNT(DISTINCT mac_address) AS devices, MAX(timestamp) AS last_seen
+         FROM dhcp_requests GROUP BY site_id ORDER BY requests DESC",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn count_for_mac(pool: &SqlitePool, mac_address: &str) -> Result<i64, sqlx::Error> {
+    let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM dhcp_requests WHERE mac_address = ?")
+        .bind(mac_address)
+        .fetch_one(pool)
+        .await?;
+    Ok(count)
+}
+
+// Changes of `column` across a device's history, skipping rows where it is NULL or `ignore`
+async fn value_changes(
+    pool: &SqlitePool,
+    mac_address: &str,
+    column: &str,
+    ignore: &str,
+) -> Result<Vec<ValueChange>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        r#"
+        SELECT timestamp, previous AS "from", value AS "to" FROM (
+            SELECT timestamp, {column} AS value,
+                   LAG({column}) OVER (ORDER BY timestamp, id) AS previous
+            FROM dhcp_requests
+            WHERE mac_address = ? AND {column} IS NOT NULL AND {column} != ?
+        )
+        WHERE previous IS NOT NULL AND previous != value
+        ORDER BY timestamp
+        "#
+    ))
+    .bind(mac_address)
+    .bind(ignore)
+    .fetch_all(pool)
+    .await
+}
+
+/// Detected OS changes over a device's whole history
+pub async fn os_changes_for_mac(pool: &SqlitePool, mac_address: &str) -> Result<Vec<ValueChange>, sqlx::Error> {
+    value_changes(pool, mac_address, "os_name", "").await
+}
+
+/// Source IP changes over a device's whole history (0.0.0.0 is not an address change)
+pub async fn ip_changes_for_mac(pool: &SqlitePool, mac_address: &str) -> Result<Vec<ValueChange>, sqlx::Error> {
+    value_changes(pool, mac_address, "source_ip", "0.0.0.0").await
+}
+
+/// How often one option code appears, and which vendor classes send it
+#[derive(Debug, serde::Serialize)]
+pub struct OptionUsage {
+    pub code: u8,
+    pub name: Option<&'static str>,
+    pub requests: i64,
+    /// Share of requests in the window that carry this option
+    pub percent: f64,
+    pub devices: i64,
+    pub vendors: Vec<VendorCount>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct VendorCount {
+    /// None for clients that don't send option 60
+    pub vendor_class: Option<String>,
+    pub requests: i64,
+}
+
+/// Per-option usage over requests in [since, until], with up to `max_vendors` vendors each
+pub async fn option_statistics(
+    pool: &SqlitePool,
+    since: Option<&str>,
+    until: Option<&str>,
+    max_vendors: usize,
+) -> Result<(i64, Vec<OptionUsage>), sqlx::Error> {
+    let window = "(?1 IS NULL OR r.timestamp >= ?1) AND (?2 IS NULL OR r.timestamp <= ?2)";
+
+    let (total,): (i64,) = sqlx::query_as(&format!(
+        "SELECT COALESCE(SUM(1 + r.repeats), 0) FROM dhcp_requests r WHERE {}",
+        window
+    ))
+    .bind(since)
+    .bind(until)
+    .fetch_one(pool)
+    .await?;
+
+    // raw_options is stored as a JSON array of {code, data}; an option repeated within
+    // a packet counts once, and folded repeats count as the requests they were
+    let carrying = format!(
+        "SELECT DISTINCT r.id, r.repeats, r.mac_address, r.vendor_class, json_extract(o.value, '$.code') AS code
+         FROM dhcp_requests r, json_each(r.raw_options) o
+         WHERE {}",
+        window
+    );
+    let per_option: Vec<(i64, i64, i64)> = sqlx::query_as(&format!(
+        "SELECT code, SUM(1 + repeats), COUNT(DISTINCT mac_address)
+         FROM ({})
+         GROUP BY code ORDER BY code",
+        carrying
+    ))
+    .bind(since)
+    .bind(until)
+    .fetch_all(pool)
+    .await?;
+
+    let per_vendor: Vec<(i64, Option<String>, i64)> = sqlx::query_as(&format!(
+        "SELECT code, vendor_class, SUM(1 + repeats) AS n
+         FROM ({})
+         GROUP BY code, vendor_class ORDER BY code, n DESC",
+        carrying
+    ))
+    .bind(since)
+    .bind(until)
+    .fetch_all(pool)
+    .await?;
+
+    let mut usage: Vec<OptionUsage> = per_option
+        .into_iter()
+        .map(|(code, requests, devices)| OptionUsage {
+            code: code as u8,
+            name: crate::options_catalog::option_name(code as u8),
+            requests,
+            percent: if total > 0 { requests as f64 * 100.0 / total as f64 } else { 0.0 },
+            devices,
+            vendors: Vec::new(),
+        })
+        .collect();
+
+    for (code, vendor_class, requests) in per_vendor {
+        if let Some(entry) = usage.iter_mut().find(|u| u.code as i64 == code) {
+            if entry.vendors.len() < max_vendors {
+                entry.vendors.push(VendorCount { vendor_class, requests });
+            }
+        }
     }
-    if filters.end_date.is_some() {
-        conditions.push(format!(
-            "timestamp <= '{}'",
-            filters.end_date.as_ref().unwrap()
-        ));
+
+    Ok((total, usage))
+}
+
+/// Column a timeseries is broken down by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeseriesSplit {
+    MessageType,
+    Vendor,
+}
+
+impl TimeseriesSplit {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "message_type" => Some(Self::MessageType),
+            "vendor" | "vendor_class" => Some(Self::Vendor),
+            _ => None,
+        }
     }
 
-    for condition in conditions {
-        query.push_str(" AND ");
-        query.push_str(&condition);
+    fn column(self) -> &'static str {
+        match self {
+            Self::MessageType => "message_type",
+            Self::Vendor => "COALESCE(vendor_class, '(none)')",
+        }
     }
+}
 
-    // Execute count query
-    let result: (i64,) = sqlx::query_as(&query).fetch_one(pool).await?;
+/// Requests in one time bucket
+#[derive(Debug, serde::Serialize)]
+pub struct TimeBucket {
+    pub start: String,
+    pub requests: i64,
+    /// Per message type or vendor when split, otherwise empty
+    #[serde(skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub split: std::collections::BTreeMap<String, i64>,
+}
 
-    Ok(result.0)
+/// Request counts in `interval_secs` buckets from `since` to `until`, aligned to
+/// the epoch; buckets without requests are included with zero counts
+pub async fn request_timeseries(
+    pool: &SqlitePool,
+    since: chrono::DateTime<chrono::Utc>,
+    until: chrono::DateTime<chrono::Utc>,
+    interval_secs: i64,
+    split: Option<TimeseriesSplit>,
+) -> Result<Vec<TimeBucket>, sqlx::Error> {
+    let key = split.map_or("NULL", TimeseriesSplit::column);
+    let rows: Vec<(i64, Option<String>, i64)> = sqlx::query_as(&format!(
+        "SELECT (CAST(strftime('%s', timestamp) AS INTEGER) / ?1) * ?1 AS bucket, {} AS key, SUM(1 + repeats)
+         FROM dhcp_requests
+         WHERE timestamp >= ?2 AND timestamp <= ?3
+         GROUP BY bucket, key ORDER BY bucket",
+        key
+    ))
+    .bind(interval_secs)
+    .bind(since.to_rfc3339())
+    .bind(until.to_rfc3339())
+    .fetch_all(pool)
+    .await?;
+
+    let first = since.timestamp() / interval_secs * interval_secs;
+    let mut buckets: Vec<TimeBucket> = (first..=until.timestamp())
+        .step_by(interval_secs as usize)
+        .map(|start| TimeBucket {
+            start: chrono::DateTime::from_timestamp(start, 0).unwrap_or_default().to_rfc3339(),
+            requests: 0,
+            split: Default::default(),
+        })
+        .collect();
+
+    for (bucket, key, count) in rows {
+        let Some(entry) = buckets.get_mut(((bucket - first) / interval_secs) as usize) else {
+            continue;
+        };
+        entry.requests += count;
+        if let Some(key) = key {
+            *entry.split.entry(key).or_insert(0) += count;
+        }
+    }
+    Ok(buckets)
 }
 
-pub async fn export_requests(
+/// Request and DISCOVER counts per device since `since`, for devices with at least `min_requests`
+pub async fn device_activity(
     pool: &SqlitePool,
+    since: &str,
+    min_requests: i64,
+) -> Result<Vec<crate::chattiness::DeviceActivity>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT mac_address,
+               SUM(1 + repeats) AS requests,
+               SUM((message_type = 'DISCOVER') * (1 + repeats)) AS discovers,
+               MIN(timestamp) AS first_seen,
+               MAX(COALESCE(last_repeat_at, timestamp)) AS last_seen,
+               MAX(os_name) AS os_name,
+               MAX(vendor_class) AS vendor_class
+        FROM dhcp_requests
+        WHERE timestamp >= ?
+        GROUP BY mac_address
+        HAVING SUM(1 + repeats) >= ?
+        "#
+    )
+    .bind(since)
+    .bind(min_requests)
+    .fetch_all(pool)
+    .await
+}
+
+/// Rows are formatted into chunks of about this many bytes before being sent
+const EXPORT_CHUNK_SIZE: usize = 64 * 1024;
+/// Chunks buffered ahead of a slow client before the database read waits
+const EXPORT_CHANNEL_SIZE: usize = 4;
+
+const CSV_HEADER: &str = "timestamp,source_ip,source_port,mac_address,message_type,xid,fingerprint,vendor_class,\
+hostname,requested_ip,server_id,os_name,device_class,detection_method,confidence,smb_dialect,smb_build,vlan_id,repeats\n";
+
+/// Export every request matching the filters as CSV, JSON, NDJSON or pcap. Rows are read
+/// from the database and formatted in a background task as the receiver drains the chunks,
+/// so the result set is never held in memory; an error ends the export.
+pub fn export_requests(
+    pool: SqlitePool,
     filters: &QueryFilters,
     format: &str,
-) -> Result<String, sqlx::Error> {
-    // Query without pagination for export
-    let mut export_filters = filters.clone();
-    export_filters.page_size = 100000; // Large limit for export
+) -> mpsc::Receiver<Result<Vec<u8>, sqlx::Error>> {
+    let mut query = select_query(filters);
+    let format = format.to_string();
+    let (tx, rx) = mpsc::channel(EXPORT_CHANNEL_SIZE);
 
-    let requests = query_requests(pool, &export_filters).await?;
+    tokio::spawn(async move {
+        let mut rows = query.build_query_as::<DbDhcpRequest>().fetch(&pool);
+        let mut chunk = match format.as_str() {
+            "csv" => CSV_HEADER.as_bytes().to_vec(),
+            "ndjson" => Vec::new(),
+            "pcap" => crate::pcap::global_header(),
+            _ => b"[".to_vec(),
+        };
+        let mut first = true;
 
-    match format {
-        "csv" => Ok(export_as_csv(&requests)),
-        "json" => Ok(export_as_json(&requests)),
-        _ => Ok(export_as_json(&requests)),
-    }
-}
+        while let Some(row) = rows.next().await {
+            let request: DhcpRequest = match row {
+                Ok(row) => row.into(),
+                Err(e) => {
+                    let _ = tx.send(Err(e)).await;
+                    return;
+                }
+            };
+            match format.as_str() {
+                "csv" => chunk.extend_from_slice(csv_row(&request).as_bytes()),
+                "ndjson" => {
+                    chunk.extend_from_slice(serde_json::to_string(&request).unwrap_or_else(|_| "null".to_string()).as_bytes());
+                    chunk.push(b'\n');
+                }
+                // Requests stored without their payload are left out
+                "pcap" => chunk.extend_from_slice(&crate::pcap::record(&request).unwrap_or_default()),
+                _ => {
+                    chunk.extend_from_slice(if first { b"\n" } else { b",\n" });
+                    chunk.extend_from_slice(
+                        serde_json::to_string_pretty(&request).unwrap_or_else(|_| "null".to_string()).as_bytes(),
+                    );
+                }
+            }
+            first = false;
 
-fn export_as_csv(requests: &[DhcpRequest]) -> String {
-    let mut csv = String::from("timestamp,source_ip,source_port,mac_address,message_type,xid,fingerprint,vendor_class\n");
+            if chunk.len() >= EXPORT_CHUNK_SIZE && tx.send(Ok(std::mem::take(&mut chunk))).await.is_err() {
+                // Client went away
+                return;
+            }
+        }
 
-    for req in requests {
-        csv.push_str(&format!(
-            "{},{},{},{},{},{},{},{}\n",
-            req.timestamp,
-            req.source_ip,
-            req.source_port,
-            req.mac_address,
-            req.message_type,
-            req.xid,
-            escape_csv_field(&req.fingerprint),
-            req.vendor_class.as_ref().unwrap_or(&"-".to_string())
-        ));
-    }
+        if format == "json" {
+            chunk.extend_from_slice(b"\n]\n");
+        }
+        let _ = tx.send(Ok(chunk)).await;
+    });
 
-    csv
+    rx
 }
 
-fn export_as_json(requests: &[DhcpRequest]) -> String {
-    serde_json::to_string_pretty(&requests).unwrap_or_else(|_| "[]".to_string())
+fn csv_row(req: &DhcpRequest) -> String {
+    let optional = |value: Option<String>| value.map(|v| escape_csv_field(&v)).unwrap_or_default();
+    let fields = [
+        escape_csv_field(&req.timestamp),
+        escape_csv_field(&req.source_ip),
+        req.source_port.to_string(),
+        escape_csv_field(&req.mac_address),
+        escape_csv_field(&req.message_type),
+        escape_csv_field(&req.xid),
+        escape_csv_field(&req.fingerprint),
+        // The importer reads "-" as no vendor class
+        escape_csv_field(req.vendor_class.as_deref().unwrap_or("-")),
+        optional(req.hostname.clone()),
+        optional(req.requested_ip.clone()),
+        optional(req.server_id.clone()),
+        optional(req.os_name.clone()),
+        optional(req.device_class.clone()),
+        optional(req.detection_method.clone()),
+        optional(req.confidence.map(|c| c.to_string())),
+        optional(req.smb_dialect.clone()),
+        optional(req.smb_build.map(|b| b.to_string())),
+        optional(req.vlan_id.map(|v| v.to_string())),
+        req.repeats.to_string(),
+    ];
+    let mut row = fields.join(",");
+    row.push('\n');
+    row
 }
 
 fn escape_csv_field(field: &str) -> String {
-    if field.contains(',') || field.contains('"') || field.contains('\n') {
+    if field.contains([',', '"', '\n', '\r']) {
         format!("\"{}\"", field.replace('"', "\"\""))
     } else {
         field.to_string()
@@ -250,7 +975,390 @@ fn sanitize_column_name(column: &str) -> &str {
         "xid" => "xid",
         "fingerprint" => "fingerprint",
         "vendor_class" => "vendor_class",
+        "vlan_id" => "vlan_id",
+        "hostname" => "hostname",
+        "requested_ip" => "requested_ip",
+        "server_id" => "server_id",
         "created_at" => "created_at",
         _ => "timestamp", // Default to timestamp
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(timestamp: &str, ip: &str) -> DhcpRequest {
+        DhcpRequest {
+            timestamp: timestamp.to_string(),
+            source_ip: ip.to_string(),
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            message_type: "REQUEST".to_string(),
+            fingerprint: "1,3,6".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export_streams_all_rows() {
+        let pool = crate::db::test_pool().await;
+        let mut windows = request("2025-01-01T00:00:00+00:00", "10.0.0.5");
+        windows.os_name = Some("Windows 10/8/8.1".to_string());
+        windows.device_class = Some("Desktop, Laptop".to_string());
+        windows.raw_packet = Some(vec![1, 2, 3, 4]);
+        insert_request(&pool, &windows).await.unwrap();
+        insert_request(&pool, &request("2025-01-01T00:01:00+00:00", "10.0.0.6")).await.unwrap();
+
+        let filters = QueryFilters {
+            sort_by: "timestamp".to_string(),
+            sort_order: "ASC".to_string(),
+            ..Default::default()
+        };
+        let collect = |format: &'static str| {
+            let pool = pool.clone();
+            let filters = filters.clone();
+            async move {
+                let mut chunks = export_requests(pool, &filters, format);
+                let mut out = Vec::new();
+                while let Some(chunk) = chunks.recv().await {
+                    out.extend_from_slice(&chunk.unwrap());
+                }
+                out
+            }
+        };
+
+        let csv = String::from_utf8(collect("csv").await).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].contains(",Windows 10/8/8.1,\"Desktop, Laptop\","));
+        assert!(lines[1].contains("\"1,3,6\""));
+
+        let json: Vec<serde_json::Value> = serde_json::from_slice(&collect("json").await).unwrap();
+        assert_eq!(json.len(), 2);
+        assert_eq!(json[1]["source_ip"], "10.0.0.6");
+
+        let ndjson = String::from_utf8(collect("ndjson").await).unwrap();
+        let (requests, errors) = super::super::import::parse_requests(&ndjson, super::super::import::ImportFormat::Ndjson).unwrap();
+        assert_eq!((requests.len(), errors), (2, 0));
+        assert_eq!(requests[0].os_name.as_deref(), Some("Windows 10/8/8.1"));
+
+        // Only the request with a stored payload becomes a packet
+        let pcap = collect("pcap").await;
+        assert_eq!(pcap.len(), 24 + 16 + 14 + 20 + 8 + 4);
+    }
+
+    #[tokio::test]
+    async fn test_request_timeseries() {
+        use chrono::TimeZone;
+        let pool = crate::db::test_pool().await;
+        let at = |minute: u32, message_type: &str| DhcpRequest {
+            message_type: message_type.to_string(),
+            ..request(&format!("2025-01-01T00:{:02}:30.123456+00:00", minute), "10.0.0.5")
+        };
+        insert_request(&pool, &at(1, "DISCOVER")).await.unwrap();
+        insert_request(&pool, &at(2, "REQUEST")).await.unwrap();
+        insert_request(&pool, &at(12, "REQUEST")).await.unwrap();
+
+        let since = chrono::Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+        let until = since + chrono::Duration::minutes(15);
+        let buckets = request_timeseries(&pool, since, until, 300, Some(TimeseriesSplit::MessageType))
+            .await
+            .unwrap();
+        assert_eq!(buckets.len(), 4);
+        assert_eq!(buckets[0].start, "2025-01-01T00:00:00+00:00");
+        assert_eq!(buckets[0].requests, 2);
+        assert_eq!(buckets[0].split["DISCOVER"], 1);
+        assert_eq!(buckets[1].requests, 0);
+        assert_eq!(buckets[2].split["REQUEST"], 1);
+
+        let unsplit = request_timeseries(&pool, since, until, 900, None).await.unwrap();
+        assert_eq!(unsplit[0].requests, 3);
+        assert!(unsplit[0].split.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_option_field_filters() {
+        let pool = crate::db::test_pool().await;
+        let mut chosen = request("2025-01-01T00:00:00Z", "0.0.0.0");
+        chosen.requested_ip = Some("10.0.0.50".to_string());
+        chosen.server_id = Some("10.0.0.1".to_string());
+        chosen.hostname = Some("Bob's iPhone".to_string());
+        chosen.vendor_class = Some("MSFT 5.0".to_string());
+        chosen.vendor_options = crate::vendor_options::decode(&[0, 0, 0x11, 0x8b, 4, 2, 2, 3, 4]);
+        chosen.subnet = Some("10.1.20.0/24".to_string());
+        insert_request(&pool, &chosen).await.unwrap();
+        insert_request(&pool, &request("2025-01-01T00:01:00Z", "10.0.0.7")).await.unwrap();
+
+        let filters = QueryFilters { server_id: Some("10.0.0.1".parse().unwrap()), ..Default::default() };
+        let found = query_requests(&pool, &filters).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].requested_ip.as_deref(), Some("10.0.0.50"));
+
+        let filters = QueryFilters { requested_ip: Some("10.0.0.51".parse().unwrap()), ..Default::default() };
+        assert_eq!(count_requests(&pool, &filters).await.unwrap(), 0);
+
+        let filters = QueryFilters { hostname: Some("bob's".to_string()), ..Default::default() };
+        assert_eq!(count_requests(&pool, &filters).await.unwrap(), 1);
+        // Filter values are bound, so SQL in them is only ever compared as text
+        let filters = QueryFilters { message_type: Some("ACK' OR '1'='1".to_string()), ..Default::default() };
+        assert_eq!(count_requests(&pool, &filters).await.unwrap(), 0);
+        let filters = QueryFilters { start_date: Some("' OR 1=1 --".to_string()), ..Default::default() };
+        assert_eq!(query_requests(&pool, &filters).await.unwrap().len(), 2);
+        let filters = QueryFilters { end_date: Some("' OR 1=1 --".to_string()), ..Default::default() };
+        assert_eq!(count_requests(&pool, &filters).await.unwrap(), 0);
+        // LIKE wildcards in a filter match only themselves
+        let filters = QueryFilters { hostname: Some("bob_s".to_string()), ..Default::default() };
+        assert_eq!(count_requests(&pool, &filters).await.unwrap(), 0);
+        let filters = QueryFilters { vendor_class: Some("%".to_string()), ..Default::default() };
+        assert_eq!(count_requests(&pool, &filters).await.unwrap(), 0);
+
+        let filters = QueryFilters { enterprise: Some(4491), ..Default::default() };
+        let found = query_requests(&pool, &filters).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].vendor_options, chosen.vendor_options);
+        let filters = QueryFilters { enterprise: Some(3561), ..Default::default() };
+        assert_eq!(count_requests(&pool, &filters).await.unwrap(), 0);
+
+        let filters = QueryFilters { subnet: Some("10.1.20.0/24".to_string()), ..Default::default() };
+        let found = query_requests(&pool, &filters).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].subnet, chosen.subnet);
+    }
+
+    #[tokio::test]
+    async fn test_site_filter() {
+        let pool = crate::db::test_pool().await;
+        let mut remote = request("2025-01-01T00:01:00Z", "10.0.0.7");
+        remote.site_id = Some("branch-1".to_string());
+        insert_request(&pool, &remote).await.unwrap();
+        insert_request(&pool, &request("2025-01-01T00:00:00Z", "0.0.0.0")).await.unwrap();
+        insert_request(&pool, &request("2025-01-01T00:02:00Z", "0.0.0.0")).await.unwrap();
+
+        let filters = QueryFilters { site_id: Some("branch-1".to_string()), ..Default::default() };
+        let found = query_requests(&pool, &filters).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].site_id.as_deref(), Some("branch-1"));
+        let filters = QueryFilters { site_id: Some(LOCAL_SITE.to_string()), ..Default::default() };
+        assert_eq!(count_requests(&pool, &filters).await.unwrap(), 2);
+
+        let sites = site_summary(&pool).await.unwrap();
+        assert_eq!(sites.len(), 2);
+        assert_eq!((sites[0].site_id.as_deref(), sites[0].requests), (None, 2));
+        assert_eq!(sites[1].last_seen, "2025-01-01T00:01:00Z");
+    }
+
+    #[tokio::test]
+    async fn test_update_detection() {
+        let pool = crate::db::test_pool().await;
+        let mut stored = request("2025-01-01T00:00:00Z", "10.0.0.5");
+        stored.os_name = Some("Windows".to_string());
+        assert!(!update_detection(&pool, &stored).await.unwrap());
+
+        stored.id = Some(insert_request(&pool, &stored).await.unwrap());
+        stored.os_name = Some("Windows 11".to_string());
+        stored.smb_dialect = Some("SMB 3.1.1".to_string());
+        assert!(update_detection(&pool, &stored).await.unwrap());
+
+        let updated = request_by_id(&pool, stored.id.unwrap()).await.unwrap().unwrap();
+        assert_eq!(updated.os_name.as_deref(), Some("Windows 11"));
+        assert_eq!(updated.smb_dialect.as_deref(), Some("SMB 3.1.1"));
+    }
+
+    #[tokio::test]
+    async fn test_repeats_fold_into_stored_row() {
+        let pool = crate::db::test_pool().await;
+        let first = request("2025-01-01T00:00:00Z", "0.0.0.0");
+        assert!(!record_repeat(&pool, &first).await.unwrap());
+        insert_request(&pool, &first).await.unwrap();
+
+        record_repeat(&pool, &request("2025-01-01T00:00:05Z", "0.0.0.0")).await.unwrap();
+        assert!(record_repeat(&pool, &request("2025-01-01T00:00:10Z", "0.0.0.0")).await.unwrap());
+
+        let stored = query_requests(&pool, &QueryFilters::default()).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].repeats, 2);
+        assert_eq!(stored[0].last_repeat_at.as_deref(), Some("2025-01-01T00:00:10Z"));
+
+        // Aggregates count the folded requests
+        let since = "2025-01-01T00:00:00Z".parse().unwrap();
+        let until = "2025-01-01T00:01:00Z".parse().unwrap();
+        let buckets = request_timeseries(&pool, since, until, 3600, None).await.unwrap();
+        assert_eq!(buckets[0].requests, 3);
+    }
+
+    #[tokio::test]
+    async fn test_latest_for_mac_skips_zero_ip() {
+        let pool = crate::db::test_pool().await;
+        insert_request(&pool, &request("2025-01-01T00:00:00Z", "10.0.0.5")).await.unwrap();
+        insert_request(&pool, &request("2025-01-02T00:00:00Z", "0.0.0.0")).await.unwrap();
+
+        let (latest, ip) = latest_for_mac(&pool, "aa:bb:cc:dd:ee:ff").await.unwrap().unwrap();
+        assert_eq!(latest.timestamp, "2025-01-02T00:00:00Z");
+        assert_eq!(ip.as_deref(), Some("10.0.0.5"));
+
+        assert!(latest_for_mac(&pool, "11:22:33:44:55:66").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_timeline_queries() {
+        let pool = crate::db::test_pool().await;
+        let with_os = |timestamp: &str, ip: &str, os: &str| DhcpRequest {
+            os_name: Some(os.to_string()),
+            ..request(timestamp, ip)
+        };
+        insert_request(&pool, &with_os("2025-01-01T00:00:00Z", "0.0.0.0", "Windows 10")).await.unwrap();
+        insert_request(&pool, &with_os("2025-01-01T00:00:01Z", "10.0.0.5", "Windows 10")).await.unwrap();
+        insert_request(&pool, &with_os("2025-01-02T00:00:00Z", "0.0.0.0", "Windows 11")).await.unwrap();
+        insert_request(&pool, &with_os("2025-01-02T00:00:01Z", "10.0.0.9", "Windows 11")).await.unwrap();
+        let mut other = request("2025-01-01T12:00:00Z", "10.0.0.7");
+        other.mac_address = "11:22:33:44:55:66".to_string();
+        insert_request(&pool, &other).await.unwrap();
+
+        assert_eq!(count_for_mac(&pool, "aa:bb:cc:dd:ee:ff").await.unwrap(), 4);
+        let page = requests_for_mac(&pool, "aa:bb:cc:dd:ee:ff", 2, 1).await.unwrap();
+        let timestamps: Vec<&str> = page.iter().map(|r| r.timestamp.as_str()).collect();
+        assert_eq!(timestamps, vec!["2025-01-01T00:00:01Z", "2025-01-02T00:00:00Z"]);
+
+        assert_eq!(
+            os_changes_for_mac(&pool, "aa:bb:cc:dd:ee:ff").await.unwrap(),
+            vec![ValueChange {
+                timestamp: "2025-01-02T00:00:00Z".to_string(),
+                from: "Windows 10".to_string(),
+                to: "Windows 11".to_string(),
+            }]
+        );
+        assert_eq!(
+            ip_changes_for_mac(&pool, "aa:bb:cc:dd:ee:ff").await.unwrap(),
+            vec![ValueChange {
+                timestamp: "2025-01-02T00:00:01Z".to_string(),
+                from: "10.0.0.5".to_string(),
+                to: "10.0.0.9".to_string(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lint_report() {
+        let pool = crate::db::test_pool().await;
+        let mut iot = request("2025-01-01T00:00:00Z", "0.0.0.0");
+        iot.vendor_class = Some("udhcp 1.19".to_string());
+        iot.lint_issues = vec!["missing_end_option".to_string(), "reserved_flags_set".to_string()];
+        insert_request(&pool, &iot).await.unwrap();
+        iot.lint_issues = vec!["missing_end_option".to_string()];
+        insert_request(&pool, &iot).await.unwrap();
+        // Folded into the row above: two more flagged packets
+        assert!(record_repeat(&pool, &iot).await.unwrap());
+        assert!(record_repeat(&pool, &iot).await.unwrap());
+        iot.lint_issues.clear();
+        insert_request(&pool, &iot).await.unwrap();
+        insert_request(&pool, &request("2025-01-01T00:00:00Z", "0.0.0.0")).await.unwrap();
+
+        let report = lint_report(&pool, None).await.unwrap();
+        assert_eq!(report.len(), 2);
+        let worst = &report[0];
+        assert_eq!(worst.vendor_class.as_deref(), Some("udhcp 1.19"));
+        assert_eq!((worst.packets, worst.flagged), (5, 4));
+        assert_eq!(
+            worst.issues,
+            vec![("missing_end_option".to_string(), 4), ("reserved_flags_set".to_string(), 1)]
+        );
+        assert_eq!(report[1].quality, 100.0);
+
+        // Lint codes survive the round trip
+        let stored = requests_for_mac(&pool, "aa:bb:cc:dd:ee:ff", 1, 0).await.unwrap();
+        assert_eq!(stored[0].lint_issues, vec!["missing_end_option", "reserved_flags_set"]);
+
+        let malformed = QueryFilters { malformed: Some(true), ..Default::default() };
+        assert_eq!(count_requests(&pool, &malformed).await.unwrap(), 2);
+        let clean = QueryFilters { malformed: Some(false), ..Default::default() };
+        assert_eq!(count_requests(&pool, &clean).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_device_inventory() {
+        let pool = crate::db::test_pool().await;
+        insert_request(&pool, &request("2025-01-01T00:00:00Z", "10.0.0.5")).await.unwrap();
+        let mut latest = request("2025-01-02T00:00:00Z", "0.0.0.0");
+        latest.os_name = Some("Windows 11".to_string());
+        insert_request(&pool, &latest).await.unwrap();
+
+        // A client rotating its MAC but keeping its client identifier
+        let client_id = crate::client_id::ClientId::decode(&[0xff, 0, 0, 0, 1, 0, 4, 0, 1]);
+        let mut rotating = request("2025-01-01T00:00:00Z", "10.0.0.8");
+        rotating.mac_address = "02:00:00:00:00:01".to_string();
+        rotating.client_id = client_id.clone();
+        insert_request(&pool, &rotating).await.unwrap();
+        rotating.mac_address = "02:00:00:00:00:02".to_string();
+        rotating.timestamp = "2025-01-03T00:00:00Z".to_string();
+        insert_request(&pool, &rotating).await.unwrap();
+
+        let devices = device_inventory(&pool).await.unwrap();
+        assert_eq!(devices.len(), 2);
+
+        let rotated = &devices[0];
+        assert_eq!(rotated.client_key, client_id.unwrap().key);
+        assert_eq!(rotated.mac_address, "02:00:00:00:00:02");
+        assert_eq!(rotated.mac_count, 2);
+
+        let device = &devices[1];
+        assert_eq!(device.client_key, "aa:bb:cc:dd:ee:ff");
+        assert_eq!(device.requests, 2);
+        assert_eq!(device.last_ip.as_deref(), Some("10.0.0.5"));
+        assert_eq!(device.os_name.as_deref(), Some("Windows 11"));
+        assert_eq!(device.first_seen, "2025-01-01T00:00:00Z");
+        assert!(!device.no_dhcp);
+
+        // A static host found by a sweep
+        crate::db::sweep::record_sighting(&pool, "aa:bb:cc:00:00:09", "10.0.0.9", "arp", "2025-01-04T00:00:00Z")
+            .await
+            .unwrap();
+        let devices = device_inventory(&pool).await.unwrap();
+        assert_eq!(devices.len(), 3);
+        assert!(devices[0].no_dhcp);
+        assert_eq!((devices[0].last_ip.as_deref(), devices[0].requests), (Some("10.0.0.9"), 0));
+    }
+
+    #[tokio::test]
+    async fn test_option_statistics() {
+        use crate::dhcp::DhcpOption;
+
+        let pool = crate::db::test_pool().await;
+        let option = |code: u8| DhcpOption { code, data: vec![0] };
+        let mut windows = request("2025-01-01T00:00:00Z", "10.0.0.5");
+        windows.vendor_class = Some("MSFT 5.0".to_string());
+        windows.raw_options = vec![option(53), option(55), option(60)];
+        let mut linux = request("2025-01-01T00:00:01Z", "10.0.0.6");
+        linux.mac_address = "11:22:33:44:55:66".to_string();
+        linux.raw_options = vec![option(53), option(55)];
+        let mut old = linux.clone();
+        old.timestamp = "2024-01-01T00:00:00Z".to_string();
+        old.raw_options = vec![option(53), option(82)];
+
+        for r in [&windows, &linux, &old] {
+            insert_request(&pool, r).await.unwrap();
+        }
+        // Two repeats of the Windows request folded into its row
+        assert!(record_repeat(&pool, &windows).await.unwrap());
+        assert!(record_repeat(&pool, &windows).await.unwrap());
+
+        let (total, usage) = option_statistics(&pool, Some("2025-01-01T00:00:00Z"), None, 10)
+            .await
+            .unwrap();
+        assert_eq!(total, 4);
+        let codes: Vec<u8> = usage.iter().map(|u| u.code).collect();
+        assert_eq!(codes, vec![53, 55, 60]);
+
+        let option_55 = &usage[1];
+        assert_eq!(option_55.name, Some("Parameter Request List"));
+        assert_eq!(option_55.requests, 4);
+        assert_eq!(option_55.devices, 2);
+        assert_eq!(option_55.percent, 100.0);
+        assert_eq!(option_55.vendors.len(), 2);
+        assert_eq!(option_55.vendors[0].requests, 3);
+
+        let option_60 = &usage[2];
+        assert_eq!(option_60.requests, 3);
+        assert_eq!(option_60.percent, 75.0);
+        assert_eq!(option_60.vendors[0].vendor_class.as_deref(), Some("MSFT 5.0"));
+    }
+}
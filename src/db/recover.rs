@@ -0,0 +1,129 @@
+use super::models::DbDhcpRequest;
+use super::queries;
+use crate::dhcp::DhcpRequest;
+use anyhow::{anyhow, Context, Result};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::SqlitePool;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Rows read per query; a chunk that fails is retried row by row
+const CHUNK_SIZE: i64 = 1000;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct RecoverySummary {
+    pub recovered: u64,
+    /// Rows that could not be read from the damaged database
+    pub lost: u64,
+}
+
+/// Id range to scan; falls back to the AUTOINCREMENT counter when the table can't be aggregated
+async fn id_bounds(source: &SqlitePool) -> Result<(i64, i64)> {
+    let bounds: Result<(Option<i64>, Option<i64>), _> =
+        sqlx::query_as("SELECT MIN(id), MAX(id) FROM dhcp_requests")
+            .fetch_one(source)
+            .await;
+    if let Ok((min, max)) = bounds {
+        return Ok((min.unwrap_or(1), max.unwrap_or(0)));
+    }
+
+    let (seq,): (i64,) = sqlx::query_as("SELECT seq FROM sqlite_sequence WHERE name = 'dhcp_requests'")
+        .fetch_one(source)
+        .await
+        .context("could not determine the id range of dhcp_requests")?;
+    Ok((1, seq))
+}
+
+/// Copy every readable request from a damaged database into a new one
+pub async fn recover_database(source_path: &str, target_path: &str) -> Result<RecoverySummary> {
+    if Path::new(target_path).exists() {
+        return Err(anyhow!("{} already exists; recover into a new file", target_path));
+    }
+
+    let source = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(SqliteConnectOptions::from_str(&format!("sqlite:{}", source_path))?.read_only(true))
+        .await
+        .with_context(|| format!("failed to open {}", source_path))?;
+    let target = super::create_pool(&format!("sqlite:{}", target_path)).await?;
+
+    let (min_id, max_id) = id_bounds(&source).await?;
+    let mut summary = RecoverySummary::default();
+
+    let mut start = min_id;
+    while start <= max_id {
+        let end = (start + CHUNK_SIZE - 1).min(max_id);
+
+        let rows: Vec<DbDhcpRequest> = match sqlx::query_as("SELECT * FROM dhcp_requests WHERE id BETWEEN ? AND ?")
+            .bind(start)
+            .bind(end)
+            .fetch_all(&source)
+            .await
+        {
+            Ok(rows) => rows,
+            Err(_) => {
+                // Damaged page somewhere in this range: salvage what we can row by row
+                let mut rows = Vec::new();
+                for id in start..=end {
+                    match sqlx::query_as("SELECT * FROM dhcp_requests WHERE id = ?")
+                        .bind(id)
+                        .fetch_optional(&source)
+                        .await
+                    {
+                        Ok(Some(row)) => rows.push(row),
+                        Ok(None) => {}
+                        Err(_) => summary.lost += 1,
+                    }
+                }
+                rows
+            }
+        };
+
+        let mut tx = target.begin().await?;
+        for row in rows {
+            let request: DhcpRequest = row.into();
+            queries::insert_request(&mut *tx, &request).await?;
+            summary.recovered += 1;
+        }
+        tx.commit().await?;
+
+        start = end + 1;
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_recover_healthy_database() {
+        let dir = std::env::temp_dir().join(format!("ks-dhcpmon-recover-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source_path = dir.join("source.db");
+        let target_path = dir.join("recovered.db");
+        let _ = std::fs::remove_file(&source_path);
+        let _ = std::fs::remove_file(&target_path);
+
+        let source = super::super::create_pool(&format!("sqlite:{}", source_path.display())).await.unwrap();
+        for i in 0..2500 {
+            let request = DhcpRequest {
+                xid: format!("{:08x}", i),
+                ..Default::default()
+            };
+            queries::insert_request(&source, &request).await.unwrap();
+        }
+        source.close().await;
+
+        let summary = recover_database(source_path.to_str().unwrap(), target_path.to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(summary, RecoverySummary { recovered: 2500, lost: 0 });
+
+        // Refuses to overwrite an existing file
+        assert!(recover_database(source_path.to_str().unwrap(), target_path.to_str().unwrap())
+            .await
+            .is_err());
+    }
+}
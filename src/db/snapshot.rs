@@ -0,0 +1,201 @@
+use super::models::DbDhcpRequest;
+use crate::dhcp::DhcpRequest;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::net::Ipv4Addr;
+
+/// What was known about a device at a point in time
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct DeviceSnapshot {
+    pub mac_address: String,
+    pub as_of: String,
+    /// Requests seen up to `as_of`
+    pub requests: i64,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub last_message_type: String,
+    pub hostname: Option<String>,
+    pub os_name: Option<String>,
+    pub device_class: Option<String>,
+    pub vendor_class: Option<String>,
+    /// Last non-0.0.0.0 source IP and when it was seen
+    pub ip_address: Option<String>,
+    pub ip_seen_at: Option<String>,
+    pub lease: Option<LeaseSnapshot>,
+    /// Name, location, owner and tags as currently set through the API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<super::device_meta::DeviceMeta>,
+}
+
+/// The client's side of its lease: the last address it asked for
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct LeaseSnapshot {
+    /// Option 50, or ciaddr-derived source IP when renewing
+    pub requested_ip: Option<String>,
+    /// Option 51 lease time, when the client asked for one
+    pub requested_lease_secs: Option<u32>,
+    pub requested_at: String,
+    /// A RELEASE was seen after the last REQUEST
+    pub released: bool,
+}
+
+// Most recent request at or before `as_of` that matches `condition`
+async fn latest_matching(
+    pool: &SqlitePool,
+    mac_address: &str,
+    as_of: &str,
+    condition: &str,
+) -> Result<Option<DhcpRequest>, sqlx::Error> {
+    let row: Option<DbDhcpRequest> = sqlx::query_as(&format!(
+        "SELECT * FROM dhcp_requests WHERE mac_address = ? AND timestamp <= ? AND ({})
+         ORDER BY timestamp DESC, id DESC LIMIT 1",
+        condition
+    ))
+    .bind(mac_address)
+    .bind(as_of)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(Into::into))
+}
+
+fn option_data(request: &DhcpRequest, code: u8) -> Option<&[u8]> {
+    request
+        .raw_options
+        .iter()
+        .find(|opt| opt.code == code)
+        .map(|opt| opt.data.as_slice())
+}
+
+/// Reconstruct a device's state as of `as_of` (RFC 3339) from stored requests
+pub async fn device_snapshot(
+    pool: &SqlitePool,
+    mac_address: &str,
+    as_of: &str,
+) -> Result<Option<DeviceSnapshot>, sqlx::Error> {
+    let Some(latest) = latest_matching(pool, mac_address, as_of, "1").await? else {
+        return Ok(None);
+    };
+
+    let (requests, first_seen): (i64, String) = sqlx::query_as(
+        "SELECT COUNT(*), MIN(timestamp) FROM dhcp_requests WHERE mac_address = ? AND timestamp <= ?",
+    )
+    .bind(mac_address)
+    .bind(as_of)
+    .fetch_one(pool)
+    .await?;
+
+    let with_os = latest_matching(pool, mac_address, as_of, "os_name IS NOT NULL").await?;
+    let with_ip = latest_matching(pool, mac_address, as_of, "source_ip != '0.0.0.0'").await?;
+    let with_hostname = latest_matching(pool, mac_address, as_of, "hostname IS NOT NULL").await?;
+    let lease_event = latest_matching(pool, mac_address, as_of, "message_type IN ('REQUEST', 'RELEASE')").await?;
+    let last_request = match &lease_event {
+        Some(event) if event.message_type == "RELEASE" => {
+            latest_matching(pool, mac_address, as_of, "message_type = 'REQUEST'").await?
+        }
+        _ => lease_event.clone(),
+    };
+
+    let lease = last_request.map(|request| {
+        let requested_ip = option_data(&request, 50)
+            .and_then(|data| <[u8; 4]>::try_from(data).ok())
+            .map(|octets| Ipv4Addr::from(octets).to_string())
+            .or_else(|| (request.source_ip != "0.0.0.0").then(|| request.source_ip.clone()));
+        let requested_lease_secs = option_data(&request, 51)
+            .and_then(|data| <[u8; 4]>::try_from(data).ok())
+            .map(u32::from_be_bytes);
+        LeaseSnapshot {
+            requested_ip,
+            requested_lease_secs,
+            requested_at: request.timestamp,
+            released: lease_event.as_ref().is_some_and(|event| event.message_type == "RELEASE"),
+        }
+    });
+
+    let (os_name, device_class) = match with_os {
+        Some(request) => (request.os_name, request.device_class),
+        None => (None, None),
+    };
+
+    Ok(Some(DeviceSnapshot {
+        mac_address: mac_address.to_string(),
+        as_of: as_of.to_string(),
+        requests,
+        first_seen,
+        last_seen: latest.timestamp,
+        last_message_type: latest.message_type,
+        hostname: with_hostname.and_then(|request| request.hostname),
+        os_name,
+        device_class,
+        vendor_class: latest.vendor_class,
+        ip_address: with_ip.as_ref().map(|request| request.source_ip.clone()),
+        ip_seen_at: with_ip.map(|request| request.timestamp),
+        lease,
+        meta: super::device_meta::get_meta(pool, mac_address).await?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::queries::insert_request;
+    use crate::dhcp::DhcpOption;
+
+    fn request(timestamp: &str, message_type: &str, ip: &str, options: Vec<DhcpOption>) -> DhcpRequest {
+        DhcpRequest {
+            timestamp: timestamp.to_string(),
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            message_type: message_type.to_string(),
+            source_ip: ip.to_string(),
+            raw_options: options,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_at_past_time() {
+        let pool = crate::db::test_pool().await;
+        let requested_ip = DhcpOption { code: 50, data: vec![10, 0, 0, 5] };
+
+        let mut first = request("2025-01-01T00:00:00+00:00", "REQUEST", "0.0.0.0", vec![requested_ip]);
+        first.hostname = Some("laptop".to_string());
+        first.os_name = Some("Windows 10".to_string());
+        insert_request(&pool, &first).await.unwrap();
+        insert_request(&pool, &request("2025-01-01T06:00:00+00:00", "REQUEST", "10.0.0.5", vec![])).await.unwrap();
+        insert_request(&pool, &request("2025-01-02T00:00:00+00:00", "RELEASE", "10.0.0.5", vec![])).await.unwrap();
+        let mut renamed = request("2025-02-01T00:00:00+00:00", "REQUEST", "10.0.0.9", vec![]);
+        renamed.hostname = Some("laptop-new".to_string());
+        renamed.os_name = Some("Windows 11".to_string());
+        insert_request(&pool, &renamed).await.unwrap();
+
+        let snapshot = device_snapshot(&pool, "aa:bb:cc:dd:ee:ff", "2025-01-01T12:00:00+00:00")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(snapshot.requests, 2);
+        assert_eq!(snapshot.hostname.as_deref(), Some("laptop"));
+        assert_eq!(snapshot.os_name.as_deref(), Some("Windows 10"));
+        assert_eq!(snapshot.ip_address.as_deref(), Some("10.0.0.5"));
+        let lease = snapshot.lease.unwrap();
+        assert_eq!(lease.requested_ip.as_deref(), Some("10.0.0.5"));
+        assert!(!lease.released);
+
+        let released = device_snapshot(&pool, "aa:bb:cc:dd:ee:ff", "2025-01-15T00:00:00+00:00")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(released.lease.unwrap().released);
+
+        let now = device_snapshot(&pool, "aa:bb:cc:dd:ee:ff", "2025-03-01T00:00:00+00:00")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(now.hostname.as_deref(), Some("laptop-new"));
+        assert_eq!(now.ip_address.as_deref(), Some("10.0.0.9"));
+
+        // Before the device was first seen
+        assert!(device_snapshot(&pool, "aa:bb:cc:dd:ee:ff", "2024-12-31T00:00:00+00:00")
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
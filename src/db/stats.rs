@@ -0,0 +1,195 @@
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use std::collections::HashMap;
+
+/// Cumulative request counters, used to seed the in-memory statistics on startup
+#[derive(Debug, Default, PartialEq)]
+pub struct StatsTotals {
+    pub total_requests: u64,
+    pub request_types: HashMap<String, u64>,
+    pub vendor_classes: HashMap<String, u64>,
+    pub vlans: HashMap<u16, u64>,
+    pub subnets: HashMap<String, u64>,
+    pub macs: Vec<String>,
+}
+
+impl StatsTotals {
+    fn add(&mut self, other: StatsTotals) {
+        self.total_requests += other.total_requests;
+        for (key, count) in other.request_types {
+            *self.request_types.entry(key).or_insert(0) += count;
+        }
+        for (key, count) in other.vendor_classes {
+            *self.vendor_classes.entry(key).or_insert(0) += count;
+        }
+        for (key, count) in other.vlans {
+            *self.vlans.entry(key).or_insert(0) += count;
+        }
+        for (key, count) in other.subnets {
+            *self.subnets.entry(key).or_insert(0) += count;
+        }
+    }
+}
+
+async fn grouped_counts(
+    tx: &mut Transaction<'_, Sqlite>,
+    column: &str,
+    after_id: i64,
+) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT CAST({0} AS TEXT), SUM(1 + repeats) FROM dhcp_requests
+         WHERE {0} IS NOT NULL AND id > ?
+         GROUP BY {0}",
+        column
+    ))
+    .bind(after_id)
+    .fetch_all(&mut **tx)
+    .await
+}
+
+/// Counters for stored requests with ids above `after_id`, including their
+/// deduplicated repeats (repeats counted on a row after it was checkpointed are missed)
+async fn aggregate_requests(tx: &mut Transaction<'_, Sqlite>, after_id: i64) -> Result<StatsTotals, sqlx::Error> {
+    let (total,): (i64,) = sqlx::query_as("SELECT COALESCE(SUM(1 + repeats), 0) FROM dhcp_requests WHERE id > ?")
+        .bind(after_id)
+        .fetch_one(&mut **tx)
+        .await?;
+
+    let counts = |rows: Vec<(String, i64)>| rows.into_iter().map(|(key, count)| (key, count as u64)).collect();
+    Ok(StatsTotals {
+        total_requests: total as u64,
+        request_types: counts(grouped_counts(tx, "message_type", after_id).await?),
+        vendor_classes: counts(grouped_counts(tx, "vendor_class", after_id).await?),
+        vlans: grouped_counts(tx, "vlan_id", after_id)
+            .await?
+            .into_iter()
+            .filter_map(|(vlan, count)| Some((vlan.parse().ok()?, count as u64)))
+            .collect(),
+        subnets: counts(grouped_counts(tx, "subnet", after_id).await?),
+        macs: Vec::new(),
+    })
+}
+
+/// The stored checkpoint and the last request id it covers
+async fn read_checkpoint(tx: &mut Transaction<'_, Sqlite>) -> Result<(StatsTotals, i64), sqlx::Error> {
+    let row: Option<(i64, i64, String, String, String, String)> = sqlx::query_as(
+        "SELECT last_id, total_requests, request_types, vendor_classes, vlans, subnets FROM stats_checkpoint WHERE id = 1"
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok(match row {
+        Some((last_id, total_requests, request_types, vendor_classes, vlans, subnets)) => (
+            StatsTotals {
+                total_requests: total_requests as u64,
+                request_types: serde_json::from_str(&request_types).unwrap_or_default(),
+                vendor_classes: serde_json::from_str(&vendor_classes).unwrap_or_default(),
+                vlans: serde_json::from_str(&vlans).unwrap_or_default(),
+                subnets: serde_json::from_str(&subnets).unwrap_or_default(),
+                macs: Vec::new(),
+            },
+            last_id,
+        ),
+        None => (StatsTotals::default(), 0),
+    })
+}
+
+/// Cumulative statistics: the last checkpoint plus requests stored after it
+pub async fn load_totals(pool: &SqlitePool) -> Result<StatsTotals, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let (mut totals, last_id) = read_checkpoint(&mut tx).await?;
+    totals.add(aggregate_requests(&mut tx, last_id).await?);
+
+    let macs: Vec<(String,)> = sqlx::query_as("SELECT DISTINCT mac_address FROM dhcp_requests")
+        .fetch_all(&mut *tx)
+        .await?;
+    totals.macs = macs.into_iter().map(|(mac,)| mac).collect();
+    tx.commit().await?;
+    Ok(totals)
+}
+
+/// Roll requests stored since the last checkpoint into it, so cumulative
+/// counts survive restarts and requests being deleted
+pub async fn save_checkpoint(pool: &SqlitePool, taken_at: &str) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let (mut totals, last_id) = read_checkpoint(&mut tx).await?;
+    let (max_id,): (Option<i64>,) = sqlx::query_as("SELECT MAX(id) FROM dhcp_requests")
+        .fetch_one(&mut *tx)
+        .await?;
+    let max_id = max_id.unwrap_or(0).max(last_id);
+    if max_id == last_id {
+        return tx.commit().await;
+    }
+    totals.add(aggregate_requests(&mut tx, last_id).await?);
+
+    sqlx::query(
+        r#"
+        INSERT INTO stats_checkpoint (id, last_id, taken_at, total_requests, request_types, vendor_classes, vlans, subnets)
+        VALUES (1, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            last_id = excluded.last_id,
+            taken_at = excluded.taken_at,
+            total_requests = excluded.total_requests,
+            request_types = excluded.request_types,
+            vendor_classes = excluded.vendor_classes,
+            vlans = excluded.vlans,
+            subnets = excluded.subnets
+        "#
+    )
+    .bind(max_id)
+    .bind(taken_at)
+    .bind(totals.total_requests as i64)
+    .bind(serde_json::to_string(&totals.request_types).unwrap_or_default())
+    .bind(serde_json::to_string(&totals.vendor_classes).unwrap_or_default())
+    .bind(serde_json::to_string(&totals.vlans).unwrap_or_default())
+    .bind(serde_json::to_string(&totals.subnets).unwrap_or_default())
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::queries::insert_request;
+    use crate::dhcp::DhcpRequest;
+
+    fn request(mac: &str, message_type: &str) -> DhcpRequest {
+        DhcpRequest {
+            timestamp: "2025-01-01T00:00:00+00:00".to_string(),
+            mac_address: mac.to_string(),
+            message_type: message_type.to_string(),
+            vendor_class: Some("MSFT 5.0".to_string()),
+            vlan_id: Some(20),
+            subnet: Some("10.1.20.0/24".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_totals_from_requests_and_checkpoint() {
+        let pool = crate::db::test_pool().await;
+        insert_request(&pool, &request("aa:aa:aa:aa:aa:01", "DISCOVER")).await.unwrap();
+        insert_request(&pool, &request("aa:aa:aa:aa:aa:01", "REQUEST")).await.unwrap();
+
+        let totals = load_totals(&pool).await.unwrap();
+        assert_eq!(totals.total_requests, 2);
+        assert_eq!(totals.request_types["DISCOVER"], 1);
+        assert_eq!(totals.vendor_classes["MSFT 5.0"], 2);
+        assert_eq!(totals.vlans[&20], 2);
+        assert_eq!(totals.macs, vec!["aa:aa:aa:aa:aa:01".to_string()]);
+
+        // Checkpointed counts outlive the rows they came from
+        save_checkpoint(&pool, "2025-01-01T00:05:00+00:00").await.unwrap();
+        save_checkpoint(&pool, "2025-01-01T00:10:00+00:00").await.unwrap();
+        sqlx::query("DELETE FROM dhcp_requests").execute(&pool).await.unwrap();
+        insert_request(&pool, &request("aa:aa:aa:aa:aa:02", "REQUEST")).await.unwrap();
+
+        let totals = load_totals(&pool).await.unwrap();
+        assert_eq!(totals.total_requests, 3);
+        assert_eq!(totals.request_types["DISCOVER"], 1);
+        assert_eq!(totals.request_types["REQUEST"], 2);
+        assert_eq!(totals.vlans[&20], 3);
+        assert_eq!(totals.subnets["10.1.20.0/24"], 3);
+        assert_eq!(totals.macs, vec!["aa:aa:aa:aa:aa:02".to_string()]);
+    }
+}
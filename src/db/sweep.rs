@@ -0,0 +1,78 @@
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+
+/// A host that answered a subnet sweep, whether or not it uses DHCP
+#[derive(Debug, Clone, PartialEq, FromRow, Serialize)]
+pub struct SweepSighting {
+    pub mac_address: String,
+    pub ip_address: String,
+    /// "icmp" or "arp"
+    pub method: String,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+/// Record that `mac_address` answered at `ip_address`
+pub async fn record_sighting(
+    pool: &SqlitePool,
+    mac_address: &str,
+    ip_address: &str,
+    method: &str,
+    seen_at: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO sweep_sightings (mac_address, ip_address, method, first_seen, last_seen) VALUES (?, ?, ?, ?, ?)
+         ON CONFLICT (mac_address) DO UPDATE SET
+             ip_address = excluded.ip_address, method = excluded.method, last_seen = excluded.last_seen",
+    )
+    .bind(mac_address)
+    .bind(ip_address)
+    .bind(method)
+    .bind(seen_at)
+    .bind(seen_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Swept hosts that never sent a DHCP request, most recently seen first
+pub async fn without_dhcp(pool: &SqlitePool) -> Result<Vec<SweepSighting>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT s.mac_address, s.ip_address, s.method, s.first_seen, s.last_seen FROM sweep_sightings s
+         WHERE NOT EXISTS (SELECT 1 FROM dhcp_requests r WHERE r.mac_address = s.mac_address)
+         ORDER BY s.last_seen DESC",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sightings_without_dhcp() {
+        let pool = crate::db::test_pool().await;
+        record_sighting(&pool, "aa:bb:cc:00:00:01", "10.0.0.2", "icmp", "2025-01-01T00:00:00+00:00")
+            .await
+            .unwrap();
+        record_sighting(&pool, "aa:bb:cc:00:00:01", "10.0.0.3", "arp", "2025-01-02T00:00:00+00:00")
+            .await
+            .unwrap();
+
+        let static_hosts = without_dhcp(&pool).await.unwrap();
+        assert_eq!(static_hosts.len(), 1);
+        assert_eq!(static_hosts[0].ip_address, "10.0.0.3");
+        assert_eq!(static_hosts[0].first_seen, "2025-01-01T00:00:00+00:00");
+
+        // Once it asks DHCP it is an ordinary device
+        sqlx::query(
+            "INSERT INTO dhcp_requests (timestamp, source_ip, source_port, mac_address, message_type, xid, fingerprint, raw_options)
+             VALUES ('2025-01-03T00:00:00+00:00', '0.0.0.0', 68, 'aa:bb:cc:00:00:01', 'DISCOVER', '0x1', '', '[]')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        assert!(without_dhcp(&pool).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+use super::queries::DeviceSummary;
+use crate::dhcp::DhcpRequest;
+use rust_xlsxwriter::{Chart, ChartType, Color, Format, FormatBorder, Workbook, Worksheet, XlsxError};
+use std::collections::HashMap;
+
+/// Spreadsheet MIME type for downloads
+pub const CONTENT_TYPE: &str = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+
+fn header_format() -> Format {
+    Format::new()
+        .set_bold()
+        .set_font_color(Color::White)
+        .set_background_color(Color::RGB(0x1E293B))
+        .set_border(FormatBorder::Thin)
+}
+
+// Bold header row, frozen below, with an autofilter over the data
+fn write_header(sheet: &mut Worksheet, headers: &[(&str, f64)], rows: usize) -> Result<(), XlsxError> {
+    let format = header_format();
+    for (col, (name, width)) in headers.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, *name, &format)?;
+        sheet.set_column_width(col as u16, *width)?;
+    }
+    sheet.set_freeze_panes(1, 0)?;
+    sheet.autofilter(0, 0, rows.max(1) as u32, headers.len() as u16 - 1)?;
+    Ok(())
+}
+
+/// Count occurrences, most common first
+fn tally<'a>(values: impl Iterator<Item = &'a str>) -> Vec<(&'a str, u32)> {
+    let mut counts: HashMap<&str, u32> = HashMap::new();
+    for value in values {
+        *counts.entry(value).or_insert(0) += 1;
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    counts
+}
+
+// Summary sheet: one two-column table per breakdown, each with a chart beside it
+fn write_summary(
+    workbook: &mut Workbook,
+    title: &str,
+    breakdowns: &[(&str, Vec<(&str, u32)>)],
+) -> Result<(), XlsxError> {
+    let header = header_format();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Summary")?;
+    sheet.write_string_with_format(0, 0, title, &Format::new().set_bold().set_font_size(14))?;
+    sheet.write_string(1, 0, format!("Generated {}", chrono::Utc::now().format("%Y-%m-%d %H:%M UTC")))?;
+    sheet.set_column_width(0, 32)?;
+    sheet.set_column_width(1, 12)?;
+
+    let mut row = 3u32;
+    for (name, counts) in breakdowns {
+        sheet.write_string_with_format(row, 0, *name, &header)?;
+        sheet.write_string_with_format(row, 1, "Count", &header)?;
+        for (i, (value, count)) in counts.iter().enumerate() {
+            sheet.write_string(row + 1 + i as u32, 0, *value)?;
+            sheet.write_number(row + 1 + i as u32, 1, *count)?;
+        }
+
+        if !counts.is_empty() {
+            let last = row + counts.len() as u32;
+            let mut chart = Chart::new(ChartType::Bar);
+            chart.title().set_name(*name);
+            chart.legend().set_hidden();
+            chart
+                .add_series()
+                .set_categories(("Summary", row + 1, 0, last, 0))
+                .set_values(("Summary", row + 1, 1, last, 1));
+            sheet.insert_chart(row, 3, &chart)?;
+        }
+
+        // Leave room for the chart (about 15 rows) below short tables
+        row += (counts.len() as u32 + 3).max(17);
+    }
+    Ok(())
+}
+
+/// Request log workbook: a Requests sheet plus a Summary sheet
+pub fn logs_workbook(requests: &[DhcpRequest]) -> Result<Vec<u8>, XlsxError> {
+    let mut workbook = Workbook::new();
+
+    let breakdowns = [
+        ("Message Type", tally(requests.iter().map(|r| r.message_type.as_str()))),
+        ("Operating System", tally(requests.iter().map(|r| r.os_name.as_deref().unwrap_or("Unknown")))),
+        ("Vendor Class", tally(requests.iter().map(|r| r.vendor_class.as_deref().unwrap_or("-")))),
+    ];
+    write_summary(&mut workbook, "DHCP Requests", &breakdowns)?;
+
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Requests")?;
+    write_header(
+        sheet,
+        &[
+            ("Timestamp", 28.0),
+            ("Source IP", 15.0),
+            ("MAC Address", 19.0),
+            ("Message Type", 13.0),
+            ("XID", 10.0),
+            ("Fingerprint", 40.0),
+            ("Vendor Class", 30.0),
+            ("OS", 24.0),
+            ("Device Class", 16.0),
+            ("Confidence", 11.0),
+            ("Detection Method", 18.0),
+            ("SMB Dialect", 12.0),
+            ("SMB Build", 10.0),
+            ("VLAN", 7.0),
+            ("Requested IP", 15.0),
+            ("Server ID", 15.0),
+            ("Hostname", 22.0),
+            ("Repeats", 8.0),
+        ],
+        requests.len(),
+    )?;
+
+    for (i, r) in requests.iter().enumerate() {
+        let row = i as u32 + 1;
+        sheet.write_string(row, 0, &r.timestamp)?;
+        sheet.write_string(row, 1, &r.source_ip)?;
+        sheet.write_string(row, 2, &r.mac_address)?;
+        sheet.write_string(row, 3, &r.message_type)?;
+        sheet.write_string(row, 4, &r.xid)?;
+        sheet.write_string(row, 5, &r.fingerprint)?;
+        sheet.write(row, 6, r.vendor_class.as_deref())?;
+        sheet.write(row, 7, r.os_name.as_deref())?;
+        sheet.write(row, 8, r.device_class.as_deref())?;
+        sheet.write(row, 9, r.confidence)?;
+        sheet.write(row, 10, r.detection_method.as_deref())?;
+        sheet.write(row, 11, r.smb_dialect.as_deref())?;
+        sheet.write(row, 12, r.smb_build)?;
+        sheet.write(row, 13, r.vlan_id)?;
+        sheet.write(row, 14, r.requested_ip.as_deref())?;
+        sheet.write(row, 15, r.server_id.as_deref())?;
+        sheet.write(row, 16, r.hostname.as_deref())?;
+        sheet.write(row, 17, r.repeats)?;
+    }
+
+    workbook.save_to_buffer()
+}
+
+/// Device inventory workbook: a Devices sheet plus a Summary sheet
+pub fn devices_workbook(devices: &[DeviceSummary]) -> Result<Vec<u8>, XlsxError> {
+    let mut workbook = Workbook::new();
+
+    let breakdowns = [
+        ("Operating System", tally(devices.iter().map(|d| d.os_name.as_deref().unwrap_or("Unknown")))),
+        ("Device Class", tally(devices.iter().map(|d| d.device_class.as_deref().unwrap_or("Unknown")))),
+    ];
+    write_summary(&mut workbook, "Device Inventory", &breakdowns)?;
+
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Devices")?;
+    write_header(
+        sheet,
+        &[
+            ("Client Key", 30.0),
+            ("MAC Address", 19.0),
+            ("MACs Seen", 10.0),
+            ("Last IP", 15.0),
+            ("OS", 24.0),
+            ("Device Class", 16.0),
+            ("Vendor Class", 30.0),
+            ("VLAN", 7.0),
+            ("Requests", 10.0),
+            ("First Seen", 28.0),
+            ("Last Seen", 28.0),
+            ("Assigned IP", 15.0),
+            ("IP Changes", 10.0),
+        ],
+        devices.len(),
+    )?;
+
+    for (i, d) in devices.iter().enumerate() {
+        let row = i as u32 + 1;
+        sheet.write_string(row, 0, &d.client_key)?;
+        sheet.write_string(row, 1, &d.mac_address)?;
+        sheet.write_number(row, 2, d.mac_count as f64)?;
+        sheet.write(row, 3, d.last_ip.as_deref())?;
+        sheet.write(row, 4, d.os_name.as_deref())?;
+        sheet.write(row, 5, d.device_class.as_deref())?;
+        sheet.write(row, 6, d.vendor_class.as_deref())?;
+        sheet.write(row, 7, d.vlan_id.map(|v| v as f64))?;
+        sheet.write_number(row, 8, d.requests as f64)?;
+        sheet.write_string(row, 9, &d.first_seen)?;
+        sheet.write_string(row, 10, &d.last_seen)?;
+        sheet.write(row, 11, d.assigned_ip.as_deref())?;
+        sheet.write_number(row, 12, d.ip_changes as f64)?;
+    }
+
+    workbook.save_to_buffer()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tally_orders_by_count() {
+        let counts = tally(["b", "a", "b", "c", "b", "a"].into_iter());
+        assert_eq!(counts, vec![("b", 3), ("a", 2), ("c", 1)]);
+    }
+
+    #[test]
+    fn test_logs_workbook_is_xlsx() {
+        let requests = vec![DhcpRequest {
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            message_type: "DISCOVER".to_string(),
+            vendor_class: Some("MSFT 5.0".to_string()),
+            ..Default::default()
+        }];
+        let bytes = logs_workbook(&requests).unwrap();
+        // xlsx files are zip archives
+        assert_eq!(&bytes[..2], b"PK");
+
+        assert_eq!(&devices_workbook(&[]).unwrap()[..2], b"PK");
+    }
+}
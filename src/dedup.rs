@@ -0,0 +1,104 @@
+//! Collapsing of repeated identical requests.
+//!
+//! Some clients resend the same DISCOVER every few seconds. Within the window,
+//! a request matching an earlier one on (MAC, message type, fingerprint) is
+//! counted on the earlier row instead of being stored again.
+
+use crate::dhcp::DhcpRequest;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+type RequestKey = (String, String, String);
+
+fn key(request: &DhcpRequest) -> RequestKey {
+    (
+        request.mac_address.clone(),
+        request.message_type.clone(),
+        request.fingerprint.clone(),
+    )
+}
+
+struct DedupState {
+    /// When the stored row for each key was first seen
+    windows: HashMap<RequestKey, Instant>,
+    last_prune: Instant,
+}
+
+pub struct RequestDeduplicator {
+    window: Duration,
+    state: Mutex<DedupState>,
+}
+
+impl RequestDeduplicator {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            state: Mutex::new(DedupState {
+                windows: HashMap::new(),
+                last_prune: Instant::now(),
+            }),
+        }
+    }
+
+    /// Whether the request repeats one stored less than a window ago;
+    /// otherwise it is stored and starts a new window
+    pub fn is_repeat(&self, request: &DhcpRequest) -> bool {
+        self.is_repeat_at(request, Instant::now())
+    }
+
+    fn is_repeat_at(&self, request: &DhcpRequest, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Forget expired windows once per window so the map only holds recent devices
+        if now.duration_since(state.last_prune) >= self.window {
+            let window = self.window;
+            state.windows.retain(|_, first| now.duration_since(*first) < window);
+            state.last_prune = now;
+        }
+
+        let key = key(request);
+        match state.windows.get(&key) {
+            Some(first) if now.duration_since(*first) < self.window => true,
+            _ => {
+                state.windows.insert(key, now);
+                false
+            }
+        }
+    }
+
+    /// Start a new window for the request, after a repeat could not be counted on a stored row
+    pub fn restart(&self, request: &DhcpRequest) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.windows.insert(key(request), Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(message_type: &str) -> DhcpRequest {
+        DhcpRequest {
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            message_type: message_type.to_string(),
+            fingerprint: "1,3,6".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_repeats_within_window() {
+        let dedup = RequestDeduplicator::new(Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert!(!dedup.is_repeat_at(&request("DISCOVER"), start));
+        assert!(dedup.is_repeat_at(&request("DISCOVER"), start + Duration::from_secs(5)));
+        assert!(dedup.is_repeat_at(&request("DISCOVER"), start + Duration::from_secs(59)));
+        // A different message type is a different request
+        assert!(!dedup.is_repeat_at(&request("REQUEST"), start + Duration::from_secs(10)));
+        // The window is fixed from the stored row, so a steady stream still yields a row per window
+        assert!(!dedup.is_repeat_at(&request("DISCOVER"), start + Duration::from_secs(61)));
+        assert!(dedup.is_repeat_at(&request("DISCOVER"), start + Duration::from_secs(90)));
+    }
+}
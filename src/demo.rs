@@ -0,0 +1,338 @@
+//! Generated DHCP traffic for `--demo`.
+//!
+//! Simulated devices go through DISCOVER/REQUEST, renew, occasionally release and
+//! now and then misbehave (DECLINE bursts, OS reinstalls) so the anomaly detector
+//! raises alerts. Packets are real BOOTREQUEST bytes, so they take the same parse,
+//! lint and detection path as captured traffic.
+
+use crate::dhcp::{DhcpPacket, DhcpPacketBuilder, DhcpRequest};
+use crate::web::state::AppState;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+/// Demo mode keeps its generated data out of the real database
+pub const DEMO_DATABASE_URL: &str = "sqlite:dhcp_monitor_demo.db";
+
+const SERVER_ID: Ipv4Addr = Ipv4Addr::new(192, 168, 50, 1);
+const MAX_DEVICES: usize = 60;
+/// More than the default anomaly decline_threshold
+const DECLINE_BURST: usize = 12;
+
+/// A kind of device and what its DHCP client sends
+struct Profile {
+    hostname: &'static str,
+    oui: [u8; 3],
+    fingerprint: &'static [u8],
+    vendor_class: Option<&'static str>,
+    /// Option 93, for PXE clients
+    client_arch: Option<u16>,
+}
+
+const PROFILES: &[Profile] = &[
+    Profile {
+        hostname: "DESKTOP",
+        oui: [0xf8, 0xbc, 0x12],
+        fingerprint: &[1, 3, 6, 15, 31, 33, 43, 44, 46, 47, 121, 249, 252, 12],
+        vendor_class: Some("MSFT 5.0"),
+        client_arch: None,
+    },
+    Profile {
+        hostname: "LAPTOP",
+        oui: [0x3c, 0xd9, 0x2b],
+        fingerprint: &[1, 3, 6, 15, 31, 33, 43, 44, 46, 47, 121, 249, 252],
+        vendor_class: Some("MSFT 5.0"),
+        client_arch: None,
+    },
+    Profile {
+        hostname: "MacBook-Pro",
+        oui: [0xa4, 0x83, 0xe7],
+        fingerprint: &[1, 3, 6, 15, 119, 252],
+        vendor_class: None,
+        client_arch: None,
+    },
+    Profile {
+        hostname: "iPhone",
+        oui: [0xa4, 0x83, 0xe7],
+        fingerprint: &[1, 121, 3, 6, 15, 119, 252, 95, 44, 46],
+        vendor_class: None,
+        client_arch: None,
+    },
+    Profile {
+        hostname: "Galaxy-S23",
+        oui: [0x8c, 0xf5, 0xa3],
+        fingerprint: &[1, 3, 6, 15, 26, 28, 51, 58, 59, 43],
+        vendor_class: Some("android-dhcp-13"),
+        client_arch: None,
+    },
+    Profile {
+        hostname: "raspberrypi",
+        oui: [0xb8, 0x27, 0xeb],
+        fingerprint: &[1, 28, 2, 3, 15, 6, 119, 12, 44, 47, 26, 121, 42],
+        vendor_class: Some("dhcpcd-9.4.1:Linux-6.1.0:aarch64:BCM2835"),
+        client_arch: None,
+    },
+    Profile {
+        hostname: "chromebook",
+        oui: [0xf4, 0xf5, 0xd8],
+        fingerprint: &[1, 3, 6, 12, 15, 28, 51, 58, 59, 119],
+        vendor_class: None,
+        client_arch: None,
+    },
+    Profile {
+        hostname: "PS5",
+        oui: [0x00, 0xd9, 0xd1],
+        fingerprint: &[1, 3, 6, 15, 12, 28],
+        vendor_class: None,
+        client_arch: None,
+    },
+    Profile {
+        hostname: "Roku-Ultra",
+        oui: [0xb0, 0xa7, 0x37],
+        fingerprint: &[1, 3, 6, 12, 15, 28, 42],
+        vendor_class: None,
+        client_arch: None,
+    },
+    Profile {
+        hostname: "pxe-node",
+        oui: [0x00, 0x1b, 0x21],
+        fingerprint: &[1, 2, 3, 4, 5, 6, 12, 13, 15, 17, 18, 22, 23, 28, 40, 41, 42, 43, 50, 51, 54, 58, 59, 60, 66, 67, 97, 128, 129, 130, 131, 132, 133, 134, 135],
+        vendor_class: Some("PXEClient:Arch:00007:UNDI:003016"),
+        client_arch: Some(7),
+    },
+];
+
+struct Device {
+    mac: [u8; 6],
+    hostname: String,
+    profile: &'static Profile,
+    ip: Option<Ipv4Addr>,
+}
+
+/// A generated packet and where it appears to come from
+pub struct DemoEvent {
+    pub data: Vec<u8>,
+    pub source: SocketAddr,
+}
+
+/// Endless stream of plausible client traffic
+pub struct DemoTraffic {
+    rng: StdRng,
+    devices: Vec<Device>,
+    next_host: u8,
+    pending: VecDeque<DemoEvent>,
+}
+
+impl DemoTraffic {
+    pub fn new(seed: u64, devices: usize) -> Self {
+        let mut traffic = Self {
+            rng: StdRng::seed_from_u64(seed),
+            devices: Vec::new(),
+            next_host: 10,
+            pending: VecDeque::new(),
+        };
+        for _ in 0..devices.min(MAX_DEVICES) {
+            traffic.add_device();
+        }
+        traffic
+    }
+
+    fn add_device(&mut self) {
+        let profile = PROFILES.choose(&mut self.rng).unwrap();
+        let mut mac = [0u8; 6];
+        mac[..3].copy_from_slice(&profile.oui);
+        self.rng.fill(&mut mac[3..]);
+        let hostname = format!("{}-{:02X}{:02X}", profile.hostname, mac[4], mac[5]);
+        self.devices.push(Device { mac, hostname, profile, ip: None });
+    }
+
+    fn allocate_ip(&mut self) -> Ipv4Addr {
+        let ip = Ipv4Addr::new(192, 168, 50, self.next_host);
+        self.next_host = if self.next_host >= 250 { 10 } else { self.next_host + 1 };
+        ip
+    }
+
+    /// Pause before the next event; queued bursts go out back to back
+    pub fn next_delay(&mut self) -> Duration {
+        if self.pending.is_empty() {
+            Duration::from_millis(self.rng.gen_range(500..3000))
+        } else {
+            Duration::from_millis(50)
+        }
+    }
+
+    pub fn next_event(&mut self) -> DemoEvent {
+        if let Some(event) = self.pending.pop_front() {
+            return event;
+        }
+
+        let roll: f64 = self.rng.gen();
+        if roll < 0.01 {
+            self.queue_decline_burst();
+            return self.pending.pop_front().unwrap();
+        }
+        if roll < 0.02 {
+            // An OS reinstall changes the fingerprint of a known MAC
+            let index = self.rng.gen_range(0..self.devices.len());
+            let profile = PROFILES.choose(&mut self.rng).unwrap();
+            self.devices[index].profile = profile;
+        } else if roll < 0.05 && self.devices.len() < MAX_DEVICES {
+            self.add_device();
+            let index = self.devices.len() - 1;
+            return self.join(index);
+        }
+
+        let index = self.rng.gen_range(0..self.devices.len());
+        match self.devices[index].ip {
+            None => self.join(index),
+            Some(ip) if self.rng.gen_bool(0.05) => {
+                self.devices[index].ip = None;
+                let device = &self.devices[index];
+                packet_event(device, 7, ip, &[(54, &SERVER_ID.octets())], &mut self.rng)
+            }
+            Some(ip) => {
+                // Renewal, unicast from the leased address
+                let device = &self.devices[index];
+                packet_event(device, 3, ip, &identity_options(device), &mut self.rng)
+            }
+        }
+    }
+
+    // DISCOVER now, the selecting REQUEST right after
+    fn join(&mut self, index: usize) -> DemoEvent {
+        let ip = self.allocate_ip();
+        self.devices[index].ip = Some(ip);
+        let device = &self.devices[index];
+        let options = identity_options(device);
+        let discover = packet_event(device, 1, Ipv4Addr::UNSPECIFIED, &options, &mut self.rng);
+
+        let mut select = options;
+        select.push((50, ip.octets().to_vec()));
+        select.push((54, SERVER_ID.octets().to_vec()));
+        let request = packet_event(device, 3, Ipv4Addr::UNSPECIFIED, &select, &mut self.rng);
+        self.pending.push_back(request);
+        discover
+    }
+
+    // Clients that find their offered address in use
+    fn queue_decline_burst(&mut self) {
+        for _ in 0..DECLINE_BURST {
+            let index = self.rng.gen_range(0..self.devices.len());
+            let ip = self.allocate_ip();
+            self.devices[index].ip = None;
+            let options = [(50, ip.octets().to_vec()), (54, SERVER_ID.octets().to_vec())];
+            let event = packet_event(&self.devices[index], 4, Ipv4Addr::UNSPECIFIED, &options, &mut self.rng);
+            self.pending.push_back(event);
+        }
+    }
+}
+
+// Options a device sends in DISCOVER and REQUEST
+fn identity_options(device: &Device) -> Vec<(u8, Vec<u8>)> {
+    let profile = device.profile;
+    let mut client_id = vec![1];
+    client_id.extend_from_slice(&device.mac);
+
+    let mut options = vec![
+        (61, client_id),
+        (57, 1500u16.to_be_bytes().to_vec()),
+        (12, device.hostname.as_bytes().to_vec()),
+    ];
+    if let Some(vendor_class) = profile.vendor_class {
+        options.push((60, vendor_class.as_bytes().to_vec()));
+    }
+    if let Some(arch) = profile.client_arch {
+        options.push((93, arch.to_be_bytes().to_vec()));
+        options.push((94, vec![1, 3, 16]));
+    }
+    options.push((55, profile.fingerprint.to_vec()));
+    options
+}
+
+fn packet_event(
+    device: &Device,
+    message_type: u8,
+    ciaddr: Ipv4Addr,
+    options: &[(u8, impl AsRef<[u8]>)],
+    rng: &mut StdRng,
+) -> DemoEvent {
+    let mut builder = DhcpPacketBuilder::message(message_type)
+        .mac(device.mac)
+        .xid(rng.gen())
+        .ciaddr(ciaddr);
+    for (code, value) in options {
+        builder = builder.option(*code, value);
+    }
+    let data = builder.build();
+
+    DemoEvent {
+        data,
+        source: SocketAddr::from((ciaddr, 68)),
+    }
+}
+
+/// Store `events` generated requests spread over the past `span`, so the
+/// history, reports and timelines have something to show from the start
+pub async fn seed_history(
+    state: &AppState,
+    traffic: &mut DemoTraffic,
+    events: usize,
+    span: chrono::Duration,
+) -> anyhow::Result<()> {
+    let start = chrono::Utc::now() - span;
+    let step = span / events.max(1) as i32;
+
+    for i in 0..events {
+        let event = traffic.next_event();
+        let packet = DhcpPacket::parse(&event.data)?;
+        let mut request = DhcpRequest::from_packet(&packet, event.source.ip().to_string(), event.source.port());
+        request.timestamp = (start + step * i as i32).to_rfc3339();
+        request.raw_packet = Some(event.data);
+        state.classify(&mut request).await;
+        crate::db::queries::insert_request(&state.db_pool, &request).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_packets_are_clean() {
+        let mut traffic = DemoTraffic::new(7, 20);
+        let mut types = std::collections::HashSet::new();
+
+        for _ in 0..2000 {
+            let event = traffic.next_event();
+            let issues = crate::lint::lint_packet(&event.data);
+            assert!(issues.is_empty(), "{:?}", issues);
+
+            let packet = DhcpPacket::parse(&event.data).unwrap();
+            let request = DhcpRequest::from_packet(&packet, event.source.ip().to_string(), 68);
+            assert!(!request.fingerprint.is_empty() || request.message_type != "DISCOVER");
+            types.insert(request.message_type);
+        }
+
+        for expected in ["DISCOVER", "REQUEST", "RELEASE", "DECLINE"] {
+            assert!(types.contains(expected), "no {} generated", expected);
+        }
+    }
+
+    #[test]
+    fn test_join_sends_discover_then_request() {
+        let mut traffic = DemoTraffic::new(1, 1);
+        let discover = traffic.next_event();
+        assert_eq!(DhcpPacket::parse(&discover.data).unwrap().get_message_type(), Some(1));
+        assert!(discover.source.ip().is_unspecified());
+        assert_eq!(traffic.next_delay(), Duration::from_millis(50));
+
+        let request = DhcpPacket::parse(&traffic.next_event().data).unwrap();
+        assert_eq!(request.get_message_type(), Some(3));
+        assert!(request.get_option(50).is_some());
+    }
+}
@@ -0,0 +1,882 @@
+//! Evidence sources for the hybrid detection pipeline.
+
+use crate::db::probe_cache::CachedProbe;
+use crate::fingerprint;
+use crate::http_probe::{self, HttpBanner};
+use crate::hybrid_detection::{DetectionSource, DeviceContext, Evidence, HybridConfig, ProbeOutcomes, TtlFamily};
+use crate::icmp::{self, PingReply};
+use crate::probe_identity::ProbeIdentity;
+use crate::probe_scheduler::ProbeScheduler;
+use crate::smb;
+use crate::smb_policy::SmbProbePolicy;
+use crate::windows_hints::{self, WindowsHint};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::Instrument;
+
+/// How long to wait for an echo reply or TCP handshake
+const PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Ports tried when ICMP sockets aren't permitted (SMB first, as that's what follows)
+const TCP_PROBE_PORTS: &[u16] = &[445, 139, 80, 443];
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// How soon a failed probe is retried: `base_secs` after the first failure, doubling
+/// with each one after up to `max_secs`, jittered by ±25% so hosts that failed
+/// together (say, after a power cut) aren't all retried at once
+#[derive(Debug, Clone, Copy)]
+struct RetryPolicy {
+    base_secs: u64,
+    max_secs: u64,
+}
+
+impl RetryPolicy {
+    /// `jitter` in [0, 1)
+    fn delay_secs(&self, failures: u32, jitter: f64) -> u64 {
+        let backoff = self
+            .base_secs
+            .saturating_mul(1 << failures.saturating_sub(1).min(16))
+            .min(self.max_secs);
+        (backoff as f64 * (0.75 + jitter * 0.5)).round() as u64
+    }
+}
+
+/// A cached probe result, or a recent failure not to retry yet
+#[derive(Debug, PartialEq)]
+enum Cached<T> {
+    Found(T),
+    Failed,
+}
+
+struct CacheEntry<T> {
+    result: Option<T>,
+    /// Consecutive failures, kept after expiry so the backoff keeps growing
+    failures: u32,
+    expires: u64,
+}
+
+/// Probe results cached per device (MAC and IP): results for a fixed time,
+/// failures until a backed-off retry
+struct ProbeCache<T> {
+    ttl_secs: u64,
+    retry: RetryPolicy,
+    entries: Mutex<HashMap<(String, String), CacheEntry<T>>>,
+}
+
+impl<T: Clone> ProbeCache<T> {
+    fn new(ttl_secs: u64, negative_ttl_secs: u64) -> Self {
+        Self {
+            ttl_secs,
+            retry: RetryPolicy {
+                base_secs: negative_ttl_secs,
+                max_secs: ttl_secs,
+            },
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn key(device: &DeviceContext<'_>) -> (String, String) {
+        (device.mac_address.to_string(), device.ip_address.to_string())
+    }
+
+    fn get(&self, device: &DeviceContext<'_>) -> Option<Cached<T>> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(&Self::key(device)).filter(|entry| now_secs() < entry.expires)?;
+        Some(match &entry.result {
+            Some(value) => Cached::Found(value.clone()),
+            None => Cached::Failed,
+        })
+    }
+
+    fn insert(&self, device: &DeviceContext<'_>, value: T) {
+        let entry = CacheEntry {
+            result: Some(value),
+            failures: 0,
+            expires: now_secs() + self.ttl_secs,
+        };
+        self.entries.lock().unwrap().insert(Self::key(device), entry);
+    }
+
+    /// Record a failed probe; returns the seconds until it may be retried
+    fn insert_failure(&self, device: &DeviceContext<'_>) -> u64 {
+        let mut entries = self.entries.lock().unwrap();
+        let key = Self::key(device);
+        let failures = entries.get(&key).map_or(0, |entry| entry.failures) + 1;
+        let delay = self.retry.delay_secs(failures, rand::random());
+        let entry = CacheEntry {
+            result: None,
+            failures,
+            expires: now_secs() + delay,
+        };
+        entries.insert(key, entry);
+        delay
+    }
+
+    /// Drop everything cached for the device's MAC or IP
+    fn remove(&self, device: &DeviceContext<'_>) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|(mac, ip), _| mac != device.mac_address && ip != device.ip_address);
+    }
+
+    /// Drop results cached for the device's MAC or IP, keeping failures and
+    /// their backoff
+    fn remove_found(&self, device: &DeviceContext<'_>) {
+        self.entries.lock().unwrap().retain(|(mac, ip), entry| {
+            entry.result.is_none() || (mac != device.mac_address && ip != device.ip_address)
+        });
+    }
+
+    /// Drop entries for a MAC and/or IP (both must match when both are given);
+    /// returns how many went
+    fn remove_matching(&self, mac_address: Option<&str>, ip_address: Option<&str>) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|(mac, ip), _| {
+            !(mac_address.is_none_or(|wanted| wanted == mac) && ip_address.is_none_or(|wanted| wanted == ip))
+        });
+        before - entries.len()
+    }
+
+    fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    fn stats(&self) -> (usize, usize) {
+        let entries = self.entries.lock().unwrap();
+        let now = now_secs();
+        let expired = entries.values().filter(|entry| now >= entry.expires).count();
+        (entries.len(), expired)
+    }
+}
+
+impl<T: Clone + Serialize + DeserializeOwned> ProbeCache<T> {
+    /// Live entries, and expired failures whose count still sets the next backoff.
+    /// A failure nobody retried for a TTL past its retry time is left out: the host
+    /// has gone, and should it come back its backoff starts over.
+    fn export(&self, source: &str) -> Vec<CachedProbe> {
+        let now = now_secs();
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, entry)| {
+                entry.expires > now || (entry.failures > 0 && now < entry.expires.saturating_add(self.ttl_secs))
+            })
+            .filter_map(|((mac, ip), entry)| {
+                Some(CachedProbe {
+                    source: source.to_string(),
+                    mac_address: mac.clone(),
+                    ip_address: ip.clone(),
+                    result: match &entry.result {
+                        Some(result) => Some(serde_json::to_string(result).ok()?),
+                        None => None,
+                    },
+                    failures: entry.failures,
+                    expires_at: entry.expires as i64,
+                })
+            })
+            .collect()
+    }
+
+    /// Load exported entries, skipping results this version can't read
+    fn import(&self, stored: &[CachedProbe]) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        for cached in stored {
+            let result = match &cached.result {
+                Some(json) => match serde_json::from_str(json) {
+                    Ok(result) => Some(result),
+                    Err(_) => continue,
+                },
+                None => None,
+            };
+            let entry = CacheEntry {
+                result,
+                failures: cached.failures,
+                expires: cached.expires_at.max(0) as u64,
+            };
+            entries.insert((cached.mac_address.clone(), cached.ip_address.clone()), entry);
+        }
+        entries.len() - before
+    }
+}
+
+/// Explicit MAC to OS mappings from mac_os_mapping.toml
+pub struct MacMappingSource;
+
+impl DetectionSource for MacMappingSource {
+    fn name(&self) -> &'static str {
+        "mac_mapping"
+    }
+
+    fn gather<'a>(&'a self, device: &'a DeviceContext<'a>, _prior: &'a [Evidence]) -> BoxFuture<'a, Vec<Evidence>> {
+        let evidence = fingerprint::lookup_mac_mapping(device.mac_address).map(|info| Evidence {
+            source: self.name(),
+            os_name: Some(info.os_name),
+            device_class: Some(info.device_class),
+            vendor: Some(info.vendor),
+            // An administrator said so: outranks anything inferred
+            confidence: 0.99,
+            method: "MAC mapping".to_string(),
+            ..Default::default()
+        });
+        async move { evidence.into_iter().collect() }.boxed()
+    }
+}
+
+/// Classification rules on the vendor class, hostname and OUI (classification_rules.toml)
+pub struct RuleSource;
+
+impl DetectionSource for RuleSource {
+    fn name(&self) -> &'static str {
+        "classification_rule"
+    }
+
+    fn gather<'a>(&'a self, device: &'a DeviceContext<'a>, _prior: &'a [Evidence]) -> BoxFuture<'a, Vec<Evidence>> {
+        let evidence = crate::rules::classify(device.mac_address, device.vendor_class, device.hostname).map(|info| Evidence {
+            source: self.name(),
+            os_name: Some(info.os_name),
+            device_class: Some(info.device_class).filter(|class| !class.is_empty()),
+            vendor: Some(info.vendor).filter(|vendor| !vendor.is_empty()),
+            // Written for this network, so it outranks the generic fingerprint table
+            confidence: 0.96,
+            method: "Classification rule".to_string(),
+            ..Default::default()
+        });
+        async move { evidence.into_iter().collect() }.boxed()
+    }
+}
+
+/// DHCP option 55 fingerprint lookup
+pub struct FingerprintSource;
+
+impl DetectionSource for FingerprintSource {
+    fn name(&self) -> &'static str {
+        "dhcp_fingerprint"
+    }
+
+    fn gather<'a>(&'a self, device: &'a DeviceContext<'a>, _prior: &'a [Evidence]) -> BoxFuture<'a, Vec<Evidence>> {
+        let evidence = fingerprint::lookup_fingerprint(device.fingerprint).map(|info| Evidence {
+            source: self.name(),
+            os_name: Some(info.os_name.into_owned()),
+            device_class: Some(info.device_class.into_owned()),
+            vendor: Some(info.vendor.into_owned()),
+            confidence: 0.95, // Exact match
+            method: "DHCP fingerprint".to_string(),
+            ..Default::default()
+        });
+        async move { evidence.into_iter().collect() }.boxed()
+    }
+}
+
+/// Windows builds and Microsoft vendor classes in options 60 and 43. A build
+/// names the release; a bare "MSFT 5.0" only says Windows, as a family hint
+pub struct WindowsHintSource;
+
+impl DetectionSource for WindowsHintSource {
+    fn name(&self) -> &'static str {
+        "windows_hint"
+    }
+
+    fn gather<'a>(&'a self, device: &'a DeviceContext<'a>, _prior: &'a [Evidence]) -> BoxFuture<'a, Vec<Evidence>> {
+        let evidence = windows_hints::decode(device.vendor_class, device.vendor_specific).map(|hint| match hint {
+            WindowsHint::Build { build, source } => Evidence {
+                source: self.name(),
+                os_name: Some(smb::build_to_windows_version(build).to_string()),
+                vendor: Some("Microsoft".to_string()),
+                // The client's own word, but nothing checks it; an SMB probe outranks it
+                confidence: 0.9,
+                method: format!("Windows build in option {}", source.option()),
+                smb_build: Some(build),
+                ..Default::default()
+            },
+            WindowsHint::Family { vendor_class } => Evidence {
+                source: self.name(),
+                family: Some(TtlFamily::Windows),
+                confidence: 0.3,
+                method: format!("vendor class {}", vendor_class),
+                ..Default::default()
+            },
+        });
+        async move { evidence.into_iter().collect() }.boxed()
+    }
+}
+
+/// PXE clients, classified by their option 93 architecture
+/// A machine network-booting has no OS yet, so the boot firmware is what we report
+pub struct PxeSource;
+
+impl DetectionSource for PxeSource {
+    fn name(&self) -> &'static str {
+        "pxe"
+    }
+
+    fn gather<'a>(&'a self, device: &'a DeviceContext<'a>, _prior: &'a [Evidence]) -> BoxFuture<'a, Vec<Evidence>> {
+        let evidence = device.client_arch.map(|client_arch| {
+            let family = match client_arch {
+                0 | 20 => "BIOS",
+                6 | 15 => "UEFI x86",
+                7 | 9 | 16 => "UEFI x64",
+                10 | 11 | 18 | 19 | 21..=24 | 41 => "ARM",
+                _ => "Other",
+            };
+            Evidence {
+                source: self.name(),
+                os_name: Some(format!("PXE Boot ({})", crate::dhcp::client_arch_name(client_arch))),
+                device_class: Some(format!("PXE Client ({})", family)),
+                // Whatever OS the fingerprint suggests isn't running yet
+                confidence: 0.97,
+                method: "PXE client architecture".to_string(),
+                ..Default::default()
+            }
+        });
+        async move { evidence.into_iter().collect() }.boxed()
+    }
+}
+
+/// Option 125 vendor blocks of vendors whose devices we recognise (TR-069
+/// CPEs, cable modems, UniFi gear). They name the platform, not the OS build,
+/// so a fingerprint match outranks them.
+pub struct VendorOptionsSource;
+
+impl DetectionSource for VendorOptionsSource {
+    fn name(&self) -> &'static str {
+        "vendor_options"
+    }
+
+    fn gather<'a>(&'a self, device: &'a DeviceContext<'a>, _prior: &'a [Evidence]) -> BoxFuture<'a, Vec<Evidence>> {
+        let evidence = crate::vendor_options::classify(device.vendor_options).map(|info| Evidence {
+            source: self.name(),
+            os_name: Some(info.os_name),
+            device_class: Some(info.device_class.to_string()),
+            vendor: info.vendor.map(str::to_string),
+            confidence: 0.6,
+            method: format!("DHCP option 125, {}", info.evidence),
+            ..Default::default()
+        });
+        async move { evidence.into_iter().collect() }.boxed()
+    }
+}
+
+/// Active SMB negotiate probe of hosts the probe policy selects, preceded by a
+/// reachability check whose reply TTL is reported as an OS family hint
+pub struct SmbSource {
+    timeout_secs: u64,
+    policy: Arc<RwLock<SmbProbePolicy>>,
+    probe_identity: ProbeIdentity,
+    smb1_fallback: bool,
+    cache: ProbeCache<smb::SmbProbeResult>,
+    outcomes: Arc<ProbeOutcomes>,
+    scheduler: Arc<ProbeScheduler>,
+}
+
+impl SmbSource {
+    pub fn new(
+        config: &HybridConfig,
+        policy: Arc<RwLock<SmbProbePolicy>>,
+        outcomes: Arc<ProbeOutcomes>,
+        scheduler: Arc<ProbeScheduler>,
+    ) -> Self {
+        Self {
+            timeout_secs: config.smb_timeout_secs,
+            policy,
+            probe_identity: config.probe_identity.clone(),
+            smb1_fallback: config.smb1_fallback,
+            cache: ProbeCache::new(config.smb_cache_ttl_secs, config.negative_cache_ttl_secs),
+            outcomes,
+            scheduler,
+        }
+    }
+
+    async fn probe(&self, device: &DeviceContext<'_>, prior: &[Evidence]) -> Vec<Evidence> {
+        let ip_address = device.ip_address;
+        let mut evidence = Vec::new();
+
+        let allowed = self.policy.read().unwrap().check(device, prior);
+        if let Err(reason) = allowed {
+            tracing::debug!(vendor = ?device.vendor_class, %reason, "Skipping SMB probe");
+            return evidence;
+        }
+
+        // Hosts that recently failed aren't pinged again until their retry is due
+        if let Some(Cached::Failed) = self.cache.get(device) {
+            tracing::debug!("SMB probe failed recently, waiting to retry");
+            self.outcomes.record(self.name(), "backoff");
+            return evidence;
+        }
+
+        // The ping and the negotiate share one slot
+        let _permit = match self.scheduler.acquire(self.name(), ip_address).await {
+            Ok(permit) => permit,
+            Err(skip) => {
+                tracing::debug!(reason = skip.outcome(), "SMB probe not scheduled");
+                self.outcomes.record(self.name(), skip.outcome());
+                if let Some(Cached::Found(result)) = self.cache.get(device) {
+                    evidence.extend(self.evidence(result));
+                }
+                return evidence;
+            }
+        };
+
+        tracing::info!(vendor = ?device.vendor_class, "Attempting SMB probe");
+
+        // First, check if host is reachable via ping (skipped in stealth mode to avoid ICMP noise)
+        if !self.probe_identity.stealth {
+            match ping_host(ip_address).await {
+                Ok(Some(reply)) => {
+                    tracing::debug!(rtt_ms = reply.rtt.as_millis() as u64, ttl = ?reply.ttl, "Host is reachable");
+                    if let Some(ttl) = reply.ttl {
+                        evidence.push(Evidence {
+                            source: "icmp_ttl",
+                            family: Some(TtlFamily::from_ttl(ttl)),
+                            confidence: 0.3,
+                            method: format!("ICMP TTL (ttl={})", ttl),
+                            ..Default::default()
+                        });
+                    }
+                }
+                Ok(None) => {
+                    // Don't probe if host is not reachable
+                    let retry_secs = self.cache.insert_failure(device);
+                    tracing::debug!(retry_secs, "Host not reachable, skipping SMB probe");
+                    self.outcomes.record(self.name(), "unreachable");
+                    return evidence;
+                }
+                Err(e) => {
+                    // Continue with SMB probe even if ping fails (some hosts may block ICMP)
+                    tracing::debug!(error = %e, "Ping failed, continuing with SMB probe");
+                }
+            }
+        }
+
+        evidence.extend(self.probe_cached(device).await.and_then(|result| self.evidence(result)));
+        evidence
+    }
+
+    fn evidence(&self, smb_result: smb::SmbProbeResult) -> Option<Evidence> {
+        if !smb_result.success {
+            tracing::debug!(result = %smb_result.os_version, "SMB probe failed");
+            return None;
+        }
+        tracing::info!(
+            os = %smb_result.os_version,
+            dialect = %smb_result.smb_dialect,
+            build = ?smb_result.build_number,
+            "SMB probe identified host"
+        );
+        // More accurate than DHCP fingerprinting; the device class still comes from DHCP.
+        // SMB1 OS strings are self-reported and Samba answers too, so they count for less
+        let legacy = smb_result.smb_dialect == smb::SMB1_DIALECT;
+        let windows = smb_result.os_version.starts_with("Windows");
+        Some(Evidence {
+            source: self.name(),
+            vendor: (!legacy || windows).then(|| "Microsoft".to_string()),
+            os_name: Some(smb_result.os_version),
+            confidence: if legacy { 0.8 } else { 0.95 },
+            method: format!("SMB probe ({})", smb_result.smb_dialect),
+            smb_dialect: Some(smb_result.smb_dialect),
+            smb_build: smb_result.build_number,
+            smb_build_exact: smb_result.build_exact,
+            smb_server: smb_result.server,
+            ..Default::default()
+        })
+    }
+
+    /// Probe SMB with caching; failures are cached too, for a shorter time
+    async fn probe_cached(&self, device: &DeviceContext<'_>) -> Option<smb::SmbProbeResult> {
+        if let Some(Cached::Found(result)) = self.cache.get(device) {
+            tracing::debug!("SMB cache hit");
+            self.outcomes.record(self.name(), "cached");
+            return Some(result);
+        }
+
+        let ip = device.ip_address;
+        let result = match smb::probe_smb(ip, self.timeout_secs, &self.probe_identity).await {
+            Ok(result) => {
+                self.outcomes.record(self.name(), if result.success { "identified" } else { "failed" });
+                Some(result)
+            }
+            Err(e) if self.smb1_fallback => {
+                tracing::debug!(error = %e, "SMB2 probe failed, trying SMB1");
+                match smb::probe_smb1(ip, self.timeout_secs, &self.probe_identity).await {
+                    Ok(result) => {
+                        self.outcomes.record(self.name(), "identified_smb1");
+                        Some(result)
+                    }
+                    Err(smb1_error) => {
+                        tracing::warn!(error = %e, smb1_error = %smb1_error, "SMB probe error");
+                        self.outcomes.record(self.name(), "error");
+                        None
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "SMB probe error");
+                self.outcomes.record(self.name(), "error");
+                None
+            }
+        };
+
+        match result.as_ref().filter(|result| result.success) {
+            Some(result) => self.cache.insert(device, result.clone()),
+            None => {
+                let retry_secs = self.cache.insert_failure(device);
+                tracing::debug!(retry_secs, "SMB probe will be retried later");
+            }
+        }
+        result
+    }
+}
+
+impl DetectionSource for SmbSource {
+    fn name(&self) -> &'static str {
+        "smb"
+    }
+
+    fn gather<'a>(&'a self, device: &'a DeviceContext<'a>, prior: &'a [Evidence]) -> BoxFuture<'a, Vec<Evidence>> {
+        self.probe(device, prior)
+            .instrument(tracing::info_span!("smb", mac = %device.mac_address, ip = %device.ip_address))
+            .boxed()
+    }
+
+    fn is_active(&self) -> bool {
+        true
+    }
+
+    fn forget(&self, device: &DeviceContext<'_>) {
+        self.cache.remove(device);
+    }
+
+    fn expire(&self, device: &DeviceContext<'_>) {
+        self.cache.remove_found(device);
+    }
+
+    fn remove_cached(&self, mac_address: Option<&str>, ip_address: Option<&str>) -> usize {
+        self.cache.remove_matching(mac_address, ip_address)
+    }
+
+    fn clear_cache(&self) {
+        self.cache.clear();
+    }
+
+    fn cache_stats(&self) -> (usize, usize) {
+        self.cache.stats()
+    }
+
+    fn export_cache(&self) -> Vec<CachedProbe> {
+        self.cache.export(self.name())
+    }
+
+    fn import_cache(&self, entries: &[CachedProbe]) -> usize {
+        self.cache.import(entries)
+    }
+}
+
+/// Ping a host to check if it's reachable
+/// Uses native ICMP echo, or a TCP connect probe where ICMP sockets aren't permitted
+/// Returns Ok(Some(reply)) if reachable, Ok(None) if not reachable, Err if the address is invalid
+pub(crate) async fn ping_host(ip: &str) -> Result<Option<PingReply>, String> {
+    let addr: IpAddr = ip.parse().map_err(|e| format!("Invalid address {}: {}", ip, e))?;
+    let echo = match addr {
+        IpAddr::V4(v4) => icmp::echo(v4, PING_TIMEOUT).await,
+        IpAddr::V6(_) => Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "ICMPv6 echo not implemented")),
+    };
+
+    match echo {
+        Ok(reply) => {
+            if let Some(ttl) = reply.and_then(|reply| reply.ttl) {
+                tracing::debug!(ttl, family = TtlFamily::from_ttl(ttl).name(), "Ping reply");
+            }
+            Ok(reply)
+        }
+        Err(e) => {
+            tracing::debug!(error = %e, "ICMP unavailable, falling back to a TCP connect probe");
+            Ok(icmp::tcp_probe(addr, TCP_PROBE_PORTS, PING_TIMEOUT).await)
+        }
+    }
+}
+
+/// HTTP/HTTPS banners of devices nothing earlier could identify
+pub struct HttpSource {
+    timeout: Duration,
+    user_agent: String,
+    cache: ProbeCache<Vec<HttpBanner>>,
+    outcomes: Arc<ProbeOutcomes>,
+    scheduler: Arc<ProbeScheduler>,
+}
+
+impl HttpSource {
+    pub fn new(config: &HybridConfig, outcomes: Arc<ProbeOutcomes>, scheduler: Arc<ProbeScheduler>) -> Self {
+        Self {
+            timeout: Duration::from_secs(config.http_timeout_secs),
+            user_agent: config.probe_identity.http_user_agent().to_string(),
+            cache: ProbeCache::new(config.http_cache_ttl_secs, config.negative_cache_ttl_secs),
+            outcomes,
+            scheduler,
+        }
+    }
+
+    async fn probe(&self, device: &DeviceContext<'_>, prior: &[Evidence]) -> Vec<Evidence> {
+        if !device.has_address() || prior.iter().any(|e| e.os_name.is_some()) {
+            return Vec::new();
+        }
+
+        let ip = device.ip_address;
+        let banners = match self.cache.get(device) {
+            Some(Cached::Found(banners)) => {
+                tracing::debug!("HTTP cache hit");
+                self.outcomes.record(self.name(), "cached");
+                banners
+            }
+            Some(Cached::Failed) => {
+                tracing::debug!("HTTP probe got no response recently, waiting to retry");
+                self.outcomes.record(self.name(), "backoff");
+                return Vec::new();
+            }
+            None => match self.scheduler.acquire(self.name(), ip).await {
+                Ok(_permit) => {
+                    let banners = http_probe::probe(ip, self.timeout, &self.user_agent).await;
+                    let outcome = match http_probe::classify(&banners) {
+                        Some(_) => "identified",
+                        None if banners.is_empty() => "no_response",
+                        None => "unidentified",
+                    };
+                    self.outcomes.record(self.name(), outcome);
+                    if banners.is_empty() {
+                        self.cache.insert_failure(device);
+                    } else {
+                        self.cache.insert(device, banners.clone());
+                    }
+                    banners
+                }
+                Err(skip) => {
+                    tracing::debug!(reason = skip.outcome(), "HTTP probe not scheduled");
+                    self.outcomes.record(self.name(), skip.outcome());
+                    match self.cache.get(device) {
+                        Some(Cached::Found(banners)) => banners,
+                        _ => return Vec::new(),
+                    }
+                }
+            },
+        };
+
+        let classification = http_probe::classify(&banners);
+        let Some(classification) = classification else {
+            return Vec::new();
+        };
+        tracing::debug!(os = %classification.os_name, evidence = %classification.evidence, "HTTP banner identified host");
+        vec![Evidence {
+            source: self.name(),
+            os_name: Some(classification.os_name),
+            device_class: Some(classification.device_class.to_string()),
+            vendor: Some(classification.vendor.to_string()),
+            confidence: 0.7,
+            method: format!("HTTP banner ({})", classification.evidence),
+            ..Default::default()
+        }]
+    }
+}
+
+impl DetectionSource for HttpSource {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn gather<'a>(&'a self, device: &'a DeviceContext<'a>, prior: &'a [Evidence]) -> BoxFuture<'a, Vec<Evidence>> {
+        self.probe(device, prior)
+            .instrument(tracing::info_span!("http", mac = %device.mac_address, ip = %device.ip_address))
+            .boxed()
+    }
+
+    fn is_active(&self) -> bool {
+        true
+    }
+
+    fn forget(&self, device: &DeviceContext<'_>) {
+        self.cache.remove(device);
+    }
+
+    fn expire(&self, device: &DeviceContext<'_>) {
+        self.cache.remove_found(device);
+    }
+
+    fn remove_cached(&self, mac_address: Option<&str>, ip_address: Option<&str>) -> usize {
+        self.cache.remove_matching(mac_address, ip_address)
+    }
+
+    fn clear_cache(&self) {
+        self.cache.clear();
+    }
+
+    fn cache_stats(&self) -> (usize, usize) {
+        self.cache.stats()
+    }
+
+    fn export_cache(&self) -> Vec<CachedProbe> {
+        self.cache.export(self.name())
+    }
+
+    fn import_cache(&self, entries: &[CachedProbe]) -> usize {
+        self.cache.import(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device<'a>(mac_address: &'a str, ip_address: &'a str) -> DeviceContext<'a> {
+        DeviceContext {
+            mac_address,
+            ip_address,
+            fingerprint: "",
+            vendor_class: None,
+            hostname: None,
+            vendor_specific: None,
+            client_arch: None,
+            vendor_options: &[],
+        }
+    }
+
+    #[test]
+    fn test_probe_cache_expiry() {
+        let device = device("aa:bb:cc:dd:ee:ff", "10.0.0.5");
+        let cache = ProbeCache::new(3600, 300);
+        cache.insert(&device, 1);
+        assert_eq!(cache.get(&device), Some(Cached::Found(1)));
+        assert_eq!(cache.stats(), (1, 0));
+        // Keyed by MAC and IP: the address moving to another device is a miss
+        assert_eq!(cache.get(&DeviceContext { mac_address: "11:22:33:44:55:66", ..device }), None);
+        cache.remove(&device);
+        assert_eq!(cache.get(&device), None);
+
+        let expired = ProbeCache::new(0, 0);
+        expired.insert(&device, 1);
+        assert_eq!(expired.get(&device), None);
+        assert_eq!(expired.stats(), (1, 1));
+    }
+
+    #[test]
+    fn test_failure_backoff() {
+        let device = device("aa:bb:cc:dd:ee:ff", "10.0.0.5");
+        let cache = ProbeCache::<u8>::new(3600, 300);
+        let first = cache.insert_failure(&device);
+        assert!((225..=375).contains(&first));
+        assert_eq!(cache.get(&device), Some(Cached::Failed));
+        let second = cache.insert_failure(&device);
+        assert!((450..=750).contains(&second));
+
+        // Doubling per failure up to the positive TTL, ±25%
+        let retry = RetryPolicy { base_secs: 300, max_secs: 3600 };
+        assert_eq!(retry.delay_secs(1, 0.5), 300);
+        assert_eq!(retry.delay_secs(3, 0.5), 1200);
+        assert_eq!(retry.delay_secs(10, 0.5), 3600);
+        assert_eq!(retry.delay_secs(1, 0.0), 225);
+        assert_eq!(retry.delay_secs(u32::MAX, 0.5), 3600);
+
+        // A success resets the backoff
+        cache.insert(&device, 1);
+        assert!((225..=375).contains(&cache.insert_failure(&device)));
+    }
+
+    #[test]
+    fn test_remove_cached_entries() {
+        let first = device("aa:bb:cc:dd:ee:01", "10.0.0.5");
+        let moved = device("aa:bb:cc:dd:ee:01", "10.0.0.9");
+        let other = device("aa:bb:cc:dd:ee:02", "10.0.0.6");
+        let cache = ProbeCache::new(3600, 300);
+        cache.insert(&first, 1);
+        cache.insert(&moved, 2);
+        cache.insert_failure(&other);
+
+        // Failures keep their backoff when only results are set aside
+        cache.remove_found(&other);
+        assert_eq!(cache.get(&other), Some(Cached::Failed));
+
+        assert_eq!(cache.remove_matching(Some("aa:bb:cc:dd:ee:01"), Some("10.0.0.9")), 1);
+        assert_eq!(cache.get(&first), Some(Cached::Found(1)));
+        assert_eq!(cache.remove_matching(Some("aa:bb:cc:dd:ee:01"), None), 1);
+        assert_eq!(cache.remove_matching(None, Some("10.0.0.6")), 1);
+        assert_eq!(cache.stats(), (0, 0));
+    }
+
+    #[test]
+    fn test_cache_export_import() {
+        let windows = device("aa:bb:cc:dd:ee:01", "10.0.0.5");
+        let offline = device("aa:bb:cc:dd:ee:02", "10.0.0.6");
+        let cache = ProbeCache::new(3600, 300);
+        cache.insert(&windows, vec![HttpBanner { port: 80, ..Default::default() }]);
+        cache.insert_failure(&offline);
+        cache.insert_failure(&offline);
+
+        let mut exported = cache.export("http");
+        exported.sort_by(|a, b| a.mac_address.cmp(&b.mac_address));
+        assert_eq!(exported[0].result.as_deref(), Some(r#"[{"port":80,"server":null,"title":null,"cert_cn":null}]"#));
+        assert_eq!((exported[1].result.as_deref(), exported[1].failures), (None, 2));
+
+        let restored = ProbeCache::<Vec<HttpBanner>>::new(3600, 300);
+        exported.push(CachedProbe { result: Some("not json".to_string()), ..exported[0].clone() });
+        assert_eq!(restored.import(&exported), 2);
+        assert!(matches!(restored.get(&windows), Some(Cached::Found(banners)) if banners[0].port == 80));
+        assert_eq!(restored.get(&offline), Some(Cached::Failed));
+    }
+
+    #[test]
+    fn test_export_drops_abandoned_failures() {
+        let now = now_secs() as i64;
+        let failure = |mac: &str, failures: u32, expires_at: i64| CachedProbe {
+            source: "smb".to_string(),
+            mac_address: mac.to_string(),
+            ip_address: "10.0.0.5".to_string(),
+            result: None,
+            failures,
+            expires_at,
+        };
+        let cache = ProbeCache::<u8>::new(3600, 300);
+        cache.import(&[
+            // Waiting for its retry, or retryable and recent enough to back off further
+            failure("aa:bb:cc:dd:ee:01", 1, now + 100),
+            failure("aa:bb:cc:dd:ee:02", 5, now - 600),
+            // At the maximum backoff and untouched for a TTL since
+            failure("aa:bb:cc:dd:ee:03", 9, now - 3700),
+            failure("aa:bb:cc:dd:ee:04", 1, now - 7200),
+        ]);
+
+        let mut kept: Vec<_> = cache.export("smb").into_iter().map(|entry| entry.mac_address).collect();
+        kept.sort();
+        assert_eq!(kept, ["aa:bb:cc:dd:ee:01", "aa:bb:cc:dd:ee:02"]);
+    }
+
+    #[tokio::test]
+    async fn test_http_skips_identified_devices() {
+        let config = HybridConfig::default();
+        let scheduler = Arc::new(ProbeScheduler::new(&config.scheduler));
+        let source = HttpSource::new(&config, Arc::default(), scheduler);
+        let device = device("aa:bb:cc:dd:ee:ff", "192.0.2.1");
+        let identified = Evidence {
+            source: "dhcp_fingerprint",
+            os_name: Some("Android".to_string()),
+            confidence: 0.95,
+            ..Default::default()
+        };
+
+        assert!(source.gather(&device, &[identified]).await.is_empty());
+        // Nothing was probed, so nothing was cached or counted
+        assert_eq!(source.cache_stats(), (0, 0));
+        assert!(source.outcomes.snapshot().is_empty());
+    }
+}
@@ -1,6 +1,82 @@
+use crate::client_id::ClientId;
+use crate::vendor_options::EnterpriseOptions;
 use serde::{Deserialize, Serialize};
 use std::net::Ipv4Addr;
 
+/// ARP hardware type codes (RFC 1700 / IANA) seen in the `htype` field
+pub const HTYPE_ETHERNET: u8 = 1;
+pub const HTYPE_IEEE802: u8 = 6;
+pub const HTYPE_FDDI: u8 = 8;
+pub const HTYPE_SERIAL: u8 = 20;
+pub const HTYPE_INFINIBAND: u8 = 32;
+
+/// Message type names stored in `DhcpRequest::message_type`
+pub const MESSAGE_TYPES: [&str; 8] = ["DISCOVER", "REQUEST", "DECLINE", "ACK", "NAK", "RELEASE", "INFORM", "UNKNOWN"];
+
+/// Short label for a hardware type, used to prefix non-Ethernet client identifiers
+pub fn hardware_type_label(htype: u8) -> String {
+    match htype {
+        HTYPE_ETHERNET => "ethernet".to_string(),
+        HTYPE_IEEE802 => "ieee802".to_string(),
+        HTYPE_FDDI => "fddi".to_string(),
+        HTYPE_SERIAL => "serial".to_string(),
+        HTYPE_INFINIBAND => "infiniband".to_string(),
+        other => format!("htype{}", other),
+    }
+}
+
+pub(crate) fn hex_colon(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// PXE client system architecture (option 93, RFC 4578 / IANA registry)
+pub fn client_arch_name(arch: u16) -> &'static str {
+    match arch {
+        0 => "x86 BIOS",
+        1 => "NEC/PC98",
+        2 => "Itanium",
+        3 => "DEC Alpha",
+        4 => "Arc x86",
+        5 => "Intel Lean Client",
+        6 => "x86 UEFI",
+        7 | 9 => "x64 UEFI",
+        8 => "Xscale UEFI",
+        10 => "ARM32 UEFI",
+        11 => "ARM64 UEFI",
+        12 => "PowerPC Open Firmware",
+        13 => "PowerPC ePAPR",
+        14 => "POWER OPAL v3",
+        15 => "x86 UEFI HTTP",
+        16 => "x64 UEFI HTTP",
+        17 => "EBC UEFI HTTP",
+        18 => "ARM32 UEFI HTTP",
+        19 => "ARM64 UEFI HTTP",
+        20 => "x86 BIOS HTTP",
+        21 | 23 => "ARM32 U-Boot",
+        22 | 24 => "ARM64 U-Boot",
+        25 | 26 => "RISC-V 32 UEFI",
+        27 | 28 => "RISC-V 64 UEFI",
+        29 | 30 => "RISC-V 128 UEFI",
+        31 | 32 => "s390",
+        33 => "MIPS32 UEFI",
+        34 => "MIPS64 UEFI",
+        35 | 36 => "Sunway UEFI",
+        37..=40 => "LoongArch UEFI",
+        41 => "ARM rpiboot",
+        _ => "Unknown",
+    }
+}
+
+/// Format a 16-byte UUID as 8-4-4-4-12 hex
+pub(crate) fn format_uuid(bytes: &[u8]) -> String {
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DhcpPacket {
     pub op: u8,
@@ -18,12 +94,17 @@ pub struct DhcpPacket {
     pub options: Vec<DhcpOption>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
 pub struct DhcpOption {
     pub code: u8,
     pub data: Vec<u8>,
 }
 
+/// Option 12 as text; some clients NUL-terminate it
+pub fn decode_hostname(data: &[u8]) -> Option<String> {
+    Some(String::from_utf8_lossy(data).trim_end_matches('\0').to_string()).filter(|name| !name.is_empty())
+}
+
 impl DhcpPacket {
     pub fn parse(data: &[u8]) -> Result<Self, anyhow::Error> {
         if data.len() < 236 {
@@ -46,9 +127,9 @@ impl DhcpPacket {
         let mut chaddr = [0u8; 16];
         chaddr.copy_from_slice(&data[28..44]);
 
-        // Skip server name (64 bytes) and boot file (128 bytes)
-        // Options start at byte 236
-        let options = Self::parse_options(&data[236..])?;
+        // Options start at byte 236, after the server name (64 bytes) and boot file
+        // (128 bytes), which option 52 can reuse for more options
+        let options = Self::parse_options(data)?;
 
         Ok(DhcpPacket {
             op,
@@ -67,14 +148,33 @@ impl DhcpPacket {
         })
     }
 
-    fn parse_options(data: &[u8]) -> Result<Vec<DhcpOption>, anyhow::Error> {
-        let mut options = Vec::new();
+    fn parse_options(packet: &[u8]) -> Result<Vec<DhcpOption>, anyhow::Error> {
+        let data = &packet[236..];
 
         // Check for magic cookie
-        if data.len() < 4 || &data[0..4] != &[99, 130, 83, 99] {
+        if data.len() < 4 || data[0..4] != [99, 130, 83, 99] {
             anyhow::bail!("Invalid DHCP magic cookie");
         }
-        let mut i = 4;
+        let mut options = Vec::new();
+        Self::parse_option_area(&data[4..], &mut options);
+
+        // Option overload (RFC 2132 9.3): options continue in the file field (1),
+        // the sname field (2) or both (3), with file read first
+        let overload = options.iter().find(|opt| opt.code == 52).and_then(|opt| opt.data.first().copied());
+        if matches!(overload, Some(1 | 3)) {
+            Self::parse_option_area(&packet[108..236], &mut options);
+        }
+        if matches!(overload, Some(2 | 3)) {
+            Self::parse_option_area(&packet[44..108], &mut options);
+        }
+
+        Ok(options)
+    }
+
+    /// Options in one area up to the end option. A code seen before continues the
+    /// earlier value, since long options are split into several (RFC 3396).
+    fn parse_option_area(data: &[u8], options: &mut Vec<DhcpOption>) {
+        let mut i = 0;
 
         while i < data.len() {
             let code = data[i];
@@ -101,29 +201,46 @@ impl DhcpPacket {
                 break;
             }
 
-            let option_data = data[i..i + len].to_vec();
-            options.push(DhcpOption {
-                code,
-                data: option_data,
-            });
+            let option_data = &data[i..i + len];
+            match options.iter_mut().find(|opt| opt.code == code) {
+                Some(existing) => existing.data.extend_from_slice(option_data),
+                None => options.push(DhcpOption {
+                    code,
+                    data: option_data.to_vec(),
+                }),
+            }
 
             i += len;
         }
+    }
 
-        Ok(options)
+    /// Whether chaddr holds a 6-byte IEEE 802 MAC address
+    pub fn has_ethernet_address(&self) -> bool {
+        matches!(self.htype, HTYPE_ETHERNET | HTYPE_IEEE802) && self.hlen == 6
     }
 
+    /// Client hardware identifier as a display string
+    /// Ethernet-style addresses are formatted as a plain MAC (aa:bb:cc:dd:ee:ff).
+    /// Other hardware types are prefixed with their type label (e.g. "fddi/...")
+    /// so they are never mistaken for a MAC. When chaddr is unusable (hlen 0 or
+    /// larger than the field), the option 61 client identifier is used instead.
     pub fn get_mac_address(&self) -> String {
         let hlen = self.hlen as usize;
-        if hlen > 16 {
-            return String::new();
+
+        if self.has_ethernet_address() {
+            let mut octets = [0u8; 6];
+            octets.copy_from_slice(&self.chaddr[..6]);
+            return crate::mac::Mac::new(octets).to_string();
+        }
+
+        if hlen == 0 || hlen > self.chaddr.len() {
+            return match self.get_option(61) {
+                Some(opt) if !opt.data.is_empty() => format!("clientid/{}", hex_colon(&opt.data)),
+                _ => String::new(),
+            };
         }
 
-        self.chaddr[..hlen]
-            .iter()
-            .map(|b| format!("{:02x}", b))
-            .collect::<Vec<_>>()
-            .join(":")
+        format!("{}/{}", hardware_type_label(self.htype), hex_colon(&self.chaddr[..hlen]))
     }
 
     pub fn get_option(&self, code: u8) -> Option<&DhcpOption> {
@@ -147,24 +264,422 @@ impl DhcpPacket {
         }
     }
 
+    /// Option 12: Host Name
+    pub fn get_hostname(&self) -> Option<String> {
+        self.get_option(12).and_then(|opt| decode_hostname(&opt.data))
+    }
+
+    /// A 4-byte option holding an IPv4 address, formatted
+    fn get_ipv4_option(&self, code: u8) -> Option<String> {
+        let octets: [u8; 4] = self.get_option(code)?.data.as_slice().try_into().ok()?;
+        Some(std::net::Ipv4Addr::from(octets).to_string())
+    }
+
+    /// Option 50: Requested IP Address
+    pub fn get_requested_ip(&self) -> Option<String> {
+        self.get_ipv4_option(50)
+    }
+
+    /// Option 54: Server Identifier (the server a REQUEST is accepting an offer from)
+    pub fn get_server_id(&self) -> Option<String> {
+        self.get_ipv4_option(54)
+    }
+
+    /// The address the client holds or is being given, and the field it came from:
+    /// yiaddr of an ACK, ciaddr of a REQUEST or INFORM from a configured client,
+    /// or the option 50 address a REQUEST asks for
+    pub fn get_assigned_ip(&self) -> Option<(String, &'static str)> {
+        match self.get_message_type()? {
+            5 if !self.yiaddr.is_unspecified() => Some((self.yiaddr.to_string(), "yiaddr")),
+            3 | 8 if !self.ciaddr.is_unspecified() => Some((self.ciaddr.to_string(), "ciaddr")),
+            3 => self.get_requested_ip().map(|ip| (ip, "requested_ip")),
+            _ => None,
+        }
+    }
+
     pub fn get_vendor_class(&self) -> Option<String> {
         // Option 60: Vendor Class Identifier
         self.get_option(60).map(|opt| {
             String::from_utf8_lossy(&opt.data).to_string()
         })
     }
+
+    /// Option 77: User Class
+    /// RFC 3004 encodes length-prefixed instances (joined with ","); Windows sends a bare string
+    pub fn get_user_class(&self) -> Option<String> {
+        let data = &self.get_option(77)?.data;
+        if data.is_empty() {
+            return None;
+        }
+
+        let mut instances = Vec::new();
+        let mut i = 0;
+        while i < data.len() {
+            let len = data[i] as usize;
+            if len == 0 || i + 1 + len > data.len() {
+                return Some(String::from_utf8_lossy(data).to_string());
+            }
+            instances.push(String::from_utf8_lossy(&data[i + 1..i + 1 + len]).to_string());
+            i += 1 + len;
+        }
+        Some(instances.join(","))
+    }
+
+    /// Option 93: Client System Architecture (first entry if several are listed)
+    pub fn get_client_arch(&self) -> Option<u16> {
+        let data = &self.get_option(93)?.data;
+        (data.len() >= 2).then(|| u16::from_be_bytes([data[0], data[1]]))
+    }
+
+    /// Option 94: Client Network Interface Identifier, e.g. "UNDI 3.16"
+    pub fn get_client_ndi(&self) -> Option<String> {
+        match self.get_option(94)?.data.as_slice() {
+            [1, major, minor] => Some(format!("UNDI {}.{}", major, minor)),
+            _ => None,
+        }
+    }
+
+    /// Option 97: Client Machine Identifier (type 0 = UUID/GUID)
+    pub fn get_client_uuid(&self) -> Option<String> {
+        match self.get_option(97)?.data.as_slice() {
+            [0, uuid @ ..] if uuid.len() == 16 => Some(format_uuid(uuid)),
+            _ => None,
+        }
+    }
+
+    /// Option 81: Client FQDN
+    pub fn get_client_fqdn(&self) -> Option<ClientFqdn> {
+        ClientFqdn::decode(&self.get_option(81)?.data)
+    }
+
+    /// The address of the client's link as relays report it: option 82
+    /// link-selection (RFC 3527), option 118 subnet selection (RFC 3011), or giaddr
+    pub fn get_link_address(&self) -> Option<Ipv4Addr> {
+        let ipv4 = |data: &[u8]| -> Option<Ipv4Addr> { Some(<[u8; 4]>::try_from(data).ok()?.into()) };
+        let link_selection = self.get_option(82).and_then(|opt| {
+            let mut rest = opt.data.as_slice();
+            while let [code, len, tail @ ..] = rest {
+                let len = (*len as usize).min(tail.len());
+                if *code == 5 {
+                    return ipv4(&tail[..len]);
+                }
+                rest = &tail[len..];
+            }
+            None
+        });
+        link_selection
+            .or_else(|| self.get_option(118).and_then(|opt| ipv4(&opt.data)))
+            .or((!self.giaddr.is_unspecified()).then_some(self.giaddr))
+    }
+
+    /// Option 125: Vendor-Identifying Vendor-Specific Information
+    pub fn get_vendor_options(&self) -> Vec<EnterpriseOptions> {
+        self.get_option(125).map(|opt| crate::vendor_options::decode(&opt.data)).unwrap_or_default()
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Builds DHCP packet bytes, for tests and generated traffic.
+/// Starts as an Ethernet BOOTREQUEST with no options; `message` adds option 53
+/// first, where clients put it, and picks BOOTREPLY for server message types.
+#[derive(Debug, Clone)]
+pub struct DhcpPacketBuilder {
+    op: u8,
+    htype: u8,
+    hlen: u8,
+    xid: u32,
+    flags: u16,
+    ciaddr: Ipv4Addr,
+    yiaddr: Ipv4Addr,
+    giaddr: Ipv4Addr,
+    chaddr: [u8; 16],
+    options: Vec<u8>,
+    end: bool,
+}
+
+impl Default for DhcpPacketBuilder {
+    fn default() -> Self {
+        Self {
+            op: 1,
+            htype: HTYPE_ETHERNET,
+            hlen: 6,
+            xid: 0,
+            flags: 0,
+            ciaddr: Ipv4Addr::UNSPECIFIED,
+            yiaddr: Ipv4Addr::UNSPECIFIED,
+            giaddr: Ipv4Addr::UNSPECIFIED,
+            chaddr: [0; 16],
+            options: Vec::new(),
+            end: true,
+        }
+    }
+}
+
+impl DhcpPacketBuilder {
+    /// A DHCP message of `message_type` (option 53)
+    pub fn message(message_type: u8) -> Self {
+        let op = match message_type {
+            2 | 5 | 6 => 2, // OFFER, ACK, NAK
+            _ => 1,
+        };
+        Self { op, ..Self::default() }.option(53, [message_type])
+    }
+
+    /// An Ethernet client hardware address
+    pub fn mac(self, mac: [u8; 6]) -> Self {
+        self.hardware(HTYPE_ETHERNET, &mac)
+    }
+
+    /// Any hardware type; hlen follows the address, which is cut to 16 bytes
+    pub fn hardware(mut self, htype: u8, chaddr: &[u8]) -> Self {
+        let len = chaddr.len().min(16);
+        self.htype = htype;
+        self.hlen = len as u8;
+        self.chaddr = [0; 16];
+        self.chaddr[..len].copy_from_slice(&chaddr[..len]);
+        self
+    }
+
+    pub fn xid(mut self, xid: u32) -> Self {
+        self.xid = xid;
+        self
+    }
+
+    pub fn flags(mut self, flags: u16) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Ask for the answer to be broadcast
+    pub fn broadcast(self) -> Self {
+        self.flags(0x8000)
+    }
+
+    pub fn ciaddr(mut self, ip: Ipv4Addr) -> Self {
+        self.ciaddr = ip;
+        self
+    }
+
+    /// Append an option. Values over 255 bytes are split into consecutive
+    /// options with the same code (RFC 3396), which parse() joins again.
+    pub fn option(mut self, code: u8, data: impl AsRef<[u8]>) -> Self {
+        let data = data.as_ref();
+        if data.is_empty() {
+            self.options.extend_from_slice(&[code, 0]);
+        }
+        for chunk in data.chunks(255) {
+            self.options.push(code);
+            self.options.push(chunk.len() as u8);
+            self.options.extend_from_slice(chunk);
+        }
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut data = vec![0u8; 236];
+        data[0] = self.op;
+        data[1] = self.htype;
+        data[2] = self.hlen;
+        data[4..8].copy_from_slice(&self.xid.to_be_bytes());
+        data[10..12].copy_from_slice(&self.flags.to_be_bytes());
+        data[12..16].copy_from_slice(&self.ciaddr.octets());
+        data[16..20].copy_from_slice(&self.yiaddr.octets());
+        data[24..28].copy_from_slice(&self.giaddr.octets());
+        data[28..44].copy_from_slice(&self.chaddr);
+        data.extend_from_slice(&[99, 130, 83, 99]);
+        data.extend_from_slice(&self.options);
+        if self.end {
+            data.push(255);
+        }
+        data
+    }
+}
+
+// Only tests build these packets
+#[cfg(test)]
+impl DhcpPacketBuilder {
+    /// A BOOTP request: magic cookie but no message type
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn discover() -> Self {
+        Self::message(1)
+    }
+
+    pub fn request() -> Self {
+        Self::message(3)
+    }
+
+    pub fn ack() -> Self {
+        Self::message(5)
+    }
+
+    /// Override hlen without touching chaddr, for inconsistent packets
+    pub fn hlen(mut self, hlen: u8) -> Self {
+        self.hlen = hlen;
+        self
+    }
+
+    pub fn yiaddr(mut self, ip: Ipv4Addr) -> Self {
+        self.yiaddr = ip;
+        self
+    }
+
+    pub fn giaddr(mut self, ip: Ipv4Addr) -> Self {
+        self.giaddr = ip;
+        self
+    }
+
+    /// Append bytes to the option area as they are, for padding or malformed options
+    pub fn raw_options(mut self, bytes: &[u8]) -> Self {
+        self.options.extend_from_slice(bytes);
+        self
+    }
+
+    /// Leave out the end option
+    pub fn without_end(mut self) -> Self {
+        self.end = false;
+        self
+    }
+}
+
+/// Option 81 flags (RFC 4702 section 2.1)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FqdnFlags {
+    /// S: the client asks the server to update its A record
+    pub server_update: bool,
+    /// O: the server overrode the client's preference (server to client only)
+    pub server_override: bool,
+    /// E: the name is in DNS wire format rather than ASCII
+    pub encoded: bool,
+    /// N: the server should not update any DNS records
+    pub no_update: bool,
+}
+
+impl FqdnFlags {
+    fn from_bits(bits: u8) -> Self {
+        Self {
+            server_update: bits & 0x01 != 0,
+            server_override: bits & 0x02 != 0,
+            encoded: bits & 0x04 != 0,
+            no_update: bits & 0x08 != 0,
+        }
+    }
+}
+
+/// Who the client expects to register it in DNS
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DdnsMode {
+    /// S: the server updates both the A and PTR records
+    Server,
+    /// Neither S nor N: the client updates its A record, the server the PTR
+    Client,
+    /// N: no server updates
+    None,
+}
+
+/// Option 81 client FQDN, decoded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientFqdn {
+    pub flags: FqdnFlags,
+    /// Raw flags byte
+    pub flag_bits: u8,
+    /// Deprecated RCODE1/RCODE2 fields (clients send 0, old servers 255)
+    pub rcode1: u8,
+    pub rcode2: u8,
+    /// The name in ASCII, e.g. "laptop.example.com"
+    pub name: String,
+    /// The name in DNS wire format (hex), whichever encoding the client used
+    pub wire: String,
+    /// Only a host label or an unterminated name; the server appends its domain
+    pub partial: bool,
+    pub ddns: DdnsMode,
+}
+
+impl ClientFqdn {
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let [flag_bits, rcode1, rcode2, name @ ..] = data else {
+            return None;
+        };
+        let flags = FqdnFlags::from_bits(*flag_bits);
+
+        let (labels, partial) = if flags.encoded {
+            // Length-prefixed labels; a name without the root label is partial
+            let mut labels = Vec::new();
+            let mut rest = name;
+            loop {
+                match rest {
+                    [] => break (labels, true),
+                    [0, ..] => break (labels, false),
+                    [len, tail @ ..] if *len as usize <= tail.len() && *len < 64 => {
+                        labels.push(String::from_utf8_lossy(&tail[..*len as usize]).into_owned());
+                        rest = &tail[*len as usize..];
+                    }
+                    _ => return None,
+                }
+            }
+        } else {
+            let ascii = String::from_utf8_lossy(name).trim_end_matches('\0').to_string();
+            let partial = !ascii.contains('.') || !ascii.ends_with('.');
+            let labels = ascii.split('.').filter(|label| !label.is_empty()).map(String::from).collect();
+            (labels, partial)
+        };
+
+        let mut wire = Vec::new();
+        for label in &labels {
+            wire.push(label.len() as u8);
+            wire.extend_from_slice(label.as_bytes());
+        }
+        if !partial {
+            wire.push(0);
+        }
+
+        Some(Self {
+            flags,
+            flag_bits: *flag_bits,
+            rcode1: *rcode1,
+            rcode2: *rcode2,
+            name: labels.join("."),
+            wire: hex_colon(&wire),
+            partial,
+            ddns: match (flags.no_update, flags.server_update) {
+                (true, _) => DdnsMode::None,
+                (false, true) => DdnsMode::Server,
+                (false, false) => DdnsMode::Client,
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, utoipa::ToSchema)]
 pub struct DhcpRequest {
+    /// Database row id, for requests read back from the database
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
     pub timestamp: String,
     pub source_ip: String,
     pub source_port: u16,
+    /// Canonical form (see `crate::mac`), whatever notation it was imported in
+    #[serde(deserialize_with = "crate::mac::deserialize_canonical")]
     pub mac_address: String,
     pub message_type: String,
     pub xid: String,
     pub fingerprint: String,
     pub vendor_class: Option<String>,
+    /// Option 12 hostname
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// Option 81 client FQDN and the DNS updates it asks for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub client_fqdn: Option<ClientFqdn>,
+    /// Option 50 requested IP address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_ip: Option<String>,
+    /// Option 54 server identifier
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
     pub os_name: Option<String>,
     pub device_class: Option<String>,
     pub raw_options: Vec<DhcpOption>,
@@ -172,6 +687,62 @@ pub struct DhcpRequest {
     pub confidence: Option<f32>,
     pub smb_dialect: Option<String>,
     pub smb_build: Option<u32>,
+    /// 802.1Q VLAN ID, when the capture backend sees link-layer headers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vlan_id: Option<u16>,
+    /// Option 77 user class
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_class: Option<String>,
+    /// Option 93 PXE client architecture
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_arch: Option<u16>,
+    /// Option 94 PXE network interface identifier
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ndi: Option<String>,
+    /// Option 97 PXE client machine UUID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_uuid: Option<String>,
+    /// Option 61 client identifier, decoded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub client_id: Option<ClientId>,
+    /// Option 125 vendor blocks: enterprise numbers and their sub-options
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schema(value_type = Vec<Object>)]
+    pub vendor_options: Vec<EnterpriseOptions>,
+    /// RFC conformance problems found in the packet (lint codes)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lint_issues: Vec<String>,
+    /// Identical requests folded into this one by deduplication
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub repeats: u32,
+    /// Time of the last folded repeat
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_repeat_at: Option<String>,
+    /// Site of the remote agent that captured the request; None for local capture
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site_id: Option<String>,
+    /// Subnet the request came from in CIDR notation (see `crate::subnets`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subnet: Option<String>,
+    /// Forwarded by a relay (giaddr set), so source_ip is the relay's, not the client's
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub relayed: bool,
+    /// Address the client holds or is being given and its source field (see
+    /// `DhcpPacket::get_assigned_ip`); recorded in ip_history, not stored per request
+    #[serde(skip)]
+    pub assigned_ip: Option<(String, &'static str)>,
+    /// The UDP payload as received, for pcap export
+    #[serde(skip)]
+    pub raw_packet: Option<Vec<u8>>,
+}
+
+fn is_zero(count: &u32) -> bool {
+    *count == 0
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
 }
 
 impl DhcpRequest {
@@ -189,19 +760,18 @@ impl DhcpRequest {
 
         let fingerprint = packet.get_fingerprint();
         let mac_address = packet.get_mac_address();
+        let vendor_class = packet.get_vendor_class();
+        let hostname = packet.get_hostname();
 
-        // Lookup OS information from MAC mapping and fingerprint
-        let (os_name, device_class) = if !fingerprint.is_empty() {
-            if let Some(os_info) = crate::fingerprint::lookup_os(&mac_address, &fingerprint) {
-                (Some(os_info.os_name.to_string()), Some(os_info.device_class.to_string()))
-            } else {
-                (None, None)
-            }
-        } else {
-            (None, None)
+        // Lookup OS information from MAC mapping, classification rules and fingerprint
+        let os_info = crate::fingerprint::lookup_os(&mac_address, &fingerprint, vendor_class.as_deref(), hostname.as_deref());
+        let (os_name, device_class) = match os_info {
+            Some(os_info) => (Some(os_info.os_name.into_owned()), Some(os_info.device_class.into_owned())),
+            None => (None, None),
         };
 
         DhcpRequest {
+            id: None,
             timestamp: chrono::Utc::now().to_rfc3339(),
             source_ip,
             source_port,
@@ -209,7 +779,11 @@ impl DhcpRequest {
             message_type,
             xid: format!("{:08x}", packet.xid),
             fingerprint,
-            vendor_class: packet.get_vendor_class(),
+            vendor_class,
+            hostname,
+            client_fqdn: packet.get_client_fqdn(),
+            requested_ip: packet.get_requested_ip(),
+            server_id: packet.get_server_id(),
             os_name,
             device_class,
             raw_options: packet.options.clone(),
@@ -217,6 +791,312 @@ impl DhcpRequest {
             confidence: None,
             smb_dialect: None,
             smb_build: None,
+            vlan_id: None,
+            user_class: packet.get_user_class(),
+            client_arch: packet.get_client_arch(),
+            client_ndi: packet.get_client_ndi(),
+            client_uuid: packet.get_client_uuid(),
+            client_id: packet.get_option(61).and_then(|opt| ClientId::decode(&opt.data)),
+            vendor_options: packet.get_vendor_options(),
+            lint_issues: Vec::new(),
+            repeats: 0,
+            last_repeat_at: None,
+            site_id: None,
+            subnet: None,
+            relayed: !packet.giaddr.is_unspecified(),
+            assigned_ip: packet.get_assigned_ip(),
+            raw_packet: None,
+        }
+    }
+
+    /// Data of the first instance of an option
+    pub fn option_data(&self, code: u8) -> Option<&[u8]> {
+        self.raw_options.iter().find(|option| option.code == code).map(|option| option.data.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_packet(htype: u8, hlen: u8, chaddr: &[u8], options: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut builder = DhcpPacketBuilder::discover().hardware(htype, chaddr).hlen(hlen);
+        for (code, value) in options {
+            builder = builder.option(*code, value);
         }
+        builder.build()
+    }
+
+    #[test]
+    fn test_requested_ip_and_server_id() {
+        let data = build_packet(1, 6, &[0xaa; 6], &[(50, &[192, 168, 1, 50]), (54, &[192, 168, 1, 1])]);
+        let packet = DhcpPacket::parse(&data).unwrap();
+        assert_eq!(packet.get_requested_ip().as_deref(), Some("192.168.1.50"));
+        assert_eq!(packet.get_server_id().as_deref(), Some("192.168.1.1"));
+
+        // Malformed lengths are ignored rather than misread
+        let data = build_packet(1, 6, &[0xaa; 6], &[(50, &[192, 168, 1])]);
+        assert_eq!(DhcpPacket::parse(&data).unwrap().get_requested_ip(), None);
+    }
+
+    #[test]
+    fn test_assigned_ip() {
+        let mut data = build_packet(1, 6, &[0xaa; 6], &[(50, &[192, 168, 1, 50])]);
+        // A DISCOVER's option 50 is only a hint
+        assert_eq!(DhcpPacket::parse(&data).unwrap().get_assigned_ip(), None);
+
+        data[242] = 3; // REQUEST while selecting
+        assert_eq!(
+            DhcpPacket::parse(&data).unwrap().get_assigned_ip(),
+            Some(("192.168.1.50".to_string(), "requested_ip"))
+        );
+
+        data[12..16].copy_from_slice(&[192, 168, 1, 60]); // renewing, ciaddr set
+        assert_eq!(
+            DhcpPacket::parse(&data).unwrap().get_assigned_ip(),
+            Some(("192.168.1.60".to_string(), "ciaddr"))
+        );
+    }
+
+    #[test]
+    fn test_option_overload_and_split_options() {
+        let mut data = build_packet(1, 6, &[0xaa; 6], &[(52, &[3]), (55, &[1, 3])]);
+        // file field: the rest of the parameter list, then the hostname
+        data[108..117].copy_from_slice(&[55, 2, 6, 15, 12, 2, b'p', b'c', 255]);
+        // sname field: vendor class
+        data[44..52].copy_from_slice(&[60, 5, b'M', b'S', b'F', b'T', b'5', 255]);
+
+        let packet = DhcpPacket::parse(&data).unwrap();
+        assert_eq!(packet.get_fingerprint(), "1,3,6,15");
+        assert_eq!(packet.get_hostname().as_deref(), Some("pc"));
+        assert_eq!(packet.get_vendor_class().as_deref(), Some("MSFT5"));
+
+        // Without option 52 the sname and file fields are not options
+        data[243..246].copy_from_slice(&[52, 1, 0]);
+        let packet = DhcpPacket::parse(&data).unwrap();
+        assert_eq!(packet.get_fingerprint(), "1,3");
+        assert_eq!(packet.get_hostname(), None);
+    }
+
+    #[test]
+    fn test_client_fqdn() {
+        // Windows: E and S set, wire-format name
+        let fqdn = ClientFqdn::decode(b"\x05\0\0\x06laptop\x07example\x03com\x00").unwrap();
+        assert!(fqdn.flags.encoded && fqdn.flags.server_update);
+        assert_eq!(fqdn.name, "laptop.example.com");
+        assert!(!fqdn.partial);
+        assert_eq!(fqdn.ddns, DdnsMode::Server);
+
+        // ASCII host label only, asking for no server updates
+        let fqdn = ClientFqdn::decode(b"\x08\xff\xffphone").unwrap();
+        assert_eq!((fqdn.name.as_str(), fqdn.partial, fqdn.ddns), ("phone", true, DdnsMode::None));
+        assert_eq!(fqdn.wire, "05:70:68:6f:6e:65");
+        assert_eq!(fqdn.rcode1, 255);
+
+        assert_eq!(ClientFqdn::decode(b"\0\0\0host.lan.").unwrap().ddns, DdnsMode::Client);
+        assert!(ClientFqdn::decode(b"\x04\0\0\x09short").is_none());
+        assert!(ClientFqdn::decode(&[0, 0]).is_none());
+    }
+
+    #[test]
+    fn test_hostname_trims_padding() {
+        let data = build_packet(1, 6, &[0xaa; 6], &[(12, b"laptop\0")]);
+        assert_eq!(DhcpPacket::parse(&data).unwrap().get_hostname().as_deref(), Some("laptop"));
+
+        let data = build_packet(1, 6, &[0xaa; 6], &[(12, b"\0")]);
+        assert_eq!(DhcpPacket::parse(&data).unwrap().get_hostname(), None);
+    }
+
+    #[test]
+    fn test_ethernet_mac() {
+        let data = build_packet(1, 6, &[0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff], &[]);
+        let packet = DhcpPacket::parse(&data).unwrap();
+        assert!(packet.has_ethernet_address());
+        assert_eq!(packet.get_mac_address(), "aa:bb:cc:dd:ee:ff");
+    }
+
+    #[test]
+    fn test_ieee802_mac() {
+        let data = build_packet(6, 6, &[0x00, 0x11, 0x22, 0x33, 0x44, 0x55], &[]);
+        let packet = DhcpPacket::parse(&data).unwrap();
+        assert_eq!(packet.get_mac_address(), "00:11:22:33:44:55");
+    }
+
+    #[test]
+    fn test_fddi_identifier_is_prefixed() {
+        let data = build_packet(8, 6, &[0x00, 0x11, 0x22, 0x33, 0x44, 0x55], &[]);
+        let packet = DhcpPacket::parse(&data).unwrap();
+        assert!(!packet.has_ethernet_address());
+        assert_eq!(packet.get_mac_address(), "fddi/00:11:22:33:44:55");
+    }
+
+    #[test]
+    fn test_serial_short_hlen() {
+        let data = build_packet(20, 2, &[0x12, 0x34], &[]);
+        let packet = DhcpPacket::parse(&data).unwrap();
+        assert_eq!(packet.get_mac_address(), "serial/12:34");
+    }
+
+    #[test]
+    fn test_ethernet_with_wrong_hlen_is_not_a_mac() {
+        let data = build_packet(1, 4, &[0xaa, 0xbb, 0xcc, 0xdd], &[]);
+        let packet = DhcpPacket::parse(&data).unwrap();
+        assert!(!packet.has_ethernet_address());
+        assert_eq!(packet.get_mac_address(), "ethernet/aa:bb:cc:dd");
+    }
+
+    #[test]
+    fn test_unknown_htype_label() {
+        let data = build_packet(99, 3, &[1, 2, 3], &[]);
+        let packet = DhcpPacket::parse(&data).unwrap();
+        assert_eq!(packet.get_mac_address(), "htype99/01:02:03");
+    }
+
+    #[test]
+    fn test_zero_hlen_uses_client_identifier() {
+        // InfiniBand clients leave chaddr empty and identify via option 61 (DUID)
+        let client_id = [0xff, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02];
+        let data = build_packet(32, 0, &[], &[(61, &client_id)]);
+        let packet = DhcpPacket::parse(&data).unwrap();
+        assert_eq!(packet.get_mac_address(), "clientid/ff:00:00:00:01:00:02");
+    }
+
+    #[test]
+    fn test_oversized_hlen_without_client_identifier() {
+        let data = build_packet(1, 20, &[0xaa; 16], &[]);
+        let packet = DhcpPacket::parse(&data).unwrap();
+        assert_eq!(packet.get_mac_address(), "");
+    }
+
+    #[test]
+    fn test_full_length_chaddr() {
+        let chaddr: Vec<u8> = (0..16).collect();
+        let data = build_packet(32, 16, &chaddr, &[]);
+        let packet = DhcpPacket::parse(&data).unwrap();
+        assert!(packet.get_mac_address().starts_with("infiniband/00:01:02"));
+        assert!(packet.get_mac_address().ends_with(":0f"));
+    }
+
+    #[test]
+    fn test_user_class() {
+        // RFC 3004 instances
+        let data = build_packet(1, 6, &[0; 6], &[(77, b"\x04iPXE\x03lab")]);
+        assert_eq!(DhcpPacket::parse(&data).unwrap().get_user_class().as_deref(), Some("iPXE,lab"));
+
+        // Windows sends a plain string
+        let data = build_packet(1, 6, &[0; 6], &[(77, b"corp-laptops")]);
+        assert_eq!(DhcpPacket::parse(&data).unwrap().get_user_class().as_deref(), Some("corp-laptops"));
+    }
+
+    #[test]
+    fn test_pxe_options() {
+        let mut uuid = vec![0u8];
+        uuid.extend(0x10..0x20u8);
+        let data = build_packet(
+            1,
+            6,
+            &[0; 6],
+            &[(93, &[0, 7]), (94, &[1, 3, 16]), (97, &uuid)],
+        );
+        let request = DhcpRequest::from_packet(&DhcpPacket::parse(&data).unwrap(), "0.0.0.0".to_string(), 68);
+
+        assert_eq!(request.client_arch, Some(7));
+        assert_eq!(client_arch_name(7), "x64 UEFI");
+        assert_eq!(request.client_ndi.as_deref(), Some("UNDI 3.16"));
+        assert_eq!(
+            request.client_uuid.as_deref(),
+            Some("10111213-1415-1617-1819-1a1b1c1d1e1f")
+        );
+        assert_eq!(request.user_class, None);
+    }
+
+    #[test]
+    fn test_builder_roundtrip() {
+        let data = DhcpPacketBuilder::ack()
+            .mac([0x00, 0x11, 0x22, 0x33, 0x44, 0x55])
+            .xid(0xdeadbeef)
+            .broadcast()
+            .yiaddr(Ipv4Addr::new(10, 0, 0, 20))
+            .giaddr(Ipv4Addr::new(10, 0, 0, 1))
+            .option(54, [10, 0, 0, 1])
+            .build();
+        let packet = DhcpPacket::parse(&data).unwrap();
+
+        assert_eq!((packet.op, packet.get_message_type()), (2, Some(5)));
+        assert_eq!((packet.xid, packet.flags), (0xdeadbeef, 0x8000));
+        assert_eq!(packet.yiaddr, Ipv4Addr::new(10, 0, 0, 20));
+        assert_eq!(packet.get_link_address(), Some(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(packet.get_mac_address(), "00:11:22:33:44:55");
+        assert_eq!(packet.get_server_id().as_deref(), Some("10.0.0.1"));
+        let request = DhcpPacket::parse(&DhcpPacketBuilder::request().build()).unwrap();
+        assert_eq!((request.op, request.get_message_type()), (1, Some(3)));
+    }
+
+    #[test]
+    fn test_parse_rejects_short_or_cookieless() {
+        let data = DhcpPacketBuilder::discover().build();
+        assert!(DhcpPacket::parse(&data[..235]).is_err());
+        // The fixed header alone has no room for the cookie
+        assert!(DhcpPacket::parse(&data[..236]).is_err());
+
+        let mut data = data;
+        data[236] = 0;
+        assert!(DhcpPacket::parse(&data).is_err());
+    }
+
+    #[test]
+    fn test_bootp_without_options() {
+        let packet = DhcpPacket::parse(&DhcpPacketBuilder::new().without_end().build()).unwrap();
+        assert!(packet.options.is_empty());
+        assert_eq!(packet.get_message_type(), None);
+        assert_eq!(packet.get_fingerprint(), "");
+    }
+
+    #[test]
+    fn test_long_option_is_split_and_joined() {
+        let vendor_class = "v".repeat(300);
+        let data = DhcpPacketBuilder::discover().option(60, &vendor_class).option(12, "pc").build();
+        // 255 bytes, then the remaining 45 in a second option 60
+        assert_eq!(&data[243..245], &[60, 255]);
+        assert_eq!(&data[500..502], &[60, 45]);
+
+        let packet = DhcpPacket::parse(&data).unwrap();
+        assert_eq!(packet.get_vendor_class(), Some(vendor_class));
+        assert_eq!(packet.get_hostname().as_deref(), Some("pc"));
+        assert_eq!(packet.options.len(), 3);
+    }
+
+    #[test]
+    fn test_pad_end_and_truncated_options() {
+        // Pad before an option, and nothing after the end option is read
+        let data = DhcpPacketBuilder::discover()
+            .raw_options(&[0, 0, 12, 2, b'p', b'c', 255, 60, 1, b'x'])
+            .build();
+        let packet = DhcpPacket::parse(&data).unwrap();
+        assert_eq!(packet.get_hostname().as_deref(), Some("pc"));
+        assert!(packet.get_vendor_class().is_none());
+
+        // An option running past the end of the packet is dropped, earlier ones kept
+        let data = DhcpPacketBuilder::discover()
+            .option(55, [1, 3, 6])
+            .raw_options(&[12, 10, b'p', b'c'])
+            .without_end()
+            .build();
+        let packet = DhcpPacket::parse(&data).unwrap();
+        assert_eq!(packet.get_fingerprint(), "1,3,6");
+        assert!(packet.get_hostname().is_none());
+
+        // A code with no length byte left
+        let data = DhcpPacketBuilder::discover().raw_options(&[12]).without_end().build();
+        assert_eq!(DhcpPacket::parse(&data).unwrap().options.len(), 1);
+    }
+
+    #[test]
+    fn test_empty_option_values() {
+        let data = DhcpPacketBuilder::discover().option(12, []).option(55, []).build();
+        let packet = DhcpPacket::parse(&data).unwrap();
+        assert_eq!(packet.get_hostname(), None);
+        assert_eq!(packet.get_fingerprint(), "");
+        assert_eq!(packet.get_option(55).map(|opt| opt.data.len()), Some(0));
     }
 }
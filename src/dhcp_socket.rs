@@ -0,0 +1,230 @@
+//! Where DHCP packets are read from.
+//!
+//! By default the monitor binds UDP port 67, and the kernel hands it DHCP payloads
+//! with the link-layer headers, including any 802.1Q tags, already stripped. With
+//! `[receive] interface` set it reads whole Ethernet frames from that interface
+//! through an AF_PACKET socket instead (Linux only), e.g. a mirror or trunk port,
+//! and records the VLAN each request was tagged with. Tags the NIC strips on
+//! receive (VLAN offload) are recovered from the kernel's packet auxdata.
+//! Clustered instances need the latter: every packet socket sees every frame,
+//! while a UDP port shared by two processes hands unicast datagrams to only one.
+
+use crate::frame::parse_dhcp_frame;
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+
+/// One DHCP payload copied into the caller's buffer
+#[derive(Debug)]
+pub struct Received {
+    /// Bytes copied; equal to the buffer's size when the payload may not have fit
+    pub len: usize,
+    pub source: SocketAddr,
+    pub vlan_id: Option<u16>,
+}
+
+pub enum DhcpSocket {
+    Udp(UdpSocket),
+    #[cfg(target_os = "linux")]
+    Packet(packet::PacketSocket),
+}
+
+impl DhcpSocket {
+    /// UDP `port` on all addresses, or frames from `interface` when given
+    pub async fn bind(port: u16, interface: Option<&str>) -> io::Result<Self> {
+        match interface {
+            None => Ok(Self::Udp(UdpSocket::bind(("0.0.0.0", port)).await?)),
+            #[cfg(target_os = "linux")]
+            Some(interface) => Ok(Self::Packet(packet::PacketSocket::open(interface, port)?)),
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "capturing from an interface needs Linux")),
+        }
+    }
+
+    pub async fn recv(&mut self, buffer: &mut [u8]) -> io::Result<Received> {
+        match self {
+            Self::Udp(socket) => {
+                let (len, source) = socket.recv_from(buffer).await?;
+                Ok(Received { len, source, vlan_id: None })
+            }
+            #[cfg(target_os = "linux")]
+            Self::Packet(socket) => loop {
+                let (len, offloaded_vlan) = socket.recv_frame().await?;
+                // Anything else crossing the interface is skipped here
+                let Some(frame) = parse_dhcp_frame(&socket.frame[..len]) else {
+                    continue;
+                };
+                if frame.dest_port != socket.port {
+                    continue;
+                }
+                let len = frame.payload.len().min(buffer.len());
+                buffer[..len].copy_from_slice(&frame.payload[..len]);
+                return Ok(Received {
+                    len,
+                    source: SocketAddr::from((frame.source_ip, frame.source_port)),
+                    // A tag still in the frame is the inner one under QinQ
+                    vlan_id: frame.vlan_id.or(offloaded_vlan),
+                });
+            },
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod packet {
+    use std::ffi::CString;
+    use std::io;
+    use std::mem;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use tokio::io::unix::AsyncFd;
+
+    /// Largest frame read; jumbo frames fit
+    const FRAME_SIZE: usize = 65536;
+
+    pub struct PacketSocket {
+        fd: AsyncFd<OwnedFd>,
+        pub frame: Vec<u8>,
+        /// UDP destination port of the packets wanted
+        pub port: u16,
+    }
+
+    fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(result)
+        }
+    }
+
+    impl PacketSocket {
+        pub fn open(interface: &str, port: u16) -> io::Result<Self> {
+            let name = CString::new(interface).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+            let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+            if index == 0 {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("no interface named {}", interface)));
+            }
+
+            let protocol = (libc::ETH_P_ALL as u16).to_be();
+            let fd = check(unsafe {
+                libc::socket(
+                    libc::AF_PACKET,
+                    libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                    protocol as libc::c_int,
+                )
+            })?;
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+            let enable: libc::c_int = 1;
+            check(unsafe {
+                libc::setsockopt(
+                    fd.as_raw_fd(),
+                    libc::SOL_PACKET,
+                    libc::PACKET_AUXDATA,
+                    &enable as *const _ as *const libc::c_void,
+                    mem::size_of_val(&enable) as libc::socklen_t,
+                )
+            })?;
+
+            let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+            addr.sll_family = libc::AF_PACKET as u16;
+            addr.sll_protocol = protocol;
+            addr.sll_ifindex = index as i32;
+            check(unsafe {
+                libc::bind(
+                    fd.as_raw_fd(),
+                    &addr as *const _ as *const libc::sockaddr,
+                    mem::size_of_val(&addr) as libc::socklen_t,
+                )
+            })?;
+
+            Ok(Self {
+                fd: AsyncFd::new(fd)?,
+                frame: vec![0; FRAME_SIZE],
+                port,
+            })
+        }
+
+        /// Read the next incoming frame into `frame`: its length and the VLAN the
+        /// NIC stripped from it, if any. Frames this host sent are skipped.
+        pub async fn recv_frame(&mut self) -> io::Result<(usize, Option<u16>)> {
+            loop {
+                let mut ready = self.fd.readable().await?;
+                match ready.try_io(|fd| recv_frame(fd.get_ref().as_raw_fd(), &mut self.frame)) {
+                    Ok(Ok(Some(received))) => return Ok(received),
+                    Ok(Ok(None)) => continue,
+                    Ok(Err(e)) => return Err(e),
+                    Err(_would_block) => continue,
+                }
+            }
+        }
+    }
+
+    fn recv_frame(fd: libc::c_int, frame: &mut [u8]) -> io::Result<Option<(usize, Option<u16>)>> {
+        let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+        let mut iov = libc::iovec {
+            iov_base: frame.as_mut_ptr() as *mut libc::c_void,
+            iov_len: frame.len(),
+        };
+        // Room for one tpacket_auxdata control message, suitably aligned
+        let mut control = [0u64; 8];
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_name = &mut addr as *mut _ as *mut libc::c_void;
+        msg.msg_namelen = mem::size_of_val(&addr) as libc::socklen_t;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+
+        let len = unsafe { libc::recvmsg(fd, &mut msg, 0) };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if addr.sll_pkttype == libc::PACKET_OUTGOING {
+            return Ok(None);
+        }
+
+        let mut vlan_id = None;
+        let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+        while !cmsg.is_null() {
+            let header = unsafe { &*cmsg };
+            if header.cmsg_level == libc::SOL_PACKET && header.cmsg_type == libc::PACKET_AUXDATA {
+                let aux: libc::tpacket_auxdata =
+                    unsafe { std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::tpacket_auxdata) };
+                if aux.tp_status & libc::TP_STATUS_VLAN_VALID != 0 {
+                    vlan_id = Some(aux.tp_vlan_tci & 0x0fff);
+                }
+            }
+            cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+        }
+        Ok(Some((len as usize, vlan_id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_interface_capture() {
+        // Packet sockets need CAP_NET_RAW
+        let mut socket = match DhcpSocket::bind(67, Some("lo")).await {
+            Ok(socket) => socket,
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => return,
+            Err(e) => panic!("{}", e),
+        };
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        // Our outgoing copies, the DHCP reply port and the ICMP unreachables are skipped
+        sender.send_to(b"reply", "127.0.0.1:68").await.unwrap();
+        sender.send_to(b"dhcp", "127.0.0.1:67").await.unwrap();
+
+        let mut buffer = [0u8; 64];
+        let received = tokio::time::timeout(std::time::Duration::from_secs(5), socket.recv(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buffer[..received.len], b"dhcp");
+        assert_eq!(received.source, sender.local_addr().unwrap());
+        assert_eq!(received.vlan_id, None);
+    }
+}
@@ -1,7 +1,21 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
+use std::sync::RwLock;
 use once_cell::sync::Lazy;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+/// Installed upstream fingerprint bundle (see fingerprint_update), unless
+/// `[fingerprint_updates] bundle_path` says otherwise
+pub const FINGERPRINT_BUNDLE_PATH: &str = "fingerprints.json";
+
+/// Where the bundle is read from on first lookup and on reload
+static BUNDLE_PATH: Lazy<RwLock<String>> = Lazy::new(|| RwLock::new(FINGERPRINT_BUNDLE_PATH.to_string()));
+
+/// Read the bundle from `path` from now on; call before the first lookup
+pub fn set_bundle_path(path: &str) {
+    *BUNDLE_PATH.write().unwrap() = path.to_string();
+}
 
 /// DHCP fingerprint database for OS identification
 /// Fingerprints are based on DHCP Option 55 (Parameter Request List)
@@ -10,229 +24,370 @@ static FINGERPRINT_DB: Lazy<HashMap<&'static str, OsInfo>> = Lazy::new(|| {
 
     // Windows 11 (must be checked before Windows 10 due to superset)
     db.insert("1,3,6,15,31,33,43,44,46,47,121,249,252,12", OsInfo {
-        os_name: "Windows 11",
-        device_class: "Desktop/Laptop",
-        vendor: "Microsoft",
+        os_name: Cow::Borrowed("Windows 11"),
+        device_class: Cow::Borrowed("Desktop/Laptop"),
+        vendor: Cow::Borrowed("Microsoft"),
     });
 
     // Windows 10/8/8.1 (same fingerprint)
     db.insert("1,3,6,15,31,33,43,44,46,47,121,249,252", OsInfo {
-        os_name: "Windows 10/8/8.1",
-        device_class: "Desktop/Laptop",
-        vendor: "Microsoft",
+        os_name: Cow::Borrowed("Windows 10/8/8.1"),
+        device_class: Cow::Borrowed("Desktop/Laptop"),
+        vendor: Cow::Borrowed("Microsoft"),
     });
 
     // Windows 7
     db.insert("1,15,3,6,44,46,47,31,33,121,249,43,252", OsInfo {
-        os_name: "Windows 7",
-        device_class: "Desktop/Laptop",
-        vendor: "Microsoft",
+        os_name: Cow::Borrowed("Windows 7"),
+        device_class: Cow::Borrowed("Desktop/Laptop"),
+        vendor: Cow::Borrowed("Microsoft"),
     });
 
     // macOS (Ventura/Sonoma)
     db.insert("1,3,6,15,119,252", OsInfo {
-        os_name: "macOS (Recent)",
-        device_class: "Desktop/Laptop",
-        vendor: "Apple",
+        os_name: Cow::Borrowed("macOS (Recent)"),
+        device_class: Cow::Borrowed("Desktop/Laptop"),
+        vendor: Cow::Borrowed("Apple"),
     });
 
     // macOS (older versions)
     db.insert("1,3,6,15,119,95,252,44,46", OsInfo {
-        os_name: "macOS (Older)",
-        device_class: "Desktop/Laptop",
-        vendor: "Apple",
+        os_name: Cow::Borrowed("macOS (Older)"),
+        device_class: Cow::Borrowed("Desktop/Laptop"),
+        vendor: Cow::Borrowed("Apple"),
     });
 
     // iOS/iPadOS
     db.insert("1,3,6,15,119,252,95,44,46", OsInfo {
-        os_name: "iOS/iPadOS",
-        device_class: "Mobile",
-        vendor: "Apple",
+        os_name: Cow::Borrowed("iOS/iPadOS"),
+        device_class: Cow::Borrowed("Mobile"),
+        vendor: Cow::Borrowed("Apple"),
     });
 
     // iOS (alternative)
     db.insert("1,121,3,6,15,119,252,95,44,46", OsInfo {
-        os_name: "iOS",
-        device_class: "Mobile",
-        vendor: "Apple",
+        os_name: Cow::Borrowed("iOS"),
+        device_class: Cow::Borrowed("Mobile"),
+        vendor: Cow::Borrowed("Apple"),
     });
 
     // Android (common)
     db.insert("1,3,6,15,26,28,51,58,59", OsInfo {
-        os_name: "Android",
-        device_class: "Mobile",
-        vendor: "Google",
+        os_name: Cow::Borrowed("Android"),
+        device_class: Cow::Borrowed("Mobile"),
+        vendor: Cow::Borrowed("Google"),
     });
 
     // Android (alternative)
     db.insert("1,3,6,12,15,26,28,51,58,59,43", OsInfo {
-        os_name: "Android",
-        device_class: "Mobile",
-        vendor: "Google",
+        os_name: Cow::Borrowed("Android"),
+        device_class: Cow::Borrowed("Mobile"),
+        vendor: Cow::Borrowed("Google"),
     });
 
     // Linux (Ubuntu/Debian)
     db.insert("1,28,2,3,15,6,119,12,44,47,26,121,42", OsInfo {
-        os_name: "Linux (Ubuntu/Debian)",
-        device_class: "Desktop/Server",
-        vendor: "Linux",
+        os_name: Cow::Borrowed("Linux (Ubuntu/Debian)"),
+        device_class: Cow::Borrowed("Desktop/Server"),
+        vendor: Cow::Borrowed("Linux"),
     });
 
     // Linux (general)
     db.insert("1,3,6,12,15,28,42,51,54,58,59", OsInfo {
-        os_name: "Linux",
-        device_class: "Desktop/Server",
-        vendor: "Linux",
+        os_name: Cow::Borrowed("Linux"),
+        device_class: Cow::Borrowed("Desktop/Server"),
+        vendor: Cow::Borrowed("Linux"),
     });
 
     // Chrome OS
     db.insert("1,3,6,12,15,28,51,58,59,119", OsInfo {
-        os_name: "Chrome OS",
-        device_class: "Chromebook",
-        vendor: "Google",
+        os_name: Cow::Borrowed("Chrome OS"),
+        device_class: Cow::Borrowed("Chromebook"),
+        vendor: Cow::Borrowed("Google"),
     });
 
     // PlayStation (PS4/PS5)
     db.insert("1,3,6,15,12,28", OsInfo {
-        os_name: "PlayStation",
-        device_class: "Gaming Console",
-        vendor: "Sony",
+        os_name: Cow::Borrowed("PlayStation"),
+        device_class: Cow::Borrowed("Gaming Console"),
+        vendor: Cow::Borrowed("Sony"),
     });
 
     // Xbox
     db.insert("1,3,6,15,44,46,47,12", OsInfo {
-        os_name: "Xbox",
-        device_class: "Gaming Console",
-        vendor: "Microsoft",
+        os_name: Cow::Borrowed("Xbox"),
+        device_class: Cow::Borrowed("Gaming Console"),
+        vendor: Cow::Borrowed("Microsoft"),
     });
 
     // Nintendo Switch
     db.insert("1,3,6,15,28,51,58,59", OsInfo {
-        os_name: "Nintendo Switch",
-        device_class: "Gaming Console",
-        vendor: "Nintendo",
+        os_name: Cow::Borrowed("Nintendo Switch"),
+        device_class: Cow::Borrowed("Gaming Console"),
+        vendor: Cow::Borrowed("Nintendo"),
     });
 
     // Roku
     db.insert("1,3,6,12,15,28,42", OsInfo {
-        os_name: "Roku",
-        device_class: "Streaming Device",
-        vendor: "Roku",
+        os_name: Cow::Borrowed("Roku"),
+        device_class: Cow::Borrowed("Streaming Device"),
+        vendor: Cow::Borrowed("Roku"),
     });
 
     // Amazon Fire TV
     db.insert("1,3,6,15,26,28,51,58,59,43,12", OsInfo {
-        os_name: "Fire TV",
-        device_class: "Streaming Device",
-        vendor: "Amazon",
+        os_name: Cow::Borrowed("Fire TV"),
+        device_class: Cow::Borrowed("Streaming Device"),
+        vendor: Cow::Borrowed("Amazon"),
     });
 
     db
 });
 
+/// OS classification; built-in entries borrow static names, the rest own theirs
 #[derive(Debug, Clone)]
 pub struct OsInfo {
-    pub os_name: &'static str,
-    pub device_class: &'static str,
-    pub vendor: &'static str,
+    pub os_name: Cow<'static, str>,
+    pub device_class: Cow<'static, str>,
+    pub vendor: Cow<'static, str>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MacOsInfo {
     pub os_name: String,
     pub device_class: String,
     pub vendor: String,
 }
 
+impl From<MacOsInfo> for OsInfo {
+    fn from(info: MacOsInfo) -> Self {
+        OsInfo {
+            os_name: Cow::Owned(info.os_name),
+            device_class: Cow::Owned(info.device_class),
+            vendor: Cow::Owned(info.vendor),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct MacMapping {
     mappings: HashMap<String, MacOsInfo>,
 }
 
+/// Explicit MAC address to OS mappings, re-read on reload
+pub const MAC_MAPPING_PATH: &str = "mac_os_mapping.toml";
+
+/// Read MAC address to OS mappings from TOML file; a missing file means no mappings
+fn read_mac_mappings(path: &str) -> anyhow::Result<HashMap<String, MacOsInfo>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(toml::from_str::<MacMapping>(&content)?
+            .mappings
+            .into_iter()
+            .map(|(mac, info)| (crate::mac::canonical(&mac), info))
+            .collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
 /// Load MAC address to OS mappings from TOML file
 fn load_mac_mappings() -> HashMap<String, MacOsInfo> {
-    match fs::read_to_string("mac_os_mapping.toml") {
-        Ok(content) => {
-            match toml::from_str::<MacMapping>(&content) {
-                Ok(mapping) => {
-                    tracing::info!("Loaded {} MAC address mappings", mapping.mappings.len());
-                    mapping.mappings
-                }
-                Err(e) => {
-                    tracing::warn!("Failed to parse mac_os_mapping.toml: {}", e);
-                    HashMap::new()
-                }
-            }
+    match read_mac_mappings(MAC_MAPPING_PATH) {
+        Ok(mappings) if mappings.is_empty() => {
+            tracing::debug!("No {} file found, MAC mapping disabled", MAC_MAPPING_PATH);
+            mappings
+        }
+        Ok(mappings) => {
+            tracing::info!("Loaded {} MAC address mappings", mappings.len());
+            mappings
         }
-        Err(_) => {
-            tracing::debug!("No mac_os_mapping.toml file found, MAC mapping disabled");
+        Err(e) => {
+            tracing::warn!("Failed to parse {}: {}", MAC_MAPPING_PATH, e);
             HashMap::new()
         }
     }
 }
 
-static MAC_MAPPINGS: Lazy<HashMap<String, MacOsInfo>> = Lazy::new(load_mac_mappings);
+static MAC_MAPPINGS: Lazy<RwLock<HashMap<String, MacOsInfo>>> = Lazy::new(|| RwLock::new(load_mac_mappings()));
+
+/// Overrides managed through the API and stored in the database; these take
+/// precedence over mac_os_mapping.toml
+static MAC_OVERRIDES: Lazy<RwLock<HashMap<String, MacOsInfo>>> = Lazy::new(Default::default);
+
+/// Mappings from mac_os_mapping.toml
+pub fn file_mac_mappings() -> HashMap<String, MacOsInfo> {
+    MAC_MAPPINGS.read().unwrap().clone()
+}
+
+/// Replace all API overrides (on startup, from the database)
+pub fn install_mac_overrides(overrides: HashMap<String, MacOsInfo>) {
+    *MAC_OVERRIDES.write().unwrap() = overrides
+        .into_iter()
+        .map(|(mac, info)| (crate::mac::canonical(&mac), info))
+        .collect();
+}
+
+pub fn set_mac_override(mac_address: &str, info: MacOsInfo) {
+    MAC_OVERRIDES.write().unwrap().insert(crate::mac::canonical(mac_address), info);
+}
+
+pub fn remove_mac_override(mac_address: &str) {
+    MAC_OVERRIDES.write().unwrap().remove(&crate::mac::canonical(mac_address));
+}
+
+/// Explicit MAC to OS mapping from an API override or mac_os_mapping.toml, if any
+pub fn lookup_mac_mapping(mac_address: &str) -> Option<MacOsInfo> {
+    let mac_address = crate::mac::canonical(mac_address);
+    if let Some(info) = MAC_OVERRIDES.read().unwrap().get(&mac_address) {
+        return Some(info.clone());
+    }
+    MAC_MAPPINGS.read().unwrap().get(&mac_address).cloned()
+}
 
 /// Lookup OS information based on MAC address and DHCP fingerprint
-/// Checks MAC mapping first, then falls back to fingerprint-based detection
+/// Checks MAC mapping first, then classification rules on the vendor class,
+/// hostname and OUI, then falls back to fingerprint-based detection
 /// Also performs explicit Option 12 check for Windows 10 vs 11 differentiation
-pub fn lookup_os(mac_address: &str, fingerprint: &str) -> Option<OsInfo> {
+pub fn lookup_os(
+    mac_address: &str,
+    fingerprint: &str,
+    vendor_class: Option<&str>,
+    hostname: Option<&str>,
+) -> Option<OsInfo> {
     // First, check if there's an explicit MAC mapping
-    if let Some(mac_info) = MAC_MAPPINGS.get(mac_address) {
+    if let Some(mac_info) = lookup_mac_mapping(mac_address) {
         tracing::debug!("Using MAC mapping for {}: {}", mac_address, mac_info.os_name);
-        return Some(OsInfo {
-            os_name: Box::leak(mac_info.os_name.clone().into_boxed_str()),
-            device_class: Box::leak(mac_info.device_class.clone().into_boxed_str()),
-            vendor: Box::leak(mac_info.vendor.clone().into_boxed_str()),
-        });
+        return Some(mac_info.into());
+    }
+
+    if let Some(rule_info) = crate::rules::classify(mac_address, vendor_class, hostname) {
+        tracing::debug!("Using classification rule for {}: {}", mac_address, rule_info.os_name);
+        return Some(rule_info.into());
     }
 
     // Fall back to fingerprint-based detection
     lookup_fingerprint(fingerprint)
 }
 
-/// Detect Windows version with confidence level
-/// Returns confidence level: High (exact match), Medium (fuzzy match)
-pub fn detect_windows_with_confidence(fingerprint: &str) -> Option<(OsInfo, &'static str)> {
-    // Check for explicit Windows fingerprints first
-    if let Some(info) = lookup_fingerprint(fingerprint) {
-        if info.vendor == "Microsoft" && info.os_name.contains("Windows") {
-            return Some((info, "High"));
-        }
-    }
+/// Versioned set of fingerprint signatures distributed from upstream
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FingerprintBundle {
+    pub version: String,
+    pub fingerprints: BTreeMap<String, MacOsInfo>,
+}
 
-    // Check if this looks like a Windows fingerprint with fuzzy matching
-    let fp_parts: Vec<&str> = fingerprint.split(',').collect();
-
-    // Windows fingerprints typically have these key options
-    let has_windows_signature = fp_parts.contains(&"1")   // Subnet mask
-        && fp_parts.contains(&"3")   // Router
-        && fp_parts.contains(&"15")  // Domain name
-        && fp_parts.contains(&"6");  // DNS
-
-    if has_windows_signature {
-        // Generic Windows detection - SMB scanning will provide specific version
-        tracing::debug!("Windows signature detected in fingerprint");
-        return Some((OsInfo {
-            os_name: "Windows",
-            device_class: "Desktop/Laptop",
-            vendor: "Microsoft",
-        }, "Medium"));
+/// Read an installed fingerprint bundle from disk
+pub fn load_bundle(path: &str) -> anyhow::Result<Option<FingerprintBundle>> {
+    match fs::read(path) {
+        Ok(content) => Ok(Some(serde_json::from_slice(&content)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
     }
+}
+
+fn bundle_to_db(bundle: &FingerprintBundle) -> HashMap<String, OsInfo> {
+    bundle
+        .fingerprints
+        .iter()
+        .map(|(fingerprint, info)| (fingerprint.clone(), info.clone().into()))
+        .collect()
+}
+
+/// Fingerprints from the installed upstream bundle, checked before the built-in table
+static BUNDLE_FINGERPRINTS: Lazy<RwLock<HashMap<String, OsInfo>>> = Lazy::new(|| {
+    let path = BUNDLE_PATH.read().unwrap().clone();
+    let db = match load_bundle(&path) {
+        Ok(Some(bundle)) => {
+            tracing::info!(
+                "Loaded fingerprint bundle {} ({} signatures)",
+                bundle.version,
+                bundle.fingerprints.len()
+            );
+            bundle_to_db(&bundle)
+        }
+        Ok(None) => HashMap::new(),
+        Err(e) => {
+            tracing::warn!("Failed to load {}: {}", path, e);
+            HashMap::new()
+        }
+    };
+    RwLock::new(db)
+});
+
+/// Replace the active upstream fingerprints with the given bundle
+pub fn install_bundle(bundle: Option<&FingerprintBundle>) {
+    let db = bundle.map(bundle_to_db).unwrap_or_default();
+    *BUNDLE_FINGERPRINTS.write().unwrap() = db;
+}
 
-    None
+/// What a reload picked up
+#[derive(Debug, Clone, Serialize)]
+pub struct ReloadSummary {
+    pub mac_mappings: usize,
+    pub classification_rules: usize,
+    pub bundle_version: Option<String>,
+    pub bundle_signatures: usize,
+}
+
+/// Re-read mac_os_mapping.toml, classification_rules.toml and the installed
+/// fingerprint bundle. All files are parsed before any is swapped in, so a broken
+/// file leaves the running data untouched.
+pub fn reload() -> anyhow::Result<ReloadSummary> {
+    let bundle_path = BUNDLE_PATH.read().unwrap().clone();
+    reload_from(MAC_MAPPING_PATH, crate::rules::RULES_PATH, &bundle_path)
+}
+
+fn reload_from(mapping_path: &str, rules_path: &str, bundle_path: &str) -> anyhow::Result<ReloadSummary> {
+    let mappings = read_mac_mappings(mapping_path)
+        .map_err(|e| anyhow::anyhow!("failed to load {}: {}", mapping_path, e))?;
+    let rules = crate::rules::read_rules(rules_path)
+        .map_err(|e| anyhow::anyhow!("failed to load {}: {}", rules_path, e))?;
+    let bundle = load_bundle(bundle_path)
+        .map_err(|e| anyhow::anyhow!("failed to load {}: {}", bundle_path, e))?;
+
+    let summary = ReloadSummary {
+        mac_mappings: mappings.len(),
+        classification_rules: rules.len(),
+        bundle_version: bundle.as_ref().map(|b| b.version.clone()),
+        bundle_signatures: bundle.as_ref().map_or(0, |b| b.fingerprints.len()),
+    };
+    *MAC_MAPPINGS.write().unwrap() = mappings;
+    crate::rules::install(rules);
+    install_bundle(bundle.as_ref());
+    Ok(summary)
 }
 
 /// Lookup OS information based on DHCP fingerprint only
 /// Simple exact match lookup - no fuzzy matching
 pub fn lookup_fingerprint(fingerprint: &str) -> Option<OsInfo> {
+    // Upstream bundle signatures take precedence over the built-in table
+    if let Some(info) = BUNDLE_FINGERPRINTS.read().unwrap().get(fingerprint) {
+        return Some(info.clone());
+    }
+
     // Direct lookup (exact match only)
     FINGERPRINT_DB.get(fingerprint).cloned()
 }
 
-/// Format OS info as a string for storage/display
-pub fn format_os_info(info: &OsInfo) -> String {
-    format!("{} ({})", info.os_name, info.device_class)
+/// Every fingerprint lookup_fingerprint knows, bundle entries first, in a stable order
+pub fn known_fingerprints() -> Vec<(String, OsInfo)> {
+    let mut bundle: Vec<_> = BUNDLE_FINGERPRINTS
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(fingerprint, info)| (fingerprint.clone(), info.clone()))
+        .collect();
+    bundle.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut builtin: Vec<_> = FINGERPRINT_DB
+        .iter()
+        .filter(|(fingerprint, _)| !bundle.iter().any(|(known, _)| known == *fingerprint))
+        .map(|(fingerprint, info)| (fingerprint.to_string(), info.clone()))
+        .collect();
+    builtin.sort_by(|a, b| a.0.cmp(&b.0));
+
+    bundle.extend(builtin);
+    bundle
 }
 
 #[cfg(test)]
@@ -268,6 +423,55 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[test]
+    fn test_mac_mapping_lookup_owns_strings() {
+        MAC_OVERRIDES.write().unwrap().insert(
+            "02:00:00:00:00:02".to_string(),
+            MacOsInfo {
+                os_name: "Mapped OS".to_string(),
+                device_class: "Server".to_string(),
+                vendor: "Example".to_string(),
+            },
+        );
+        let info = lookup_os("02:00:00:00:00:02", "99,98,97", None, None).unwrap();
+        assert!(matches!(info.os_name, Cow::Owned(_)));
+        assert_eq!(info.os_name, "Mapped OS");
+        MAC_OVERRIDES.write().unwrap().remove("02:00:00:00:00:02");
+
+        // Built-in signatures are handed out without allocating
+        let builtin = lookup_os("02:00:00:00:00:02", "1,3,6,15,31,33,43,44,46,47,121,249,252,12", None, None).unwrap();
+        assert!(matches!(builtin.os_name, Cow::Borrowed("Windows 11")));
+    }
+
+    #[test]
+    fn test_reload_keeps_data_on_parse_error() {
+        let dir = std::env::temp_dir().join(format!("ks-dhcpmon-reload-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mapping_path = dir.join("mac_os_mapping.toml");
+        let mapping_path = mapping_path.to_str().unwrap();
+        let bundle_path = dir.join("missing.json");
+        let bundle_path = bundle_path.to_str().unwrap();
+        let rules_path = dir.join("missing.toml");
+        let rules_path = rules_path.to_str().unwrap();
+
+        fs::write(
+            mapping_path,
+            "[mappings.\"02:00:00:00:00:01\"]\nos_name = \"Reloaded OS\"\ndevice_class = \"Server\"\nvendor = \"Example\"\n",
+        )
+        .unwrap();
+        let summary = reload_from(mapping_path, rules_path, bundle_path).unwrap();
+        assert_eq!(summary.mac_mappings, 1);
+        assert_eq!(summary.bundle_version, None);
+        assert_eq!(lookup_mac_mapping("02:00:00:00:00:01").unwrap().os_name, "Reloaded OS");
+
+        fs::write(mapping_path, "[mappings").unwrap();
+        assert!(reload_from(mapping_path, rules_path, bundle_path).is_err());
+        assert!(lookup_mac_mapping("02:00:00:00:00:01").is_some());
+
+        *MAC_MAPPINGS.write().unwrap() = HashMap::new();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_partial_no_match() {
         // Partial fingerprint should NOT match (exact only)
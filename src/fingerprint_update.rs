@@ -0,0 +1,291 @@
+use crate::fingerprint::{self, FingerprintBundle, MacOsInfo};
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::Serialize;
+use std::fs;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// Configuration for pulling fingerprint bundles from upstream
+#[derive(Debug, Clone)]
+pub struct UpdaterConfig {
+    /// URL of the JSON fingerprint bundle
+    pub url: String,
+    /// URL of the detached base64 Ed25519 signature (defaults to `<url>.sig`)
+    pub signature_url: Option<String>,
+    /// Base64-encoded Ed25519 public key the bundle must be signed with
+    pub public_key: String,
+    /// Where the installed bundle is kept; the previous one is kept as `<path>.bak`
+    pub bundle_path: String,
+}
+
+/// A signature whose classification differs between two bundles
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangedSignature {
+    pub fingerprint: String,
+    pub old: MacOsInfo,
+    pub new: MacOsInfo,
+}
+
+/// Differences between the installed bundle and a candidate bundle
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BundleDiff {
+    pub from_version: Option<String>,
+    pub to_version: Option<String>,
+    pub added: Vec<String>,
+    pub changed: Vec<ChangedSignature>,
+    pub removed: Vec<String>,
+}
+
+/// Compute added/changed/removed signatures between two bundles
+pub fn diff_bundles(old: Option<&FingerprintBundle>, new: Option<&FingerprintBundle>) -> BundleDiff {
+    let empty = Default::default();
+    let old_fps = old.map(|b| &b.fingerprints).unwrap_or(&empty);
+    let new_fps = new.map(|b| &b.fingerprints).unwrap_or(&empty);
+
+    let mut diff = BundleDiff {
+        from_version: old.map(|b| b.version.clone()),
+        to_version: new.map(|b| b.version.clone()),
+        ..Default::default()
+    };
+
+    for (fingerprint, info) in new_fps {
+        match old_fps.get(fingerprint) {
+            None => diff.added.push(fingerprint.clone()),
+            Some(old_info) if old_info != info => diff.changed.push(ChangedSignature {
+                fingerprint: fingerprint.clone(),
+                old: old_info.clone(),
+                new: info.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+
+    diff.removed = old_fps
+        .keys()
+        .filter(|fingerprint| !new_fps.contains_key(*fingerprint))
+        .cloned()
+        .collect();
+
+    diff
+}
+
+/// Parse a base64-encoded Ed25519 public key
+pub fn parse_public_key(encoded: &str) -> Result<VerifyingKey> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .context("public key is not valid base64")?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| anyhow!("public key must be 32 bytes"))?;
+    Ok(VerifyingKey::from_bytes(&bytes)?)
+}
+
+/// Verify a detached base64 Ed25519 signature over the bundle bytes
+pub fn verify_signature(key: &VerifyingKey, data: &[u8], signature_b64: &str) -> Result<()> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(signature_b64.trim())
+        .context("signature is not valid base64")?;
+    let signature = Signature::from_slice(&bytes)?;
+    key.verify(data, &signature)
+        .map_err(|_| anyhow!("fingerprint bundle signature verification failed"))
+}
+
+/// Fetches, verifies and installs upstream fingerprint bundles
+pub struct FingerprintUpdater {
+    config: UpdaterConfig,
+    public_key: VerifyingKey,
+    client: reqwest::Client,
+    // Serializes apply/rollback so the bundle file and backup stay consistent
+    lock: Mutex<()>,
+}
+
+impl FingerprintUpdater {
+    pub fn new(config: UpdaterConfig) -> Result<Self> {
+        for url in std::iter::once(&config.url).chain(&config.signature_url) {
+            let parsed = reqwest::Url::parse(url).map_err(|e| anyhow!("invalid URL '{}': {}", url, e))?;
+            if !matches!(parsed.scheme(), "https" | "http") {
+                return Err(anyhow!("URL '{}' must be http(s)", url));
+            }
+        }
+        let public_key = parse_public_key(&config.public_key)?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?;
+
+        Ok(Self {
+            config,
+            public_key,
+            client,
+            lock: Mutex::new(()),
+        })
+    }
+
+    fn backup_path(&self) -> String {
+        format!("{}.bak", self.config.bundle_path)
+    }
+
+    async fn fetch_verified(&self) -> Result<(Vec<u8>, FingerprintBundle)> {
+        let signature_url = self
+            .config
+            .signature_url
+            .clone()
+            .unwrap_or_else(|| format!("{}.sig", self.config.url));
+
+        let data = self.client.get(&self.config.url).send().await?
+            .error_for_status()?
+            .bytes().await?
+            .to_vec();
+        let signature = self.client.get(&signature_url).send().await?
+            .error_for_status()?
+            .text().await?;
+
+        verify_signature(&self.public_key, &data, &signature)?;
+        let bundle: FingerprintBundle = serde_json::from_slice(&data)
+            .context("fingerprint bundle is not valid JSON")?;
+
+        Ok((data, bundle))
+    }
+
+    /// Fetch and verify the upstream bundle, returning what applying it would change
+    pub async fn check(&self) -> Result<BundleDiff> {
+        let (_, bundle) = self.fetch_verified().await?;
+        let installed = fingerprint::load_bundle(&self.config.bundle_path)?;
+        Ok(diff_bundles(installed.as_ref(), Some(&bundle)))
+    }
+
+    /// Fetch, verify and install the upstream bundle, keeping the previous one for rollback
+    pub async fn apply(&self) -> Result<BundleDiff> {
+        let (data, bundle) = self.fetch_verified().await?;
+        let _guard = self.lock.lock().await;
+
+        let installed = fingerprint::load_bundle(&self.config.bundle_path)?;
+        let diff = diff_bundles(installed.as_ref(), Some(&bundle));
+
+        if installed.is_some() {
+            fs::copy(&self.config.bundle_path, self.backup_path())
+                .context("failed to back up installed fingerprint bundle")?;
+        }
+
+        // Write to a temporary file and rename so a crash never leaves a partial bundle
+        let tmp_path = format!("{}.tmp", self.config.bundle_path);
+        if let Err(e) = fs::write(&tmp_path, &data).and_then(|_| fs::rename(&tmp_path, &self.config.bundle_path)) {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(anyhow!("failed to install fingerprint bundle: {}", e));
+        }
+
+        fingerprint::install_bundle(Some(&bundle));
+        tracing::info!(
+            "Installed fingerprint bundle {} (+{} ~{} -{})",
+            bundle.version,
+            diff.added.len(),
+            diff.changed.len(),
+            diff.removed.len()
+        );
+
+        Ok(diff)
+    }
+
+    /// Restore the bundle that was installed before the last apply
+    pub async fn rollback(&self) -> Result<BundleDiff> {
+        let _guard = self.lock.lock().await;
+
+        let previous = fingerprint::load_bundle(&self.backup_path())?
+            .ok_or_else(|| anyhow!("no previous fingerprint bundle to roll back to"))?;
+        let installed = fingerprint::load_bundle(&self.config.bundle_path)?;
+
+        fs::rename(self.backup_path(), &self.config.bundle_path)
+            .context("failed to restore previous fingerprint bundle")?;
+        fingerprint::install_bundle(Some(&previous));
+        tracing::info!("Rolled back fingerprint bundle to {}", previous.version);
+
+        Ok(diff_bundles(installed.as_ref(), Some(&previous)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn info(os_name: &str) -> MacOsInfo {
+        MacOsInfo {
+            os_name: os_name.to_string(),
+            device_class: "Desktop/Laptop".to_string(),
+            vendor: "Example".to_string(),
+        }
+    }
+
+    fn bundle(version: &str, entries: &[(&str, &str)]) -> FingerprintBundle {
+        FingerprintBundle {
+            version: version.to_string(),
+            fingerprints: entries
+                .iter()
+                .map(|(fp, os)| (fp.to_string(), info(os)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_diff_bundles() {
+        let old = bundle("1", &[("1,3,6", "A"), ("1,3,15", "B"), ("1,2", "C")]);
+        let new = bundle("2", &[("1,3,6", "A"), ("1,3,15", "B2"), ("9,9", "D")]);
+
+        let diff = diff_bundles(Some(&old), Some(&new));
+        assert_eq!(diff.from_version.as_deref(), Some("1"));
+        assert_eq!(diff.to_version.as_deref(), Some("2"));
+        assert_eq!(diff.added, vec!["9,9"]);
+        assert_eq!(diff.removed, vec!["1,2"]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].fingerprint, "1,3,15");
+        assert_eq!(diff.changed[0].new.os_name, "B2");
+    }
+
+    #[test]
+    fn test_diff_against_nothing_installed() {
+        let new = bundle("1", &[("1,3,6", "A")]);
+        let diff = diff_bundles(None, Some(&new));
+        assert_eq!(diff.from_version, None);
+        assert_eq!(diff.added, vec!["1,3,6"]);
+    }
+
+    #[test]
+    fn test_signature_verification() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let engine = base64::engine::general_purpose::STANDARD;
+        let public_key = parse_public_key(&engine.encode(signing_key.verifying_key().to_bytes())).unwrap();
+
+        let data = br#"{"version":"1","fingerprints":{}}"#;
+        let signature = engine.encode(signing_key.sign(data).to_bytes());
+
+        assert!(verify_signature(&public_key, data, &signature).is_ok());
+        assert!(verify_signature(&public_key, b"tampered", &signature).is_err());
+        assert!(verify_signature(&public_key, data, "not base64!").is_err());
+    }
+
+    #[test]
+    fn test_parse_public_key_rejects_wrong_length() {
+        let engine = base64::engine::general_purpose::STANDARD;
+        assert!(parse_public_key(&engine.encode([1u8; 16])).is_err());
+    }
+
+    #[test]
+    fn test_new_rejects_bad_urls() {
+        let engine = base64::engine::general_purpose::STANDARD;
+        let config = |url: &str| UpdaterConfig {
+            url: url.to_string(),
+            signature_url: None,
+            public_key: engine.encode(SigningKey::from_bytes(&[7u8; 32]).verifying_key().to_bytes()),
+            bundle_path: "fingerprints.json".to_string(),
+        };
+        assert!(FingerprintUpdater::new(config("https://example.com/fingerprints.json")).is_ok());
+        assert!(FingerprintUpdater::new(config("not a url")).is_err());
+        assert!(FingerprintUpdater::new(config("file:///etc/fingerprints.json")).is_err());
+        let bad_signature = UpdaterConfig {
+            signature_url: Some("ftp://example.com/sig".to_string()),
+            ..config("https://example.com/f.json")
+        };
+        assert!(FingerprintUpdater::new(bad_signature).is_err());
+    }
+}
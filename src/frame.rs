@@ -0,0 +1,136 @@
+//! Link-layer frame decoding for the interface capture (`[receive] interface`),
+//! which sees whole Ethernet frames (e.g. a trunk-port mirror). The UDP socket
+//! listener never sees these headers - the kernel strips them, including any
+//! 802.1Q tags.
+
+use std::net::Ipv4Addr;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+const IPPROTO_UDP: u8 = 17;
+
+/// A DHCP datagram extracted from an Ethernet frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DhcpFrame<'a> {
+    /// Innermost 802.1Q VLAN ID, if the frame was tagged
+    pub vlan_id: Option<u16>,
+    pub source_ip: Ipv4Addr,
+    pub source_port: u16,
+    pub dest_port: u16,
+    /// UDP payload (the BOOTP/DHCP message)
+    pub payload: &'a [u8],
+}
+
+/// Decode Ethernet (optionally 802.1Q / QinQ tagged) -> IPv4 -> UDP and return
+/// the payload if the datagram is addressed to a DHCP port (67 or 68)
+pub fn parse_dhcp_frame(frame: &[u8]) -> Option<DhcpFrame<'_>> {
+    // Destination MAC (6) + source MAC (6) + EtherType (2)
+    let mut offset = 12;
+    let mut vlan_id = None;
+
+    let mut ethertype = u16::from_be_bytes([*frame.get(offset)?, *frame.get(offset + 1)?]);
+    offset += 2;
+
+    // Walk stacked VLAN tags; the last one seen is the customer (inner) VLAN
+    while ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ {
+        let tci = u16::from_be_bytes([*frame.get(offset)?, *frame.get(offset + 1)?]);
+        vlan_id = Some(tci & 0x0fff);
+        ethertype = u16::from_be_bytes([*frame.get(offset + 2)?, *frame.get(offset + 3)?]);
+        offset += 4;
+    }
+
+    if ethertype != ETHERTYPE_IPV4 {
+        return None;
+    }
+
+    let ip = frame.get(offset..)?;
+    if ip.len() < 20 || ip[0] >> 4 != 4 || ip[9] != IPPROTO_UDP {
+        return None;
+    }
+    let header_len = ((ip[0] & 0x0f) as usize) * 4;
+    let source_ip = Ipv4Addr::new(ip[12], ip[13], ip[14], ip[15]);
+
+    let udp = ip.get(header_len..)?;
+    if udp.len() < 8 {
+        return None;
+    }
+    let source_port = u16::from_be_bytes([udp[0], udp[1]]);
+    let dest_port = u16::from_be_bytes([udp[2], udp[3]]);
+    if dest_port != 67 && dest_port != 68 {
+        return None;
+    }
+    let udp_len = (u16::from_be_bytes([udp[4], udp[5]]) as usize).clamp(8, udp.len());
+
+    Some(DhcpFrame {
+        vlan_id,
+        source_ip,
+        source_port,
+        dest_port,
+        payload: &udp[8..udp_len],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_frame(tags: &[(u16, u16)], dest_port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0xff; 6];
+        frame.extend_from_slice(&[0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]);
+        for (tpid, vid) in tags {
+            frame.extend_from_slice(&tpid.to_be_bytes());
+            frame.extend_from_slice(&vid.to_be_bytes());
+        }
+        frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+
+        let udp_len = (8 + payload.len()) as u16;
+        let mut ip = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, IPPROTO_UDP, 0, 0];
+        ip.extend_from_slice(&[10, 1, 2, 3]);
+        ip.extend_from_slice(&[255, 255, 255, 255]);
+        frame.extend_from_slice(&ip);
+        frame.extend_from_slice(&68u16.to_be_bytes());
+        frame.extend_from_slice(&dest_port.to_be_bytes());
+        frame.extend_from_slice(&udp_len.to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn test_untagged_frame() {
+        let frame = build_frame(&[], 67, b"dhcp");
+        let parsed = parse_dhcp_frame(&frame).unwrap();
+        assert_eq!(parsed.vlan_id, None);
+        assert_eq!(parsed.source_ip, Ipv4Addr::new(10, 1, 2, 3));
+        assert_eq!(parsed.source_port, 68);
+        assert_eq!(parsed.dest_port, 67);
+        assert_eq!(parsed.payload, b"dhcp");
+    }
+
+    #[test]
+    fn test_single_tag() {
+        // PCP 5 set in the TCI must not leak into the VLAN ID
+        let frame = build_frame(&[(ETHERTYPE_VLAN, 0xa000 | 42)], 67, b"dhcp");
+        assert_eq!(parse_dhcp_frame(&frame).unwrap().vlan_id, Some(42));
+    }
+
+    #[test]
+    fn test_qinq_records_inner_tag() {
+        let frame = build_frame(&[(ETHERTYPE_QINQ, 100), (ETHERTYPE_VLAN, 20)], 67, b"dhcp");
+        assert_eq!(parse_dhcp_frame(&frame).unwrap().vlan_id, Some(20));
+    }
+
+    #[test]
+    fn test_non_dhcp_port_ignored() {
+        let frame = build_frame(&[(ETHERTYPE_VLAN, 5)], 53, b"dns");
+        assert!(parse_dhcp_frame(&frame).is_none());
+    }
+
+    #[test]
+    fn test_truncated_frame() {
+        let frame = build_frame(&[(ETHERTYPE_VLAN, 5)], 67, b"dhcp");
+        assert!(parse_dhcp_frame(&frame[..20]).is_none());
+        assert!(parse_dhcp_frame(&[]).is_none());
+    }
+}
@@ -0,0 +1,336 @@
+//! Byte-level view of a stored packet.
+//!
+//! Lists every field a raw DHCP packet is made of, in wire order: the fixed
+//! BOOTP header, the magic cookie and each option as it appears on the wire
+//! (so a long option split in pieces shows every piece), each with its offset,
+//! length and decoded value. Damaged packets are annotated as far as they go.
+
+use crate::dhcp::{hardware_type_label, hex_colon, DhcpOption};
+use crate::options_catalog;
+use serde::Serialize;
+use std::net::Ipv4Addr;
+
+/// Bytes per line of the dump
+const LINE_WIDTH: usize = 16;
+const HEADER_LEN: usize = 236;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+/// Where the server name and boot file fields sit, for option overload (option 52)
+const SNAME: (usize, usize) = (44, 64);
+const FILE: (usize, usize) = (108, 128);
+
+/// One field of the packet and the bytes it occupies
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PacketField {
+    pub offset: usize,
+    pub length: usize,
+    pub name: String,
+    /// Option code, for option fields
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<u8>,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PacketDump {
+    pub length: usize,
+    /// The whole packet as lowercase hex, two digits per byte
+    pub hex: String,
+    /// Classic dump: offset, 16 bytes in hex and their printable characters
+    pub lines: Vec<String>,
+    pub fields: Vec<PacketField>,
+}
+
+pub fn dump(packet: &[u8]) -> PacketDump {
+    PacketDump {
+        length: packet.len(),
+        hex: packet.iter().map(|b| format!("{:02x}", b)).collect(),
+        lines: packet
+            .chunks(LINE_WIDTH)
+            .enumerate()
+            .map(|(i, chunk)| dump_line(i * LINE_WIDTH, chunk))
+            .collect(),
+        fields: annotate(packet),
+    }
+}
+
+fn dump_line(offset: usize, chunk: &[u8]) -> String {
+    let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+    let text: String = chunk
+        .iter()
+        .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+        .collect();
+    format!("{:04x}  {:<47}  |{}|", offset, hex.join(" "), text)
+}
+
+fn field(offset: usize, length: usize, name: &str, value: String) -> PacketField {
+    PacketField {
+        offset,
+        length,
+        name: name.to_string(),
+        code: None,
+        value,
+    }
+}
+
+/// The packet's fields in wire order
+pub fn annotate(packet: &[u8]) -> Vec<PacketField> {
+    let mut fields = Vec::new();
+    if !annotate_header(packet, &mut fields) {
+        return fields;
+    }
+
+    let cookie = &packet[HEADER_LEN..(HEADER_LEN + 4).min(packet.len())];
+    if cookie != MAGIC_COOKIE {
+        let value = match cookie.len() {
+            0 => return fields,
+            4 => format!("{} (not DHCP)", hex_colon(cookie)),
+            _ => format!("{} (truncated)", hex_colon(cookie)),
+        };
+        fields.push(field(HEADER_LEN, cookie.len(), "magic cookie", value));
+        return fields;
+    }
+    fields.push(field(HEADER_LEN, 4, "magic cookie", format!("{} (DHCP)", hex_colon(cookie))));
+
+    let mut seen = Vec::new();
+    let overload = annotate_options(packet, HEADER_LEN + 4, packet.len(), &mut seen, &mut fields);
+    // Overloaded areas are read file first, as the parser does
+    if matches!(overload, Some(1 | 3)) {
+        annotate_options(packet, FILE.0, FILE.0 + FILE.1, &mut seen, &mut fields);
+    }
+    if matches!(overload, Some(2 | 3)) {
+        annotate_options(packet, SNAME.0, SNAME.0 + SNAME.1, &mut seen, &mut fields);
+    }
+    fields
+}
+
+/// Header fields that fit in the packet; false when it is cut short
+fn annotate_header(packet: &[u8], fields: &mut Vec<PacketField>) -> bool {
+    let overload = overload_value(packet);
+    let header: [(&str, usize); 14] = [
+        ("op", 1),
+        ("htype", 1),
+        ("hlen", 1),
+        ("hops", 1),
+        ("xid", 4),
+        ("secs", 2),
+        ("flags", 2),
+        ("ciaddr", 4),
+        ("yiaddr", 4),
+        ("siaddr", 4),
+        ("giaddr", 4),
+        ("chaddr", 16),
+        ("sname", SNAME.1),
+        ("file", FILE.1),
+    ];
+
+    let mut offset = 0;
+    for (name, length) in header {
+        let Some(bytes) = packet.get(offset..offset + length) else {
+            if offset < packet.len() {
+                fields.push(field(offset, packet.len() - offset, "truncated header", hex_colon(&packet[offset..])));
+            }
+            return false;
+        };
+        let value = match name {
+            "op" => match bytes[0] {
+                1 => "1 (BOOTREQUEST)".to_string(),
+                2 => "2 (BOOTREPLY)".to_string(),
+                other => other.to_string(),
+            },
+            "htype" => format!("{} ({})", bytes[0], hardware_type_label(bytes[0])),
+            "hlen" | "hops" => bytes[0].to_string(),
+            "xid" => format!("0x{:08x}", u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            "secs" => u16::from_be_bytes([bytes[0], bytes[1]]).to_string(),
+            "flags" => {
+                let flags = u16::from_be_bytes([bytes[0], bytes[1]]);
+                if flags & 0x8000 != 0 {
+                    format!("0x{:04x} (broadcast)", flags)
+                } else {
+                    format!("0x{:04x}", flags)
+                }
+            }
+            "ciaddr" | "yiaddr" | "siaddr" | "giaddr" => Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]).to_string(),
+            "chaddr" => hex_colon(&bytes[..(packet[2] as usize).clamp(1, 16)]),
+            "sname" if matches!(overload, Some(2 | 3)) => "options (overload)".to_string(),
+            "file" if matches!(overload, Some(1 | 3)) => "options (overload)".to_string(),
+            _ => {
+                let text = bytes.split(|&b| b == 0).next().unwrap_or_default();
+                if text.is_empty() {
+                    "(empty)".to_string()
+                } else {
+                    String::from_utf8_lossy(text).into_owned()
+                }
+            }
+        };
+        fields.push(field(offset, length, name, value));
+        offset += length;
+    }
+    true
+}
+
+/// The option 52 value in the main option area, if any
+fn overload_value(packet: &[u8]) -> Option<u8> {
+    if packet.get(HEADER_LEN..HEADER_LEN + 4)? != MAGIC_COOKIE {
+        return None;
+    }
+    let mut fields = Vec::new();
+    annotate_options(packet, HEADER_LEN + 4, packet.len(), &mut Vec::new(), &mut fields)
+}
+
+/// Options between `start` and `end`, up to the end option. Returns the option
+/// overload value when option 52 is among them.
+fn annotate_options(
+    packet: &[u8],
+    start: usize,
+    end: usize,
+    seen: &mut Vec<u8>,
+    fields: &mut Vec<PacketField>,
+) -> Option<u8> {
+    let mut overload = None;
+    let mut i = start;
+    while i < end {
+        let code = packet[i];
+        match code {
+            0 => {
+                let pads = packet[i..end].iter().take_while(|&&b| b == 0).count();
+                fields.push(field(i, pads, "pad", format!("{} bytes", pads)));
+                i += pads;
+            }
+            255 => {
+                fields.push(PacketField {
+                    code: Some(255),
+                    ..field(i, 1, "end", String::new())
+                });
+                if end > i + 1 {
+                    let rest = &packet[i + 1..end];
+                    let value = if rest.iter().all(|&b| b == 0) {
+                        format!("{} zero bytes", rest.len())
+                    } else {
+                        hex_colon(rest)
+                    };
+                    fields.push(field(i + 1, rest.len(), "after end", value));
+                }
+                break;
+            }
+            _ => {
+                let Some(&len) = packet.get(i + 1).filter(|_| i + 1 < end) else {
+                    fields.push(field(i, end - i, "truncated option", format!("code {} without a length", code)));
+                    break;
+                };
+                let len = len as usize;
+                if i + 2 + len > end {
+                    fields.push(field(
+                        i,
+                        end - i,
+                        "truncated option",
+                        format!("code {} claims {} bytes, {} left", code, len, end - i - 2),
+                    ));
+                    break;
+                }
+
+                let data = &packet[i + 2..i + 2 + len];
+                let decoded = options_catalog::decode(&DhcpOption {
+                    code,
+                    data: data.to_vec(),
+                });
+                let mut name = format!("option {}", code);
+                if let Some(known) = decoded.name {
+                    name = format!("{} {}", name, known);
+                }
+                if seen.contains(&code) {
+                    name.push_str(" (continued)");
+                } else {
+                    seen.push(code);
+                }
+                if code == 52 {
+                    overload = data.first().copied();
+                }
+                fields.push(PacketField {
+                    code: Some(code),
+                    ..field(i, 2 + len, &name, decoded.display)
+                });
+                i += 2 + len;
+            }
+        }
+    }
+    overload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dhcp::DhcpPacketBuilder;
+
+    fn find<'a>(fields: &'a [PacketField], name: &str) -> &'a PacketField {
+        fields.iter().find(|f| f.name == name).unwrap_or_else(|| panic!("no {} in {:?}", name, fields))
+    }
+
+    #[test]
+    fn test_annotates_header_and_options() {
+        let packet = DhcpPacketBuilder::discover()
+            .mac([0xaa, 0xbb, 0xcc, 0x00, 0x00, 0x01])
+            .xid(0x1234)
+            .broadcast()
+            .option(12, b"laptop")
+            .build();
+        let dump = dump(&packet);
+        assert_eq!(dump.length, packet.len());
+        assert_eq!(dump.hex.len(), packet.len() * 2);
+        assert!(dump.lines[0].starts_with("0000  01 01 06 00 00 00 12 34"));
+        assert_eq!(dump.lines.len(), packet.len().div_ceil(LINE_WIDTH));
+
+        let fields = &dump.fields;
+        assert_eq!(find(fields, "op").value, "1 (BOOTREQUEST)");
+        assert_eq!(find(fields, "xid").value, "0x00001234");
+        assert_eq!(find(fields, "flags").value, "0x8000 (broadcast)");
+        assert_eq!(find(fields, "chaddr").value, "aa:bb:cc:00:00:01");
+        assert_eq!(find(fields, "sname").value, "(empty)");
+        assert_eq!(find(fields, "magic cookie").offset, 236);
+
+        let message_type = find(fields, "option 53 DHCP Message Type");
+        assert_eq!((message_type.offset, message_type.length), (240, 3));
+        assert_eq!(message_type.value, "DHCPDISCOVER (1)");
+        let hostname = fields.iter().find(|f| f.code == Some(12)).unwrap();
+        assert_eq!((hostname.offset, hostname.length, hostname.value.as_str()), (243, 8, "laptop"));
+        assert_eq!(fields.last().unwrap().name, "end");
+
+        // Fields cover the packet without gaps
+        let mut next = 0;
+        for field in fields {
+            assert_eq!(field.offset, next, "{:?}", field);
+            next += field.length;
+        }
+        assert_eq!(next, packet.len());
+    }
+
+    #[test]
+    fn test_split_and_damaged_options() {
+        let packet = DhcpPacketBuilder::discover().option(43, vec![7u8; 300]).build();
+        let fields = annotate(&packet);
+        let pieces: Vec<_> = fields.iter().filter(|f| f.code == Some(43)).collect();
+        assert_eq!(pieces.len(), 2);
+        assert!(pieces[1].name.ends_with("(continued)"));
+
+        let mut truncated = DhcpPacketBuilder::discover().without_end().build();
+        truncated.extend_from_slice(&[12, 10, b'a']);
+        assert_eq!(annotate(&truncated).last().unwrap().value, "code 12 claims 10 bytes, 1 left");
+
+        let fields = annotate(&[1, 1, 6, 0, 0xde, 0xad]);
+        assert_eq!(fields.len(), 5);
+        assert_eq!(fields[4].name, "truncated header");
+
+        let mut bootp = DhcpPacketBuilder::new().build();
+        bootp.truncate(HEADER_LEN);
+        assert_eq!(annotate(&bootp).last().unwrap().name, "file");
+    }
+
+    #[test]
+    fn test_overloaded_file_field() {
+        let mut packet = DhcpPacketBuilder::discover().option(52, [1]).build();
+        packet[FILE.0..FILE.0 + 5].copy_from_slice(&[12, 2, b'p', b'c', 255]);
+        let fields = annotate(&packet);
+        assert_eq!(find(&fields, "file").value, "options (overload)");
+        let hostname = fields.iter().find(|f| f.code == Some(12)).unwrap();
+        assert_eq!((hostname.offset, hostname.value.as_str()), (FILE.0, "pc"));
+    }
+}
@@ -0,0 +1,309 @@
+//! HTTP/HTTPS banner probing for devices DHCP can't identify.
+//!
+//! Printers, cameras, NAS boxes and routers nearly all run an embedded web
+//! server; the Server header, page title and TLS certificate name usually give
+//! away what they are.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Most of a page we read looking for the title
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// What a device's web server said about itself
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HttpBanner {
+    pub port: u16,
+    pub server: Option<String>,
+    pub title: Option<String>,
+    /// Subject CN of the TLS certificate (HTTPS only)
+    pub cert_cn: Option<String>,
+}
+
+impl HttpBanner {
+    fn is_empty(&self) -> bool {
+        self.server.is_none() && self.title.is_none() && self.cert_cn.is_none()
+    }
+
+    // All identifying text, for signature matching
+    fn text(&self) -> String {
+        [&self.server, &self.title, &self.cert_cn]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+}
+
+/// Device kind inferred from banners
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpClassification {
+    pub os_name: String,
+    pub device_class: &'static str,
+    pub vendor: &'static str,
+    /// The port and banner text that matched
+    pub evidence: String,
+}
+
+/// (lowercase needle, device class, vendor)
+const SIGNATURES: &[(&str, &str, &str)] = &[
+    // Printers
+    ("laserjet", "Printer", "HP"),
+    ("officejet", "Printer", "HP"),
+    ("hp http server", "Printer", "HP"),
+    ("brother", "Printer", "Brother"),
+    ("epson", "Printer", "Epson"),
+    ("canon http server", "Printer", "Canon"),
+    ("xerox", "Printer", "Xerox"),
+    ("lexmark", "Printer", "Lexmark"),
+    ("kyocera", "Printer", "Kyocera"),
+    ("ricoh", "Printer", "Ricoh"),
+    ("cups", "Printer", "CUPS"),
+    ("printer", "Printer", "Unknown"),
+    // Cameras
+    ("hikvision", "IP Camera", "Hikvision"),
+    ("dnvrs-webs", "IP Camera", "Hikvision"),
+    ("app-webs", "IP Camera", "Hikvision"),
+    ("dahua", "IP Camera", "Dahua"),
+    ("axis ", "IP Camera", "Axis"),
+    ("amcrest", "IP Camera", "Amcrest"),
+    ("reolink", "IP Camera", "Reolink"),
+    ("network camera", "IP Camera", "Unknown"),
+    ("ip camera", "IP Camera", "Unknown"),
+    ("nvr", "IP Camera", "Unknown"),
+    // NAS
+    ("synology", "NAS", "Synology"),
+    ("diskstation", "NAS", "Synology"),
+    ("qnap", "NAS", "QNAP"),
+    ("readynas", "NAS", "Netgear"),
+    ("truenas", "NAS", "iXsystems"),
+    ("freenas", "NAS", "iXsystems"),
+    ("my cloud", "NAS", "Western Digital"),
+    ("unraid", "NAS", "Lime Technology"),
+    ("terramaster", "NAS", "TerraMaster"),
+    // Routers and access points
+    ("mikrotik", "Router/AP", "MikroTik"),
+    ("routeros", "Router/AP", "MikroTik"),
+    ("openwrt", "Router/AP", "OpenWrt"),
+    ("dd-wrt", "Router/AP", "DD-WRT"),
+    ("pfsense", "Router/AP", "Netgate"),
+    ("opnsense", "Router/AP", "OPNsense"),
+    ("unifi", "Router/AP", "Ubiquiti"),
+    ("edgeos", "Router/AP", "Ubiquiti"),
+    ("ubiquiti", "Router/AP", "Ubiquiti"),
+    ("fritz!box", "Router/AP", "AVM"),
+    ("asuswrt", "Router/AP", "ASUS"),
+    ("tp-link", "Router/AP", "TP-Link"),
+    ("netgear", "Router/AP", "Netgear"),
+    ("linksys", "Router/AP", "Linksys"),
+    ("router", "Router/AP", "Unknown"),
+];
+
+/// Match banners against known device signatures; earlier signatures win
+pub fn classify(banners: &[HttpBanner]) -> Option<HttpClassification> {
+    let texts: Vec<(u16, String)> = banners.iter().map(|banner| (banner.port, banner.text())).collect();
+    SIGNATURES.iter().find_map(|(needle, device_class, vendor)| {
+        let (port, text) = texts.iter().find(|(_, text)| text.to_lowercase().contains(needle))?;
+        let os_name = match *vendor {
+            "Unknown" => device_class.to_string(),
+            vendor => format!("{} {}", vendor, device_class),
+        };
+        Some(HttpClassification {
+            os_name,
+            device_class,
+            vendor,
+            evidence: format!("port {}: {}", port, text),
+        })
+    })
+}
+
+/// Fetch `/` over HTTP (80) and HTTPS (443) and collect whatever identifies the device
+pub async fn probe(ip: &str, timeout: Duration, user_agent: &str) -> Vec<HttpBanner> {
+    let client = match reqwest::Client::builder()
+        .timeout(timeout)
+        .connect_timeout(timeout)
+        // Embedded devices almost always have self-signed certificates
+        .danger_accept_invalid_certs(true)
+        .tls_info(true)
+        // A redirect could point anywhere, including hosts that aren't the device
+        .redirect(reqwest::redirect::Policy::none())
+        .user_agent(user_agent)
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::warn!("Failed to build HTTP probe client: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let (http_url, https_url) = (format!("http://{}/", ip), format!("https://{}/", ip));
+    let (http, https) = tokio::join!(
+        fetch_banner(&client, &http_url, 80),
+        fetch_banner(&client, &https_url, 443),
+    );
+    [http, https].into_iter().flatten().collect()
+}
+
+async fn fetch_banner(client: &reqwest::Client, url: &str, port: u16) -> Option<HttpBanner> {
+    let mut response = match client.get(url).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::debug!("HTTP probe of {} failed: {}", url, e);
+            return None;
+        }
+    };
+
+    let server = response
+        .headers()
+        .get(reqwest::header::SERVER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+    let cert_cn = response
+        .extensions()
+        .get::<reqwest::tls::TlsInfo>()
+        .and_then(|info| info.peer_certificate())
+        .and_then(certificate_cn);
+
+    let mut body = Vec::new();
+    while body.len() < MAX_BODY_BYTES {
+        match response.chunk().await {
+            Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+            _ => break,
+        }
+    }
+    let title = extract_title(&String::from_utf8_lossy(&body));
+
+    let banner = HttpBanner { port, server, title, cert_cn };
+    (!banner.is_empty()).then_some(banner)
+}
+
+/// Contents of the first <title> element, whitespace collapsed
+fn extract_title(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    let title = html[start..end].split_whitespace().collect::<Vec<_>>().join(" ");
+    (!title.is_empty()).then(|| title.chars().take(200).collect())
+}
+
+/// Subject common name from a DER certificate. The subject follows the issuer in
+/// the TBSCertificate, so the last CN attribute is the subject's.
+fn certificate_cn(der: &[u8]) -> Option<String> {
+    const CN_OID: [u8; 5] = [0x06, 0x03, 0x55, 0x04, 0x03];
+    let position = der.windows(CN_OID.len()).rposition(|window| window == CN_OID)?;
+    // OID is followed by the string value: tag, short-form length, bytes
+    let value = der.get(position + CN_OID.len()..)?;
+    let (&tag, rest) = value.split_first()?;
+    // UTF8String, PrintableString, IA5String, T61String
+    if !matches!(tag, 0x0c | 0x13 | 0x16 | 0x14) {
+        return None;
+    }
+    let (len, rest) = der_length(rest)?;
+    let name = rest.get(..len)?;
+    Some(String::from_utf8_lossy(name).into_owned())
+}
+
+/// DER length (short form, or long form up to four length bytes) and what follows it
+fn der_length(data: &[u8]) -> Option<(usize, &[u8])> {
+    let (&first, rest) = data.split_first()?;
+    if first & 0x80 == 0 {
+        return Some((usize::from(first), rest));
+    }
+    let count = usize::from(first & 0x7f);
+    if count == 0 || count > 4 || rest.len() < count {
+        return None;
+    }
+    let len = rest[..count].iter().fold(0usize, |len, &byte| (len << 8) | usize::from(byte));
+    Some((len, &rest[count..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn banner(server: Option<&str>, title: Option<&str>, cert_cn: Option<&str>) -> HttpBanner {
+        HttpBanner {
+            port: 80,
+            server: server.map(str::to_string),
+            title: title.map(str::to_string),
+            cert_cn: cert_cn.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        let nas = classify(&[banner(Some("nginx"), Some("Synology DiskStation - nas01"), None)]).unwrap();
+        assert_eq!(nas.os_name, "Synology NAS");
+        assert_eq!(nas.device_class, "NAS");
+
+        let camera = classify(&[banner(Some("DNVRS-Webs"), None, None)]).unwrap();
+        assert_eq!(camera.vendor, "Hikvision");
+
+        let printer = classify(&[banner(Some("HP HTTP Server; HP Color LaserJet MFP M277dw"), None, None)]).unwrap();
+        assert_eq!(printer.os_name, "HP Printer");
+
+        let router = classify(&[banner(None, None, Some("router.asus.com")), banner(None, Some("RouterOS router configuration page"), None)]).unwrap();
+        assert_eq!(router.vendor, "MikroTik");
+
+        assert!(classify(&[banner(Some("nginx/1.24.0"), Some("Welcome to nginx!"), None)]).is_none());
+    }
+
+    #[test]
+    fn test_extract_title() {
+        assert_eq!(
+            extract_title("<html><head><TITLE lang=en>\n  Web Image Monitor\n</TITLE></head>").as_deref(),
+            Some("Web Image Monitor")
+        );
+        assert_eq!(extract_title("<title></title>"), None);
+        assert_eq!(extract_title("no title here"), None);
+    }
+
+    #[test]
+    fn test_certificate_cn() {
+        // Issuer CN then subject CN, as laid out in a TBSCertificate
+        let mut der = vec![0x30, 0x00];
+        der.extend_from_slice(&[0x06, 0x03, 0x55, 0x04, 0x03, 0x0c, 0x07]);
+        der.extend_from_slice(b"Root CA");
+        der.extend_from_slice(&[0x06, 0x03, 0x55, 0x04, 0x03, 0x13, 0x0b]);
+        der.extend_from_slice(b"QNAP NAS-01");
+        assert_eq!(certificate_cn(&der).as_deref(), Some("QNAP NAS-01"));
+        assert_eq!(certificate_cn(&[0x30, 0x00]), None);
+
+        // Names of 128 bytes or more use a long-form length
+        let long_name = "a".repeat(150);
+        let mut der = vec![0x06, 0x03, 0x55, 0x04, 0x03, 0x0c, 0x81, 150];
+        der.extend_from_slice(long_name.as_bytes());
+        assert_eq!(certificate_cn(&der), Some(long_name));
+        // A length running past the certificate gives nothing
+        assert_eq!(certificate_cn(&[0x06, 0x03, 0x55, 0x04, 0x03, 0x0c, 0x82, 0x01]), None);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_banner() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request).await;
+            let body = "<html><title>Brother HL-L2350DW</title></html>";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nServer: debut/1.30\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let client = reqwest::Client::new();
+        let banner = fetch_banner(&client, &format!("http://{}/", addr), addr.port()).await.unwrap();
+        assert_eq!(banner.server.as_deref(), Some("debut/1.30"));
+        assert_eq!(classify(&[banner]).unwrap().os_name, "Brother Printer");
+    }
+}
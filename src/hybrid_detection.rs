@@ -1,22 +1,44 @@
-use crate::fingerprint;
-use crate::smb;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use crate::db::probe_cache::CachedProbe;
+use crate::detection_sources::{
+    FingerprintSource, HttpSource, MacMappingSource, PxeSource, RuleSource, SmbSource, VendorOptionsSource,
+    WindowsHintSource,
+};
+use crate::probe_identity::ProbeIdentity;
+use crate::probe_scheduler::{ProbeScheduler, SchedulerConfig, SchedulerStatus};
+use crate::smb_policy::SmbProbePolicy;
+use dashmap::DashMap;
+use futures::future::BoxFuture;
+use serde::Serialize;
 use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::process::Command;
+use std::sync::{Arc, RwLock};
 
 /// Configuration for hybrid detection
 #[derive(Debug, Clone)]
 pub struct HybridConfig {
-    /// Enable SMB probing as fallback
+    /// Enable SMB probing of the devices the policy selects
     pub enable_smb_probing: bool,
     /// SMB probe timeout in seconds
     pub smb_timeout_secs: u64,
-    /// Only probe when DHCP confidence is below this threshold
-    pub smb_probe_confidence_threshold: f32,
     /// Cache SMB results for this many seconds
     pub smb_cache_ttl_secs: u64,
+    /// Which devices get an SMB probe
+    pub smb_policy: SmbProbePolicy,
+    /// Try an SMB1 negotiate when a host refuses SMB2
+    pub smb1_fallback: bool,
+    /// Fetch web pages from devices DHCP can't identify
+    pub enable_http_probing: bool,
+    /// HTTP probe timeout in seconds
+    pub http_timeout_secs: u64,
+    /// Cache HTTP banners for this many seconds
+    pub http_cache_ttl_secs: u64,
+    /// Wait this long before retrying a failed probe, doubling per failure up to the cache TTL
+    pub negative_cache_ttl_secs: u64,
+    /// Per-source multipliers applied to evidence confidence (default 1.0)
+    pub source_weights: HashMap<String, f32>,
+    /// How probes present themselves on the wire
+    pub probe_identity: ProbeIdentity,
+    /// Limits shared by all active probes
+    pub scheduler: SchedulerConfig,
 }
 
 impl Default for HybridConfig {
@@ -24,14 +46,22 @@ impl Default for HybridConfig {
         Self {
             enable_smb_probing: true,
             smb_timeout_secs: 3,
-            smb_probe_confidence_threshold: 0.8,
             smb_cache_ttl_secs: 3600, // 1 hour
+            smb_policy: SmbProbePolicy::default(),
+            smb1_fallback: false,
+            enable_http_probing: false,
+            http_timeout_secs: 3,
+            http_cache_ttl_secs: 3600,
+            negative_cache_ttl_secs: 300,
+            source_weights: HashMap::new(),
+            probe_identity: ProbeIdentity::default(),
+            scheduler: SchedulerConfig::default(),
         }
     }
 }
 
 /// Result of hybrid detection
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DetectionResult {
     pub os_name: String,
     pub device_class: String,
@@ -42,267 +72,466 @@ pub struct DetectionResult {
     pub smb_build: Option<u32>,
 }
 
-/// Cache entry for SMB probe results
+/// Combined result and the evidence behind it
 #[derive(Debug, Clone)]
-struct SmbCacheEntry {
-    result: smb::SmbProbeResult,
-    timestamp: u64,
+pub struct Detection {
+    pub result: DetectionResult,
+    pub evidence: Vec<Evidence>,
+}
+
+/// What detection sources know about the device being classified
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceContext<'a> {
+    pub mac_address: &'a str,
+    pub ip_address: &'a str,
+    pub fingerprint: &'a str,
+    pub vendor_class: Option<&'a str>,
+    /// Option 12 hostname
+    pub hostname: Option<&'a str>,
+    /// Option 43 vendor-specific information
+    pub vendor_specific: Option<&'a [u8]>,
+    /// Option 93 PXE client architecture
+    pub client_arch: Option<u16>,
+    /// Option 125 vendor blocks
+    pub vendor_options: &'a [crate::vendor_options::EnterpriseOptions],
+}
+
+impl DeviceContext<'_> {
+    /// Whether the device has an address active probes can reach
+    pub fn has_address(&self) -> bool {
+        !self.ip_address.is_empty() && self.ip_address != "0.0.0.0"
+    }
+}
+
+/// One source's verdict about a device
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Evidence {
+    pub source: &'static str,
+    /// None when the source only offers an OS family hint
+    pub os_name: Option<String>,
+    pub device_class: Option<String>,
+    pub vendor: Option<String>,
+    /// OS family implied without naming an OS (e.g. ping TTL)
+    pub family: Option<TtlFamily>,
+    pub confidence: f32,
+    /// How the source reached its verdict
+    pub method: String,
+    pub smb_dialect: Option<String>,
+    pub smb_build: Option<u32>,
+    /// smb_build is what the server reported, not an estimate from its dialect
+    pub smb_build_exact: bool,
+    /// Signing, capabilities and clock of the SMB server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smb_server: Option<crate::smb::SmbServerInfo>,
+}
+
+impl Evidence {
+    fn identifies(&self) -> bool {
+        self.os_name.is_some()
+    }
+
+    /// The verdict: an OS name, or the family for hints
+    pub fn value(&self) -> Option<&str> {
+        self.os_name.as_deref().or(self.family.map(TtlFamily::name))
+    }
+}
+
+/// A source of evidence in the detection pipeline
+pub trait DetectionSource: Send + Sync {
+    /// Name used in evidence and `[detection.weights]`
+    fn name(&self) -> &'static str;
+
+    /// Gather evidence; `prior` holds what earlier sources found, so expensive
+    /// sources can skip devices that are already identified
+    fn gather<'a>(
+        &'a self,
+        device: &'a DeviceContext<'a>,
+        prior: &'a [Evidence],
+    ) -> BoxFuture<'a, Vec<Evidence>>;
+
+    /// Whether the source probes the device over the network, rather than
+    /// looking at what the DHCP request already says
+    fn is_active(&self) -> bool {
+        false
+    }
+
+    /// Drop cached results for the device's MAC and address
+    fn forget(&self, _device: &DeviceContext<'_>) {}
+
+    /// Drop cached results for the device, keeping failure backoff
+    fn expire(&self, _device: &DeviceContext<'_>) {}
+
+    /// Drop cached results for a MAC and/or IP; returns how many entries went
+    fn remove_cached(&self, _mac_address: Option<&str>, _ip_address: Option<&str>) -> usize {
+        0
+    }
+
+    /// Drop all cached results
+    fn clear_cache(&self) {}
+
+    /// (total, expired) cache entries
+    fn cache_stats(&self) -> (usize, usize) {
+        (0, 0)
+    }
+
+    /// Cache entries worth keeping across a restart
+    fn export_cache(&self) -> Vec<CachedProbe> {
+        Vec::new()
+    }
+
+    /// Restore this source's exported entries; returns how many were loaded
+    fn import_cache(&self, _entries: &[CachedProbe]) -> usize {
+        0
+    }
+}
+
+/// OS family implied by the initial TTL of an ICMP echo reply
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum TtlFamily {
+    /// Initial TTL 64: Linux, macOS, iOS, Android, BSD
+    Unix,
+    /// Initial TTL 128
+    Windows,
+    /// Initial TTL 255: routers, switches, Solaris
+    NetworkDevice,
 }
 
-/// Hybrid detection engine that combines DHCP fingerprinting with SMB probing
+impl TtlFamily {
+    /// Round an observed TTL up to the nearest common initial TTL
+    pub fn from_ttl(ttl: u8) -> Self {
+        match ttl {
+            0..=64 => Self::Unix,
+            65..=128 => Self::Windows,
+            _ => Self::NetworkDevice,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Unix => "Linux/macOS",
+            Self::Windows => "Windows",
+            Self::NetworkDevice => "Network device",
+        }
+    }
+
+    // Whether a result belongs to this family
+    fn matches(self, result: &DetectionResult) -> bool {
+        let windows = result.vendor == "Microsoft" || result.os_name.contains("Windows");
+        match self {
+            Self::Windows => windows,
+            Self::Unix => !windows,
+            // DHCP fingerprints don't identify network equipment
+            Self::NetworkDevice => false,
+        }
+    }
+}
+
+/// Cumulative counts of active probe outcomes, by (probe, outcome)
+#[derive(Debug, Default)]
+pub struct ProbeOutcomes {
+    counts: DashMap<(&'static str, &'static str), u64>,
+}
+
+impl ProbeOutcomes {
+    pub fn record(&self, probe: &'static str, outcome: &'static str) {
+        *self.counts.entry((probe, outcome)).or_insert(0) += 1;
+    }
+
+    /// ((probe, outcome), count), sorted
+    pub fn snapshot(&self) -> Vec<((&'static str, &'static str), u64)> {
+        let mut counts: Vec<_> = self.counts.iter().map(|entry| (*entry.key(), *entry.value())).collect();
+        counts.sort_unstable();
+        counts
+    }
+}
+
+/// Probe cache entries of one source; expired ones are failures kept for their backoff
+/// or results waiting to be replaced
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub source: &'static str,
+    pub entries: usize,
+    pub expired: usize,
+}
+
+/// Hybrid detection engine: a pipeline of evidence sources and a combiner
 pub struct HybridDetector {
-    config: HybridConfig,
-    smb_cache: Arc<RwLock<HashMap<String, SmbCacheEntry>>>,
+    sources: Vec<Box<dyn DetectionSource>>,
+    weights: HashMap<String, f32>,
+    probe_outcomes: Arc<ProbeOutcomes>,
+    scheduler: Arc<ProbeScheduler>,
+    smb_policy: Arc<RwLock<SmbProbePolicy>>,
 }
 
 impl HybridDetector {
     pub fn new(config: HybridConfig) -> Self {
+        // Passive lookups first, then active probes that can use their results
+        let probe_outcomes = Arc::new(ProbeOutcomes::default());
+        let scheduler = Arc::new(ProbeScheduler::new(&config.scheduler));
+        let smb_policy = Arc::new(RwLock::new(config.smb_policy.clone()));
+        let mut sources: Vec<Box<dyn DetectionSource>> = vec![
+            Box::new(MacMappingSource),
+            Box::new(RuleSource),
+            Box::new(FingerprintSource),
+            Box::new(WindowsHintSource),
+            Box::new(PxeSource),
+            Box::new(VendorOptionsSource),
+        ];
+        if config.enable_smb_probing {
+            sources.push(Box::new(SmbSource::new(&config, smb_policy.clone(), probe_outcomes.clone(), scheduler.clone())));
+        }
+        if config.enable_http_probing {
+            sources.push(Box::new(HttpSource::new(&config, probe_outcomes.clone(), scheduler.clone())));
+        }
+
         Self {
-            config,
-            smb_cache: Arc::new(RwLock::new(HashMap::new())),
+            sources,
+            weights: config.source_weights,
+            probe_outcomes,
+            scheduler,
+            smb_policy,
         }
     }
 
-    /// Detect OS using hybrid approach: Use DHCP IP for active SMB scanning
-    pub async fn detect(
-        &self,
-        mac_address: &str,
-        ip_address: &str,
-        dhcp_fingerprint: &str,
-        vendor_class: Option<&str>,
-    ) -> DetectionResult {
-        // Step 1: Get basic DHCP fingerprint info for fallback
-        let dhcp_result = self.detect_via_dhcp(mac_address, dhcp_fingerprint);
-
-        // Step 2: Only try SMB probing if enabled AND conditions are met
-        // Conditions: IP is not 0.0.0.0 AND vendor class contains "MSFT"
-        let should_probe_smb = self.config.enable_smb_probing
-            && ip_address != "0.0.0.0"
-            && vendor_class.map_or(false, |vc| vc.contains("MSFT"));
-
-        if should_probe_smb {
-            println!("🔍 SMB PROBE: Attempting probe to {} (MAC: {}, vendor: {:?})",
-                ip_address, mac_address, vendor_class);
-            tracing::info!(
-                "Attempting SMB probe to {} (MAC: {}, vendor: {:?})",
-                ip_address,
-                mac_address,
-                vendor_class
-            );
+    /// Replace the SMB probe policy; probes already running finish under the old one
+    pub fn set_smb_policy(&self, policy: SmbProbePolicy) {
+        *self.smb_policy.write().unwrap() = policy;
+    }
 
-            // First, check if host is reachable via ping
-            match Self::ping_host(ip_address).await {
-                Ok(true) => {
-                    println!("✅ PING SUCCESS: {} is reachable", ip_address);
-                }
-                Ok(false) => {
-                    println!("❌ PING FAILED: {} is not reachable, skipping SMB probe", ip_address);
-                    tracing::debug!("Host {} not reachable via ping, skipping SMB probe", ip_address);
-                    // Don't probe if host is not reachable
-                    return dhcp_result;
-                }
-                Err(e) => {
-                    println!("⚠️  PING ERROR: {} - {}, continuing with SMB probe anyway", ip_address, e);
-                    tracing::debug!("Ping error for {}: {}, continuing with SMB probe", ip_address, e);
-                    // Continue with SMB probe even if ping fails (some hosts may block ICMP)
-                }
-            }
+    /// How SMB and HTTP probes have turned out since startup
+    pub fn probe_outcomes(&self) -> &ProbeOutcomes {
+        &self.probe_outcomes
+    }
 
-            match self.probe_smb_cached(ip_address).await {
-                Some(smb_result) if smb_result.success => {
-                    println!("✅ SMB PROBE SUCCESS: {} => {} (dialect: {}, build: {:?})",
-                        ip_address, smb_result.os_version, smb_result.smb_dialect, smb_result.build_number);
-                    // Use SMB results - this is more accurate than DHCP fingerprinting
-                    return self.combine_results(dhcp_result, smb_result);
-                }
-                Some(smb_result) => {
-                    println!("❌ SMB PROBE FAILED: {} => {}", ip_address, smb_result.os_version);
-                    tracing::debug!("SMB probe failed for {}: {}", ip_address, smb_result.os_version);
-                }
-                None => {
-                    println!("⚠️  SMB PROBE ERROR: {} returned no result", ip_address);
-                    tracing::debug!("SMB probe returned no result for {}", ip_address);
-                }
-            }
-        } else if self.config.enable_smb_probing {
-            let reason = if ip_address == "0.0.0.0" {
-                "IP is 0.0.0.0"
-            } else if vendor_class.is_none() {
-                "no vendor class"
-            } else if !vendor_class.map_or(false, |vc| vc.contains("MSFT")) {
-                "vendor class doesn't contain MSFT"
-            } else {
-                "unknown"
-            };
-            println!("⏭️  SMB PROBE SKIP: {} (MAC: {}) - {}", ip_address, mac_address, reason);
-            tracing::debug!(
-                "Skipping SMB probe for {} (IP: {}, vendor: {:?}) - conditions not met",
-                mac_address,
-                ip_address,
-                vendor_class
-            );
+    /// Probes running and queued right now
+    pub fn probe_status(&self) -> SchedulerStatus {
+        self.scheduler.status()
+    }
+
+    /// Run every source and merge their evidence
+    pub async fn detect(&self, device: &DeviceContext<'_>) -> Detection {
+        let evidence = self.gather(device).await;
+        Detection {
+            result: self.combine(&evidence),
+            evidence,
         }
+    }
 
-        // Fall back to DHCP result if SMB fails or is disabled
-        tracing::debug!("Using DHCP-only detection for {}", mac_address);
-        dhcp_result
-    }
-
-    /// Detect via DHCP fingerprinting only
-    /// Priority: 1) MAC address mapping, 2) Exact fingerprint match, 3) Unknown
-    fn detect_via_dhcp(&self, mac_address: &str, fingerprint: &str) -> DetectionResult {
-        // Priority 1: Check MAC address mapping first (most reliable)
-        // This uses lookup_os which checks MAC mapping before fingerprint
-        if let Some(info) = fingerprint::lookup_os(mac_address, fingerprint) {
-            return DetectionResult {
-                os_name: info.os_name.to_string(),
-                device_class: info.device_class.to_string(),
-                vendor: info.vendor.to_string(),
-                confidence: 0.95, // High confidence for explicit mapping or exact match
-                detection_method: "MAC/Fingerprint lookup".to_string(),
-                smb_dialect: None,
-                smb_build: None,
-            };
+    /// Detection from the DHCP request alone, without waiting on probes
+    pub async fn detect_passive(&self, device: &DeviceContext<'_>) -> Detection {
+        let evidence = self.gather_from(device, false).await;
+        Detection {
+            result: self.combine(&evidence),
+            evidence,
         }
+    }
 
-        // Unknown - no match found
-        DetectionResult {
-            os_name: "Unknown".to_string(),
-            device_class: "Unknown".to_string(),
-            vendor: "Unknown".to_string(),
-            confidence: 0.0,
-            detection_method: "None".to_string(),
-            smb_dialect: None,
-            smb_build: None,
+    /// Whether `detect` would probe the device over the network
+    pub fn probes(&self, device: &DeviceContext<'_>) -> bool {
+        device.has_address() && self.sources.iter().any(|source| source.is_active())
+    }
+
+    /// Run detection again, discarding cached probe results for the IP first. The probe
+    /// limits still apply, so a host probed within the cooldown isn't probed again.
+    pub async fn redetect(&self, device: &DeviceContext<'_>) -> Detection {
+        for source in &self.sources {
+            source.forget(device);
         }
+        self.detect(device).await
     }
 
-    /// Ping a host to check if it's reachable
-    /// Returns Ok(true) if reachable, Ok(false) if not reachable, Err if ping command fails
-    async fn ping_host(ip: &str) -> Result<bool, String> {
-        println!("📡 PING: Checking reachability of {}...", ip);
-
-        // Use platform-specific ping command
-        // Linux: ping -c 1 -W 1 <ip>
-        // -c 1: send 1 packet
-        // -W 1: wait 1 second for response
-        let output = Command::new("ping")
-            .arg("-c")
-            .arg("1")
-            .arg("-W")
-            .arg("1")
-            .arg(ip)
-            .output()
-            .await
-            .map_err(|e| format!("Failed to execute ping: {}", e))?;
-
-        let success = output.status.success();
-
-        if success {
-            // Parse output to get response time if available
-            if let Ok(stdout) = String::from_utf8(output.stdout) {
-                if let Some(time_line) = stdout.lines().find(|line| line.contains("time=")) {
-                    if let Some(time_str) = time_line.split("time=").nth(1) {
-                        if let Some(time_ms) = time_str.split_whitespace().next() {
-                            println!("  ⏱️  Response time: {} ms", time_ms);
-                        }
-                    }
-                }
-            }
+    /// Run detection again with fresh probes, as `redetect` does, but within the
+    /// probe limits: cooldowns, queue limits and failure backoff still apply
+    pub async fn reverify(&self, device: &DeviceContext<'_>) -> Detection {
+        for source in &self.sources {
+            source.expire(device);
         }
+        self.detect(device).await
+    }
+
+    /// Evidence from each source in pipeline order
+    pub async fn gather(&self, device: &DeviceContext<'_>) -> Vec<Evidence> {
+        self.gather_from(device, true).await
+    }
 
-        Ok(success)
+    #[tracing::instrument(name = "detect", level = "debug", skip_all, fields(mac = %device.mac_address))]
+    async fn gather_from(&self, device: &DeviceContext<'_>, active: bool) -> Vec<Evidence> {
+        let mut evidence = Vec::new();
+        for source in self.sources.iter().filter(|source| active || !source.is_active()) {
+            let started = std::time::Instant::now();
+            let found = source.gather(device, &evidence).await;
+            tracing::debug!(
+                source = source.name(),
+                count = found.len(),
+                duration_ms = started.elapsed().as_millis() as u64,
+                "Gathered evidence"
+            );
+            evidence.extend(found);
+        }
+        evidence
     }
 
-    /// Probe SMB with caching
-    async fn probe_smb_cached(&self, ip: &str) -> Option<smb::SmbProbeResult> {
-        // Check cache first
-        {
-            let cache = self.smb_cache.read().await;
-            if let Some(entry) = cache.get(ip) {
-                let now = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
+    fn weight(&self, source: &str) -> f32 {
+        self.weights.get(source).copied().unwrap_or(1.0)
+    }
 
-                if now - entry.timestamp < self.config.smb_cache_ttl_secs {
-                    println!("💾 SMB CACHE HIT: {} (age: {}s)", ip, now - entry.timestamp);
-                    tracing::debug!("SMB cache hit for {}", ip);
-                    return Some(entry.result.clone());
+    /// Merge evidence into one result: the strongest identification wins (later
+    /// sources on ties), gaps are filled from the rest, family hints adjust confidence
+    pub fn combine(&self, evidence: &[Evidence]) -> DetectionResult {
+        let score = |e: &Evidence| e.confidence * self.weight(e.source);
+        let mut ranked: Vec<&Evidence> = evidence
+            .iter()
+            .filter(|e| e.identifies() && score(e) > 0.0)
+            .collect();
+        // Stable sort keeps order among equals; reversed first so later sources lead
+        ranked.reverse();
+        ranked.sort_by(|a, b| score(b).total_cmp(&score(a)));
+
+        let mut result = match ranked.first() {
+            Some(best) => {
+                let fill = |field: fn(&Evidence) -> &Option<String>| {
+                    ranked
+                        .iter()
+                        .find_map(|e| field(e).clone())
+                        .unwrap_or_else(|| "Unknown".to_string())
+                };
+                DetectionResult {
+                    os_name: best.os_name.clone().unwrap_or_default(),
+                    device_class: fill(|e| &e.device_class),
+                    vendor: fill(|e| &e.vendor),
+                    confidence: score(best).min(1.0),
+                    detection_method: best.method.clone(),
+                    smb_dialect: best.smb_dialect.clone(),
+                    smb_build: best.smb_build,
                 }
             }
+            None => DetectionResult {
+                os_name: "Unknown".to_string(),
+                device_class: "Unknown".to_string(),
+                vendor: "Unknown".to_string(),
+                confidence: 0.0,
+                detection_method: "None".to_string(),
+                smb_dialect: None,
+                smb_build: None,
+            },
+        };
+
+        // A build the SMB server reported itself outranks what the client claims
+        let exact_build = ranked.first().is_some_and(|best| best.smb_build_exact);
+        if let Some(hint) = evidence.iter().find(|e| e.source == "windows_hint" && e.smb_build.is_some()) {
+            if !exact_build {
+                Self::apply_build_hint(&mut result, hint, score(hint));
+            }
         }
 
-        println!("🌐 SMB PROBE: Connecting to {}:445 (timeout: {}s)...", ip, self.config.smb_timeout_secs);
-
-        // Probe SMB
-        match smb::probe_smb(ip, self.config.smb_timeout_secs).await {
-            Ok(result) => {
-                println!("📦 SMB RESPONSE: {} returned (success: {})", ip, result.success);
-
-                // Cache the result
-                let now = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
+        for hint in evidence.iter().filter(|e| !e.identifies()) {
+            if let Some(family) = hint.family {
+                Self::apply_family_hint(&mut result, family, &hint.method);
+            }
+        }
 
-                let mut cache = self.smb_cache.write().await;
-                cache.insert(ip.to_string(), SmbCacheEntry {
-                    result: result.clone(),
-                    timestamp: now,
-                });
+        result
+    }
 
-                Some(result)
-            }
-            Err(e) => {
-                println!("❌ SMB PROBE ERROR: {} failed - {}", ip, e);
-                tracing::warn!("SMB probe error for {}: {}", ip, e);
-                None
-            }
+    /// Merge a Windows build the client reported into a Windows result from another
+    /// source: fingerprints stop at "Windows 10/8/8.1" and SMB estimates the build
+    /// from the dialect when the server left it out, so the reported build names the release
+    fn apply_build_hint(result: &mut DetectionResult, hint: &Evidence, score: f32) {
+        let (Some(build), Some(os_name)) = (hint.smb_build, &hint.os_name) else {
+            return;
+        };
+        if result.detection_method == hint.method || !TtlFamily::Windows.matches(result) {
+            return;
+        }
+        // A dialect newer than the reported build allows means the report is stale
+        if result.smb_build.is_some_and(|estimate| estimate > build) {
+            return;
         }
+        result.os_name = os_name.clone();
+        result.smb_build = Some(build);
+        result.confidence = (result.confidence.max(score) + 0.02).min(0.99);
+        result.detection_method = format!("{} + {}", result.detection_method, hint.method);
     }
 
-    /// Combine DHCP and SMB results
-    fn combine_results(
-        &self,
-        dhcp_result: DetectionResult,
-        smb_result: smb::SmbProbeResult,
-    ) -> DetectionResult {
-        // Use SMB detection results directly - they are more accurate
-        let os_name = &smb_result.os_version;
-
-        DetectionResult {
-            os_name: os_name.to_string(),
-            device_class: dhcp_result.device_class,
-            vendor: "Microsoft".to_string(),
-            confidence: 0.95, // Very high confidence with SMB probing
-            detection_method: format!("SMB probe ({})", smb_result.smb_dialect),
-            smb_dialect: Some(smb_result.smb_dialect),
-            smb_build: smb_result.build_number,
+    /// Weigh a result against an OS family hint
+    fn apply_family_hint(result: &mut DetectionResult, family: TtlFamily, method: &str) {
+        if result.confidence == 0.0 {
+            // Nothing else identified the device: the hint is the only clue
+            result.os_name = family.name().to_string();
+            result.confidence = 0.3;
+            result.detection_method = format!("{} hint", method);
+        } else if family.matches(result) {
+            result.confidence = (result.confidence + 0.05).min(0.99);
+            result.detection_method = format!("{} + {}", result.detection_method, method);
+        } else {
+            result.confidence *= 0.6;
+            result.detection_method = format!(
+                "{} ({} suggests {})",
+                result.detection_method,
+                method,
+                family.name()
+            );
         }
     }
 
-    /// Clear SMB cache
+    /// Clear probe caches
     pub async fn clear_cache(&self) {
-        let mut cache = self.smb_cache.write().await;
-        cache.clear();
-        tracing::info!("SMB probe cache cleared");
+        for source in &self.sources {
+            source.clear_cache();
+        }
+        tracing::info!("Probe caches cleared");
     }
 
-    /// Get cache statistics
-    pub async fn cache_stats(&self) -> (usize, usize) {
-        let cache = self.smb_cache.read().await;
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+    /// Every source's cache, for storing across restarts
+    pub fn export_cache(&self) -> Vec<CachedProbe> {
+        self.sources.iter().flat_map(|source| source.export_cache()).collect()
+    }
+
+    /// Hand stored cache entries back to the sources that exported them
+    pub fn import_cache(&self, entries: &[CachedProbe]) -> usize {
+        self.sources
+            .iter()
+            .map(|source| {
+                let own: Vec<CachedProbe> = entries.iter().filter(|e| e.source == source.name()).cloned().collect();
+                source.import_cache(&own)
+            })
+            .sum()
+    }
 
-        let total = cache.len();
-        let expired = cache.values()
-            .filter(|entry| now - entry.timestamp >= self.config.smb_cache_ttl_secs)
-            .count();
+    /// Drop cached probe results for a MAC and/or IP (both must match when both are
+    /// given) and lift the address's probe cooldown, so the next request probes afresh
+    pub fn forget_cached(&self, mac_address: Option<&str>, ip_address: Option<&str>) -> usize {
+        if let Some(ip) = ip_address {
+            self.scheduler.forget(ip);
+        }
+        self.sources
+            .iter()
+            .map(|source| source.remove_cached(mac_address, ip_address))
+            .sum()
+    }
+
+    /// Get cache statistics (total, expired) across sources
+    pub async fn cache_stats(&self) -> (usize, usize) {
+        self.sources
+            .iter()
+            .map(|source| source.cache_stats())
+            .fold((0, 0), |(total, expired), (t, e)| (total + t, expired + e))
+    }
 
-        (total, expired)
+    /// Cache statistics of each probing source
+    pub fn source_cache_stats(&self) -> Vec<CacheStats> {
+        self.sources
+            .iter()
+            .filter(|source| source.is_active())
+            .map(|source| {
+                let (entries, expired) = source.cache_stats();
+                CacheStats {
+                    source: source.name(),
+                    entries,
+                    expired,
+                }
+            })
+            .collect()
     }
 }
 
@@ -310,6 +539,29 @@ impl HybridDetector {
 mod tests {
     use super::*;
 
+    fn device<'a>(fingerprint: &'a str, client_arch: Option<u16>) -> DeviceContext<'a> {
+        DeviceContext {
+            mac_address: "aa:bb:cc:dd:ee:ff",
+            ip_address: "0.0.0.0",
+            fingerprint,
+            vendor_class: None,
+            hostname: None,
+            vendor_specific: None,
+            client_arch,
+            vendor_options: &[],
+        }
+    }
+
+    fn ttl_hint(ttl: u8) -> Evidence {
+        Evidence {
+            source: "icmp_ttl",
+            family: Some(TtlFamily::from_ttl(ttl)),
+            confidence: 0.3,
+            method: format!("ICMP TTL (ttl={})", ttl),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_default_config() {
         let config = HybridConfig::default();
@@ -322,10 +574,7 @@ mod tests {
         let detector = HybridDetector::new(HybridConfig::default());
 
         // Windows fingerprint (exact match)
-        let result = detector.detect_via_dhcp(
-            "aa:bb:cc:dd:ee:ff",
-            "1,3,6,15,31,33,43,44,46,47,121,249,252"
-        );
+        let result = detector.detect(&device("1,3,6,15,31,33,43,44,46,47,121,249,252", None)).await.result;
 
         assert!(result.os_name.contains("Windows"));
         assert!(result.confidence > 0.5);
@@ -340,4 +589,165 @@ mod tests {
 
         detector.clear_cache().await;
     }
+
+    #[tokio::test]
+    async fn test_passive_detection() {
+        let detector = HybridDetector::new(HybridConfig::default());
+        let windows = device("1,3,6,15,31,33,43,44,46,47,121,249,252", None);
+        assert!(!detector.probes(&windows));
+        assert!(detector.probes(&DeviceContext { ip_address: "192.0.2.10", ..windows }));
+
+        let passive = detector.detect_passive(&DeviceContext { ip_address: "192.0.2.10", ..windows }).await;
+        assert!(passive.evidence.iter().all(|e| e.source == "dhcp_fingerprint"));
+        assert!(passive.result.os_name.contains("Windows"));
+
+        let disabled = HybridDetector::new(HybridConfig {
+            enable_smb_probing: false,
+            enable_http_probing: false,
+            ..Default::default()
+        });
+        assert!(!disabled.probes(&DeviceContext { ip_address: "192.0.2.10", ..windows }));
+    }
+
+    #[tokio::test]
+    async fn test_pxe_detection() {
+        let detector = HybridDetector::new(HybridConfig::default());
+        let result = detector.detect(&device("", Some(7))).await.result;
+        assert_eq!(result.os_name, "PXE Boot (x64 UEFI)");
+        assert_eq!(result.device_class, "PXE Client (UEFI x64)");
+
+        assert_eq!(detector.detect(&device("", Some(0))).await.result.device_class, "PXE Client (BIOS)");
+        assert_eq!(detector.detect(&device("", Some(11))).await.result.device_class, "PXE Client (ARM)");
+    }
+
+    #[tokio::test]
+    async fn test_vendor_options_detection() {
+        let detector = HybridDetector::new(HybridConfig::default());
+        let unifi = crate::vendor_options::decode(&[0, 0, 0xa0, 0x98, 6, 1, 4, 10, 0, 0, 1]);
+        let result = detector.detect(&DeviceContext { vendor_options: &unifi, ..device("", None) }).await.result;
+        assert_eq!(result.os_name, "Ubiquiti UniFi");
+        assert_eq!(result.vendor, "Ubiquiti");
+        assert_eq!(result.detection_method, "DHCP option 125, enterprise 41112 (Ubiquiti)");
+
+        // A fingerprint match is more specific
+        let windows = device("1,3,6,15,31,33,43,44,46,47,121,249,252", None);
+        let result = detector.detect(&DeviceContext { vendor_options: &unifi, ..windows }).await.result;
+        assert!(result.os_name.contains("Windows"));
+    }
+
+    #[tokio::test]
+    async fn test_windows_hints() {
+        let detector = HybridDetector::new(HybridConfig {
+            enable_smb_probing: false,
+            enable_http_probing: false,
+            ..Default::default()
+        });
+        let windows = device("1,3,6,15,31,33,43,44,46,47,121,249,252", None);
+        let fingerprint_only = detector.detect(&windows).await.result;
+
+        // The bare vendor class backs the fingerprint up
+        let msft = detector.detect(&DeviceContext { vendor_class: Some("MSFT 5.0"), ..windows }).await.result;
+        assert_eq!(msft.os_name, fingerprint_only.os_name);
+        assert!(msft.confidence > fingerprint_only.confidence);
+
+        // A reported build names the release and carries the build number
+        let build = detector
+            .detect(&DeviceContext { vendor_class: Some("MSFT 5.0 10.0.19045"), ..windows })
+            .await
+            .result;
+        assert_eq!(build.os_name, "Windows 10 22H2");
+        assert_eq!(build.smb_build, Some(19045));
+        assert!(build.confidence > fingerprint_only.confidence);
+        assert_eq!(build.detection_method, "DHCP fingerprint + Windows build in option 60");
+
+        // A dialect newer than the build allows keeps the SMB verdict
+        let mut evidence = detector.gather(&DeviceContext { vendor_class: Some("MSFT 5.0 6.1.7601"), ..windows }).await;
+        evidence.push(Evidence {
+            source: "smb",
+            os_name: Some("Windows 10/11 (SMB 3.1.1)".to_string()),
+            vendor: Some("Microsoft".to_string()),
+            confidence: 0.99,
+            method: "SMB probe (SMB 3.1.1)".to_string(),
+            smb_build: Some(19041),
+            ..Default::default()
+        });
+        assert_eq!(detector.combine(&evidence).os_name, "Windows 10/11 (SMB 3.1.1)");
+
+        // So does a build the server reported, even an older one
+        let mut evidence = detector.gather(&DeviceContext { vendor_class: Some("MSFT 5.0 10.0.22631"), ..windows }).await;
+        evidence.push(Evidence {
+            source: "smb",
+            os_name: Some("Windows 10 22H2".to_string()),
+            vendor: Some("Microsoft".to_string()),
+            confidence: 0.99,
+            method: "SMB probe (SMB 3.1.1)".to_string(),
+            smb_build: Some(19045),
+            smb_build_exact: true,
+            ..Default::default()
+        });
+        let combined = detector.combine(&evidence);
+        assert_eq!(combined.os_name, "Windows 10 22H2");
+        assert_eq!(combined.smb_build, Some(19045));
+    }
+
+    #[tokio::test]
+    async fn test_ttl_hint() {
+        assert_eq!(TtlFamily::from_ttl(57), TtlFamily::Unix);
+        assert_eq!(TtlFamily::from_ttl(127), TtlFamily::Windows);
+        assert_eq!(TtlFamily::from_ttl(250), TtlFamily::NetworkDevice);
+
+        let detector = HybridDetector::new(HybridConfig::default());
+        let mut evidence = detector.gather(&device("1,3,6,15,31,33,43,44,46,47,121,249,252", None)).await;
+        let windows = detector.combine(&evidence);
+
+        // Agreement raises confidence, disagreement lowers it
+        evidence.push(ttl_hint(128));
+        let agreed = detector.combine(&evidence);
+        assert!(agreed.confidence > windows.confidence);
+        assert!(agreed.detection_method.ends_with("+ ICMP TTL (ttl=128)"));
+        *evidence.last_mut().unwrap() = ttl_hint(64);
+        let disputed = detector.combine(&evidence);
+        assert!(disputed.confidence < windows.confidence);
+        assert_eq!(disputed.os_name, windows.os_name);
+
+        // With no identification the TTL family is all there is
+        let hinted = detector.combine(&[ttl_hint(64)]);
+        assert_eq!(hinted.os_name, "Linux/macOS");
+        assert_eq!(hinted.confidence, 0.3);
+    }
+
+    #[test]
+    fn test_combine_weights_and_fill() {
+        let fingerprint = Evidence {
+            source: "dhcp_fingerprint",
+            os_name: Some("Windows 10/8/8.1".to_string()),
+            device_class: Some("Desktop/Laptop".to_string()),
+            vendor: Some("Microsoft".to_string()),
+            confidence: 0.95,
+            method: "DHCP fingerprint".to_string(),
+            ..Default::default()
+        };
+        let smb = Evidence {
+            source: "smb",
+            os_name: Some("Windows 11 22H2".to_string()),
+            vendor: Some("Microsoft".to_string()),
+            confidence: 0.95,
+            method: "SMB probe (SMB 3.1.1)".to_string(),
+            ..Default::default()
+        };
+
+        // Ties go to the later (active) source; the device class comes from DHCP
+        let detector = HybridDetector::new(HybridConfig::default());
+        let result = detector.combine(&[fingerprint.clone(), smb.clone()]);
+        assert_eq!(result.os_name, "Windows 11 22H2");
+        assert_eq!(result.device_class, "Desktop/Laptop");
+
+        // Down-weighting a source lets the other win
+        let config = HybridConfig {
+            source_weights: HashMap::from([("smb".to_string(), 0.5)]),
+            ..Default::default()
+        };
+        let result = HybridDetector::new(config).combine(&[fingerprint, smb]);
+        assert_eq!(result.os_name, "Windows 10/8/8.1");
+    }
 }
@@ -0,0 +1,262 @@
+//! Native ICMP echo for reachability checks and TTL hints.
+//!
+//! Uses an unprivileged ICMP datagram socket where the kernel allows it
+//! (Linux `net.ipv4.ping_group_range`, macOS), then a raw socket, and reports
+//! `PermissionDenied`/`Unsupported` when neither can be opened so callers can
+//! fall back to a TCP connect probe.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+
+const ECHO_REQUEST: u8 = 8;
+const ECHO_REPLY: u8 = 0;
+const PAYLOAD: &[u8] = b"ks-dhcpmon reachability";
+
+/// An answered echo (or TCP connect) probe
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PingReply {
+    /// IP TTL of the reply; unknown for TCP probes
+    pub ttl: Option<u8>,
+    pub rtt: Duration,
+}
+
+/// Internet checksum (RFC 1071)
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|pair| u32::from(u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn echo_request(identifier: u16, sequence: u16) -> Vec<u8> {
+    let mut packet = vec![ECHO_REQUEST, 0, 0, 0];
+    packet.extend_from_slice(&identifier.to_be_bytes());
+    packet.extend_from_slice(&sequence.to_be_bytes());
+    packet.extend_from_slice(PAYLOAD);
+    let sum = checksum(&packet);
+    packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    packet
+}
+
+/// Match a received datagram against our request; returns the TTL from the IP
+/// header when one is present (raw sockets, and datagram sockets on macOS)
+fn parse_reply(data: &[u8], identifier: Option<u16>, sequence: u16) -> Option<Option<u8>> {
+    // An ICMP message starts with its type (0 for a reply); an IPv4 header with 0x4_
+    let (icmp, ttl) = match data.first()? >> 4 {
+        4 => {
+            let header_len = usize::from(data[0] & 0x0f) * 4;
+            (data.get(header_len..)?, Some(*data.get(8)?))
+        }
+        _ => (data, None),
+    };
+
+    if icmp.len() < 8 || icmp[0] != ECHO_REPLY {
+        return None;
+    }
+    // Datagram sockets rewrite the identifier, so only raw sockets check it
+    let id_matches = identifier.is_none_or(|id| icmp[4..6] == id.to_be_bytes());
+    (id_matches && icmp[6..8] == sequence.to_be_bytes()).then_some(ttl)
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod native {
+    use super::*;
+    use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+    use std::os::fd::AsRawFd;
+    use tokio::io::unix::AsyncFd;
+
+    pub struct EchoSocket {
+        fd: AsyncFd<Socket>,
+        raw: bool,
+    }
+
+    impl EchoSocket {
+        pub fn open(addr: Ipv4Addr) -> io::Result<Self> {
+            let (socket, raw) = match Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::ICMPV4)) {
+                Ok(socket) => (socket, false),
+                Err(_) => (Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4))?, true),
+            };
+            if !raw {
+                // Ask for the reply TTL as ancillary data
+                let enable: libc::c_int = 1;
+                let result = unsafe {
+                    libc::setsockopt(
+                        socket.as_raw_fd(),
+                        libc::IPPROTO_IP,
+                        libc::IP_RECVTTL,
+                        (&enable as *const libc::c_int).cast(),
+                        std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+                    )
+                };
+                if result != 0 {
+                    tracing::debug!("IP_RECVTTL unavailable: {}", io::Error::last_os_error());
+                }
+            }
+            socket.set_nonblocking(true)?;
+            // Connecting filters out replies from other hosts
+            socket.connect(&SockAddr::from(SocketAddr::from((addr, 0))))?;
+            Ok(Self { fd: AsyncFd::new(socket)?, raw })
+        }
+
+        pub fn is_raw(&self) -> bool {
+            self.raw
+        }
+
+        pub async fn send(&self, packet: &[u8]) -> io::Result<()> {
+            loop {
+                let mut guard = self.fd.writable().await?;
+                if let Ok(result) = guard.try_io(|fd| fd.get_ref().send(packet)) {
+                    return result.map(|_| ());
+                }
+            }
+        }
+
+        /// Receive one datagram and the TTL carried as ancillary data, if any
+        pub async fn recv(&self, buf: &mut [u8]) -> io::Result<(usize, Option<u8>)> {
+            loop {
+                let mut guard = self.fd.readable().await?;
+                if let Ok(result) = guard.try_io(|fd| recv_with_ttl(fd.get_ref(), buf)) {
+                    return result;
+                }
+            }
+        }
+    }
+
+    fn recv_with_ttl(socket: &Socket, buf: &mut [u8]) -> io::Result<(usize, Option<u8>)> {
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        };
+        // u64 keeps the control buffer aligned for cmsghdr
+        let mut control = [0u64; 8];
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = std::mem::size_of_val(&control) as _;
+
+        let received = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut ttl = None;
+        let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+        while !cmsg.is_null() {
+            let header = unsafe { &*cmsg };
+            // Linux labels it IP_TTL, macOS IP_RECVTTL
+            if header.cmsg_level == libc::IPPROTO_IP
+                && (header.cmsg_type == libc::IP_TTL || header.cmsg_type == libc::IP_RECVTTL)
+            {
+                let data = unsafe { libc::CMSG_DATA(cmsg) };
+                ttl = Some(if cfg!(target_os = "macos") {
+                    unsafe { *data }
+                } else {
+                    let value = unsafe { std::ptr::read_unaligned(data as *const libc::c_int) };
+                    value as u8
+                });
+            }
+            cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+        }
+
+        Ok((received as usize, ttl))
+    }
+}
+
+/// Send one ICMP echo request and wait up to `timeout` for the reply.
+/// `Ok(None)` means no reply; an error means ICMP sockets aren't available.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub async fn echo(addr: Ipv4Addr, timeout: Duration) -> io::Result<Option<PingReply>> {
+    let socket = native::EchoSocket::open(addr)?;
+    let identifier: u16 = rand::random();
+    let sequence: u16 = rand::random();
+    let expected_id = socket.is_raw().then_some(identifier);
+
+    let started = Instant::now();
+    socket.send(&echo_request(identifier, sequence)).await?;
+
+    let wait = async {
+        let mut buf = [0u8; 1500];
+        loop {
+            let (len, ancillary_ttl) = socket.recv(&mut buf).await?;
+            if let Some(header_ttl) = parse_reply(&buf[..len], expected_id, sequence) {
+                return Ok::<_, io::Error>(PingReply {
+                    ttl: header_ttl.or(ancillary_ttl),
+                    rtt: started.elapsed(),
+                });
+            }
+        }
+    };
+
+    match tokio::time::timeout(timeout, wait).await {
+        Ok(reply) => reply.map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub async fn echo(_addr: Ipv4Addr, _timeout: Duration) -> io::Result<Option<PingReply>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "native ICMP is not supported on this platform"))
+}
+
+/// Reachability without ICMP: a completed or refused TCP handshake on any of
+/// `ports` both prove the host is up
+pub async fn tcp_probe(addr: IpAddr, ports: &[u16], timeout: Duration) -> Option<PingReply> {
+    for &port in ports {
+        let started = Instant::now();
+        match tokio::time::timeout(timeout, TcpStream::connect((addr, port))).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) if e.kind() == io::ErrorKind::ConnectionRefused => {}
+            _ => continue,
+        }
+        return Some(PingReply { ttl: None, rtt: started.elapsed() });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_echo_request_checksum() {
+        let packet = echo_request(0x1234, 7);
+        assert_eq!(packet[0], ECHO_REQUEST);
+        assert_eq!(&packet[4..8], &[0x12, 0x34, 0, 7]);
+        // A packet including its own checksum sums to zero
+        assert_eq!(checksum(&packet), 0);
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let mut reply = echo_request(0x1234, 7);
+        reply[0] = ECHO_REPLY;
+
+        // Datagram socket on Linux: no IP header, identifier rewritten
+        assert_eq!(parse_reply(&reply, None, 7), Some(None));
+        assert_eq!(parse_reply(&reply, None, 8), None);
+
+        // Raw socket: IP header with TTL 57
+        let mut with_header = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 57, 1, 0, 0, 10, 0, 0, 5, 10, 0, 0, 1];
+        with_header.extend_from_slice(&reply);
+        assert_eq!(parse_reply(&with_header, Some(0x1234), 7), Some(Some(57)));
+        assert_eq!(parse_reply(&with_header, Some(0x9999), 7), None);
+
+        // Our own request looped back is not a reply
+        assert_eq!(parse_reply(&echo_request(0x1234, 7), None, 7), None);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_probe_refused_port_is_reachable() {
+        // Bind then drop to get a local port nothing listens on
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let reply = tcp_probe(IpAddr::V4(Ipv4Addr::LOCALHOST), &[port], Duration::from_secs(1)).await;
+        assert!(reply.is_some_and(|reply| reply.ttl.is_none()));
+    }
+}
@@ -0,0 +1,221 @@
+//! Push metrics to InfluxDB, or anything else accepting line protocol over HTTP (such as
+//! Telegraf's http_listener_v2), for setups that collect by push rather than scraping.
+//! Every flush interval the counts since the previous flush are written: requests by
+//! message type, devices announced as new, and active probe outcomes.
+
+use crate::web::state::AppState;
+use anyhow::{bail, Result};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::TryRecvError;
+
+/// Flushes kept for retry while the endpoint is unreachable; the oldest are dropped
+const MAX_PENDING_FLUSHES: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct InfluxConfig {
+    /// Full write URL, e.g. "http://influx:8086/api/v2/write?org=home&bucket=dhcpmon"
+    pub url: String,
+    /// Sent as "Authorization: Token ..." (InfluxDB 2.x)
+    pub token: Option<String>,
+    /// Basic authentication (InfluxDB 1.x)
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Measurements are `<prefix>_requests`, `<prefix>_new_devices` and `<prefix>_probes`
+    pub measurement_prefix: String,
+    /// Tags added to every point
+    pub tags: Vec<(String, String)>,
+    pub flush_interval: Duration,
+    pub timeout: Duration,
+}
+
+/// Turns cumulative counters into counts since the previous flush
+#[derive(Debug)]
+pub struct IntervalCounter<K> {
+    previous: HashMap<K, u64>,
+}
+
+impl<K: std::hash::Hash + Eq + Clone + Ord> IntervalCounter<K> {
+    /// Start counting from the current totals, so history restored at startup isn't reported
+    pub fn new(baseline: impl IntoIterator<Item = (K, u64)>) -> Self {
+        Self {
+            previous: baseline.into_iter().collect(),
+        }
+    }
+
+    /// Increase of each counter since the last call, sorted by key; counters that
+    /// didn't move are reported as 0 so series stay continuous
+    pub fn advance(&mut self, totals: impl IntoIterator<Item = (K, u64)>) -> Vec<(K, u64)> {
+        let mut deltas: Vec<(K, u64)> = totals
+            .into_iter()
+            .map(|(key, total)| {
+                let previous = self.previous.insert(key.clone(), total).unwrap_or(0);
+                (key, total.saturating_sub(previous))
+            })
+            .collect();
+        deltas.sort_unstable();
+        deltas
+    }
+}
+
+/// Counts for one flush interval
+#[derive(Debug, Default, PartialEq)]
+pub struct IntervalCounts {
+    /// (message type, requests)
+    pub requests: Vec<(String, u64)>,
+    pub new_devices: u64,
+    /// ((probe, outcome), probes)
+    pub probes: Vec<((&'static str, &'static str), u64)>,
+}
+
+// Measurement names escape commas and spaces; tag keys and values also escape '='
+fn escape(value: &str, tag: bool) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == ',' || c == ' ' || (tag && c == '=') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Encode the counts as line protocol with nanosecond timestamps
+pub fn encode(config: &InfluxConfig, counts: &IntervalCounts, timestamp_ns: i64) -> String {
+    let common: String = config
+        .tags
+        .iter()
+        .map(|(key, value)| format!(",{}={}", escape(key, true), escape(value, true)))
+        .collect();
+    let mut lines = String::new();
+    let mut point = |measurement: &str, tags: &[(&str, &str)], count: u64| {
+        lines.push_str(&escape(&format!("{}_{}", config.measurement_prefix, measurement), false));
+        lines.push_str(&common);
+        // Line protocol has no empty tag values
+        for (key, value) in tags.iter().filter(|(_, value)| !value.is_empty()) {
+            lines.push_str(&format!(",{}={}", key, escape(value, true)));
+        }
+        lines.push_str(&format!(" count={}i {}\n", count, timestamp_ns));
+    };
+
+    for (message_type, count) in &counts.requests {
+        point("requests", &[("type", message_type)], *count);
+    }
+    point("new_devices", &[], counts.new_devices);
+    for ((probe, outcome), count) in &counts.probes {
+        point("probes", &[("probe", probe), ("outcome", outcome)], *count);
+    }
+    lines
+}
+
+async fn write(client: &reqwest::Client, config: &InfluxConfig, body: String) -> Result<()> {
+    let mut request = client
+        .post(&config.url)
+        .header(reqwest::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(body);
+    if let Some(token) = &config.token {
+        request = request.header(reqwest::header::AUTHORIZATION, format!("Token {}", token));
+    } else if let Some(username) = &config.username {
+        request = request.basic_auth(username, config.password.as_deref());
+    }
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let detail = response.text().await.unwrap_or_default();
+        bail!("{} returned {}: {}", config.url, status, detail.trim());
+    }
+    Ok(())
+}
+
+/// Write the counts every flush interval, holding on to failed writes until the endpoint is back
+pub fn spawn(config: InfluxConfig, state: Arc<AppState>) -> Result<()> {
+    let client = reqwest::Client::builder().timeout(config.timeout).build()?;
+    let mut devices = state.device_tx.subscribe();
+    let mut requests = IntervalCounter::new(state.get_stats().request_types);
+    let mut probes = IntervalCounter::new(state.hybrid_detector.probe_outcomes().snapshot());
+
+    tokio::spawn(async move {
+        let mut pending: VecDeque<String> = VecDeque::new();
+        let mut interval = tokio::time::interval(config.flush_interval);
+        // The first tick is immediate; skip it so the first point covers a whole interval
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+
+            let mut new_devices = 0;
+            loop {
+                match devices.try_recv() {
+                    Ok(_) => new_devices += 1,
+                    Err(TryRecvError::Lagged(skipped)) => new_devices += skipped,
+                    Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+                }
+            }
+            let counts = IntervalCounts {
+                requests: requests.advance(state.get_stats().request_types),
+                new_devices,
+                probes: probes.advance(state.hybrid_detector.probe_outcomes().snapshot()),
+            };
+            let timestamp_ns = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+            if pending.len() >= MAX_PENDING_FLUSHES {
+                pending.pop_front();
+            }
+            pending.push_back(encode(&config, &counts, timestamp_ns));
+
+            let body: String = pending.iter().map(String::as_str).collect();
+            match write(&client, &config, body).await {
+                Ok(()) => {
+                    if pending.len() > 1 {
+                        tracing::info!("Wrote {} delayed metric flushes to InfluxDB", pending.len() - 1);
+                    }
+                    pending.clear();
+                }
+                Err(e) => tracing::warn!("Failed to write metrics ({} flushes pending): {}", pending.len(), e),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interval_counter() {
+        let mut counter = IntervalCounter::new([("DISCOVER".to_string(), 100)]);
+        let totals = |discover, request| [("REQUEST".to_string(), request), ("DISCOVER".to_string(), discover)];
+
+        assert_eq!(counter.advance(totals(103, 2)), [("DISCOVER".to_string(), 3), ("REQUEST".to_string(), 2)]);
+        assert_eq!(counter.advance(totals(103, 5)), [("DISCOVER".to_string(), 0), ("REQUEST".to_string(), 3)]);
+    }
+
+    #[test]
+    fn test_encode_line_protocol() {
+        let config = InfluxConfig {
+            url: "http://localhost:8086/api/v2/write?org=home&bucket=dhcp".to_string(),
+            token: None,
+            username: None,
+            password: None,
+            measurement_prefix: "dhcpmon".to_string(),
+            tags: vec![("site".to_string(), "home lab".to_string())],
+            flush_interval: Duration::from_secs(60),
+            timeout: Duration::from_secs(10),
+        };
+        let counts = IntervalCounts {
+            requests: vec![("DISCOVER".to_string(), 4), ("".to_string(), 1)],
+            new_devices: 2,
+            probes: vec![(("smb", "identified"), 1)],
+        };
+
+        assert_eq!(
+            encode(&config, &counts, 1_700_000_000_000_000_000),
+            "dhcpmon_requests,site=home\\ lab,type=DISCOVER count=4i 1700000000000000000\n\
+             dhcpmon_requests,site=home\\ lab count=1i 1700000000000000000\n\
+             dhcpmon_new_devices,site=home\\ lab count=2i 1700000000000000000\n\
+             dhcpmon_probes,site=home\\ lab,probe=smb,outcome=identified count=1i 1700000000000000000\n"
+        );
+        assert_eq!(escape("a=b,c d", true), "a\\=b\\,c\\ d");
+        assert_eq!(escape("a=b", false), "a=b");
+    }
+}
@@ -0,0 +1,262 @@
+use serde::Serialize;
+
+/// DHCP magic cookie (RFC 2131 section 3)
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+/// Only the broadcast bit of the flags field is defined; the rest MUST be zero
+const BROADCAST_FLAG: u16 = 0x8000;
+
+/// An RFC conformance problem found in a client packet
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LintIssue {
+    /// Stable identifier used for aggregation (e.g. "missing_end_option")
+    pub code: &'static str,
+    pub detail: String,
+}
+
+fn issue(code: &'static str, detail: impl Into<String>) -> LintIssue {
+    LintIssue {
+        code,
+        detail: detail.into(),
+    }
+}
+
+/// Why an option's length is wrong, or None if it is acceptable
+fn option_length_problem(code: u8, data: &[u8]) -> Option<String> {
+    let len = data.len();
+    let expected = match code {
+        // Fixed-length options
+        1 | 2 | 16 | 24 | 28 | 32 | 35 | 38 | 50 | 51 | 54 | 58 | 59 => (len != 4).then_some("4"),
+        19 | 20 | 23 | 27 | 29 | 30 | 31 | 34 | 36 | 37 | 39 | 46 | 52 | 53 | 116 => (len != 1).then_some("1"),
+        13 | 22 | 26 | 57 => (len != 2).then_some("2"),
+        94 => (len != 3).then_some("3"),
+        80 => (len != 0).then_some("0"),
+        // Lists of IPv4 addresses, and of address pairs
+        3 | 4 | 5 | 6 | 7 | 8 | 9 | 10 | 11 | 41 | 42 | 44 | 45 | 48 | 49 | 65 | 69..=76 => {
+            (len == 0 || !len.is_multiple_of(4)).then_some("a multiple of 4")
+        }
+        21 | 33 => (len == 0 || !len.is_multiple_of(8)).then_some("a multiple of 8"),
+        // Lists of 16-bit architecture types
+        93 => (len == 0 || !len.is_multiple_of(2)).then_some("a multiple of 2"),
+        // Strings and lists that must not be empty
+        12 | 15 | 55 | 60 | 77 => (len == 0).then_some("at least 1"),
+        // Hardware type plus identifier
+        61 => (len < 2).then_some("at least 2"),
+        // Type 0 is followed by a 16-byte UUID
+        97 => (data.first() == Some(&0) && len != 17).then_some("17"),
+        _ => None,
+    }?;
+    Some(format!("option {} length {} (expected {})", code, len, expected))
+}
+
+/// Check a client packet against RFC 2131/2132 and return every problem found
+pub fn lint_packet(data: &[u8]) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    if data.len() < 236 {
+        issues.push(issue("truncated_packet", format!("{} bytes, BOOTP header needs 236", data.len())));
+        return issues;
+    }
+
+    if data[0] != 1 {
+        issues.push(issue("not_bootrequest", format!("op {} from a client", data[0])));
+    }
+    if data[2] > 16 {
+        issues.push(issue("bad_hlen", format!("hlen {} exceeds the 16-byte chaddr field", data[2])));
+    }
+    if data[2] == 0 {
+        issues.push(issue("zero_hlen", "hlen 0: no client hardware address"));
+    }
+    let flags = u16::from_be_bytes([data[10], data[11]]);
+    if flags & !BROADCAST_FLAG != 0 {
+        issues.push(issue("reserved_flags_set", format!("flags 0x{:04x}", flags)));
+    }
+    let ciaddr_set = data[12..16] != [0, 0, 0, 0];
+
+    if data.len() < 240 || data[236..240] != MAGIC_COOKIE {
+        issues.push(issue("missing_magic_cookie", "no DHCP magic cookie after the BOOTP header"));
+        return issues;
+    }
+
+    // Option codes seen, with the length of the latest occurrence
+    let mut present: Vec<(u8, usize)> = Vec::new();
+    let mut message_type = None;
+    let mut max_message_size = None;
+    let mut end_found = false;
+    let mut i = 240;
+    while i < data.len() {
+        let code = data[i];
+        if code == 255 {
+            end_found = true;
+            break;
+        }
+        if code == 0 {
+            i += 1;
+            continue;
+        }
+        if i + 1 >= data.len() || i + 2 + data[i + 1] as usize > data.len() {
+            issues.push(issue("truncated_option", format!("option {} runs past the end of the packet", code)));
+            break;
+        }
+
+        let value = &data[i + 2..i + 2 + data[i + 1] as usize];
+        if let Some(problem) = option_length_problem(code, value) {
+            issues.push(issue("bad_option_length", problem));
+        }
+        match (code, value) {
+            (53, [t]) => message_type = Some(*t),
+            (57, [hi, lo]) => max_message_size = Some(u16::from_be_bytes([*hi, *lo])),
+            _ => {}
+        }
+        match present.iter_mut().find(|(seen, _)| *seen == code) {
+            // Long options are split into pieces (RFC 3396), all but the last of them full
+            Some((_, last_len)) => {
+                if *last_len < 255 {
+                    issues.push(issue("duplicate_option", format!("option {} appears more than once", code)));
+                }
+                *last_len = value.len();
+            }
+            None => present.push((code, value.len())),
+        }
+        i += 2 + value.len();
+    }
+
+    if !end_found {
+        issues.push(issue("missing_end_option", "options are not terminated by option 255"));
+    }
+    if max_message_size.is_some_and(|size| size < 576) {
+        issues.push(issue(
+            "max_message_size_too_small",
+            format!("option 57 is {}, minimum is 576", max_message_size.unwrap_or_default()),
+        ));
+    }
+
+    let has = |code: u8| present.iter().any(|(seen, _)| *seen == code);
+    let broadcast = flags & BROADCAST_FLAG != 0;
+    match message_type {
+        None if !has(53) => issues.push(issue("missing_message_type", "no option 53")),
+        None => {}
+        // DISCOVER
+        Some(1) => {
+            if ciaddr_set {
+                issues.push(issue("ciaddr_in_discover", "ciaddr MUST be zero in DHCPDISCOVER"));
+            }
+            if has(54) {
+                issues.push(issue("server_id_in_discover", "option 54 MUST NOT appear in DHCPDISCOVER"));
+            }
+        }
+        // REQUEST
+        Some(3) => {
+            if ciaddr_set && has(50) {
+                issues.push(issue("requested_ip_with_ciaddr", "option 50 MUST NOT be sent while renewing (ciaddr set)"));
+            }
+            if ciaddr_set && broadcast {
+                issues.push(issue("broadcast_with_ciaddr", "broadcast flag set by a client that already has an address"));
+            }
+        }
+        // DECLINE
+        Some(4) => {
+            if !has(50) {
+                issues.push(issue("missing_requested_ip", "DHCPDECLINE MUST carry option 50"));
+            }
+            if !has(54) {
+                issues.push(issue("missing_server_id", "DHCPDECLINE MUST carry option 54"));
+            }
+        }
+        // RELEASE
+        Some(7) => {
+            if !has(54) {
+                issues.push(issue("missing_server_id", "DHCPRELEASE MUST carry option 54"));
+            }
+            if broadcast {
+                issues.push(issue("broadcast_with_ciaddr", "broadcast flag set on DHCPRELEASE"));
+            }
+        }
+        // INFORM
+        Some(8) => {
+            if has(50) {
+                issues.push(issue("requested_ip_in_inform", "option 50 MUST NOT appear in DHCPINFORM"));
+            }
+            if broadcast {
+                issues.push(issue("broadcast_with_ciaddr", "broadcast flag set on DHCPINFORM"));
+            }
+        }
+        Some(other) => issues.push(issue("unexpected_message_type", format!("client sent message type {}", other))),
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(flags: u16, ciaddr: [u8; 4], options: &[u8]) -> Vec<u8> {
+        let mut data = vec![0u8; 236];
+        data[0] = 1;
+        data[1] = 1;
+        data[2] = 6;
+        data[10..12].copy_from_slice(&flags.to_be_bytes());
+        data[12..16].copy_from_slice(&ciaddr);
+        data.extend_from_slice(&MAGIC_COOKIE);
+        data.extend_from_slice(options);
+        data
+    }
+
+    fn codes(data: &[u8]) -> Vec<&'static str> {
+        lint_packet(data).into_iter().map(|i| i.code).collect()
+    }
+
+    #[test]
+    fn test_clean_discover() {
+        let data = packet(BROADCAST_FLAG, [0; 4], &[53, 1, 1, 55, 3, 1, 3, 6, 57, 2, 0x05, 0xdc, 255]);
+        assert!(lint_packet(&data).is_empty());
+    }
+
+    #[test]
+    fn test_structural_problems() {
+        // No option 53, no end option, reserved flag bit
+        assert_eq!(
+            codes(&packet(0x0001, [0; 4], &[55, 2, 1, 3])),
+            vec!["reserved_flags_set", "missing_end_option", "missing_message_type"]
+        );
+
+        // Option 50 with 3 bytes, then an option running off the end
+        let issues = lint_packet(&packet(0, [0; 4], &[53, 1, 3, 50, 3, 10, 0, 0, 12, 9, b'a']));
+        assert_eq!(issues[0].code, "bad_option_length");
+        assert_eq!(issues[0].detail, "option 50 length 3 (expected 4)");
+        assert_eq!(issues[1].code, "truncated_option");
+
+        assert_eq!(codes(&[0u8; 240]), vec!["not_bootrequest", "zero_hlen", "missing_magic_cookie"]);
+    }
+
+    #[test]
+    fn test_header_and_repeated_options() {
+        let mut data = packet(0, [0; 4], &[53, 1, 1, 12, 2, b'p', b'c', 12, 2, b'p', b'c', 1, 3, 255, 255, 0, 255]);
+        data[2] = 0;
+        assert_eq!(codes(&data), vec!["zero_hlen", "duplicate_option", "bad_option_length"]);
+
+        // A long option split over full pieces is one option, not a duplicate
+        let mut options = vec![53, 1, 1, 43, 255];
+        options.extend([0u8; 255]);
+        options.extend([43, 10]);
+        options.extend([0u8; 10]);
+        options.push(255);
+        assert!(lint_packet(&packet(0, [0; 4], &options)).is_empty());
+
+        let data = packet(BROADCAST_FLAG, [10, 0, 0, 5], &[53, 1, 8, 255]);
+        assert_eq!(codes(&data), vec!["broadcast_with_ciaddr"]);
+    }
+
+    #[test]
+    fn test_message_rules() {
+        // Renewing REQUEST with option 50 and the broadcast flag
+        let data = packet(BROADCAST_FLAG, [10, 0, 0, 5], &[53, 1, 3, 50, 4, 10, 0, 0, 5, 255]);
+        assert_eq!(codes(&data), vec!["requested_ip_with_ciaddr", "broadcast_with_ciaddr"]);
+
+        let data = packet(0, [0; 4], &[53, 1, 4, 255]);
+        assert_eq!(codes(&data), vec!["missing_requested_ip", "missing_server_id"]);
+
+        let data = packet(0, [0; 4], &[53, 1, 5, 255]);
+        assert_eq!(codes(&data), vec!["unexpected_message_type"]);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::dhcp::DhcpRequest;
+use anyhow::Result;
+use std::io::Write;
+
+/// How requests are written to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleFormat {
+    Off,
+    /// One JSON object per line, for piping to other tools
+    Ndjson,
+    /// Indented JSON, for watching by eye
+    Pretty,
+}
+
+impl ConsoleFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "off" | "none" => Some(Self::Off),
+            "ndjson" | "jsonl" => Some(Self::Ndjson),
+            "pretty" => Some(Self::Pretty),
+            _ => None,
+        }
+    }
+}
+
+/// Writes an event for each request carrying one of the selected options
+pub struct ConsoleSink {
+    format: ConsoleFormat,
+    options: Vec<u8>,
+}
+
+impl ConsoleSink {
+    pub fn new(format: ConsoleFormat, options: Vec<u8>) -> Self {
+        Self { format, options }
+    }
+
+    pub fn send(&self, request: &DhcpRequest) -> Result<()> {
+        let Some(event) = encode_event(request, &self.options) else {
+            return Ok(());
+        };
+        let line = match self.format {
+            ConsoleFormat::Off => return Ok(()),
+            ConsoleFormat::Ndjson => serde_json::to_string(&event)?,
+            ConsoleFormat::Pretty => serde_json::to_string_pretty(&event)?,
+        };
+        writeln!(std::io::stdout().lock(), "{}", line)?;
+        Ok(())
+    }
+}
+
+/// The request's selected options, raw and decoded; None when it has none of them
+/// and no ciaddr
+pub fn encode_event(request: &DhcpRequest, options: &[u8]) -> Option<serde_json::Value> {
+    let ciaddr = request
+        .assigned_ip
+        .as_ref()
+        .filter(|(_, source)| *source == "ciaddr")
+        .map(|(ip, _)| ip);
+    let selected: Vec<_> = request
+        .raw_options
+        .iter()
+        .filter(|option| options.contains(&option.code))
+        .collect();
+    if selected.is_empty() && ciaddr.is_none() {
+        return None;
+    }
+
+    let mut event = serde_json::json!({
+        "mac_address": request.mac_address,
+        "source_ip": request.source_ip,
+        "timestamp": request.timestamp,
+        "message_type": request.message_type,
+        "xid": request.xid,
+    });
+    if let Some(ciaddr) = ciaddr {
+        event["ciaddr"] = serde_json::json!(ciaddr);
+    }
+
+    for option in selected {
+        let code = option.code;
+        event[format!("option_{}", code)] = serde_json::json!(option.data);
+        match code {
+            12 => event["option_12_hostname"] = serde_json::json!(request.hostname),
+            55 => event["option_55_csv"] = serde_json::json!(request.fingerprint),
+            60 => event["option_60_string"] = serde_json::json!(String::from_utf8_lossy(&option.data)),
+            81 => {
+                if let Some(fqdn) = &request.client_fqdn {
+                    event["option_81_flags"] = serde_json::json!(fqdn.flag_bits);
+                    event["option_81_fqdn"] = serde_json::json!(fqdn.name);
+                    event["option_81_ddns"] = serde_json::json!(fqdn.ddns);
+                }
+            }
+            _ => event[format!("option_{}_value", code)] = crate::options_catalog::decode(option).value,
+        }
+    }
+    Some(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dhcp::DhcpOption;
+
+    #[test]
+    fn test_encode_selected_options() {
+        let request = DhcpRequest {
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            message_type: "REQUEST".to_string(),
+            fingerprint: "1,3,6".to_string(),
+            hostname: Some("pc".to_string()),
+            raw_options: vec![
+                DhcpOption { code: 12, data: b"pc".to_vec() },
+                DhcpOption { code: 51, data: vec![0, 1, 81, 128] },
+                DhcpOption { code: 55, data: vec![1, 3, 6] },
+            ],
+            ..Default::default()
+        };
+
+        let event = encode_event(&request, &[12, 51]).unwrap();
+        assert_eq!(event["option_12_hostname"], "pc");
+        assert_eq!(event["option_51_value"], 86400);
+        assert!(event.get("option_55").is_none());
+        assert!(event.get("ciaddr").is_none());
+
+        // Nothing selected is present and there is no ciaddr
+        assert_eq!(encode_event(&request, &[60, 81]), None);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::dhcp::DhcpRequest;
+use anyhow::Result;
+use std::os::unix::net::UnixDatagram;
+
+/// journald's native protocol socket
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Append one field in journald's native export format
+fn push_field(buf: &mut Vec<u8>, key: &str, value: &str) {
+    buf.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        // Binary-safe form: key, newline, little-endian length, value
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(b'\n');
+}
+
+/// Encode a request as a journal entry with DHCP_* fields
+pub fn encode_entry(request: &DhcpRequest, identifier: &str) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let message = format!(
+        "{} from {} ({})",
+        request.message_type, request.mac_address, request.source_ip
+    );
+
+    push_field(&mut buf, "MESSAGE", &message);
+    push_field(&mut buf, "PRIORITY", "6");
+    push_field(&mut buf, "SYSLOG_IDENTIFIER", identifier);
+    push_field(&mut buf, "DHCP_MAC", &request.mac_address);
+    push_field(&mut buf, "DHCP_MESSAGE_TYPE", &request.message_type);
+    push_field(&mut buf, "DHCP_SOURCE_IP", &request.source_ip);
+    push_field(&mut buf, "DHCP_XID", &request.xid);
+    push_field(&mut buf, "DHCP_FINGERPRINT", &request.fingerprint);
+
+    let optional = [
+        ("DHCP_VENDOR_CLASS", request.vendor_class.clone()),
+        ("DHCP_OS_NAME", request.os_name.clone()),
+        ("DHCP_DEVICE_CLASS", request.device_class.clone()),
+        ("DHCP_DETECTION_METHOD", request.detection_method.clone()),
+        ("DHCP_VLAN_ID", request.vlan_id.map(|v| v.to_string())),
+        ("DHCP_HOSTNAME", request.hostname.clone()),
+        ("DHCP_REQUESTED_IP", request.requested_ip.clone()),
+        ("DHCP_SERVER_ID", request.server_id.clone()),
+        ("DHCP_USER_CLASS", request.user_class.clone()),
+        ("DHCP_CLIENT_ARCH", request.client_arch.map(|a| a.to_string())),
+        ("DHCP_CLIENT_UUID", request.client_uuid.clone()),
+    ];
+    for (key, value) in optional {
+        if let Some(value) = value {
+            push_field(&mut buf, key, &value);
+        }
+    }
+
+    buf
+}
+
+/// Sends requests to the local systemd journal
+pub struct JournaldSink {
+    socket: UnixDatagram,
+    identifier: String,
+}
+
+impl JournaldSink {
+    pub fn connect(identifier: &str) -> Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(JOURNAL_SOCKET)?;
+        Ok(Self {
+            socket,
+            identifier: identifier.to_string(),
+        })
+    }
+
+    pub fn send(&self, request: &DhcpRequest) -> Result<()> {
+        self.socket.send(&encode_entry(request, &self.identifier))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_entry() {
+        let request = DhcpRequest {
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            message_type: "REQUEST".to_string(),
+            os_name: Some("Windows 10/11".to_string()),
+            vendor_class: Some("line1\nline2".to_string()),
+            ..Default::default()
+        };
+
+        let entry = encode_entry(&request, "ks-dhcpmon");
+        let text = String::from_utf8_lossy(&entry);
+        assert!(text.starts_with("MESSAGE=REQUEST from aa:bb:cc:dd:ee:ff ()\n"));
+        assert!(text.contains("SYSLOG_IDENTIFIER=ks-dhcpmon\n"));
+        assert!(text.contains("DHCP_OS_NAME=Windows 10/11\n"));
+        assert!(!text.contains("DHCP_VLAN_ID"));
+
+        // Multi-line values use the length-prefixed form
+        let mut expected = b"DHCP_VENDOR_CLASS\n".to_vec();
+        expected.extend_from_slice(&11u64.to_le_bytes());
+        expected.extend_from_slice(b"line1\nline2\n");
+        assert!(entry.windows(expected.len()).any(|w| w == expected.as_slice()));
+    }
+}
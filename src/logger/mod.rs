@@ -0,0 +1,435 @@
+pub mod console;
+pub mod journald;
+pub mod syslog;
+
+use crate::dhcp::DhcpRequest;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::thread::JoinHandle;
+use console::ConsoleSink;
+use journald::JournaldSink;
+use syslog::{SyslogConfig, SyslogSender};
+
+/// Time-based rotation period
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationPeriod {
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl RotationPeriod {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "never" | "none" => Some(Self::Never),
+            "hourly" => Some(Self::Hourly),
+            "daily" => Some(Self::Daily),
+            _ => None,
+        }
+    }
+
+    /// Identifier of the period a point in time falls into
+    fn bucket(&self, time: &DateTime<Local>) -> String {
+        match self {
+            Self::Never => String::new(),
+            Self::Hourly => time.format("%Y%m%d%H").to_string(),
+            Self::Daily => time.format("%Y%m%d").to_string(),
+        }
+    }
+}
+
+/// Rotation settings for the request log file
+#[derive(Debug, Clone)]
+pub struct RotationConfig {
+    /// Rotate once the file reaches this size (None = no size limit)
+    pub max_bytes: Option<u64>,
+    pub period: RotationPeriod,
+    /// Number of rotated files to keep
+    pub max_files: usize,
+    /// Gzip rotated files
+    pub compress: bool,
+}
+
+impl Default for RotationConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: None,
+            period: RotationPeriod::Never,
+            max_files: 0,
+            compress: false,
+        }
+    }
+}
+
+struct LogFile {
+    file: File,
+    size: u64,
+    period: String,
+}
+
+/// An append-only line file with size/time based rotation
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: RwLock<RotationConfig>,
+    file: Mutex<LogFile>,
+}
+
+impl RotatingFile {
+    pub fn open(path: &str, rotation: RotationConfig) -> Result<Self> {
+        let path = PathBuf::from(path);
+        let file = Self::open_file(&path, &rotation)?;
+
+        Ok(Self {
+            path,
+            rotation: RwLock::new(rotation),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Apply new limits from the next line on, pruning rotated files beyond the new
+    /// max_files now. A period change starts counting from the current one, so it
+    /// doesn't rotate the file straight away.
+    pub fn set_rotation(&self, rotation: RotationConfig) {
+        let mut file = self.file.lock().unwrap();
+        file.period = rotation.period.bucket(&Local::now());
+        prune_rotated(&self.path, rotation.max_files);
+        *self.rotation.write().unwrap() = rotation;
+    }
+
+    fn open_file(path: &Path, rotation: &RotationConfig) -> io::Result<LogFile> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(LogFile {
+            file,
+            size,
+            period: rotation.period.bucket(&Local::now()),
+        })
+    }
+
+    /// Append a line, rotating first if it would exceed the limits
+    pub fn write_line(&self, line: &str) -> Result<()> {
+        let mut file = self.file.lock().unwrap();
+        let rotation = self.rotation.read().unwrap().clone();
+        let len = line.len() as u64 + 1;
+
+        let now = Local::now();
+        let period_changed = rotation.period.bucket(&now) != file.period;
+        let size_exceeded = rotation
+            .max_bytes
+            .is_some_and(|max| file.size > 0 && file.size + len > max);
+
+        if period_changed || size_exceeded {
+            // Compression runs in the background; the handle is only needed by tests
+            let _ = self.rotate(&mut file, &now, &rotation)?;
+        }
+
+        writeln!(file.file, "{}", line)?;
+        file.file.flush()?;
+        file.size += len;
+        Ok(())
+    }
+
+    /// Move the current file aside, reopen a fresh one and prune old files
+    fn rotate(
+        &self,
+        current: &mut LogFile,
+        now: &DateTime<Local>,
+        rotation: &RotationConfig,
+    ) -> Result<Option<JoinHandle<()>>> {
+        current.file.flush()?;
+
+        let rotated = self.rotated_path(now);
+        fs::rename(&self.path, &rotated)?;
+        *current = Self::open_file(&self.path, rotation)?;
+        tracing::info!("Rotated {} to {}", self.path.display(), rotated.display());
+
+        let handle = if rotation.compress {
+            let max_files = rotation.max_files;
+            let base = self.path.clone();
+            Some(std::thread::spawn(move || {
+                if let Err(e) = compress_file(&rotated) {
+                    tracing::warn!("Failed to compress {}: {}", rotated.display(), e);
+                }
+                prune_rotated(&base, max_files);
+            }))
+        } else {
+            prune_rotated(&self.path, rotation.max_files);
+            None
+        };
+
+        Ok(handle)
+    }
+
+    /// Rotated files are named `<file>.<timestamp>` so they sort chronologically
+    fn rotated_path(&self, now: &DateTime<Local>) -> PathBuf {
+        let base = format!("{}.{}", self.path.display(), now.format("%Y%m%dT%H%M%S%.3f"));
+        let mut candidate = PathBuf::from(&base);
+        let mut n = 1;
+        while candidate.exists() || PathBuf::from(format!("{}.gz", candidate.display())).exists() {
+            candidate = PathBuf::from(format!("{}-{}", base, n));
+            n += 1;
+        }
+        candidate
+    }
+}
+
+/// Where logged requests go
+enum Backend {
+    Disabled,
+    File(RotatingFile),
+    Syslog(SyslogSender),
+    Journald(JournaldSink),
+}
+
+pub struct RequestLogger {
+    backend: Backend,
+    /// Events on stdout, alongside whichever backend is configured
+    console: Option<ConsoleSink>,
+}
+
+impl RequestLogger {
+    pub fn new(path: &str, rotation: RotationConfig) -> Result<Self> {
+        Ok(Self {
+            backend: Backend::File(RotatingFile::open(path, rotation)?),
+            console: None,
+        })
+    }
+
+    /// A logger that discards everything (the database is the source of truth)
+    pub fn disabled() -> Self {
+        Self {
+            backend: Backend::Disabled,
+            console: None,
+        }
+    }
+
+    /// Send each request as an RFC 5424 message to a syslog collector
+    pub fn syslog(config: SyslogConfig) -> Self {
+        Self {
+            backend: Backend::Syslog(SyslogSender::spawn(config)),
+            console: None,
+        }
+    }
+
+    /// Write each request to the local journal with structured DHCP_* fields
+    pub fn journald(identifier: &str) -> Result<Self> {
+        Ok(Self {
+            backend: Backend::Journald(JournaldSink::connect(identifier)?),
+            console: None,
+        })
+    }
+
+    pub fn with_console(mut self, console: Option<ConsoleSink>) -> Self {
+        self.console = console;
+        self
+    }
+
+    /// Whether requests go to a rotated file
+    pub fn writes_file(&self) -> bool {
+        matches!(self.backend, Backend::File(_))
+    }
+
+    /// Change how the request log file rotates (no-op for the other backends)
+    pub fn set_rotation(&self, rotation: RotationConfig) {
+        if let Backend::File(file) = &self.backend {
+            file.set_rotation(rotation);
+        }
+    }
+
+    pub fn log(&self, request: &DhcpRequest) -> Result<()> {
+        let logged = match &self.backend {
+            Backend::Disabled => Ok(()),
+            Backend::File(file) => file.write_line(&serde_json::to_string(request)?),
+            Backend::Syslog(sender) => sender.send(request),
+            Backend::Journald(sink) => sink.send(request),
+        };
+        if let Some(console) = &self.console {
+            console.send(request)?;
+        }
+        logged
+    }
+}
+
+fn compress_file(path: &Path) -> io::Result<()> {
+    let gz_path = PathBuf::from(format!("{}.gz", path.display()));
+    let mut input = File::open(path)?;
+    let mut encoder = GzEncoder::new(File::create(&gz_path)?, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)
+}
+
+/// Delete the oldest rotated files beyond max_files
+fn prune_rotated(base: &Path, max_files: usize) {
+    let (Some(dir), Some(name)) = (base.parent(), base.file_name()) else {
+        return;
+    };
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let prefix = format!("{}.", name.to_string_lossy());
+
+    let mut rotated: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().starts_with(&prefix))
+            .map(|e| e.path())
+            .collect(),
+        Err(_) => return,
+    };
+    rotated.sort();
+
+    let excess = rotated.len().saturating_sub(max_files);
+    for path in rotated.into_iter().take(excess) {
+        if let Err(e) = fs::remove_file(&path) {
+            tracing::warn!("Failed to remove old log {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn temp_dir() -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "ks-dhcpmon-logger-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn files_in(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn request() -> DhcpRequest {
+        DhcpRequest {
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            message_type: "DISCOVER".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_size_rotation_and_pruning() {
+        let dir = temp_dir();
+        let path = dir.join("request.json");
+        let logger = RequestLogger::new(path.to_str().unwrap(), RotationConfig {
+            max_bytes: Some(300),
+            max_files: 2,
+            ..Default::default()
+        })
+        .unwrap();
+
+        for _ in 0..20 {
+            logger.log(&request()).unwrap();
+        }
+
+        let files = files_in(&dir);
+        assert_eq!(files.len(), 3, "{:?}", files);
+        assert!(files.contains(&"request.json".to_string()));
+        assert!(fs::metadata(&path).unwrap().len() <= 300);
+    }
+
+    #[test]
+    fn test_line_longer_than_max_bytes() {
+        let dir = temp_dir();
+        let path = dir.join("request.json");
+        let logger = RequestLogger::new(path.to_str().unwrap(), RotationConfig {
+            max_bytes: Some(10),
+            max_files: 5,
+            ..Default::default()
+        })
+        .unwrap();
+
+        // Lines are never split, so each one gets a file of its own
+        for _ in 0..3 {
+            logger.log(&request()).unwrap();
+        }
+        let line_len = serde_json::to_string(&request()).unwrap().len() as u64 + 1;
+        assert_eq!(files_in(&dir).len(), 3);
+        assert_eq!(fs::metadata(&path).unwrap().len(), line_len);
+    }
+
+    #[test]
+    fn test_set_rotation_prunes_to_new_limit() {
+        let dir = temp_dir();
+        let path = dir.join("request.json");
+        let logger = RequestLogger::new(path.to_str().unwrap(), RotationConfig {
+            max_bytes: Some(1000),
+            max_files: 5,
+            ..Default::default()
+        })
+        .unwrap();
+        for _ in 0..20 {
+            logger.log(&request()).unwrap();
+        }
+        assert!(files_in(&dir).len() > 2);
+
+        logger.set_rotation(RotationConfig {
+            max_bytes: Some(1000),
+            max_files: 1,
+            ..Default::default()
+        });
+        assert_eq!(files_in(&dir).len(), 2);
+    }
+
+    #[test]
+    fn test_rotation_with_compression() {
+        let dir = temp_dir();
+        let path = dir.join("request.json");
+        let logger = RequestLogger::new(path.to_str().unwrap(), RotationConfig {
+            max_files: 5,
+            compress: true,
+            ..Default::default()
+        })
+        .unwrap();
+        logger.log(&request()).unwrap();
+
+        let Backend::File(file) = &logger.backend else {
+            panic!("expected file backend");
+        };
+        let rotation = file.rotation.read().unwrap().clone();
+        let handle = file.rotate(&mut file.file.lock().unwrap(), &Local::now(), &rotation).unwrap();
+        handle.unwrap().join().unwrap();
+
+        let files = files_in(&dir);
+        assert_eq!(files.len(), 2, "{:?}", files);
+        assert!(files.iter().any(|f| f.ends_with(".gz")));
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_disabled_logger_writes_nothing() {
+        let logger = RequestLogger::disabled();
+        assert!(logger.log(&request()).is_ok());
+    }
+
+    #[test]
+    fn test_period_buckets() {
+        let time = Local::now();
+        assert_eq!(RotationPeriod::Never.bucket(&time), "");
+        assert_eq!(RotationPeriod::Daily.bucket(&time).len(), 8);
+        assert_eq!(RotationPeriod::Hourly.bucket(&time).len(), 10);
+        assert_eq!(RotationPeriod::from_name("Daily"), Some(RotationPeriod::Daily));
+        assert_eq!(RotationPeriod::from_name("weekly"), None);
+    }
+}
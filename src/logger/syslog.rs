@@ -0,0 +1,317 @@
+use crate::dhcp::DhcpRequest;
+use anyhow::{anyhow, Result};
+use chrono::{SecondsFormat, Utc};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+/// Messages buffered while the collector is slow or unreachable
+const QUEUE_SIZE: usize = 4096;
+
+/// Wait before retrying a failed connection; messages are dropped meanwhile
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Severity "informational"
+const SEVERITY_INFO: u8 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyslogProtocol {
+    Udp,
+    Tcp,
+    Tls,
+}
+
+impl SyslogProtocol {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "udp" => Some(Self::Udp),
+            "tcp" => Some(Self::Tcp),
+            "tls" => Some(Self::Tls),
+            _ => None,
+        }
+    }
+}
+
+/// Map a facility name to its numeric code
+pub fn facility_code(name: &str) -> Option<u8> {
+    let code = match name.to_ascii_lowercase().as_str() {
+        "user" => 1,
+        "daemon" => 3,
+        "auth" => 4,
+        "local0" => 16,
+        "local1" => 17,
+        "local2" => 18,
+        "local3" => 19,
+        "local4" => 20,
+        "local5" => 21,
+        "local6" => 22,
+        "local7" => 23,
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Where and how to send RFC 5424 messages
+#[derive(Debug, Clone)]
+pub struct SyslogConfig {
+    /// Collector address, "host:port"
+    pub address: String,
+    pub protocol: SyslogProtocol,
+    pub facility: u8,
+    pub app_name: String,
+    pub hostname: String,
+    /// IANA private enterprise number naming the structured-data element
+    /// (`dhcp@<number>`); without one the fields go in the message text
+    pub enterprise_number: Option<u32>,
+}
+
+/// Escape a structured-data parameter value (RFC 5424 section 6.3.3)
+fn escape_param(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Header fields must be printable ASCII without spaces, or "-"
+fn header_field(value: &str, max_len: usize) -> String {
+    let field: String = value
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(max_len)
+        .collect();
+    if field.is_empty() { "-".to_string() } else { field }
+}
+
+/// Format a request as an RFC 5424 message, with the request fields as structured
+/// data when an enterprise number is configured
+pub fn format_message(request: &DhcpRequest, config: &SyslogConfig) -> String {
+    let pri = config.facility as u16 * 8 + SEVERITY_INFO as u16;
+    let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+
+    let mut params = vec![
+        ("mac", request.mac_address.clone()),
+        ("type", request.message_type.clone()),
+        ("src", request.source_ip.clone()),
+        ("xid", request.xid.clone()),
+        ("fingerprint", request.fingerprint.clone()),
+    ];
+    let optional = [
+        ("vendor_class", request.vendor_class.clone()),
+        ("os", request.os_name.clone()),
+        ("device_class", request.device_class.clone()),
+        ("detection", request.detection_method.clone()),
+        ("vlan", request.vlan_id.map(|v| v.to_string())),
+        ("hostname", request.hostname.clone()),
+        ("requested_ip", request.requested_ip.clone()),
+        ("server_id", request.server_id.clone()),
+        ("user_class", request.user_class.clone()),
+        ("client_arch", request.client_arch.map(|a| a.to_string())),
+        ("client_uuid", request.client_uuid.clone()),
+    ];
+    params.extend(optional.into_iter().filter_map(|(k, v)| v.map(|v| (k, v))));
+
+    let fields: String = params
+        .iter()
+        .map(|(k, v)| format!(" {}=\"{}\"", k, escape_param(v)))
+        .collect();
+
+    let summary = format!(
+        "{} from {} ({}){}",
+        request.message_type,
+        request.mac_address,
+        request.source_ip,
+        request
+            .os_name
+            .as_ref()
+            .map(|os| format!(" os={}", os))
+            .unwrap_or_default()
+    );
+    // SD-IDs without an "@" are reserved for IANA, so there is none to use without a number
+    let (structured, summary) = match config.enterprise_number {
+        Some(number) => (format!("[dhcp@{}{}]", number, fields), summary),
+        None => ("-".to_string(), format!("{}{}", summary, fields)),
+    };
+
+    format!(
+        "<{}>1 {} {} {} {} {} {} {}",
+        pri,
+        timestamp,
+        header_field(&config.hostname, 255),
+        header_field(&config.app_name, 48),
+        std::process::id(),
+        header_field(&request.message_type, 32),
+        structured,
+        summary
+    )
+}
+
+/// Queue feeding the background transport task
+pub struct SyslogSender {
+    config: SyslogConfig,
+    tx: mpsc::Sender<String>,
+}
+
+impl SyslogSender {
+    /// Start the transport task; must be called from within the tokio runtime
+    pub fn spawn(config: SyslogConfig) -> Self {
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(run_transport(config.clone(), rx));
+        Self { config, tx }
+    }
+
+    pub fn send(&self, request: &DhcpRequest) -> Result<()> {
+        self.tx
+            .try_send(format_message(request, &self.config))
+            .map_err(|_| anyhow!("syslog queue full, message dropped"))
+    }
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Stream(Box<dyn AsyncWrite + Unpin + Send>),
+}
+
+impl Connection {
+    async fn open(config: &SyslogConfig) -> Result<Self> {
+        match config.protocol {
+            SyslogProtocol::Udp => {
+                let socket = UdpSocket::bind("0.0.0.0:0").await?;
+                socket.connect(&config.address).await?;
+                Ok(Self::Udp(socket))
+            }
+            SyslogProtocol::Tcp => Ok(Self::Stream(Box::new(TcpStream::connect(&config.address).await?))),
+            SyslogProtocol::Tls => {
+                let host = config
+                    .address
+                    .rsplit_once(':')
+                    .map(|(host, _)| host.trim_matches(|c| c == '[' || c == ']'))
+                    .unwrap_or(&config.address);
+                let server_name = ServerName::try_from(host.to_string())?;
+
+                let roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+                let tls_config = ClientConfig::builder()
+                    .with_root_certificates(roots)
+                    .with_no_client_auth();
+                let tcp = TcpStream::connect(&config.address).await?;
+                let tls = TlsConnector::from(Arc::new(tls_config))
+                    .connect(server_name, tcp)
+                    .await?;
+                Ok(Self::Stream(Box::new(tls)))
+            }
+        }
+    }
+
+    async fn send(&mut self, message: &str) -> Result<()> {
+        match self {
+            Self::Udp(socket) => {
+                socket.send(message.as_bytes()).await?;
+            }
+            Self::Stream(stream) => {
+                // Octet-counting framing (RFC 6587 / RFC 5425)
+                let frame = format!("{} {}", message.len(), message);
+                stream.write_all(frame.as_bytes()).await?;
+                stream.flush().await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn run_transport(config: SyslogConfig, mut rx: mpsc::Receiver<String>) {
+    let mut connection: Option<Connection> = None;
+    let mut last_attempt: Option<Instant> = None;
+    let mut dropped: u64 = 0;
+
+    while let Some(message) = rx.recv().await {
+        if connection.is_none() {
+            if last_attempt.is_some_and(|t| t.elapsed() < RECONNECT_DELAY) {
+                dropped += 1;
+                continue;
+            }
+            last_attempt = Some(Instant::now());
+            match Connection::open(&config).await {
+                Ok(c) => {
+                    if dropped > 0 {
+                        tracing::warn!("Syslog collector {} reachable again, {} messages dropped", config.address, dropped);
+                        dropped = 0;
+                    }
+                    connection = Some(c);
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to connect to syslog collector {}: {}", config.address, e);
+                    dropped += 1;
+                    continue;
+                }
+            }
+        }
+
+        if let Some(c) = connection.as_mut() {
+            if let Err(e) = c.send(&message).await {
+                tracing::warn!("Failed to send to syslog collector {}: {}", config.address, e);
+                connection = None;
+                dropped += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> SyslogConfig {
+        SyslogConfig {
+            address: "127.0.0.1:514".to_string(),
+            protocol: SyslogProtocol::Udp,
+            facility: facility_code("local0").unwrap(),
+            app_name: "ks-dhcpmon".to_string(),
+            hostname: "monitor host".to_string(),
+            enterprise_number: Some(99999),
+        }
+    }
+
+    #[test]
+    fn test_format_message() {
+        let request = DhcpRequest {
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            message_type: "DISCOVER".to_string(),
+            source_ip: "10.0.0.5".to_string(),
+            xid: "0x1234".to_string(),
+            fingerprint: "1,3,6".to_string(),
+            vendor_class: Some("MSFT \"5.0\"]".to_string()),
+            ..Default::default()
+        };
+
+        let message = format_message(&request, &config());
+        // local0 (16) * 8 + informational (6)
+        assert!(message.starts_with("<134>1 "));
+        assert!(message.contains(" monitorhost ks-dhcpmon "));
+        assert!(message.contains(" DISCOVER [dhcp@99999 mac=\"aa:bb:cc:dd:ee:ff\" type=\"DISCOVER\""));
+        assert!(message.contains("vendor_class=\"MSFT \\\"5.0\\\"\\]\""));
+        assert!(!message.contains(" os="));
+        assert!(message.ends_with("] DISCOVER from aa:bb:cc:dd:ee:ff (10.0.0.5)"));
+
+        let config = SyslogConfig { enterprise_number: None, ..config() };
+        let message = format_message(&request, &config);
+        assert!(message.contains(" DISCOVER - DISCOVER from aa:bb:cc:dd:ee:ff (10.0.0.5) mac=\"aa:bb:cc:dd:ee:ff\""));
+        assert!(!message.contains('['));
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(facility_code("LOCAL7"), Some(23));
+        assert_eq!(facility_code("kern"), None);
+        assert_eq!(SyslogProtocol::from_name("TLS"), Some(SyslogProtocol::Tls));
+        assert_eq!(SyslogProtocol::from_name("relp"), None);
+    }
+}
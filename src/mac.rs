@@ -0,0 +1,178 @@
+//! Canonical MAC addresses.
+//!
+//! MACs reach the monitor as "AA-BB-CC-DD-EE-FF" from Windows tools, "aabb.ccdd.eeff"
+//! from switches, bare hex from spreadsheets and "aa:bb:cc:dd:ee:ff" from packets.
+//! Everything is stored and compared in the lower-case, colon-separated form so a
+//! lookup never misses because of notation.
+//!
+//! Requests, the device inventory and the database hold that form as text rather
+//! than a `Mac`: the same field carries client identifiers that aren't Ethernet
+//! MACs ("fddi/...", "clientid/..."), which `canonical` lower-cases as they are.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// A 48-bit Ethernet MAC address
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Mac([u8; 6]);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidMac(pub String);
+
+impl fmt::Display for InvalidMac {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid MAC address '{}'", self.0)
+    }
+}
+
+impl std::error::Error for InvalidMac {}
+
+impl Mac {
+    pub fn new(octets: [u8; 6]) -> Self {
+        Self(octets)
+    }
+
+    pub fn octets(&self) -> [u8; 6] {
+        self.0
+    }
+}
+
+impl FromStr for Mac {
+    type Err = InvalidMac;
+
+    /// Accepts colon, dash or dot (Cisco) separated and bare hex, in any case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidMac(s.to_string());
+        let trimmed = s.trim();
+        let groups: Vec<&str> = trimmed.split([':', '-', '.']).collect();
+        // Bare hex, three groups of four or six groups of two
+        let digits: String = match groups.len() {
+            1 => trimmed.to_string(),
+            3 if groups.iter().all(|g| g.len() == 4) => groups.concat(),
+            6 if groups.iter().all(|g| g.len() == 2) => groups.concat(),
+            _ => return Err(invalid()),
+        };
+        if digits.len() != 12 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+
+        let mut octets = [0u8; 6];
+        for (i, octet) in octets.iter_mut().enumerate() {
+            *octet = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(octets))
+    }
+}
+
+impl fmt::Display for Mac {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", a, b, c, d, e, g)
+    }
+}
+
+impl fmt::Debug for Mac {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Mac({})", self)
+    }
+}
+
+impl Serialize for Mac {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Mac {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Canonical form of a client identifier: MACs in any notation become
+/// "aa:bb:cc:dd:ee:ff"; anything else ("fddi/...", "clientid/...") is lower-cased
+pub fn canonical(identifier: &str) -> String {
+    match identifier.parse::<Mac>() {
+        Ok(mac) => mac.to_string(),
+        Err(_) => identifier.trim().to_lowercase(),
+    }
+}
+
+/// Canonical form of a partial MAC used as a substring or prefix filter:
+/// "00-15-5D" and "00155d" both become "00:15:5d"
+pub fn canonical_fragment(fragment: &str) -> String {
+    let fragment = fragment.trim().to_lowercase();
+    let bare_hex = fragment.len() > 2 && fragment.len().is_multiple_of(2) && fragment.chars().all(|c| c.is_ascii_hexdigit());
+    if bare_hex {
+        let pairs: Vec<&str> = (0..fragment.len()).step_by(2).map(|i| &fragment[i..i + 2]).collect();
+        return pairs.join(":");
+    }
+    fragment.replace('-', ":")
+}
+
+/// serde `deserialize_with` for identifier fields stored in canonical form
+pub fn deserialize_canonical<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(canonical(&String::deserialize(deserializer)?))
+}
+
+/// SQL expression giving the canonical form of a MAC column, for normalizing rows
+/// written before MACs were canonicalized (mirrors `canonical`)
+pub fn canonical_sql(column: &str) -> String {
+    let digits = format!("replace(replace(replace(lower({}), ':', ''), '-', ''), '.', '')", column);
+    let pairs: Vec<String> = (0..6).map(|i| format!("substr({}, {}, 2)", digits, i * 2 + 1)).collect();
+    format!(
+        "CASE WHEN length({digits}) = 12 AND {digits} NOT GLOB '*[^0-9a-f]*' \
+         AND (length({column}) = 12 OR length({column}) = 14 OR length({column}) = 17) \
+         THEN {joined} ELSE lower(trim({column})) END",
+        digits = digits,
+        column = column,
+        joined = pairs.join(" || ':' || "),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_notations() {
+        let expected = Mac::new([0xaa, 0xbb, 0xcc, 0x0d, 0xee, 0xff]);
+        for notation in ["aa:bb:cc:0d:ee:ff", "AA-BB-CC-0D-EE-FF", "aabb.cc0d.eeff", "AABBCC0DEEFF", " aa:bb:cc:0d:ee:ff "] {
+            assert_eq!(notation.parse::<Mac>(), Ok(expected), "{}", notation);
+        }
+        assert_eq!(expected.to_string(), "aa:bb:cc:0d:ee:ff");
+
+        for invalid in ["aa:bb:cc:dd:ee", "aa:bb-cc:dd:ee:ff:00", "aa:bb:cc:dd:ee:gg", "a:bb:cc:dd:ee:fff", ""] {
+            assert!(invalid.parse::<Mac>().is_err(), "{}", invalid);
+        }
+
+        let json = serde_json::to_string(&expected).unwrap();
+        assert_eq!(json, "\"aa:bb:cc:0d:ee:ff\"");
+        assert_eq!(serde_json::from_str::<Mac>("\"AA-BB-CC-0D-EE-FF\"").unwrap(), expected);
+    }
+
+    #[test]
+    fn test_canonical_forms() {
+        assert_eq!(canonical("AA-BB-CC-DD-EE-FF"), "aa:bb:cc:dd:ee:ff");
+        assert_eq!(canonical("fddi/00:11:22:33:44:55"), "fddi/00:11:22:33:44:55");
+        assert_eq!(canonical("ClientID/01:02"), "clientid/01:02");
+        assert_eq!(canonical_fragment("00-15-5D"), "00:15:5d");
+        assert_eq!(canonical_fragment("00155D"), "00:15:5d");
+        assert_eq!(canonical_fragment("5d"), "5d");
+    }
+
+    #[tokio::test]
+    async fn test_canonical_sql_matches_canonical() {
+        let pool = crate::db::test_pool().await;
+        for input in ["AA-BB-CC-DD-EE-FF", "aabb.ccdd.eeff", "AABBCCDDEEFF", "aa:bb:cc:dd:ee:ff", "FDDI/00:11", "abc"] {
+            let (normalized,): (String,) = sqlx::query_as(&format!("SELECT {}", canonical_sql("?1")))
+                .bind(input)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            assert_eq!(normalized, canonical(input), "{}", input);
+        }
+    }
+}
@@ -1,29 +1,1208 @@
+mod active_scan;
+mod agent;
+mod alerts;
+mod anomaly;
+mod arp;
+mod capture_filter;
+mod chattiness;
+mod client_id;
+mod cluster;
+mod demo;
 mod dhcp;
+mod dhcp_socket;
+mod detection_sources;
 mod logger;
+mod mac;
+mod new_device;
+mod mqtt;
+mod notify;
 mod web;
 mod db;
+mod dedup;
 mod fingerprint;
+mod fingerprint_update;
+mod lint;
+mod options_catalog;
+mod pcap;
+mod rdns;
+mod receive;
+mod reload;
+mod reverify;
+mod rules;
+mod scripting;
+mod simulate;
+mod frame;
+mod hexdump;
+mod http_probe;
+mod icmp;
+mod influx;
 mod smb;
+mod smb_policy;
+mod systemd;
 mod hybrid_detection;
+mod presence;
+mod probe_identity;
+mod probe_scheduler;
+mod subnets;
+mod sweep;
+mod vendor_options;
+mod windows_hints;
 
-use anyhow::Result;
+#[cfg(test)]
+mod corpus_tests;
+
+use alerts::{AlertConfig, AlertManager};
+use anomaly::{AnomalyConfig, AnomalyDetector};
+use anyhow::{anyhow, Result};
+use capture_filter::CaptureFilter;
+use cluster::{ClusterConfig, LeaderElection};
+use demo::DemoTraffic;
 use dhcp::{DhcpPacket, DhcpRequest};
-use logger::RequestLogger;
+use dhcp_socket::{DhcpSocket, Received};
+use mqtt::{HomeAssistantConfig, MqttConfig};
+use notify::email::{EmailConfig, SmtpSecurity};
+use logger::syslog::{self, SyslogConfig, SyslogProtocol};
+use logger::console::{ConsoleFormat, ConsoleSink};
+use logger::{RequestLogger, RotationConfig, RotationPeriod};
+use fingerprint_update::{FingerprintUpdater, UpdaterConfig};
 use hybrid_detection::{HybridDetector, HybridConfig};
+use probe_identity::ProbeIdentity;
+use probe_scheduler::SchedulerConfig;
+use smb_policy::{ProbeMode, SmbProbePolicy, Subnet};
+use subnets::SubnetMap;
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::net::UdpSocket;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use web::access_log::AccessLogger;
+use web::proxy::ProxyConfig;
+use web::rate_limit::{EndpointLimit, RateLimitConfig, RateLimiter};
+use web::server::Listen;
 use web::state::{AppState, WEB_SERVER_PORT};
 use serde::Deserialize;
 
 const DHCP_SERVER_PORT: u16 = 67;
 const BUFFER_SIZE: usize = 4096;
+/// How often request counters are rolled up into the stats checkpoint
+const STATS_CHECKPOINT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(300);
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 struct Config {
     #[serde(default)]
     detection: DetectionConfig,
+    #[serde(default)]
+    fingerprint_updates: Option<FingerprintUpdateConfig>,
+    #[serde(default)]
+    probe_identity: ProbeIdentityConfig,
+    #[serde(default)]
+    alerts: AlertsConfig,
+    #[serde(default)]
+    anomaly: AnomalySection,
+    #[serde(default)]
+    capture: CaptureConfig,
+    #[serde(default)]
+    subnets: SubnetsConfig,
+    #[serde(default)]
+    receive: ReceiveSection,
+    #[serde(default)]
+    dedup: DedupConfig,
+    #[serde(default)]
+    new_devices: NewDevicesConfig,
+    #[serde(default)]
+    request_log: RequestLogConfig,
+    #[serde(default)]
+    console: ConsoleConfig,
+    #[serde(default)]
+    access_log: AccessLogConfig,
+    #[serde(default)]
+    rate_limits: RateLimitsConfig,
+    #[serde(default)]
+    web: WebConfig,
+    #[serde(default)]
+    arp: ArpConfig,
+    #[serde(default)]
+    presence: PresenceSection,
+    #[serde(default)]
+    active_scan: ActiveScanSection,
+    #[serde(default)]
+    sweep: SweepSection,
+    #[serde(default)]
+    reverse_dns: ReverseDnsConfig,
+    #[serde(default)]
+    grpc: GrpcConfig,
+    #[serde(default)]
+    scripting: Option<ScriptingConfig>,
+    #[serde(default)]
+    agent: Option<AgentSection>,
+    #[serde(default)]
+    ingest: IngestConfig,
+    #[serde(default)]
+    mqtt: Option<MqttSinkConfig>,
+    #[serde(default)]
+    influx: Option<InfluxSinkConfig>,
+    #[serde(default)]
+    notify: NotifySection,
+    #[serde(default)]
+    database: DatabaseConfig,
+    #[serde(default)]
+    cluster: Option<ClusterSection>,
+    #[serde(default)]
+    logging: LoggingConfig,
+}
+
+/// The monitor's own application logs
+#[derive(Debug, Deserialize)]
+struct LoggingConfig {
+    /// "text" or "json" (one object per line)
+    #[serde(default = "default_log_format")]
+    format: String,
+    /// Default filter when RUST_LOG isn't set, e.g. "info,ks_dhcpmon::smb=debug"
+    #[serde(default = "default_log_level")]
+    level: String,
+    /// Append logs to this file instead of the console
+    #[serde(default)]
+    file: Option<String>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: default_log_format(),
+            level: default_log_level(),
+            file: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DatabaseConfig {
+    #[serde(default = "default_database_url")]
+    url: String,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: default_database_url(),
+        }
+    }
+}
+
+/// Present when several instances share one database
+#[derive(Debug, Deserialize)]
+struct ClusterSection {
+    /// Unique name of this instance. Required: two instances on one host share a
+    /// hostname, and a node renews a lease held under its own name
+    node_id: String,
+    /// Seconds a silent leader keeps the lease before another node takes over
+    #[serde(default = "default_lease_secs")]
+    lease_secs: u64,
+}
+
+impl ClusterSection {
+    fn to_cluster(&self, receive: &ReceiveSection) -> Result<ClusterConfig> {
+        let node_id = self.node_id.trim();
+        if node_id.is_empty() {
+            return Err(anyhow!("cluster node_id must not be empty"));
+        }
+        if receive.interface.is_none() {
+            // On a shared UDP port unicast datagrams, such as relayed requests, reach only
+            // one socket, which may be the standby's
+            return Err(anyhow!("[cluster] requires [receive] interface capture so every instance sees all requests"));
+        }
+        Ok(ClusterConfig {
+            node_id: node_id.to_string(),
+            lease_ttl: std::time::Duration::from_secs(self.lease_secs.max(3)),
+        })
+    }
+}
+
+fn system_hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|h| h.trim().to_string())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Deserialize)]
+struct MqttSinkConfig {
+    host: String,
+    #[serde(default = "default_mqtt_port")]
+    port: u16,
+    #[serde(default = "default_app_name")]
+    client_id: String,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    tls: bool,
+    #[serde(default)]
+    ca_file: Option<String>,
+    #[serde(default = "default_topic_prefix")]
+    topic_prefix: String,
+    #[serde(default)]
+    qos: u8,
+    #[serde(default)]
+    home_assistant: Option<HomeAssistantSinkConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HomeAssistantSinkConfig {
+    #[serde(default = "default_discovery_prefix")]
+    discovery_prefix: String,
+    /// Seconds without DHCP activity before a device is marked not_home
+    #[serde(default = "default_consider_home")]
+    consider_home_secs: u64,
+}
+
+impl MqttSinkConfig {
+    fn to_mqtt(&self) -> Result<MqttConfig> {
+        let qos = mqtt::qos_from_level(self.qos)
+            .ok_or_else(|| anyhow!("invalid MQTT qos {} (expected 0, 1 or 2)", self.qos))?;
+
+        Ok(MqttConfig {
+            host: self.host.clone(),
+            port: self.port,
+            client_id: self.client_id.clone(),
+            username: self.username.clone(),
+            password: self.password.clone(),
+            tls: self.tls,
+            ca_file: self.ca_file.clone(),
+            topic_prefix: self.topic_prefix.trim_end_matches('/').to_string(),
+            qos,
+            home_assistant: self.home_assistant.as_ref().map(|ha| HomeAssistantConfig {
+                discovery_prefix: ha.discovery_prefix.trim_end_matches('/').to_string(),
+                consider_home: std::time::Duration::from_secs(ha.consider_home_secs),
+            }),
+        })
+    }
+}
+
+/// Line-protocol metrics pushed to InfluxDB
+#[derive(Debug, Deserialize)]
+struct InfluxSinkConfig {
+    /// Write endpoint including org/bucket (2.x) or db (1.x) query parameters
+    url: String,
+    #[serde(default)]
+    token: Option<String>,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default = "default_measurement_prefix")]
+    measurement_prefix: String,
+    #[serde(default = "default_influx_flush_secs")]
+    flush_interval_secs: u64,
+    #[serde(default = "default_influx_timeout_secs")]
+    timeout_secs: u64,
+    /// Added to every point, e.g. { site = "home" }
+    #[serde(default)]
+    tags: std::collections::BTreeMap<String, String>,
+}
+
+impl InfluxSinkConfig {
+    fn to_influx(&self) -> Result<influx::InfluxConfig> {
+        let url = reqwest::Url::parse(&self.url).map_err(|e| anyhow!("invalid [influx] url '{}': {}", self.url, e))?;
+        if !matches!(url.scheme(), "https" | "http") {
+            return Err(anyhow!("[influx] url must be an http(s) URL"));
+        }
+        if self.measurement_prefix.is_empty() {
+            return Err(anyhow!("[influx] measurement_prefix must not be empty"));
+        }
+        if let Some((key, _)) = self.tags.iter().find(|(key, value)| key.is_empty() || value.is_empty()) {
+            return Err(anyhow!("[influx] tag '{}' must have a non-empty name and value", key));
+        }
+        Ok(influx::InfluxConfig {
+            url: self.url.clone(),
+            token: self.token.clone(),
+            username: self.username.clone(),
+            password: self.password.clone(),
+            measurement_prefix: self.measurement_prefix.clone(),
+            tags: self.tags.clone().into_iter().collect(),
+            flush_interval: std::time::Duration::from_secs(self.flush_interval_secs.max(1)),
+            timeout: std::time::Duration::from_secs(self.timeout_secs.max(1)),
+        })
+    }
+}
+
+fn default_measurement_prefix() -> String { "dhcpmon".to_string() }
+fn default_influx_flush_secs() -> u64 { 60 }
+fn default_influx_timeout_secs() -> u64 { 10 }
+
+/// Where the web UI listens, and serving it behind a reverse proxy and to other origins
+#[derive(Debug, Deserialize)]
+struct WebConfig {
+    /// Address to bind, e.g. 127.0.0.1 to keep the UI off the network
+    #[serde(default = "default_web_bind_address")]
+    bind_address: std::net::IpAddr,
+    #[serde(default = "default_web_port")]
+    port: u16,
+    /// Listen on this Unix domain socket instead of TCP
+    #[serde(default)]
+    unix_socket: Option<String>,
+    /// Sub-path the UI is mounted at, e.g. "/dhcpmon" (default: the root)
+    #[serde(default)]
+    base_path: String,
+    /// Honor X-Forwarded-For and X-Forwarded-Prefix from the proxy
+    #[serde(default)]
+    trust_forwarded_headers: bool,
+    /// Origins allowed to call the API from a browser ("*" for any; default: none)
+    #[serde(default)]
+    cors_origins: Vec<String>,
+    /// Bearer token for the admin endpoints; without one only local clients may use them
+    #[serde(default)]
+    admin_token: Option<String>,
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: default_web_bind_address(),
+            port: default_web_port(),
+            unix_socket: None,
+            base_path: String::new(),
+            trust_forwarded_headers: false,
+            cors_origins: Vec::new(),
+            admin_token: None,
+        }
+    }
+}
+
+impl WebConfig {
+    fn to_proxy(&self) -> ProxyConfig {
+        ProxyConfig::new(&self.base_path, self.trust_forwarded_headers, self.cors_origins.clone())
+    }
+
+    fn to_listen(&self) -> Listen {
+        match &self.unix_socket {
+            Some(path) => Listen::Unix(path.into()),
+            None => Listen::Tcp(SocketAddr::new(self.bind_address, self.port)),
+        }
+    }
+}
+
+fn default_web_bind_address() -> std::net::IpAddr { std::net::Ipv4Addr::UNSPECIFIED.into() }
+fn default_web_port() -> u16 { WEB_SERVER_PORT }
+
+/// Host ARP table correlation for device presence
+#[derive(Debug, Deserialize)]
+struct ArpConfig {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default = "default_arp_path")]
+    path: String,
+    #[serde(default = "default_arp_interval_secs")]
+    interval_secs: u64,
+    /// A device counts as active while it was in the table this recently
+    #[serde(default = "default_arp_active_window_secs")]
+    active_window_secs: u64,
+}
+
+impl Default for ArpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: default_arp_path(),
+            interval_secs: default_arp_interval_secs(),
+            active_window_secs: default_arp_active_window_secs(),
+        }
+    }
+}
+
+impl ArpConfig {
+    fn to_config(&self) -> arp::NeighborConfig {
+        arp::NeighborConfig {
+            path: self.path.clone().into(),
+            interval: std::time::Duration::from_secs(self.interval_secs.max(1)),
+            active_window: std::time::Duration::from_secs(self.active_window_secs),
+        }
+    }
+}
+
+/// Online/idle/offline tracking per device
+#[derive(Debug, Deserialize)]
+struct PresenceSection {
+    #[serde(default)]
+    enabled: bool,
+    /// Devices active this recently are online
+    #[serde(default = "default_presence_online_secs")]
+    online_secs: u64,
+    /// Devices silent longer than this are offline; in between they are idle
+    #[serde(default = "default_presence_offline_secs")]
+    offline_secs: u64,
+    #[serde(default = "default_presence_interval_secs")]
+    interval_secs: u64,
+    /// Ping idle devices before they time out
+    #[serde(default)]
+    ping: bool,
+}
+
+impl Default for PresenceSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            online_secs: default_presence_online_secs(),
+            offline_secs: default_presence_offline_secs(),
+            interval_secs: default_presence_interval_secs(),
+            ping: false,
+        }
+    }
+}
+
+impl PresenceSection {
+    fn to_config(&self) -> presence::PresenceConfig {
+        presence::PresenceConfig {
+            online_timeout: std::time::Duration::from_secs(self.online_secs),
+            offline_timeout: std::time::Duration::from_secs(self.offline_secs.max(self.online_secs)),
+            interval: std::time::Duration::from_secs(self.interval_secs.max(1)),
+            ping: self.ping,
+        }
+    }
+}
+
+fn default_presence_online_secs() -> u64 { 900 }
+fn default_presence_offline_secs() -> u64 { 7200 }
+fn default_presence_interval_secs() -> u64 { 30 }
+
+fn default_arp_path() -> String { "/proc/net/arp".to_string() }
+fn default_arp_interval_secs() -> u64 { 60 }
+fn default_arp_active_window_secs() -> u64 { 300 }
+
+/// Opt-in active mode: probe the segment for DHCP servers instead of only listening
+#[derive(Debug, Deserialize)]
+struct ActiveScanSection {
+    #[serde(default)]
+    enabled: bool,
+    /// "discover" (servers offer an address) or "inform" (configuration only)
+    #[serde(default = "default_active_scan_probe")]
+    probe: String,
+    #[serde(default = "default_active_scan_interval_secs")]
+    interval_secs: u64,
+    /// How long to collect answers after each probe
+    #[serde(default = "default_active_scan_wait_secs")]
+    wait_secs: u64,
+    /// Client hardware address of the probes, never logged as a client
+    #[serde(default = "default_active_scan_mac")]
+    client_mac: String,
+    /// The monitor's own address, required for inform probes
+    #[serde(default)]
+    client_ip: Option<std::net::Ipv4Addr>,
+    /// Send probes on this interface only (Linux)
+    #[serde(default)]
+    interface: Option<String>,
+    /// Servers allowed to answer; any other raises a rogue_server alert
+    #[serde(default)]
+    expected_servers: Vec<std::net::Ipv4Addr>,
+}
+
+impl Default for ActiveScanSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            probe: default_active_scan_probe(),
+            interval_secs: default_active_scan_interval_secs(),
+            wait_secs: default_active_scan_wait_secs(),
+            client_mac: default_active_scan_mac(),
+            client_ip: None,
+            interface: None,
+            expected_servers: Vec::new(),
+        }
+    }
+}
+
+impl ActiveScanSection {
+    fn to_config(&self) -> Result<active_scan::ActiveScanConfig> {
+        let probe = active_scan::ProbeKind::from_name(&self.probe)
+            .ok_or_else(|| anyhow!("unknown active_scan probe '{}'", self.probe))?;
+        let client_ip = match (probe, self.client_ip) {
+            (active_scan::ProbeKind::Inform, None) => {
+                return Err(anyhow!("active_scan probe 'inform' requires client_ip"))
+            }
+            (_, client_ip) => client_ip.unwrap_or(std::net::Ipv4Addr::UNSPECIFIED),
+        };
+        Ok(active_scan::ActiveScanConfig {
+            probe,
+            interval: std::time::Duration::from_secs(self.interval_secs.max(60)),
+            wait: std::time::Duration::from_secs(self.wait_secs.max(1)),
+            client_mac: self.client_mac.parse()?,
+            client_ip,
+            interface: self.interface.clone(),
+            expected_servers: self.expected_servers.clone(),
+        })
+    }
+}
+
+/// Scheduled ping or ARP sweeps finding hosts that never ask DHCP
+#[derive(Debug, Deserialize)]
+struct SweepSection {
+    #[serde(default)]
+    enabled: bool,
+    /// CIDR ranges on directly attached segments
+    #[serde(default)]
+    subnets: Vec<String>,
+    /// "icmp" (ping each address) or "arp" (resolve each address)
+    #[serde(default = "default_sweep_method")]
+    method: String,
+    #[serde(default = "default_sweep_interval_secs")]
+    interval_secs: u64,
+    /// Addresses probed at once
+    #[serde(default = "default_sweep_concurrency")]
+    concurrency: usize,
+}
+
+impl Default for SweepSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            subnets: Vec::new(),
+            method: default_sweep_method(),
+            interval_secs: default_sweep_interval_secs(),
+            concurrency: default_sweep_concurrency(),
+        }
+    }
+}
+
+impl SweepSection {
+    fn to_config(&self, neighbor_path: &str) -> Result<sweep::SweepConfig> {
+        let method = sweep::SweepMethod::from_name(&self.method)
+            .ok_or_else(|| anyhow!("unknown sweep method '{}'", self.method))?;
+        let subnets = self
+            .subnets
+            .iter()
+            .map(|cidr| Subnet::parse(cidr).ok_or_else(|| anyhow!("invalid sweep subnet '{}'", cidr)))
+            .collect::<Result<Vec<_>>>()?;
+        if subnets.is_empty() {
+            return Err(anyhow!("sweep requires at least one subnet"));
+        }
+        sweep::SweepConfig::check_size(&subnets, sweep::MAX_HOSTS)?;
+        Ok(sweep::SweepConfig {
+            subnets,
+            method,
+            interval: std::time::Duration::from_secs(self.interval_secs.max(60)),
+            concurrency: self.concurrency.max(1),
+            neighbor_path: neighbor_path.into(),
+        })
+    }
+}
+
+fn default_sweep_method() -> String { "icmp".to_string() }
+fn default_sweep_interval_secs() -> u64 { 3600 }
+fn default_sweep_concurrency() -> usize { 64 }
+
+fn default_active_scan_probe() -> String { "discover".to_string() }
+fn default_active_scan_interval_secs() -> u64 { 3600 }
+fn default_active_scan_wait_secs() -> u64 { 5 }
+fn default_active_scan_mac() -> String { "02:00:00:d4:c9:01".to_string() }
+
+/// PTR and mDNS lookups naming devices by their addresses
+#[derive(Debug, Deserialize)]
+struct ReverseDnsConfig {
+    #[serde(default)]
+    enabled: bool,
+    /// DNS server address ("" = first nameserver in /etc/resolv.conf, "none" = mDNS only)
+    #[serde(default)]
+    resolver: String,
+    /// Ask the device itself over unicast mDNS when DNS has no name
+    #[serde(default = "default_true")]
+    mdns: bool,
+    #[serde(default = "default_rdns_timeout_ms")]
+    timeout_ms: u64,
+    /// Look each address up again after this long
+    #[serde(default = "default_rdns_cache_secs")]
+    cache_secs: u64,
+}
+
+impl Default for ReverseDnsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            resolver: String::new(),
+            mdns: true,
+            timeout_ms: default_rdns_timeout_ms(),
+            cache_secs: default_rdns_cache_secs(),
+        }
+    }
+}
+
+impl ReverseDnsConfig {
+    fn to_config(&self) -> Result<rdns::RdnsConfig> {
+        let resolver = match self.resolver.trim() {
+            "" => rdns::system_resolver(),
+            "none" => None,
+            server => Some(
+                server
+                    .parse::<std::net::SocketAddr>()
+                    .or_else(|_| server.parse::<std::net::IpAddr>().map(|ip| std::net::SocketAddr::new(ip, 53)))
+                    .map_err(|_| anyhow::anyhow!("Invalid [reverse_dns] resolver '{}'", server))?,
+            ),
+        };
+        Ok(rdns::RdnsConfig {
+            resolver,
+            mdns: self.mdns,
+            timeout: std::time::Duration::from_millis(self.timeout_ms),
+            cache_ttl: std::time::Duration::from_secs(self.cache_secs),
+        })
+    }
+}
+
+fn default_rdns_timeout_ms() -> u64 { 1000 }
+fn default_rdns_cache_secs() -> u64 { 3600 }
+
+/// Site policy script run on each request
+#[derive(Debug, Deserialize)]
+struct ScriptingConfig {
+    /// Rhai script defining on_request(request)
+    path: String,
+    /// Budget per call; a script exceeding it fails instead of stalling capture
+    #[serde(default = "default_script_max_operations")]
+    max_operations: u64,
+}
+
+impl ScriptingConfig {
+    fn load(&self) -> Result<scripting::RequestScript> {
+        scripting::RequestScript::load(std::path::Path::new(&self.path), self.max_operations)
+    }
+}
+
+fn default_script_max_operations() -> u64 { 100_000 }
+
+/// gRPC API for machine consumers
+#[derive(Debug, Deserialize)]
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+struct GrpcConfig {
+    #[serde(default)]
+    enabled: bool,
+    /// Address to bind; the API has no authentication or TLS, so loopback by default
+    #[serde(default = "default_grpc_bind_address")]
+    bind_address: std::net::IpAddr,
+    #[serde(default = "default_grpc_port")]
+    port: u16,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: default_grpc_bind_address(),
+            port: default_grpc_port(),
+        }
+    }
+}
+
+fn default_grpc_bind_address() -> std::net::IpAddr { std::net::Ipv4Addr::LOCALHOST.into() }
+fn default_grpc_port() -> u16 { 50051 }
+
+/// Run as a branch-site agent forwarding requests to a central instance
+#[derive(Debug, Deserialize)]
+struct AgentSection {
+    /// Base URL of the central instance, e.g. "https://dhcpmon.example.com"
+    central_url: String,
+    /// Bearer token listed for this site in the central instance's [ingest]
+    token: String,
+    #[serde(default = "default_agent_batch_size")]
+    batch_size: usize,
+    #[serde(default = "default_agent_flush_secs")]
+    flush_interval_secs: u64,
+    /// Requests buffered while the central instance is unreachable; the oldest are dropped
+    #[serde(default = "default_agent_max_buffered")]
+    max_buffered: usize,
+    #[serde(default = "default_agent_timeout_secs")]
+    timeout_secs: u64,
+}
+
+impl AgentSection {
+    fn to_config(&self) -> Result<agent::AgentConfig> {
+        let url = reqwest::Url::parse(&self.central_url)
+            .map_err(|e| anyhow!("invalid [agent] central_url '{}': {}", self.central_url, e))?;
+        if !matches!(url.scheme(), "https" | "http") {
+            return Err(anyhow!("[agent] central_url must be an http(s) URL"));
+        }
+        if self.token.trim().is_empty() {
+            return Err(anyhow!("[agent] token must not be empty"));
+        }
+        Ok(agent::AgentConfig {
+            central_url: self.central_url.clone(),
+            token: self.token.trim().to_string(),
+            batch_size: self.batch_size.clamp(1, agent::MAX_INGEST_BATCH),
+            flush_interval: std::time::Duration::from_secs(self.flush_interval_secs.max(1)),
+            max_buffered: self.max_buffered.max(1),
+            timeout: std::time::Duration::from_secs(self.timeout_secs.max(1)),
+        })
+    }
+}
+
+fn default_agent_batch_size() -> usize { 100 }
+fn default_agent_flush_secs() -> u64 { 5 }
+fn default_agent_max_buffered() -> usize { 10_000 }
+fn default_agent_timeout_secs() -> u64 { 10 }
+
+/// Agents allowed to forward requests to this (central) instance
+#[derive(Debug, Default, Deserialize)]
+struct IngestConfig {
+    #[serde(default)]
+    agents: Vec<IngestAgentConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IngestAgentConfig {
+    /// Site the agent's requests are tagged with
+    site: String,
+    token: String,
+}
+
+impl IngestConfig {
+    fn to_registry(&self) -> Result<agent::AgentRegistry> {
+        let mut agents: Vec<(String, String)> = Vec::new();
+        for entry in &self.agents {
+            let (site, token) = (entry.site.trim(), entry.token.trim());
+            if site.is_empty() || site.eq_ignore_ascii_case(db::queries::LOCAL_SITE) {
+                return Err(anyhow!("invalid [ingest] site name '{}'", entry.site));
+            }
+            if token.is_empty() {
+                return Err(anyhow!("[ingest] agent '{}' has no token", site));
+            }
+            if agents.iter().any(|(_, other)| other == token) {
+                return Err(anyhow!("[ingest] agent '{}' reuses another agent's token", site));
+            }
+            agents.push((site.to_string(), token.to_string()));
+        }
+        Ok(agent::AgentRegistry::new(agents))
+    }
+}
+
+/// Limits protecting the capture pipeline from heavy API and WebSocket use
+#[derive(Debug, Deserialize)]
+struct RateLimitsConfig {
+    #[serde(default = "default_true")]
+    enabled: bool,
+    /// Token buckets per client IP; the longest matching path prefix applies
+    #[serde(default = "default_endpoint_limits")]
+    endpoints: Vec<EndpointLimitConfig>,
+    /// Concurrent /ws connections overall and per client IP (0 = unlimited)
+    #[serde(default = "default_max_websockets")]
+    max_websockets: usize,
+    #[serde(default = "default_max_websockets_per_client")]
+    max_websockets_per_client: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct EndpointLimitConfig {
+    path: String,
+    per_minute: u32,
+    /// Requests allowed back to back (default: per_minute)
+    #[serde(default)]
+    burst: Option<u32>,
+}
+
+impl Default for RateLimitsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            endpoints: default_endpoint_limits(),
+            max_websockets: default_max_websockets(),
+            max_websockets_per_client: default_max_websockets_per_client(),
+        }
+    }
+}
+
+impl RateLimitsConfig {
+    fn to_config(&self) -> RateLimitConfig {
+        if !self.enabled {
+            return RateLimitConfig::default();
+        }
+        RateLimitConfig {
+            endpoints: self
+                .endpoints
+                .iter()
+                .map(|endpoint| EndpointLimit {
+                    path: endpoint.path.clone(),
+                    per_minute: endpoint.per_minute,
+                    burst: endpoint.burst.unwrap_or(endpoint.per_minute),
+                })
+                .collect(),
+            max_websockets: self.max_websockets,
+            max_websockets_per_client: self.max_websockets_per_client,
+        }
+    }
+}
+
+fn default_endpoint_limits() -> Vec<EndpointLimitConfig> {
+    vec![
+        EndpointLimitConfig { path: "/api/logs".to_string(), per_minute: 120, burst: Some(30) },
+        EndpointLimitConfig { path: "/api/logs/export".to_string(), per_minute: 6, burst: Some(3) },
+        EndpointLimitConfig { path: "/api/graphql".to_string(), per_minute: 60, burst: Some(20) },
+    ]
+}
+
+/// Alert notification channels
+#[derive(Debug, Default, Deserialize)]
+struct NotifySection {
+    #[serde(default)]
+    email: Option<EmailSinkConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmailSinkConfig {
+    host: String,
+    /// Default: 465 for "tls", 587 for "starttls", 25 for "none"
+    #[serde(default)]
+    port: Option<u16>,
+    /// "tls", "starttls" or "none"
+    #[serde(default = "default_smtp_security")]
+    security: String,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    from: String,
+    #[serde(default)]
+    to: Vec<String>,
+    /// Recipients per alert type, overriding `to`
+    #[serde(default)]
+    routes: HashMap<String, Vec<String>>,
+    #[serde(default = "default_email_min_severity")]
+    min_severity: String,
+    /// Batch alerts arriving within this many seconds into one message; 0 sends each at once
+    #[serde(default = "default_email_digest")]
+    digest_secs: u64,
+}
+
+impl EmailSinkConfig {
+    fn to_email(&self) -> Result<EmailConfig> {
+        let security = SmtpSecurity::from_name(&self.security).ok_or_else(|| {
+            anyhow!("invalid SMTP security {:?} (expected \"tls\", \"starttls\" or \"none\")", self.security)
+        })?;
+        let min_severity = alerts::Severity::from_name(&self.min_severity).ok_or_else(|| {
+            anyhow!("invalid min_severity {:?} (expected \"info\", \"warning\" or \"critical\")", self.min_severity)
+        })?;
+
+        Ok(EmailConfig {
+            host: self.host.clone(),
+            port: self.port.unwrap_or(match security {
+                SmtpSecurity::Tls => 465,
+                SmtpSecurity::StartTls => 587,
+                SmtpSecurity::None => 25,
+            }),
+            security,
+            username: self.username.clone(),
+            password: self.password.clone(),
+            from: self.from.clone(),
+            to: self.to.clone(),
+            routes: self.routes.clone(),
+            min_severity,
+            digest: std::time::Duration::from_secs(self.digest_secs),
+        })
+    }
+}
+
+/// Rotation settings shared by the request and access logs
+#[derive(Debug, Deserialize)]
+struct LogRotationConfig {
+    /// Rotate when the file exceeds this size (0 = never rotate on size)
+    #[serde(default)]
+    max_size_mb: u64,
+    /// "never", "hourly" or "daily"
+    #[serde(default = "default_rotation")]
+    rotation: String,
+    #[serde(default = "default_max_files")]
+    max_files: usize,
+    #[serde(default)]
+    compress: bool,
+}
+
+impl Default for LogRotationConfig {
+    fn default() -> Self {
+        Self {
+            max_size_mb: 0,
+            rotation: default_rotation(),
+            max_files: default_max_files(),
+            compress: false,
+        }
+    }
+}
+
+impl LogRotationConfig {
+    fn to_rotation(&self) -> RotationConfig {
+        let period = RotationPeriod::from_name(&self.rotation).unwrap_or_else(|| {
+            warn!("Unknown log rotation '{}', not rotating by time", self.rotation);
+            RotationPeriod::Never
+        });
+        RotationConfig {
+            max_bytes: (self.max_size_mb > 0).then(|| self.max_size_mb * 1024 * 1024),
+            period,
+            max_files: self.max_files,
+            compress: self.compress,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RequestLogConfig {
+    /// Disable to rely on the database only
+    #[serde(default = "default_true")]
+    enabled: bool,
+    /// "file", "syslog" or "journald"
+    #[serde(default = "default_log_backend")]
+    backend: String,
+    #[serde(default = "default_request_log_path")]
+    path: String,
+    #[serde(flatten)]
+    rotation: LogRotationConfig,
+    #[serde(default)]
+    syslog: Option<SyslogSinkConfig>,
+}
+
+impl Default for RequestLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            backend: default_log_backend(),
+            path: default_request_log_path(),
+            rotation: LogRotationConfig::default(),
+            syslog: None,
+        }
+    }
+}
+
+/// Request events on stdout
+#[derive(Debug, Deserialize)]
+struct ConsoleConfig {
+    /// "off", "ndjson" or "pretty"
+    #[serde(default = "default_console_format")]
+    format: String,
+    /// Options included; requests carrying none of them (and no ciaddr) are skipped
+    #[serde(default = "default_console_options")]
+    options: Vec<u8>,
+}
+
+impl Default for ConsoleConfig {
+    fn default() -> Self {
+        Self {
+            format: default_console_format(),
+            options: default_console_options(),
+        }
+    }
+}
+
+impl ConsoleConfig {
+    fn to_sink(&self) -> Result<Option<ConsoleSink>> {
+        match ConsoleFormat::from_name(&self.format) {
+            Some(ConsoleFormat::Off) => Ok(None),
+            Some(format) => Ok(Some(ConsoleSink::new(format, self.options.clone()))),
+            None => Err(anyhow!("unknown console format '{}'", self.format)),
+        }
+    }
+
+    /// Whether stdout carries NDJSON events that other output must stay out of
+    fn is_ndjson(&self) -> bool {
+        ConsoleFormat::from_name(&self.format) == Some(ConsoleFormat::Ndjson)
+    }
+}
+
+fn default_console_format() -> String { "pretty".to_string() }
+fn default_console_options() -> Vec<u8> { vec![12, 55, 60, 81] }
+
+#[derive(Debug, Deserialize)]
+struct SyslogSinkConfig {
+    /// Collector "host:port"
+    address: String,
+    /// "udp", "tcp" or "tls"
+    #[serde(default = "default_syslog_protocol")]
+    protocol: String,
+    #[serde(default = "default_syslog_facility")]
+    facility: String,
+    #[serde(default = "default_app_name")]
+    app_name: String,
+    /// Hostname reported in messages (default: the system hostname)
+    #[serde(default)]
+    hostname: Option<String>,
+    /// Your organization's IANA private enterprise number, for structured data
+    #[serde(default)]
+    enterprise_number: Option<u32>,
+}
+
+impl SyslogSinkConfig {
+    fn to_syslog(&self) -> Result<SyslogConfig> {
+        let protocol = SyslogProtocol::from_name(&self.protocol)
+            .ok_or_else(|| anyhow!("unknown syslog protocol '{}'", self.protocol))?;
+        let facility = syslog::facility_code(&self.facility)
+            .ok_or_else(|| anyhow!("unknown syslog facility '{}'", self.facility))?;
+        let hostname = self.hostname.clone().unwrap_or_else(system_hostname);
+
+        Ok(SyslogConfig {
+            address: self.address.clone(),
+            protocol,
+            facility,
+            app_name: self.app_name.clone(),
+            hostname,
+            enterprise_number: self.enterprise_number,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AccessLogConfig {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default = "default_access_log_path")]
+    path: String,
+    /// Header carrying the user authenticated by a reverse proxy
+    #[serde(default)]
+    principal_header: Option<String>,
+    #[serde(flatten)]
+    rotation: LogRotationConfig,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: default_access_log_path(),
+            principal_header: None,
+            rotation: LogRotationConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AlertsConfig {
+    #[serde(default = "default_alert_cooldown")]
+    cooldown_secs: u64,
+    #[serde(default = "default_flap_window")]
+    flap_window_secs: u64,
+    #[serde(default = "default_flap_threshold")]
+    flap_threshold: usize,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self {
+            cooldown_secs: default_alert_cooldown(),
+            flap_window_secs: default_flap_window(),
+            flap_threshold: default_flap_threshold(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct AnomalySection {
+    enabled: bool,
+    /// Previously-unseen MACs sending DISCOVER within the window that indicate starvation
+    starvation_macs: usize,
+    starvation_window_secs: u64,
+    starvation_patterned_macs: usize,
+    decline_threshold: usize,
+    decline_window_secs: u64,
+    nak_threshold: usize,
+    nak_window_secs: u64,
+    fingerprint_changes: bool,
+    ip_conflicts: bool,
+    conflict_window_secs: u64,
+}
+
+impl Default for AnomalySection {
+    fn default() -> Self {
+        let defaults = AnomalyConfig::default();
+        Self {
+            enabled: true,
+            starvation_macs: defaults.starvation_macs,
+            starvation_window_secs: defaults.starvation_window.as_secs(),
+            starvation_patterned_macs: defaults.starvation_patterned_macs,
+            decline_threshold: defaults.decline_threshold,
+            decline_window_secs: defaults.decline_window.as_secs(),
+            nak_threshold: defaults.nak_threshold,
+            nak_window_secs: defaults.nak_window.as_secs(),
+            fingerprint_changes: defaults.fingerprint_changes,
+            ip_conflicts: defaults.ip_conflicts,
+            conflict_window_secs: defaults.conflict_window.as_secs(),
+        }
+    }
+}
+
+impl AnomalySection {
+    fn to_anomaly(&self) -> AnomalyConfig {
+        use std::time::Duration;
+        AnomalyConfig {
+            starvation_macs: self.starvation_macs,
+            starvation_window: Duration::from_secs(self.starvation_window_secs),
+            starvation_patterned_macs: self.starvation_patterned_macs,
+            decline_threshold: self.decline_threshold,
+            decline_window: Duration::from_secs(self.decline_window_secs),
+            nak_threshold: self.nak_threshold,
+            nak_window: Duration::from_secs(self.nak_window_secs),
+            fingerprint_changes: self.fingerprint_changes,
+            ip_conflicts: self.ip_conflicts,
+            conflict_window: Duration::from_secs(self.conflict_window_secs),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ProbeIdentityConfig {
+    /// Fixed SMB ClientGuid; a random one is generated at startup when unset
+    #[serde(default)]
+    smb_client_guid: Option<String>,
+    /// Local ports SMB probes connect from, low to high; HTTP probes leave it to the OS
+    #[serde(default)]
+    source_port_range: Option<(u16, u16)>,
+    #[serde(default)]
+    stealth: bool,
+    /// HTTP probe User-Agent; the built-in (or stealth) one when unset
+    #[serde(default)]
+    http_user_agent: Option<String>,
+    /// Workstation name in NTLMSSP negotiate messages; none is sent when unset
+    #[serde(default)]
+    ntlm_workstation: Option<String>,
+}
+
+impl ProbeIdentityConfig {
+    /// The probe identity, failing on a reversed port range or an invalid workstation name
+    fn to_identity(&self) -> Result<ProbeIdentity> {
+        if let Some((low, high)) = self.source_port_range {
+            if low > high {
+                return Err(anyhow!("probe_identity source_port_range [{}, {}] is reversed", low, high));
+            }
+        }
+        let mut identity = ProbeIdentity {
+            source_ports: self.source_port_range,
+            stealth: self.stealth,
+            user_agent: self.http_user_agent.clone().filter(|agent| !agent.trim().is_empty()),
+            workstation: self.ntlm_workstation.as_deref().map(probe_identity::parse_workstation).transpose()?,
+            ..Default::default()
+        };
+        if let Some(guid) = &self.smb_client_guid {
+            match probe_identity::parse_guid(guid) {
+                Ok(parsed) => identity.smb_client_guid = parsed,
+                Err(e) => warn!("{}, using a random SMB client GUID", e),
+            }
+        }
+        Ok(identity)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct FingerprintUpdateConfig {
+    url: String,
+    #[serde(default)]
+    signature_url: Option<String>,
+    public_key: String,
+    #[serde(default = "default_bundle_path")]
+    bundle_path: String,
+}
+
+impl FingerprintUpdateConfig {
+    /// The updater, failing on an invalid URL or public key
+    fn to_updater(&self) -> Result<FingerprintUpdater> {
+        FingerprintUpdater::new(UpdaterConfig {
+            url: self.url.clone(),
+            signature_url: self.signature_url.clone(),
+            public_key: self.public_key.clone(),
+            bundle_path: self.bundle_path.clone(),
+        })
+        .map_err(|e| anyhow!("invalid [fingerprint_updates]: {}", e))
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -34,16 +1213,349 @@ struct DetectionConfig {
     enable_smb_probing: bool,
     #[serde(default = "default_smb_timeout")]
     smb_timeout_secs: u64,
-    #[serde(default = "default_confidence_threshold")]
-    smb_probe_confidence_threshold: f32,
     #[serde(default = "default_cache_ttl")]
     smb_cache_ttl_secs: u64,
+    /// Retry hosts that refuse SMB2 with a legacy SMB1 negotiate
+    #[serde(default)]
+    smb1_fallback: bool,
+    /// Active probes (ping, SMB, HTTP) running at once
+    #[serde(default = "default_max_concurrent_probes")]
+    max_concurrent_probes: usize,
+    /// Probes waiting for a slot before new ones are skipped
+    #[serde(default = "default_max_queued_probes")]
+    max_queued_probes: usize,
+    /// Seconds before the same probe is sent to a host again
+    #[serde(default = "default_probe_cooldown")]
+    probe_cooldown_secs: u64,
+    /// Off unless asked for: requests to every unidentified device's web server are loud
+    #[serde(default)]
+    enable_http_probing: bool,
+    #[serde(default = "default_http_timeout")]
+    http_timeout_secs: u64,
+    #[serde(default = "default_cache_ttl")]
+    http_cache_ttl_secs: u64,
+    /// First retry delay after a failed probe, doubled per failure up to the cache TTL
+    #[serde(default = "default_negative_cache_ttl")]
+    negative_cache_ttl_secs: u64,
+    /// Per-source confidence multipliers, e.g. smb = 1.0, http = 0.8
+    #[serde(default)]
+    weights: HashMap<String, f32>,
+    #[serde(default)]
+    smb_policy: SmbPolicyConfig,
+    #[serde(default)]
+    reverify: ReverifySection,
+}
+
+/// Confidence decay and scheduled re-verification of stored detections
+#[derive(Debug, Deserialize)]
+struct ReverifySection {
+    #[serde(default)]
+    enabled: bool,
+    /// Hours for detection confidence to halve
+    #[serde(default = "default_reverify_half_life_hours")]
+    half_life_hours: f64,
+    /// Devices whose decayed confidence falls below this are detected again
+    #[serde(default = "default_reverify_threshold")]
+    threshold: f64,
+    #[serde(default = "default_reverify_interval_secs")]
+    interval_secs: u64,
+    /// Devices re-verified per run
+    #[serde(default = "default_reverify_max_per_run")]
+    max_per_run: usize,
+}
+
+impl Default for ReverifySection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            half_life_hours: default_reverify_half_life_hours(),
+            threshold: default_reverify_threshold(),
+            interval_secs: default_reverify_interval_secs(),
+            max_per_run: default_reverify_max_per_run(),
+        }
+    }
+}
+
+impl ReverifySection {
+    fn to_config(&self) -> Result<reverify::ReverifyConfig> {
+        if self.half_life_hours.is_nan() || self.half_life_hours <= 0.0 {
+            return Err(anyhow!("detection.reverify half_life_hours must be positive"));
+        }
+        if !(0.0..=1.0).contains(&self.threshold) {
+            return Err(anyhow!("detection.reverify threshold must be between 0 and 1"));
+        }
+        let half_life = std::time::Duration::try_from_secs_f64(self.half_life_hours * 3600.0).map_err(|_| {
+            anyhow!("detection.reverify half_life_hours is too large: {}", self.half_life_hours)
+        })?;
+        Ok(reverify::ReverifyConfig {
+            half_life,
+            threshold: self.threshold,
+            interval: std::time::Duration::from_secs(self.interval_secs.max(60)),
+            max_per_run: self.max_per_run.max(1),
+        })
+    }
+}
+
+fn default_reverify_half_life_hours() -> f64 { 168.0 }
+fn default_reverify_threshold() -> f64 { 0.5 }
+fn default_reverify_interval_secs() -> u64 { 900 }
+fn default_reverify_max_per_run() -> usize { 16 }
+
+/// Which devices get an SMB probe
+#[derive(Debug, Deserialize)]
+struct SmbPolicyConfig {
+    /// "always", "never", "vendor" or "fingerprint"
+    #[serde(default = "default_smb_policy_mode")]
+    mode: String,
+    /// Regex matched against the vendor class in "vendor" mode
+    #[serde(default = "default_smb_vendor_pattern")]
+    vendor_pattern: String,
+    /// Regex matched against the OS detected so far in "fingerprint" mode
+    #[serde(default = "default_smb_fingerprint_pattern")]
+    fingerprint_pattern: String,
+    /// CIDRs; when set, only these are probed
+    #[serde(default)]
+    allow_subnets: Vec<String>,
+    /// CIDRs never probed
+    #[serde(default)]
+    deny_subnets: Vec<String>,
+}
+
+impl Default for SmbPolicyConfig {
+    fn default() -> Self {
+        Self {
+            mode: default_smb_policy_mode(),
+            vendor_pattern: default_smb_vendor_pattern(),
+            fingerprint_pattern: default_smb_fingerprint_pattern(),
+            allow_subnets: Vec::new(),
+            deny_subnets: Vec::new(),
+        }
+    }
+}
+
+impl SmbPolicyConfig {
+    /// The policy, failing on any invalid entry: a dropped deny subnet would get probed
+    fn to_policy(&self) -> Result<SmbProbePolicy> {
+        let pattern = |pattern: &str| {
+            regex::Regex::new(pattern).map_err(|e| anyhow!("invalid SMB policy pattern '{}': {}", pattern, e))
+        };
+        let subnets = |cidrs: &[String]| {
+            cidrs
+                .iter()
+                .map(|cidr| Subnet::parse(cidr).ok_or_else(|| anyhow!("invalid SMB policy subnet '{}'", cidr)))
+                .collect::<Result<Vec<_>>>()
+        };
+
+        Ok(SmbProbePolicy {
+            mode: ProbeMode::from_name(&self.mode)
+                .ok_or_else(|| anyhow!("unknown SMB policy mode '{}'", self.mode))?,
+            vendor_pattern: pattern(&self.vendor_pattern)?,
+            fingerprint_pattern: pattern(&self.fingerprint_pattern)?,
+            allow_subnets: subnets(&self.allow_subnets)?,
+            deny_subnets: subnets(&self.deny_subnets)?,
+        })
+    }
+}
+
+/// Folding of repeated identical requests
+#[derive(Debug, Default, Deserialize)]
+struct DedupConfig {
+    /// Identical (MAC, message type, fingerprint) requests within this many seconds
+    /// of a stored one are counted on it instead of stored; 0 disables
+    #[serde(default)]
+    window_secs: u64,
+}
+
+/// Announcements of devices seen for the first time
+#[derive(Debug, Deserialize)]
+struct NewDevicesConfig {
+    #[serde(default = "default_true")]
+    enabled: bool,
+    /// Announce a device again when it returns after this many hours of silence; 0 only announces it once
+    #[serde(default)]
+    reannounce_after_hours: u64,
+    /// POST each announcement as JSON to this URL
+    #[serde(default)]
+    webhook_url: Option<String>,
+}
+
+impl Default for NewDevicesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            reannounce_after_hours: 0,
+            webhook_url: None,
+        }
+    }
+}
+
+impl NewDevicesConfig {
+    fn to_config(&self) -> Result<new_device::NewDeviceConfig> {
+        let reannounce_after = match self.reannounce_after_hours {
+            0 => None,
+            hours => Some(
+                i64::try_from(hours)
+                    .ok()
+                    .and_then(chrono::Duration::try_hours)
+                    .ok_or_else(|| anyhow!("new_devices reannounce_after_hours is too large: {}", hours))?,
+            ),
+        };
+        Ok(new_device::NewDeviceConfig {
+            reannounce_after,
+            webhook_url: self.webhook_url.clone().filter(|url| !url.trim().is_empty()),
+        })
+    }
+}
+
+/// Hand-off from the DHCP socket to the processing workers
+#[derive(Debug, Deserialize)]
+struct ReceiveSection {
+    #[serde(default = "default_receive_workers")]
+    workers: usize,
+    #[serde(default = "default_receive_queue_size")]
+    queue_size: usize,
+    /// "block", "drop-new" or "drop-oldest"
+    #[serde(default = "default_receive_overflow")]
+    overflow: String,
+    /// Read whole frames from this interface instead of UDP port 67, recording
+    /// 802.1Q VLAN IDs (Linux)
+    #[serde(default)]
+    interface: Option<String>,
+}
+
+impl Default for ReceiveSection {
+    fn default() -> Self {
+        Self {
+            workers: default_receive_workers(),
+            queue_size: default_receive_queue_size(),
+            overflow: default_receive_overflow(),
+            interface: None,
+        }
+    }
+}
+
+impl ReceiveSection {
+    fn to_config(&self) -> Result<receive::ReceiveConfig> {
+        let overflow = receive::OverflowPolicy::from_name(&self.overflow)
+            .ok_or_else(|| anyhow!("unknown receive overflow policy '{}'", self.overflow))?;
+        Ok(receive::ReceiveConfig {
+            workers: self.workers.max(1),
+            queue_size: self.queue_size.max(1),
+            overflow,
+        })
+    }
+}
+
+fn default_receive_workers() -> usize { receive::ReceiveConfig::default().workers }
+fn default_receive_queue_size() -> usize { receive::ReceiveConfig::default().queue_size }
+fn default_receive_overflow() -> String { receive::ReceiveConfig::default().overflow.as_str().to_string() }
+
+/// Packets dropped before logging and storage
+#[derive(Debug, Default, Deserialize)]
+struct CaptureConfig {
+    /// MAC prefixes ("00:15:5d" or "00-15-5D")
+    #[serde(default)]
+    ignore_mac_prefixes: Vec<String>,
+    /// Regexes matched against the vendor class
+    #[serde(default)]
+    ignore_vendor_classes: Vec<String>,
+    /// CIDRs; when set, only clients in these are kept
+    #[serde(default)]
+    only_subnets: Vec<String>,
+}
+
+impl CaptureConfig {
+    /// The filter, failing on any invalid entry: with every only_subnets entry
+    /// dropped the filter would capture everything
+    fn to_filter(&self) -> Result<CaptureFilter> {
+        Ok(CaptureFilter {
+            ignore_mac_prefixes: self.ignore_mac_prefixes.iter().map(|prefix| mac::canonical_fragment(prefix)).collect(),
+            ignore_vendor_classes: self
+                .ignore_vendor_classes
+                .iter()
+                .map(|pattern| {
+                    regex::Regex::new(pattern)
+                        .map_err(|e| anyhow!("invalid capture vendor class pattern '{}': {}", pattern, e))
+                })
+                .collect::<Result<_>>()?,
+            only_subnets: self
+                .only_subnets
+                .iter()
+                .map(|cidr| Subnet::parse(cidr).ok_or_else(|| anyhow!("invalid capture subnet '{}'", cidr)))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+/// Attribution of requests to subnets, for multi-VLAN networks behind relays
+#[derive(Debug, Deserialize)]
+struct SubnetsConfig {
+    /// CIDRs requests are attributed to; the most specific match wins
+    #[serde(default)]
+    known: Vec<String>,
+    /// Prefix length assumed around link addresses outside `known`
+    #[serde(default = "default_subnet_prefix_len")]
+    default_prefix_len: u32,
+}
+
+impl Default for SubnetsConfig {
+    fn default() -> Self {
+        Self {
+            known: Vec::new(),
+            default_prefix_len: default_subnet_prefix_len(),
+        }
+    }
+}
+
+impl SubnetsConfig {
+    /// The map, failing on any invalid subnet rather than misattributing its traffic
+    fn to_map(&self) -> Result<SubnetMap> {
+        let known = self
+            .known
+            .iter()
+            .map(|cidr| Subnet::parse(cidr).ok_or_else(|| anyhow!("invalid subnet '{}'", cidr)))
+            .collect::<Result<_>>()?;
+        Ok(SubnetMap::new(known, self.default_prefix_len))
+    }
 }
 
 fn default_true() -> bool { true }
 fn default_smb_timeout() -> u64 { 3 }
-fn default_confidence_threshold() -> f32 { 0.8 }
+fn default_http_timeout() -> u64 { 3 }
 fn default_cache_ttl() -> u64 { 3600 }
+fn default_negative_cache_ttl() -> u64 { 300 }
+fn default_max_concurrent_probes() -> usize { 16 }
+fn default_max_queued_probes() -> usize { 256 }
+fn default_probe_cooldown() -> u64 { 30 }
+fn default_subnet_prefix_len() -> u32 { 24 }
+fn default_smb_policy_mode() -> String { "vendor".to_string() }
+fn default_smb_vendor_pattern() -> String { "MSFT".to_string() }
+fn default_smb_fingerprint_pattern() -> String { "(?i)windows".to_string() }
+fn default_alert_cooldown() -> u64 { 900 }
+fn default_flap_window() -> u64 { 3600 }
+fn default_flap_threshold() -> usize { 3 }
+fn default_mqtt_port() -> u16 { 1883 }
+fn default_discovery_prefix() -> String { "homeassistant".to_string() }
+fn default_consider_home() -> u64 { 1800 }
+fn default_topic_prefix() -> String { "dhcpmon".to_string() }
+fn default_smtp_security() -> String { "starttls".to_string() }
+fn default_email_min_severity() -> String { "warning".to_string() }
+fn default_email_digest() -> u64 { 300 }
+fn default_max_websockets() -> usize { 100 }
+fn default_max_websockets_per_client() -> usize { 10 }
+fn default_log_backend() -> String { "file".to_string() }
+fn default_log_format() -> String { "text".to_string() }
+fn default_log_level() -> String { "info".to_string() }
+fn default_syslog_protocol() -> String { "udp".to_string() }
+fn default_syslog_facility() -> String { "local0".to_string() }
+fn default_app_name() -> String { "ks-dhcpmon".to_string() }
+fn default_request_log_path() -> String { "request.json".to_string() }
+fn default_access_log_path() -> String { "access.json".to_string() }
+fn default_rotation() -> String { "never".to_string() }
+fn default_max_files() -> usize { 7 }
+fn default_bundle_path() -> String { fingerprint::FINGERPRINT_BUNDLE_PATH.to_string() }
+fn default_database_url() -> String { "sqlite:dhcp_monitor.db".to_string() }
+fn default_lease_secs() -> u64 { 15 }
 
 impl Default for DetectionConfig {
     fn default() -> Self {
@@ -51,129 +1563,930 @@ impl Default for DetectionConfig {
             enable_hybrid: true,
             enable_smb_probing: true,
             smb_timeout_secs: 3,
-            smb_probe_confidence_threshold: 0.8,
             smb_cache_ttl_secs: 3600,
+            smb1_fallback: false,
+            max_concurrent_probes: default_max_concurrent_probes(),
+            max_queued_probes: default_max_queued_probes(),
+            probe_cooldown_secs: default_probe_cooldown(),
+            enable_http_probing: false,
+            http_timeout_secs: 3,
+            http_cache_ttl_secs: 3600,
+            negative_cache_ttl_secs: default_negative_cache_ttl(),
+            weights: HashMap::new(),
+            smb_policy: SmbPolicyConfig::default(),
+            reverify: ReverifySection::default(),
+        }
+    }
+}
+
+/// Load configuration from config.toml, checked like --check-config, or use defaults
+/// when there is none. Logging is configured from the result, so what happened is
+/// returned to be logged afterwards.
+fn load_config() -> Result<(Config, &'static str)> {
+    match std::fs::read_to_string("config.toml") {
+        Ok(content) => {
+            let config = check_config(&content).map_err(|e| anyhow!("config.toml is invalid: {}", e))?;
+            Ok((config, "Loaded configuration from config.toml"))
         }
+        Err(_) => Ok((Config::default(), "No config.toml found, using default configuration")),
+    }
+}
+
+/// Parse config.toml content and check everything startup would reject (the policy
+/// script is compiled), without opening sockets, log files or the database. Startup
+/// runs this too, so --check-config and a real start always agree.
+fn check_config(content: &str) -> Result<Config> {
+    let config: Config = toml::from_str(content)?;
+
+    match config.request_log.backend.as_str() {
+        "file" | "journald" => {}
+        "syslog" => {
+            config
+                .request_log
+                .syslog
+                .as_ref()
+                .ok_or_else(|| anyhow!("request_log backend 'syslog' requires a [request_log.syslog] section"))?
+                .to_syslog()?;
+        }
+        other => return Err(anyhow!("unknown request_log backend '{}'", other)),
+    }
+    config.console.to_sink()?;
+    if let Some(agent) = &config.agent {
+        agent.to_config()?;
+    }
+    config.ingest.to_registry()?;
+    if let Some(scripting) = &config.scripting {
+        scripting.load()?;
     }
+    if config.subnets.default_prefix_len > 32 {
+        return Err(anyhow!("subnets default_prefix_len must be at most 32"));
+    }
+    config.subnets.to_map()?;
+    config.capture.to_filter()?;
+    config.detection.smb_policy.to_policy()?;
+    config.probe_identity.to_identity()?;
+    if let Some(update_config) = &config.fingerprint_updates {
+        update_config.to_updater()?;
+    }
+    if config.detection.reverify.enabled {
+        config.detection.reverify.to_config()?;
+    }
+    if !matches!(config.logging.format.as_str(), "text" | "json") {
+        return Err(anyhow!("unknown log format '{}'", config.logging.format));
+    }
+    if let Some(header) = &config.access_log.principal_header {
+        axum::http::HeaderName::from_bytes(header.as_bytes())
+            .map_err(|_| anyhow!("invalid access_log principal_header '{}'", header))?;
+    }
+    if let Some(cluster) = &config.cluster {
+        cluster.to_cluster(&config.receive)?;
+    }
+    if let Some(mqtt) = &config.mqtt {
+        mqtt.to_mqtt()?;
+    }
+    if let Some(influx) = &config.influx {
+        influx.to_influx()?;
+    }
+    if let Some(email) = &config.notify.email {
+        notify::email::EmailNotifier::new(email.to_email()?)?;
+    }
+    if config.new_devices.enabled {
+        new_device::NewDeviceTracker::new(config.new_devices.to_config()?)?;
+    }
+    config.receive.to_config()?;
+    if config.grpc.enabled && cfg!(not(feature = "grpc")) {
+        return Err(anyhow!("[grpc] is enabled but this build has no gRPC support (build with --features grpc)"));
+    }
+    if config.active_scan.enabled {
+        config.active_scan.to_config()?;
+    }
+    if config.sweep.enabled {
+        config.sweep.to_config(&config.arp.path)?;
+    }
+    Ok(config)
+}
+
+/// Validate config.toml content like check_config and extract the settings a reload
+/// applies without a restart
+fn runtime_settings(content: &str) -> Result<reload::RuntimeSettings> {
+    let config = check_config(content)?;
+    let log_config = &config.request_log;
+    Ok(reload::RuntimeSettings {
+        capture_filter: config.capture.to_filter()?,
+        smb_policy: config.detection.smb_policy.to_policy()?,
+        log_rotation: (log_config.enabled && log_config.backend == "file").then(|| log_config.rotation.to_rotation()),
+        new_devices: config.new_devices.enabled.then(|| config.new_devices.to_config()).transpose()?,
+        email: config.notify.email.as_ref().map(|email| email.to_email()).transpose()?,
+    })
 }
 
-/// Load configuration from config.toml or use defaults
-fn load_config() -> Config {
-    match std::fs::read_to_string("config.toml") {
-        Ok(content) => match toml::from_str(&content) {
-            Ok(config) => {
-                info!("Loaded configuration from config.toml");
-                config
-            }
-            Err(e) => {
-                warn!("Failed to parse config.toml: {}, using defaults", e);
-                Config { detection: DetectionConfig::default() }
-            }
-        },
-        Err(_) => {
-            info!("No config.toml found, using default configuration");
-            Config { detection: DetectionConfig::default() }
+/// Set up the tracing subscriber; RUST_LOG overrides the configured level
+fn init_logging(config: &LoggingConfig, json: bool, console_ndjson: bool) -> Result<()> {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .or_else(|_| tracing_subscriber::EnvFilter::try_new(&config.level))
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+
+    let writer = match &config.file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+            BoxMakeWriter::new(std::sync::Mutex::new(file))
         }
+        // Keep logs apart from request events on stdout
+        None if json || console_ndjson => BoxMakeWriter::new(std::io::stderr),
+        None => BoxMakeWriter::new(std::io::stdout),
+    };
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_thread_ids(false)
+        .with_level(true);
+    if json {
+        builder.json().with_current_span(true).with_span_list(false).init();
+    } else {
+        builder.with_target(false).with_ansi(config.file.is_none()).init();
     }
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_target(false)
-        .with_thread_ids(false)
-        .with_level(true)
-        .init();
+    let args: Vec<String> = std::env::args().collect();
+
+    // Validate config.toml and exit: ks-dhcpmon --check-config
+    if args.iter().skip(1).any(|arg| arg == "--check-config") {
+        let content = std::fs::read_to_string("config.toml").map_err(|e| anyhow!("config.toml: {}", e))?;
+        check_config(&content).map_err(|e| anyhow!("config.toml is invalid: {}", e))?;
+        println!("config.toml is valid");
+        return Ok(());
+    }
+
+    // Offline salvage of a damaged database: ks-dhcpmon recover-db <damaged.db> <new.db>
+    // Runs before config.toml is read, so a broken config doesn't get in the way
+    if args.get(1).map(String::as_str) == Some("recover-db") {
+        let (Some(source), Some(target)) = (args.get(2), args.get(3)) else {
+            return Err(anyhow!("usage: {} recover-db <damaged.db> <new.db>", args[0]));
+        };
+        println!("Recovering {} into {}", source, target);
+        let summary = db::recover::recover_database(source, target).await?;
+        println!("Recovered {} requests, {} unreadable", summary.recovered, summary.lost);
+        return Ok(());
+    }
+
+    // Load and check configuration before starting anything, then initialize tracing
+    // (--json-logs overrides [logging] format)
+    let (mut config, loaded) = load_config()?;
+    let json_logs = args.iter().skip(1).any(|arg| arg == "--json-logs") || config.logging.format == "json";
+    init_logging(&config.logging, json_logs, config.console.is_ndjson())?;
+    info!("{}", loaded);
+
+    // Branch-site agent: capture and forward, without a database or web UI
+    if let Some(agent) = &config.agent {
+        return run_agent(
+            agent.to_config()?,
+            config.capture.to_filter()?,
+            config.subnets.to_map()?,
+            config.receive.to_config()?,
+            config.receive.interface.clone(),
+        )
+        .await;
+    }
+
+    // Load generation: ks-dhcpmon simulate [--target host:port] [--rate n] ...
+    // Without a target the traffic feeds this instance in place of capture
+    let simulation = if args.get(1).map(String::as_str) == Some("simulate") {
+        let simulation = simulate::SimulateConfig::from_args(&args[2..])?;
+        if let Some(target) = simulation.target {
+            return simulate::send(&simulation, target).await;
+        }
+        Some(simulation)
+    } else {
+        None
+    };
+
+    // Generated traffic instead of capture: ks-dhcpmon --demo
+    let demo = args.iter().skip(1).any(|arg| arg == "--demo");
+    let generated = demo || simulation.is_some();
 
     info!("Starting DHCP Monitor with Web UI and Hybrid Detection");
 
-    // Load configuration
-    let config = load_config();
+    if generated {
+        // Simulated hosts can't be probed, and generated data stays out of the real database and logs
+        info!(
+            "{} mode: generating traffic instead of listening on port {}",
+            if demo { "Demo" } else { "Simulation" },
+            DHCP_SERVER_PORT
+        );
+        config.detection.enable_smb_probing = false;
+        config.detection.enable_http_probing = false;
+        config.database.url = if demo { demo::DEMO_DATABASE_URL } else { simulate::SIMULATE_DATABASE_URL }.to_string();
+        config.request_log.enabled = false;
+        config.cluster = None;
+        config.mqtt = None;
+        config.influx = None;
+        config.notify.email = None;
+    }
     info!("Hybrid detection: {}", if config.detection.enable_hybrid { "enabled" } else { "disabled" });
     info!("SMB probing: {}", if config.detection.enable_smb_probing { "enabled" } else { "disabled" });
+    info!("HTTP probing: {}", if config.detection.enable_http_probing { "enabled" } else { "disabled" });
+
+    // Build probe identity
+    let probe_identity = config.probe_identity.to_identity()?;
+    if probe_identity.stealth {
+        info!("Probe stealth profile enabled");
+    }
 
     // Create hybrid detector
     let hybrid_config = HybridConfig {
         enable_smb_probing: config.detection.enable_smb_probing,
         smb_timeout_secs: config.detection.smb_timeout_secs,
-        smb_probe_confidence_threshold: config.detection.smb_probe_confidence_threshold,
         smb_cache_ttl_secs: config.detection.smb_cache_ttl_secs,
+        smb_policy: config.detection.smb_policy.to_policy()?,
+        smb1_fallback: config.detection.smb1_fallback,
+        enable_http_probing: config.detection.enable_http_probing,
+        http_timeout_secs: config.detection.http_timeout_secs,
+        http_cache_ttl_secs: config.detection.http_cache_ttl_secs,
+        negative_cache_ttl_secs: config.detection.negative_cache_ttl_secs,
+        source_weights: config.detection.weights.clone(),
+        probe_identity,
+        scheduler: SchedulerConfig {
+            // A zero-permit semaphore would leave every probe waiting forever
+            max_concurrent: config.detection.max_concurrent_probes.max(1),
+            max_queued: config.detection.max_queued_probes,
+            host_cooldown: std::time::Duration::from_secs(config.detection.probe_cooldown_secs),
+        },
     };
     let hybrid_detector = Arc::new(HybridDetector::new(hybrid_config));
-    info!("Hybrid detector initialized (SMB timeout: {}s, confidence threshold: {:.0}%)",
-        config.detection.smb_timeout_secs,
-        config.detection.smb_probe_confidence_threshold * 100.0
-    );
+    info!("Hybrid detector initialized (SMB timeout: {}s)", config.detection.smb_timeout_secs);
+
+    // Lookups, reloads and the updater all use the configured bundle file
+    if let Some(update_config) = &config.fingerprint_updates {
+        fingerprint::set_bundle_path(&update_config.bundle_path);
+    }
+
+    // Create the fingerprint updater if an upstream bundle source is configured
+    let fingerprint_updater = match &config.fingerprint_updates {
+        Some(update_config) => {
+            info!("Fingerprint updates enabled");
+            Some(Arc::new(update_config.to_updater()?))
+        }
+        None => None,
+    };
+
+    // Create the alert manager (dedup and flap suppression for all alert sources)
+    let alert_manager = Arc::new(AlertManager::new(AlertConfig {
+        cooldown_secs: config.alerts.cooldown_secs,
+        flap_window_secs: config.alerts.flap_window_secs,
+        flap_threshold: config.alerts.flap_threshold,
+    }));
+
+    // Create the anomaly detector
+    let anomaly_detector = if config.anomaly.enabled {
+        Some(AnomalyDetector::new(config.anomaly.to_anomaly()))
+    } else {
+        info!("Anomaly detection disabled");
+        None
+    };
 
     // Create the logger
-    let logger = Arc::new(RequestLogger::new("request.json")?);
-    info!("Logging requests to request.json");
+    let log_config = &config.request_log;
+    let logger = if log_config.enabled {
+        let logger = match log_config.backend.as_str() {
+            "file" => {
+                info!("Logging requests to {}", log_config.path);
+                RequestLogger::new(&log_config.path, log_config.rotation.to_rotation())?
+            }
+            "syslog" => {
+                let syslog_config = log_config
+                    .syslog
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("request_log backend 'syslog' requires a [request_log.syslog] section"))?
+                    .to_syslog()?;
+                info!("Logging requests to syslog collector {} ({:?})", syslog_config.address, syslog_config.protocol);
+                RequestLogger::syslog(syslog_config)
+            }
+            "journald" => {
+                info!("Logging requests to the systemd journal");
+                RequestLogger::journald(&default_app_name())?
+            }
+            other => return Err(anyhow!("unknown request_log backend '{}'", other)),
+        };
+        Arc::new(logger.with_console(config.console.to_sink()?))
+    } else {
+        info!("Request file logging disabled");
+        Arc::new(RequestLogger::disabled().with_console(config.console.to_sink()?))
+    };
 
     // Create database pool
-    let db_pool = db::create_pool("sqlite:dhcp_monitor.db").await?;
-    info!("Database initialized at dhcp_monitor.db");
+    let db_pool = db::create_pool(&config.database.url).await?;
+    info!("Database initialized at {}", config.database.url);
+
+    // MAC mapping overrides saved through the API take precedence over mac_os_mapping.toml
+    match db::overrides::load_overrides(&db_pool).await {
+        Ok(overrides) => {
+            if !overrides.is_empty() {
+                info!("Loaded {} MAC mapping overrides", overrides.len());
+            }
+            fingerprint::install_mac_overrides(overrides);
+        }
+        Err(e) => warn!("Failed to load MAC mapping overrides: {}", e),
+    }
+
+    // Join the cluster; only the leader captures and probes
+    let cluster = match &config.cluster {
+        Some(section) => {
+            let cluster_config = section.to_cluster(&config.receive)?;
+            info!(
+                "Cluster mode: node {} (lease {}s)",
+                cluster_config.node_id,
+                cluster_config.lease_ttl.as_secs()
+            );
+            let election = Arc::new(LeaderElection::new(cluster_config, db_pool.clone()));
+            if !election.tick().await {
+                info!("Another node holds the leader lease, standing by");
+            }
+            election.spawn();
+            Some(election)
+        }
+        None => None,
+    };
+
+    // The monitor's own probes reach the listener too; keep them out of the request log
+    let active_scan = if config.active_scan.enabled && !generated {
+        let scan_config = config.active_scan.to_config()?;
+        info!(
+            "Active mode: broadcasting a {} probe every {}s as {}",
+            scan_config.probe.as_str(),
+            scan_config.interval.as_secs(),
+            scan_config.client_mac
+        );
+        Some(scan_config)
+    } else {
+        info!("Monitor-only mode: no DHCP packets are sent");
+        None
+    };
+    let mut capture_filter = config.capture.to_filter()?;
+    if let Some(scan_config) = &active_scan {
+        capture_filter.ignore_mac_prefixes.push(scan_config.client_mac.to_string());
+    }
+    if !capture_filter.is_empty() {
+        info!(
+            "Capture filters: {} ignored MAC prefixes, {} ignored vendor classes, {} captured subnets",
+            capture_filter.ignore_mac_prefixes.len(),
+            capture_filter.ignore_vendor_classes.len(),
+            capture_filter.only_subnets.len()
+        );
+    }
+
+    let dedup = match config.dedup.window_secs {
+        0 => None,
+        secs => {
+            info!("Folding identical requests within {}s into one row", secs);
+            Some(dedup::RequestDeduplicator::new(std::time::Duration::from_secs(secs)))
+        }
+    };
+
+    let new_devices = if config.new_devices.enabled {
+        let new_device_config = config.new_devices.to_config()?;
+        if let Some(url) = &new_device_config.webhook_url {
+            info!("Posting new device announcements to {}", url);
+        }
+        Some(new_device::NewDeviceTracker::new(new_device_config)?)
+    } else {
+        None
+    };
+
+    let neighbors = if config.arp.enabled {
+        info!("Reading {} every {}s for device presence", config.arp.path, config.arp.interval_secs);
+        let table = Arc::new(arp::NeighborTable::new(config.arp.to_config()));
+        arp::spawn(table.clone());
+        Some(table)
+    } else {
+        None
+    };
+
+    let presence = if config.presence.enabled {
+        info!(
+            "Tracking device presence: idle after {}s, offline after {}s{}",
+            config.presence.online_secs,
+            config.presence.offline_secs,
+            if config.presence.ping { ", pinging idle devices" } else { "" }
+        );
+        Some(Arc::new(presence::PresenceEngine::new(config.presence.to_config())))
+    } else {
+        None
+    };
+
+    let reverse_dns = if config.reverse_dns.enabled {
+        let rdns_config = config.reverse_dns.to_config()?;
+        match rdns_config.resolver {
+            Some(resolver) => info!("Resolving device names via {} (mDNS fallback: {})", resolver, rdns_config.mdns),
+            None => info!("Resolving device names via mDNS only"),
+        }
+        Some(Arc::new(rdns::ReverseResolver::new(rdns_config)))
+    } else {
+        None
+    };
+
+    let agents = config.ingest.to_registry()?;
+    if agents.len() > 0 {
+        info!("Accepting requests from {} branch-site agents", agents.len());
+    }
+
+    let script = match &config.scripting {
+        Some(scripting) => {
+            info!("Running policy script {} on each request", scripting.path);
+            Some(Arc::new(scripting.load()?))
+        }
+        None => None,
+    };
 
     // Create shared application state
-    let app_state = Arc::new(AppState::new(logger, db_pool, hybrid_detector));
+    let app_state = Arc::new(AppState::new(
+        logger,
+        db_pool,
+        hybrid_detector,
+        fingerprint_updater,
+        alert_manager,
+        anomaly_detector,
+        cluster,
+    )
+    .with_capture_filter(capture_filter)
+    .with_subnets(config.subnets.to_map()?)
+    .with_dedup(dedup)
+    .with_new_devices(new_devices)
+    .with_rate_limiter(RateLimiter::new(config.rate_limits.to_config()))
+    .with_proxy(config.web.to_proxy())
+    .with_admin_token(config.web.admin_token.clone().filter(|token| !token.trim().is_empty()))
+    .with_neighbors(neighbors)
+    .with_presence(presence)
+    .with_reverse_dns(reverse_dns)
+    .with_agents(agents)
+    .with_script(script)
+    .with_active_scan(active_scan)
+    .with_reverify(config.detection.reverify.enabled.then(|| config.detection.reverify.to_config()).transpose()?)
+    .with_email(config.notify.email.as_ref().map(|email| email.to_email()).transpose()?));
+
+    // Carry cumulative statistics and probe results across restarts, checkpointing them periodically
+    if let Err(e) = app_state.restore_stats().await {
+        warn!("Failed to restore statistics from the database: {}", e);
+    }
+    if let Err(e) = app_state.restore_identities().await {
+        warn!("Failed to restore device fingerprints from the database: {}", e);
+    }
+    if let Err(e) = app_state.restore_probe_cache().await {
+        warn!("Failed to restore cached probe results from the database: {}", e);
+    }
+    if let Some(presence) = &app_state.presence {
+        match db::queries::device_inventory(&app_state.db_pool).await {
+            Ok(devices) => presence.restore(&devices, chrono::Utc::now()),
+            Err(e) => warn!("Failed to restore device presence from the database: {}", e),
+        }
+    }
+    presence::spawn(app_state.clone());
+    let checkpoint_state = app_state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(STATS_CHECKPOINT_INTERVAL);
+        loop {
+            interval.tick().await;
+            let pool = &checkpoint_state.db_pool;
+            if let Err(e) = db::stats::save_checkpoint(pool, &chrono::Utc::now().to_rfc3339()).await {
+                debug!("Failed to checkpoint statistics: {}", e);
+            }
+            if let Err(e) = checkpoint_state.save_probe_cache().await {
+                debug!("Failed to save cached probe results: {}", e);
+            }
+        }
+    });
 
-    // Spawn UDP listener task
-    let udp_state = app_state.clone();
+    // SIGHUP re-reads mac_os_mapping.toml, the fingerprint bundle and config.toml
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+    let hangup_state = app_state.clone();
     tokio::spawn(async move {
-        if let Err(e) = run_udp_listener(udp_state).await {
-            error!("UDP listener error: {}", e);
+        while hangup.recv().await.is_some() {
+            match fingerprint::reload() {
+                Ok(summary) => info!(
+                    "SIGHUP: reloaded {} MAC mappings, {} classification rules and {} bundle signatures",
+                    summary.mac_mappings, summary.classification_rules, summary.bundle_signatures
+                ),
+                Err(e) => warn!("SIGHUP: reload failed, keeping current data: {}", e),
+            }
+            match reload::reload_file(&hangup_state) {
+                Ok(summary) => summary.log("SIGHUP"),
+                Err(e) => warn!("SIGHUP: {} is invalid, keeping current settings: {}", reload::CONFIG_PATH, e),
+            }
         }
     });
 
-    // Run web server (blocks on main thread)
-    info!("Starting web server on port {}", WEB_SERVER_PORT);
-    web::server::run_server(app_state, WEB_SERVER_PORT).await?;
+    // Publish requests and device changes to MQTT
+    if let Some(mqtt_config) = &config.mqtt {
+        let mqtt_config = mqtt_config.to_mqtt()?;
+        info!(
+            "Publishing to MQTT broker {}:{} under {}/",
+            mqtt_config.host, mqtt_config.port, mqtt_config.topic_prefix
+        );
+        mqtt::spawn(mqtt_config, app_state.broadcast_tx.subscribe(), app_state.capture.clone())?;
+    }
+
+    // Push per-interval counts to InfluxDB
+    if let Some(influx_config) = &config.influx {
+        let influx_config = influx_config.to_influx()?;
+        info!(
+            "Writing metrics to {} every {}s",
+            influx_config.url,
+            influx_config.flush_interval.as_secs()
+        );
+        influx::spawn(influx_config, app_state.clone())?;
+    }
+
+    // Email alerts to administrators
+    if let Some(email) = &app_state.email {
+        let email_config = email.borrow().clone();
+        info!(
+            "Emailing alerts via {}:{} (digest every {}s)",
+            email_config.host, email_config.port, email_config.digest.as_secs()
+        );
+        notify::email::spawn(email.subscribe(), app_state.alert_tx.subscribe(), app_state.capture.clone())?;
+    }
+
+    if let Some(simulation) = simulation {
+        let receive_config = config.receive.to_config()?;
+        info!(
+            "Simulating {} devices at {} packets/s into {} workers",
+            simulation.devices, simulation.rate, receive_config.workers
+        );
+        let traffic = simulate::SimulatedTraffic::new(&simulation)?;
+        tokio::spawn(run_simulation_feed(app_state.clone(), simulation, traffic, receive_config));
+    } else if demo {
+        // Seed a day of history on first run, then keep the live feed going
+        let mut traffic = DemoTraffic::new(rand::random(), 25);
+        let existing = db::queries::count_requests(&app_state.db_pool, &Default::default()).await?;
+        if existing == 0 {
+            demo::seed_history(&app_state, &mut traffic, 2000, chrono::Duration::hours(24)).await?;
+            info!("Seeded demo database with 2000 requests");
+        }
+        tokio::spawn(run_demo_feed(app_state.clone(), traffic));
+    } else {
+        active_scan::spawn(app_state.clone());
+        if config.sweep.enabled {
+            let sweep_config = config.sweep.to_config(&config.arp.path)?;
+            info!(
+                "Sweeping {} subnets by {} every {}s",
+                sweep_config.subnets.len(),
+                sweep_config.method.as_str(),
+                sweep_config.interval.as_secs()
+            );
+            sweep::spawn(app_state.clone(), sweep_config);
+        }
+        if let Some(reverify_config) = &app_state.reverify {
+            info!(
+                "Re-verifying detections below {} confidence every {}s (half-life {}h)",
+                reverify_config.threshold,
+                reverify_config.interval.as_secs(),
+                reverify_config.half_life.as_secs() / 3600
+            );
+            reverify::spawn(app_state.clone(), reverify_config.clone());
+        }
+
+        // Spawn UDP listener task
+        let udp_state = app_state.clone();
+        let receive_config = config.receive.to_config()?;
+        info!(
+            "Processing packets with {} workers (queue of {}, {} on overflow)",
+            receive_config.workers,
+            receive_config.queue_size,
+            receive_config.overflow.as_str()
+        );
+        let interface = config.receive.interface.clone();
+        tokio::spawn(async move {
+            if let Err(e) = run_listener(udp_state.clone(), receive_config, interface).await {
+                udp_state.listener.mark_failed();
+                error!("DHCP listener error: {}", e);
+            }
+        });
+    }
+
+    // Create the HTTP access logger
+    let access_config = &config.access_log;
+    let access_logger = if access_config.enabled {
+        let logger = AccessLogger::new(
+            &access_config.path,
+            access_config.rotation.to_rotation(),
+            access_config.principal_header.as_deref(),
+        )?
+        .with_proxy(config.web.to_proxy());
+        info!("Logging HTTP access to {}", access_config.path);
+        Some(Arc::new(logger))
+    } else {
+        None
+    };
+
+    // Typed API for NAC systems and other services
+    #[cfg(feature = "grpc")]
+    if config.grpc.enabled {
+        let grpc_state = app_state.clone();
+        let addr = std::net::SocketAddr::new(config.grpc.bind_address, config.grpc.port);
+        tokio::spawn(async move {
+            if let Err(e) = web::grpc::run_server(grpc_state, addr).await {
+                error!("gRPC server error: {}", e);
+            }
+        });
+    }
+
+    // Tell systemd once requests are being received, then keep its watchdog fed
+    tokio::spawn(supervise_systemd(app_state.clone()));
+
+    // Run web server (blocks on main thread) until asked to stop
+    let shutdown_state = app_state.clone();
+    tokio::select! {
+        result = web::server::run_server(app_state, config.web.to_listen(), access_logger) => result?,
+        result = shutdown_signal() => {
+            result?;
+            info!("Shutting down");
+            // Probe results since the last checkpoint would otherwise be probed again
+            if let Err(e) = shutdown_state.save_probe_cache().await {
+                warn!("Failed to save cached probe results: {}", e);
+            }
+        }
+    }
 
     Ok(())
 }
 
-async fn run_udp_listener(state: Arc<AppState>) -> Result<()> {
-    info!("Starting DHCP listener on port {}", DHCP_SERVER_PORT);
+/// Resolves on SIGTERM or Ctrl-C
+async fn shutdown_signal() -> Result<()> {
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result?,
+        _ = terminate.recv() => {}
+    }
+    Ok(())
+}
 
-    let socket = UdpSocket::bind(format!("0.0.0.0:{}", DHCP_SERVER_PORT)).await?;
-    info!("Listening for DHCP requests on 0.0.0.0:{}", DHCP_SERVER_PORT);
+async fn run_listener(state: Arc<AppState>, config: receive::ReceiveConfig, interface: Option<String>) -> Result<()> {
+    info!("Starting DHCP listener on port {}", DHCP_SERVER_PORT);
 
-    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut socket = DhcpSocket::bind(DHCP_SERVER_PORT, interface.as_deref())
+        .await
+        .map_err(|e| anyhow!("{}: {}", interface.as_deref().unwrap_or("UDP port 67"), e))?;
+    match &interface {
+        Some(interface) => info!("Capturing DHCP requests to port {} from frames on {}", DHCP_SERVER_PORT, interface),
+        None => info!("Listening for DHCP requests on 0.0.0.0:{}", DHCP_SERVER_PORT),
+    }
+    state.listener.mark_bound();
 
+    let (pool, queue) = start_workers(&state, &config);
     loop {
-        match socket.recv_from(&mut buffer).await {
-            Ok((len, source)) => {
-                let data = buffer[..len].to_vec();
-                let state = state.clone();
-
-                // Spawn a task to handle the request
-                tokio::spawn(async move {
-                    if let Err(e) = handle_dhcp_request(data, source, state).await {
-                        error!("Error handling DHCP request: {}", e);
-                    }
-                });
+        let mut buffer = pool.take();
+        match socket.recv(buffer.space()).await {
+            Ok(Received { len, source, vlan_id }) => {
+                state.listener.record_packet();
+
+                // Standby nodes keep the socket open so failover is immediate
+                if !state.is_active() {
+                    continue;
+                }
+                state.capture.record_received();
+
+                // A datagram that fills the buffer was probably cut short
+                if len == buffer.capacity() {
+                    state.capture.record_oversized();
+                    warn!("Dropping oversized packet from {} (at least {} bytes)", source, len);
+                    continue;
+                }
+
+                // With the block policy this waits while the queue is full
+                buffer.set_len(len);
+                let shed = queue.push(receive::Datagram { data: buffer, source, vlan_id }).await;
+                if shed > 0 {
+                    state.capture.record_shed(shed);
+                    debug!("Receive queue full, shed a packet ({})", config.overflow.as_str());
+                }
             }
             Err(e) => {
+                state.listener.record_error();
                 error!("Error receiving data: {}", e);
             }
         }
     }
 }
 
+/// The buffer pool and queue feeding `config.workers` tasks running handle_dhcp_request
+fn start_workers(
+    state: &Arc<AppState>,
+    config: &receive::ReceiveConfig,
+) -> (Arc<receive::BufferPool>, Arc<receive::PacketQueue>) {
+    // Buffers in the queue plus one per worker are all that can be in flight
+    let pool = receive::BufferPool::new(BUFFER_SIZE, config.queue_size + config.workers);
+    let queue = receive::PacketQueue::new(config.queue_size, config.overflow);
+    let worker_state = state.clone();
+    receive::spawn_workers(config.workers, queue.clone(), move |datagram: receive::Datagram| {
+        let state = worker_state.clone();
+        async move {
+            if let Err(e) = handle_dhcp_request(&datagram.data, datagram.source, datagram.vlan_id, state).await {
+                error!("Error handling DHCP request: {}", e);
+            }
+        }
+    });
+    (pool, queue)
+}
+
+/// READY=1 once the listener is up, then WATCHDOG=1 for as long as it stays up
+async fn supervise_systemd(state: Arc<AppState>) {
+    while !state.listener.is_up() {
+        if state.listener.status().failed {
+            let _ = systemd::notify("STATUS=DHCP listener failed to start");
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+    }
+    match systemd::notify("READY=1\nSTATUS=Monitoring DHCP requests") {
+        Ok(true) => info!("Notified systemd that the monitor is ready"),
+        Ok(false) => return,
+        Err(e) => {
+            warn!("Failed to notify systemd: {}", e);
+            return;
+        }
+    }
+
+    let Some(interval) = systemd::watchdog_interval() else {
+        return;
+    };
+    info!("systemd watchdog enabled ({}s)", interval.as_secs());
+    let mut ticker = tokio::time::interval(interval / 2);
+    loop {
+        ticker.tick().await;
+        // A stopped listener means no more captures; let systemd restart us
+        if !state.listener.is_up() {
+            warn!("DHCP listener is down, no longer feeding the systemd watchdog");
+            let _ = systemd::notify("STATUS=DHCP listener stopped");
+            return;
+        }
+        if let Err(e) = systemd::notify("WATCHDOG=1") {
+            debug!("Failed to send watchdog keep-alive: {}", e);
+        }
+    }
+}
+
+async fn run_demo_feed(state: Arc<AppState>, mut traffic: DemoTraffic) {
+    // The generated feed stands in for the listener in health checks
+    state.listener.mark_bound();
+    loop {
+        let event = traffic.next_event();
+        state.listener.record_packet();
+        state.capture.record_received();
+        if let Err(e) = handle_dhcp_request(&event.data, event.source, None, state.clone()).await {
+            error!("Error handling demo request: {}", e);
+        }
+        tokio::time::sleep(traffic.next_delay()).await;
+    }
+}
+
+/// Feed simulated traffic through the same queue and workers as captured packets
+async fn run_simulation_feed(
+    state: Arc<AppState>,
+    simulation: simulate::SimulateConfig,
+    mut traffic: simulate::SimulatedTraffic,
+    config: receive::ReceiveConfig,
+) {
+    state.listener.mark_bound();
+    let (pool, queue) = start_workers(&state, &config);
+    let mut pacer = simulate::Pacer::new(simulation.rate);
+    let mut progress = simulate::Progress::new();
+
+    while !simulation.finished(progress.sent) {
+        for _ in 0..pacer.next_batch().await {
+            if simulation.finished(progress.sent) {
+                break;
+            }
+            let event = traffic.next_event();
+            state.listener.record_packet();
+            state.capture.record_received();
+            let mut buffer = pool.take();
+            buffer.space()[..event.data.len()].copy_from_slice(&event.data);
+            buffer.set_len(event.data.len());
+            let shed = queue.push(receive::Datagram { data: buffer, source: event.source, vlan_id: None }).await;
+            if shed > 0 {
+                state.capture.record_shed(shed);
+                progress.failed += shed;
+            }
+            progress.sent += 1;
+        }
+        progress.report(false);
+    }
+    progress.report(true);
+}
+
+/// Capture on this host and forward everything to the central instance, receiving
+/// through the same socket, queue and workers as run_listener
+async fn run_agent(
+    config: agent::AgentConfig,
+    capture_filter: CaptureFilter,
+    subnets: SubnetMap,
+    receive_config: receive::ReceiveConfig,
+    interface: Option<String>,
+) -> Result<()> {
+    info!("Running as agent, forwarding requests to {}", config.central_url);
+    let forwarder = Arc::new(agent::Forwarder::new(config)?);
+    forwarder.clone().spawn();
+
+    let mut socket = DhcpSocket::bind(DHCP_SERVER_PORT, interface.as_deref())
+        .await
+        .map_err(|e| anyhow!("{}: {}", interface.as_deref().unwrap_or("UDP port 67"), e))?;
+    match &interface {
+        Some(interface) => info!("Capturing DHCP requests to port {} from frames on {}", DHCP_SERVER_PORT, interface),
+        None => info!("Listening for DHCP requests on 0.0.0.0:{}", DHCP_SERVER_PORT),
+    }
+    if let Err(e) = systemd::notify("READY=1\nSTATUS=Forwarding DHCP requests") {
+        warn!("Failed to notify systemd: {}", e);
+    }
+
+    let pool = receive::BufferPool::new(BUFFER_SIZE, receive_config.queue_size + receive_config.workers);
+    let queue = receive::PacketQueue::new(receive_config.queue_size, receive_config.overflow);
+    let capture_filter = Arc::new(capture_filter);
+    let subnets = Arc::new(subnets);
+    receive::spawn_workers(receive_config.workers, queue.clone(), move |datagram: receive::Datagram| {
+        let (forwarder, capture_filter, subnets) = (forwarder.clone(), capture_filter.clone(), subnets.clone());
+        async move {
+            if let Ok(mut request) = parse_request(&datagram.data, datagram.source, &capture_filter, &subnets) {
+                request.vlan_id = datagram.vlan_id;
+                forwarder.push(&request);
+            }
+        }
+    });
+
+    loop {
+        let mut buffer = pool.take();
+        match socket.recv(buffer.space()).await {
+            Ok(Received { len, source, vlan_id }) => {
+                if len == buffer.capacity() {
+                    warn!("Dropping oversized packet from {} (at least {} bytes)", source, len);
+                    continue;
+                }
+                buffer.set_len(len);
+                if queue.push(receive::Datagram { data: buffer, source, vlan_id }).await > 0 {
+                    debug!("Receive queue full, shed a packet ({})", receive_config.overflow.as_str());
+                }
+            }
+            Err(e) => error!("Error receiving data: {}", e),
+        }
+    }
+}
+
 async fn handle_dhcp_request(
-    data: Vec<u8>,
+    data: &[u8],
     source: SocketAddr,
+    vlan_id: Option<u16>,
     state: Arc<AppState>,
 ) -> Result<()> {
+    let mut request = match parse_request(data, source, &state.capture_filter(), &state.subnets) {
+        Ok(request) => request,
+        Err(Dropped::Malformed) => {
+            state.capture.record_malformed();
+            return Ok(());
+        }
+        Err(Dropped::Filtered) => return Ok(()),
+    };
+    request.vlan_id = vlan_id;
+
+    // Process request through state manager (handles logging, broadcasting, stats)
+    state.process_request(request).await?;
+
+    Ok(())
+}
+
+/// Why a packet produced no request
+#[derive(Debug, PartialEq)]
+enum Dropped {
+    Malformed,
+    Filtered,
+}
+
+/// Parse a packet into a request, unless it is malformed or filtered out
+fn parse_request(
+    data: &[u8],
+    source: SocketAddr,
+    capture_filter: &CaptureFilter,
+    subnets: &SubnetMap,
+) -> Result<DhcpRequest, Dropped> {
     // Parse the DHCP packet
-    let packet = match DhcpPacket::parse(&data) {
+    let packet = match DhcpPacket::parse(data) {
         Ok(p) => p,
         Err(e) => {
             warn!("Failed to parse DHCP packet from {}: {}", source, e);
-            return Ok(());
+            return Err(Dropped::Malformed);
         }
     };
 
+    let source_ipv4 = match source.ip() {
+        std::net::IpAddr::V4(ip) => ip,
+        std::net::IpAddr::V6(_) => std::net::Ipv4Addr::UNSPECIFIED,
+    };
+    if let Some(reason) = capture_filter.reject(&packet, source_ipv4) {
+        debug!("Ignoring packet from {}: {}", source, reason);
+        return Err(Dropped::Filtered);
+    }
+
     let message_type = packet.get_message_type();
     let mac = packet.get_mac_address();
 
@@ -192,74 +2505,355 @@ async fn handle_dhcp_request(
     );
 
     // Create request object
-    let request = DhcpRequest::from_packet(&packet, source.ip().to_string(), source.port());
-
-    // Extract options and ciaddr
-    let option_12 = packet.get_option(12);
-    let option_55 = packet.get_option(55);
-    let option_60 = packet.get_option(60);
-    let option_81 = packet.get_option(81);
-    let ciaddr = packet.ciaddr;
-
-    // Log relevant data to console as JSON if any field is present
-    if option_12.is_some() || option_55.is_some() || option_60.is_some() || option_81.is_some() || !ciaddr.is_unspecified() {
-        let mut options_json = serde_json::json!({
-            "mac_address": mac,
-            "source_ip": source.ip().to_string(),
-            "timestamp": chrono::Utc::now().to_rfc3339()
-        });
+    let mut request = DhcpRequest::from_packet(&packet, source.ip().to_string(), source.port());
+    request.subnet = subnets.attribute(&packet, source_ipv4);
 
-        // Add ciaddr if not 0.0.0.0
-        if !ciaddr.is_unspecified() {
-            options_json["ciaddr"] = serde_json::json!(ciaddr.to_string());
-        }
+    // Check the packet for RFC conformance problems
+    let issues = lint::lint_packet(data);
+    for issue in &issues {
+        debug!("Lint {} from {}: {}", issue.code, mac, issue.detail);
+    }
+    request.lint_issues = issues.into_iter().map(|issue| issue.code.to_string()).collect();
+    request.lint_issues.sort_unstable();
+    request.lint_issues.dedup();
+    // Only packets that are kept are copied out of the receive buffer
+    request.raw_packet = Some(data.to_vec());
+    Ok(request)
+}
 
-        // Add Option 12 (Hostname) if present
-        if let Some(opt12) = option_12 {
-            options_json["option_12"] = serde_json::json!(opt12.data);
-            options_json["option_12_hostname"] = serde_json::json!(
-                String::from_utf8_lossy(&opt12.data).to_string()
-            );
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // Add Option 55 if present
-        if let Some(opt55) = option_55 {
-            options_json["option_55"] = serde_json::json!(opt55.data);
-            options_json["option_55_csv"] = serde_json::json!(
-                opt55.data.iter()
-                    .map(|b| b.to_string())
-                    .collect::<Vec<_>>()
-                    .join(",")
-            );
-        }
+    #[test]
+    fn test_example_config_parses() {
+        let content = std::fs::read_to_string("config.toml").unwrap();
+        let config: Config = toml::from_str(&content).unwrap();
+        assert!(config.request_log.enabled);
+        assert_eq!(config.request_log.rotation.max_files, 7);
+        assert!(!config.access_log.enabled);
+        assert!(config.mqtt.is_none());
+        assert_eq!(config.logging.format, "text");
+        assert_eq!(config.anomaly.to_anomaly().starvation_macs, AnomalyConfig::default().starvation_macs);
+    }
 
-        // Add Option 60 if present
-        if let Some(opt60) = option_60 {
-            options_json["option_60"] = serde_json::json!(opt60.data);
-            options_json["option_60_string"] = serde_json::json!(
-                String::from_utf8_lossy(&opt60.data).to_string()
-            );
-        }
+    #[test]
+    fn test_check_config() {
+        let content = std::fs::read_to_string("config.toml").unwrap();
+        assert!(check_config(&content).is_ok());
+        // Startup without a config.toml uses the defaults, which must pass too
+        assert!(check_config("").is_ok());
+        assert!(check_config("[fingerprint_updates]\nurl = \"https://example.com/f.json\"\npublic_key = \"short\"").is_err());
 
-        // Add Option 81 (Client FQDN) if present
-        if let Some(opt81) = option_81 {
-            options_json["option_81"] = serde_json::json!(opt81.data);
-            // Parse Option 81 structure: Flags (1 byte) + RCODE1 (1 byte) + RCODE2 (1 byte) + Domain Name
-            if opt81.data.len() >= 3 {
-                let flags = opt81.data[0];
-                let fqdn_bytes = &opt81.data[3..];
-                options_json["option_81_flags"] = serde_json::json!(flags);
-                options_json["option_81_fqdn"] = serde_json::json!(
-                    String::from_utf8_lossy(fqdn_bytes).to_string()
-                );
-            }
-        }
+        let err = check_config("[request_log]\nbackend = \"syslog\"\n").unwrap_err();
+        assert!(err.to_string().contains("[request_log.syslog]"));
+        assert!(check_config("[logging]\nformat = \"xml\"\n").is_err());
+        assert!(check_config("[rate_limits]\nmax_websockets = \"many\"\n").is_err());
+    }
+
+    #[test]
+    fn test_smb_policy_section() {
+        let config: Config = toml::from_str(
+            r#"
+            [detection.smb_policy]
+            mode = "fingerprint"
+            allow_subnets = ["10.0.0.0/8"]
+            "#,
+        )
+        .unwrap();
 
-        println!("{}", serde_json::to_string_pretty(&options_json)?);
+        let policy = config.detection.smb_policy.to_policy().unwrap();
+        assert_eq!(policy.mode, ProbeMode::Fingerprint);
+        assert_eq!(policy.vendor_pattern.as_str(), "MSFT");
+        assert_eq!(policy.allow_subnets, vec![Subnet::parse("10.0.0.0/8").unwrap()]);
+        assert_eq!(Config::default().detection.smb_policy.to_policy().unwrap().mode, ProbeMode::Vendor);
+
+        // A mistyped entry fails the config rather than being dropped
+        assert!(check_config("[detection.smb_policy]\ndeny_subnets = [\"10.0.0.0/33\"]").is_err());
+        assert!(check_config("[detection.smb_policy]\nvendor_pattern = \"(unclosed\"").is_err());
+        assert!(check_config("[detection.smb_policy]\nmode = \"sometimes\"").is_err());
     }
 
-    // Process request through state manager (handles logging, broadcasting, stats)
-    state.process_request(request).await?;
+    #[test]
+    fn test_subnets_section() {
+        let config: Config = toml::from_str("[subnets]\nknown = [\"10.1.0.0/22\"]").unwrap();
+        assert!(config.subnets.to_map().is_ok());
+        assert!(check_config("[subnets]\nknown = [\"10.1.0.0/22\", \"10.2.0/16\"]").is_err());
+    }
 
-    Ok(())
+    #[test]
+    fn test_web_listen() {
+        assert_eq!(Config::default().web.to_listen(), Listen::Tcp("0.0.0.0:8080".parse().unwrap()));
+
+        let config: Config = toml::from_str("[web]\nbind_address = \"127.0.0.1\"\nport = 9090").unwrap();
+        assert_eq!(config.web.to_listen(), Listen::Tcp("127.0.0.1:9090".parse().unwrap()));
+
+        let config: Config = toml::from_str("[web]\nunix_socket = \"/run/dhcpmon/web.sock\"").unwrap();
+        assert_eq!(config.web.to_listen(), Listen::Unix("/run/dhcpmon/web.sock".into()));
+
+        assert!(check_config("[web]\nbind_address = \"localhost\"").is_err());
+    }
+
+    #[test]
+    fn test_grpc_section() {
+        let config: Config = toml::from_str("[grpc]\nenabled = true").unwrap();
+        assert_eq!(config.grpc.bind_address, std::net::IpAddr::from(std::net::Ipv4Addr::LOCALHOST));
+        assert_eq!(check_config("[grpc]\nenabled = true\nbind_address = \"10.0.0.2\"").is_ok(), cfg!(feature = "grpc"));
+    }
+
+    #[test]
+    fn test_active_scan_section() {
+        let config: Config = toml::from_str("[active_scan]\nenabled = true\nexpected_servers = [\"10.0.0.1\"]").unwrap();
+        let scan = config.active_scan.to_config().unwrap();
+        assert_eq!(scan.probe, active_scan::ProbeKind::Discover);
+        assert_eq!(scan.client_mac.to_string(), "02:00:00:d4:c9:01");
+        assert_eq!(scan.expected_servers, vec![std::net::Ipv4Addr::new(10, 0, 0, 1)]);
+
+        assert!(check_config("[active_scan]\nenabled = true\nprobe = \"inform\"").is_err());
+        assert!(check_config("[active_scan]\nenabled = true\nprobe = \"inform\"\nclient_ip = \"10.0.0.2\"").is_ok());
+        assert!(check_config("[active_scan]\nenabled = true\nprobe = \"request\"").is_err());
+        // Nothing is validated while the monitor stays passive
+        assert!(check_config("[active_scan]\nprobe = \"request\"").is_ok());
+    }
+
+    #[test]
+    fn test_sweep_section() {
+        let config: Config = toml::from_str("[sweep]\nenabled = true\nsubnets = [\"10.0.0.0/24\"]\nmethod = \"arp\"").unwrap();
+        let sweep_config = config.sweep.to_config(&config.arp.path).unwrap();
+        assert_eq!(sweep_config.method, sweep::SweepMethod::Arp);
+        assert_eq!(sweep_config.neighbor_path, std::path::PathBuf::from("/proc/net/arp"));
+
+        assert!(check_config("[sweep]\nenabled = true").is_err());
+        assert!(check_config("[sweep]\nenabled = true\nsubnets = [\"10.0.0.0/8\"]").is_err());
+        assert!(check_config("[sweep]\nenabled = true\nsubnets = [\"10.0.0.0/24\"]\nmethod = \"tcp\"").is_err());
+    }
+
+    #[test]
+    fn test_reverify_section() {
+        let config: Config = toml::from_str("[detection.reverify]\nenabled = true\nhalf_life_hours = 24").unwrap();
+        let reverify_config = config.detection.reverify.to_config().unwrap();
+        assert_eq!(reverify_config.half_life, std::time::Duration::from_secs(86400));
+        assert_eq!(reverify_config.threshold, 0.5);
+
+        assert!(check_config("[detection.reverify]\nenabled = true\nhalf_life_hours = 0").is_err());
+        assert!(check_config("[detection.reverify]\nenabled = true\nhalf_life_hours = inf").is_err());
+        assert!(check_config("[detection.reverify]\nenabled = true\nhalf_life_hours = 1e300").is_err());
+        assert!(check_config("[detection.reverify]\nenabled = true\nthreshold = 1.5").is_err());
+    }
+
+    #[test]
+    fn test_new_devices_section() {
+        let config: Config = toml::from_str("[new_devices]\nreannounce_after_hours = 48").unwrap();
+        assert_eq!(config.new_devices.to_config().unwrap().reannounce_after, Some(chrono::Duration::hours(48)));
+        assert!(check_config("[new_devices]\nreannounce_after_hours = 9223372036854775807").is_err());
+        // Out of range is only checked while announcements are on
+        assert!(check_config("[new_devices]\nenabled = false\nreannounce_after_hours = 9223372036854775807").is_ok());
+    }
+
+    #[test]
+    fn test_receive_section() {
+        assert_eq!(Config::default().receive.to_config().unwrap(), receive::ReceiveConfig::default());
+        let config: Config = toml::from_str("[receive]\nqueue_size = 0\noverflow = \"drop_oldest\"").unwrap();
+        let receive_config = config.receive.to_config().unwrap();
+        assert_eq!(receive_config.queue_size, 1);
+        assert_eq!(receive_config.overflow, receive::OverflowPolicy::DropOldest);
+        assert!(check_config("[receive]\noverflow = \"drop-random\"").is_err());
+    }
+
+    #[test]
+    fn test_capture_section() {
+        let config: Config = toml::from_str(
+            r#"
+            [capture]
+            ignore_mac_prefixes = ["00-15-5D"]
+            ignore_vendor_classes = ["^dhcpcd"]
+            only_subnets = ["10.1.0.0/16"]
+            "#,
+        )
+        .unwrap();
+
+        let filter = config.capture.to_filter().unwrap();
+        assert_eq!(filter.ignore_mac_prefixes, vec!["00:15:5d".to_string()]);
+        assert_eq!(filter.ignore_vendor_classes.len(), 1);
+        assert_eq!(filter.only_subnets, vec![Subnet::parse("10.1.0.0/16").unwrap()]);
+        assert!(Config::default().capture.to_filter().unwrap().is_empty());
+
+        // Dropping the only subnet would capture everything
+        assert!(check_config("[capture]\nonly_subnets = [\"bogus\"]").is_err());
+        assert!(check_config("[capture]\nignore_vendor_classes = [\"(unclosed\"]").is_err());
+    }
+
+    #[test]
+    fn test_mqtt_section() {
+        let config: Config = toml::from_str(
+            r#"
+            [mqtt]
+            host = "broker.local"
+            tls = true
+            qos = 1
+            topic_prefix = "home/dhcp/"
+
+            [mqtt.home_assistant]
+            consider_home_secs = 600
+            "#,
+        )
+        .unwrap();
+
+        let mqtt = config.mqtt.unwrap().to_mqtt().unwrap();
+        assert_eq!(mqtt.port, 1883);
+        assert_eq!(mqtt.topic_prefix, "home/dhcp");
+        assert_eq!(mqtt.qos, rumqttc::QoS::AtLeastOnce);
+        let ha = mqtt.home_assistant.unwrap();
+        assert_eq!(ha.discovery_prefix, "homeassistant");
+        assert_eq!(ha.consider_home.as_secs(), 600);
+    }
+
+    #[test]
+    fn test_influx_section() {
+        let config: Config = toml::from_str(
+            r#"
+            [influx]
+            url = "http://influx.local:8086/api/v2/write?org=home&bucket=dhcp"
+            token = "secret"
+
+            [influx.tags]
+            site = "home"
+            "#,
+        )
+        .unwrap();
+
+        let influx = config.influx.unwrap().to_influx().unwrap();
+        assert_eq!(influx.measurement_prefix, "dhcpmon");
+        assert_eq!(influx.flush_interval.as_secs(), 60);
+        assert_eq!(influx.tags, vec![("site".to_string(), "home".to_string())]);
+        assert!(check_config("[influx]\nurl = \"influx.local:8086\"\n").is_err());
+    }
+
+    #[test]
+    fn test_rate_limits_section() {
+        let config: Config = toml::from_str(
+            r#"
+            [rate_limits]
+            max_websockets = 5
+
+            [[rate_limits.endpoints]]
+            path = "/api/logs/export"
+            per_minute = 2
+            "#,
+        )
+        .unwrap();
+
+        let limits = config.rate_limits.to_config();
+        assert_eq!(limits.endpoints, vec![EndpointLimit { path: "/api/logs/export".to_string(), per_minute: 2, burst: 2 }]);
+        assert_eq!((limits.max_websockets, limits.max_websockets_per_client), (5, 10));
+        assert_eq!(Config::default().rate_limits.to_config().endpoints.len(), 3);
+    }
+
+    #[test]
+    fn test_web_section() {
+        let config: Config = toml::from_str(
+            r#"
+            [web]
+            base_path = "/dhcpmon/"
+            trust_forwarded_headers = true
+            cors_origins = ["https://grafana.example.com"]
+            "#,
+        )
+        .unwrap();
+
+        let proxy = config.web.to_proxy();
+        assert_eq!(proxy.base_path, "/dhcpmon");
+        assert!(proxy.trust_forwarded_headers && proxy.cors_layer().is_some());
+        assert!(Config::default().web.to_proxy().cors_layer().is_none());
+    }
+
+    #[test]
+    fn test_email_section() {
+        let config: Config = toml::from_str(
+            r#"
+            [notify.email]
+            host = "smtp.example.com"
+            security = "tls"
+            from = "dhcpmon@example.com"
+            to = ["admins@example.com"]
+            digest_secs = 0
+
+            [notify.email.routes]
+            rogue_dhcp_server = ["netops@example.com", "admins@example.com"]
+            "#,
+        )
+        .unwrap();
+
+        let email = config.notify.email.unwrap().to_email().unwrap();
+        assert_eq!(email.port, 465);
+        assert_eq!(email.security, SmtpSecurity::Tls);
+        assert_eq!(email.min_severity, alerts::Severity::Warning);
+        assert!(email.digest.is_zero());
+        assert_eq!(email.routes["rogue_dhcp_server"].len(), 2);
+        assert!(Config::default().notify.email.is_none());
+    }
+
+    #[test]
+    fn test_probe_identity_section() {
+        let config: Config = toml::from_str(
+            "[probe_identity]\nsource_port_range = [49152, 65535]\nntlm_workstation = \"DESKTOP-1\"",
+        )
+        .unwrap();
+        let identity = config.probe_identity.to_identity().unwrap();
+        assert_eq!(identity.source_ports, Some((49152, 65535)));
+        assert_eq!(identity.workstation.as_deref(), Some("DESKTOP-1"));
+
+        assert!(check_config("[probe_identity]\nsource_port_range = [65535, 49152]").is_err());
+        assert!(check_config("[probe_identity]\nntlm_workstation = \"not a netbios name\"").is_err());
+    }
+
+    #[test]
+    fn test_cluster_section() {
+        let config: Config = toml::from_str(
+            r#"
+            [database]
+            url = "sqlite:/srv/shared/dhcp_monitor.db"
+
+            [cluster]
+            node_id = "monitor-b"
+
+            [receive]
+            interface = "eth1"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.database.url, "sqlite:/srv/shared/dhcp_monitor.db");
+        let cluster = config.cluster.unwrap().to_cluster(&config.receive).unwrap();
+        assert_eq!(cluster.node_id, "monitor-b");
+        assert_eq!(cluster.lease_ttl.as_secs(), 15);
+
+        assert!(check_config("[cluster]\nnode_id = \"monitor-b\"").is_err());
+        assert!(check_config("[cluster]\n\n[receive]\ninterface = \"eth1\"").is_err());
+        assert!(check_config("[cluster]\nnode_id = \" \"\n\n[receive]\ninterface = \"eth1\"").is_err());
+        assert!(check_config("[cluster]\nnode_id = \"monitor-b\"\n\n[receive]\ninterface = \"eth1\"").is_ok());
+    }
+
+    #[test]
+    fn test_request_log_syslog_section() {
+        let config: Config = toml::from_str(
+            r#"
+            [request_log]
+            backend = "syslog"
+            max_size_mb = 10
+
+            [request_log.syslog]
+            address = "127.0.0.1:514"
+            protocol = "tcp"
+            hostname = "probe1"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.request_log.rotation.max_size_mb, 10);
+        let syslog = config.request_log.syslog.unwrap().to_syslog().unwrap();
+        assert_eq!(syslog.protocol, SyslogProtocol::Tcp);
+        assert_eq!(syslog.facility, 16);
+        assert_eq!(syslog.hostname, "probe1");
+    }
 }
@@ -0,0 +1,440 @@
+use crate::dhcp::DhcpRequest;
+use crate::web::stats::CaptureCounters;
+use anyhow::{anyhow, Result};
+use rumqttc::{AsyncClient, MqttOptions, QoS, TlsConfiguration, Transport};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Outgoing messages buffered while the broker is unreachable
+const CLIENT_QUEUE_SIZE: usize = 1000;
+
+/// Connection and topic settings for MQTT publishing
+#[derive(Debug, Clone)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub tls: bool,
+    /// PEM CA bundle for the broker certificate (default: system roots)
+    pub ca_file: Option<String>,
+    /// Topics are `<prefix>/requests` and `<prefix>/devices/<mac>`
+    pub topic_prefix: String,
+    pub qos: QoS,
+    /// Publish Home Assistant discovery and presence when set
+    pub home_assistant: Option<HomeAssistantConfig>,
+}
+
+/// Home Assistant MQTT discovery settings
+#[derive(Debug, Clone)]
+pub struct HomeAssistantConfig {
+    pub discovery_prefix: String,
+    /// A device is marked not_home after this long without DHCP activity
+    pub consider_home: Duration,
+}
+
+pub fn qos_from_level(level: u8) -> Option<QoS> {
+    match level {
+        0 => Some(QoS::AtMostOnce),
+        1 => Some(QoS::AtLeastOnce),
+        2 => Some(QoS::ExactlyOnce),
+        _ => None,
+    }
+}
+
+/// Retained per-device message, published when a device joins or its details change
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeviceState {
+    /// "joined" or "changed"
+    pub event: &'static str,
+    pub mac_address: String,
+    pub ip_address: Option<String>,
+    pub os_name: Option<String>,
+    pub device_class: Option<String>,
+    pub vendor_class: Option<String>,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+/// Remembers the last published state per MAC to detect joins and changes
+#[derive(Default)]
+pub struct DeviceTracker {
+    devices: HashMap<String, DeviceState>,
+}
+
+impl DeviceTracker {
+    /// Returns the new state when it should be published
+    pub fn observe(&mut self, request: &DhcpRequest) -> Option<DeviceState> {
+        let ip_address = (request.source_ip != "0.0.0.0" && !request.source_ip.is_empty())
+            .then(|| request.source_ip.clone());
+
+        match self.devices.get_mut(&request.mac_address) {
+            None => {
+                let state = DeviceState {
+                    event: "joined",
+                    mac_address: request.mac_address.clone(),
+                    ip_address,
+                    os_name: request.os_name.clone(),
+                    device_class: request.device_class.clone(),
+                    vendor_class: request.vendor_class.clone(),
+                    first_seen: request.timestamp.clone(),
+                    last_seen: request.timestamp.clone(),
+                };
+                self.devices.insert(request.mac_address.clone(), state.clone());
+                Some(state)
+            }
+            Some(state) => {
+                let mut changed = false;
+                // Keep the last known address when the device asks from 0.0.0.0
+                if ip_address.is_some() && state.ip_address != ip_address {
+                    state.ip_address = ip_address;
+                    changed = true;
+                }
+                if state.os_name != request.os_name {
+                    state.os_name = request.os_name.clone();
+                    changed = true;
+                }
+                if state.device_class != request.device_class {
+                    state.device_class = request.device_class.clone();
+                    changed = true;
+                }
+                if state.vendor_class != request.vendor_class {
+                    state.vendor_class = request.vendor_class.clone();
+                    changed = true;
+                }
+                state.last_seen = request.timestamp.clone();
+
+                if changed {
+                    state.event = "changed";
+                    Some(state.clone())
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+struct Presence {
+    last_seen: Instant,
+    home: bool,
+    /// Name used in the last discovery message (None = not announced yet)
+    announced_name: Option<String>,
+}
+
+/// What to publish after DHCP activity from a device
+#[derive(Debug, PartialEq)]
+pub struct PresenceUpdate {
+    /// Discovery config must be (re)published with this name
+    pub announce: Option<String>,
+    /// State flipped to home
+    pub arrived: bool,
+}
+
+/// Tracks DHCP activity per MAC for Home Assistant device_tracker entities
+#[derive(Default)]
+pub struct PresenceTracker {
+    devices: HashMap<String, Presence>,
+}
+
+impl PresenceTracker {
+    pub fn seen(&mut self, mac: &str, name: &str, now: Instant) -> PresenceUpdate {
+        let presence = self.devices.entry(mac.to_string()).or_insert(Presence {
+            last_seen: now,
+            home: false,
+            announced_name: None,
+        });
+        presence.last_seen = now;
+
+        let announce = (presence.announced_name.as_deref() != Some(name)).then(|| {
+            presence.announced_name = Some(name.to_string());
+            name.to_string()
+        });
+        let arrived = !presence.home;
+        presence.home = true;
+
+        PresenceUpdate { announce, arrived }
+    }
+
+    /// MACs that just went quiet for longer than `timeout`
+    pub fn expire(&mut self, now: Instant, timeout: Duration) -> Vec<String> {
+        let mut left = Vec::new();
+        for (mac, presence) in self.devices.iter_mut() {
+            if presence.home && now.duration_since(presence.last_seen) > timeout {
+                presence.home = false;
+                left.push(mac.clone());
+            }
+        }
+        left
+    }
+}
+
+/// Entity-safe identifier derived from a MAC (or client-id) string
+fn object_id(mac: &str) -> String {
+    let id: String = mac
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    format!("dhcpmon_{}", id)
+}
+
+fn presence_topic(config: &MqttConfig, mac: &str) -> String {
+    format!("{}/devices/{}/presence", config.topic_prefix, mac)
+}
+
+/// Home Assistant discovery topic and config payload for a device_tracker entity
+pub fn discovery_message(
+    config: &MqttConfig,
+    ha: &HomeAssistantConfig,
+    mac: &str,
+    name: &str,
+) -> (String, serde_json::Value) {
+    let id = object_id(mac);
+    let topic = format!("{}/device_tracker/{}/config", ha.discovery_prefix, id);
+    let mut connections = Vec::new();
+    if mac.len() == 17 && mac.split(':').count() == 6 {
+        connections.push(serde_json::json!(["mac", mac]));
+    }
+
+    let payload = serde_json::json!({
+        "name": null,
+        "unique_id": id,
+        "object_id": id,
+        "state_topic": presence_topic(config, mac),
+        "payload_home": "home",
+        "payload_not_home": "not_home",
+        "source_type": "router",
+        "json_attributes_topic": format!("{}/devices/{}", config.topic_prefix, mac),
+        "device": {
+            "identifiers": [id],
+            "connections": connections,
+            "name": name,
+        },
+    });
+    (topic, payload)
+}
+
+fn publish(client: &AsyncClient, topic: &str, qos: QoS, retain: bool, payload: Vec<u8>) {
+    if let Err(e) = client.try_publish(topic, qos, retain, payload) {
+        tracing::debug!("MQTT publish to {} dropped: {}", topic, e);
+    }
+}
+
+fn build_options(config: &MqttConfig) -> Result<MqttOptions> {
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.clone().unwrap_or_default());
+    }
+
+    if config.tls {
+        let transport = match &config.ca_file {
+            Some(path) => {
+                let ca = std::fs::read(path)
+                    .map_err(|e| anyhow!("failed to read MQTT CA file {}: {}", path, e))?;
+                Transport::Tls(TlsConfiguration::Simple {
+                    ca,
+                    alpn: None,
+                    client_auth: None,
+                })
+            }
+            None => Transport::tls_with_default_config(),
+        };
+        options.set_transport(transport);
+    }
+
+    Ok(options)
+}
+
+/// Connect to the broker and publish every processed request and device change;
+/// requests missed by lagging count towards `capture`
+pub fn spawn(
+    config: MqttConfig,
+    mut rx: broadcast::Receiver<Arc<DhcpRequest>>,
+    capture: Arc<CaptureCounters>,
+) -> Result<()> {
+    let (client, mut eventloop) = AsyncClient::new(build_options(&config)?, CLIENT_QUEUE_SIZE);
+
+    // The event loop drives the connection and reconnects after errors
+    let broker = format!("{}:{}", config.host, config.port);
+    tokio::spawn(async move {
+        let mut connected = false;
+        loop {
+            match eventloop.poll().await {
+                Ok(rumqttc::Event::Incoming(rumqttc::Packet::ConnAck(_))) => {
+                    tracing::info!("Connected to MQTT broker {}", broker);
+                    connected = true;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    if connected {
+                        tracing::warn!("MQTT connection to {} lost: {}", broker, e);
+                        connected = false;
+                    } else {
+                        tracing::debug!("MQTT connection to {} failed: {}", broker, e);
+                    }
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        let requests_topic = format!("{}/requests", config.topic_prefix);
+        let mut tracker = DeviceTracker::default();
+        let mut presence = PresenceTracker::default();
+        let mut expiry = tokio::time::interval(Duration::from_secs(30));
+
+        loop {
+            let request = tokio::select! {
+                received = rx.recv() => match received {
+                    Ok(request) => request,
+                    Err(RecvError::Lagged(skipped)) => {
+                        capture.record_lagged(skipped);
+                        tracing::warn!("MQTT publisher lagging, skipped {} requests", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = expiry.tick() => {
+                    if let Some(ha) = &config.home_assistant {
+                        for mac in presence.expire(Instant::now(), ha.consider_home) {
+                            publish(&client, &presence_topic(&config, &mac), config.qos, true, b"not_home".to_vec());
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            match serde_json::to_vec(&*request) {
+                Ok(payload) => publish(&client, &requests_topic, config.qos, false, payload),
+                Err(e) => tracing::warn!("Failed to serialize request for MQTT: {}", e),
+            }
+
+            if let Some(state) = tracker.observe(&request) {
+                let topic = format!("{}/devices/{}", config.topic_prefix, state.mac_address);
+                match serde_json::to_vec(&state) {
+                    // Retained so subscribers get the current state on connect
+                    Ok(payload) => publish(&client, &topic, config.qos, true, payload),
+                    Err(e) => tracing::warn!("Failed to serialize device state for MQTT: {}", e),
+                }
+            }
+
+            if let Some(ha) = &config.home_assistant {
+                let mac = &request.mac_address;
+                let name = request.hostname.clone().unwrap_or_else(|| mac.clone());
+                let update = presence.seen(mac, &name, Instant::now());
+
+                if let Some(name) = update.announce {
+                    let (topic, payload) = discovery_message(&config, ha, mac, &name);
+                    publish(&client, &topic, config.qos, true, payload.to_string().into_bytes());
+                }
+                if update.arrived {
+                    publish(&client, &presence_topic(&config, mac), config.qos, true, b"home".to_vec());
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(ip: &str, os: &str, timestamp: &str) -> DhcpRequest {
+        DhcpRequest {
+            timestamp: timestamp.to_string(),
+            source_ip: ip.to_string(),
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            os_name: Some(os.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_device_tracker_events() {
+        let mut tracker = DeviceTracker::default();
+
+        let joined = tracker.observe(&request("0.0.0.0", "Windows", "t1")).unwrap();
+        assert_eq!(joined.event, "joined");
+        assert_eq!(joined.ip_address, None);
+
+        // Same details again: nothing to publish
+        assert!(tracker.observe(&request("0.0.0.0", "Windows", "t2")).is_none());
+
+        let changed = tracker.observe(&request("10.0.0.5", "Windows", "t3")).unwrap();
+        assert_eq!(changed.event, "changed");
+        assert_eq!(changed.ip_address.as_deref(), Some("10.0.0.5"));
+        assert_eq!(changed.first_seen, "t1");
+
+        // Asking from 0.0.0.0 later keeps the known address
+        assert!(tracker.observe(&request("0.0.0.0", "Windows", "t4")).is_none());
+
+        let changed = tracker.observe(&request("10.0.0.5", "Windows 11", "t5")).unwrap();
+        assert_eq!(changed.os_name.as_deref(), Some("Windows 11"));
+        assert_eq!(changed.last_seen, "t5");
+    }
+
+    #[test]
+    fn test_presence_tracker() {
+        let mut presence = PresenceTracker::default();
+        let start = Instant::now();
+        let timeout = Duration::from_secs(600);
+
+        let update = presence.seen("aa:bb:cc:dd:ee:ff", "aa:bb:cc:dd:ee:ff", start);
+        assert_eq!(update, PresenceUpdate { announce: Some("aa:bb:cc:dd:ee:ff".to_string()), arrived: true });
+
+        // Hostname learned later: re-announce, but still home
+        let update = presence.seen("aa:bb:cc:dd:ee:ff", "laptop", start + Duration::from_secs(60));
+        assert_eq!(update, PresenceUpdate { announce: Some("laptop".to_string()), arrived: false });
+
+        assert!(presence.expire(start + Duration::from_secs(300), timeout).is_empty());
+        assert_eq!(presence.expire(start + Duration::from_secs(700), timeout), vec!["aa:bb:cc:dd:ee:ff"]);
+        // Only reported once
+        assert!(presence.expire(start + Duration::from_secs(800), timeout).is_empty());
+
+        let update = presence.seen("aa:bb:cc:dd:ee:ff", "laptop", start + Duration::from_secs(900));
+        assert_eq!(update, PresenceUpdate { announce: None, arrived: true });
+    }
+
+    #[test]
+    fn test_discovery_message() {
+        let config = MqttConfig {
+            host: "localhost".to_string(),
+            port: 1883,
+            client_id: "ks-dhcpmon".to_string(),
+            username: None,
+            password: None,
+            tls: false,
+            ca_file: None,
+            topic_prefix: "dhcpmon".to_string(),
+            qos: QoS::AtMostOnce,
+            home_assistant: None,
+        };
+        let ha = HomeAssistantConfig {
+            discovery_prefix: "homeassistant".to_string(),
+            consider_home: Duration::from_secs(600),
+        };
+
+        let (topic, payload) = discovery_message(&config, &ha, "AA:bb:cc:dd:ee:ff", "laptop");
+        assert_eq!(topic, "homeassistant/device_tracker/dhcpmon_aabbccddeeff/config");
+        assert_eq!(payload["state_topic"], "dhcpmon/devices/AA:bb:cc:dd:ee:ff/presence");
+        assert_eq!(payload["device"]["name"], "laptop");
+        assert_eq!(payload["device"]["connections"][0][0], "mac");
+    }
+
+    #[test]
+    fn test_qos_levels() {
+        assert_eq!(qos_from_level(1), Some(QoS::AtLeastOnce));
+        assert_eq!(qos_from_level(3), None);
+    }
+}
@@ -0,0 +1,201 @@
+//! Announcements of devices joining the network.
+//!
+//! The first request ever seen from a MAC raises a "new_device" alert, is pushed to
+//! WebSocket clients and, when configured, posted to a webhook. With a quiet period
+//! set, a device that was silent for longer than that is announced again on return.
+
+use crate::db::known_devices;
+use crate::dhcp::DhcpRequest;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+
+/// Upper bound on MACs whose last sighting is kept in memory; past it the
+/// cache is dropped and sightings are looked up in the database again
+const MAX_TRACKED_MACS: usize = 100_000;
+
+#[derive(Debug, Clone, Default)]
+pub struct NewDeviceConfig {
+    /// Silence after which a returning device is announced again (None: only the first time)
+    pub reannounce_after: Option<Duration>,
+    /// URL the event is POSTed to as JSON
+    pub webhook_url: Option<String>,
+}
+
+/// A device seen for the first time, or back after the quiet period
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NewDeviceEvent {
+    pub mac_address: String,
+    pub timestamp: String,
+    pub source_ip: String,
+    pub hostname: Option<String>,
+    pub vendor_class: Option<String>,
+    pub os_name: Option<String>,
+    pub device_class: Option<String>,
+    /// Last request before this one, for a returning device
+    pub previously_seen: Option<String>,
+}
+
+impl NewDeviceEvent {
+    fn new(request: &DhcpRequest, previously_seen: Option<String>) -> Self {
+        Self {
+            mac_address: request.mac_address.clone(),
+            timestamp: request.timestamp.clone(),
+            source_ip: request.source_ip.clone(),
+            hostname: request.hostname.clone(),
+            vendor_class: request.vendor_class.clone(),
+            os_name: request.os_name.clone(),
+            device_class: request.device_class.clone(),
+            previously_seen,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        let mut description = self.mac_address.clone();
+        if let Some(hostname) = &self.hostname {
+            description.push_str(&format!(" ({})", hostname));
+        }
+        if let Some(os_name) = &self.os_name {
+            description.push_str(&format!(", {}", os_name));
+        }
+        match &self.previously_seen {
+            Some(previously_seen) => format!("Device returned to the network: {}, last seen {}", description, previously_seen),
+            None => format!("New device joined the network: {}", description),
+        }
+    }
+}
+
+fn parse_time(timestamp: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(timestamp).ok().map(|t| t.with_timezone(&Utc))
+}
+
+pub struct NewDeviceTracker {
+    config: RwLock<NewDeviceConfig>,
+    client: reqwest::Client,
+    /// Latest sighting per MAC
+    last_seen: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+impl NewDeviceTracker {
+    pub fn new(config: NewDeviceConfig) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()?;
+        Ok(Self {
+            config: RwLock::new(config),
+            client,
+            last_seen: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn is_return(&self, previous: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        self.config.read().unwrap().reannounce_after.is_some_and(|quiet| now - previous >= quiet)
+    }
+
+    /// Replace the quiet period and webhook; sightings already tracked are kept
+    pub fn set_config(&self, config: NewDeviceConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    /// The event to announce for this request, if its device is new or returning.
+    /// Call before the request is stored.
+    pub async fn observe(&self, pool: &SqlitePool, request: &DhcpRequest) -> Result<Option<NewDeviceEvent>, sqlx::Error> {
+        let now = parse_time(&request.timestamp).unwrap_or_else(Utc::now);
+
+        let previous = {
+            let mut last_seen = self.last_seen.lock().unwrap();
+            if last_seen.len() >= MAX_TRACKED_MACS && !last_seen.contains_key(&request.mac_address) {
+                last_seen.clear();
+            }
+            let previous = last_seen.get(&request.mac_address).copied();
+            last_seen.insert(request.mac_address.clone(), previous.map_or(now, |p| p.max(now)));
+            previous
+        };
+
+        if let Some(previous) = previous {
+            let event = self
+                .is_return(previous, now)
+                .then(|| NewDeviceEvent::new(request, Some(previous.to_rfc3339())));
+            return Ok(event);
+        }
+
+        // Not in memory: first sighting since startup
+        if known_devices::first_seen(pool, &request.mac_address).await?.is_none() {
+            let inserted = known_devices::insert_known(pool, &request.mac_address, &request.timestamp).await?;
+            return Ok(inserted.then(|| NewDeviceEvent::new(request, None)));
+        }
+
+        let previously_seen = known_devices::last_request_before(pool, &request.mac_address, &request.timestamp).await?;
+        let returned = previously_seen
+            .as_deref()
+            .and_then(parse_time)
+            .is_some_and(|previous| self.is_return(previous, now));
+        Ok(returned.then(|| NewDeviceEvent::new(request, previously_seen)))
+    }
+
+    /// POST the event to the configured webhook in the background
+    pub fn notify_webhook(&self, event: &NewDeviceEvent) {
+        let Some(url) = self.config.read().unwrap().webhook_url.clone() else {
+            return;
+        };
+        let body = match serde_json::to_vec(event) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::debug!("Failed to serialize new device event: {}", e);
+                return;
+            }
+        };
+        let request = self
+            .client
+            .post(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body);
+        tokio::spawn(async move {
+            match request.send().await.and_then(|response| response.error_for_status()) {
+                Ok(_) => tracing::debug!("Posted new device event to {}", url),
+                Err(e) => tracing::warn!("New device webhook {} failed: {}", url, e),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(timestamp: &str) -> DhcpRequest {
+        DhcpRequest {
+            timestamp: timestamp.to_string(),
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            message_type: "DISCOVER".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_announces_new_and_returning_devices() {
+        let pool = crate::db::test_pool().await;
+        let config = NewDeviceConfig {
+            reannounce_after: Some(Duration::hours(1)),
+            webhook_url: None,
+        };
+        let tracker = NewDeviceTracker::new(config.clone()).unwrap();
+
+        let first = request("2025-01-01T00:00:00+00:00");
+        let event = tracker.observe(&pool, &first).await.unwrap().unwrap();
+        assert_eq!(event.previously_seen, None);
+        crate::db::queries::insert_request(&pool, &first).await.unwrap();
+
+        assert_eq!(tracker.observe(&pool, &request("2025-01-01T00:30:00+00:00")).await.unwrap(), None);
+        let returned = tracker.observe(&pool, &request("2025-01-01T02:00:00+00:00")).await.unwrap().unwrap();
+        assert_eq!(returned.previously_seen.as_deref(), Some("2025-01-01T00:30:00+00:00"));
+
+        // After a restart the last sighting comes from the stored requests
+        let restarted = NewDeviceTracker::new(config).unwrap();
+        assert_eq!(restarted.observe(&pool, &request("2025-01-01T00:45:00+00:00")).await.unwrap(), None);
+        let restarted = NewDeviceTracker::new(NewDeviceConfig::default()).unwrap();
+        assert_eq!(restarted.observe(&pool, &request("2025-01-02T00:00:00+00:00")).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,332 @@
+//! Alert emails over SMTP.
+//!
+//! Alerts at or above the configured severity are mailed to the recipients routed
+//! for their type. With a digest window set, alerts arriving within the window are
+//! batched into one message per recipient list instead of one message each.
+
+use crate::alerts::{Alert, Severity};
+use crate::web::stats::CaptureCounters;
+use anyhow::{anyhow, Result};
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, watch};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::Instant;
+
+/// Alerts listed in full in a digest; the rest are summarised by count
+const MAX_DIGEST_ALERTS: usize = 200;
+
+/// How the connection to the SMTP server is secured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpSecurity {
+    /// Implicit TLS from the first byte (usually port 465)
+    Tls,
+    /// Plain connection upgraded with STARTTLS (usually port 587)
+    StartTls,
+    /// No encryption, for a local relay
+    None,
+}
+
+impl SmtpSecurity {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "tls" => Some(Self::Tls),
+            "starttls" => Some(Self::StartTls),
+            "none" => Some(Self::None),
+            _ => None,
+        }
+    }
+}
+
+/// SMTP server, sender and routing settings for alert emails
+#[derive(Debug, Clone)]
+pub struct EmailConfig {
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    /// Recipients for alert types without a route
+    pub to: Vec<String>,
+    /// Recipients per alert type; an empty list mutes that type
+    pub routes: HashMap<String, Vec<String>>,
+    /// Alerts below this severity are not mailed
+    pub min_severity: Severity,
+    /// Batch alerts arriving within this long into one message (zero sends each alert at once)
+    pub digest: Duration,
+}
+
+impl EmailConfig {
+    /// Who should receive this alert; empty when it isn't mailed
+    pub fn recipients(&self, alert: &Alert) -> &[String] {
+        if alert.severity < self.min_severity {
+            return &[];
+        }
+        self.routes.get(&alert.alert_type).unwrap_or(&self.to)
+    }
+}
+
+/// Subject and plain-text body for one or more alerts
+pub fn compose(alerts: &[Alert]) -> (String, String) {
+    let subject = match alerts {
+        [alert] => format!(
+            "[ks-dhcpmon] {} {}: {}",
+            alert.severity.as_str().to_uppercase(),
+            alert.alert_type,
+            alert.message
+        ),
+        _ => {
+            let critical = alerts.iter().filter(|a| a.severity == Severity::Critical).count();
+            if critical > 0 {
+                format!("[ks-dhcpmon] {} alerts ({} critical)", alerts.len(), critical)
+            } else {
+                format!("[ks-dhcpmon] {} alerts", alerts.len())
+            }
+        }
+    };
+
+    let mut body = String::new();
+    for alert in alerts.iter().take(MAX_DIGEST_ALERTS) {
+        let flapping = if alert.flapping { ", flapping" } else { "" };
+        body.push_str(&format!(
+            "[{}] {} ({})\n  {}\n  seen {} times{}, first {}, last {}\n\n",
+            alert.severity.as_str(),
+            alert.alert_type,
+            alert.key,
+            alert.message,
+            alert.occurrences,
+            flapping,
+            alert.first_seen.to_rfc3339(),
+            alert.last_seen.to_rfc3339(),
+        ));
+    }
+    if alerts.len() > MAX_DIGEST_ALERTS {
+        body.push_str(&format!("... and {} more\n", alerts.len() - MAX_DIGEST_ALERTS));
+    }
+    (subject, body)
+}
+
+/// Alerts waiting for the digest window to close, grouped by recipient list
+#[derive(Debug, Default)]
+pub struct Digest {
+    pending: BTreeMap<Vec<String>, Vec<Alert>>,
+}
+
+impl Digest {
+    pub fn push(&mut self, recipients: &[String], alert: Alert) {
+        let mut key = recipients.to_vec();
+        key.sort();
+        key.dedup();
+        self.pending.entry(key).or_default().push(alert);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn take(&mut self) -> BTreeMap<Vec<String>, Vec<Alert>> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+pub struct EmailNotifier {
+    config: EmailConfig,
+    from: Mailbox,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl EmailNotifier {
+    pub fn new(config: EmailConfig) -> Result<Self> {
+        let from: Mailbox = config
+            .from
+            .parse()
+            .map_err(|e| anyhow!("invalid sender address {:?}: {}", config.from, e))?;
+        for address in config.to.iter().chain(config.routes.values().flatten()) {
+            address
+                .parse::<Mailbox>()
+                .map_err(|e| anyhow!("invalid recipient address {:?}: {}", address, e))?;
+        }
+
+        let mut builder = match config.security {
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+            SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?,
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+        }
+        .port(config.port)
+        .timeout(Some(Duration::from_secs(30)));
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(Self {
+            config,
+            from,
+            transport: builder.build(),
+        })
+    }
+
+    async fn send(&self, recipients: &[String], alerts: &[Alert]) -> Result<()> {
+        let (subject, body) = compose(alerts);
+        let mut message = Message::builder().from(self.from.clone()).subject(subject);
+        for recipient in recipients {
+            message = message.to(recipient.parse()?);
+        }
+        let message = message.header(ContentType::TEXT_PLAIN).body(body)?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+
+    async fn send_logged(&self, recipients: &[String], alerts: &[Alert]) {
+        match self.send(recipients, alerts).await {
+            Ok(()) => tracing::debug!("Emailed {} alert(s) to {}", alerts.len(), recipients.join(", ")),
+            Err(e) => tracing::warn!("Failed to email {} alert(s) via {}: {}", alerts.len(), self.config.host, e),
+        }
+    }
+}
+
+/// Mail notified alerts until the alert channel closes; alerts missed by lagging count towards `capture`.
+/// A new configuration sent on `config` (a reload) replaces the server and routing for later
+/// alerts, including those already waiting in a digest.
+pub fn spawn(
+    mut config: watch::Receiver<EmailConfig>,
+    mut rx: broadcast::Receiver<Alert>,
+    capture: Arc<CaptureCounters>,
+) -> Result<()> {
+    let mut notifier = EmailNotifier::new(config.borrow_and_update().clone())?;
+
+    tokio::spawn(async move {
+        let mut digest = Digest::default();
+        let mut deadline: Option<Instant> = None;
+        let mut reloadable = true;
+
+        loop {
+            let flush = async {
+                match deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                received = rx.recv() => match received {
+                    Ok(alert) => {
+                        let recipients = notifier.config.recipients(&alert).to_vec();
+                        if recipients.is_empty() {
+                            continue;
+                        }
+                        if notifier.config.digest.is_zero() {
+                            notifier.send_logged(&recipients, &[alert]).await;
+                        } else {
+                            if digest.is_empty() {
+                                deadline = Some(Instant::now() + notifier.config.digest);
+                            }
+                            digest.push(&recipients, alert);
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        capture.record_lagged(skipped);
+                        tracing::warn!("Email notifier lagging, skipped {} alerts", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = flush => {
+                    deadline = None;
+                    for (recipients, alerts) in digest.take() {
+                        notifier.send_logged(&recipients, &alerts).await;
+                    }
+                }
+                changed = config.changed(), if reloadable => {
+                    if changed.is_err() {
+                        reloadable = false;
+                        continue;
+                    }
+                    match EmailNotifier::new(config.borrow_and_update().clone()) {
+                        Ok(reloaded) => notifier = reloaded,
+                        Err(e) => tracing::warn!("Keeping the previous email settings: {}", e),
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn alert(alert_type: &str, severity: Severity) -> Alert {
+        Alert {
+            alert_type: alert_type.to_string(),
+            key: "10.0.0.9".to_string(),
+            severity,
+            message: format!("{} seen", alert_type),
+            first_seen: Utc::now(),
+            last_seen: Utc::now(),
+            occurrences: 1,
+            flapping: false,
+        }
+    }
+
+    fn config() -> EmailConfig {
+        EmailConfig {
+            host: "localhost".to_string(),
+            port: 25,
+            security: SmtpSecurity::None,
+            username: None,
+            password: None,
+            from: "dhcpmon@example.com".to_string(),
+            to: vec!["admins@example.com".to_string()],
+            routes: HashMap::from([
+                ("rogue_dhcp_server".to_string(), vec!["netops@example.com".to_string()]),
+                ("fingerprint_changed".to_string(), vec![]),
+            ]),
+            min_severity: Severity::Warning,
+            digest: Duration::from_secs(300),
+        }
+    }
+
+    #[test]
+    fn test_alerts_are_routed_by_type_and_severity() {
+        let config = config();
+        assert_eq!(config.recipients(&alert("rogue_dhcp_server", Severity::Critical)), ["netops@example.com"]);
+        assert_eq!(config.recipients(&alert("nak_flood", Severity::Warning)), ["admins@example.com"]);
+        assert!(config.recipients(&alert("fingerprint_changed", Severity::Warning)).is_empty());
+        assert!(config.recipients(&alert("new_device", Severity::Info)).is_empty());
+    }
+
+    #[test]
+    fn test_digest_batches_by_recipients() {
+        let mut digest = Digest::default();
+        let admins = vec!["admins@example.com".to_string()];
+        digest.push(&admins, alert("nak_flood", Severity::Warning));
+        digest.push(&admins, alert("rogue_dhcp_server", Severity::Critical));
+        digest.push(&["netops@example.com".to_string()], alert("rogue_dhcp_server", Severity::Critical));
+
+        let batches = digest.take();
+        assert!(digest.is_empty());
+        assert_eq!(batches.len(), 2);
+        let (subject, body) = compose(&batches[&admins]);
+        assert_eq!(subject, "[ks-dhcpmon] 2 alerts (1 critical)");
+        assert!(body.contains("[critical] rogue_dhcp_server (10.0.0.9)"));
+
+        let (subject, _) = compose(&[alert("rogue_dhcp_server", Severity::Critical)]);
+        assert_eq!(subject, "[ks-dhcpmon] CRITICAL rogue_dhcp_server: rogue_dhcp_server seen");
+    }
+
+    #[test]
+    fn test_notifier_rejects_bad_addresses() {
+        let mut bad = config();
+        bad.to = vec!["not an address".to_string()];
+        assert!(EmailNotifier::new(bad).is_err());
+        assert!(EmailNotifier::new(config()).is_ok());
+    }
+}
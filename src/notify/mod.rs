@@ -0,0 +1,3 @@
+//! Notification channels that alerts are delivered to besides the dashboard.
+
+pub mod email;
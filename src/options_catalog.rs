@@ -0,0 +1,396 @@
+//! DHCP option names and typed decoders.
+//!
+//! Options are stored as raw bytes; this turns them into names and
+//! human-readable values (addresses, durations, strings, flags) for display.
+
+use crate::client_id::ClientId;
+use crate::dhcp::{client_arch_name, hex_colon, DhcpOption};
+use serde::Serialize;
+use std::net::Ipv4Addr;
+
+/// How an option's payload is encoded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OptionKind {
+    Ipv4,
+    Ipv4List,
+    /// Pairs of addresses (destination/router, address/mask)
+    Ipv4Pairs,
+    Seconds,
+    /// Signed offset from UTC in seconds
+    TimeOffset,
+    U8,
+    U16,
+    /// 1 byte, 0 or 1
+    Flag,
+    /// No payload; presence is the value
+    Present,
+    Text,
+    MessageType,
+    ParameterList,
+    ClientId,
+    ClientArch,
+    /// Option 125 enterprise blocks
+    VendorOptions,
+    Bytes,
+}
+
+use OptionKind::*;
+
+/// Option codes, names and encodings (RFC 2132 and later, IANA registry)
+const CATALOG: &[(u8, &str, OptionKind)] = &[
+    (1, "Subnet Mask", Ipv4),
+    (2, "Time Offset", TimeOffset),
+    (3, "Router", Ipv4List),
+    (4, "Time Server", Ipv4List),
+    (5, "Name Server", Ipv4List),
+    (6, "Domain Name Server", Ipv4List),
+    (7, "Log Server", Ipv4List),
+    (8, "Cookie Server", Ipv4List),
+    (9, "LPR Server", Ipv4List),
+    (10, "Impress Server", Ipv4List),
+    (11, "Resource Location Server", Ipv4List),
+    (12, "Host Name", Text),
+    (13, "Boot File Size", U16),
+    (14, "Merit Dump File", Text),
+    (15, "Domain Name", Text),
+    (16, "Swap Server", Ipv4),
+    (17, "Root Path", Text),
+    (18, "Extensions Path", Text),
+    (19, "IP Forwarding", Flag),
+    (20, "Non-Local Source Routing", Flag),
+    (21, "Policy Filter", Ipv4Pairs),
+    (22, "Maximum Datagram Reassembly Size", U16),
+    (23, "Default IP Time-to-live", U8),
+    (24, "Path MTU Aging Timeout", Seconds),
+    (25, "Path MTU Plateau Table", Bytes),
+    (26, "Interface MTU", U16),
+    (27, "All Subnets are Local", Flag),
+    (28, "Broadcast Address", Ipv4),
+    (29, "Perform Mask Discovery", Flag),
+    (30, "Mask Supplier", Flag),
+    (31, "Perform Router Discovery", Flag),
+    (32, "Router Solicitation Address", Ipv4),
+    (33, "Static Route", Ipv4Pairs),
+    (34, "Trailer Encapsulation", Flag),
+    (35, "ARP Cache Timeout", Seconds),
+    (36, "Ethernet Encapsulation", Flag),
+    (37, "TCP Default TTL", U8),
+    (38, "TCP Keepalive Interval", Seconds),
+    (39, "TCP Keepalive Garbage", Flag),
+    (40, "NIS Domain", Text),
+    (41, "NIS Servers", Ipv4List),
+    (42, "NTP Servers", Ipv4List),
+    (43, "Vendor Specific Information", Bytes),
+    (44, "NetBIOS Name Server", Ipv4List),
+    (45, "NetBIOS Datagram Distribution Server", Ipv4List),
+    (46, "NetBIOS Node Type", U8),
+    (47, "NetBIOS Scope", Text),
+    (48, "X Window Font Server", Ipv4List),
+    (49, "X Window Display Manager", Ipv4List),
+    (50, "Requested IP Address", Ipv4),
+    (51, "IP Address Lease Time", Seconds),
+    (52, "Option Overload", U8),
+    (53, "DHCP Message Type", MessageType),
+    (54, "Server Identifier", Ipv4),
+    (55, "Parameter Request List", ParameterList),
+    (56, "Message", Text),
+    (57, "Maximum DHCP Message Size", U16),
+    (58, "Renewal Time", Seconds),
+    (59, "Rebinding Time", Seconds),
+    (60, "Vendor Class Identifier", Text),
+    (61, "Client Identifier", ClientId),
+    (62, "NetWare/IP Domain", Text),
+    (63, "NetWare/IP Information", Bytes),
+    (64, "NIS+ Domain", Text),
+    (65, "NIS+ Servers", Ipv4List),
+    (66, "TFTP Server Name", Text),
+    (67, "Bootfile Name", Text),
+    (68, "Mobile IP Home Agent", Ipv4List),
+    (69, "SMTP Server", Ipv4List),
+    (70, "POP3 Server", Ipv4List),
+    (71, "NNTP Server", Ipv4List),
+    (72, "WWW Server", Ipv4List),
+    (73, "Finger Server", Ipv4List),
+    (74, "IRC Server", Ipv4List),
+    (75, "StreetTalk Server", Ipv4List),
+    (76, "StreetTalk Directory Assistance Server", Ipv4List),
+    (77, "User Class", Bytes),
+    (78, "SLP Directory Agent", Bytes),
+    (79, "SLP Service Scope", Bytes),
+    (80, "Rapid Commit", Present),
+    (81, "Client FQDN", Bytes),
+    (82, "Relay Agent Information", Bytes),
+    (83, "iSNS", Bytes),
+    (85, "NDS Servers", Ipv4List),
+    (86, "NDS Tree Name", Text),
+    (87, "NDS Context", Text),
+    (88, "BCMCS Controller Domain Name", Bytes),
+    (89, "BCMCS Controller Address", Ipv4List),
+    (90, "Authentication", Bytes),
+    (91, "Client Last Transaction Time", Seconds),
+    (92, "Associated IP", Ipv4List),
+    (93, "Client System Architecture", ClientArch),
+    (94, "Client Network Interface Identifier", Bytes),
+    (97, "Client Machine Identifier", Bytes),
+    (98, "User Authentication Protocol", Text),
+    (99, "GeoConf Civic", Bytes),
+    (100, "PCode", Text),
+    (101, "TCode", Text),
+    (108, "IPv6-Only Preferred", Seconds),
+    (112, "NetInfo Parent Server Address", Ipv4List),
+    (113, "NetInfo Parent Server Tag", Text),
+    (114, "Captive Portal", Text),
+    (116, "Auto-Configure", Flag),
+    (117, "Name Service Search", Bytes),
+    (118, "Subnet Selection", Ipv4),
+    (119, "Domain Search", Bytes),
+    (120, "SIP Servers", Bytes),
+    (121, "Classless Static Route", Bytes),
+    (122, "CableLabs Client Configuration", Bytes),
+    (123, "GeoConf", Bytes),
+    (124, "Vendor-Identifying Vendor Class", Bytes),
+    (125, "Vendor-Identifying Vendor-Specific Information", VendorOptions),
+    (136, "PANA Authentication Agent", Ipv4List),
+    (137, "LoST Server", Bytes),
+    (138, "CAPWAP Access Controller", Ipv4List),
+    (141, "SIP UA Configuration Service Domains", Bytes),
+    (142, "ANDSF IPv4 Address", Ipv4List),
+    (145, "Forcerenew Nonce Capable", Bytes),
+    (150, "TFTP Server Address", Ipv4List),
+    (175, "Etherboot", Bytes),
+    (209, "PXELINUX Configuration File", Text),
+    (210, "PXELINUX Path Prefix", Text),
+    (211, "PXELINUX Reboot Time", Seconds),
+    (249, "Microsoft Classless Static Route", Bytes),
+    (252, "Web Proxy Auto-Discovery", Text),
+];
+
+fn lookup(code: u8) -> Option<(&'static str, OptionKind)> {
+    CATALOG
+        .iter()
+        .find(|(c, _, _)| *c == code)
+        .map(|(_, name, kind)| (*name, *kind))
+}
+
+/// Name of a DHCP option code
+pub fn option_name(code: u8) -> Option<&'static str> {
+    lookup(code).map(|(name, _)| name)
+}
+
+/// An option with its name and decoded value
+#[derive(Debug, Clone, Serialize)]
+pub struct DecodedOption {
+    pub code: u8,
+    pub name: Option<&'static str>,
+    pub length: usize,
+    /// Typed value: string, number, boolean or list; raw hex when the payload doesn't fit the option's type
+    pub value: serde_json::Value,
+    /// Human-readable rendering of the value
+    pub display: String,
+}
+
+/// "86400" -> "1d", "5400" -> "1h 30m"
+fn format_duration(secs: u32) -> String {
+    if secs == u32::MAX {
+        return "infinite".to_string();
+    }
+    let parts: Vec<String> = [(86400, "d"), (3600, "h"), (60, "m"), (1, "s")]
+        .iter()
+        .scan(secs, |rest, (unit, suffix)| {
+            let count = *rest / unit;
+            *rest %= unit;
+            Some((count, suffix))
+        })
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, suffix)| format!("{}{}", count, suffix))
+        .collect();
+    if parts.is_empty() {
+        "0s".to_string()
+    } else {
+        parts.join(" ")
+    }
+}
+
+fn addresses(data: &[u8]) -> Option<Vec<String>> {
+    if data.is_empty() || !data.len().is_multiple_of(4) {
+        return None;
+    }
+    Some(
+        data.chunks(4)
+            .map(|octets| Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]).to_string())
+            .collect(),
+    )
+}
+
+fn message_type_name(value: u8) -> &'static str {
+    match value {
+        1 => "DHCPDISCOVER",
+        2 => "DHCPOFFER",
+        3 => "DHCPREQUEST",
+        4 => "DHCPDECLINE",
+        5 => "DHCPACK",
+        6 => "DHCPNAK",
+        7 => "DHCPRELEASE",
+        8 => "DHCPINFORM",
+        9 => "DHCPFORCERENEW",
+        10 => "DHCPLEASEQUERY",
+        _ => "unknown",
+    }
+}
+
+/// Decode according to `kind`, or None when the payload doesn't fit it
+fn decode_value(kind: OptionKind, data: &[u8]) -> Option<(serde_json::Value, String)> {
+    use serde_json::json;
+
+    match kind {
+        Ipv4 => {
+            let address = addresses(data).filter(|list| list.len() == 1)?.remove(0);
+            Some((json!(address), address))
+        }
+        Ipv4List => {
+            let list = addresses(data)?;
+            let display = list.join(", ");
+            Some((json!(list), display))
+        }
+        Ipv4Pairs => {
+            let list = addresses(data).filter(|list| list.len().is_multiple_of(2))?;
+            let display = list.chunks(2).map(|pair| format!("{} / {}", pair[0], pair[1])).collect::<Vec<_>>().join(", ");
+            Some((json!(list), display))
+        }
+        Seconds => {
+            let secs = u32::from_be_bytes(data.try_into().ok()?);
+            Some((json!(secs), format!("{}s ({})", secs, format_duration(secs))))
+        }
+        TimeOffset => {
+            let secs = i32::from_be_bytes(data.try_into().ok()?);
+            Some((json!(secs), format!("{}s", secs)))
+        }
+        U8 => {
+            let [value] = data else { return None };
+            Some((json!(value), value.to_string()))
+        }
+        U16 => {
+            let value = u16::from_be_bytes(data.try_into().ok()?);
+            Some((json!(value), value.to_string()))
+        }
+        Flag => match data {
+            [0] => Some((json!(false), "disabled".to_string())),
+            [1] => Some((json!(true), "enabled".to_string())),
+            _ => None,
+        },
+        Present => data.is_empty().then(|| (json!(true), "present".to_string())),
+        Text => {
+            let text = std::str::from_utf8(data).ok()?.trim_end_matches('\0');
+            Some((json!(text), text.to_string()))
+        }
+        MessageType => {
+            let [value] = data else { return None };
+            Some((json!(value), format!("{} ({})", message_type_name(*value), value)))
+        }
+        ParameterList => {
+            let display = data
+                .iter()
+                .map(|code| match option_name(*code) {
+                    Some(name) => format!("{} ({})", code, name),
+                    None => code.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            Some((json!(data), display))
+        }
+        ClientId => {
+            let id = ClientId::decode(data)?;
+            let display = id.key.clone();
+            Some((serde_json::to_value(id).ok()?, display))
+        }
+        ClientArch => {
+            if data.is_empty() || !data.len().is_multiple_of(2) {
+                return None;
+            }
+            let arches: Vec<u16> = data.chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+            let display = arches
+                .iter()
+                .map(|arch| format!("{} ({})", client_arch_name(*arch), arch))
+                .collect::<Vec<_>>()
+                .join(", ");
+            Some((json!(arches), display))
+        }
+        VendorOptions => {
+            let blocks = crate::vendor_options::decode(data);
+            if blocks.is_empty() {
+                return None;
+            }
+            let display = blocks
+                .iter()
+                .map(|block| {
+                    let sub_options = block
+                        .sub_options
+                        .iter()
+                        .map(|sub| format!("{}={}", sub.name.clone().unwrap_or_else(|| sub.code.to_string()), sub.value))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    match &block.vendor {
+                        Some(vendor) => format!("{} ({}): {}", block.enterprise, vendor, sub_options),
+                        None => format!("{}: {}", block.enterprise, sub_options),
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("; ");
+            Some((serde_json::to_value(blocks).ok()?, display))
+        }
+        Bytes => None,
+    }
+}
+
+/// Name and human-readable value of an option
+pub fn decode(option: &DhcpOption) -> DecodedOption {
+    let known = lookup(option.code);
+    let (value, display) = known
+        .and_then(|(_, kind)| decode_value(kind, &option.data))
+        .unwrap_or_else(|| {
+            let hex = hex_colon(&option.data);
+            (serde_json::json!(hex), hex)
+        });
+
+    DecodedOption {
+        code: option.code,
+        name: known.map(|(name, _)| name),
+        length: option.data.len(),
+        value,
+        display,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(code: u8, data: &[u8]) -> DecodedOption {
+        decode(&DhcpOption { code, data: data.to_vec() })
+    }
+
+    #[test]
+    fn test_decoders() {
+        assert_eq!(decoded(6, &[8, 8, 8, 8, 1, 1, 1, 1]).display, "8.8.8.8, 1.1.1.1");
+        assert_eq!(decoded(51, &[0, 1, 81, 128]).display, "86400s (1d)");
+        assert_eq!(decoded(51, &[0xff; 4]).display, "4294967295s (infinite)");
+        assert_eq!(decoded(12, b"laptop\0").value, serde_json::json!("laptop"));
+        assert_eq!(decoded(53, &[3]).display, "DHCPREQUEST (3)");
+        assert_eq!(decoded(55, &[1, 3]).display, "1 (Subnet Mask), 3 (Router)");
+        assert_eq!(decoded(116, &[1]).value, serde_json::json!(true));
+        assert_eq!(decoded(80, &[]).display, "present");
+        assert_eq!(decoded(93, &[0, 7]).display, "x64 UEFI (7)");
+        assert_eq!(decoded(125, &[0, 0, 0xa0, 0x98, 6, 1, 4, 10, 0, 0, 1]).display, "41112 (Ubiquiti): Controller Address=10.0.0.1");
+    }
+
+    #[test]
+    fn test_malformed_and_unknown_fall_back_to_hex() {
+        let bad_address = decoded(50, &[10, 0, 0]);
+        assert_eq!(bad_address.name, Some("Requested IP Address"));
+        assert_eq!(bad_address.display, "0a:00:00");
+
+        let unknown = decoded(230, &[1, 2]);
+        assert_eq!(unknown.name, None);
+        assert_eq!(unknown.display, "01:02");
+    }
+}
@@ -0,0 +1,129 @@
+//! Reconstruction of captured requests as a pcap file.
+//!
+//! Only the UDP payload is captured, so each packet is wrapped in synthesized
+//! Ethernet, IPv4 and UDP headers: from the client's MAC, source address and port
+//! to the broadcast address on port 67, with an 802.1Q tag when a VLAN was seen.
+
+use crate::dhcp::DhcpRequest;
+use std::net::Ipv4Addr;
+
+/// MIME type for pcap downloads
+pub const CONTENT_TYPE: &str = "application/vnd.tcpdump.pcap";
+
+const LINKTYPE_ETHERNET: u32 = 1;
+const SNAPLEN: u32 = 65535;
+const DHCP_SERVER_PORT: u16 = 67;
+
+pub fn global_header() -> Vec<u8> {
+    let mut header = Vec::with_capacity(24);
+    header.extend_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+    header.extend_from_slice(&2u16.to_le_bytes());
+    header.extend_from_slice(&4u16.to_le_bytes());
+    header.extend_from_slice(&0i32.to_le_bytes()); // thiszone
+    header.extend_from_slice(&0u32.to_le_bytes()); // sigfigs
+    header.extend_from_slice(&SNAPLEN.to_le_bytes());
+    header.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+    header
+}
+
+// Non-Ethernet client identifiers get an all-zero hardware address
+fn parse_mac(mac_address: &str) -> [u8; 6] {
+    mac_address.parse::<crate::mac::Mac>().map_or([0u8; 6], |mac| mac.octets())
+}
+
+/// RFC 1071 ones' complement sum over the IPv4 header
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// The request's payload as an Ethernet frame, or None when no payload was stored
+fn frame(request: &DhcpRequest) -> Option<Vec<u8>> {
+    let payload = request.raw_packet.as_deref()?;
+    let source: Ipv4Addr = request.source_ip.parse().unwrap_or(Ipv4Addr::UNSPECIFIED);
+    let udp_len = 8 + payload.len();
+    let ip_len = 20 + udp_len;
+
+    let mut frame = Vec::with_capacity(18 + ip_len);
+    frame.extend_from_slice(&[0xff; 6]);
+    frame.extend_from_slice(&parse_mac(&request.mac_address));
+    if let Some(vlan_id) = request.vlan_id {
+        frame.extend_from_slice(&0x8100u16.to_be_bytes());
+        frame.extend_from_slice(&(vlan_id & 0x0fff).to_be_bytes());
+    }
+    frame.extend_from_slice(&0x0800u16.to_be_bytes());
+
+    let mut ip = Vec::with_capacity(20);
+    ip.extend_from_slice(&[0x45, 0]);
+    ip.extend_from_slice(&(ip_len as u16).to_be_bytes());
+    ip.extend_from_slice(&[0, 0, 0, 0]); // identification, flags and fragment offset
+    ip.extend_from_slice(&[64, 17, 0, 0]); // TTL, UDP, checksum placeholder
+    ip.extend_from_slice(&source.octets());
+    ip.extend_from_slice(&Ipv4Addr::BROADCAST.octets());
+    let checksum = ipv4_checksum(&ip);
+    ip[10..12].copy_from_slice(&checksum.to_be_bytes());
+    frame.extend_from_slice(&ip);
+
+    frame.extend_from_slice(&request.source_port.to_be_bytes());
+    frame.extend_from_slice(&DHCP_SERVER_PORT.to_be_bytes());
+    frame.extend_from_slice(&(udp_len as u16).to_be_bytes());
+    frame.extend_from_slice(&[0, 0]); // no UDP checksum
+    frame.extend_from_slice(payload);
+    Some(frame)
+}
+
+/// One pcap record for the request, to follow `global_header`, or None when no
+/// payload was stored
+pub fn record(request: &DhcpRequest) -> Option<Vec<u8>> {
+    let frame = frame(request)?;
+    let time = chrono::DateTime::parse_from_rfc3339(&request.timestamp).unwrap_or_default();
+    let mut record = Vec::with_capacity(16 + frame.len());
+    record.extend_from_slice(&(time.timestamp() as u32).to_le_bytes());
+    record.extend_from_slice(&time.timestamp_subsec_micros().to_le_bytes());
+    record.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+    record.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+    record.extend_from_slice(&frame);
+    Some(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pcap_records() {
+        let stored = DhcpRequest {
+            timestamp: "2025-01-01T00:00:01.5+00:00".to_string(),
+            source_ip: "0.0.0.0".to_string(),
+            source_port: 68,
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            vlan_id: Some(20),
+            raw_packet: Some(vec![1, 2, 3, 4]),
+            ..Default::default()
+        };
+        assert!(record(&DhcpRequest::default()).is_none());
+        let mut pcap = global_header();
+        pcap.extend_from_slice(&record(&stored).unwrap());
+
+        assert_eq!(&pcap[..4], &[0xd4, 0xc3, 0xb2, 0xa1]);
+        let record = &pcap[24..];
+        assert_eq!(u32::from_le_bytes(record[0..4].try_into().unwrap()), 1_735_689_601);
+        assert_eq!(u32::from_le_bytes(record[4..8].try_into().unwrap()), 500_000);
+        // Ethernet (with 802.1Q tag) + IPv4 + UDP + payload
+        let frame_len = 18 + 20 + 8 + 4;
+        assert_eq!(u32::from_le_bytes(record[8..12].try_into().unwrap()), frame_len as u32);
+        assert_eq!(record.len(), 16 + frame_len);
+
+        let frame = &record[16..];
+        assert_eq!(&frame[6..12], &[0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]);
+        assert_eq!(&frame[12..16], &[0x81, 0x00, 0x00, 20]);
+        assert_eq!(ipv4_checksum(&frame[18..38]), 0);
+        assert_eq!(&frame[frame_len - 4..], &[1, 2, 3, 4]);
+    }
+}
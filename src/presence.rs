@@ -0,0 +1,438 @@
+//! Device presence: online, idle or offline.
+//!
+//! Each device's latest sign of life, whether a DHCP request, a sighting in the
+//! host's ARP table or an answered ping, decides its state: online while that is
+//! recent, idle once it ages past `online_timeout`, offline past `offline_timeout`
+//! or right after a DHCPRELEASE. With pinging on, idle devices are pinged so one
+//! that is still there goes back online before it would time out. Transitions are
+//! pushed to WebSocket clients as `{"type": "presence_changed", "presence": {...}}`.
+
+use crate::arp::NeighborTable;
+use crate::db::queries::DeviceSummary;
+use crate::dhcp::DhcpRequest;
+use crate::web::state::AppState;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::debug;
+
+/// Pings in flight at once during a sweep
+const PING_CONCURRENCY: usize = 32;
+/// Devices offline this long are forgotten so the map doesn't grow without limit
+const FORGET_AFTER: chrono::Duration = chrono::Duration::days(30);
+
+#[derive(Debug, Clone)]
+pub struct PresenceConfig {
+    /// Devices active this recently are online
+    pub online_timeout: Duration,
+    /// Devices silent longer than this are offline; in between they are idle
+    pub offline_timeout: Duration,
+    /// How often states are re-evaluated (and idle devices pinged)
+    pub interval: Duration,
+    /// Ping idle devices at their last known address
+    pub ping: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceState {
+    Online,
+    Idle,
+    Offline,
+}
+
+/// Presence of one device, as shown in /api/devices
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PresenceStatus {
+    pub state: PresenceState,
+    /// When the device entered this state
+    pub since: DateTime<Utc>,
+    pub last_activity: Option<DateTime<Utc>>,
+    /// What showed the last activity: "dhcp", "arp" or "ping"
+    pub last_source: Option<&'static str>,
+}
+
+/// A device changing state, pushed to WebSocket clients
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PresenceChange {
+    pub mac_address: String,
+    pub ip_address: Option<String>,
+    pub previous: PresenceState,
+    pub state: PresenceState,
+    /// "dhcp", "arp" or "ping" for activity, "release" or "timeout" otherwise
+    pub reason: &'static str,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+struct Track {
+    ip: Option<Ipv4Addr>,
+    last_activity: DateTime<Utc>,
+    last_source: &'static str,
+    /// A DHCPRELEASE after the last activity keeps the device offline
+    released: bool,
+    state: PresenceState,
+    since: DateTime<Utc>,
+}
+
+pub struct PresenceEngine {
+    config: PresenceConfig,
+    devices: RwLock<HashMap<String, Track>>,
+}
+
+impl PresenceEngine {
+    pub fn new(config: PresenceConfig) -> Self {
+        Self {
+            config,
+            devices: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn state_for(&self, track: &Track, now: DateTime<Utc>) -> PresenceState {
+        let age = (now - track.last_activity).to_std().unwrap_or_default();
+        if track.released || age > self.config.offline_timeout {
+            PresenceState::Offline
+        } else if age > self.config.online_timeout {
+            PresenceState::Idle
+        } else {
+            PresenceState::Online
+        }
+    }
+
+    /// Start from what the inventory knows, without announcing anything
+    pub fn restore(&self, devices: &[DeviceSummary], now: DateTime<Utc>) {
+        let mut tracked = self.devices.write().unwrap();
+        for device in devices {
+            let Ok(last_seen) = DateTime::parse_from_rfc3339(&device.last_seen) else {
+                continue;
+            };
+            let mut track = Track {
+                ip: device.assigned_ip.as_deref().or(device.last_ip.as_deref()).and_then(|ip| ip.parse().ok()),
+                last_activity: last_seen.with_timezone(&Utc),
+                last_source: "dhcp",
+                released: false,
+                state: PresenceState::Offline,
+                since: now,
+            };
+            track.state = self.state_for(&track, now);
+            tracked.entry(device.mac_address.clone()).or_insert(track);
+        }
+    }
+
+    /// A DHCP request from the device
+    pub fn record_request(&self, request: &DhcpRequest) -> Option<PresenceChange> {
+        let at = DateTime::parse_from_rfc3339(&request.timestamp)
+            .map(|t| t.with_timezone(&Utc))
+            .unwrap_or_else(|_| Utc::now());
+        // A relayed request comes from the relay's address
+        let ip = request
+            .assigned_ip
+            .as_ref()
+            .map(|(ip, _)| ip.as_str())
+            .or((!request.relayed).then_some(request.source_ip.as_str()))
+            .and_then(|ip| ip.parse::<Ipv4Addr>().ok())
+            .filter(|ip| !ip.is_unspecified());
+        if request.message_type == "RELEASE" {
+            return self.record_release(&request.mac_address, ip, at);
+        }
+        self.record_activity(&request.mac_address, ip, at, "dhcp")
+    }
+
+    fn record_release(&self, mac: &str, ip: Option<Ipv4Addr>, at: DateTime<Utc>) -> Option<PresenceChange> {
+        let mut devices = self.devices.write().unwrap();
+        let track = devices.entry(mac.to_string()).or_insert_with(|| Track {
+            ip,
+            last_activity: at,
+            last_source: "dhcp",
+            released: true,
+            state: PresenceState::Offline,
+            since: at,
+        });
+        track.released = true;
+        track.ip = ip.or(track.ip);
+        track.last_activity = track.last_activity.max(at);
+        transition(mac, track, PresenceState::Offline, "release", at)
+    }
+
+    /// Activity seen by `source` ("dhcp", "arp" or "ping"); None unless the state changes
+    pub fn record_activity(
+        &self,
+        mac: &str,
+        ip: Option<Ipv4Addr>,
+        at: DateTime<Utc>,
+        source: &'static str,
+    ) -> Option<PresenceChange> {
+        let mut devices = self.devices.write().unwrap();
+        let track = devices.entry(mac.to_string()).or_insert_with(|| Track {
+            ip,
+            last_activity: at,
+            last_source: source,
+            released: false,
+            state: PresenceState::Offline,
+            since: at,
+        });
+        if at < track.last_activity {
+            return None;
+        }
+        track.ip = ip.or(track.ip);
+        track.last_activity = at;
+        track.last_source = source;
+        track.released = false;
+        let state = self.state_for(track, at);
+        transition(mac, track, state, source, at)
+    }
+
+    /// Move devices whose activity aged past a timeout; forget long-gone ones
+    pub fn evaluate(&self, now: DateTime<Utc>) -> Vec<PresenceChange> {
+        let mut devices = self.devices.write().unwrap();
+        devices.retain(|_, track| now - track.last_activity <= FORGET_AFTER);
+        devices
+            .iter_mut()
+            .filter_map(|(mac, track)| {
+                let state = self.state_for(track, now);
+                transition(mac, track, state, "timeout", now)
+            })
+            .collect()
+    }
+
+    /// Take sightings from the host's ARP table for the tracked devices
+    pub fn merge_neighbors(&self, neighbors: &NeighborTable, now: DateTime<Utc>) -> Vec<PresenceChange> {
+        let sightings: Vec<_> = self
+            .devices
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|(mac, track)| {
+                let presence = neighbors.presence(mac, None, now);
+                let seen = presence.arp_seen.filter(|seen| *seen > track.last_activity)?;
+                let ip = presence.arp_ip.and_then(|ip| ip.parse().ok());
+                Some((mac.clone(), ip, seen))
+            })
+            .collect();
+        sightings
+            .into_iter()
+            .filter_map(|(mac, ip, seen)| self.record_activity(&mac, ip, seen, "arp"))
+            .collect()
+    }
+
+    /// Idle devices with a known address, to ping
+    pub fn idle_addresses(&self) -> Vec<(String, Ipv4Addr)> {
+        self.devices
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, track)| track.state == PresenceState::Idle)
+            .filter_map(|(mac, track)| Some((mac.clone(), track.ip?)))
+            .collect()
+    }
+
+    pub fn status(&self, mac: &str) -> Option<PresenceStatus> {
+        self.devices.read().unwrap().get(mac).map(|track| PresenceStatus {
+            state: track.state,
+            since: track.since,
+            last_activity: Some(track.last_activity),
+            last_source: Some(track.last_source),
+        })
+    }
+
+    /// Fill in the presence state of each device in the inventory
+    pub fn annotate(&self, devices: &mut [DeviceSummary]) {
+        for device in devices {
+            device.presence_state = self.status(&device.mac_address);
+        }
+    }
+}
+
+// Record a state change on the track and describe it
+fn transition(
+    mac: &str,
+    track: &mut Track,
+    state: PresenceState,
+    reason: &'static str,
+    at: DateTime<Utc>,
+) -> Option<PresenceChange> {
+    if track.state == state {
+        return None;
+    }
+    let change = PresenceChange {
+        mac_address: mac.to_string(),
+        ip_address: track.ip.map(|ip| ip.to_string()),
+        previous: track.state,
+        state,
+        reason,
+        timestamp: at,
+    };
+    track.state = state;
+    track.since = at;
+    Some(change)
+}
+
+/// Re-evaluate presence in the background, folding in ARP sightings and pings
+pub fn spawn(state: Arc<AppState>) {
+    let Some(engine) = state.presence.clone() else {
+        return;
+    };
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(engine.config.interval);
+        loop {
+            interval.tick().await;
+            let now = Utc::now();
+            let mut changes = Vec::new();
+            if let Some(neighbors) = &state.neighbors {
+                changes.extend(engine.merge_neighbors(neighbors, now));
+            }
+            if engine.config.ping && state.is_active() {
+                changes.extend(ping_idle(&engine).await);
+            }
+            changes.extend(engine.evaluate(Utc::now()));
+            for change in changes {
+                announce(&state, change);
+            }
+        }
+    });
+}
+
+// Ping every idle device once; those answering count as active
+async fn ping_idle(engine: &PresenceEngine) -> Vec<PresenceChange> {
+    let idle = engine.idle_addresses();
+    let answered: Vec<_> = stream::iter(idle)
+        .map(|(mac, ip)| async move {
+            let reply = crate::detection_sources::ping_host(&ip.to_string()).await;
+            reply.ok().flatten().map(|_| (mac, ip, Utc::now()))
+        })
+        .buffer_unordered(PING_CONCURRENCY)
+        .filter_map(|answered| async move { answered })
+        .collect()
+        .await;
+    debug!("Presence sweep: {} idle devices answered a ping", answered.len());
+    answered
+        .into_iter()
+        .filter_map(|(mac, ip, at)| engine.record_activity(&mac, Some(ip), at, "ping"))
+        .collect()
+}
+
+/// Log and push a state change
+pub fn announce(state: &AppState, change: PresenceChange) {
+    debug!(
+        "{} is now {:?} (was {:?}, {})",
+        change.mac_address, change.state, change.previous, change.reason
+    );
+    let _ = state.presence_tx.send(change);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arp::{Neighbor, NeighborConfig};
+
+    fn engine() -> PresenceEngine {
+        PresenceEngine::new(PresenceConfig {
+            online_timeout: Duration::from_secs(600),
+            offline_timeout: Duration::from_secs(3600),
+            interval: Duration::from_secs(30),
+            ping: false,
+        })
+    }
+
+    fn request(mac: &str, message_type: &str, at: DateTime<Utc>) -> DhcpRequest {
+        DhcpRequest {
+            mac_address: mac.to_string(),
+            message_type: message_type.to_string(),
+            timestamp: at.to_rfc3339(),
+            source_ip: "10.0.0.5".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_state_machine() {
+        let engine = engine();
+        let start = Utc::now();
+        let mac = "aa:bb:cc:00:00:01";
+
+        let change = engine.record_request(&request(mac, "REQUEST", start)).unwrap();
+        assert_eq!((change.previous, change.state, change.reason), (PresenceState::Offline, PresenceState::Online, "dhcp"));
+        assert_eq!(change.ip_address.as_deref(), Some("10.0.0.5"));
+        assert!(engine.record_request(&request(mac, "REQUEST", start)).is_none());
+
+        assert!(engine.evaluate(start + chrono::Duration::seconds(600)).is_empty());
+        let changes = engine.evaluate(start + chrono::Duration::seconds(601));
+        assert_eq!((changes[0].state, changes[0].reason), (PresenceState::Idle, "timeout"));
+        assert_eq!(engine.idle_addresses(), vec![(mac.to_string(), Ipv4Addr::new(10, 0, 0, 5))]);
+
+        // An answered ping brings it back
+        let pinged = start + chrono::Duration::seconds(700);
+        let change = engine.record_activity(mac, None, pinged, "ping").unwrap();
+        assert_eq!(change.state, PresenceState::Online);
+        assert_eq!(engine.status(mac).unwrap().last_source, Some("ping"));
+
+        let changes = engine.evaluate(pinged + chrono::Duration::seconds(3601));
+        assert_eq!(changes[0].previous, PresenceState::Online);
+        assert_eq!(changes[0].state, PresenceState::Offline);
+    }
+
+    #[test]
+    fn test_relayed_request_without_address() {
+        let engine = engine();
+        let mac = "aa:bb:cc:00:00:04";
+        // A DISCOVER through a relay: 10.0.0.5 is the relay, not the client
+        let relayed = DhcpRequest { relayed: true, ..request(mac, "DISCOVER", Utc::now()) };
+        let change = engine.record_request(&relayed).unwrap();
+        assert_eq!(change.ip_address, None);
+    }
+
+    #[test]
+    fn test_release_goes_offline_until_next_request() {
+        let engine = engine();
+        let start = Utc::now();
+        let mac = "aa:bb:cc:00:00:02";
+        engine.record_request(&request(mac, "REQUEST", start));
+
+        let change = engine.record_request(&request(mac, "RELEASE", start + chrono::Duration::seconds(5))).unwrap();
+        assert_eq!((change.state, change.reason), (PresenceState::Offline, "release"));
+        assert!(engine.evaluate(start + chrono::Duration::seconds(10)).is_empty());
+
+        let change = engine.record_request(&request(mac, "DISCOVER", start + chrono::Duration::seconds(20))).unwrap();
+        assert_eq!(change.state, PresenceState::Online);
+    }
+
+    #[test]
+    fn test_arp_sightings_and_restore() {
+        let engine = engine();
+        let now = Utc::now();
+        let devices = vec![DeviceSummary {
+            mac_address: "aa:bb:cc:00:00:03".to_string(),
+            last_seen: (now - chrono::Duration::hours(2)).to_rfc3339(),
+            last_ip: Some("10.0.0.9".to_string()),
+            ..Default::default()
+        }];
+        engine.restore(&devices, now);
+        assert_eq!(engine.status("aa:bb:cc:00:00:03").unwrap().state, PresenceState::Offline);
+
+        let neighbors = NeighborTable::new(NeighborConfig {
+            path: Default::default(),
+            interval: Duration::from_secs(60),
+            active_window: Duration::from_secs(300),
+        });
+        neighbors.update(
+            vec![Neighbor {
+                ip: "10.0.0.9".to_string(),
+                mac: "aa:bb:cc:00:00:03".to_string(),
+                interface: "eth0".to_string(),
+            }],
+            now,
+        );
+        let changes = engine.merge_neighbors(&neighbors, now);
+        assert_eq!((changes[0].state, changes[0].reason), (PresenceState::Online, "arp"));
+        // The same sighting again is no news
+        assert!(engine.merge_neighbors(&neighbors, now).is_empty());
+
+        let mut devices = devices;
+        engine.annotate(&mut devices);
+        assert_eq!(devices[0].presence_state.as_ref().unwrap().state, PresenceState::Online);
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{anyhow, Result};
+use rand::Rng;
+use std::net::SocketAddr;
+use tokio::net::{TcpSocket, TcpStream};
+
+/// Windows' default dynamic port range, used for probes in stealth mode
+pub const WINDOWS_EPHEMERAL_PORTS: (u16, u16) = (49152, 65535);
+
+/// Browser User-Agent sent by HTTP probes in stealth mode (Edge on Windows 11)
+const WINDOWS_USER_AGENT: &str =
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36 Edg/124.0.0.0";
+
+/// How active probes present themselves on the wire
+#[derive(Debug, Clone)]
+pub struct ProbeIdentity {
+    /// SMB2 ClientGuid sent in negotiate requests (wire byte order)
+    pub smb_client_guid: [u8; 16],
+    /// Local port range SMB probes connect from; None lets the OS choose. HTTP probes
+    /// go through reqwest, which can't bind a source port, so they ignore it
+    pub source_ports: Option<(u16, u16)>,
+    /// Mimic a stock Windows client and skip noisy pre-checks (ICMP ping)
+    pub stealth: bool,
+    /// User-Agent for HTTP probes instead of the built-in one
+    pub user_agent: Option<String>,
+    /// NetBIOS name sent as the workstation in NTLMSSP negotiate messages
+    pub workstation: Option<String>,
+}
+
+impl Default for ProbeIdentity {
+    fn default() -> Self {
+        Self {
+            smb_client_guid: random_guid(),
+            source_ports: None,
+            stealth: false,
+            user_agent: None,
+            workstation: None,
+        }
+    }
+}
+
+impl ProbeIdentity {
+    /// User-Agent for HTTP probes: the configured one, else a stock browser in stealth
+    /// mode, otherwise our own
+    pub fn http_user_agent(&self) -> &str {
+        if let Some(user_agent) = &self.user_agent {
+            user_agent
+        } else if self.stealth {
+            WINDOWS_USER_AGENT
+        } else {
+            concat!("ks-dhcpmon/", env!("CARGO_PKG_VERSION"))
+        }
+    }
+
+    /// Source port range in effect, falling back to the Windows range in stealth mode
+    fn effective_source_ports(&self) -> Option<(u16, u16)> {
+        self.source_ports
+            .or(if self.stealth { Some(WINDOWS_EPHEMERAL_PORTS) } else { None })
+    }
+
+    /// Open a TCP connection, binding to a port from the configured range if any
+    pub async fn connect(&self, addr: SocketAddr) -> Result<TcpStream> {
+        let Some((low, high)) = self.effective_source_ports() else {
+            return Ok(TcpStream::connect(addr).await?);
+        };
+
+        // A few attempts in case the randomly picked port is already in use
+        let mut last_error = None;
+        for _ in 0..8 {
+            let port = rand::thread_rng().gen_range(low..=high);
+            let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+            let local: SocketAddr = if addr.is_ipv4() {
+                ([0, 0, 0, 0], port).into()
+            } else {
+                ([0u16; 8], port).into()
+            };
+
+            match socket.bind(local) {
+                Ok(()) => return Ok(socket.connect(addr).await?),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(anyhow!(
+            "no free source port in {}-{}: {}",
+            low,
+            high,
+            last_error.map(|e| e.to_string()).unwrap_or_default()
+        ))
+    }
+}
+
+/// Generate a random (version 4) GUID in wire byte order
+pub fn random_guid() -> [u8; 16] {
+    let mut guid: [u8; 16] = rand::thread_rng().gen();
+    guid[7] = (guid[7] & 0x0f) | 0x40;
+    guid[8] = (guid[8] & 0x3f) | 0x80;
+    guid
+}
+
+/// Parse "xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx" into the little-endian
+/// mixed layout Windows uses on the wire (first three groups byte-swapped)
+pub fn parse_guid(text: &str) -> Result<[u8; 16]> {
+    let hex: String = text.trim().trim_matches(|c| c == '{' || c == '}').replace('-', "");
+    if hex.len() != 32 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("invalid GUID: {}", text));
+    }
+
+    let mut bytes = [0u8; 16];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)?;
+    }
+
+    bytes[0..4].reverse();
+    bytes[4..6].reverse();
+    bytes[6..8].reverse();
+    Ok(bytes)
+}
+
+/// Check a NetBIOS computer name for the NTLMSSP workstation field: 1 to 15 ASCII
+/// letters, digits or hyphens, sent upper-case as Windows does
+pub fn parse_workstation(text: &str) -> Result<String> {
+    let name = text.trim();
+    if name.is_empty() || name.len() > 15 || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(anyhow!("invalid NTLMSSP workstation name '{}': use 1-15 letters, digits or hyphens", text));
+    }
+    Ok(name.to_ascii_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_guid_wire_order() {
+        let guid = parse_guid("{00112233-4455-6677-8899-aabbccddeeff}").unwrap();
+        assert_eq!(
+            guid,
+            [0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]
+        );
+    }
+
+    #[test]
+    fn test_parse_guid_rejects_garbage() {
+        assert!(parse_guid("not-a-guid").is_err());
+        assert!(parse_guid("00112233-4455-6677-8899-aabbccddeezz").is_err());
+    }
+
+    #[test]
+    fn test_random_guid_is_not_zeroed() {
+        let guid = random_guid();
+        assert_ne!(guid, [0u8; 16]);
+        assert_eq!(guid[7] >> 4, 4);
+        assert_ne!(random_guid(), guid);
+    }
+
+    #[test]
+    fn test_stealth_defaults_to_windows_ports() {
+        let identity = ProbeIdentity { stealth: true, ..Default::default() };
+        assert_eq!(identity.effective_source_ports(), Some(WINDOWS_EPHEMERAL_PORTS));
+
+        let identity = ProbeIdentity { source_ports: Some((40000, 40010)), stealth: true, ..Default::default() };
+        assert_eq!(identity.effective_source_ports(), Some((40000, 40010)));
+
+        assert_eq!(ProbeIdentity::default().effective_source_ports(), None);
+    }
+
+    #[test]
+    fn test_configured_user_agent_wins() {
+        let identity = ProbeIdentity { user_agent: Some("Scanner/1.0".to_string()), stealth: true, ..Default::default() };
+        assert_eq!(identity.http_user_agent(), "Scanner/1.0");
+        assert!(ProbeIdentity::default().http_user_agent().starts_with("ks-dhcpmon/"));
+    }
+
+    #[test]
+    fn test_parse_workstation() {
+        assert_eq!(parse_workstation(" desktop-4f2k ").unwrap(), "DESKTOP-4F2K");
+        assert!(parse_workstation("").is_err());
+        assert!(parse_workstation("a-name-far-too-long").is_err());
+        assert!(parse_workstation("bad name").is_err());
+    }
+}
@@ -0,0 +1,228 @@
+//! Admission control for active probes. Every probe the detection sources send
+//! (ping, SMB, HTTP) goes through one scheduler, so a DISCOVER storm queues up
+//! behind a global concurrency limit instead of opening hundreds of connections.
+//! Concurrent probes of the same target are collapsed into one, and a target
+//! that was just probed is left alone for a cooldown period.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, Semaphore, SemaphorePermit};
+
+/// Completed targets kept before the map is pruned of expired cooldowns
+const PRUNE_THRESHOLD: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct SchedulerConfig {
+    /// Probes running at once, across all sources
+    pub max_concurrent: usize,
+    /// Probes waiting for a slot; more are turned away
+    pub max_queued: usize,
+    /// Minimum time between two probes of the same kind to the same host
+    pub host_cooldown: Duration,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 16,
+            max_queued: 256,
+            host_cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Why a probe wasn't run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Skip {
+    /// Another probe of the same target was running; its result is in the source's cache
+    Deduplicated,
+    /// The target was probed less than the cooldown ago
+    Cooldown,
+    /// The queue is full
+    QueueFull,
+}
+
+impl Skip {
+    /// Outcome recorded in the probe counters
+    pub fn outcome(self) -> &'static str {
+        match self {
+            Self::Deduplicated => "deduplicated",
+            Self::Cooldown => "cooldown",
+            Self::QueueFull => "queue_full",
+        }
+    }
+}
+
+enum Target {
+    Running(Arc<Notify>),
+    Done(Instant),
+}
+
+type TargetKey = (&'static str, String);
+
+/// Shared by all detection sources
+pub struct ProbeScheduler {
+    slots: Semaphore,
+    max_concurrent: usize,
+    max_queued: usize,
+    cooldown: Duration,
+    queued: AtomicUsize,
+    targets: Mutex<HashMap<TargetKey, Target>>,
+}
+
+/// Probes running and waiting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SchedulerStatus {
+    pub running: usize,
+    pub queued: usize,
+}
+
+impl ProbeScheduler {
+    pub fn new(config: &SchedulerConfig) -> Self {
+        Self {
+            slots: Semaphore::new(config.max_concurrent),
+            max_concurrent: config.max_concurrent,
+            max_queued: config.max_queued,
+            cooldown: config.host_cooldown,
+            queued: AtomicUsize::new(0),
+            targets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Wait for a slot to run `probe` against `ip`; the slot is released and the
+    /// cooldown starts when the permit is dropped
+    pub async fn acquire(&self, probe: &'static str, ip: &str) -> Result<ProbePermit<'_>, Skip> {
+        let key = (probe, ip.to_string());
+        let running = {
+            let mut targets = self.targets.lock().unwrap();
+            match targets.get(&key) {
+                // Created under the lock, so the wakeup can't be missed
+                Some(Target::Running(done)) => Some(done.clone().notified_owned()),
+                Some(Target::Done(finished)) if finished.elapsed() < self.cooldown => return Err(Skip::Cooldown),
+                _ => {
+                    if self.queued.load(Ordering::Relaxed) >= self.max_queued {
+                        return Err(Skip::QueueFull);
+                    }
+                    if targets.len() >= PRUNE_THRESHOLD {
+                        let cooldown = self.cooldown;
+                        targets.retain(|_, target| match target {
+                            Target::Running(_) => true,
+                            Target::Done(finished) => finished.elapsed() < cooldown,
+                        });
+                    }
+                    targets.insert(key.clone(), Target::Running(Arc::new(Notify::new())));
+                    None
+                }
+            }
+        };
+        if let Some(done) = running {
+            done.await;
+            return Err(Skip::Deduplicated);
+        }
+
+        // From here on dropping the permit, even while queued, finishes the target
+        let mut permit = ProbePermit {
+            scheduler: self,
+            key,
+            _slot: None,
+        };
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        let queued = QueuedGuard(&self.queued);
+        permit._slot = Some(self.slots.acquire().await.expect("probe semaphore is never closed"));
+        drop(queued);
+        Ok(permit)
+    }
+
+    /// Let the next probe of an address run immediately
+    pub fn forget(&self, ip: &str) {
+        self.targets
+            .lock()
+            .unwrap()
+            .retain(|(_, target_ip), target| target_ip != ip || matches!(target, Target::Running(_)));
+    }
+
+    pub fn status(&self) -> SchedulerStatus {
+        SchedulerStatus {
+            running: self.max_concurrent - self.slots.available_permits(),
+            queued: self.queued.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct QueuedGuard<'a>(&'a AtomicUsize);
+
+impl Drop for QueuedGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A running probe's slot
+pub struct ProbePermit<'a> {
+    scheduler: &'a ProbeScheduler,
+    key: TargetKey,
+    _slot: Option<SemaphorePermit<'a>>,
+}
+
+impl Drop for ProbePermit<'_> {
+    fn drop(&mut self) {
+        let mut targets = self.scheduler.targets.lock().unwrap();
+        if let Some(Target::Running(done)) = targets.insert(self.key.clone(), Target::Done(Instant::now())) {
+            done.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler(max_concurrent: usize, max_queued: usize, cooldown_secs: u64) -> ProbeScheduler {
+        ProbeScheduler::new(&SchedulerConfig {
+            max_concurrent,
+            max_queued,
+            host_cooldown: Duration::from_secs(cooldown_secs),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_dedup_and_cooldown() {
+        let scheduler = scheduler(4, 4, 60);
+        let permit = scheduler.acquire("smb", "10.0.0.5").await.unwrap();
+
+        // A second probe of the same target waits for the first and is skipped
+        let duplicate = scheduler.acquire("smb", "10.0.0.5");
+        tokio::pin!(duplicate);
+        assert!(futures::poll!(duplicate.as_mut()).is_pending());
+        // Other probes of the host and other hosts aren't held up
+        assert!(scheduler.acquire("http", "10.0.0.5").await.is_ok());
+        assert!(scheduler.acquire("smb", "10.0.0.6").await.is_ok());
+        drop(permit);
+        assert_eq!(duplicate.await.err(), Some(Skip::Deduplicated));
+
+        assert_eq!(scheduler.acquire("smb", "10.0.0.5").await.err(), Some(Skip::Cooldown));
+        scheduler.forget("10.0.0.5");
+        assert!(scheduler.acquire("smb", "10.0.0.5").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_and_queue_limits() {
+        let scheduler = scheduler(1, 1, 0);
+        let running = scheduler.acquire("smb", "10.0.0.1").await.unwrap();
+
+        let queued = scheduler.acquire("smb", "10.0.0.2");
+        tokio::pin!(queued);
+        assert!(futures::poll!(queued.as_mut()).is_pending());
+        assert_eq!(scheduler.status(), SchedulerStatus { running: 1, queued: 1 });
+        assert_eq!(scheduler.acquire("smb", "10.0.0.3").await.err(), Some(Skip::QueueFull));
+
+        drop(running);
+        let next = queued.await.unwrap();
+        assert_eq!(scheduler.status(), SchedulerStatus { running: 1, queued: 0 });
+        drop(next);
+        assert_eq!(scheduler.status(), SchedulerStatus { running: 0, queued: 0 });
+    }
+}
@@ -0,0 +1,254 @@
+//! Reverse DNS enrichment: a PTR lookup for each address a device is seen with, as
+//! another naming signal for clients that don't send option 12. When the resolver has
+//! no record, the device itself is asked over unicast mDNS (port 5353), which Apple,
+//! Avahi and most printers answer with their ".local" name.
+
+use crate::db::dns_names::DnsName;
+use anyhow::{anyhow, bail, Result};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+const TYPE_PTR: u16 = 12;
+const CLASS_IN: u16 = 1;
+const MDNS_PORT: u16 = 5353;
+/// Addresses remembered in the lookup cache
+const MAX_CACHED: usize = 50_000;
+
+#[derive(Debug, Clone)]
+pub struct RdnsConfig {
+    /// DNS server for PTR lookups (None: skip, only ask devices over mDNS)
+    pub resolver: Option<SocketAddr>,
+    /// Ask the device over unicast mDNS when the resolver has no name
+    pub mdns: bool,
+    pub timeout: Duration,
+    /// How long before an address is looked up again
+    pub cache_ttl: Duration,
+}
+
+/// First nameserver in /etc/resolv.conf
+pub fn system_resolver() -> Option<SocketAddr> {
+    let content = std::fs::read_to_string("/etc/resolv.conf").ok()?;
+    parse_resolv_conf(&content)
+}
+
+fn parse_resolv_conf(content: &str) -> Option<SocketAddr> {
+    content.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        match (fields.next(), fields.next()) {
+            (Some("nameserver"), Some(server)) => server.parse::<IpAddr>().ok().map(|ip| SocketAddr::new(ip, 53)),
+            _ => None,
+        }
+    })
+}
+
+/// A PTR query for `ip`; recursion is only asked of the resolver, not of mDNS responders
+fn ptr_query(id: u16, ip: Ipv4Addr, recursion: bool) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(48);
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&(if recursion { 0x0100u16 } else { 0 }).to_be_bytes());
+    packet.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]); // one question
+    let [a, b, c, d] = ip.octets();
+    for label in [d.to_string(), c.to_string(), b.to_string(), a.to_string(), "in-addr".to_string(), "arpa".to_string()] {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    packet
+}
+
+/// Read a possibly compressed name at `offset`, returning it and the offset after it
+fn read_name(packet: &[u8], mut offset: usize) -> Result<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Bounds the number of compression pointers followed, so loops can't hang us
+    for _ in 0..64 {
+        let len = *packet.get(offset).ok_or_else(|| anyhow!("name runs past the packet"))? as usize;
+        match len {
+            0 => {
+                let name = labels.join(".");
+                return Ok((name, end.unwrap_or(offset + 1)));
+            }
+            len if len & 0xc0 == 0xc0 => {
+                let low = *packet.get(offset + 1).ok_or_else(|| anyhow!("truncated pointer"))? as usize;
+                end.get_or_insert(offset + 2);
+                offset = ((len & 0x3f) << 8) | low;
+            }
+            len => {
+                let label = packet
+                    .get(offset + 1..offset + 1 + len)
+                    .ok_or_else(|| anyhow!("label runs past the packet"))?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                offset += 1 + len;
+            }
+        }
+    }
+    bail!("too many compression pointers")
+}
+
+/// The first PTR answer in a response to query `id`
+fn parse_ptr_response(id: u16, packet: &[u8]) -> Result<Option<String>> {
+    if packet.len() < 12 || u16::from_be_bytes([packet[0], packet[1]]) != id {
+        bail!("not a response to our query");
+    }
+    let flags = u16::from_be_bytes([packet[2], packet[3]]);
+    // NXDOMAIN and friends simply mean no name
+    if flags & 0x8000 == 0 || flags & 0x000f != 0 {
+        return Ok(None);
+    }
+    let questions = u16::from_be_bytes([packet[4], packet[5]]);
+    let answers = u16::from_be_bytes([packet[6], packet[7]]);
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = read_name(packet, offset)?.1 + 4;
+    }
+    for _ in 0..answers {
+        offset = read_name(packet, offset)?.1;
+        let header = packet.get(offset..offset + 10).ok_or_else(|| anyhow!("truncated answer"))?;
+        let record_type = u16::from_be_bytes([header[0], header[1]]);
+        let length = u16::from_be_bytes([header[8], header[9]]) as usize;
+        if record_type == TYPE_PTR {
+            let (name, _) = read_name(packet, offset + 10)?;
+            return Ok(Some(name).filter(|name| !name.is_empty()));
+        }
+        offset += 10 + length;
+    }
+    Ok(None)
+}
+
+// Send one query and wait for the matching reply
+async fn query(server: SocketAddr, ip: Ipv4Addr, recursion: bool, timeout: Duration) -> Result<Option<String>> {
+    let bind: SocketAddr = if server.is_ipv4() { "0.0.0.0:0".parse()? } else { "[::]:0".parse()? };
+    let socket = UdpSocket::bind(bind).await?;
+    let id: u16 = rand::random();
+    socket.send_to(&ptr_query(id, ip, recursion), server).await?;
+
+    let mut buffer = [0u8; 1500];
+    tokio::time::timeout(timeout, async {
+        loop {
+            let (len, from) = socket.recv_from(&mut buffer).await?;
+            if from.ip() != server.ip() {
+                continue;
+            }
+            if let Ok(answer) = parse_ptr_response(id, &buffer[..len]) {
+                return Ok(answer);
+            }
+        }
+    })
+    .await
+    .map_err(|_| anyhow!("no reply from {} within {:?}", server, timeout))?
+}
+
+/// Resolves names for device addresses in the background, caching by address
+pub struct ReverseResolver {
+    config: RdnsConfig,
+    /// When each address was last looked up
+    looked_up: Mutex<HashMap<Ipv4Addr, Instant>>,
+}
+
+impl ReverseResolver {
+    pub fn new(config: RdnsConfig) -> Self {
+        Self {
+            config,
+            looked_up: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Name for `ip`, from the resolver or else the device itself
+    pub async fn resolve(&self, ip: Ipv4Addr) -> Option<DnsName> {
+        let found = |name: String, source: &str| DnsName {
+            ip_address: ip.to_string(),
+            name: name.trim_end_matches('.').to_string(),
+            source: source.to_string(),
+            resolved_at: chrono::Utc::now().to_rfc3339(),
+        };
+
+        if let Some(resolver) = self.config.resolver {
+            match query(resolver, ip, true, self.config.timeout).await {
+                Ok(Some(name)) => return Some(found(name, "dns")),
+                Ok(None) => {}
+                Err(e) => tracing::debug!("PTR lookup of {} failed: {}", ip, e),
+            }
+        }
+        if self.config.mdns {
+            match query(SocketAddr::new(IpAddr::V4(ip), MDNS_PORT), ip, false, self.config.timeout).await {
+                Ok(Some(name)) => return Some(found(name, "mdns")),
+                Ok(None) => {}
+                Err(e) => tracing::debug!("mDNS lookup of {} failed: {}", ip, e),
+            }
+        }
+        None
+    }
+
+    // Whether the address is due for a lookup, marking it looked up if so
+    fn due(&self, ip: Ipv4Addr) -> bool {
+        let now = Instant::now();
+        let mut looked_up = self.looked_up.lock().unwrap();
+        if looked_up.get(&ip).is_some_and(|at| now.duration_since(*at) < self.config.cache_ttl) {
+            return false;
+        }
+        if looked_up.len() >= MAX_CACHED {
+            looked_up.retain(|_, at| now.duration_since(*at) < self.config.cache_ttl);
+        }
+        looked_up.insert(ip, now);
+        true
+    }
+
+    /// Look the address up without holding up request processing, storing any name found
+    pub fn lookup_in_background(self: &Arc<Self>, pool: SqlitePool, mac_address: String, ip_address: &str) {
+        let Ok(ip) = ip_address.parse::<Ipv4Addr>() else {
+            return;
+        };
+        if ip.is_unspecified() || ip.is_broadcast() || !self.due(ip) {
+            return;
+        }
+        let resolver = self.clone();
+        tokio::spawn(async move {
+            let Some(name) = resolver.resolve(ip).await else {
+                return;
+            };
+            tracing::debug!("{} ({}) resolves to {} via {}", ip, mac_address, name.name, name.source);
+            if let Err(e) = crate::db::dns_names::record_name(&pool, &mac_address, &name).await {
+                tracing::debug!("Failed to store name for {}: {}", ip, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ptr_round_trip() {
+        let ip = Ipv4Addr::new(10, 0, 0, 5);
+        let query = ptr_query(0x1234, ip, true);
+        assert_eq!(&query[12..], b"\x015\x010\x010\x0210\x07in-addr\x04arpa\x00\x00\x0c\x00\x01");
+
+        // Response: the question, then a PTR answer whose owner name is a pointer to it
+        let mut response = query.clone();
+        response[2..4].copy_from_slice(&0x8180u16.to_be_bytes());
+        response[6..8].copy_from_slice(&1u16.to_be_bytes());
+        response.extend_from_slice(&[0xc0, 12, 0, 12, 0, 1, 0, 0, 0x0e, 0x10]);
+        let rdata = b"\x07printer\x03lan\x00";
+        response.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        response.extend_from_slice(rdata);
+        assert_eq!(parse_ptr_response(0x1234, &response).unwrap().as_deref(), Some("printer.lan"));
+
+        // NXDOMAIN, and replies to someone else's query
+        response[3] = 0x83;
+        assert_eq!(parse_ptr_response(0x1234, &response).unwrap(), None);
+        assert!(parse_ptr_response(0x4321, &response).is_err());
+
+        assert_eq!(
+            parse_resolv_conf("# comment\nsearch lan\nnameserver 192.168.1.1\nnameserver 1.1.1.1\n"),
+            Some("192.168.1.1:53".parse().unwrap())
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//! Receive path from the DHCP socket to request processing.
+//!
+//! Datagrams are read into buffers taken from a pool and handed over a bounded
+//! queue to a fixed set of workers, instead of each being copied into a fresh
+//! Vec and given its own task. Buffers return to the pool once a worker is done
+//! with them, and the queue never holds more than `queue_size` packets, so a
+//! DISCOVER storm costs a queue's worth of buffers rather than unbounded tasks
+//! and allocations. What happens to packets beyond that is the overflow policy.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// What a full queue does with the next packet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowPolicy {
+    /// Stop reading until a worker catches up; the kernel's socket buffer takes the
+    /// rest and drops silently once it fills
+    Block,
+    /// Discard the packet just read
+    DropNew,
+    /// Discard the longest-waiting packet to make room, keeping the freshest
+    DropOldest,
+}
+
+impl OverflowPolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "block" => Some(Self::Block),
+            "drop-new" => Some(Self::DropNew),
+            "drop-oldest" => Some(Self::DropOldest),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Block => "block",
+            Self::DropNew => "drop-new",
+            Self::DropOldest => "drop-oldest",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReceiveConfig {
+    /// Tasks processing packets concurrently
+    pub workers: usize,
+    /// Packets waiting for a worker before the overflow policy applies
+    pub queue_size: usize,
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for ReceiveConfig {
+    fn default() -> Self {
+        Self {
+            workers: 8,
+            queue_size: 1024,
+            overflow: OverflowPolicy::Block,
+        }
+    }
+}
+
+/// Fixed-size receive buffers, reused instead of allocated per packet
+pub struct BufferPool {
+    buffer_size: usize,
+    /// Idle buffers kept beyond this are freed
+    max_idle: usize,
+    idle: Mutex<Vec<Vec<u8>>>,
+}
+
+impl BufferPool {
+    pub fn new(buffer_size: usize, max_idle: usize) -> Arc<Self> {
+        Arc::new(Self {
+            buffer_size,
+            max_idle,
+            idle: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn take(self: &Arc<Self>) -> PooledBuffer {
+        let data = self
+            .idle
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| vec![0; self.buffer_size]);
+        PooledBuffer {
+            data,
+            len: 0,
+            pool: self.clone(),
+        }
+    }
+
+    fn give_back(&self, data: Vec<u8>) {
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < self.max_idle {
+            idle.push(data);
+        }
+    }
+}
+
+/// A buffer on loan from the pool; derefs to the received bytes
+pub struct PooledBuffer {
+    data: Vec<u8>,
+    len: usize,
+    pool: Arc<BufferPool>,
+}
+
+impl PooledBuffer {
+    /// The whole buffer, to receive into
+    pub fn space(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn set_len(&mut self, len: usize) {
+        self.len = len.min(self.data.len());
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        self.pool.give_back(std::mem::take(&mut self.data));
+    }
+}
+
+/// A packet read from the socket
+pub struct Datagram {
+    pub data: PooledBuffer,
+    pub source: SocketAddr,
+    /// 802.1Q VLAN, when captured from an interface
+    pub vlan_id: Option<u16>,
+}
+
+/// Bounded hand-off from the reader to the workers
+pub struct PacketQueue {
+    capacity: usize,
+    overflow: OverflowPolicy,
+    inner: Mutex<QueueState>,
+    not_empty: Notify,
+    not_full: Notify,
+}
+
+#[derive(Default)]
+struct QueueState {
+    packets: VecDeque<Datagram>,
+    closed: bool,
+}
+
+impl PacketQueue {
+    pub fn new(capacity: usize, overflow: OverflowPolicy) -> Arc<Self> {
+        Arc::new(Self {
+            capacity: capacity.max(1),
+            overflow,
+            inner: Mutex::new(QueueState::default()),
+            not_empty: Notify::new(),
+            not_full: Notify::new(),
+        })
+    }
+
+    /// Queue a packet, applying the overflow policy when full. Returns how many
+    /// packets were shed (0 or 1); blocking waits for room instead.
+    pub async fn push(&self, datagram: Datagram) -> u64 {
+        loop {
+            let not_full = self.not_full.notified();
+            {
+                let mut state = self.inner.lock().unwrap();
+                let full = state.packets.len() >= self.capacity;
+                if !full || self.overflow != OverflowPolicy::Block {
+                    let mut shed = 0;
+                    if full {
+                        if self.overflow == OverflowPolicy::DropNew {
+                            return 1;
+                        }
+                        state.packets.pop_front();
+                        shed = 1;
+                    }
+                    state.packets.push_back(datagram);
+                    drop(state);
+                    self.not_empty.notify_one();
+                    return shed;
+                }
+            }
+            not_full.await;
+        }
+    }
+
+    /// The next packet, waiting for one; None once the queue is closed and drained
+    pub async fn pop(&self) -> Option<Datagram> {
+        loop {
+            let not_empty = self.not_empty.notified();
+            {
+                let mut state = self.inner.lock().unwrap();
+                if let Some(datagram) = state.packets.pop_front() {
+                    // Pass the wakeup on so idle workers pick up the rest
+                    if !state.packets.is_empty() {
+                        self.not_empty.notify_one();
+                    }
+                    drop(state);
+                    self.not_full.notify_one();
+                    return Some(datagram);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            not_empty.await;
+        }
+    }
+
+    /// Let the workers finish what is queued and stop; the listener itself never does
+    #[cfg(test)]
+    pub fn close(&self) {
+        self.inner.lock().unwrap().closed = true;
+        self.not_empty.notify_waiters();
+    }
+}
+
+/// Start `workers` tasks taking datagrams off the queue until it closes
+pub fn spawn_workers<F, Fut>(workers: usize, queue: Arc<PacketQueue>, handle: F)
+where
+    F: Fn(Datagram) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    for _ in 0..workers.max(1) {
+        let queue = queue.clone();
+        let handle = handle.clone();
+        tokio::spawn(async move {
+            while let Some(datagram) = queue.pop().await {
+                handle(datagram).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_buffers_are_reused() {
+        let pool = BufferPool::new(16, 1);
+        let mut first = pool.take();
+        first.space()[..3].copy_from_slice(b"abc");
+        first.set_len(3);
+        assert_eq!(&*first, b"abc");
+        assert_eq!(first.capacity(), 16);
+
+        let second = pool.take();
+        drop(first);
+        drop(second);
+        // Only max_idle buffers are kept
+        assert_eq!(pool.idle.lock().unwrap().len(), 1);
+        let reused = pool.take();
+        assert_eq!((reused.len(), reused.capacity()), (0, 16));
+        assert!(pool.idle.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_workers_drain_the_queue() {
+        let pool = BufferPool::new(16, 8);
+        let queue = PacketQueue::new(4, OverflowPolicy::Block);
+        let handled = Arc::new(AtomicUsize::new(0));
+        let counter = handled.clone();
+        spawn_workers(3, queue.clone(), move |_datagram| {
+            let counter = counter.clone();
+            async move {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+
+        for _ in 0..20 {
+            let datagram = Datagram { data: pool.take(), source: "10.0.0.1:68".parse().unwrap(), vlan_id: None };
+            assert_eq!(queue.push(datagram).await, 0);
+        }
+        queue.close();
+        for _ in 0..100 {
+            if handled.load(Ordering::Relaxed) == 20 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(handled.load(Ordering::Relaxed), 20);
+        assert!(!pool.idle.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_overflow_policies() {
+        let pool = BufferPool::new(16, 8);
+        let datagram = |byte: u8| {
+            let mut data = pool.take();
+            data.space()[0] = byte;
+            data.set_len(1);
+            Datagram { data, source: "10.0.0.1:68".parse().unwrap(), vlan_id: None }
+        };
+
+        let queue = PacketQueue::new(2, OverflowPolicy::DropNew);
+        for byte in 1..=3 {
+            queue.push(datagram(byte)).await;
+        }
+        assert_eq!(queue.push(datagram(4)).await, 1);
+        queue.close();
+        assert_eq!(queue.pop().await.unwrap().data[0], 1);
+        assert_eq!(queue.pop().await.unwrap().data[0], 2);
+        assert!(queue.pop().await.is_none());
+
+        let queue = PacketQueue::new(2, OverflowPolicy::DropOldest);
+        for byte in 1..=3 {
+            queue.push(datagram(byte)).await;
+        }
+        assert_eq!(queue.push(datagram(4)).await, 1);
+        assert_eq!(queue.pop().await.unwrap().data[0], 3);
+        assert_eq!(queue.pop().await.unwrap().data[0], 4);
+
+        // A blocked push completes once a worker takes a packet
+        let queue = PacketQueue::new(1, OverflowPolicy::Block);
+        queue.push(datagram(1)).await;
+        let blocked = tokio::spawn({
+            let queue = queue.clone();
+            let next = datagram(2);
+            async move { queue.push(next).await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert!(!blocked.is_finished());
+        assert_eq!(queue.pop().await.unwrap().data[0], 1);
+        assert_eq!(blocked.await.unwrap(), 0);
+        assert_eq!(queue.pop().await.unwrap().data[0], 2);
+    }
+}
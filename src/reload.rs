@@ -0,0 +1,162 @@
+//! Applying changed settings without a restart.
+//!
+//! SIGHUP and `POST /api/admin/config` re-read config.toml (or take the posted
+//! TOML) and apply the runtime-tunable parts: capture filters, the SMB probe
+//! policy, request log rotation and retention, and notification targets (the new
+//! device webhook and alert email). The whole file is validated before anything
+//! changes, down to every subnet and pattern, so a broken config leaves the running
+//! settings untouched rather than applying what parsed; reloads are applied one at
+//! a time. Everything else still needs a restart.
+
+use crate::capture_filter::CaptureFilter;
+use crate::logger::RotationConfig;
+use crate::new_device::NewDeviceConfig;
+use crate::notify::email::EmailConfig;
+use crate::smb_policy::SmbProbePolicy;
+use crate::web::state::AppState;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::sync::Mutex;
+
+/// The file SIGHUP and an empty reload request read
+pub const CONFIG_PATH: &str = "config.toml";
+
+/// Serializes reloads so two of them can't interleave their changes
+static RELOAD: Mutex<()> = Mutex::new(());
+
+/// The runtime-tunable settings of one config file
+#[derive(Debug, Clone)]
+pub struct RuntimeSettings {
+    pub capture_filter: CaptureFilter,
+    pub smb_policy: SmbProbePolicy,
+    /// Request log rotation (None unless requests are logged to a file)
+    pub log_rotation: Option<RotationConfig>,
+    /// None when new device announcements are disabled
+    pub new_devices: Option<NewDeviceConfig>,
+    pub email: Option<EmailConfig>,
+}
+
+/// What a reload changed
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReloadSummary {
+    /// Settings applied to the running monitor
+    pub applied: Vec<&'static str>,
+    /// Settings switched on or off in the file, which takes a restart
+    pub restart_required: Vec<&'static str>,
+}
+
+impl ReloadSummary {
+    pub fn log(&self, trigger: &str) {
+        tracing::info!("{}: reloaded {}", trigger, self.applied.join(", "));
+        if !self.restart_required.is_empty() {
+            tracing::warn!("{}: restart to apply changes to {}", trigger, self.restart_required.join(", "));
+        }
+    }
+}
+
+/// Validate config.toml content and apply its runtime-tunable settings
+pub fn reload(state: &AppState, content: &str) -> Result<ReloadSummary> {
+    let settings = crate::runtime_settings(content)?;
+    Ok(apply(state, settings))
+}
+
+/// Re-read config.toml and apply it
+pub fn reload_file(state: &AppState) -> Result<ReloadSummary> {
+    let content = std::fs::read_to_string(CONFIG_PATH).map_err(|e| anyhow!("{}: {}", CONFIG_PATH, e))?;
+    reload(state, &content)
+}
+
+/// Swap validated settings into the running components
+pub fn apply(state: &AppState, settings: RuntimeSettings) -> ReloadSummary {
+    let _reloading = RELOAD.lock().unwrap();
+    let mut summary = ReloadSummary::default();
+
+    let mut capture_filter = settings.capture_filter;
+    if let Some(scan) = &state.active_scan {
+        // Our own probes stay out of the log whatever the new filters say
+        capture_filter.ignore_mac_prefixes.push(scan.client_mac.to_string());
+    }
+    state.set_capture_filter(capture_filter);
+    summary.applied.push("capture");
+
+    state.hybrid_detector.set_smb_policy(settings.smb_policy);
+    summary.applied.push("detection.smb_policy");
+
+    match (state.logger.writes_file(), settings.log_rotation) {
+        (true, Some(rotation)) => {
+            state.logger.set_rotation(rotation);
+            summary.applied.push("request_log");
+        }
+        (false, None) => {}
+        _ => summary.restart_required.push("request_log"),
+    }
+
+    match (&state.new_devices, settings.new_devices) {
+        (Some(tracker), Some(config)) => {
+            tracker.set_config(config);
+            summary.applied.push("new_devices");
+        }
+        (None, None) => {}
+        _ => summary.restart_required.push("new_devices"),
+    }
+
+    match (&state.email, settings.email) {
+        (Some(email), Some(config)) => {
+            email.send_replace(config);
+            summary.applied.push("notify.email");
+        }
+        (None, None) => {}
+        _ => summary.restart_required.push("notify.email"),
+    }
+
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::AlertManager;
+    use crate::hybrid_detection::{HybridConfig, HybridDetector};
+    use crate::logger::RequestLogger;
+    use std::sync::Arc;
+
+    async fn state() -> AppState {
+        AppState::new(
+            Arc::new(RequestLogger::disabled()),
+            crate::db::test_pool().await,
+            Arc::new(HybridDetector::new(HybridConfig::default())),
+            None,
+            Arc::new(AlertManager::new(Default::default())),
+            None,
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_reload_applies_or_keeps_everything() {
+        let state = state().await;
+        assert!(state.capture_filter().is_empty());
+
+        let summary = reload(
+            &state,
+            "[capture]\nignore_mac_prefixes = [\"00:15:5d\"]\n[request_log]\nenabled = false\n[new_devices]\nenabled = false",
+        )
+        .unwrap();
+        assert_eq!(state.capture_filter().ignore_mac_prefixes, ["00:15:5d"]);
+        assert_eq!(summary.applied, ["capture", "detection.smb_policy"]);
+        assert!(summary.restart_required.is_empty());
+
+        // An invalid file changes nothing
+        assert!(reload(&state, "[capture]\nignore_mac_prefixes = [\"aa:bb\"]\n[request_log]\nbackend = \"tape\"").is_err());
+        assert_eq!(state.capture_filter().ignore_mac_prefixes, ["00:15:5d"]);
+        // So does a single broken entry, rather than being dropped from what is applied
+        let broken = "[capture]\nonly_subnets = [\"10.1.0.0/33\"]\n[request_log]\nenabled = false\n[new_devices]\nenabled = false";
+        assert!(reload(&state, broken).is_err());
+        assert!(state.capture_filter().only_subnets.is_empty());
+        assert!(reload(&state, "[detection.smb_policy]\ndeny_subnets = [\"10.0.0.0/8\", \"bogus\"]").is_err());
+
+        // Switching features on still takes a restart
+        let summary = reload(&state, "").unwrap();
+        assert_eq!(summary.restart_required, ["request_log", "new_devices"]);
+    }
+}
@@ -0,0 +1,205 @@
+//! Confidence decay and re-verification of stored detections.
+//!
+//! A detection is only as good as the last time it was checked: devices get
+//! reinstalled, upgraded or replaced behind the same MAC. Confidence halves
+//! every `half_life` since a device's detection was made or last verified, and
+//! every `interval` the devices whose confidence decayed below `threshold` are
+//! detected again with fresh probes, longest unverified first and at most
+//! `max_per_run` at a time. Only detections that were at or above the threshold
+//! when made count as decayed: an "Unknown" or TTL-only verdict starts below it
+//! and would otherwise take every slot on every run. Re-verification goes through
+//! the probe scheduler like any detection, so its cooldowns, queue limits and
+//! failure backoff hold; devices the presence engine knows to be offline wait
+//! until they return, without using up a slot.
+
+use crate::db::queries::{DeviceSummary, StoredDetection};
+use crate::presence::PresenceState;
+use crate::web::state::AppState;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+#[derive(Debug, Clone)]
+pub struct ReverifyConfig {
+    /// Time for confidence to halve
+    pub half_life: Duration,
+    /// Devices below this decayed confidence are re-verified
+    pub threshold: f64,
+    pub interval: Duration,
+    /// Devices re-verified per run
+    pub max_per_run: usize,
+}
+
+impl ReverifyConfig {
+    /// Confidence left `verified_at` (RFC 3339) later; None if the time doesn't parse
+    pub fn decayed(&self, confidence: f64, verified_at: &str, now: DateTime<Utc>) -> Option<f64> {
+        let verified_at = DateTime::parse_from_rfc3339(verified_at).ok()?;
+        let age = (now - verified_at.with_timezone(&Utc)).num_seconds().max(0) as f64;
+        Some(confidence * 0.5f64.powf(age / self.half_life.as_secs_f64().max(1.0)))
+    }
+
+    /// Set each device's current (decayed) confidence for /api/devices
+    pub fn annotate(&self, devices: &mut [DeviceSummary], now: DateTime<Utc>) {
+        for device in devices {
+            device.current_confidence = device
+                .confidence
+                .zip(device.verified_at.as_deref())
+                .and_then(|(confidence, verified_at)| self.decayed(confidence, verified_at, now));
+        }
+    }
+
+    /// Detections that decayed below the threshold from at or above it, longest
+    /// unverified first
+    fn due(&self, detections: Vec<StoredDetection>, now: DateTime<Utc>) -> Vec<(StoredDetection, f64)> {
+        let mut due: Vec<_> = detections
+            .into_iter()
+            .filter(|detection| detection.confidence >= self.threshold)
+            .filter_map(|detection| {
+                let verified_at = DateTime::parse_from_rfc3339(&detection.verified_at).ok()?;
+                let decayed = self.decayed(detection.confidence, &detection.verified_at, now)?;
+                (decayed < self.threshold).then_some((verified_at, detection, decayed))
+            })
+            .collect();
+        due.sort_by_key(|(verified_at, _, _)| *verified_at);
+        due.into_iter().map(|(_, detection, decayed)| (detection, decayed)).collect()
+    }
+}
+
+/// Re-verify decayed detections every `interval`; only the cluster leader probes
+pub fn spawn(state: Arc<AppState>, config: ReverifyConfig) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.interval);
+        loop {
+            interval.tick().await;
+            if !state.is_active() {
+                continue;
+            }
+            if let Err(e) = run(&state, &config).await {
+                warn!("Re-verification failed: {}", e);
+            }
+        }
+    });
+}
+
+async fn run(state: &AppState, config: &ReverifyConfig) -> Result<(), sqlx::Error> {
+    let detections = crate::db::queries::latest_detections(&state.db_pool).await?;
+    let offline = |mac_address: &str| {
+        state.presence.as_ref().is_some_and(|presence| {
+            presence
+                .status(mac_address)
+                .is_some_and(|status| status.state == PresenceState::Offline)
+        })
+    };
+    let due = config
+        .due(detections, Utc::now())
+        .into_iter()
+        .filter(|(detection, _)| !offline(&detection.mac_address))
+        .take(config.max_per_run);
+    let mut verified = 0;
+    for (detection, decayed) in due {
+        let Some(request) = state.reverify(&detection.mac_address).await? else {
+            continue;
+        };
+        debug!(
+            "Re-verified {}: {:?} at {:.2} (had decayed to {:.2})",
+            detection.mac_address,
+            request.os_name,
+            request.confidence.unwrap_or_default(),
+            decayed
+        );
+        verified += 1;
+    }
+    if verified > 0 {
+        info!("Re-verified {} devices with decayed detection confidence", verified);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ReverifyConfig {
+        ReverifyConfig {
+            half_life: Duration::from_secs(86400),
+            threshold: 0.5,
+            interval: Duration::from_secs(900),
+            max_per_run: 1,
+        }
+    }
+
+    fn detection(mac_address: &str, confidence: f64, verified_at: &str) -> StoredDetection {
+        StoredDetection {
+            mac_address: mac_address.to_string(),
+            confidence,
+            verified_at: verified_at.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_decay_and_due() {
+        let config = config();
+        let now = DateTime::parse_from_rfc3339("2025-01-03T00:00:00+00:00").unwrap().with_timezone(&Utc);
+        assert_eq!(config.decayed(0.9, "2025-01-03T00:00:00+00:00", now), Some(0.9));
+        assert_eq!(config.decayed(0.9, "2025-01-02T00:00:00+00:00", now), Some(0.45));
+        assert_eq!(config.decayed(0.8, "2025-01-01T00:00:00+00:00", now), Some(0.2));
+        assert_eq!(config.decayed(0.8, "yesterday", now), None);
+
+        let due = config.due(
+            vec![
+                detection("aa:bb:cc:00:00:01", 0.95, "2025-01-02T12:00:00+00:00"),
+                detection("aa:bb:cc:00:00:02", 0.99, "2025-01-01T12:00:00+00:00"),
+                detection("aa:bb:cc:00:00:03", 0.8, "2025-01-01T00:00:00+00:00"),
+                // Below the threshold from the start: nothing decayed, nothing to refresh
+                detection("aa:bb:cc:00:00:04", 0.0, "2025-01-01T00:00:00+00:00"),
+                detection("aa:bb:cc:00:00:05", 0.3, "2025-01-02T23:00:00+00:00"),
+            ],
+            now,
+        );
+        // Longest unverified first
+        let macs: Vec<_> = due.iter().map(|(detection, _)| detection.mac_address.as_str()).collect();
+        assert_eq!(macs, ["aa:bb:cc:00:00:03", "aa:bb:cc:00:00:02"]);
+    }
+
+    #[tokio::test]
+    async fn test_reverify_restarts_decay() {
+        use crate::alerts::AlertManager;
+        use crate::hybrid_detection::{HybridConfig, HybridDetector};
+        use crate::logger::RequestLogger;
+
+        let state = AppState::new(
+            Arc::new(RequestLogger::disabled()),
+            crate::db::test_pool().await,
+            Arc::new(HybridDetector::new(HybridConfig {
+                enable_smb_probing: false,
+                enable_http_probing: false,
+                ..Default::default()
+            })),
+            None,
+            Arc::new(AlertManager::new(Default::default())),
+            None,
+            None,
+        );
+        sqlx::query(
+            "INSERT INTO dhcp_requests (timestamp, source_ip, source_port, mac_address, message_type, xid, fingerprint,
+                 raw_options, os_name, confidence)
+             VALUES ('2025-01-01T00:00:00+00:00', '10.0.0.5', 68, 'aa:bb:cc:00:00:01', 'REQUEST', '0x1',
+                 '1,3,6,15,31,33,43,44,46,47,121,249,252', '[]', 'Windows (old guess)', 0.9)",
+        )
+        .execute(&state.db_pool)
+        .await
+        .unwrap();
+
+        run(&state, &config()).await.unwrap();
+        let detections = crate::db::queries::latest_detections(&state.db_pool).await.unwrap();
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].confidence as f32, 0.95);
+        assert_ne!(detections[0].verified_at, "2025-01-01T00:00:00+00:00");
+        let (latest, _) = crate::db::queries::latest_for_mac(&state.db_pool, "aa:bb:cc:00:00:01")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(latest.os_name.as_deref(), Some("Windows 10/8/8.1"));
+    }
+}
@@ -0,0 +1,224 @@
+//! Classification rules: device labels from what a client says about itself.
+//!
+//! Many clients name their platform outright in option 60 ("android-dhcp-13",
+//! "udhcp 1.36.1", "Hewlett-Packard JetDirect") or their hostname, and some are
+//! identified by their MAC OUI alone. Rules in classification_rules.toml match
+//! case-insensitive regexes against the vendor class, the option 12 hostname and
+//! the OUI ("aa:bb:cc"), and are checked after MAC mappings but before the option
+//! 55 fingerprint. The first rule whose patterns all match wins.
+
+use crate::fingerprint::MacOsInfo;
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::sync::RwLock;
+use utoipa::ToSchema;
+
+/// Classification rules, re-read on reload and rewritten by the API
+pub const RULES_PATH: &str = "classification_rules.toml";
+
+/// One rule as written in the file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ClassificationRule {
+    /// Pattern for the option 60 vendor class
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vendor_class: Option<String>,
+    /// Pattern for the option 12 hostname
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// Pattern for the first three octets of the MAC, as "aa:bb:cc"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oui: Option<String>,
+    pub os_name: String,
+    #[serde(default)]
+    pub device_class: String,
+    #[serde(default)]
+    pub vendor: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RulesFile {
+    #[serde(default)]
+    rules: Vec<ClassificationRule>,
+}
+
+/// A rule with its patterns compiled
+#[derive(Debug, Clone)]
+pub struct CompiledRule {
+    rule: ClassificationRule,
+    vendor_class: Option<Regex>,
+    hostname: Option<Regex>,
+    oui: Option<Regex>,
+}
+
+fn pattern(field: &str, pattern: &Option<String>) -> Result<Option<Regex>> {
+    pattern
+        .as_deref()
+        .map(|pattern| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| anyhow!("invalid {} pattern '{}': {}", field, pattern, e))
+        })
+        .transpose()
+}
+
+impl CompiledRule {
+    pub fn new(rule: ClassificationRule) -> Result<Self> {
+        if rule.os_name.trim().is_empty() {
+            return Err(anyhow!("rule without an os_name"));
+        }
+        let compiled = Self {
+            vendor_class: pattern("vendor_class", &rule.vendor_class)?,
+            hostname: pattern("hostname", &rule.hostname)?,
+            oui: pattern("oui", &rule.oui)?,
+            rule,
+        };
+        if compiled.vendor_class.is_none() && compiled.hostname.is_none() && compiled.oui.is_none() {
+            return Err(anyhow!(
+                "rule for '{}' needs a vendor_class, hostname or oui pattern",
+                compiled.rule.os_name
+            ));
+        }
+        Ok(compiled)
+    }
+
+    /// Whether every pattern the rule has matches; a missing field never matches
+    fn matches(&self, mac_address: &str, vendor_class: Option<&str>, hostname: Option<&str>) -> bool {
+        let field = |regex: &Option<Regex>, value: Option<&str>| {
+            regex.as_ref().is_none_or(|regex| value.is_some_and(|value| regex.is_match(value)))
+        };
+        let mac = crate::mac::canonical(mac_address);
+        let oui = mac.get(..8);
+        field(&self.vendor_class, vendor_class) && field(&self.hostname, hostname) && field(&self.oui, oui)
+    }
+}
+
+/// Compile rules, failing on the first invalid one
+pub fn compile(rules: Vec<ClassificationRule>) -> Result<Vec<CompiledRule>> {
+    rules
+        .into_iter()
+        .enumerate()
+        .map(|(i, rule)| CompiledRule::new(rule).map_err(|e| anyhow!("rule {}: {}", i + 1, e)))
+        .collect()
+}
+
+/// Read and compile a rules file; a missing file means no rules
+pub fn read_rules(path: &str) -> Result<Vec<CompiledRule>> {
+    match fs::read_to_string(path) {
+        Ok(content) => compile(toml::from_str::<RulesFile>(&content)?.rules),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Write rules to `path`, through a temporary file so a crash never leaves half a file
+pub fn write_rules(path: &str, rules: &[CompiledRule]) -> Result<()> {
+    let file = RulesFile {
+        rules: rules.iter().map(|compiled| compiled.rule.clone()).collect(),
+    };
+    let content = toml::to_string(&file)?;
+    let tmp_path = format!("{}.tmp", path);
+    fs::write(&tmp_path, content).and_then(|_| fs::rename(&tmp_path, path)).map_err(|e| {
+        let _ = fs::remove_file(&tmp_path);
+        anyhow!("failed to write {}: {}", path, e)
+    })
+}
+
+static RULES: Lazy<RwLock<Vec<CompiledRule>>> = Lazy::new(|| {
+    let rules = match read_rules(RULES_PATH) {
+        Ok(rules) => rules,
+        Err(e) => {
+            tracing::warn!("Failed to load {}: {}", RULES_PATH, e);
+            Vec::new()
+        }
+    };
+    if !rules.is_empty() {
+        tracing::info!("Loaded {} classification rules", rules.len());
+    }
+    RwLock::new(rules)
+});
+
+/// Replace the active rules
+pub fn install(rules: Vec<CompiledRule>) {
+    *RULES.write().unwrap() = rules;
+}
+
+/// The active rules in evaluation order
+pub fn rules() -> Vec<ClassificationRule> {
+    RULES.read().unwrap().iter().map(|compiled| compiled.rule.clone()).collect()
+}
+
+/// The label of the first rule matching the client, if any
+pub fn classify(mac_address: &str, vendor_class: Option<&str>, hostname: Option<&str>) -> Option<MacOsInfo> {
+    RULES
+        .read()
+        .unwrap()
+        .iter()
+        .find(|compiled| compiled.matches(mac_address, vendor_class, hostname))
+        .map(|compiled| MacOsInfo {
+            os_name: compiled.rule.os_name.clone(),
+            device_class: compiled.rule.device_class.clone(),
+            vendor: compiled.rule.vendor.clone(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(vendor_class: Option<&str>, hostname: Option<&str>, oui: Option<&str>, os_name: &str) -> ClassificationRule {
+        ClassificationRule {
+            vendor_class: vendor_class.map(str::to_string),
+            hostname: hostname.map(str::to_string),
+            oui: oui.map(str::to_string),
+            os_name: os_name.to_string(),
+            device_class: String::new(),
+            vendor: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_rule_matching() {
+        let rules = compile(vec![
+            rule(Some("^android-dhcp-13"), None, None, "Android 13"),
+            rule(Some("^udhcp"), Some("^cam-"), None, "Camera firmware"),
+            rule(Some("^udhcp"), None, None, "Embedded Linux"),
+            rule(None, None, Some("^00:1b:78$"), "Printer"),
+        ])
+        .unwrap();
+        let label = |mac: &str, vendor_class: Option<&str>, hostname: Option<&str>| {
+            rules
+                .iter()
+                .find(|compiled| compiled.matches(mac, vendor_class, hostname))
+                .map(|compiled| compiled.rule.os_name.as_str())
+        };
+
+        assert_eq!(label("aa:bb:cc:00:00:01", Some("Android-DHCP-13"), None), Some("Android 13"));
+        assert_eq!(label("aa:bb:cc:00:00:01", Some("udhcp 1.36.1"), Some("cam-garage")), Some("Camera firmware"));
+        assert_eq!(label("aa:bb:cc:00:00:01", Some("udhcp 1.36.1"), None), Some("Embedded Linux"));
+        // OUI patterns see the canonical form whatever notation the MAC came in
+        assert_eq!(label("00-1B-78-12-34-56", None, None), Some("Printer"));
+        assert_eq!(label("aa:bb:cc:00:00:01", Some("MSFT 5.0"), None), None);
+    }
+
+    #[test]
+    fn test_invalid_rules_and_file_round_trip() {
+        assert!(compile(vec![rule(Some("(unclosed"), None, None, "Broken")]).is_err());
+        assert!(compile(vec![rule(None, None, None, "Matches nothing")]).is_err());
+        assert!(compile(vec![rule(Some("^x"), None, None, " ")]).is_err());
+
+        let path = std::env::temp_dir().join(format!("ks-dhcpmon-rules-{}.toml", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert!(read_rules(path).unwrap().is_empty());
+
+        let rules = compile(vec![rule(Some("JetDirect"), None, None, "Printer")]).unwrap();
+        write_rules(path, &rules).unwrap();
+        let read = read_rules(path).unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].rule, rules[0].rule);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+//! Site policy scripts in Rhai. The script defines `on_request(request)`, which is
+//! called with each classified request as a map and may return a map of actions:
+//!
+//! ```rhai
+//! fn on_request(request) {
+//!     if request.mac_address.starts_with("00:15:5d") {
+//!         return #{ suppress: true };
+//!     }
+//!     if request.vendor_class == "MSFT 5.0" {
+//!         return #{ tags: ["windows"], notify: "Windows client on " + request.source_ip };
+//!     }
+//! }
+//! ```
+//!
+//! Returning nothing leaves the request alone. Scripts run with an operation budget so a
+//! runaway loop fails the call instead of stalling capture.
+
+use crate::alerts::Severity;
+use crate::db::device_meta::DeviceMeta;
+use crate::dhcp::DhcpRequest;
+use anyhow::{anyhow, bail, Result};
+use rhai::{Dynamic, Engine, Scope, AST};
+use serde::Deserialize;
+use std::path::Path;
+
+/// Function the script must define
+const HOOK: &str = "on_request";
+
+/// What a script asked to be done with a request
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScriptActions {
+    /// Drop the request: not logged, stored, counted or broadcast
+    pub suppress: bool,
+    /// Added to the device's tags
+    pub tags: Vec<String>,
+    /// Replace the device's name, location or owner
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub owner: Option<String>,
+    /// Raise a "script" alert for the device with this message
+    pub notify: Option<String>,
+    /// "info" (default), "warning" or "critical"
+    pub severity: Option<String>,
+}
+
+impl ScriptActions {
+    pub fn updates_meta(&self) -> bool {
+        !self.tags.is_empty() || self.name.is_some() || self.location.is_some() || self.owner.is_some()
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity.as_deref().and_then(Severity::from_name).unwrap_or(Severity::Info)
+    }
+
+    /// Apply the metadata changes, returning whether anything changed
+    pub fn apply_to(&self, meta: &mut DeviceMeta) -> bool {
+        let mut changed = false;
+        for (field, value) in [
+            (&mut meta.name, &self.name),
+            (&mut meta.location, &self.location),
+            (&mut meta.owner, &self.owner),
+        ] {
+            if value.is_some() && field != value {
+                field.clone_from(value);
+                changed = true;
+            }
+        }
+        for tag in &self.tags {
+            if !meta.tags.contains(tag) {
+                meta.tags.push(tag.clone());
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// A compiled policy script
+pub struct RequestScript {
+    engine: Engine,
+    ast: AST,
+}
+
+impl RequestScript {
+    pub fn load(path: &Path, max_operations: u64) -> Result<Self> {
+        let source = std::fs::read_to_string(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        Self::new(&source, max_operations).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    pub fn new(source: &str, max_operations: u64) -> Result<Self> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(max_operations);
+        engine.set_max_call_levels(32);
+        engine.set_max_string_size(64 * 1024);
+        engine.set_max_array_size(10_000);
+        engine.set_max_map_size(10_000);
+        // print() and debug() go to the application log
+        engine.on_print(|text| tracing::info!("script: {}", text));
+        engine.on_debug(|text, _, position| tracing::debug!("script {}: {}", position, text));
+
+        let ast = engine.compile(source).map_err(|e| anyhow!("{}", e))?;
+        if !ast.iter_functions().any(|f| f.name == HOOK && f.params.len() == 1) {
+            bail!("no {}(request) function defined", HOOK);
+        }
+        Ok(Self { engine, ast })
+    }
+
+    /// Call the hook with the request and read back its actions
+    pub fn run(&self, request: &DhcpRequest) -> Result<ScriptActions> {
+        let request = rhai::serde::to_dynamic(request).map_err(|e| anyhow!("{}", e))?;
+        let result: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), &self.ast, HOOK, (request,))
+            .map_err(|e| anyhow!("{}", e))?;
+        if result.is_unit() {
+            return Ok(ScriptActions::default());
+        }
+        rhai::serde::from_dynamic(&result).map_err(|e| anyhow!("invalid result from {}: {}", HOOK, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_actions() {
+        let script = RequestScript::new(
+            r#"
+            fn on_request(request) {
+                if request.mac_address.starts_with("00:15:5d") {
+                    return #{ suppress: true };
+                }
+                if request.vendor_class == "MSFT 5.0" {
+                    return #{ tags: ["windows"], name: request.hostname, notify: "seen", severity: "warning" };
+                }
+            }
+            "#,
+            10_000,
+        )
+        .unwrap();
+        let request = |mac: &str, vendor: Option<&str>| DhcpRequest {
+            mac_address: mac.to_string(),
+            vendor_class: vendor.map(str::to_string),
+            hostname: Some("pc".to_string()),
+            ..Default::default()
+        };
+
+        assert!(script.run(&request("00:15:5d:00:00:01", None)).unwrap().suppress);
+        assert_eq!(script.run(&request("aa:bb:cc:dd:ee:ff", None)).unwrap(), ScriptActions::default());
+
+        let actions = script.run(&request("aa:bb:cc:dd:ee:ff", Some("MSFT 5.0"))).unwrap();
+        assert_eq!(actions.severity(), Severity::Warning);
+        let mut meta = DeviceMeta { tags: vec!["lab".to_string()], ..Default::default() };
+        assert!(actions.apply_to(&mut meta));
+        assert_eq!((meta.name.as_deref(), meta.tags.clone()), (Some("pc"), vec!["lab".to_string(), "windows".to_string()]));
+        assert!(!actions.apply_to(&mut meta));
+
+        // Runaway loops hit the operation budget; scripts without the hook are rejected
+        let spin = RequestScript::new("fn on_request(request) { loop {} }", 1_000).unwrap();
+        assert!(spin.run(&request("aa:bb:cc:dd:ee:ff", None)).is_err());
+        assert!(RequestScript::new("fn other(x) {}", 1_000).is_err());
+    }
+}
@@ -0,0 +1,429 @@
+//! Load generation for `ks-dhcpmon simulate`.
+//!
+//! Devices are drawn from the fingerprint database (optionally weighted by
+//! `--mix`) and given random locally administered MACs, as phones with MAC
+//! randomization use. They join with DISCOVER/REQUEST, renew and now and then
+//! release, at a fixed packet rate. The packets either go over UDP to a running
+//! instance or, without `--target`, straight into this process's receive queue,
+//! so the DB writer and WebSocket fanout can be loaded without a network.
+
+use crate::demo::DemoEvent;
+use crate::dhcp::DhcpPacketBuilder;
+use crate::fingerprint::{self, OsInfo};
+use anyhow::{anyhow, Result};
+use rand::distributions::WeightedIndex;
+use rand::prelude::Distribution;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tracing::{info, warn};
+
+/// Simulated traffic stays out of the real database
+pub const SIMULATE_DATABASE_URL: &str = "sqlite:dhcp_monitor_simulate.db";
+
+const SERVER_ID: Ipv4Addr = Ipv4Addr::new(10, 100, 0, 1);
+const USAGE: &str = "simulate [--target host[:port]] [--rate packets/s] [--devices n] [--count n] [--mix term=weight,...] [--seed n]";
+/// How often progress is logged
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulateConfig {
+    /// Send to a running instance instead of feeding this process's pipeline
+    pub target: Option<SocketAddr>,
+    /// Packets per second
+    pub rate: f64,
+    pub devices: usize,
+    /// Stop after this many packets
+    pub count: Option<u64>,
+    /// Case-insensitive substrings of vendor, OS name or device class with their
+    /// weights; an empty mix draws evenly from every fingerprint
+    pub mix: Vec<(String, u32)>,
+    pub seed: u64,
+}
+
+impl Default for SimulateConfig {
+    fn default() -> Self {
+        Self {
+            target: None,
+            rate: 10.0,
+            devices: 100,
+            count: None,
+            mix: Vec::new(),
+            seed: rand::random(),
+        }
+    }
+}
+
+impl SimulateConfig {
+    /// Parse the arguments after `simulate`
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let mut config = Self::default();
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| anyhow!("{} needs a value; usage: {}", flag, USAGE))?;
+            match flag.as_str() {
+                "--target" => config.target = Some(parse_target(value)?),
+                "--rate" => {
+                    config.rate = value.parse().map_err(|_| anyhow!("invalid --rate '{}'", value))?;
+                    if !(config.rate > 0.0 && config.rate.is_finite()) {
+                        return Err(anyhow!("--rate must be above 0"));
+                    }
+                }
+                "--devices" => {
+                    config.devices = value.parse().map_err(|_| anyhow!("invalid --devices '{}'", value))?;
+                    config.devices = config.devices.max(1);
+                }
+                "--count" => {
+                    config.count = Some(value.parse().map_err(|_| anyhow!("invalid --count '{}'", value))?)
+                }
+                "--mix" => config.mix = parse_mix(value)?,
+                "--seed" => config.seed = value.parse().map_err(|_| anyhow!("invalid --seed '{}'", value))?,
+                other => return Err(anyhow!("unknown option '{}'; usage: {}", other, USAGE)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Whether `sent` packets reach `count`
+    pub fn finished(&self, sent: u64) -> bool {
+        self.count.is_some_and(|count| sent >= count)
+    }
+}
+
+/// host:port, or a host alone for the DHCP server port
+fn parse_target(value: &str) -> Result<SocketAddr> {
+    let with_port = if value.contains(':') { value.to_string() } else { format!("{}:67", value) };
+    with_port
+        .to_socket_addrs()
+        .map_err(|e| anyhow!("invalid --target '{}': {}", value, e))?
+        .find(SocketAddr::is_ipv4)
+        .ok_or_else(|| anyhow!("--target '{}' has no IPv4 address", value))
+}
+
+/// "windows=3,apple=1,printer"; a term without a weight counts 1
+fn parse_mix(value: &str) -> Result<Vec<(String, u32)>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|term| !term.is_empty())
+        .map(|term| match term.split_once('=') {
+            Some((name, weight)) => weight
+                .trim()
+                .parse()
+                .map(|weight| (name.trim().to_lowercase(), weight))
+                .map_err(|_| anyhow!("invalid weight in --mix term '{}'", term)),
+            None => Ok((term.to_lowercase(), 1)),
+        })
+        .collect()
+}
+
+/// What a kind of device sends, taken from a fingerprint database entry
+struct Kind {
+    parameter_list: Vec<u8>,
+    vendor_class: Option<&'static str>,
+    hostname: String,
+}
+
+impl Kind {
+    fn from_entry(fingerprint: &str, info: &OsInfo) -> Option<Self> {
+        let parameter_list = fingerprint
+            .split(',')
+            .map(|code| code.trim().parse().ok())
+            .collect::<Option<Vec<u8>>>()?;
+        let vendor_class = if info.vendor == "Microsoft" {
+            Some("MSFT 5.0")
+        } else if info.os_name.contains("Android") {
+            Some("android-dhcp-14")
+        } else {
+            None
+        };
+        let hostname = info
+            .os_name
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .take(12)
+            .collect::<String>();
+        Some(Self { parameter_list, vendor_class, hostname })
+    }
+
+    fn weight(info: &OsInfo, mix: &[(String, u32)]) -> u32 {
+        if mix.is_empty() {
+            return 1;
+        }
+        let fields = [&info.vendor, &info.os_name, &info.device_class].map(|field| field.to_lowercase());
+        mix.iter()
+            .filter(|(term, _)| fields.iter().any(|field| field.contains(term.as_str())))
+            .map(|(_, weight)| weight)
+            .sum()
+    }
+}
+
+struct Device {
+    mac: [u8; 6],
+    hostname: String,
+    kind: usize,
+    ip: Option<Ipv4Addr>,
+}
+
+/// Endless client traffic from a fingerprint-database device mix
+pub struct SimulatedTraffic {
+    rng: StdRng,
+    kinds: Vec<Kind>,
+    devices: Vec<Device>,
+    next_host: u16,
+    pending: VecDeque<DemoEvent>,
+}
+
+impl SimulatedTraffic {
+    pub fn new(config: &SimulateConfig) -> Result<Self> {
+        let mut kinds = Vec::new();
+        let mut weights = Vec::new();
+        for (fingerprint, info) in fingerprint::known_fingerprints() {
+            let weight = Kind::weight(&info, &config.mix);
+            if weight == 0 {
+                continue;
+            }
+            if let Some(kind) = Kind::from_entry(&fingerprint, &info) {
+                kinds.push(kind);
+                weights.push(weight);
+            }
+        }
+        if kinds.is_empty() {
+            return Err(anyhow!("--mix matches no fingerprint in the database"));
+        }
+
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let pick = WeightedIndex::new(&weights)?;
+        let devices = (0..config.devices.max(1))
+            .map(|_| {
+                let kind = pick.sample(&mut rng);
+                let mut mac: [u8; 6] = rng.gen();
+                // Locally administered unicast, like a randomized client MAC
+                mac[0] = (mac[0] & 0xfc) | 0x02;
+                let hostname = format!("{}-{:02x}{:02x}", kinds[kind].hostname, mac[4], mac[5]);
+                Device { mac, hostname, kind, ip: None }
+            })
+            .collect();
+
+        Ok(Self {
+            rng,
+            kinds,
+            devices,
+            next_host: 10,
+            pending: VecDeque::new(),
+        })
+    }
+
+    fn allocate_ip(&mut self) -> Ipv4Addr {
+        let [high, low] = self.next_host.to_be_bytes();
+        self.next_host = if self.next_host >= 0xfffe { 10 } else { self.next_host + 1 };
+        Ipv4Addr::new(10, 100, high, low)
+    }
+
+    pub fn next_event(&mut self) -> DemoEvent {
+        if let Some(event) = self.pending.pop_front() {
+            return event;
+        }
+
+        let index = self.rng.gen_range(0..self.devices.len());
+        match self.devices[index].ip {
+            None => {
+                // DISCOVER now, the selecting REQUEST next
+                let ip = self.allocate_ip();
+                self.devices[index].ip = Some(ip);
+                let discover = self.packet(index, 1, Ipv4Addr::UNSPECIFIED, &[]);
+                let select = [(50, ip.octets()), (54, SERVER_ID.octets())];
+                let request = self.packet(index, 3, Ipv4Addr::UNSPECIFIED, &select);
+                self.pending.push_back(request);
+                discover
+            }
+            Some(ip) if self.rng.gen_bool(0.05) => {
+                self.devices[index].ip = None;
+                self.packet(index, 7, ip, &[(54, SERVER_ID.octets())])
+            }
+            Some(ip) => self.packet(index, 3, ip, &[]),
+        }
+    }
+
+    fn packet(&mut self, index: usize, message_type: u8, ciaddr: Ipv4Addr, extra: &[(u8, [u8; 4])]) -> DemoEvent {
+        let device = &self.devices[index];
+        let kind = &self.kinds[device.kind];
+        let mut client_id = vec![1];
+        client_id.extend_from_slice(&device.mac);
+
+        let mut builder = DhcpPacketBuilder::message(message_type)
+            .mac(device.mac)
+            .xid(self.rng.gen())
+            .ciaddr(ciaddr)
+            .option(61, client_id);
+        for (code, value) in extra {
+            builder = builder.option(*code, value);
+        }
+        if message_type != 7 {
+            builder = builder.option(12, &device.hostname);
+            if let Some(vendor_class) = kind.vendor_class {
+                builder = builder.option(60, vendor_class);
+            }
+            builder = builder.option(55, &kind.parameter_list);
+        }
+
+        DemoEvent {
+            data: builder.build(),
+            source: SocketAddr::from((ciaddr, 68)),
+        }
+    }
+}
+
+/// Releases packets at a fixed rate, in batches when the caller falls behind
+pub struct Pacer {
+    rate: f64,
+    started: Instant,
+    released: u64,
+}
+
+impl Pacer {
+    pub fn new(rate: f64) -> Self {
+        Self { rate, started: Instant::now(), released: 0 }
+    }
+
+    /// Wait for the next packet to be due; returns how many are due now
+    pub async fn next_batch(&mut self) -> u64 {
+        let next = self.started + Duration::from_secs_f64((self.released + 1) as f64 / self.rate);
+        tokio::time::sleep_until(next.into()).await;
+        let due = (self.started.elapsed().as_secs_f64() * self.rate) as u64;
+        let batch = due.saturating_sub(self.released).max(1);
+        self.released += batch;
+        batch
+    }
+}
+
+/// Logs packets sent and the rate achieved every few seconds
+pub struct Progress {
+    started: Instant,
+    last_report: Instant,
+    pub sent: u64,
+    pub failed: u64,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self { started: now, last_report: now, sent: 0, failed: 0 }
+    }
+
+    pub fn report(&mut self, done: bool) {
+        if !done && self.last_report.elapsed() < REPORT_INTERVAL {
+            return;
+        }
+        self.last_report = Instant::now();
+        let elapsed = self.started.elapsed().as_secs_f64().max(0.001);
+        info!(
+            "Simulated {} packets in {:.0}s ({:.0}/s), {} failed",
+            self.sent,
+            elapsed,
+            self.sent as f64 / elapsed,
+            self.failed
+        );
+    }
+}
+
+/// Send simulated traffic to a running instance until `count` packets are out
+pub async fn send(config: &SimulateConfig, target: SocketAddr) -> Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    if let SocketAddr::V4(v4) = target {
+        socket.set_broadcast(v4.ip().is_broadcast())?;
+    }
+    let mut traffic = SimulatedTraffic::new(config)?;
+    info!(
+        "Simulating {} devices at {} packets/s against {}",
+        config.devices, config.rate, target
+    );
+
+    let mut pacer = Pacer::new(config.rate);
+    let mut progress = Progress::new();
+    while !config.finished(progress.sent) {
+        for _ in 0..pacer.next_batch().await {
+            if config.finished(progress.sent) {
+                break;
+            }
+            let event = traffic.next_event();
+            match socket.send_to(&event.data, target).await {
+                Ok(_) => progress.sent += 1,
+                Err(e) => {
+                    // Keep going; a full socket buffer is what overloading looks like
+                    if progress.failed == 0 {
+                        warn!("Failed to send to {}: {}", target, e);
+                    }
+                    progress.failed += 1;
+                }
+            }
+        }
+        progress.report(false);
+    }
+    progress.report(true);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dhcp::DhcpPacket;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_from_args() {
+        let config = SimulateConfig::from_args(&args(
+            "--target 127.0.0.1 --rate 500 --devices 0 --count 10 --mix windows=3,apple --seed 4",
+        ))
+        .unwrap();
+        assert_eq!(config.target, Some("127.0.0.1:67".parse().unwrap()));
+        assert_eq!((config.rate, config.devices, config.count, config.seed), (500.0, 1, Some(10), 4));
+        assert_eq!(config.mix, vec![("windows".to_string(), 3), ("apple".to_string(), 1)]);
+        assert_eq!(
+            SimulateConfig::from_args(&args("--target 127.0.0.1:6767")).unwrap().target,
+            Some("127.0.0.1:6767".parse().unwrap())
+        );
+
+        assert!(SimulateConfig::from_args(&args("--rate 0")).is_err());
+        assert!(SimulateConfig::from_args(&args("--rate")).is_err());
+        assert!(SimulateConfig::from_args(&args("--mix windows=x")).is_err());
+        assert!(SimulateConfig::from_args(&args("--verbose 1")).is_err());
+    }
+
+    #[test]
+    fn test_packets_come_from_the_mix() {
+        let config = SimulateConfig {
+            devices: 30,
+            mix: vec![("apple".to_string(), 1)],
+            seed: 9,
+            ..Default::default()
+        };
+        let mut traffic = SimulatedTraffic::new(&config).unwrap();
+        let mut types = std::collections::HashSet::new();
+
+        for _ in 0..500 {
+            let event = traffic.next_event();
+            assert!(crate::lint::lint_packet(&event.data).is_empty());
+            let packet = DhcpPacket::parse(&event.data).unwrap();
+            assert_eq!(packet.chaddr[0] & 0x03, 0x02);
+            let message_type = packet.get_message_type().unwrap();
+            if message_type != 7 {
+                let info = fingerprint::lookup_fingerprint(&packet.get_fingerprint()).unwrap();
+                assert_eq!(info.vendor, "Apple");
+            }
+            types.insert(message_type);
+        }
+        assert_eq!(types, [1, 3, 7].into_iter().collect());
+
+        let config = SimulateConfig { mix: vec![("no-such-vendor".to_string(), 1)], ..Default::default() };
+        assert!(SimulatedTraffic::new(&config).is_err());
+    }
+}
@@ -1,29 +1,117 @@
+use crate::probe_identity::ProbeIdentity;
 use tokio::net::TcpStream;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::time::{timeout, Duration};
 use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 
 /// SMB probe result containing OS detection information
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SmbProbeResult {
     pub os_version: String,
     pub build_number: Option<u32>,
+    /// The build came from the server's NTLMSSP challenge, not the dialect
+    #[serde(default)]
+    pub build_exact: bool,
     pub smb_dialect: String,
     pub success: bool,
+    /// What the server said about itself in the negotiate response
+    pub server: Option<SmbServerInfo>,
 }
 
+/// Server details from an SMB2 negotiate response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SmbServerInfo {
+    pub signing_enabled: bool,
+    /// Clients can't connect without signing (the default on domain controllers)
+    pub signing_required: bool,
+    /// Capability flags, e.g. "dfs", "leasing", "encryption"
+    pub capabilities: Vec<String>,
+    /// Not sent by SMB1 servers without extended security
+    pub server_guid: Option<String>,
+    /// The server's clock when it answered
+    pub system_time: Option<DateTime<Utc>>,
+    /// When the SMB server started; Windows 10 and later report none
+    pub server_start_time: Option<DateTime<Utc>>,
+    /// Seconds between server_start_time and system_time
+    pub uptime_secs: Option<u64>,
+    /// Self-reported by SMB1 servers in the session setup response, e.g. "Windows 5.1"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub native_os: Option<String>,
+    /// e.g. "Windows 2000 LAN Manager" or "Samba 3.0.37"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub native_lan_manager: Option<String>,
+    /// Domain or workgroup the SMB1 server belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub primary_domain: Option<String>,
+}
+
+/// SMB2 negotiate response capability bits (MS-SMB2 2.2.4)
+const CAPABILITIES: &[(u32, &str)] = &[
+    (0x01, "dfs"),
+    (0x02, "leasing"),
+    (0x04, "large_mtu"),
+    (0x08, "multi_channel"),
+    (0x10, "persistent_handles"),
+    (0x20, "directory_leasing"),
+    (0x40, "encryption"),
+];
+
+/// SMB1 negotiate response capability bits (MS-CIFS 2.2.4.52.2)
+const SMB1_CAPABILITIES: &[(u32, &str)] = &[
+    (0x0000_0004, "unicode"),
+    (0x0000_0008, "large_files"),
+    (0x0000_0010, "nt_smbs"),
+    (0x0000_0040, "nt_status"),
+    (0x0000_1000, "dfs"),
+    (0x8000_0000, "extended_security"),
+];
+
+/// Dialect reported for servers that only answered the SMB1 fallback
+pub const SMB1_DIALECT: &str = "SMB 1 (NT LM 0.12, legacy)";
+
+/// Seconds from the FILETIME epoch (1601-01-01) to the Unix epoch
+const FILETIME_UNIX_OFFSET_SECS: i64 = 11_644_473_600;
+
+/// A FILETIME (100 ns ticks since 1601), None for 0 (not set)
+fn filetime(ticks: u64) -> Option<DateTime<Utc>> {
+    if ticks == 0 {
+        return None;
+    }
+    let secs = (ticks / 10_000_000) as i64 - FILETIME_UNIX_OFFSET_SECS;
+    let nanos = (ticks % 10_000_000) as u32 * 100;
+    DateTime::from_timestamp(secs, nanos)
+}
+
+/// GUID in its usual text form (the first three fields are little-endian)
+fn format_guid(bytes: &[u8]) -> String {
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{}",
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        u16::from_le_bytes([bytes[4], bytes[5]]),
+        u16::from_le_bytes([bytes[6], bytes[7]]),
+        bytes[8],
+        bytes[9],
+        bytes[10..16].iter().map(|b| format!("{:02x}", b)).collect::<String>()
+    )
+}
+
+const UNKNOWN_WINDOWS_VERSION: &str = "Windows (unknown version)";
+
 /// Windows version detection based on build number
 /// Reference: https://learn.microsoft.com/en-us/windows/release-health/windows11-release-information
-fn build_to_windows_version(build: u32) -> &'static str {
+pub fn build_to_windows_version(build: u32) -> &'static str {
     match build {
         // Windows 11 builds
-        22000..=22999 => "Windows 11 21H2",
+        22000..=22620 => "Windows 11 21H2",
         22621..=22630 => "Windows 11 22H2",
         22631..=22999 => "Windows 11 23H2",
         26000..=29999 => "Windows 11 (Insider/Future)",
 
         // Windows 10 builds
-        19041..=19045 => "Windows 10 2004/20H2/21H1",
+        19041 => "Windows 10 2004",
         19042 => "Windows 10 20H2",
         19043 => "Windows 10 21H1",
         19044 => "Windows 10 21H2",
@@ -44,55 +132,86 @@ fn build_to_windows_version(build: u32) -> &'static str {
         // Windows 7
         7600..=7601 => "Windows 7",
 
-        _ => "Windows (unknown version)",
+        _ => UNKNOWN_WINDOWS_VERSION,
     }
 }
 
 /// Probe an IP address via SMB to detect Windows version
 /// This performs a passive SMB negotiation without authentication
-pub async fn probe_smb(ip: &str, timeout_secs: u64) -> Result<SmbProbeResult> {
-    tracing::debug!("Probing SMB on {}:445", ip);
+#[tracing::instrument(name = "smb_probe", skip_all, fields(ip = %ip, dialect))]
+pub async fn probe_smb(ip: &str, timeout_secs: u64, identity: &ProbeIdentity) -> Result<SmbProbeResult> {
+    tracing::debug!("Probing SMB on port 445");
+    let started = std::time::Instant::now();
+
+    let addr: SocketAddr = format!("{}:445", ip).parse()?;
 
     // Try to connect to SMB port with timeout
-    let stream = match timeout(
+    let mut stream = match timeout(
         Duration::from_secs(timeout_secs),
-        TcpStream::connect(format!("{}:445", ip))
+        identity.connect(addr)
     ).await {
         Ok(Ok(s)) => {
-            println!("  🔌 TCP connection established to {}:445", ip);
+            tracing::trace!(elapsed_ms = started.elapsed().as_millis() as u64, "TCP connection established");
             s
         }
-        Ok(Err(_e)) => {
-            println!("  🚫 Connection refused by {}:445 (port closed or filtered)", ip);
+        Ok(Err(e)) => {
+            tracing::debug!(error = %e, "SMB port closed or filtered");
             return Ok(SmbProbeResult {
                 os_version: "Unknown (SMB port closed)".to_string(),
                 build_number: None,
+                build_exact: false,
                 smb_dialect: "N/A".to_string(),
                 success: false,
+                server: None,
             });
         }
         Err(_) => {
-            println!("  ⏱️  Connection timeout to {}:445 ({}s elapsed)", ip, timeout_secs);
+            tracing::debug!(elapsed_ms = started.elapsed().as_millis() as u64, "SMB connection timed out");
             return Ok(SmbProbeResult {
                 os_version: "Unknown (connection timeout)".to_string(),
                 build_number: None,
+                build_exact: false,
                 smb_dialect: "N/A".to_string(),
                 success: false,
+                server: None,
             });
         }
     };
 
     // Send SMB2 Negotiate request
-    println!("  📤 Sending SMB2 negotiate request to {}...", ip);
-    let result = send_smb2_negotiate(stream, timeout_secs).await?;
+    let mut result = send_smb2_negotiate(&mut stream, timeout_secs, identity).await?;
+    tracing::Span::current().record("dialect", result.smb_dialect.as_str());
+
+    // Then start a session like any client would: Windows names its exact build in
+    // the NTLMSSP challenge. The handshake stops there, so no logon is attempted.
+    match ntlmssp_build(&mut stream, timeout_secs, identity).await {
+        Ok(Some(build)) => {
+            let version = build_to_windows_version(build);
+            if version != UNKNOWN_WINDOWS_VERSION {
+                result.os_version = version.to_string();
+            }
+            result.build_number = Some(build);
+            result.build_exact = true;
+        }
+        Ok(None) => tracing::debug!("NTLMSSP challenge carried no version"),
+        Err(e) => tracing::debug!(error = %e, "SMB session setup gave no NTLMSSP challenge"),
+    }
+    tracing::debug!(
+        os = %result.os_version,
+        build = ?result.build_number,
+        signing_required = ?result.server.as_ref().map(|server| server.signing_required),
+        uptime_secs = ?result.server.as_ref().and_then(|server| server.uptime_secs),
+        duration_ms = started.elapsed().as_millis() as u64,
+        "SMB negotiate answered"
+    );
 
     Ok(result)
 }
 
 /// Send SMB2 Negotiate request and parse response
-async fn send_smb2_negotiate(mut stream: TcpStream, timeout_secs: u64) -> Result<SmbProbeResult> {
+async fn send_smb2_negotiate(stream: &mut TcpStream, timeout_secs: u64, identity: &ProbeIdentity) -> Result<SmbProbeResult> {
     // Build SMB2 Negotiate packet
-    let negotiate_packet = build_smb2_negotiate_packet();
+    let negotiate_packet = build_smb2_negotiate_packet(identity);
 
     // Send the packet with timeout
     match timeout(
@@ -100,112 +219,206 @@ async fn send_smb2_negotiate(mut stream: TcpStream, timeout_secs: u64) -> Result
         stream.write_all(&negotiate_packet)
     ).await {
         Ok(Ok(_)) => {
-            println!("  ✉️  Sent {} bytes negotiate packet", negotiate_packet.len());
-        }
-        Ok(Err(e)) => {
-            println!("  ❌ Failed to send negotiate: {}", e);
-            return Err(anyhow!("Failed to send SMB negotiate: {}", e));
-        }
-        Err(_) => {
-            println!("  ⏱️  Send timeout");
-            return Err(anyhow!("SMB negotiate send timeout"));
+            tracing::trace!(bytes = negotiate_packet.len(), "Sent SMB2 negotiate request");
         }
+        Ok(Err(e)) => return Err(anyhow!("Failed to send SMB negotiate: {}", e)),
+        Err(_) => return Err(anyhow!("SMB negotiate send timeout")),
     }
 
-    // Read response with timeout
-    let mut response = vec![0u8; 4096];
-    let bytes_read = match timeout(
-        Duration::from_secs(timeout_secs),
-        stream.read(&mut response)
-    ).await {
-        Ok(Ok(n)) => {
-            println!("  📥 Received {} bytes response", n);
-            n
-        }
-        Ok(Err(e)) => {
-            println!("  ❌ Failed to read response: {}", e);
-            return Err(anyhow!("Failed to read SMB response: {}", e));
-        }
-        Err(_) => {
-            println!("  ⏱️  Response timeout");
-            return Err(anyhow!("SMB response read timeout"));
-        }
-    };
-
-    if bytes_read == 0 {
-        println!("  ⚠️  Empty response received");
-        return Err(anyhow!("Empty SMB response"));
-    }
+    // Read the whole response, so the session setup that follows starts clean
+    let response = read_netbios_message(stream, Duration::from_secs(timeout_secs)).await?;
+    tracing::trace!(bytes = response.len(), "Received SMB2 negotiate response");
 
     // Parse the SMB2 response
-    println!("  🔍 Parsing SMB2 response...");
-    let result = parse_smb2_response(&response[..bytes_read])?;
-    println!("  ✅ Detected: {} (dialect: {})", result.os_version, result.smb_dialect);
-    Ok(result)
+    parse_smb2_response(&response)
+}
+
+/// Send an SMB2 Session Setup with an NTLMSSP negotiate; the build from the challenge, if any
+async fn ntlmssp_build(stream: &mut TcpStream, timeout_secs: u64, identity: &ProbeIdentity) -> Result<Option<u32>> {
+    let wait = Duration::from_secs(timeout_secs);
+    timeout(wait, stream.write_all(&build_smb2_session_setup_packet(identity)))
+        .await
+        .map_err(|_| anyhow!("SMB session setup send timeout"))??;
+    let response = read_netbios_message(stream, wait).await?;
+    parse_ntlmssp_challenge(&response)
 }
 
 /// Build SMB2 Negotiate packet
-/// This is a minimal SMB2 negotiate request
-fn build_smb2_negotiate_packet() -> Vec<u8> {
-    let mut packet = Vec::new();
+/// This is a minimal SMB2 negotiate request; in stealth mode the header and
+/// negotiate fields match what a stock Windows 10/11 client sends
+fn build_smb2_negotiate_packet(identity: &ProbeIdentity) -> Vec<u8> {
+    let mut packet = smb2_header(0x0000, 0, identity);
 
-    // NetBIOS Session Service header (4 bytes)
-    packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // Length placeholder
+    // SMB2 Negotiate Request (36 bytes)
+    packet.extend_from_slice(&[0x24, 0x00]); // StructureSize (36)
+    packet.extend_from_slice(&[0x05, 0x00]); // DialectCount (5 dialects)
+    if identity.stealth {
+        packet.extend_from_slice(&[0x01, 0x00]); // SecurityMode: signing enabled
+        packet.extend_from_slice(&[0x00, 0x00]); // Reserved
+        packet.extend_from_slice(&[0x7F, 0x00, 0x00, 0x00]); // Capabilities (Windows client set)
+    } else {
+        packet.extend_from_slice(&[0x00, 0x00]); // SecurityMode
+        packet.extend_from_slice(&[0x00, 0x00]); // Reserved
+        packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // Capabilities
+    }
+    packet.extend_from_slice(&identity.smb_client_guid); // ClientGuid
+    packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]); // ClientStartTime
 
-    // SMB2 Header (64 bytes)
+    // Dialects: SMB 2.0.2, 2.1, 3.0, 3.0.2, 3.1.1
+    packet.extend_from_slice(&[0x02, 0x02]); // SMB 2.0.2
+    packet.extend_from_slice(&[0x10, 0x02]); // SMB 2.1
+    packet.extend_from_slice(&[0x00, 0x03]); // SMB 3.0
+    packet.extend_from_slice(&[0x02, 0x03]); // SMB 3.0.2
+    packet.extend_from_slice(&[0x11, 0x03]); // SMB 3.1.1
+
+    finish_netbios(packet)
+}
+
+/// NetBIOS length placeholder and a 64-byte SMB2 header for `command`
+fn smb2_header(command: u16, message_id: u64, identity: &ProbeIdentity) -> Vec<u8> {
+    let mut packet = vec![0x00, 0x00, 0x00, 0x00]; // NetBIOS length placeholder
     packet.extend_from_slice(&[0xFE, b'S', b'M', b'B']); // Protocol: SMB2
     packet.extend_from_slice(&[0x40, 0x00]); // Header length (64)
     packet.extend_from_slice(&[0x00, 0x00]); // Credit charge
     packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // Status
-    packet.extend_from_slice(&[0x00, 0x00]); // Command: Negotiate (0x0000)
-    packet.extend_from_slice(&[0x00, 0x00]); // Credits requested
+    packet.extend_from_slice(&command.to_le_bytes());
+    if identity.stealth {
+        packet.extend_from_slice(&[0x1F, 0x00]); // Credits requested (31, as Windows)
+    } else {
+        packet.extend_from_slice(&[0x00, 0x00]); // Credits requested
+    }
     packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // Flags
     packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // NextCommand
-    packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]); // MessageId
+    packet.extend_from_slice(&message_id.to_le_bytes()); // MessageId
     packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // Reserved
     packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // TreeId
     packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]); // SessionId
     packet.extend_from_slice(&[0x00; 16]); // Signature
+    packet
+}
 
-    // SMB2 Negotiate Request (36 bytes)
-    packet.extend_from_slice(&[0x24, 0x00]); // StructureSize (36)
-    packet.extend_from_slice(&[0x05, 0x00]); // DialectCount (5 dialects)
-    packet.extend_from_slice(&[0x00, 0x00]); // SecurityMode
-    packet.extend_from_slice(&[0x00, 0x00]); // Reserved
-    packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // Capabilities
-    packet.extend_from_slice(&[0x00; 16]); // ClientGuid
-    packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]); // ClientStartTime
+/// NTLMSSP negotiate flags a Windows client sends: Unicode, OEM, request target, NTLM,
+/// always sign, extended session security, version, 128-bit, key exchange, 56-bit
+const NTLMSSP_NEGOTIATE_FLAGS: u32 = 0xE208_8207;
+const NTLMSSP_NEGOTIATE_OEM_WORKSTATION_SUPPLIED: u32 = 0x0000_2000;
+const NTLMSSP_NEGOTIATE_VERSION: u32 = 0x0200_0000;
+const STATUS_MORE_PROCESSING_REQUIRED: u32 = 0xC000_0016;
+
+/// NTLMSSP NEGOTIATE_MESSAGE, naming the configured workstation if any
+fn build_ntlmssp_negotiate(workstation: Option<&str>) -> Vec<u8> {
+    let workstation = workstation.unwrap_or_default().as_bytes();
+    let mut flags = NTLMSSP_NEGOTIATE_FLAGS;
+    if !workstation.is_empty() {
+        flags |= NTLMSSP_NEGOTIATE_OEM_WORKSTATION_SUPPLIED;
+    }
 
-    // Dialects: SMB 2.0.2, 2.1, 3.0, 3.0.2, 3.1.1
-    packet.extend_from_slice(&[0x02, 0x02]); // SMB 2.0.2
-    packet.extend_from_slice(&[0x10, 0x02]); // SMB 2.1
-    packet.extend_from_slice(&[0x00, 0x03]); // SMB 3.0
-    packet.extend_from_slice(&[0x02, 0x03]); // SMB 3.0.2
-    packet.extend_from_slice(&[0x11, 0x03]); // SMB 3.1.1
+    let mut token = b"NTLMSSP\0".to_vec();
+    token.extend_from_slice(&1u32.to_le_bytes()); // MessageType: negotiate
+    token.extend_from_slice(&flags.to_le_bytes());
+    token.extend_from_slice(&[0x00; 8]); // DomainNameFields: none
+    token.extend_from_slice(&(workstation.len() as u16).to_le_bytes()); // WorkstationLen
+    token.extend_from_slice(&(workstation.len() as u16).to_le_bytes()); // WorkstationMaxLen
+    token.extend_from_slice(&40u32.to_le_bytes()); // WorkstationBufferOffset, after Version
+    token.extend_from_slice(&[10, 0]); // Version: Windows 10.0
+    token.extend_from_slice(&19045u16.to_le_bytes()); // ProductBuild
+    token.extend_from_slice(&[0x00, 0x00, 0x00, 0x0F]); // Reserved, NTLMSSP revision 15
+    token.extend_from_slice(workstation);
+    token
+}
 
-    // Update NetBIOS Session Service length (total length - 4 bytes)
-    let total_len = (packet.len() - 4) as u32;
-    packet[0..4].copy_from_slice(&total_len.to_be_bytes());
+/// DER element with a short or long form length
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut element = vec![tag];
+    match content.len() {
+        len @ 0..=0x7F => element.push(len as u8),
+        len @ 0x80..=0xFF => element.extend_from_slice(&[0x81, len as u8]),
+        len => {
+            element.push(0x82);
+            element.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+    }
+    element.extend_from_slice(content);
+    element
+}
 
-    packet
+/// SPNEGO NegTokenInit offering only NTLMSSP and carrying its first token
+fn spnego_init(token: &[u8]) -> Vec<u8> {
+    const SPNEGO_OID: &[u8] = &[0x06, 0x06, 0x2B, 0x06, 0x01, 0x05, 0x05, 0x02];
+    const NTLMSSP_OID: &[u8] = &[0x06, 0x0A, 0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x02, 0x0A];
+    let mech_types = der(0xA0, &der(0x30, NTLMSSP_OID));
+    let mech_token = der(0xA2, &der(0x04, token));
+    let neg_token_init = der(0xA0, &der(0x30, &[mech_types, mech_token].concat()));
+    der(0x60, &[SPNEGO_OID, &neg_token_init].concat())
+}
+
+/// SMB2 Session Setup carrying an NTLMSSP negotiate
+fn build_smb2_session_setup_packet(identity: &ProbeIdentity) -> Vec<u8> {
+    let security_buffer = spnego_init(&build_ntlmssp_negotiate(identity.workstation.as_deref()));
+    let mut packet = smb2_header(0x0001, 1, identity);
+    packet.extend_from_slice(&[0x19, 0x00]); // StructureSize (25)
+    packet.push(0x00); // Flags
+    packet.push(0x01); // SecurityMode: signing enabled
+    packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // Capabilities
+    packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // Channel
+    packet.extend_from_slice(&88u16.to_le_bytes()); // SecurityBufferOffset, from the SMB2 header
+    packet.extend_from_slice(&(security_buffer.len() as u16).to_le_bytes());
+    packet.extend_from_slice(&[0x00; 8]); // PreviousSessionId
+    packet.extend_from_slice(&security_buffer);
+    finish_netbios(packet)
+}
+
+/// Build number from the NTLMSSP challenge in a Session Setup response; None when
+/// the server left out its version (Samba sends one with build 0)
+fn parse_ntlmssp_challenge(data: &[u8]) -> Result<Option<u32>> {
+    if data.len() < 68 || data[4..8] != [0xFE, b'S', b'M', b'B'] {
+        return Err(anyhow!("Invalid SMB2 session setup response"));
+    }
+    let status = u32::from_le_bytes([data[12], data[13], data[14], data[15]]);
+    if status != STATUS_MORE_PROCESSING_REQUIRED {
+        return Err(anyhow!("SMB session setup failed with status 0x{:08x}", status));
+    }
+
+    // The challenge sits inside the SPNEGO response; find it by its signature
+    let start = data[68..]
+        .windows(8)
+        .position(|window| window == b"NTLMSSP\0")
+        .ok_or_else(|| anyhow!("No NTLMSSP challenge in session setup response"))?;
+    let challenge = &data[68 + start..];
+    if challenge.len() < 56 || challenge[8..12] != 2u32.to_le_bytes() {
+        return Err(anyhow!("Truncated NTLMSSP challenge"));
+    }
+    let flags = u32::from_le_bytes(challenge[20..24].try_into().unwrap());
+    let build = u16::from_le_bytes([challenge[50], challenge[51]]) as u32;
+    Ok((flags & NTLMSSP_NEGOTIATE_VERSION != 0 && build != 0).then_some(build))
 }
 
 /// Parse SMB2 Negotiate response to extract OS information
 fn parse_smb2_response(data: &[u8]) -> Result<SmbProbeResult> {
-    // Minimum SMB2 response is at least 68 bytes (NetBIOS header + SMB2 header)
-    if data.len() < 68 {
+    // NetBIOS header (4 bytes), SMB2 header (64 bytes), then the negotiate response
+    const BODY: usize = 68;
+    // Fixed part of the negotiate response, up to NegotiateContextOffset
+    const NEGOTIATE_LEN: usize = 64;
+
+    if data.len() < BODY {
         return Err(anyhow!("SMB response too short: {} bytes", data.len()));
     }
 
     // Skip NetBIOS header (4 bytes) and verify SMB2 signature
-    if data.len() < 8 || &data[4..8] != &[0xFE, b'S', b'M', b'B'] {
+    if data[4..8] != [0xFE, b'S', b'M', b'B'] {
         return Err(anyhow!("Invalid SMB2 signature"));
     }
+    let status = u32::from_le_bytes([data[12], data[13], data[14], data[15]]);
+    if status != 0 {
+        return Err(anyhow!("SMB negotiate failed with status 0x{:08x}", status));
+    }
 
-    // Get SMB dialect from response (at offset 68-70)
-    let smb_dialect = if data.len() >= 70 {
-        let dialect_code = u16::from_le_bytes([data[68], data[69]]);
-        match dialect_code {
+    let u16_at = |offset: usize| u16::from_le_bytes([data[BODY + offset], data[BODY + offset + 1]]);
+    let u32_at = |offset: usize| u32::from_le_bytes(data[BODY + offset..BODY + offset + 4].try_into().unwrap());
+    let u64_at = |offset: usize| u64::from_le_bytes(data[BODY + offset..BODY + offset + 8].try_into().unwrap());
+
+    // DialectRevision follows StructureSize and SecurityMode
+    let smb_dialect = if data.len() >= BODY + 6 {
+        match u16_at(4) {
             0x0202 => "SMB 2.0.2",
             0x0210 => "SMB 2.1",
             0x0300 => "SMB 3.0",
@@ -217,9 +430,34 @@ fn parse_smb2_response(data: &[u8]) -> Result<SmbProbeResult> {
         "SMB 2.x/3.x"
     };
 
-    // Try to extract more detailed version info from Security Buffer
-    // This is where NTLMSSP challenge would contain build numbers
-    // For now, we'll use heuristics based on SMB dialect
+    // Truncated responses still give the dialect, just no server details
+    let server = (data.len() >= BODY + NEGOTIATE_LEN).then(|| {
+        let security_mode = u16_at(2);
+        let capability_bits = u32_at(24);
+        let system_time = filetime(u64_at(40));
+        let server_start_time = filetime(u64_at(48));
+        SmbServerInfo {
+            signing_enabled: security_mode & 0x01 != 0,
+            signing_required: security_mode & 0x02 != 0,
+            capabilities: CAPABILITIES
+                .iter()
+                .filter(|(bit, _)| capability_bits & bit != 0)
+                .map(|(_, name)| name.to_string())
+                .collect(),
+            server_guid: Some(format_guid(&data[BODY + 8..BODY + 24])),
+            system_time,
+            server_start_time,
+            uptime_secs: system_time
+                .zip(server_start_time)
+                .and_then(|(now, start)| (now - start).num_seconds().try_into().ok()),
+            native_os: None,
+            native_lan_manager: None,
+            primary_domain: None,
+        }
+    });
+
+    // Estimate from the dialect; probe_smb replaces this with the exact build from
+    // the NTLMSSP challenge when the server sends one
 
     let (os_version, build_estimate) = match smb_dialect {
         "SMB 3.1.1" => {
@@ -245,21 +483,231 @@ fn parse_smb2_response(data: &[u8]) -> Result<SmbProbeResult> {
     Ok(SmbProbeResult {
         os_version: os_version.to_string(),
         build_number: build_estimate,
+        build_exact: false,
         smb_dialect: smb_dialect.to_string(),
         success: true,
+        server,
     })
 }
 
-/// Extended SMB probe with NTLMSSP authentication (more detailed but requires auth)
-/// This gets the exact build number from NTLMSSP challenge
-pub async fn probe_smb_with_ntlmssp(ip: &str, timeout_secs: u64) -> Result<SmbProbeResult> {
-    tracing::debug!("Probing SMB with NTLMSSP on {}:445", ip);
+/// Probe a server that doesn't speak SMB2 with an SMB1 negotiate and an anonymous
+/// session setup, which is where SMB1 servers name their OS and SMB implementation
+#[tracing::instrument(name = "smb1_probe", skip_all, fields(ip = %ip))]
+pub async fn probe_smb1(ip: &str, timeout_secs: u64, identity: &ProbeIdentity) -> Result<SmbProbeResult> {
+    let addr: SocketAddr = format!("{}:445", ip).parse()?;
+    let wait = Duration::from_secs(timeout_secs);
+    let mut stream = timeout(wait, identity.connect(addr))
+        .await
+        .map_err(|_| anyhow!("SMB1 connection timeout"))??;
+
+    timeout(wait, stream.write_all(&build_smb1_negotiate_packet()))
+        .await
+        .map_err(|_| anyhow!("SMB1 negotiate send timeout"))??;
+    let negotiate = read_netbios_message(&mut stream, wait).await?;
+    let (mut server, session_key) = parse_smb1_negotiate(&negotiate)?;
+
+    // Servers refusing anonymous sessions still answered the negotiate
+    let session = async {
+        timeout(wait, stream.write_all(&build_smb1_session_setup_packet(session_key)))
+            .await
+            .map_err(|_| anyhow!("SMB1 session setup send timeout"))??;
+        let response = read_netbios_message(&mut stream, wait).await?;
+        parse_smb1_session_setup(&response)
+    };
+    match session.await {
+        Ok((native_os, native_lan_manager, primary_domain)) => {
+            server.native_os = native_os;
+            server.native_lan_manager = native_lan_manager;
+            server.primary_domain = primary_domain.or(server.primary_domain);
+        }
+        Err(e) => tracing::debug!(error = %e, "SMB1 session setup gave no OS strings"),
+    }
+
+    let os_version = legacy_os_name(server.native_os.as_deref(), server.native_lan_manager.as_deref());
+    tracing::debug!(os = %os_version, lan_manager = ?server.native_lan_manager, "SMB1 negotiate answered");
+    Ok(SmbProbeResult {
+        os_version,
+        build_number: None,
+        build_exact: false,
+        smb_dialect: SMB1_DIALECT.to_string(),
+        success: true,
+        server: Some(server),
+    })
+}
+
+/// Read one NetBIOS session message, header included
+async fn read_netbios_message(stream: &mut TcpStream, wait: Duration) -> Result<Vec<u8>> {
+    let mut message = vec![0u8; 4];
+    timeout(wait, stream.read_exact(&mut message))
+        .await
+        .map_err(|_| anyhow!("SMB response read timeout"))??;
+    let len = u32::from_be_bytes([0, message[1], message[2], message[3]]) as usize;
+    if len > 64 * 1024 {
+        return Err(anyhow!("SMB response too long: {} bytes", len));
+    }
+    message.resize(4 + len, 0);
+    timeout(wait, stream.read_exact(&mut message[4..]))
+        .await
+        .map_err(|_| anyhow!("SMB response read timeout"))??;
+    Ok(message)
+}
+
+/// SMB1 header for `command`: Unicode strings and NT status codes, no extended
+/// security so the server sends its domain and accepts a plain anonymous session
+fn smb1_header(command: u8) -> Vec<u8> {
+    let mut packet = vec![0x00, 0x00, 0x00, 0x00]; // NetBIOS length placeholder
+    packet.extend_from_slice(&[0xFF, b'S', b'M', b'B']);
+    packet.push(command);
+    packet.extend_from_slice(&[0x00; 4]); // Status
+    packet.push(0x18); // Flags: canonicalized, case-insensitive paths
+    packet.extend_from_slice(&0xC001u16.to_le_bytes()); // Flags2: Unicode, NT status, long names
+    packet.extend_from_slice(&[0x00; 12]); // PIDHigh, SecurityFeatures, Reserved
+    packet.extend_from_slice(&[0x00, 0x00]); // TID
+    packet.extend_from_slice(&[0xFE, 0xFF]); // PIDLow
+    packet.extend_from_slice(&[0x00, 0x00]); // UID
+    packet.extend_from_slice(&[0x00, 0x00]); // MID
+    packet
+}
+
+fn finish_netbios(mut packet: Vec<u8>) -> Vec<u8> {
+    let total_len = (packet.len() - 4) as u32;
+    packet[0..4].copy_from_slice(&total_len.to_be_bytes());
+    packet
+}
+
+/// SMB1 Negotiate offering only NT LM 0.12, the dialect every SMB1 server since NT 4 speaks
+fn build_smb1_negotiate_packet() -> Vec<u8> {
+    let mut packet = smb1_header(0x72);
+    let dialects = b"\x02NT LM 0.12\x00";
+    packet.push(0x00); // WordCount
+    packet.extend_from_slice(&(dialects.len() as u16).to_le_bytes());
+    packet.extend_from_slice(dialects);
+    finish_netbios(packet)
+}
+
+/// Anonymous (null session) Session Setup AndX
+fn build_smb1_session_setup_packet(session_key: u32) -> Vec<u8> {
+    let mut packet = smb1_header(0x73);
+    packet.push(13); // WordCount
+    packet.extend_from_slice(&[0xFF, 0x00, 0x00, 0x00]); // No AndX command
+    packet.extend_from_slice(&4356u16.to_le_bytes()); // MaxBufferSize
+    packet.extend_from_slice(&2u16.to_le_bytes()); // MaxMpxCount
+    packet.extend_from_slice(&[0x00, 0x00]); // VcNumber
+    packet.extend_from_slice(&session_key.to_le_bytes());
+    packet.extend_from_slice(&[0x00; 4]); // Empty OEM and Unicode passwords
+    packet.extend_from_slice(&[0x00; 4]); // Reserved
+    packet.extend_from_slice(&0x0000_0054u32.to_le_bytes()); // Capabilities: Unicode, NT SMBs, NT status
+    // Pad to align the Unicode strings, then empty account, domain, OS and LAN Manager
+    let bytes = [0x00; 9];
+    packet.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+    packet.extend_from_slice(&bytes);
+    finish_netbios(packet)
+}
+
+/// Check an SMB1 response's signature, command and status; returns (Flags2, parameter block offset)
+fn smb1_response(data: &[u8], command: u8) -> Result<(u16, usize)> {
+    if data.len() < 4 + 33 {
+        return Err(anyhow!("SMB1 response too short: {} bytes", data.len()));
+    }
+    if data[4..8] != [0xFF, b'S', b'M', b'B'] {
+        return Err(anyhow!("Invalid SMB1 signature"));
+    }
+    if data[8] != command {
+        return Err(anyhow!("Unexpected SMB1 command 0x{:02x}", data[8]));
+    }
+    let status = u32::from_le_bytes([data[9], data[10], data[11], data[12]]);
+    if status != 0 {
+        return Err(anyhow!("SMB1 command 0x{:02x} failed with status 0x{:08x}", command, status));
+    }
+    Ok((u16::from_le_bytes([data[14], data[15]]), 4 + 32))
+}
+
+/// Null-terminated strings from an SMB1 data block, UTF-16LE when `unicode`
+fn smb1_strings(data: &[u8], unicode: bool) -> Vec<String> {
+    if unicode {
+        let units: Vec<u16> = data.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+        units.split(|unit| *unit == 0).map(String::from_utf16_lossy).collect()
+    } else {
+        data.split(|byte| *byte == 0).map(|bytes| String::from_utf8_lossy(bytes).into_owned()).collect()
+    }
+}
+
+/// Parse an NT LM 0.12 negotiate response: server details and the session key to echo
+fn parse_smb1_negotiate(data: &[u8]) -> Result<(SmbServerInfo, u32)> {
+    let (flags2, words) = smb1_response(data, 0x72)?;
+    if data[words] != 17 || data.len() < words + 37 {
+        return Err(anyhow!("SMB1 server does not speak NT LM 0.12"));
+    }
+    let security_mode = data[words + 3];
+    let session_key = u32::from_le_bytes(data[words + 16..words + 20].try_into().unwrap());
+    let capability_bits = u32::from_le_bytes(data[words + 20..words + 24].try_into().unwrap());
+    let system_time = filetime(u64::from_le_bytes(data[words + 24..words + 32].try_into().unwrap()));
+    let challenge_len = data[words + 34] as usize;
+    let byte_count = u16::from_le_bytes([data[words + 35], data[words + 36]]) as usize;
+    let bytes = &data[words + 37..data.len().min(words + 37 + byte_count)];
+
+    // Without extended security the challenge is followed by the domain name
+    let primary_domain = (capability_bits & 0x8000_0000 == 0)
+        .then(|| bytes.get(challenge_len..))
+        .flatten()
+        .and_then(|rest| smb1_strings(rest, flags2 & 0x8000 != 0).into_iter().next())
+        .filter(|domain| !domain.is_empty());
+
+    let server = SmbServerInfo {
+        signing_enabled: security_mode & 0x04 != 0,
+        signing_required: security_mode & 0x08 != 0,
+        capabilities: SMB1_CAPABILITIES
+            .iter()
+            .filter(|(bit, _)| capability_bits & bit != 0)
+            .map(|(_, name)| name.to_string())
+            .collect(),
+        server_guid: None,
+        system_time,
+        server_start_time: None,
+        uptime_secs: None,
+        native_os: None,
+        native_lan_manager: None,
+        primary_domain,
+    };
+    Ok((server, session_key))
+}
 
-    // This would require a full NTLMSSP negotiation
-    // For now, we'll use the simpler SMB dialect negotiation
-    // Future enhancement: implement full NTLMSSP to get exact build number
+/// (NativeOS, NativeLanMan, PrimaryDomain) from a Session Setup AndX response
+fn parse_smb1_session_setup(data: &[u8]) -> Result<(Option<String>, Option<String>, Option<String>)> {
+    let (flags2, words) = smb1_response(data, 0x73)?;
+    let word_count = data[words] as usize;
+    let byte_count_at = words + 1 + word_count * 2;
+    if data.len() < byte_count_at + 2 {
+        return Err(anyhow!("SMB1 session setup response truncated"));
+    }
+    let byte_count = u16::from_le_bytes([data[byte_count_at], data[byte_count_at + 1]]) as usize;
+    let mut start = byte_count_at + 2;
+    let end = data.len().min(start + byte_count);
+    let unicode = flags2 & 0x8000 != 0;
+    // Unicode strings are aligned to 2 bytes from the start of the SMB header
+    if unicode && (start - 4) % 2 == 1 {
+        start += 1;
+    }
 
-    probe_smb(ip, timeout_secs).await
+    let mut strings = smb1_strings(data.get(start..end).unwrap_or_default(), unicode)
+        .into_iter()
+        .map(|value| Some(value.trim().to_string()).filter(|value| !value.is_empty()));
+    let mut next = || strings.next().flatten();
+    Ok((next(), next(), next()))
+}
+
+/// Name an SMB1 server from its self-reported strings
+fn legacy_os_name(native_os: Option<&str>, lan_manager: Option<&str>) -> String {
+    match (native_os, lan_manager) {
+        (Some("Windows 5.0"), _) => "Windows 2000".to_string(),
+        (Some("Windows 5.1"), _) => "Windows XP".to_string(),
+        (Some(os), _) if os.starts_with("Windows") => os.to_string(),
+        (os, Some(lan_manager)) if lan_manager.contains("Samba") => {
+            format!("{} ({})", os.unwrap_or("Unix"), lan_manager)
+        }
+        (Some(os), _) => os.to_string(),
+        (None, _) => "Unknown (SMB1 server)".to_string(),
+    }
 }
 
 #[cfg(test)]
@@ -270,18 +718,196 @@ mod tests {
     fn test_build_to_version() {
         assert_eq!(build_to_windows_version(22621), "Windows 11 22H2");
         assert_eq!(build_to_windows_version(19045), "Windows 10 22H2");
-        assert_eq!(build_to_windows_version(19041), "Windows 10 2004/20H2/21H1");
+        assert_eq!(build_to_windows_version(19041), "Windows 10 2004");
+        assert_eq!(build_to_windows_version(19042), "Windows 10 20H2");
         assert_eq!(build_to_windows_version(7601), "Windows 7");
     }
 
     #[test]
     fn test_smb2_negotiate_packet() {
-        let packet = build_smb2_negotiate_packet();
+        let identity = ProbeIdentity::default();
+        let packet = build_smb2_negotiate_packet(&identity);
 
         // Should have NetBIOS header + SMB2 header + Negotiate + Dialects
         assert!(packet.len() > 100);
 
         // Check SMB2 signature
         assert_eq!(&packet[4..8], &[0xFE, b'S', b'M', b'B']);
+
+        // ClientGuid follows the 64-byte header and 12 bytes of negotiate fields
+        assert_eq!(&packet[80..96], &identity.smb_client_guid);
+    }
+
+    // NetBIOS + SMB2 header + 64-byte negotiate response, as a Windows 7 server sends it
+    fn negotiate_response(status: u32, security_mode: u16, dialect: u16, capabilities: u32) -> Vec<u8> {
+        let mut packet = vec![0x00, 0x00, 0x00, 0x80, 0xFE, b'S', b'M', b'B', 0x40, 0x00, 0x00, 0x00];
+        packet.extend_from_slice(&status.to_le_bytes());
+        packet.resize(68, 0);
+        packet.extend_from_slice(&65u16.to_le_bytes()); // StructureSize
+        packet.extend_from_slice(&security_mode.to_le_bytes());
+        packet.extend_from_slice(&dialect.to_le_bytes());
+        packet.extend_from_slice(&[0x00, 0x00]);
+        packet.extend_from_slice(&[0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]);
+        packet.extend_from_slice(&capabilities.to_le_bytes());
+        packet.extend_from_slice(&[0x00; 12]); // MaxTransactSize, MaxReadSize, MaxWriteSize
+        // 2024-01-01T00:00:00Z, and a server started a day and an hour earlier
+        let now: u64 = (1_704_067_200 + 11_644_473_600) * 10_000_000;
+        packet.extend_from_slice(&now.to_le_bytes());
+        packet.extend_from_slice(&(now - 90_000 * 10_000_000).to_le_bytes());
+        packet.extend_from_slice(&[0x00; 8]); // Security buffer and negotiate contexts
+        packet
+    }
+
+    #[test]
+    fn test_parse_negotiate_response() {
+        let result = parse_smb2_response(&negotiate_response(0, 0x03, 0x0210, 0x07)).unwrap();
+        assert_eq!(result.smb_dialect, "SMB 2.1");
+        assert_eq!(result.os_version, "Windows 7/Server 2008 R2");
+
+        let server = result.server.unwrap();
+        assert!(server.signing_enabled && server.signing_required);
+        assert_eq!(server.capabilities, ["dfs", "leasing", "large_mtu"]);
+        assert_eq!(server.server_guid.as_deref(), Some("00112233-4455-6677-8899-aabbccddeeff"));
+        assert_eq!(server.system_time.unwrap().to_rfc3339(), "2024-01-01T00:00:00+00:00");
+        assert_eq!(server.uptime_secs, Some(90_000));
+
+        // Windows 10+ leave the start time out; truncated responses still give the dialect
+        let mut modern = negotiate_response(0, 0x01, 0x0311, 0x2f);
+        modern[68 + 48..68 + 56].fill(0);
+        let server = parse_smb2_response(&modern).unwrap().server.unwrap();
+        assert!(!server.signing_required);
+        assert_eq!((server.server_start_time, server.uptime_secs), (None, None));
+        let truncated = parse_smb2_response(&modern[..80]).unwrap();
+        assert_eq!((truncated.smb_dialect.as_str(), truncated.server), ("SMB 3.1.1", None));
+
+        assert!(parse_smb2_response(&negotiate_response(0xC000_0022, 0x01, 0x0311, 0)).is_err());
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().chain([0]).flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn test_parse_smb1_responses() {
+        // NT LM 0.12 negotiate response from a Samba 3 NAS in workgroup WORKGROUP
+        let mut negotiate = build_smb1_negotiate_packet()[..36].to_vec();
+        negotiate[14..16].copy_from_slice(&0xC001u16.to_le_bytes());
+        negotiate.push(17);
+        negotiate.extend_from_slice(&0u16.to_le_bytes()); // DialectIndex
+        negotiate.push(0x03); // SecurityMode: user level, encrypted passwords
+        negotiate.extend_from_slice(&[0x32, 0x00, 0x01, 0x00]); // MaxMpx, MaxVcs
+        negotiate.extend_from_slice(&[0x00; 8]); // MaxBuffer, MaxRaw
+        negotiate.extend_from_slice(&0x1234u32.to_le_bytes()); // SessionKey
+        negotiate.extend_from_slice(&0x0000_105cu32.to_le_bytes()); // Capabilities
+        negotiate.extend_from_slice(&((1_704_067_200u64 + 11_644_473_600) * 10_000_000).to_le_bytes());
+        negotiate.extend_from_slice(&[0x00, 0x00, 0x08]); // TimeZone, ChallengeLength
+        let mut bytes = vec![0xAA; 8];
+        bytes.extend(utf16("WORKGROUP"));
+        negotiate.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+        negotiate.extend(bytes);
+
+        let (server, session_key) = parse_smb1_negotiate(&negotiate).unwrap();
+        assert_eq!(session_key, 0x1234);
+        assert!(!server.signing_enabled && !server.signing_required);
+        assert_eq!(server.capabilities, ["unicode", "large_files", "nt_smbs", "nt_status", "dfs"]);
+        assert_eq!(server.primary_domain.as_deref(), Some("WORKGROUP"));
+        assert_eq!(server.system_time.unwrap().to_rfc3339(), "2024-01-01T00:00:00+00:00");
+
+        // Session setup response: 3 words, then a pad byte and the strings
+        let mut session = build_smb1_session_setup_packet(0x1234)[..36].to_vec();
+        session[14..16].copy_from_slice(&0xC001u16.to_le_bytes());
+        session.push(3);
+        session.extend_from_slice(&[0xFF, 0x00, 0x00, 0x00, 0x01, 0x00]);
+        let mut bytes = vec![0x00];
+        for text in ["Unix", "Samba 3.0.37", "WORKGROUP"] {
+            bytes.extend(utf16(text));
+        }
+        session.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+        session.extend(bytes);
+
+        let (os, lan_manager, domain) = parse_smb1_session_setup(&session).unwrap();
+        assert_eq!((os.as_deref(), lan_manager.as_deref()), (Some("Unix"), Some("Samba 3.0.37")));
+        assert_eq!(domain.as_deref(), Some("WORKGROUP"));
+        assert_eq!(legacy_os_name(os.as_deref(), lan_manager.as_deref()), "Unix (Samba 3.0.37)");
+        assert_eq!(legacy_os_name(Some("Windows 5.1"), Some("Windows 2000 LAN Manager")), "Windows XP");
+
+        // A refused session carries an error status and no strings
+        session[9..13].copy_from_slice(&0xC000_006Du32.to_le_bytes());
+        assert!(parse_smb1_session_setup(&session).is_err());
+    }
+
+    #[test]
+    fn test_stealth_negotiate_fields() {
+        let identity = ProbeIdentity { stealth: true, ..Default::default() };
+        let packet = build_smb2_negotiate_packet(&identity);
+
+        assert_eq!(&packet[72..74], &[0x01, 0x00]); // SecurityMode
+        assert_eq!(&packet[76..80], &[0x7F, 0x00, 0x00, 0x00]); // Capabilities
+        assert_ne!(&packet[80..96], &[0u8; 16]);
+    }
+
+    #[test]
+    fn test_ntlmssp_session_setup() {
+        let identity = ProbeIdentity { workstation: Some("DESKTOP-4F2K".to_string()), ..Default::default() };
+        let packet = build_smb2_session_setup_packet(&identity);
+        assert_eq!(u32::from_be_bytes(packet[0..4].try_into().unwrap()) as usize, packet.len() - 4);
+        assert_eq!(&packet[16..18], &[0x01, 0x00]); // Command: Session Setup
+        assert_eq!(&packet[28..36], &1u64.to_le_bytes()); // MessageId
+
+        // The security buffer the header points at is a SPNEGO token wrapping the negotiate
+        let offset = u16::from_le_bytes([packet[80], packet[81]]) as usize;
+        let len = u16::from_le_bytes([packet[82], packet[83]]) as usize;
+        let buffer = &packet[4 + offset..];
+        assert_eq!(buffer.len(), len);
+        assert_eq!(buffer[0], 0x60);
+        assert_eq!(buffer[1] as usize, len - 2);
+        let token = &buffer[len - 52..];
+        assert_eq!(&token[..8], b"NTLMSSP\0");
+        let flags = u32::from_le_bytes(token[12..16].try_into().unwrap());
+        assert_ne!(flags & NTLMSSP_NEGOTIATE_OEM_WORKSTATION_SUPPLIED, 0);
+        assert_eq!(&token[40..], b"DESKTOP-4F2K");
+
+        // Without a name the workstation field stays empty
+        let token = build_ntlmssp_negotiate(None);
+        assert_eq!(token.len(), 40);
+        assert_eq!(u32::from_le_bytes(token[12..16].try_into().unwrap()), NTLMSSP_NEGOTIATE_FLAGS);
+    }
+
+    #[test]
+    fn test_der_long_lengths() {
+        assert_eq!(der(0x04, &[0xAA; 3]), [0x04, 0x03, 0xAA, 0xAA, 0xAA]);
+        assert_eq!(&der(0x04, &[0; 200])[..3], &[0x04, 0x81, 200]);
+        assert_eq!(&der(0x04, &[0; 300])[..4], &[0x04, 0x82, 0x01, 0x2C]);
+    }
+
+    #[test]
+    fn test_parse_ntlmssp_challenge() {
+        let mut response = vec![0x00, 0x00, 0x00, 0x00, 0xFE, b'S', b'M', b'B'];
+        response.extend_from_slice(&[0x40, 0x00, 0x00, 0x00]);
+        response.extend_from_slice(&STATUS_MORE_PROCESSING_REQUIRED.to_le_bytes());
+        response.resize(68, 0);
+        response.extend_from_slice(&[0x09, 0x00, 0x00, 0x00, 0x48, 0x00, 0x00, 0x00]);
+        // SPNEGO framing before the challenge is skipped over
+        response.extend_from_slice(&[0xA1, 0x81, 0x90, 0x30]);
+        let mut challenge = b"NTLMSSP\0".to_vec();
+        challenge.extend_from_slice(&2u32.to_le_bytes());
+        challenge.resize(20, 0);
+        challenge.extend_from_slice(&(NTLMSSP_NEGOTIATE_FLAGS | 0x0080_0000).to_le_bytes());
+        challenge.resize(48, 0);
+        challenge.extend_from_slice(&[10, 0]);
+        challenge.extend_from_slice(&22631u16.to_le_bytes());
+        challenge.extend_from_slice(&[0x00, 0x00, 0x00, 0x0F]);
+        response.extend_from_slice(&challenge);
+        assert_eq!(parse_ntlmssp_challenge(&response).unwrap(), Some(22631));
+        assert_eq!(build_to_windows_version(22631), "Windows 11 23H2");
+
+        // Samba reports a version without a build
+        let build_at = response.len() - 6;
+        response[build_at..build_at + 2].copy_from_slice(&[0x00, 0x00]);
+        assert_eq!(parse_ntlmssp_challenge(&response).unwrap(), None);
+
+        // A server refusing the session setup
+        response[12..16].copy_from_slice(&0xC000_0022u32.to_le_bytes());
+        assert!(parse_ntlmssp_challenge(&response).is_err());
     }
 }
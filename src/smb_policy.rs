@@ -0,0 +1,226 @@
+//! Which devices get an SMB probe.
+//!
+//! The default only probes clients whose vendor class contains "MSFT", which
+//! misses Samba servers and Windows stacks that don't send one. Operators can
+//! instead probe everything, nothing, clients whose fingerprint names a matching
+//! OS, or restrict any of these to particular subnets.
+
+use crate::hybrid_detection::{DeviceContext, Evidence};
+use regex::Regex;
+use std::net::Ipv4Addr;
+
+/// What triggers a probe
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProbeMode {
+    Always,
+    Never,
+    /// Vendor class (option 60) matches `vendor_pattern`
+    Vendor,
+    /// OS named by earlier evidence matches `fingerprint_pattern`
+    Fingerprint,
+}
+
+impl ProbeMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "always" => Some(Self::Always),
+            "never" => Some(Self::Never),
+            "vendor" => Some(Self::Vendor),
+            "fingerprint" => Some(Self::Fingerprint),
+            _ => None,
+        }
+    }
+}
+
+/// An IPv4 network in CIDR notation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Subnet {
+    network: u32,
+    mask: u32,
+}
+
+impl Subnet {
+    /// Parse "10.0.0.0/8"; a bare address is a /32
+    pub fn parse(cidr: &str) -> Option<Self> {
+        let (addr, prefix) = match cidr.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, prefix.parse::<u32>().ok().filter(|p| *p <= 32)?),
+            None => (cidr.trim(), 32),
+        };
+        let addr: Ipv4Addr = addr.parse().ok()?;
+        let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+        Some(Self { network: u32::from(addr) & mask, mask })
+    }
+
+    pub fn contains(&self, addr: Ipv4Addr) -> bool {
+        u32::from(addr) & self.mask == self.network
+    }
+
+    /// The /`prefix_len` network containing `addr`
+    pub fn around(addr: Ipv4Addr, prefix_len: u32) -> Self {
+        let mask = u32::MAX.checked_shl(32 - prefix_len.min(32)).unwrap_or(0);
+        Self { network: u32::from(addr) & mask, mask }
+    }
+
+    pub fn prefix_len(&self) -> u32 {
+        self.mask.count_ones()
+    }
+
+    /// Usable host addresses: all but the network and broadcast address, except
+    /// on /31 and /32 where every address is a host
+    pub fn hosts(&self) -> impl Iterator<Item = Ipv4Addr> {
+        let last = self.network | !self.mask;
+        let range = if self.prefix_len() >= 31 { self.network..=last } else { self.network + 1..=last - 1 };
+        range.map(Ipv4Addr::from)
+    }
+}
+
+impl std::fmt::Display for Subnet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", Ipv4Addr::from(self.network), self.prefix_len())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SmbProbePolicy {
+    pub mode: ProbeMode,
+    pub vendor_pattern: Regex,
+    pub fingerprint_pattern: Regex,
+    /// When non-empty, only addresses in these subnets are probed
+    pub allow_subnets: Vec<Subnet>,
+    /// Never probed, even if also allowed
+    pub deny_subnets: Vec<Subnet>,
+}
+
+impl Default for SmbProbePolicy {
+    fn default() -> Self {
+        Self {
+            mode: ProbeMode::Vendor,
+            vendor_pattern: Regex::new("MSFT").unwrap(),
+            fingerprint_pattern: Regex::new("(?i)windows").unwrap(),
+            allow_subnets: Vec::new(),
+            deny_subnets: Vec::new(),
+        }
+    }
+}
+
+impl SmbProbePolicy {
+    /// Whether to probe the device; Err carries the reason to skip it
+    pub fn check(&self, device: &DeviceContext<'_>, prior: &[Evidence]) -> Result<(), String> {
+        if self.mode == ProbeMode::Never {
+            return Err("SMB probing policy is never".to_string());
+        }
+        if !device.has_address() {
+            return Err("IP is 0.0.0.0".to_string());
+        }
+
+        let addr: Ipv4Addr = device
+            .ip_address
+            .parse()
+            .map_err(|_| format!("{} is not an IPv4 address", device.ip_address))?;
+        if self.deny_subnets.iter().any(|subnet| subnet.contains(addr)) {
+            return Err("address is in a denied subnet".to_string());
+        }
+        if !self.allow_subnets.is_empty() && !self.allow_subnets.iter().any(|subnet| subnet.contains(addr)) {
+            return Err("address is outside the allowed subnets".to_string());
+        }
+
+        match self.mode {
+            ProbeMode::Always | ProbeMode::Never => Ok(()),
+            ProbeMode::Vendor => match device.vendor_class {
+                None => Err("no vendor class".to_string()),
+                Some(vendor_class) if self.vendor_pattern.is_match(vendor_class) => Ok(()),
+                Some(_) => Err(format!("vendor class doesn't match {}", self.vendor_pattern)),
+            },
+            ProbeMode::Fingerprint => {
+                let matched = prior
+                    .iter()
+                    .filter_map(|evidence| evidence.os_name.as_deref())
+                    .any(|os_name| self.fingerprint_pattern.is_match(os_name));
+                if matched {
+                    Ok(())
+                } else {
+                    Err(format!("no detected OS matches {}", self.fingerprint_pattern))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device<'a>(ip_address: &'a str, vendor_class: Option<&'a str>) -> DeviceContext<'a> {
+        DeviceContext {
+            mac_address: "aa:bb:cc:dd:ee:ff",
+            ip_address,
+            fingerprint: "",
+            vendor_class,
+            hostname: None,
+            vendor_specific: None,
+            client_arch: None,
+            vendor_options: &[],
+        }
+    }
+
+    #[test]
+    fn test_subnet() {
+        let subnet = Subnet::parse("192.168.1.77/24").unwrap();
+        assert!(subnet.contains(Ipv4Addr::new(192, 168, 1, 200)));
+        assert!(!subnet.contains(Ipv4Addr::new(192, 168, 2, 1)));
+        assert!(Subnet::parse("0.0.0.0/0").unwrap().contains(Ipv4Addr::new(8, 8, 8, 8)));
+        assert!(Subnet::parse("10.0.0.5").unwrap().contains(Ipv4Addr::new(10, 0, 0, 5)));
+        assert_eq!(Subnet::parse("10.0.0.0/33"), None);
+        assert_eq!(Subnet::parse("nonsense/8"), None);
+        assert_eq!(subnet.to_string(), "192.168.1.0/24");
+        assert_eq!(Subnet::around(Ipv4Addr::new(10, 1, 20, 1), 22).to_string(), "10.1.20.0/22");
+
+        let hosts: Vec<_> = Subnet::parse("10.0.0.0/30").unwrap().hosts().collect();
+        assert_eq!(hosts, [Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2)]);
+        assert_eq!(Subnet::parse("10.0.0.0/31").unwrap().hosts().count(), 2);
+        assert_eq!(Subnet::parse("10.0.0.9").unwrap().hosts().collect::<Vec<_>>(), [Ipv4Addr::new(10, 0, 0, 9)]);
+    }
+
+    #[test]
+    fn test_policy_modes() {
+        let msft = device("10.0.0.5", Some("MSFT 5.0"));
+        let samba = device("10.0.0.6", Some("udhcp 1.36.1"));
+
+        let default = SmbProbePolicy::default();
+        assert!(default.check(&msft, &[]).is_ok());
+        assert!(default.check(&samba, &[]).is_err());
+        assert!(default.check(&device("0.0.0.0", Some("MSFT 5.0")), &[]).is_err());
+
+        let always = SmbProbePolicy { mode: ProbeMode::Always, ..Default::default() };
+        assert!(always.check(&samba, &[]).is_ok());
+
+        let never = SmbProbePolicy { mode: ProbeMode::Never, ..Default::default() };
+        assert!(never.check(&msft, &[]).is_err());
+
+        let fingerprint = SmbProbePolicy {
+            mode: ProbeMode::Fingerprint,
+            fingerprint_pattern: Regex::new("(?i)windows|linux").unwrap(),
+            ..Default::default()
+        };
+        let linux = Evidence {
+            source: "dhcp_fingerprint",
+            os_name: Some("Linux".to_string()),
+            ..Default::default()
+        };
+        assert!(fingerprint.check(&samba, &[linux]).is_ok());
+        assert!(fingerprint.check(&samba, &[]).is_err());
+    }
+
+    #[test]
+    fn test_policy_subnets() {
+        let policy = SmbProbePolicy {
+            mode: ProbeMode::Always,
+            allow_subnets: vec![Subnet::parse("10.0.0.0/8").unwrap()],
+            deny_subnets: vec![Subnet::parse("10.0.99.0/24").unwrap()],
+            ..Default::default()
+        };
+        assert!(policy.check(&device("10.0.1.5", None), &[]).is_ok());
+        assert!(policy.check(&device("10.0.99.5", None), &[]).is_err());
+        assert!(policy.check(&device("192.168.1.5", None), &[]).is_err());
+    }
+}
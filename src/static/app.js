@@ -2,6 +2,7 @@
 let ws = null;
 let isPaused = false;
 let requests = [];
+let uiVersion = null;
 const MAX_DISPLAY_REQUESTS = 500;
 
 // DOM elements
@@ -17,32 +18,70 @@ const reqPerMin = document.getElementById('req-per-min');
 const uptimeEl = document.getElementById('uptime');
 const messageTypes = document.getElementById('message-types');
 
+// Alert elements
+const btnAlerts = document.getElementById('btn-alerts');
+const alertCount = document.getElementById('alert-count');
+const alertsPanel = document.getElementById('alerts-panel');
+const alertsList = document.getElementById('alerts-list');
+const btnAckAll = document.getElementById('btn-ack-all');
+
 // Filter elements
 const filterMac = document.getElementById('filter-mac');
 const filterVendor = document.getElementById('filter-vendor');
 const filterType = document.getElementById('filter-type');
+const filterHostname = document.getElementById('filter-hostname');
+const filterOs = document.getElementById('filter-os');
+const filterFingerprint = document.getElementById('filter-fingerprint');
+const filterSite = document.getElementById('filter-site');
+const filterSince = document.getElementById('filter-since');
+const filterUntil = document.getElementById('filter-until');
 const btnClearFilters = document.getElementById('btn-clear-filters');
 const btnPause = document.getElementById('btn-pause');
 
 // Initialize WebSocket connection
 function connectWebSocket() {
-    const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
-    const wsUrl = `${protocol}//${window.location.host}/ws`;
+    // Relative to <base href> so the UI also works under a reverse-proxy sub-path
+    const wsUrl = new URL('ws', document.baseURI);
+    wsUrl.protocol = wsUrl.protocol === 'https:' ? 'wss:' : 'ws:';
 
     ws = new WebSocket(wsUrl);
 
     ws.onopen = () => {
         console.log('WebSocket connected');
         updateStatus('connected');
+        checkVersion();
         loadStatistics();
+        loadAlerts();
     };
 
     ws.onmessage = (event) => {
         if (isPaused) return;
 
         try {
-            const request = JSON.parse(event.data);
-            addRequest(request);
+            const message = JSON.parse(event.data);
+            if (message.type === 'alert') {
+                handleAlert(message.alert);
+                return;
+            }
+            if (message.type === 'new_device') {
+                handleNewDevice(message.device);
+                return;
+            }
+            if (message.type === 'presence_changed') {
+                // Presence is shown from /api/devices; nothing in the live feed to update
+                console.debug(`${message.presence.mac_address} is ${message.presence.state}`);
+                return;
+            }
+            if (message.type === 'request_updated') {
+                updateRequest(message.request);
+                return;
+            }
+            if (message.type === 'dropped') {
+                console.warn(`Server dropped ${message.count} updates (${message.total} this session)`);
+                statusText.textContent = `Connected (${message.total} updates dropped)`;
+                return;
+            }
+            addRequest(message);
         } catch (error) {
             console.error('Error parsing message:', error);
         }
@@ -62,6 +101,91 @@ function connectWebSocket() {
     };
 }
 
+// Handle an alert pushed by the server
+function handleAlert(alert) {
+    console.warn(`[${alert.severity}] ${alert.alert_type}: ${alert.message}`);
+    loadAlerts();
+}
+
+// Handle a device joining the network (its alert refreshes the badge)
+function handleNewDevice(device) {
+    console.info(`New device: ${device.mac_address}${device.hostname ? ` (${device.hostname})` : ''}`);
+}
+
+// Load unacknowledged alerts for the header badge
+// Acknowledging alerts is an admin request: with [web] admin_token set it carries the
+// token, which is asked for once and kept for this browser session
+async function adminFetch(url, options = {}) {
+    const send = () => {
+        const headers = { ...(options.headers || {}) };
+        const token = sessionStorage.getItem('adminToken');
+        if (token) headers.Authorization = `Bearer ${token}`;
+        return fetch(url, { ...options, headers });
+    };
+
+    let response = await send();
+    if (response.status === 401) {
+        const token = prompt('Admin token');
+        if (token) {
+            sessionStorage.setItem('adminToken', token.trim());
+            response = await send();
+        }
+    }
+    return response;
+}
+
+async function loadAlerts() {
+    try {
+        const response = await fetch('api/alerts?acknowledged=false&limit=50');
+        const data = await response.json();
+        renderAlerts(data);
+    } catch (error) {
+        console.error('Error loading alerts:', error);
+    }
+}
+
+function renderAlerts(data) {
+    alertCount.textContent = data.unacknowledged;
+    btnAlerts.classList.toggle('active', data.unacknowledged > 0);
+
+    alertsList.innerHTML = '';
+    if (data.alerts.length === 0) {
+        const empty = document.createElement('li');
+        empty.textContent = 'No unacknowledged alerts';
+        alertsList.appendChild(empty);
+        return;
+    }
+
+    for (const alert of data.alerts) {
+        const item = document.createElement('li');
+        item.className = `alert-${alert.severity}`;
+
+        const text = document.createElement('div');
+        const message = document.createElement('div');
+        message.textContent = alert.message;
+        const meta = document.createElement('div');
+        meta.className = 'alert-meta';
+        meta.textContent = `${alert.alert_type} · ${new Date(alert.last_seen).toLocaleString()} · ×${alert.occurrences}`;
+        text.append(message, meta);
+
+        const ack = document.createElement('button');
+        ack.textContent = 'Ack';
+        ack.addEventListener('click', () => acknowledgeAlert(alert.id));
+
+        item.append(text, ack);
+        alertsList.appendChild(item);
+    }
+}
+
+async function acknowledgeAlert(id) {
+    try {
+        await adminFetch(`api/alerts/${id}/ack`, { method: 'POST' });
+    } catch (error) {
+        console.error('Error acknowledging alert:', error);
+    }
+    loadAlerts();
+}
+
 // Update connection status
 function updateStatus(status) {
     statusIndicator.className = `indicator ${status}`;
@@ -93,18 +217,42 @@ function addRequest(request) {
     renderRequests();
 }
 
+// Replace a request shown earlier once probes have refined its detection
+function updateRequest(updated) {
+    const index = requests.findIndex(req => updated.id != null
+        ? req.id === updated.id
+        : req.mac_address === updated.mac_address && req.timestamp === updated.timestamp && req.xid === updated.xid);
+    if (index === -1) return;
+    requests[index] = updated;
+    renderRequests();
+}
+
 // Render requests based on filters
 function renderRequests() {
-    const macFilter = filterMac.value.toLowerCase();
-    const vendorFilter = filterVendor.value.toLowerCase();
+    // Same semantics as /api/search: case-insensitive substrings, ':' or '-' in MACs
+    const macFilter = filterMac.value.trim().toLowerCase().replaceAll('-', ':');
+    const vendorFilter = filterVendor.value.trim().toLowerCase();
     const typeFilter = filterType.value;
+    const hostnameFilter = filterHostname.value.trim().toLowerCase();
+    const osFilter = filterOs.value.trim().toLowerCase();
+    const fingerprintFilter = filterFingerprint.value.trim();
+    // Exact site name; "local" is what this instance captured itself
+    const siteFilter = filterSite.value.trim().toLowerCase();
+    const since = filterSince.value ? new Date(filterSince.value) : null;
+    const until = filterUntil.value ? new Date(filterUntil.value) : null;
+    const contains = (value, filter) => !filter || (value && value.toLowerCase().includes(filter));
 
     const filtered = requests.filter(req => {
-        const macMatch = !macFilter || req.mac_address.toLowerCase().includes(macFilter);
-        const vendorMatch = !vendorFilter || (req.vendor_class && req.vendor_class.toLowerCase().includes(vendorFilter));
-        const typeMatch = !typeFilter || req.message_type === typeFilter;
-
-        return macMatch && vendorMatch && typeMatch;
+        const timestamp = new Date(req.timestamp);
+        return contains(req.mac_address, macFilter)
+            && contains(req.vendor_class, vendorFilter)
+            && (!typeFilter || req.message_type === typeFilter)
+            && contains(req.hostname, hostnameFilter)
+            && contains(req.os_name, osFilter)
+            && contains(req.fingerprint, fingerprintFilter)
+            && (!siteFilter || (req.site_id ? req.site_id.toLowerCase() : 'local') === siteFilter)
+            && (!since || timestamp >= since)
+            && (!until || timestamp <= until);
     });
 
     requestsBody.innerHTML = '';
@@ -113,7 +261,8 @@ function renderRequests() {
         const row = document.createElement('tr');
         row.innerHTML = `
             <td class="timestamp">${formatTimestamp(req.timestamp)}</td>
-            <td class="mac">${req.mac_address}</td>
+            <td class="mac">${req.mac_address}${req.site_id ? ` <span class="site" title="Forwarded by the agent at this site">${escapeHtml(req.site_id)}</span>` : ''}</td>
+            <td class="hostname">${formatHostname(req)}</td>
             <td>${req.source_ip}:${req.source_port}</td>
             <td><span class="badge badge-${req.message_type.toLowerCase()}">${req.message_type}</span></td>
             <td class="os-info">${req.os_name ? req.os_name + (req.device_class ? ' <span class="device-class">(' + req.device_class + ')</span>' : '') : '-'}</td>
@@ -128,6 +277,28 @@ function renderRequests() {
 }
 
 // Format timestamp
+// Hostnames are chosen by the client, so never insert them as markup
+// Option 12 hostname, with the option 81 FQDN and who registers the client in DNS
+const DDNS_TITLES = {
+    server: 'Client asks the DHCP server to update its A and PTR records',
+    client: 'Client updates its own A record; the server updates the PTR',
+    none: 'Client asks for no server DNS updates',
+};
+
+function formatHostname(req) {
+    const fqdn = req.client_fqdn;
+    const hostname = req.hostname ? escapeHtml(req.hostname) : '';
+    if (!fqdn) return hostname || '-';
+    const name = fqdn.name ? escapeHtml(fqdn.name) + (fqdn.partial ? '' : '.') : '(no name)';
+    return `${hostname}<div class="fqdn">${name} <span class="ddns ddns-${fqdn.ddns}" title="${DDNS_TITLES[fqdn.ddns]}">DDNS: ${fqdn.ddns}</span></div>`;
+}
+
+function escapeHtml(text) {
+    const div = document.createElement('div');
+    div.textContent = text;
+    return div.innerHTML;
+}
+
 function formatTimestamp(timestamp) {
     const date = new Date(timestamp);
     return date.toLocaleTimeString() + '.' + date.getMilliseconds().toString().padStart(3, '0');
@@ -138,10 +309,27 @@ function truncate(str, length) {
     return str.length > length ? str.substring(0, length) + '...' : str;
 }
 
+// Reload the page if the server was upgraded to a different UI bundle
+async function checkVersion() {
+    try {
+        const response = await fetch('api/version', { cache: 'no-store' });
+        const version = await response.json();
+
+        if (uiVersion === null) {
+            uiVersion = version.ui_version;
+        } else if (version.ui_version !== uiVersion) {
+            console.log('UI version changed, reloading');
+            window.location.reload();
+        }
+    } catch (error) {
+        console.error('Error checking version:', error);
+    }
+}
+
 // Load statistics from API
 async function loadStatistics() {
     try {
-        const response = await fetch('/api/stats');
+        const response = await fetch('api/stats');
         const stats = await response.json();
         updateStatistics(stats);
     } catch (error) {
@@ -154,6 +342,7 @@ function updateStatistics(stats) {
     totalRequests.textContent = stats.total_requests.toLocaleString();
     uniqueMacs.textContent = stats.unique_macs.toLocaleString();
     reqPerMin.textContent = stats.requests_per_minute.toFixed(2);
+    reqPerMin.title = `5 min: ${stats.requests_per_minute_5m.toFixed(2)}/min, 15 min: ${stats.requests_per_minute_15m.toFixed(2)}/min`;
     uptimeEl.textContent = formatUptime(stats.uptime_seconds);
 
     // Update message type distribution
@@ -189,11 +378,23 @@ function formatUptime(seconds) {
 filterMac.addEventListener('input', renderRequests);
 filterVendor.addEventListener('input', renderRequests);
 filterType.addEventListener('change', renderRequests);
+filterHostname.addEventListener('input', renderRequests);
+filterOs.addEventListener('input', renderRequests);
+filterFingerprint.addEventListener('input', renderRequests);
+filterSite.addEventListener('input', renderRequests);
+filterSince.addEventListener('change', renderRequests);
+filterUntil.addEventListener('change', renderRequests);
 
 btnClearFilters.addEventListener('click', () => {
     filterMac.value = '';
     filterVendor.value = '';
     filterType.value = '';
+    filterHostname.value = '';
+    filterOs.value = '';
+    filterFingerprint.value = '';
+    filterSite.value = '';
+    filterSince.value = '';
+    filterUntil.value = '';
     renderRequests();
 });
 
@@ -203,6 +404,19 @@ btnPause.addEventListener('click', () => {
     btnPause.classList.toggle('paused');
 });
 
+btnAlerts.addEventListener('click', () => {
+    alertsPanel.hidden = !alertsPanel.hidden;
+});
+
+btnAckAll.addEventListener('click', async () => {
+    try {
+        await adminFetch('api/alerts/ack', { method: 'POST' });
+    } catch (error) {
+        console.error('Error acknowledging alerts:', error);
+    }
+    loadAlerts();
+});
+
 // Refresh statistics every 5 seconds
 setInterval(loadStatistics, 5000);
 
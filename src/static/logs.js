@@ -6,6 +6,13 @@ let currentFilters = {
     vendor_class: null,
     message_type: null,
     xid: null,
+    hostname: null,
+    requested_ip: null,
+    server_id: null,
+    site_id: null,
+    enterprise: null,
+    subnet: null,
+    malformed: null,
 };
 let currentSort = {
     sort_by: 'timestamp',
@@ -29,6 +36,13 @@ const filterMac = document.getElementById('filter-mac');
 const filterVendor = document.getElementById('filter-vendor');
 const filterType = document.getElementById('filter-type');
 const filterXid = document.getElementById('filter-xid');
+const filterHostname = document.getElementById('filter-hostname');
+const filterRequestedIp = document.getElementById('filter-requested-ip');
+const filterServerId = document.getElementById('filter-server-id');
+const filterSite = document.getElementById('filter-site');
+const filterEnterprise = document.getElementById('filter-enterprise');
+const filterSubnet = document.getElementById('filter-subnet');
+const filterMalformed = document.getElementById('filter-malformed');
 const pageSizeSelect = document.getElementById('page-size');
 
 // Buttons
@@ -36,6 +50,15 @@ const btnApplyFilters = document.getElementById('btn-apply-filters');
 const btnClearFilters = document.getElementById('btn-clear-filters');
 const btnExportCsv = document.getElementById('btn-export-csv');
 const btnExportJson = document.getElementById('btn-export-json');
+const btnExportXlsx = document.getElementById('btn-export-xlsx');
+const btnExportPcap = document.getElementById('btn-export-pcap');
+const btnExportNdjson = document.getElementById('btn-export-ndjson');
+
+// Packet detail drawer
+const packetDrawer = document.getElementById('packet-drawer');
+const packetTitle = document.getElementById('packet-title');
+const packetBody = document.getElementById('packet-body');
+const btnCloseDrawer = document.getElementById('btn-close-drawer');
 
 // Load logs from API
 async function loadLogs() {
@@ -46,6 +69,7 @@ async function loadLogs() {
         page_size: pageSize,
         sort_by: currentSort.sort_by,
         sort_order: currentSort.sort_order,
+        envelope: true,
     });
 
     // Add filters
@@ -55,13 +79,23 @@ async function loadLogs() {
     if (currentFilters.vendor_class) params.append('vendor_class', currentFilters.vendor_class);
     if (currentFilters.message_type) params.append('message_type', currentFilters.message_type);
     if (currentFilters.xid) params.append('xid', currentFilters.xid);
+    if (currentFilters.hostname) params.append('hostname', currentFilters.hostname);
+    if (currentFilters.requested_ip) params.append('requested_ip', currentFilters.requested_ip);
+    if (currentFilters.server_id) params.append('server_id', currentFilters.server_id);
+    if (currentFilters.site_id) params.append('site_id', currentFilters.site_id);
+    if (currentFilters.enterprise) params.append('enterprise', currentFilters.enterprise);
+    if (currentFilters.subnet) params.append('subnet', currentFilters.subnet);
+    if (currentFilters.malformed) params.append('malformed', currentFilters.malformed);
 
     try {
-        const response = await fetch(`/api/logs?${params}`);
-        const logs = await response.json();
+        const response = await fetch(`api/logs?${params}`);
+        if (!response.ok) throw new Error((await response.json()).error);
+        const data = await response.json();
 
-        renderLogs(logs);
-        await loadCount();
+        renderLogs(data.items);
+        totalRecords = data.total;
+        totalCount.textContent = totalRecords.toLocaleString();
+        renderPagination();
     } catch (error) {
         console.error('Error loading logs:', error);
         hideLoading();
@@ -69,29 +103,6 @@ async function loadLogs() {
     }
 }
 
-// Load total count
-async function loadCount() {
-    const params = new URLSearchParams();
-
-    // Add filters
-    if (currentFilters.start_date) params.append('start_date', currentFilters.start_date);
-    if (currentFilters.end_date) params.append('end_date', currentFilters.end_date);
-    if (currentFilters.mac_address) params.append('mac_address', currentFilters.mac_address);
-    if (currentFilters.vendor_class) params.append('vendor_class', currentFilters.vendor_class);
-    if (currentFilters.message_type) params.append('message_type', currentFilters.message_type);
-    if (currentFilters.xid) params.append('xid', currentFilters.xid);
-
-    try {
-        const response = await fetch(`/api/logs/count?${params}`);
-        const data = await response.json();
-        totalRecords = data.count;
-        totalCount.textContent = totalRecords.toLocaleString();
-        renderPagination();
-    } catch (error) {
-        console.error('Error loading count:', error);
-    }
-}
-
 // Render logs in table
 function renderLogs(logs) {
     hideLoading();
@@ -108,18 +119,126 @@ function renderLogs(logs) {
         const row = document.createElement('tr');
         row.innerHTML = `
             <td class="timestamp">${formatTimestamp(log.timestamp)}</td>
-            <td class="mac">${log.mac_address}</td>
+            <td class="mac">${log.mac_address}${log.site_id ? ` <span class="site" title="Forwarded by the agent at this site">${escapeHtml(log.site_id)}</span>` : ''}${log.meta && log.meta.name ? `<div class="device-name" title="${escapeHtml([log.meta.location, log.meta.owner, ...log.meta.tags].filter(Boolean).join(' · '))}">${escapeHtml(log.meta.name)}</div>` : ''}</td>
+            <td class="hostname">${formatHostname(log)}</td>
             <td>${log.source_ip}:${log.source_port}</td>
-            <td><span class="badge badge-${log.message_type.toLowerCase()}">${log.message_type}</span></td>
+            <td><span class="badge badge-${log.message_type.toLowerCase()}">${log.message_type}</span>${log.repeats ? ` <span class="repeats" title="Last repeat ${formatTimestamp(log.last_repeat_at)}">×${log.repeats + 1}</span>` : ''}${log.lint_issues && log.lint_issues.length ? ` <span class="lint-warning" title="${escapeHtml(log.lint_issues.join(', '))}">⚠</span>` : ''}</td>
             <td class="os-info">${log.os_name ? log.os_name + (log.device_class ? ' <span class="device-class">(' + log.device_class + ')</span>' : '') : '-'}</td>
             <td class="vendor">${log.vendor_class || '-'}</td>
             <td class="xid">${log.xid}</td>
             <td class="fingerprint">${log.fingerprint}</td>
         `;
+        if (log.id !== undefined) {
+            row.classList.add('expandable');
+            row.addEventListener('click', () => toggleDecodedOptions(row, log.id));
+        }
         logsBody.appendChild(row);
     });
 }
 
+// Show a request's options with names and decoded values below its row
+async function toggleDecodedOptions(row, id) {
+    const next = row.nextElementSibling;
+    if (next && next.classList.contains('decoded-row')) {
+        next.remove();
+        return;
+    }
+
+    const detail = document.createElement('tr');
+    detail.className = 'decoded-row';
+    detail.innerHTML = `<td colspan="${row.children.length}">Loading options...</td>`;
+    row.after(detail);
+
+    try {
+        const response = await fetch(`api/requests/${id}/decoded`);
+        if (!response.ok) throw new Error(`HTTP ${response.status}`);
+        const data = await response.json();
+        const rows = data.options.map(opt => `
+            <tr>
+                <td class="option-code">${opt.code}</td>
+                <td>${opt.name || 'Unknown'}</td>
+                <td class="option-value">${escapeHtml(opt.display)}</td>
+            </tr>
+        `).join('');
+        detail.firstElementChild.innerHTML = `
+            <table class="decoded-options">
+                <thead><tr><th>Option</th><th>Name</th><th>Value</th></tr></thead>
+                <tbody>${rows}</tbody>
+            </table>
+            <button class="btn-secondary btn-packet">Packet bytes</button>
+        `;
+        detail.querySelector('.btn-packet').addEventListener('click', () => openPacketDrawer(id));
+    } catch (error) {
+        console.error('Error loading decoded options:', error);
+        detail.firstElementChild.textContent = 'Failed to load options';
+    }
+}
+
+// Show a request's raw packet in the drawer: the hex dump beside its fields,
+// highlighting the bytes of the field under the pointer
+async function openPacketDrawer(id) {
+    packetTitle.textContent = `Packet #${id}`;
+    packetBody.textContent = 'Loading packet...';
+    packetDrawer.hidden = false;
+
+    try {
+        const response = await fetch(`api/requests/${id}/hexdump`);
+        if (response.status === 404) {
+            packetBody.textContent = 'The packet bytes of this request were not stored';
+            return;
+        }
+        if (!response.ok) throw new Error(`HTTP ${response.status}`);
+        const data = await response.json();
+
+        let hex = '';
+        for (let offset = 0; offset < data.length; offset += 16) {
+            const bytes = [];
+            for (let i = offset; i < Math.min(offset + 16, data.length); i++) {
+                bytes.push(`<span class="hex-byte" data-offset="${i}">${data.hex.substr(i * 2, 2)}</span>`);
+            }
+            hex += `<div><span class="hex-offset">${offset.toString(16).padStart(4, '0')}</span> ${bytes.join(' ')}</div>`;
+        }
+        const fields = data.fields.map((field, index) => `
+            <tr data-field="${index}">
+                <td class="option-code">${field.offset}</td>
+                <td class="option-code">${field.length}</td>
+                <td>${escapeHtml(field.name)}</td>
+                <td class="option-value">${escapeHtml(field.value)}</td>
+            </tr>
+        `).join('');
+
+        packetTitle.textContent = `Packet #${id} · ${data.message_type} from ${data.mac_address} · ${data.length} bytes`;
+        packetBody.innerHTML = `
+            <div class="hex-dump">${hex}</div>
+            <table class="decoded-options packet-fields">
+                <thead><tr><th>Offset</th><th>Length</th><th>Field</th><th>Value</th></tr></thead>
+                <tbody>${fields}</tbody>
+            </table>
+        `;
+
+        const byteSpans = packetBody.querySelectorAll('.hex-byte');
+        packetBody.querySelectorAll('.packet-fields tbody tr').forEach(row => {
+            const field = data.fields[row.dataset.field];
+            row.addEventListener('mouseenter', () => {
+                for (let i = field.offset; i < field.offset + field.length; i++) {
+                    byteSpans[i].classList.add('highlight');
+                }
+            });
+            row.addEventListener('mouseleave', () => {
+                byteSpans.forEach(span => span.classList.remove('highlight'));
+            });
+        });
+    } catch (error) {
+        console.error('Error loading packet:', error);
+        packetBody.textContent = 'Failed to load packet';
+    }
+}
+
+function closePacketDrawer() {
+    packetDrawer.hidden = true;
+    packetBody.innerHTML = '';
+}
+
 // Render pagination controls
 function renderPagination() {
     const totalPages = Math.ceil(totalRecords / pageSize);
@@ -181,6 +300,13 @@ function applyFilters() {
         vendor_class: filterVendor.value || null,
         message_type: filterType.value || null,
         xid: filterXid.value || null,
+        hostname: filterHostname.value.trim() || null,
+        requested_ip: filterRequestedIp.value.trim() || null,
+        server_id: filterServerId.value.trim() || null,
+        site_id: filterSite.value.trim() || null,
+        enterprise: filterEnterprise.value.trim() || null,
+        subnet: filterSubnet.value.trim() || null,
+        malformed: filterMalformed.value || null,
     };
     currentPage = 1;
     loadLogs();
@@ -194,6 +320,13 @@ function clearFilters() {
     filterVendor.value = '';
     filterType.value = '';
     filterXid.value = '';
+    filterHostname.value = '';
+    filterRequestedIp.value = '';
+    filterServerId.value = '';
+    filterSite.value = '';
+    filterEnterprise.value = '';
+    filterSubnet.value = '';
+    filterMalformed.value = '';
     currentFilters = {
         start_date: null,
         end_date: null,
@@ -201,6 +334,13 @@ function clearFilters() {
         vendor_class: null,
         message_type: null,
         xid: null,
+        hostname: null,
+        requested_ip: null,
+        server_id: null,
+        site_id: null,
+        enterprise: null,
+        subnet: null,
+        malformed: null,
     };
     currentPage = 1;
     loadLogs();
@@ -247,11 +387,40 @@ async function exportData(format) {
     if (currentFilters.vendor_class) params.append('vendor_class', currentFilters.vendor_class);
     if (currentFilters.message_type) params.append('message_type', currentFilters.message_type);
     if (currentFilters.xid) params.append('xid', currentFilters.xid);
-
-    window.location.href = `/api/logs/export?${params}`;
+    if (currentFilters.hostname) params.append('hostname', currentFilters.hostname);
+    if (currentFilters.requested_ip) params.append('requested_ip', currentFilters.requested_ip);
+    if (currentFilters.server_id) params.append('server_id', currentFilters.server_id);
+    if (currentFilters.site_id) params.append('site_id', currentFilters.site_id);
+    if (currentFilters.enterprise) params.append('enterprise', currentFilters.enterprise);
+    if (currentFilters.subnet) params.append('subnet', currentFilters.subnet);
+    if (currentFilters.malformed) params.append('malformed', currentFilters.malformed);
+
+    window.location.href = `api/logs/export?${params}`;
 }
 
 // Format timestamp
+// Hostnames are chosen by the client, so never insert them as markup
+// Option 12 hostname, with the option 81 FQDN and who registers the client in DNS
+const DDNS_TITLES = {
+    server: 'Client asks the DHCP server to update its A and PTR records',
+    client: 'Client updates its own A record; the server updates the PTR',
+    none: 'Client asks for no server DNS updates',
+};
+
+function formatHostname(req) {
+    const fqdn = req.client_fqdn;
+    const hostname = req.hostname ? escapeHtml(req.hostname) : '';
+    if (!fqdn) return hostname || '-';
+    const name = fqdn.name ? escapeHtml(fqdn.name) + (fqdn.partial ? '' : '.') : '(no name)';
+    return `${hostname}<div class="fqdn">${name} <span class="ddns ddns-${fqdn.ddns}" title="${DDNS_TITLES[fqdn.ddns]}">DDNS: ${fqdn.ddns}</span></div>`;
+}
+
+function escapeHtml(text) {
+    const div = document.createElement('div');
+    div.textContent = text;
+    return div.innerHTML;
+}
+
 function formatTimestamp(timestamp) {
     const date = new Date(timestamp);
     return date.toLocaleString();
@@ -283,6 +452,13 @@ btnApplyFilters.addEventListener('click', applyFilters);
 btnClearFilters.addEventListener('click', clearFilters);
 btnExportCsv.addEventListener('click', () => exportData('csv'));
 btnExportJson.addEventListener('click', () => exportData('json'));
+btnExportXlsx.addEventListener('click', () => exportData('xlsx'));
+btnExportPcap.addEventListener('click', () => exportData('pcap'));
+btnExportNdjson.addEventListener('click', () => exportData('ndjson'));
+btnCloseDrawer.addEventListener('click', closePacketDrawer);
+document.addEventListener('keydown', event => {
+    if (event.key === 'Escape' && !packetDrawer.hidden) closePacketDrawer();
+});
 pageSizeSelect.addEventListener('change', () => {
     pageSize = parseInt(pageSizeSelect.value);
     currentPage = 1;
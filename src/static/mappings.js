@@ -0,0 +1,146 @@
+// Device identity overrides: MAC -> OS mappings applied to future detections
+
+const mappingsBody = document.getElementById('mappings-body');
+const noResults = document.getElementById('no-results');
+const inputMac = document.getElementById('mapping-mac');
+const inputOs = document.getElementById('mapping-os');
+const inputClass = document.getElementById('mapping-class');
+const inputVendor = document.getElementById('mapping-vendor');
+const mappingStatus = document.getElementById('mapping-status');
+
+function escapeHtml(text) {
+    const div = document.createElement('div');
+    div.textContent = text;
+    return div.innerHTML;
+}
+
+// Edits are admin requests: with [web] admin_token set they carry the token, which is
+// asked for once and kept for this browser session
+async function adminFetch(url, options = {}) {
+    const send = () => {
+        const headers = { ...(options.headers || {}) };
+        const token = sessionStorage.getItem('adminToken');
+        if (token) headers.Authorization = `Bearer ${token}`;
+        return fetch(url, { ...options, headers });
+    };
+
+    let response = await send();
+    if (response.status === 401) {
+        const token = prompt('Admin token');
+        if (token) {
+            sessionStorage.setItem('adminToken', token.trim());
+            response = await send();
+        }
+    }
+    return response;
+}
+
+async function loadMappings() {
+    try {
+        const response = await fetch('api/mappings');
+        if (!response.ok) throw new Error(`HTTP ${response.status}`);
+        renderMappings(await response.json());
+    } catch (error) {
+        console.error('Error loading mappings:', error);
+        mappingStatus.textContent = 'Failed to load mappings';
+    }
+}
+
+function renderMappings(mappings) {
+    mappingsBody.innerHTML = '';
+    noResults.style.display = mappings.length === 0 ? 'block' : 'none';
+
+    mappings.forEach(mapping => {
+        const row = document.createElement('tr');
+        row.innerHTML = `
+            <td class="mac">${mapping.mac_address}</td>
+            <td>${escapeHtml(mapping.os_name)}</td>
+            <td>${escapeHtml(mapping.device_class) || '-'}</td>
+            <td>${escapeHtml(mapping.vendor) || '-'}</td>
+            <td>${mapping.source === 'file' ? 'mac_os_mapping.toml' : 'Override'}</td>
+            <td class="timestamp">${mapping.updated_at ? new Date(mapping.updated_at).toLocaleString() : '-'}</td>
+            <td></td>
+        `;
+
+        const actions = row.lastElementChild;
+        const edit = document.createElement('button');
+        edit.className = 'btn-secondary';
+        edit.textContent = 'Edit';
+        edit.addEventListener('click', () => {
+            inputMac.value = mapping.mac_address;
+            inputOs.value = mapping.os_name;
+            inputClass.value = mapping.device_class;
+            inputVendor.value = mapping.vendor;
+            inputOs.focus();
+        });
+        actions.appendChild(edit);
+
+        if (mapping.source === 'override') {
+            const remove = document.createElement('button');
+            remove.className = 'btn-secondary';
+            remove.textContent = 'Delete';
+            remove.addEventListener('click', () => deleteMapping(mapping.mac_address));
+            actions.appendChild(remove);
+        }
+        mappingsBody.appendChild(row);
+    });
+}
+
+async function saveMapping() {
+    const body = {
+        mac_address: inputMac.value.trim(),
+        os_name: inputOs.value.trim(),
+        device_class: inputClass.value.trim(),
+        vendor: inputVendor.value.trim(),
+    };
+
+    try {
+        const response = await adminFetch('api/mappings', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify(body),
+        });
+        if (!response.ok) {
+            mappingStatus.textContent = (await response.json()).error;
+            return;
+        }
+        mappingStatus.textContent = `Saved ${body.mac_address}`;
+        clearForm();
+        loadMappings();
+    } catch (error) {
+        console.error('Error saving mapping:', error);
+        mappingStatus.textContent = 'Failed to save mapping';
+    }
+}
+
+async function deleteMapping(mac) {
+    if (!confirm(`Remove the override for ${mac}?`)) return;
+
+    try {
+        const response = await adminFetch(`api/mappings/${encodeURIComponent(mac)}`, { method: 'DELETE' });
+        if (!response.ok) {
+            mappingStatus.textContent = (await response.json()).error;
+            return;
+        }
+        mappingStatus.textContent = `Removed ${mac}`;
+        loadMappings();
+    } catch (error) {
+        console.error('Error deleting mapping:', error);
+        mappingStatus.textContent = 'Failed to delete mapping';
+    }
+}
+
+function clearForm() {
+    inputMac.value = '';
+    inputOs.value = '';
+    inputClass.value = '';
+    inputVendor.value = '';
+}
+
+document.getElementById('btn-save-mapping').addEventListener('click', saveMapping);
+document.getElementById('btn-clear-mapping').addEventListener('click', () => {
+    clearForm();
+    mappingStatus.textContent = '';
+});
+
+loadMappings();
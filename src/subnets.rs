@@ -0,0 +1,81 @@
+//! Which subnet each request came from.
+//!
+//! Behind relays one monitor sees many VLANs at once. Relays report the
+//! client's link (giaddr, option 118, option 82 link-selection); that address
+//! is matched against the configured subnets, and otherwise stands for the
+//! network of the default prefix length around it.
+
+use crate::capture_filter::subnet_address;
+use crate::dhcp::DhcpPacket;
+use crate::smb_policy::Subnet;
+use std::net::Ipv4Addr;
+
+#[derive(Debug, Clone)]
+pub struct SubnetMap {
+    /// Most specific first
+    known: Vec<Subnet>,
+    /// Prefix length for addresses outside the known subnets
+    default_prefix_len: u32,
+}
+
+impl Default for SubnetMap {
+    fn default() -> Self {
+        Self::new(Vec::new(), 24)
+    }
+}
+
+impl SubnetMap {
+    pub fn new(mut known: Vec<Subnet>, default_prefix_len: u32) -> Self {
+        known.sort_by_key(|subnet| std::cmp::Reverse(subnet.prefix_len()));
+        Self { known, default_prefix_len }
+    }
+
+    pub fn subnet_of(&self, addr: Ipv4Addr) -> Subnet {
+        self.known
+            .iter()
+            .find(|subnet| subnet.contains(addr))
+            .copied()
+            .unwrap_or_else(|| Subnet::around(addr, self.default_prefix_len))
+    }
+
+    /// The packet's subnet in CIDR notation, or None when nothing places it
+    pub fn attribute(&self, packet: &DhcpPacket, source: Ipv4Addr) -> Option<String> {
+        subnet_address(packet, source).map(|addr| self.subnet_of(addr).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dhcp::DhcpPacketBuilder;
+
+    fn packet(giaddr: [u8; 4], options: &[(u8, &[u8])]) -> DhcpPacket {
+        let mut builder = DhcpPacketBuilder::discover().giaddr(giaddr.into());
+        for (code, value) in options {
+            builder = builder.option(*code, value);
+        }
+        DhcpPacket::parse(&builder.build()).unwrap()
+    }
+
+    #[test]
+    fn test_attribution() {
+        let map = SubnetMap::new(
+            vec![Subnet::parse("10.0.0.0/8").unwrap(), Subnet::parse("10.1.0.0/22").unwrap()],
+            24,
+        );
+        let unassigned = Ipv4Addr::UNSPECIFIED;
+
+        // giaddr, overridden by option 118, overridden by option 82 link-selection
+        let relayed = packet([10, 1, 2, 1], &[]);
+        assert_eq!(map.attribute(&relayed, unassigned).as_deref(), Some("10.1.0.0/22"));
+        let selected = packet([10, 1, 2, 1], &[(118, &[10, 9, 0, 1])]);
+        assert_eq!(map.attribute(&selected, unassigned).as_deref(), Some("10.0.0.0/8"));
+        let linked = packet([10, 1, 2, 1], &[(118, &[10, 9, 0, 1]), (82, &[1, 2, 0, 7, 5, 4, 192, 168, 7, 1])]);
+        assert_eq!(map.attribute(&linked, unassigned).as_deref(), Some("192.168.7.0/24"));
+
+        // Unrelayed clients by their own address; nothing places a fresh DISCOVER
+        let local = packet([0; 4], &[]);
+        assert_eq!(map.attribute(&local, Ipv4Addr::new(172, 16, 5, 20)).as_deref(), Some("172.16.5.0/24"));
+        assert_eq!(map.attribute(&local, unassigned), None);
+    }
+}
@@ -0,0 +1,218 @@
+//! Periodic sweeps of configured subnets for hosts that never ask DHCP.
+//!
+//! Every address of each subnet is either pinged (`icmp`, falling back to a TCP
+//! connect where ICMP sockets aren't permitted) or sent one UDP datagram to the
+//! discard port so the kernel ARPs for it (`arp`, which also finds hosts that drop
+//! ping). Either way the answering hosts' MACs come from the neighbor table, so
+//! only segments this host is attached to can be swept. Hosts found refresh
+//! their ARP and presence state and are stored; those that never sent a DHCP
+//! request appear in /api/devices marked `no_dhcp`.
+
+use crate::arp::{self, Neighbor};
+use crate::smb_policy::Subnet;
+use crate::web::state::AppState;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use futures::stream::{self, StreamExt};
+use std::collections::HashSet;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tracing::{debug, info, warn};
+
+/// Largest subnet accepted for sweeping
+pub const MAX_HOSTS: usize = 4096;
+/// The discard service; nothing is expected to answer on it
+const DISCARD_PORT: u16 = 9;
+/// Time for the kernel to finish resolving after the last ARP-triggering datagram
+const ARP_SETTLE: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SweepMethod {
+    Icmp,
+    Arp,
+}
+
+impl SweepMethod {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "icmp" | "ping" => Some(Self::Icmp),
+            "arp" => Some(Self::Arp),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Icmp => "icmp",
+            Self::Arp => "arp",
+        }
+    }
+
+    /// Presence source credited for hosts found this way
+    fn presence_source(&self) -> &'static str {
+        match self {
+            Self::Icmp => "ping",
+            Self::Arp => "arp",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SweepConfig {
+    pub subnets: Vec<Subnet>,
+    pub method: SweepMethod,
+    pub interval: Duration,
+    /// Addresses probed at once
+    pub concurrency: usize,
+    /// Neighbor table the answering hosts' MACs are read from
+    pub neighbor_path: PathBuf,
+}
+
+impl SweepConfig {
+    /// Refuse subnets with more than `max_hosts` addresses, which would take too
+    /// long to sweep and flood the neighbor table
+    pub fn check_size(subnets: &[Subnet], max_hosts: usize) -> Result<()> {
+        for subnet in subnets {
+            if subnet.prefix_len() < 32 && (1usize << (32 - subnet.prefix_len())) > max_hosts {
+                return Err(anyhow!("sweep subnet {} has more than {} addresses", subnet, max_hosts));
+            }
+        }
+        Ok(())
+    }
+
+    fn addresses(&self) -> Vec<Ipv4Addr> {
+        let mut addresses: Vec<_> = self.subnets.iter().flat_map(Subnet::hosts).collect();
+        addresses.sort();
+        addresses.dedup();
+        addresses
+    }
+}
+
+/// Sweep in the background every `interval`; only the cluster leader sweeps
+pub fn spawn(state: Arc<AppState>, config: SweepConfig) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.interval);
+        loop {
+            interval.tick().await;
+            if !state.is_active() {
+                continue;
+            }
+            match sweep(&config).await {
+                Ok(found) => record(&config, &state, found).await,
+                Err(e) => warn!("Subnet sweep failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Probe every address and return the hosts the neighbor table then resolves
+pub async fn sweep(config: &SweepConfig) -> Result<Vec<Neighbor>> {
+    let addresses = config.addresses();
+    let probed = addresses.len();
+    let answered: Vec<Ipv4Addr> = match config.method {
+        SweepMethod::Icmp => {
+            stream::iter(addresses)
+                .map(|ip| async move {
+                    let reply = crate::detection_sources::ping_host(&ip.to_string()).await;
+                    reply.ok().flatten().map(|_| ip)
+                })
+                .buffer_unordered(config.concurrency.max(1))
+                .filter_map(|ip| async move { ip })
+                .collect()
+                .await
+        }
+        SweepMethod::Arp => {
+            let socket = UdpSocket::bind("0.0.0.0:0").await?;
+            for ip in &addresses {
+                // Unreachable and refused sends are expected; only the ARP matters
+                let _ = socket.send_to(&[], SocketAddr::from((*ip, DISCARD_PORT))).await;
+            }
+            tokio::time::sleep(ARP_SETTLE).await;
+            addresses
+        }
+    };
+
+    let content = tokio::fs::read_to_string(&config.neighbor_path)
+        .await
+        .map_err(|e| anyhow!("failed to read {}: {}", config.neighbor_path.display(), e))?;
+    let found = resolve(&arp::parse_proc_net_arp(&content), &answered);
+    debug!(
+        "{} sweep of {} addresses: {} answered, {} resolved to a MAC",
+        config.method.as_str(),
+        probed,
+        answered.len(),
+        found.len()
+    );
+    Ok(found)
+}
+
+// Neighbor entries for the addresses that answered
+fn resolve(neighbors: &[Neighbor], answered: &[Ipv4Addr]) -> Vec<Neighbor> {
+    let answered: HashSet<_> = answered.iter().collect();
+    neighbors
+        .iter()
+        .filter(|neighbor| neighbor.ip.parse::<Ipv4Addr>().is_ok_and(|ip| answered.contains(&ip)))
+        .cloned()
+        .collect()
+}
+
+async fn record(config: &SweepConfig, state: &AppState, found: Vec<Neighbor>) {
+    let now = Utc::now();
+    let seen_at = now.to_rfc3339();
+    for host in &found {
+        if let Err(e) =
+            crate::db::sweep::record_sighting(&state.db_pool, &host.mac, &host.ip, config.method.as_str(), &seen_at).await
+        {
+            warn!("Failed to store sweep sighting of {}: {}", host.mac, e);
+        }
+        if let Some(presence) = &state.presence {
+            let ip = host.ip.parse().ok();
+            if let Some(change) = presence.record_activity(&host.mac, ip, now, config.method.presence_source()) {
+                crate::presence::announce(state, change);
+            }
+        }
+    }
+    info!("Subnet sweep found {} hosts", found.len());
+    if let Some(neighbors) = &state.neighbors {
+        neighbors.update(found, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_addresses_and_size_limit() {
+        let subnets = vec![Subnet::parse("10.0.0.0/30").unwrap(), Subnet::parse("10.0.0.2").unwrap()];
+        let config = SweepConfig {
+            subnets: subnets.clone(),
+            method: SweepMethod::Arp,
+            interval: Duration::from_secs(60),
+            concurrency: 8,
+            neighbor_path: PathBuf::new(),
+        };
+        assert_eq!(config.addresses(), [Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2)]);
+
+        assert!(SweepConfig::check_size(&subnets, 4).is_ok());
+        assert!(SweepConfig::check_size(&[Subnet::parse("10.0.0.0/16").unwrap()], 4096).is_err());
+        assert_eq!(SweepMethod::from_name("PING"), Some(SweepMethod::Icmp));
+        assert_eq!(SweepMethod::from_name("tcp"), None);
+    }
+
+    #[test]
+    fn test_resolve_keeps_answering_hosts() {
+        let neighbors = arp::parse_proc_net_arp(
+            "IP address       HW type     Flags       HW address            Mask     Device
+10.0.0.1         0x1         0x2         aa:bb:cc:00:00:01     *        eth0
+10.0.0.7         0x1         0x2         aa:bb:cc:00:00:07     *        eth0
+",
+        );
+        let found = resolve(&neighbors, &[Ipv4Addr::new(10, 0, 0, 7), Ipv4Addr::new(10, 0, 0, 8)]);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].mac, "aa:bb:cc:00:00:07");
+    }
+}
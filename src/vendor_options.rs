@@ -0,0 +1,197 @@
+//! DHCP option 125, vendor-identifying vendor-specific information (RFC 3925).
+//!
+//! Each block carries an IANA enterprise number and that vendor's own
+//! sub-options. Blocks of vendors we know are decoded into named values;
+//! the enterprise numbers alone already say a lot about what the device is.
+
+use crate::dhcp::hex_colon;
+use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
+
+/// One sub-option of a vendor block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VendorSubOption {
+    pub code: u8,
+    /// Name, for vendors and sub-options we know
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Decoded value; hex when the encoding is unknown and the data isn't text
+    pub value: String,
+}
+
+/// One enterprise's block of option 125
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnterpriseOptions {
+    /// IANA private enterprise number
+    pub enterprise: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vendor: Option<String>,
+    pub sub_options: Vec<VendorSubOption>,
+}
+
+#[derive(Clone, Copy)]
+enum SubOptionKind {
+    Text,
+    Ipv4List,
+    /// Sub-option codes the client asks for
+    CodeList,
+}
+
+use SubOptionKind::*;
+
+/// Enterprise numbers seen in option 125
+const ENTERPRISES: &[(u32, &str)] = &[
+    (9, "Cisco"),
+    (311, "Microsoft"),
+    (2011, "Huawei"),
+    (3561, "Broadband Forum"),
+    (4491, "CableLabs"),
+    (14823, "Aruba"),
+    (25053, "Ruckus"),
+    (41112, "Ubiquiti"),
+];
+
+/// Sub-options we decode: (enterprise, code, name, encoding)
+const SUB_OPTIONS: &[(u32, u8, &str, SubOptionKind)] = &[
+    // TR-111 device and gateway identity
+    (3561, 1, "DeviceManufacturerOUI", Text),
+    (3561, 2, "DeviceSerialNumber", Text),
+    (3561, 3, "DeviceProductClass", Text),
+    (3561, 4, "GatewayManufacturerOUI", Text),
+    (3561, 5, "GatewaySerialNumber", Text),
+    (3561, 6, "GatewayProductClass", Text),
+    // eDOCSIS cable modems and PacketCable eMTAs
+    (4491, 1, "Option Request", CodeList),
+    (4491, 2, "TFTP Servers", Ipv4List),
+    (41112, 1, "Controller Address", Ipv4List),
+];
+
+pub fn enterprise_name(enterprise: u32) -> Option<&'static str> {
+    ENTERPRISES.iter().find(|(number, _)| *number == enterprise).map(|(_, name)| *name)
+}
+
+fn decode_sub_option(enterprise: u32, code: u8, data: &[u8]) -> VendorSubOption {
+    let known = SUB_OPTIONS.iter().find(|(number, sub_code, ..)| *number == enterprise && *sub_code == code);
+    let value = known.and_then(|(.., kind)| match kind {
+        Text => std::str::from_utf8(data).ok().map(|text| text.trim_end_matches('\0').to_string()),
+        Ipv4List => (!data.is_empty() && data.len().is_multiple_of(4)).then(|| {
+            data.chunks(4)
+                .map(|octets| Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]).to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }),
+        CodeList => Some(data.iter().map(u8::to_string).collect::<Vec<_>>().join(", ")),
+    });
+    let value = value.unwrap_or_else(|| match std::str::from_utf8(data) {
+        Ok(text) if !text.is_empty() && text.chars().all(|c| c.is_ascii_graphic() || c == ' ') => text.to_string(),
+        _ => hex_colon(data),
+    });
+
+    VendorSubOption {
+        code,
+        name: known.map(|(_, _, name, _)| name.to_string()),
+        value,
+    }
+}
+
+/// Decode option 125's enterprise blocks. A truncated block ends decoding;
+/// the blocks before it are kept.
+pub fn decode(data: &[u8]) -> Vec<EnterpriseOptions> {
+    let mut blocks = Vec::new();
+    let mut rest = data;
+    while let [a, b, c, d, len, tail @ ..] = rest {
+        let len = *len as usize;
+        if len > tail.len() {
+            break;
+        }
+        let enterprise = u32::from_be_bytes([*a, *b, *c, *d]);
+        let mut sub_options = Vec::new();
+        let mut body = &tail[..len];
+        while let [code, sub_len, value @ ..] = body {
+            let sub_len = *sub_len as usize;
+            if sub_len > value.len() {
+                break;
+            }
+            sub_options.push(decode_sub_option(enterprise, *code, &value[..sub_len]));
+            body = &value[sub_len..];
+        }
+        blocks.push(EnterpriseOptions {
+            enterprise,
+            vendor: enterprise_name(enterprise).map(str::to_string),
+            sub_options,
+        });
+        rest = &tail[len..];
+    }
+    blocks
+}
+
+/// What a device's vendor blocks say it is
+#[derive(Debug, Clone, PartialEq)]
+pub struct VendorClassification {
+    pub os_name: String,
+    pub device_class: &'static str,
+    pub vendor: Option<&'static str>,
+    /// The block it was based on, e.g. "enterprise 4491 (CableLabs)"
+    pub evidence: String,
+}
+
+/// Classify from the first block of a vendor whose devices we recognise
+pub fn classify(blocks: &[EnterpriseOptions]) -> Option<VendorClassification> {
+    blocks.iter().find_map(|block| {
+        let (os_name, device_class, vendor) = match block.enterprise {
+            3561 => {
+                let product_class = block
+                    .sub_options
+                    .iter()
+                    .find(|sub| sub.code == 3)
+                    .map_or("unknown product class", |sub| sub.value.as_str());
+                (format!("TR-069 CPE ({})", product_class), "Managed CPE", None)
+            }
+            4491 => ("DOCSIS/PacketCable firmware".to_string(), "Cable Modem/eMTA", None),
+            41112 => ("Ubiquiti UniFi".to_string(), "Router/AP", Some("Ubiquiti")),
+            _ => return None,
+        };
+        Some(VendorClassification {
+            os_name,
+            device_class,
+            vendor,
+            evidence: format!("enterprise {} ({})", block.enterprise, block.vendor.as_deref().unwrap_or("unknown")),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_vendor_blocks() {
+        let mut data = vec![0, 0, 0x0d, 0xe9, 22];
+        data.extend_from_slice(&[1, 6]);
+        data.extend_from_slice(b"00D09E");
+        data.extend_from_slice(&[2, 4]);
+        data.extend_from_slice(b"SN01");
+        data.extend_from_slice(&[3, 6]);
+        data.extend_from_slice(b"IGD-42");
+        data.extend_from_slice(&[0, 0, 0xa0, 0x98, 6, 1, 4, 10, 0, 0, 1]);
+        // Unknown vendor with binary data, then a truncated block
+        data.extend_from_slice(&[0, 0, 0x30, 0x39, 3, 7, 1, 0xff]);
+        data.extend_from_slice(&[0, 0, 0x11, 0x8b, 9, 1]);
+
+        let blocks = decode(&data);
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].enterprise, 3561);
+        assert_eq!(blocks[0].vendor.as_deref(), Some("Broadband Forum"));
+        assert_eq!(blocks[0].sub_options[2].name.as_deref(), Some("DeviceProductClass"));
+        assert_eq!(blocks[0].sub_options[2].value, "IGD-42");
+        assert_eq!(blocks[1].sub_options[0].value, "10.0.0.1");
+        assert_eq!(blocks[2].vendor, None);
+        assert_eq!(blocks[2].sub_options, [VendorSubOption { code: 7, name: None, value: "ff".to_string() }]);
+
+        let classified = classify(&blocks).unwrap();
+        assert_eq!(classified.os_name, "TR-069 CPE (IGD-42)");
+        assert_eq!(classified.evidence, "enterprise 3561 (Broadband Forum)");
+        assert_eq!(classify(&blocks[1..]).unwrap().vendor, Some("Ubiquiti"));
+        assert_eq!(classify(&blocks[2..]), None);
+    }
+}
@@ -0,0 +1,136 @@
+use super::proxy::ProxyConfig;
+use crate::logger::{RotatingFile, RotationConfig};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderName},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// One line of the HTTP access log
+#[derive(Debug, Serialize)]
+pub struct AccessLogEntry {
+    pub timestamp: DateTime<Utc>,
+    pub client: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub query: Option<String>,
+    pub status: u16,
+    pub latency_ms: f64,
+    /// User asserted by the authenticating reverse proxy, if any
+    pub principal: Option<String>,
+}
+
+/// Writes HTTP requests to their own rotating file, separate from tracing output
+pub struct AccessLogger {
+    file: RotatingFile,
+    principal_header: Option<HeaderName>,
+    proxy: ProxyConfig,
+}
+
+impl AccessLogger {
+    pub fn new(path: &str, rotation: RotationConfig, principal_header: Option<&str>) -> anyhow::Result<Self> {
+        let principal_header = principal_header
+            .map(|name| HeaderName::from_bytes(name.as_bytes()))
+            .transpose()?;
+
+        Ok(Self {
+            file: RotatingFile::open(path, rotation)?,
+            principal_header,
+            proxy: ProxyConfig::default(),
+        })
+    }
+
+    /// Log the client the reverse proxy saw when its forwarded headers are trusted
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    fn principal(&self, headers: &HeaderMap) -> Option<String> {
+        let header = self.principal_header.as_ref()?;
+        headers
+            .get(header)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| !value.is_empty())
+            .map(|value| value.to_string())
+    }
+
+    pub fn log(&self, entry: &AccessLogEntry) {
+        let result = serde_json::to_string(entry)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| self.file.write_line(&line));
+        if let Err(e) = result {
+            tracing::warn!("Failed to write access log: {}", e);
+        }
+    }
+}
+
+/// Middleware recording method, path, status, latency and principal of every request
+pub async fn log_access(
+    State(logger): State<Arc<AccessLogger>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let timestamp = Utc::now();
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let query = request.uri().query().map(|q| q.to_string());
+    let principal = logger.principal(request.headers());
+    let client = logger.proxy.client_ip(request.headers(), connect_info.as_ref());
+
+    let response = next.run(request).await;
+
+    logger.log(&AccessLogEntry {
+        timestamp,
+        client: client.map(|ip| ip.to_string()),
+        method,
+        path,
+        query,
+        status: response.status().as_u16(),
+        latency_ms: started.elapsed().as_secs_f64() * 1000.0,
+        principal,
+    });
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_principal_from_configured_header() {
+        let dir = std::env::temp_dir().join(format!("ks-dhcpmon-access-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("access.json");
+        let logger = AccessLogger::new(path.to_str().unwrap(), RotationConfig::default(), Some("X-Forwarded-User")).unwrap();
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(logger.principal(&headers), None);
+        headers.insert("x-forwarded-user", HeaderValue::from_static("alice"));
+        assert_eq!(logger.principal(&headers).as_deref(), Some("alice"));
+
+        logger.log(&AccessLogEntry {
+            timestamp: Utc::now(),
+            client: Some("127.0.0.1".to_string()),
+            method: "GET".to_string(),
+            path: "/api/stats".to_string(),
+            query: None,
+            status: 200,
+            latency_ms: 1.5,
+            principal: Some("alice".to_string()),
+        });
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.contains("\"path\":\"/api/stats\""));
+        assert!(written.contains("\"principal\":\"alice\""));
+    }
+}
@@ -0,0 +1,109 @@
+//! Access control for endpoints that change how the monitor runs.
+//!
+//! Config reloads, detection data reloads, probe cache deletion, classification
+//! rule, MAC mapping and device metadata edits, alert acknowledgement and fingerprint
+//! bundle updates can blind the monitor or redirect its notifications, and on-demand
+//! re-detection sends probes into the network, so they are not open like the read
+//! API. With `[web] admin_token` set they take `Authorization: Bearer <token>`;
+//! browsers never attach that on their own and CORS doesn't allow it, so pages on
+//! other sites can't forge these requests. Without a token only local command-line
+//! clients get in: the peer must be a loopback address, and requests that were
+//! forwarded by a proxy or sent by a browser (they carry an `Origin` header) are
+//! refused.
+
+use super::error::ApiError;
+use super::state::AppState;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Whether a request may use the admin endpoints
+fn authorize(
+    token: Option<&str>,
+    headers: &HeaderMap,
+    connect_info: Option<&ConnectInfo<SocketAddr>>,
+) -> Result<(), ApiError> {
+    if let Some(token) = token {
+        let presented = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        return match presented {
+            Some(presented) if crate::agent::constant_time_eq(presented.trim().as_bytes(), token.as_bytes()) => Ok(()),
+            _ => Err(ApiError::new(StatusCode::UNAUTHORIZED, "Missing or wrong admin token")),
+        };
+    }
+
+    let local = connect_info.is_some_and(|ConnectInfo(addr)| addr.ip().is_loopback())
+        && !headers.contains_key("x-forwarded-for")
+        && !headers.contains_key("forwarded")
+        && !headers.contains_key(header::ORIGIN);
+    if local {
+        Ok(())
+    } else {
+        Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "Admin endpoints are only open to local clients; set [web] admin_token to use them remotely",
+        ))
+    }
+}
+
+/// Middleware refusing admin requests that aren't authorized
+pub async fn require_admin(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Err(e) = authorize(state.admin_token.as_deref(), request.headers(), connect_info.as_ref()) {
+        tracing::warn!(
+            "Refused {} {} from {:?}",
+            request.method(),
+            request.uri().path(),
+            connect_info.map(|ConnectInfo(addr)| addr.ip())
+        );
+        return e.into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(addr: &str) -> Option<ConnectInfo<SocketAddr>> {
+        Some(ConnectInfo(addr.parse().unwrap()))
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_local_clients_without_token() {
+        assert!(authorize(None, &HeaderMap::new(), peer("127.0.0.1:50000").as_ref()).is_ok());
+        assert!(authorize(None, &HeaderMap::new(), peer("[::1]:50000").as_ref()).is_ok());
+        assert!(authorize(None, &HeaderMap::new(), peer("10.0.0.5:50000").as_ref()).is_err());
+        // Unix socket: no peer address to vouch for
+        assert!(authorize(None, &HeaderMap::new(), None).is_err());
+        // A proxy on the same host, or a browser page posting to localhost
+        assert!(authorize(None, &headers(&[("x-forwarded-for", "203.0.113.9")]), peer("127.0.0.1:50000").as_ref()).is_err());
+        assert!(authorize(None, &headers(&[("origin", "https://evil.example")]), peer("127.0.0.1:50000").as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_token_required_when_set() {
+        let remote = peer("10.0.0.5:50000");
+        assert!(authorize(Some("s3cret"), &headers(&[("authorization", "Bearer s3cret")]), remote.as_ref()).is_ok());
+        assert!(authorize(Some("s3cret"), &headers(&[("authorization", "Bearer guess")]), remote.as_ref()).is_err());
+        // Loopback alone isn't enough once a token is configured
+        assert!(authorize(Some("s3cret"), &HeaderMap::new(), peer("127.0.0.1:50000").as_ref()).is_err());
+    }
+}
@@ -0,0 +1,59 @@
+use once_cell::sync::Lazy;
+
+// Content-hashed asset paths and UI_VERSION, generated by build.rs
+include!(concat!(env!("OUT_DIR"), "/asset_hashes.rs"));
+
+/// Cache-Control for content-hashed URLs (content can never change under them)
+pub const CACHE_IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// Cache-Control for pages and unversioned asset URLs
+pub const CACHE_REVALIDATE: &str = "no-cache";
+
+// Pages link assets relative to their <base href>, so drop the leading slash
+fn relative(path: &str) -> &str {
+    path.trim_start_matches('/')
+}
+
+/// index.html with asset references rewritten to their hashed URLs
+pub static INDEX_HTML: Lazy<String> = Lazy::new(|| {
+    include_str!("../static/index.html")
+        .replace("\"app.js\"", &format!("\"{}\"", relative(APP_JS_PATH)))
+        .replace("\"styles.css\"", &format!("\"{}\"", relative(STYLES_CSS_PATH)))
+});
+
+/// logs.html with asset references rewritten to their hashed URLs
+pub static LOGS_HTML: Lazy<String> = Lazy::new(|| {
+    include_str!("../static/logs.html")
+        .replace("\"logs.js\"", &format!("\"{}\"", relative(LOGS_JS_PATH)))
+        .replace("\"logs.css\"", &format!("\"{}\"", relative(LOGS_CSS_PATH)))
+});
+
+/// mappings.html with asset references rewritten to their hashed URLs
+pub static MAPPINGS_HTML: Lazy<String> = Lazy::new(|| {
+    include_str!("../static/mappings.html")
+        .replace("\"mappings.js\"", &format!("\"{}\"", relative(MAPPINGS_JS_PATH)))
+        .replace("\"logs.css\"", &format!("\"{}\"", relative(LOGS_CSS_PATH)))
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pages_reference_hashed_assets() {
+        assert!(INDEX_HTML.contains(relative(APP_JS_PATH)));
+        assert!(INDEX_HTML.contains(relative(STYLES_CSS_PATH)));
+        assert!(!INDEX_HTML.contains("\"app.js\""));
+        assert!(LOGS_HTML.contains(relative(LOGS_JS_PATH)));
+        assert!(LOGS_HTML.contains(relative(LOGS_CSS_PATH)));
+        assert!(MAPPINGS_HTML.contains(relative(MAPPINGS_JS_PATH)));
+        assert!(MAPPINGS_HTML.contains(relative(LOGS_CSS_PATH)));
+    }
+
+    #[test]
+    fn test_hashed_paths_differ_from_plain() {
+        assert!(APP_JS_PATH.starts_with("/app.") && APP_JS_PATH.ends_with(".js"));
+        assert_ne!(APP_JS_PATH, "/app.js");
+        assert_eq!(UI_VERSION.len(), 16);
+    }
+}
@@ -0,0 +1,572 @@
+//! GraphQL endpoint for dashboards that need more than the fixed REST parameters.
+//!
+//! Requests, devices, address leases and live statistics can be queried with any
+//! field selection; devices nest their requests, leases and metadata, and every
+//! list takes its own filter.
+
+use super::state::AppState;
+use crate::db::queries::{DeviceSummary, QueryFilters};
+use crate::dhcp::DhcpRequest;
+use async_graphql::{
+    ComplexObject, Context, EmptyMutation, EmptySubscription, InputObject, Object, Schema, SimpleObject,
+};
+use once_cell::sync::Lazy;
+use std::sync::Arc;
+
+/// Largest page any list returns
+const MAX_PAGE_SIZE: i64 = 500;
+
+pub type DhcpSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// The schema is stateless; the app state is attached to each request
+pub static SCHEMA: Lazy<DhcpSchema> = Lazy::new(|| {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(8)
+        .limit_complexity(2000)
+        .finish()
+});
+
+fn state<'a>(ctx: &Context<'a>) -> async_graphql::Result<&'a Arc<AppState>> {
+    ctx.data::<Arc<AppState>>()
+}
+
+/// Filters for stored requests; unset fields match everything
+#[derive(Debug, Default, InputObject)]
+pub struct RequestFilter {
+    pub mac_address: Option<String>,
+    pub vendor_class: Option<String>,
+    pub message_type: Option<String>,
+    pub xid: Option<String>,
+    pub hostname: Option<String>,
+    pub vlan_id: Option<u16>,
+    pub requested_ip: Option<String>,
+    pub server_id: Option<String>,
+    /// Agent site, or "local" for requests captured by this instance
+    pub site_id: Option<String>,
+    /// Option 125 enterprise number
+    pub enterprise: Option<u32>,
+    /// Subnet in CIDR notation, e.g. "10.1.20.0/24"
+    pub subnet: Option<String>,
+    /// true for packets with conformance warnings, false for clean ones
+    pub malformed: Option<bool>,
+    /// RFC 3339 bounds on the request timestamp
+    pub since: Option<String>,
+    pub until: Option<String>,
+}
+
+impl RequestFilter {
+    fn to_filters(&self, page: i64, page_size: i64, oldest_first: bool) -> async_graphql::Result<QueryFilters> {
+        let address = |value: &Option<String>| -> async_graphql::Result<Option<std::net::Ipv4Addr>> {
+            value
+                .as_deref()
+                .map(|ip| ip.parse().map_err(|_| format!("Invalid IPv4 address '{}'", ip).into()))
+                .transpose()
+        };
+        let bound = |value: &Option<String>| -> async_graphql::Result<Option<String>> {
+            value
+                .as_deref()
+                .map(|time| {
+                    crate::db::queries::timestamp_bound(time)
+                        .ok_or_else(|| format!("Invalid RFC 3339 timestamp '{}'", time).into())
+                })
+                .transpose()
+        };
+        if let Some(message_type) = &self.message_type {
+            if !crate::dhcp::MESSAGE_TYPES.contains(&message_type.as_str()) {
+                return Err(format!("Unknown message type '{}'", message_type).into());
+            }
+        }
+        Ok(QueryFilters {
+            mac_address: self.mac_address.as_deref().map(crate::mac::canonical_fragment),
+            vendor_class: self.vendor_class.clone(),
+            message_type: self.message_type.clone(),
+            xid: self.xid.clone(),
+            start_date: bound(&self.since)?,
+            end_date: bound(&self.until)?,
+            vlan_id: self.vlan_id,
+            hostname: self.hostname.clone(),
+            requested_ip: address(&self.requested_ip)?,
+            server_id: address(&self.server_id)?,
+            site_id: self.site_id.clone(),
+            enterprise: self.enterprise,
+            subnet: self.subnet.clone(),
+            malformed: self.malformed,
+            sort_by: "timestamp".to_string(),
+            sort_order: if oldest_first { "ASC" } else { "DESC" }.to_string(),
+            page: page.max(1),
+            page_size: page_size.clamp(1, MAX_PAGE_SIZE),
+        })
+    }
+}
+
+/// Filters for the device inventory
+#[derive(Debug, Default, InputObject)]
+pub struct DeviceFilter {
+    /// Substring of the detected OS, case-insensitive
+    pub os_name: Option<String>,
+    pub device_class: Option<String>,
+    /// Substring of the vendor class, case-insensitive
+    pub vendor_class: Option<String>,
+    pub vlan_id: Option<i64>,
+    /// Devices that have a tag set through the API
+    pub tag: Option<String>,
+    /// RFC 3339; devices seen at or after this time
+    pub seen_since: Option<String>,
+}
+
+fn contains(value: &Option<String>, needle: &Option<String>) -> bool {
+    match needle {
+        Some(needle) => value
+            .as_deref()
+            .is_some_and(|value| value.to_lowercase().contains(&needle.to_lowercase())),
+        None => true,
+    }
+}
+
+impl DeviceFilter {
+    fn matches(&self, device: &DeviceSummary) -> bool {
+        contains(&device.os_name, &self.os_name)
+            && contains(&device.vendor_class, &self.vendor_class)
+            && self.device_class.as_ref().is_none_or(|class| device.device_class.as_ref() == Some(class))
+            && self.vlan_id.is_none_or(|vlan| device.vlan_id == Some(vlan))
+            && self.seen_since.as_ref().is_none_or(|since| device.last_seen >= *since)
+            && self.tag.as_ref().is_none_or(|tag| {
+                device.meta.as_ref().is_some_and(|meta| meta.tags.contains(tag))
+            })
+    }
+}
+
+/// Filters for address leases
+#[derive(Debug, Default, InputObject)]
+pub struct LeaseFilter {
+    pub ip_address: Option<String>,
+    /// RFC 3339; leases still seen at or after this time
+    pub seen_since: Option<String>,
+}
+
+/// A stored DHCP request
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Request {
+    pub id: Option<i64>,
+    pub timestamp: String,
+    pub source_ip: String,
+    pub source_port: u16,
+    pub mac_address: String,
+    pub message_type: String,
+    pub xid: String,
+    pub fingerprint: String,
+    pub vendor_class: Option<String>,
+    pub hostname: Option<String>,
+    pub requested_ip: Option<String>,
+    pub server_id: Option<String>,
+    pub os_name: Option<String>,
+    pub device_class: Option<String>,
+    pub detection_method: Option<String>,
+    pub confidence: Option<f32>,
+    pub smb_dialect: Option<String>,
+    pub smb_build: Option<u32>,
+    pub vlan_id: Option<u16>,
+    pub user_class: Option<String>,
+    pub client_arch: Option<u16>,
+    pub repeats: u32,
+    pub lint_issues: Vec<String>,
+    pub site_id: Option<String>,
+    /// Option 125 enterprise numbers
+    pub enterprise_numbers: Vec<u32>,
+    pub subnet: Option<String>,
+}
+
+impl From<DhcpRequest> for Request {
+    fn from(request: DhcpRequest) -> Self {
+        Self {
+            id: request.id,
+            timestamp: request.timestamp,
+            source_ip: request.source_ip,
+            source_port: request.source_port,
+            mac_address: request.mac_address,
+            message_type: request.message_type,
+            xid: request.xid,
+            fingerprint: request.fingerprint,
+            vendor_class: request.vendor_class,
+            hostname: request.hostname,
+            requested_ip: request.requested_ip,
+            server_id: request.server_id,
+            os_name: request.os_name,
+            device_class: request.device_class,
+            detection_method: request.detection_method,
+            confidence: request.confidence,
+            smb_dialect: request.smb_dialect,
+            smb_build: request.smb_build,
+            vlan_id: request.vlan_id,
+            user_class: request.user_class,
+            client_arch: request.client_arch,
+            repeats: request.repeats,
+            lint_issues: request.lint_issues,
+            site_id: request.site_id,
+            enterprise_numbers: request.vendor_options.iter().map(|block| block.enterprise).collect(),
+            subnet: request.subnet,
+        }
+    }
+}
+
+#[ComplexObject]
+impl Request {
+    /// The inventory entry of the device that sent this request
+    async fn device(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Device>> {
+        find_device(ctx, &self.mac_address).await
+    }
+}
+
+/// One page of requests; the total is only counted when selected
+pub struct RequestPage {
+    filters: QueryFilters,
+}
+
+#[Object]
+impl RequestPage {
+    async fn items(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Request>> {
+        let requests = crate::db::queries::query_requests(&state(ctx)?.db_pool, &self.filters).await?;
+        Ok(requests.into_iter().map(Request::from).collect())
+    }
+
+    /// Requests matching the filter across all pages
+    async fn total(&self, ctx: &Context<'_>) -> async_graphql::Result<i64> {
+        Ok(crate::db::queries::count_requests(&state(ctx)?.db_pool, &self.filters).await?)
+    }
+
+    async fn page(&self) -> i64 {
+        self.filters.page
+    }
+
+    async fn page_size(&self) -> i64 {
+        self.filters.page_size
+    }
+}
+
+/// Name, location, owner and tags set through the API
+#[derive(SimpleObject)]
+pub struct DeviceMeta {
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub owner: Option<String>,
+    pub tags: Vec<String>,
+    pub updated_at: String,
+}
+
+impl From<crate::db::device_meta::DeviceMeta> for DeviceMeta {
+    fn from(meta: crate::db::device_meta::DeviceMeta) -> Self {
+        Self {
+            name: meta.name,
+            location: meta.location,
+            owner: meta.owner,
+            tags: meta.tags,
+            updated_at: meta.updated_at,
+        }
+    }
+}
+
+/// A stretch of time a device held one address
+#[derive(SimpleObject)]
+pub struct Lease {
+    pub mac_address: String,
+    pub ip_address: String,
+    /// Where the address was seen: "yiaddr", "ciaddr" or "requested_ip"
+    pub source: String,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+/// A client from the device inventory
+pub struct Device(DeviceSummary);
+
+#[Object]
+impl Device {
+    async fn mac_address(&self) -> &str {
+        &self.0.mac_address
+    }
+
+    /// Option 61 client identifier when the device sends one, otherwise its MAC
+    async fn client_key(&self) -> &str {
+        &self.0.client_key
+    }
+
+    async fn mac_count(&self) -> i64 {
+        self.0.mac_count
+    }
+
+    async fn last_ip(&self) -> Option<&str> {
+        self.0.last_ip.as_deref()
+    }
+
+    async fn assigned_ip(&self) -> Option<&str> {
+        self.0.assigned_ip.as_deref()
+    }
+
+    async fn ip_changes(&self) -> i64 {
+        self.0.ip_changes
+    }
+
+    async fn os_name(&self) -> Option<&str> {
+        self.0.os_name.as_deref()
+    }
+
+    async fn device_class(&self) -> Option<&str> {
+        self.0.device_class.as_deref()
+    }
+
+    async fn vendor_class(&self) -> Option<&str> {
+        self.0.vendor_class.as_deref()
+    }
+
+    async fn vlan_id(&self) -> Option<i64> {
+        self.0.vlan_id
+    }
+
+    async fn request_count(&self) -> i64 {
+        self.0.requests
+    }
+
+    async fn first_seen(&self) -> &str {
+        &self.0.first_seen
+    }
+
+    async fn last_seen(&self) -> &str {
+        &self.0.last_seen
+    }
+
+    async fn meta(&self) -> Option<DeviceMeta> {
+        self.0.meta.clone().map(DeviceMeta::from)
+    }
+
+    /// This device's requests, newest first unless `oldestFirst`
+    async fn requests(
+        &self,
+        filter: Option<RequestFilter>,
+        #[graphql(default = 1)] page: i64,
+        #[graphql(default = 100)] page_size: i64,
+        #[graphql(default = false)] oldest_first: bool,
+    ) -> async_graphql::Result<RequestPage> {
+        let mut filters = filter.unwrap_or_default().to_filters(page, page_size, oldest_first)?;
+        filters.mac_address = Some(self.0.mac_address.clone());
+        Ok(RequestPage { filters })
+    }
+
+    /// Addresses the device has held, most recent first
+    async fn leases(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Lease>> {
+        let history = crate::db::ip_history::ip_history(&state(ctx)?.db_pool, &self.0.mac_address).await?;
+        Ok(history
+            .into_iter()
+            .map(|assignment| Lease {
+                mac_address: self.0.mac_address.clone(),
+                ip_address: assignment.ip_address,
+                source: assignment.source,
+                first_seen: assignment.first_seen,
+                last_seen: assignment.last_seen,
+            })
+            .collect())
+    }
+}
+
+async fn find_device(ctx: &Context<'_>, mac_address: &str) -> async_graphql::Result<Option<Device>> {
+    let mac_address = crate::mac::canonical(mac_address);
+    let devices = crate::db::queries::device_inventory(&state(ctx)?.db_pool).await?;
+    Ok(devices
+        .into_iter()
+        .find(|device| device.mac_address == mac_address)
+        .map(Device))
+}
+
+/// A label and how often it was seen
+#[derive(SimpleObject)]
+pub struct Count {
+    pub key: String,
+    pub count: u64,
+}
+
+fn counts<K: ToString>(map: std::collections::HashMap<K, u64>) -> Vec<Count> {
+    let mut counts: Vec<Count> = map
+        .into_iter()
+        .map(|(key, count)| Count { key: key.to_string(), count })
+        .collect();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+    counts
+}
+
+/// Live counters since startup, as served by /api/stats
+#[derive(SimpleObject)]
+pub struct Stats {
+    pub total_requests: u64,
+    pub unique_macs: u64,
+    pub requests_per_minute: f64,
+    pub requests_per_minute_5m: f64,
+    pub requests_per_minute_15m: f64,
+    pub uptime_seconds: u64,
+    pub last_updated: String,
+    pub request_types: Vec<Count>,
+    pub vendor_classes: Vec<Count>,
+    pub vlans: Vec<Count>,
+    pub subnets: Vec<Count>,
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Stored requests, newest first unless `oldestFirst`
+    async fn requests(
+        &self,
+        filter: Option<RequestFilter>,
+        #[graphql(default = 1)] page: i64,
+        #[graphql(default = 100)] page_size: i64,
+        #[graphql(default = false)] oldest_first: bool,
+    ) -> async_graphql::Result<RequestPage> {
+        let filters = filter.unwrap_or_default().to_filters(page, page_size, oldest_first)?;
+        Ok(RequestPage { filters })
+    }
+
+    /// The device inventory, most recently seen first
+    async fn devices(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<DeviceFilter>,
+        #[graphql(default = 100)] limit: i64,
+    ) -> async_graphql::Result<Vec<Device>> {
+        let filter = filter.unwrap_or_default();
+        let devices = crate::db::queries::device_inventory(&state(ctx)?.db_pool).await?;
+        Ok(devices
+            .into_iter()
+            .filter(|device| filter.matches(device))
+            .take(limit.clamp(0, MAX_PAGE_SIZE) as usize)
+            .map(Device)
+            .collect())
+    }
+
+    async fn device(&self, ctx: &Context<'_>, mac_address: String) -> async_graphql::Result<Option<Device>> {
+        find_device(ctx, &mac_address).await
+    }
+
+    /// Address assignments across all devices, most recent first
+    async fn leases(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<LeaseFilter>,
+        #[graphql(default = 100)] limit: i64,
+    ) -> async_graphql::Result<Vec<Lease>> {
+        let filter = filter.unwrap_or_default();
+        let assignments = crate::db::ip_history::assignments(
+            &state(ctx)?.db_pool,
+            filter.ip_address.as_deref(),
+            filter.seen_since.as_deref(),
+            limit.clamp(0, MAX_PAGE_SIZE),
+        )
+        .await?;
+        Ok(assignments
+            .into_iter()
+            .map(|held| Lease {
+                mac_address: held.mac_address,
+                ip_address: held.assignment.ip_address,
+                source: held.assignment.source,
+                first_seen: held.assignment.first_seen,
+                last_seen: held.assignment.last_seen,
+            })
+            .collect())
+    }
+
+    async fn stats(&self, ctx: &Context<'_>) -> async_graphql::Result<Stats> {
+        let stats = state(ctx)?.get_stats();
+        Ok(Stats {
+            total_requests: stats.total_requests,
+            unique_macs: stats.unique_macs,
+            requests_per_minute: stats.requests_per_minute,
+            requests_per_minute_5m: stats.requests_per_minute_5m,
+            requests_per_minute_15m: stats.requests_per_minute_15m,
+            uptime_seconds: stats.uptime_seconds,
+            last_updated: stats.last_updated.to_rfc3339(),
+            request_types: counts(stats.request_types),
+            vendor_classes: counts(stats.vendor_classes),
+            vlans: counts(stats.vlans),
+            subnets: counts(stats.subnets),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(os_name: &str, tags: &[&str]) -> DeviceSummary {
+        DeviceSummary {
+            client_key: "aa:bb:cc:dd:ee:ff".to_string(),
+            client_id: None,
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            mac_count: 1,
+            last_ip: None,
+            os_name: Some(os_name.to_string()),
+            device_class: None,
+            vendor_class: Some("MSFT 5.0".to_string()),
+            confidence: None,
+            verified_at: None,
+            current_confidence: None,
+            vlan_id: Some(10),
+            requests: 3,
+            first_seen: "2025-01-01T00:00:00+00:00".to_string(),
+            last_seen: "2025-01-02T00:00:00+00:00".to_string(),
+            assigned_ip: None,
+            ip_changes: 0,
+            dns_name: None,
+            presence: None,
+            presence_state: None,
+            no_dhcp: false,
+            meta: Some(crate::db::device_meta::DeviceMeta {
+                tags: tags.iter().map(|tag| tag.to_string()).collect(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_filters() {
+        let filter = DeviceFilter {
+            os_name: Some("windows".to_string()),
+            tag: Some("lab".to_string()),
+            vlan_id: Some(10),
+            ..Default::default()
+        };
+        assert!(filter.matches(&device("Windows 11", &["lab"])));
+        assert!(!filter.matches(&device("Windows 11", &["office"])));
+        assert!(!filter.matches(&device("macOS", &["lab"])));
+
+        let requests = RequestFilter {
+            requested_ip: Some("10.0.0.300".to_string()),
+            ..Default::default()
+        };
+        assert!(requests.to_filters(1, 100, false).is_err());
+        let requests = RequestFilter {
+            since: Some("2025-01-01' OR 1=1 --".to_string()),
+            ..Default::default()
+        };
+        assert!(requests.to_filters(1, 100, false).is_err());
+        let requests = RequestFilter {
+            message_type: Some("ACK' OR '1'='1".to_string()),
+            ..Default::default()
+        };
+        assert!(requests.to_filters(1, 100, false).is_err());
+        let requests = RequestFilter {
+            message_type: Some("DISCOVER".to_string()),
+            since: Some("2025-01-01T02:00:00+02:00".to_string()),
+            ..Default::default()
+        };
+        let filters = requests.to_filters(1, 100, false).unwrap();
+        assert_eq!(filters.start_date.as_deref(), Some("2025-01-01T00:00:00+00:00"));
+        let filters = RequestFilter::default().to_filters(0, 10_000, true).unwrap();
+        assert_eq!((filters.page, filters.page_size, filters.sort_order.as_str()), (1, MAX_PAGE_SIZE, "ASC"));
+    }
+
+    #[tokio::test]
+    async fn test_schema_rejects_unknown_fields() {
+        assert!(SCHEMA.sdl().contains("type Device"));
+        let response = SCHEMA.execute("{ requests { items { nope } } }").await;
+        assert!(!response.errors.is_empty());
+    }
+}
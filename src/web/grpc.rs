@@ -0,0 +1,245 @@
+//! gRPC API (proto/dhcpmon.proto): live requests as a server stream, and the stored-log
+//! queries of /api/logs, for consumers that want typed messages rather than JSON.
+
+// tonic's Status is large, but it is what every RPC returns
+#![allow(clippy::result_large_err)]
+
+use super::state::{AppState, HistoryFilter};
+use crate::db::queries::{self, QueryFilters};
+use crate::dhcp::DhcpRequest;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status};
+
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("dhcpmon.v1");
+}
+
+use proto::dhcp_monitor_server::{DhcpMonitor, DhcpMonitorServer};
+
+/// Largest page QueryLogs returns, as for /api/logs
+const MAX_PAGE_SIZE: i64 = 500;
+
+impl From<&DhcpRequest> for proto::DhcpRequest {
+    fn from(request: &DhcpRequest) -> Self {
+        Self {
+            id: request.id,
+            timestamp: request.timestamp.clone(),
+            source_ip: request.source_ip.clone(),
+            source_port: request.source_port.into(),
+            mac_address: request.mac_address.clone(),
+            message_type: request.message_type.clone(),
+            xid: request.xid.clone(),
+            fingerprint: request.fingerprint.clone(),
+            vendor_class: request.vendor_class.clone(),
+            hostname: request.hostname.clone(),
+            client_fqdn: request.client_fqdn.as_ref().map(|fqdn| fqdn.name.clone()),
+            requested_ip: request.requested_ip.clone(),
+            server_id: request.server_id.clone(),
+            os_name: request.os_name.clone(),
+            device_class: request.device_class.clone(),
+            detection_method: request.detection_method.clone(),
+            confidence: request.confidence,
+            smb_dialect: request.smb_dialect.clone(),
+            smb_build: request.smb_build,
+            vlan_id: request.vlan_id.map(u32::from),
+            user_class: request.user_class.clone(),
+            client_arch: request.client_arch.map(u32::from),
+            client_ndi: request.client_ndi.clone(),
+            client_uuid: request.client_uuid.clone(),
+            client_id: request.client_id.as_ref().map(|id| id.key.clone()),
+            lint_issues: request.lint_issues.clone(),
+            repeats: request.repeats,
+            last_repeat_at: request.last_repeat_at.clone(),
+            site_id: request.site_id.clone(),
+            raw_options: request
+                .raw_options
+                .iter()
+                .map(|option| proto::DhcpOption {
+                    code: option.code.into(),
+                    data: option.data.clone(),
+                })
+                .collect(),
+            enterprise_numbers: request.vendor_options.iter().map(|block| block.enterprise).collect(),
+            subnet: request.subnet.clone(),
+        }
+    }
+}
+
+impl proto::LogsQuery {
+    // The same filters /api/logs builds from its query string
+    fn to_filters(&self) -> Result<QueryFilters, Status> {
+        let address = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(|ip| ip.parse().map_err(|_| Status::invalid_argument(format!("Invalid IPv4 address '{}'", ip))))
+                .transpose()
+        };
+        let vlan_id = self
+            .vlan_id
+            .map(u16::try_from)
+            .transpose()
+            .map_err(|_| Status::invalid_argument("vlan_id out of range"))?;
+        let bound = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(|time| {
+                    crate::db::queries::timestamp_bound(time)
+                        .ok_or_else(|| Status::invalid_argument(format!("Invalid RFC 3339 timestamp '{}'", time)))
+                })
+                .transpose()
+        };
+        if let Some(message_type) = &self.message_type {
+            if !crate::dhcp::MESSAGE_TYPES.contains(&message_type.as_str()) {
+                return Err(Status::invalid_argument(format!("Unknown message type '{}'", message_type)));
+            }
+        }
+        Ok(QueryFilters {
+            mac_address: self.mac_address.as_deref().map(crate::mac::canonical_fragment),
+            vendor_class: self.vendor_class.clone(),
+            message_type: self.message_type.clone(),
+            xid: self.xid.clone(),
+            start_date: bound(&self.start_date)?,
+            end_date: bound(&self.end_date)?,
+            vlan_id,
+            hostname: self.hostname.clone(),
+            requested_ip: address(&self.requested_ip)?,
+            server_id: address(&self.server_id)?,
+            site_id: self.site_id.clone(),
+            enterprise: self.enterprise,
+            subnet: self.subnet.clone(),
+            malformed: self.malformed,
+            sort_by: self.sort_by.clone().unwrap_or_else(|| "timestamp".to_string()),
+            sort_order: self.sort_order.clone().unwrap_or_else(|| "DESC".to_string()),
+            page: self.page.unwrap_or(1).max(1),
+            page_size: self.page_size.unwrap_or(100).clamp(1, MAX_PAGE_SIZE),
+        })
+    }
+}
+
+fn database_error(e: sqlx::Error) -> Status {
+    tracing::error!("gRPC database error: {}", e);
+    Status::internal("Database error")
+}
+
+pub struct GrpcService {
+    state: Arc<AppState>,
+}
+
+#[tonic::async_trait]
+impl DhcpMonitor for GrpcService {
+    type StreamRequestsStream = Pin<Box<dyn Stream<Item = Result<proto::DhcpRequest, Status>> + Send>>;
+
+    async fn stream_requests(
+        &self,
+        request: Request<proto::StreamFilter>,
+    ) -> Result<Response<Self::StreamRequestsStream>, Status> {
+        let filter = request.into_inner();
+        let filter = HistoryFilter {
+            mac: filter.mac_address,
+            vendor: filter.vendor_class,
+            msg_type: filter.message_type,
+            hostname: filter.hostname,
+            fingerprint: filter.fingerprint,
+            os_name: filter.os_name,
+            site: filter.site_id,
+            start: None,
+            end: None,
+        }
+        .normalized();
+
+        let capture = self.state.capture.clone();
+        let stream = BroadcastStream::new(self.state.broadcast_tx.subscribe()).filter_map(move |item| match item {
+            Ok(request) => filter.matches(&request).then(|| Ok(proto::DhcpRequest::from(request.as_ref()))),
+            // A slow consumer misses requests rather than holding up capture
+            Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                capture.record_lagged(missed);
+                tracing::warn!("gRPC stream fell behind, skipped {} requests", missed);
+                None
+            }
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn query_logs(&self, request: Request<proto::LogsQuery>) -> Result<Response<proto::LogsPage>, Status> {
+        let filters = request.into_inner().to_filters()?;
+        let pool = &self.state.db_pool;
+        let (requests, total) = tokio::try_join!(
+            queries::query_requests(pool, &filters),
+            queries::count_requests(pool, &filters),
+        )
+        .map_err(database_error)?;
+        Ok(Response::new(proto::LogsPage {
+            items: requests.iter().map(proto::DhcpRequest::from).collect(),
+            total,
+            page: filters.page,
+            page_size: filters.page_size,
+        }))
+    }
+
+    async fn count_logs(&self, request: Request<proto::LogsQuery>) -> Result<Response<proto::CountResponse>, Status> {
+        let filters = request.into_inner().to_filters()?;
+        let count = queries::count_requests(&self.state.db_pool, &filters)
+            .await
+            .map_err(database_error)?;
+        Ok(Response::new(proto::CountResponse { count }))
+    }
+}
+
+/// Serve the gRPC API on its own address; it has no authentication or TLS of its own
+pub async fn run_server(state: Arc<AppState>, addr: SocketAddr) -> anyhow::Result<()> {
+    tracing::info!("gRPC API listening on {}", addr);
+    tonic::transport::Server::builder()
+        .add_service(DhcpMonitorServer::new(GrpcService { state }))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_logs_query_filters() {
+        let query = proto::LogsQuery {
+            mac_address: Some("AA-BB-CC".to_string()),
+            server_id: Some("10.0.0.1".to_string()),
+            vlan_id: Some(20),
+            page_size: Some(10_000),
+            ..Default::default()
+        };
+        let filters = query.to_filters().unwrap();
+        assert_eq!(filters.mac_address.as_deref(), Some("aa:bb:cc"));
+        assert_eq!(filters.server_id, Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(filters.vlan_id, Some(20));
+        assert_eq!((filters.page, filters.page_size, filters.sort_order.as_str()), (1, MAX_PAGE_SIZE, "DESC"));
+
+        let bad = proto::LogsQuery { vlan_id: Some(70_000), ..Default::default() };
+        assert_eq!(bad.to_filters().unwrap_err().code(), tonic::Code::InvalidArgument);
+        let bad = proto::LogsQuery { start_date: Some("2025-01-01' OR 1=1 --".to_string()), ..Default::default() };
+        assert_eq!(bad.to_filters().unwrap_err().code(), tonic::Code::InvalidArgument);
+        let bad = proto::LogsQuery { message_type: Some("ACK' OR '1'='1".to_string()), ..Default::default() };
+        assert_eq!(bad.to_filters().unwrap_err().code(), tonic::Code::InvalidArgument);
+        let query = proto::LogsQuery {
+            message_type: Some("REQUEST".to_string()),
+            end_date: Some("2025-01-01T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(query.to_filters().unwrap().end_date.as_deref(), Some("2025-01-01T00:00:00+00:00"));
+
+        let request = DhcpRequest {
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            vlan_id: Some(20),
+            raw_options: vec![crate::dhcp::DhcpOption { code: 12, data: b"pc".to_vec() }],
+            ..Default::default()
+        };
+        let message = proto::DhcpRequest::from(&request);
+        assert_eq!(message.vlan_id, Some(20));
+        assert_eq!(message.raw_options[0].code, 12);
+    }
+}
@@ -1,38 +1,79 @@
-use super::state::AppState;
+use super::assets;
+use super::error::ApiError;
+use crate::hybrid_detection::DetectionResult;
+use super::health::{BroadcastStatus, ListenerStatus, Readiness};
+use super::proxy::with_base_href;
+use super::state::{AppState, HistoryFilter};
 use axum::{
-    extract::{Query, State, WebSocketUpgrade},
+    extract::{ConnectInfo, Path, Query, State, WebSocketUpgrade},
+    http::{HeaderMap, StatusCode, Uri},
     response::{Html, IntoResponse, Response},
     Json,
 };
-use axum::extract::ws::{WebSocket, Message};
-use futures::{sink::SinkExt, stream::StreamExt};
 use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
-// Serve embedded HTML
-pub async fn serve_index() -> Html<&'static str> {
-    Html(include_str!("../static/index.html"))
+// Serve an embedded asset; hashed URLs may be cached forever, plain ones must revalidate
+fn static_asset(uri: &Uri, hashed_path: &str, content_type: &'static str, body: &'static str) -> Response {
+    let cache_control = if uri.path() == hashed_path {
+        assets::CACHE_IMMUTABLE
+    } else {
+        assets::CACHE_REVALIDATE
+    };
+
+    (
+        [("content-type", content_type), ("cache-control", cache_control)],
+        body,
+    )
+        .into_response()
 }
 
-// Serve embedded JavaScript
-pub async fn serve_js() -> impl IntoResponse {
+// Serve embedded HTML
+pub async fn serve_index(State(state): State<Arc<AppState>>, headers: HeaderMap) -> impl IntoResponse {
     (
-        [("content-type", "application/javascript")],
-        include_str!("../static/app.js"),
+        [("cache-control", assets::CACHE_REVALIDATE)],
+        Html(with_base_href(&assets::INDEX_HTML, &state.proxy.external_base(&headers))),
     )
 }
 
+// Serve embedded JavaScript
+pub async fn serve_js(uri: Uri) -> Response {
+    static_asset(&uri, assets::APP_JS_PATH, "application/javascript", include_str!("../static/app.js"))
+}
+
 // Serve embedded CSS
-pub async fn serve_css() -> impl IntoResponse {
-    (
-        [("content-type", "text/css")],
-        include_str!("../static/styles.css"),
+pub async fn serve_css(uri: Uri) -> Response {
+    static_asset(&uri, assets::STYLES_CSS_PATH, "text/css", include_str!("../static/styles.css"))
+}
+
+// UI bundle and server version, so clients can detect upgrades
+#[derive(serde::Serialize, ToSchema)]
+pub struct VersionResponse {
+    version: &'static str,
+    ui_version: &'static str,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/version",
+    tag = "system",
+    responses(
+        (status = 200, body = VersionResponse),
     )
+)]
+pub async fn get_version() -> Json<VersionResponse> {
+    Json(VersionResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        ui_version: assets::UI_VERSION,
+    })
 }
 
 // Get recent history
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct HistoryQuery {
     #[serde(default = "default_limit")]
     limit: usize,
@@ -42,6 +83,15 @@ fn default_limit() -> usize {
     100
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/history",
+    tag = "requests",
+    params(HistoryQuery),
+    responses(
+        (status = 200, description = "Most recent requests held in memory", body = Vec<crate::dhcp::DhcpRequest>),
+    )
+)]
 pub async fn get_history(
     State(state): State<Arc<AppState>>,
     Query(params): Query<HistoryQuery>,
@@ -52,263 +102,2234 @@ pub async fn get_history(
     Json(owned)
 }
 
+#[derive(serde::Serialize, ToSchema)]
+pub struct HealthResponse {
+    pub status: &'static str,
+    #[schema(value_type = Object)]
+    pub database: crate::db::health::DbHealth,
+    #[schema(value_type = Object)]
+    pub listener: ListenerStatus,
+    #[schema(value_type = Object)]
+    pub broadcast: BroadcastStatus,
+    /// Active probes running and waiting for a slot
+    #[schema(value_type = Object)]
+    pub probes: crate::probe_scheduler::SchedulerStatus,
+}
+
+// Liveness/health check; 503 while the database is degraded or the listener has stopped
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "system",
+    responses(
+        (status = 200, description = "Database writable and listener running", body = HealthResponse),
+        (status = 503, description = "Degraded: requests are buffered in memory or not received", body = HealthResponse),
+    )
+)]
+pub async fn healthz(State(state): State<Arc<AppState>>) -> Response {
+    let database = state.db_guard.health().await;
+    let listener = state.listener.status();
+    let degraded = database.condition != crate::db::health::DbCondition::Ok || listener.failed;
+
+    let status_code = if degraded {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    let status = if degraded { "degraded" } else { "ok" };
+
+    let broadcast = state.broadcast_status();
+    let probes = state.hybrid_detector.probe_status();
+    (status_code, Json(HealthResponse { status, database, listener, broadcast, probes })).into_response()
+}
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct ReadyResponse {
+    pub ready: bool,
+    /// Why the monitor is not ready (empty when ready)
+    pub reasons: Vec<String>,
+    #[schema(value_type = Object)]
+    pub database: crate::db::health::DbHealth,
+    #[schema(value_type = Object)]
+    pub listener: ListenerStatus,
+    #[schema(value_type = Object)]
+    pub broadcast: BroadcastStatus,
+}
+
+// Readiness check; 503 until the listener is bound and while the database is degraded
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "system",
+    responses(
+        (status = 200, description = "Receiving and storing requests", body = ReadyResponse),
+        (status = 503, description = "Starting up or degraded, with the reasons", body = ReadyResponse),
+    )
+)]
+pub async fn readyz(State(state): State<Arc<AppState>>) -> Response {
+    let database = state.db_guard.health().await;
+    let listener = state.listener.status();
+    let readiness = Readiness::evaluate(&listener, &database);
+
+    let status_code = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let response = ReadyResponse {
+        ready: readiness.ready,
+        reasons: readiness.reasons,
+        database,
+        listener,
+        broadcast: state.broadcast_status(),
+    };
+    (status_code, Json(response)).into_response()
+}
+
+// Leader election state of this node (404 when not clustered)
+#[utoipa::path(
+    get,
+    path = "/api/cluster",
+    tag = "system",
+    responses(
+        (status = 200, description = "Leader election state of this node", body = Object),
+        (status = 404, description = "Cluster mode is not enabled", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn get_cluster_status(State(state): State<Arc<AppState>>) -> Response {
+    let Some(cluster) = &state.cluster else {
+        return ApiError::new(StatusCode::NOT_FOUND, "Cluster mode is not enabled").into_response();
+    };
+
+    match cluster.status().await {
+        Ok(status) => Json(status).into_response(),
+        Err(e) => {
+            error!("Failed to read cluster status: {}", e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read cluster status").into_response()
+        }
+    }
+}
+
 // Get statistics
+#[utoipa::path(
+    get,
+    path = "/api/stats",
+    tag = "stats",
+    responses(
+        (status = 200, description = "Counters since startup", body = Object),
+    )
+)]
 pub async fn get_statistics(
     State(state): State<Arc<AppState>>,
-) -> Json<super::state::Statistics> {
-    let stats = state.get_stats().await;
+) -> Json<super::stats::Statistics> {
+    let stats = state.get_stats();
     Json(stats)
 }
 
-// Search requests
-#[derive(Deserialize)]
-pub struct SearchQuery {
-    mac: Option<String>,
-    vendor: Option<String>,
-    msg_type: Option<String>,
+// Counters in the Prometheus text format
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "stats",
+    responses(
+        (status = 200, description = "Prometheus text exposition (version 0.0.4)", content_type = "text/plain", body = String),
+    )
+)]
+pub async fn get_metrics(State(state): State<Arc<AppState>>) -> Response {
+    (
+        [("content-type", "text/plain; version=0.0.4; charset=utf-8")],
+        super::metrics::render(&state.get_stats()),
+    )
+        .into_response()
 }
 
-pub async fn search_requests(
-    State(state): State<Arc<AppState>>,
-    Query(params): Query<SearchQuery>,
-) -> Json<Vec<crate::dhcp::DhcpRequest>> {
-    let results = state.search_history(
-        params.mac.as_deref(),
-        params.vendor.as_deref(),
-        params.msg_type.as_deref(),
-    ).await;
-    // Convert Arc to owned values
-    let owned: Vec<_> = results.iter().map(|r| (**r).clone()).collect();
-    Json(owned)
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OptionStatsQuery {
+    /// Window length in hours back from now (default: 24, 0 = all time)
+    hours: Option<i64>,
+    /// Explicit window bounds (RFC 3339), override `hours`
+    since: Option<String>,
+    until: Option<String>,
+    /// Vendors listed per option (default: 10)
+    vendors: Option<usize>,
 }
 
-// WebSocket handler
-pub async fn websocket_handler(
-    ws: WebSocketUpgrade,
+#[derive(serde::Serialize)]
+pub struct OptionStatsResponse {
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub total_requests: i64,
+    pub options: Vec<crate::db::queries::OptionUsage>,
+}
+
+// Start of a window reaching `hours` back from now; None (all time) when not positive
+fn hours_ago(hours: i64) -> Result<Option<String>, ApiError> {
+    if hours <= 0 {
+        return Ok(None);
+    }
+    chrono::Duration::try_hours(hours)
+        .and_then(|window| chrono::Utc::now().checked_sub_signed(window))
+        .map(|since| Some(since.to_rfc3339()))
+        .ok_or_else(|| ApiError::new(StatusCode::BAD_REQUEST, format!("hours is too large: {}", hours)))
+}
+
+// How often each DHCP option appears in requests over a window, and who sends it
+#[utoipa::path(
+    get,
+    path = "/api/stats/options",
+    tag = "stats",
+    params(OptionStatsQuery),
+    responses(
+        (status = 200, body = Object),
+        (status = 400, description = "Invalid hours, since or until", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn get_option_statistics(
     State(state): State<Arc<AppState>>,
+    Query(params): Query<OptionStatsQuery>,
 ) -> Response {
-    ws.on_upgrade(|socket| handle_websocket(socket, state))
+    // Bounds are compared as text, so they take the stored timestamps' UTC form
+    let (since, until) = match (
+        parse_bound("since", params.since.as_deref()),
+        parse_bound("until", params.until.as_deref()),
+    ) {
+        (Ok(since), Ok(until)) => (since.map(|since| since.to_rfc3339()), until.map(|until| until.to_rfc3339())),
+        (Err(e), _) | (_, Err(e)) => return e.into_response(),
+    };
+    let since = match since {
+        Some(since) => Some(since),
+        None => match hours_ago(params.hours.unwrap_or(24)) {
+            Ok(since) => since,
+            Err(e) => return e.into_response(),
+        },
+    };
+
+    match crate::db::queries::option_statistics(
+        &state.db_pool,
+        since.as_deref(),
+        until.as_deref(),
+        params.vendors.unwrap_or(10),
+    )
+    .await
+    {
+        Ok((total_requests, options)) => Json(OptionStatsResponse {
+            since,
+            until,
+            total_requests,
+            options,
+        })
+        .into_response(),
+        Err(e) => {
+            error!("Failed to compute option statistics: {}", e);
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to compute option statistics",
+            )
+            .into_response()
+        }
+    }
 }
 
-async fn handle_websocket(socket: WebSocket, state: Arc<AppState>) {
-    let (mut sender, mut receiver) = socket.split();
+/// Most buckets one timeseries request may produce
+const MAX_TIMESERIES_BUCKETS: i64 = 10_000;
 
-    // Subscribe to broadcast channel
-    let mut rx = state.broadcast_tx.subscribe();
+/// Longest span the stats endpoints look back over, ten years in seconds
+const MAX_WINDOW_SECS: i64 = 10 * 366 * 86400;
 
-    info!("WebSocket client connected");
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TimeseriesQuery {
+    /// Bucket width, e.g. "30s", "5m", "1h" (default: 5m)
+    interval: Option<String>,
+    /// How far back from now, e.g. "24h", "7d" (default: 24h)
+    window: Option<String>,
+    /// Break counts down by "message_type" or "vendor"
+    split: Option<String>,
+}
 
-    // Send initial history on connection
-    let history = state.get_history(50).await;
-    for request in history {
-        let json = match serde_json::to_string(&*request) {
-            Ok(j) => j,
-            Err(e) => {
-                error!("Failed to serialize request: {}", e);
-                continue;
-            }
-        };
+#[derive(serde::Serialize)]
+pub struct TimeseriesResponse {
+    pub interval_secs: i64,
+    pub since: String,
+    pub until: String,
+    pub split: Option<String>,
+    pub buckets: Vec<crate::db::queries::TimeBucket>,
+}
 
-        if sender.send(Message::Text(json)).await.is_err() {
-            warn!("Failed to send initial history to client");
-            return;
+/// Parse a span like "90s", "5m", "24h" or "7d" into seconds, up to MAX_WINDOW_SECS
+fn parse_span(span: &str) -> Option<i64> {
+    let span = span.trim();
+    let (number, unit) = span.split_at(span.find(|c: char| !c.is_ascii_digit())?);
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return None,
+    };
+    number
+        .parse::<i64>()
+        .ok()
+        .filter(|n| *n > 0)?
+        .checked_mul(multiplier)
+        .filter(|secs| *secs <= MAX_WINDOW_SECS)
+}
+
+// Request counts over time in fixed buckets, for activity graphs
+#[utoipa::path(
+    get,
+    path = "/api/stats/timeseries",
+    tag = "stats",
+    params(TimeseriesQuery),
+    responses(
+        (status = 200, body = Object),
+        (status = 400, description = "Invalid interval, window or split", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn get_stats_timeseries(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<TimeseriesQuery>,
+) -> Response {
+    let interval = params.interval.as_deref().unwrap_or("5m");
+    let window = params.window.as_deref().unwrap_or("24h");
+    let (Some(interval_secs), Some(window_secs)) = (parse_span(interval), parse_span(window)) else {
+        return ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "interval and window must look like 30s, 5m, 1h or 7d, and span at most ten years",
+        )
+        .into_response();
+    };
+    if window_secs / interval_secs > MAX_TIMESERIES_BUCKETS {
+        return ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("At most {} buckets per request; use a larger interval", MAX_TIMESERIES_BUCKETS),
+        )
+        .into_response();
+    }
+    let split = match params.split.as_deref().map(crate::db::queries::TimeseriesSplit::from_name) {
+        None => None,
+        Some(Some(split)) => Some(split),
+        Some(None) => {
+            return ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "split must be message_type or vendor",
+            )
+            .into_response();
+        }
+    };
+
+    let until = chrono::Utc::now();
+    let since = until - chrono::Duration::seconds(window_secs);
+    match crate::db::queries::request_timeseries(&state.db_pool, since, until, interval_secs, split).await {
+        Ok(buckets) => Json(TimeseriesResponse {
+            interval_secs,
+            since: since.to_rfc3339(),
+            until: until.to_rfc3339(),
+            split: params.split,
+            buckets,
+        })
+        .into_response(),
+        Err(e) => {
+            error!("Failed to compute request timeseries: {}", e);
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to compute request timeseries",
+            )
+            .into_response()
         }
     }
+}
 
-    // Spawn task to handle incoming messages (ping/pong)
-    let mut recv_task = tokio::spawn(async move {
-        while let Some(Ok(msg)) = receiver.next().await {
-            // Handle client messages (e.g., ping)
-            if matches!(msg, Message::Close(_)) {
-                break;
-            }
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LintReportQuery {
+    /// Window length in hours back from now (default: 24, 0 = all time)
+    hours: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+pub struct LintReportResponse {
+    pub since: Option<String>,
+    /// Vendors ordered worst quality first
+    pub vendors: Vec<crate::db::queries::VendorQuality>,
+}
+
+// Per-vendor client quality from packet lint results
+#[utoipa::path(
+    get,
+    path = "/api/lint/report",
+    tag = "stats",
+    params(LintReportQuery),
+    responses(
+        (status = 200, body = Object),
+        (status = 400, description = "Invalid hours", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn get_lint_report(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<LintReportQuery>,
+) -> Response {
+    let since = match hours_ago(params.hours.unwrap_or(24)) {
+        Ok(since) => since,
+        Err(e) => return e.into_response(),
+    };
+
+    match crate::db::queries::lint_report(&state.db_pool, since.as_deref()).await {
+        Ok(vendors) => Json(LintReportResponse { since, vendors }).into_response(),
+        Err(e) => {
+            error!("Failed to build lint report: {}", e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to build lint report").into_response()
         }
-    });
+    }
+}
 
-    // Spawn task to send broadcast updates to client
-    let mut send_task = tokio::spawn(async move {
-        while let Ok(request) = rx.recv().await {
-            let json = match serde_json::to_string(&*request) {
-                Ok(j) => j,
-                Err(e) => {
-                    error!("Failed to serialize request: {}", e);
-                    continue;
-                }
-            };
-
-            if sender.send(Message::Text(json)).await.is_err() {
-                // Client disconnected
-                break;
-            }
+// A stored request as it is now, including detection filled in by later probes
+#[utoipa::path(
+    get,
+    path = "/api/requests/{id}",
+    tag = "requests",
+    params(("id" = i64, Path, description = "Stored request id")),
+    responses(
+        (status = 200, description = "The stored request", body = crate::dhcp::DhcpRequest),
+        (status = 404, description = "No such request", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn get_request(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Response {
+    match crate::db::queries::request_by_id(&state.db_pool, id).await {
+        Ok(Some(request)) => Json(request).into_response(),
+        Ok(None) => ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("No request with id {}", id),
+        )
+        .into_response(),
+        Err(e) => {
+            error!("Failed to load request {}: {}", id, e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to load request").into_response()
         }
-    });
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct DecodedRequestResponse {
+    id: i64,
+    timestamp: String,
+    mac_address: String,
+    message_type: String,
+    options: Vec<crate::options_catalog::DecodedOption>,
+}
 
-    // Wait for either task to finish
-    tokio::select! {
-        _ = (&mut send_task) => {
-            recv_task.abort();
+// A stored request's options with names and human-readable values
+#[utoipa::path(
+    get,
+    path = "/api/requests/{id}/decoded",
+    tag = "requests",
+    params(("id" = i64, Path, description = "Stored request id")),
+    responses(
+        (status = 200, description = "Options decoded by the catalog", body = Object),
+        (status = 404, description = "No such request", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn get_decoded_request(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Response {
+    match crate::db::queries::request_by_id(&state.db_pool, id).await {
+        Ok(Some(request)) => Json(DecodedRequestResponse {
+            id,
+            options: request.raw_options.iter().map(crate::options_catalog::decode).collect(),
+            timestamp: request.timestamp,
+            mac_address: request.mac_address,
+            message_type: request.message_type,
+        })
+        .into_response(),
+        Ok(None) => ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("No request with id {}", id),
+        )
+        .into_response(),
+        Err(e) => {
+            error!("Failed to load request {}: {}", id, e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to load request").into_response()
         }
-        _ = (&mut recv_task) => {
-            send_task.abort();
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct HexdumpResponse {
+    id: i64,
+    timestamp: String,
+    mac_address: String,
+    message_type: String,
+    #[serde(flatten)]
+    dump: crate::hexdump::PacketDump,
+}
+
+// A stored request's raw packet as hex, with each header field and option annotated
+#[utoipa::path(
+    get,
+    path = "/api/requests/{id}/hexdump",
+    tag = "requests",
+    params(("id" = i64, Path, description = "Stored request id")),
+    responses(
+        (status = 200, description = "Hex dump and per-field decode", body = Object),
+        (status = 404, description = "No such request, or its packet bytes weren't stored", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn get_request_hexdump(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Response {
+    match crate::db::queries::request_by_id(&state.db_pool, id).await {
+        Ok(Some(request)) => match &request.raw_packet {
+            Some(packet) => Json(HexdumpResponse {
+                id,
+                dump: crate::hexdump::dump(packet),
+                timestamp: request.timestamp,
+                mac_address: request.mac_address,
+                message_type: request.message_type,
+            })
+            .into_response(),
+            None => ApiError::new(
+                StatusCode::NOT_FOUND,
+                format!("Request {} has no stored packet bytes", id),
+            )
+            .into_response(),
+        },
+        Ok(None) => ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("No request with id {}", id),
+        )
+        .into_response(),
+        Err(e) => {
+            error!("Failed to load request {}: {}", id, e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to load request").into_response()
         }
     }
+}
+
+// Search requests
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    /// MAC address substring, any case, ':' or '-' separated
+    mac: Option<String>,
+    vendor: Option<String>,
+    msg_type: Option<String>,
+    hostname: Option<String>,
+    /// Option 55 fingerprint substring, e.g. "1,3,6"
+    fingerprint: Option<String>,
+    os_name: Option<String>,
+    /// Agent site, or "local" for requests captured by this instance
+    site_id: Option<String>,
+    /// RFC 3339 bounds, inclusive
+    start_date: Option<String>,
+    end_date: Option<String>,
+}
 
-    info!("WebSocket client disconnected");
+// Parse an optional RFC 3339 query bound, naming the parameter on failure
+fn parse_bound(name: &str, value: Option<&str>) -> Result<Option<chrono::DateTime<chrono::Utc>>, ApiError> {
+    value
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            chrono::DateTime::parse_from_rfc3339(value)
+                .map(|parsed| parsed.with_timezone(&chrono::Utc))
+                .map_err(|_| ApiError::new(StatusCode::BAD_REQUEST, format!("Invalid {}: {}", name, value)))
+        })
+        .transpose()
 }
 
-// Serve historical logs page
-pub async fn serve_logs_page() -> Html<&'static str> {
-    Html(include_str!("../static/logs.html"))
+#[utoipa::path(
+    get,
+    path = "/api/search",
+    tag = "requests",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Matching requests held in memory", body = Vec<crate::dhcp::DhcpRequest>),
+        (status = 400, description = "Invalid date", body = ApiError),
+    )
+)]
+pub async fn search_requests(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<SearchQuery>,
+) -> Response {
+    let (start, end) = match (
+        parse_bound("start_date", params.start_date.as_deref()),
+        parse_bound("end_date", params.end_date.as_deref()),
+    ) {
+        (Ok(start), Ok(end)) => (start, end),
+        (Err(e), _) | (_, Err(e)) => return e.into_response(),
+    };
+    let filter = HistoryFilter {
+        mac: params.mac,
+        vendor: params.vendor,
+        msg_type: params.msg_type,
+        hostname: params.hostname,
+        fingerprint: params.fingerprint,
+        os_name: params.os_name,
+        site: params.site_id,
+        start,
+        end,
+    };
+    let results = state.search_history(&filter).await;
+    // Convert Arc to owned values
+    let owned: Vec<_> = results.iter().map(|r| (**r).clone()).collect();
+    Json(owned).into_response()
 }
 
-// Serve logs JavaScript
-pub async fn serve_logs_js() -> impl IntoResponse {
+// WebSocket handler
+pub async fn websocket_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+) -> Response {
+    let client = super::rate_limit::client_ip(&state.proxy, &headers, connect_info);
+    let Some(permit) = state.rate_limiter.try_websocket(client) else {
+        warn!("Refusing WebSocket connection from {}: connection limit reached", client);
+        return ApiError::new(StatusCode::TOO_MANY_REQUESTS, "Too many WebSocket connections").into_response();
+    };
+    ws.on_upgrade(|socket| async move {
+        super::websocket::handle_websocket(socket, state).await;
+        drop(permit);
+    })
+}
+
+// Serve historical logs page
+pub async fn serve_logs_page(State(state): State<Arc<AppState>>, headers: HeaderMap) -> impl IntoResponse {
     (
-        [("content-type", "application/javascript")],
-        include_str!("../static/logs.js"),
+        [("cache-control", assets::CACHE_REVALIDATE)],
+        Html(with_base_href(&assets::LOGS_HTML, &state.proxy.external_base(&headers))),
     )
 }
 
+// Serve logs JavaScript
+pub async fn serve_logs_js(uri: Uri) -> Response {
+    static_asset(&uri, assets::LOGS_JS_PATH, "application/javascript", include_str!("../static/logs.js"))
+}
+
 // Serve logs CSS
-pub async fn serve_logs_css() -> impl IntoResponse {
+pub async fn serve_logs_css(uri: Uri) -> Response {
+    static_asset(&uri, assets::LOGS_CSS_PATH, "text/css", include_str!("../static/logs.css"))
+}
+
+// Serve device mappings page
+pub async fn serve_mappings_page(State(state): State<Arc<AppState>>, headers: HeaderMap) -> impl IntoResponse {
     (
-        [("content-type", "text/css")],
-        include_str!("../static/logs.css"),
+        [("cache-control", assets::CACHE_REVALIDATE)],
+        Html(with_base_href(&assets::MAPPINGS_HTML, &state.proxy.external_base(&headers))),
     )
 }
 
-// Query parameters for logs
-#[derive(Deserialize)]
-pub struct LogsQuery {
+// Serve device mappings JavaScript
+pub async fn serve_mappings_js(uri: Uri) -> Response {
+    static_asset(&uri, assets::MAPPINGS_JS_PATH, "application/javascript", include_str!("../static/mappings.js"))
+}
+
+// Filters shared by the log listing, count and export
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RequestFilterQuery {
     mac_address: Option<String>,
     vendor_class: Option<String>,
     message_type: Option<String>,
     xid: Option<String>,
     start_date: Option<String>,
     end_date: Option<String>,
+    vlan_id: Option<u16>,
+    hostname: Option<String>,
+    #[param(value_type = Option<String>)]
+    requested_ip: Option<std::net::Ipv4Addr>,
+    #[param(value_type = Option<String>)]
+    server_id: Option<std::net::Ipv4Addr>,
+    /// Agent site, or "local" for requests captured by this instance
+    site_id: Option<String>,
+    /// Option 125 enterprise number, e.g. 4491 for CableLabs devices
+    enterprise: Option<u32>,
+    /// Subnet the request was attributed to, e.g. "10.1.20.0/24"
+    subnet: Option<String>,
+    /// true for packets with conformance warnings (lint_issues), false for clean ones
+    malformed: Option<bool>,
+}
+
+impl RequestFilterQuery {
+    /// Query filters with the default sort and paging, for callers to override
+    fn into_filters(self) -> crate::db::queries::QueryFilters {
+        crate::db::queries::QueryFilters {
+            mac_address: self.mac_address.as_deref().map(crate::mac::canonical_fragment),
+            vendor_class: self.vendor_class,
+            message_type: self.message_type,
+            xid: self.xid,
+            start_date: self.start_date,
+            end_date: self.end_date,
+            vlan_id: self.vlan_id,
+            hostname: self.hostname,
+            requested_ip: self.requested_ip,
+            server_id: self.server_id,
+            site_id: self.site_id,
+            enterprise: self.enterprise,
+            subnet: self.subnet,
+            malformed: self.malformed,
+            ..Default::default()
+        }
+    }
+}
+
+// Sorting and paging for the log listing
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LogsQuery {
     sort_by: Option<String>,
     sort_order: Option<String>,
     page: Option<i64>,
     page_size: Option<i64>,
+    /// Wrap the page as {items, total, page, page_size} instead of a bare array
+    #[serde(default)]
+    envelope: bool,
 }
 
 // Response for count
-#[derive(serde::Serialize)]
+#[derive(serde::Serialize, ToSchema)]
 pub struct CountResponse {
     count: i64,
 }
 
+// A logged request with the device's name, location, owner and tags
+#[derive(serde::Serialize, ToSchema)]
+pub struct LogEntry {
+    #[serde(flatten)]
+    request: crate::dhcp::DhcpRequest,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meta: Option<crate::db::device_meta::DeviceMeta>,
+}
+
+// One page of logs with the total matching the filters
+#[derive(serde::Serialize, ToSchema)]
+pub struct LogsPage {
+    items: Vec<LogEntry>,
+    total: i64,
+    page: i64,
+    page_size: i64,
+}
+
 // Get logs with filters and pagination
+#[utoipa::path(
+    get,
+    path = "/api/logs",
+    tag = "logs",
+    params(RequestFilterQuery, LogsQuery),
+    responses(
+        (status = 200, description = "One page of stored requests; a LogsPage with envelope=true", body = Vec<LogEntry>),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
 pub async fn get_logs(
     State(state): State<Arc<AppState>>,
+    Query(filter): Query<RequestFilterQuery>,
     Query(params): Query<LogsQuery>,
-) -> Json<Vec<crate::dhcp::DhcpRequest>> {
+) -> Response {
     let filters = crate::db::queries::QueryFilters {
-        mac_address: params.mac_address,
-        vendor_class: params.vendor_class,
-        message_type: params.message_type,
-        xid: params.xid,
-        start_date: params.start_date,
-        end_date: params.end_date,
         sort_by: params.sort_by.unwrap_or_else(|| "timestamp".to_string()),
         sort_order: params.sort_order.unwrap_or_else(|| "DESC".to_string()),
         page: params.page.unwrap_or(1),
         page_size: params.page_size.unwrap_or(100).min(500),
+        ..filter.into_filters()
     };
 
-    match crate::db::queries::query_requests(&state.db_pool, &filters).await {
-        Ok(requests) => Json(requests),
+    // The enveloped shape carries the total, so clients need no separate /api/logs/count call
+    let total = async {
+        if params.envelope {
+            crate::db::queries::count_requests(&state.db_pool, &filters).await.map(Some)
+        } else {
+            Ok(None)
+        }
+    };
+    let (requests, total) = match tokio::try_join!(crate::db::queries::query_requests(&state.db_pool, &filters), total) {
+        Ok(found) => found,
         Err(e) => {
             error!("Database query error: {}", e);
-            Json(vec![])
+            return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to query logs").into_response();
         }
+    };
+    let meta = crate::db::device_meta::all_meta(&state.db_pool).await.unwrap_or_else(|e| {
+        warn!("Failed to load device metadata: {}", e);
+        Default::default()
+    });
+
+    let entries: Vec<LogEntry> = requests
+        .into_iter()
+        .map(|request| LogEntry {
+            meta: meta.get(&request.mac_address).cloned(),
+            request,
+        })
+        .collect();
+    match total {
+        Some(total) => Json(LogsPage {
+            items: entries,
+            total,
+            page: filters.page,
+            page_size: filters.page_size,
+        })
+        .into_response(),
+        None => Json(entries).into_response(),
     }
 }
 
 // Get count of logs matching filters
+#[utoipa::path(
+    get,
+    path = "/api/logs/count",
+    tag = "logs",
+    params(RequestFilterQuery),
+    responses(
+        (status = 200, description = "Requests matching the filters", body = CountResponse),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
 pub async fn get_logs_count(
     State(state): State<Arc<AppState>>,
-    Query(params): Query<LogsQuery>,
-) -> Json<CountResponse> {
-    let filters = crate::db::queries::QueryFilters {
-        mac_address: params.mac_address,
-        vendor_class: params.vendor_class,
-        message_type: params.message_type,
-        xid: params.xid,
-        start_date: params.start_date,
-        end_date: params.end_date,
-        sort_by: "timestamp".to_string(),
-        sort_order: "DESC".to_string(),
-        page: 1,
-        page_size: 1,
-    };
-
-    let count = crate::db::queries::count_requests(&state.db_pool, &filters)
-        .await
-        .unwrap_or(0);
+    Query(filter): Query<RequestFilterQuery>,
+) -> Response {
+    let filters = filter.into_filters();
 
-    Json(CountResponse { count })
+    match crate::db::queries::count_requests(&state.db_pool, &filters).await {
+        Ok(count) => Json(CountResponse { count }).into_response(),
+        Err(e) => {
+            error!("Database count error: {}", e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to count logs").into_response()
+        }
+    }
 }
 
 // Export logs
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ExportQuery {
     format: String,
-    mac_address: Option<String>,
-    vendor_class: Option<String>,
-    message_type: Option<String>,
-    xid: Option<String>,
-    start_date: Option<String>,
-    end_date: Option<String>,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/logs/export",
+    tag = "logs",
+    params(ExportQuery, RequestFilterQuery),
+    responses(
+        (status = 200, description = "Attachment in the requested format (csv, json, ndjson, xlsx or pcap)"),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
 pub async fn export_logs(
     State(state): State<Arc<AppState>>,
     Query(params): Query<ExportQuery>,
+    Query(filter): Query<RequestFilterQuery>,
 ) -> impl IntoResponse {
     let filters = crate::db::queries::QueryFilters {
-        mac_address: params.mac_address,
-        vendor_class: params.vendor_class,
-        message_type: params.message_type,
-        xid: params.xid,
-        start_date: params.start_date,
-        end_date: params.end_date,
-        sort_by: "timestamp".to_string(),
-        sort_order: "DESC".to_string(),
+        // Oldest first in a pcap, as a capture would have recorded them
+        sort_order: if params.format == "pcap" { "ASC" } else { "DESC" }.to_string(),
         page: 1,
         page_size: 100000,
+        ..filter.into_filters()
     };
 
-    match crate::db::queries::export_requests(&state.db_pool, &filters, &params.format).await {
-        Ok(data) => {
-            let content_type = if params.format == "csv" {
-                "text/csv"
-            } else {
-                "application/json"
-            };
+    if params.format == "xlsx" {
+        return match crate::db::queries::query_requests(&state.db_pool, &filters).await {
+            Ok(requests) => xlsx_response("dhcp_logs", crate::db::xlsx::logs_workbook(&requests)),
+            Err(e) => {
+                error!("Export error: {}", e);
+                ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Export failed").into_response()
+            }
+        };
+    }
 
-            let filename = format!(
-                "dhcp_logs_{}.{}",
-                chrono::Utc::now().format("%Y%m%d_%H%M%S"),
-                params.format
-            );
+    let format = match params.format.as_str() {
+        "csv" => "csv",
+        "ndjson" => "ndjson",
+        "pcap" => "pcap",
+        _ => "json",
+    };
+    let chunks = crate::db::queries::export_requests(state.db_pool.clone(), &filters, format);
+    let body = futures::stream::unfold(chunks, |mut chunks| async move {
+        let chunk = chunks.recv().await?;
+        if let Err(e) = &chunk {
+            error!("Export error: {}", e);
+        }
+        Some((chunk, chunks))
+    });
+
+    let content_type = match format {
+        "csv" => "text/csv",
+        "ndjson" => "application/x-ndjson",
+        "pcap" => crate::pcap::CONTENT_TYPE,
+        _ => "application/json",
+    };
+    let filename = format!("dhcp_logs_{}.{}", chrono::Utc::now().format("%Y%m%d_%H%M%S"), format);
+    (
+        [
+            ("content-type", content_type),
+            ("content-disposition", &format!("attachment; filename=\"{}\"", filename)),
+        ],
+        axum::body::Body::from_stream(body),
+    )
+        .into_response()
+}
 
+// Serve a generated workbook as a timestamped download
+fn xlsx_response(prefix: &str, workbook: Result<Vec<u8>, rust_xlsxwriter::XlsxError>) -> Response {
+    match workbook {
+        Ok(bytes) => {
+            let filename = format!("{}_{}.xlsx", prefix, chrono::Utc::now().format("%Y%m%d_%H%M%S"));
             (
                 [
-                    ("content-type", content_type),
+                    ("content-type", crate::db::xlsx::CONTENT_TYPE),
                     ("content-disposition", &format!("attachment; filename=\"{}\"", filename)),
                 ],
-                data,
+                bytes,
             )
                 .into_response()
         }
         Err(e) => {
-            error!("Export error: {}", e);
-            (
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-                "Export failed",
+            error!("Failed to build spreadsheet: {}", e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Export failed").into_response()
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DevicesQuery {
+    /// "json" (default) or "xlsx"
+    #[serde(default)]
+    format: Option<String>,
+}
+
+// Device inventory: one row per MAC, described by its latest request
+#[utoipa::path(
+    get,
+    path = "/api/devices",
+    tag = "devices",
+    params(DevicesQuery),
+    responses(
+        (status = 200, description = "Device inventory as JSON or an xlsx attachment", body = Object),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn get_devices(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<DevicesQuery>,
+) -> Response {
+    let mut devices = match crate::db::queries::device_inventory(&state.db_pool).await {
+        Ok(devices) => devices,
+        Err(e) => {
+            error!("Failed to load device inventory: {}", e);
+            return ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to load device inventory",
             )
-                .into_response()
+            .into_response();
         }
+    };
+    if let Some(neighbors) = &state.neighbors {
+        neighbors.annotate(&mut devices);
+    }
+    if let Some(presence) = &state.presence {
+        presence.annotate(&mut devices);
+    }
+    if let Some(reverify) = &state.reverify {
+        reverify.annotate(&mut devices, chrono::Utc::now());
+    }
+
+    match params.format.as_deref() {
+        Some("xlsx") => xlsx_response("dhcp_devices", crate::db::xlsx::devices_workbook(&devices)),
+        _ => Json(devices).into_response(),
+    }
+}
+
+// Media type of a request body, without parameters such as charset
+fn content_type(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(str::trim)
+}
+
+/// Body types an import accepts; none can be posted cross-site without a CORS preflight
+const IMPORT_CONTENT_TYPES: [&str; 3] = ["application/json", "application/x-ndjson", "text/csv"];
+
+// Import historical logs (request.json, or a previous CSV/JSON export)
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+    format: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/logs/import",
+    tag = "logs",
+    params(ImportQuery),
+    request_body(content = String, description = "request.json lines, a JSON export or a CSV export (admin only)", content_type = "application/x-ndjson"),
+    responses(
+        (status = 200, description = "Import summary", body = Object),
+        (status = 400, description = "Unsupported format or unparseable data", body = ApiError),
+        (status = 415, description = "A body sent as anything but application/json, application/x-ndjson or text/csv", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn import_logs(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ImportQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    use crate::db::import::{self, ImportFormat};
+
+    let accepted = content_type(&headers).is_some_and(|content_type| {
+        IMPORT_CONTENT_TYPES.iter().any(|accepted| content_type.eq_ignore_ascii_case(accepted))
+    });
+    if !accepted {
+        return ApiError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Send the import as application/json, application/x-ndjson or text/csv",
+        )
+        .into_response();
     }
+
+    let format = match params.format.as_deref() {
+        Some(name) => match ImportFormat::from_name(name) {
+            Some(format) => format,
+            None => {
+                return ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    format!("Unsupported import format: {}", name),
+                )
+                .into_response();
+            }
+        },
+        None => ImportFormat::detect(&body),
+    };
+
+    let (requests, parse_errors) = match import::parse_requests(&body, format) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            warn!("Import parse error: {}", e);
+            return ApiError::new(StatusCode::BAD_REQUEST, format!("Import failed: {}", e)).into_response();
+        }
+    };
+
+    match import::import_requests(&state.db_pool, &requests).await {
+        Ok(mut summary) => {
+            summary.errors += parse_errors;
+            info!(
+                "Imported {} requests ({} duplicates, {} errors)",
+                summary.imported, summary.duplicates, summary.errors
+            );
+            Json(summary).into_response()
+        }
+        Err(e) => {
+            error!("Import error: {}", e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Import failed").into_response()
+        }
+    }
+}
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct IngestResponse {
+    pub site_id: String,
+    pub accepted: usize,
+}
+
+// Requests forwarded by a branch-site agent, stored under the site its token belongs to
+#[utoipa::path(
+    post,
+    path = "/api/ingest",
+    tag = "sites",
+    request_body = Vec<crate::agent::ForwardedRequest>,
+    responses(
+        (status = 200, body = IngestResponse),
+        (status = 401, description = "Missing or unknown agent token", body = ApiError),
+        (status = 413, description = "Batch too large", body = ApiError),
+    )
+)]
+pub async fn ingest_requests(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(batch): Json<Vec<crate::agent::ForwardedRequest>>,
+) -> Response {
+    let site = headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|token| state.agents.authenticate(token.trim()));
+    let Some(site) = site else {
+        return ApiError::new(StatusCode::UNAUTHORIZED, "Missing or unknown agent token").into_response();
+    };
+    if batch.len() > crate::agent::MAX_INGEST_BATCH {
+        return ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("At most {} requests per batch", crate::agent::MAX_INGEST_BATCH),
+        )
+        .into_response();
+    }
+
+    let mut accepted = 0;
+    for forwarded in batch {
+        match state.process_request(forwarded.into_request(site)).await {
+            Ok(()) => accepted += 1,
+            Err(e) => warn!("Failed to process request from site {}: {}", site, e),
+        }
+    }
+    debug!("Ingested {} requests from site {}", accepted, site);
+    Json(IngestResponse { site_id: site.to_string(), accepted }).into_response()
+}
+
+// Requests and devices per agent site, including this instance's own capture
+#[utoipa::path(
+    get,
+    path = "/api/sites",
+    tag = "sites",
+    responses(
+        (status = 200, body = Vec<crate::db::queries::SiteSummary>),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn get_sites(State(state): State<Arc<AppState>>) -> Response {
+    match crate::db::queries::site_summary(&state.db_pool).await {
+        Ok(sites) => Json(sites).into_response(),
+        Err(e) => {
+            error!("Failed to summarize sites: {}", e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to summarize sites").into_response()
+        }
+    }
+}
+
+// Fingerprint bundle updates from upstream
+#[derive(Clone, Copy)]
+enum FingerprintUpdateAction {
+    Check,
+    Apply,
+    Rollback,
+}
+
+async fn run_fingerprint_update(state: &AppState, action: FingerprintUpdateAction) -> Response {
+    let Some(updater) = &state.fingerprint_updater else {
+        return ApiError::new(StatusCode::NOT_FOUND, "Fingerprint updates are not configured").into_response();
+    };
+
+    let result = match action {
+        FingerprintUpdateAction::Check => updater.check().await,
+        FingerprintUpdateAction::Apply => updater.apply().await,
+        FingerprintUpdateAction::Rollback => updater.rollback().await,
+    };
+
+    match result {
+        Ok(diff) => Json(diff).into_response(),
+        Err(e) => {
+            error!("Fingerprint update failed: {}", e);
+            ApiError::new(
+                StatusCode::BAD_GATEWAY,
+                format!("Fingerprint update failed: {}", e),
+            )
+            .into_response()
+        }
+    }
+}
+
+// Show what the upstream bundle would change without installing it
+#[utoipa::path(
+    get,
+    path = "/api/fingerprints/update",
+    tag = "admin",
+    responses(
+        (status = 200, description = "What the upstream bundle would change", body = Object),
+        (status = 404, description = "Fingerprint updates are not configured", body = ApiError),
+        (status = 502, description = "Fetching or verifying the bundle failed", body = ApiError),
+    )
+)]
+pub async fn check_fingerprint_update(State(state): State<Arc<AppState>>) -> Response {
+    run_fingerprint_update(&state, FingerprintUpdateAction::Check).await
+}
+
+// Install the upstream bundle
+#[utoipa::path(
+    post,
+    path = "/api/fingerprints/update",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Installed changes", body = Object),
+        (status = 404, description = "Fingerprint updates are not configured", body = ApiError),
+        (status = 502, description = "Fetching or verifying the bundle failed", body = ApiError),
+    )
+)]
+pub async fn apply_fingerprint_update(State(state): State<Arc<AppState>>) -> Response {
+    run_fingerprint_update(&state, FingerprintUpdateAction::Apply).await
+}
+
+// Restore the previously installed bundle
+#[utoipa::path(
+    post,
+    path = "/api/fingerprints/rollback",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Restored changes", body = Object),
+        (status = 404, description = "Fingerprint updates are not configured", body = ApiError),
+        (status = 502, description = "Rollback failed", body = ApiError),
+    )
+)]
+pub async fn rollback_fingerprint_update(State(state): State<Arc<AppState>>) -> Response {
+    run_fingerprint_update(&state, FingerprintUpdateAction::Rollback).await
+}
+
+// Re-read mac_os_mapping.toml and the fingerprint bundle (SIGHUP also does this)
+#[utoipa::path(
+    post,
+    path = "/api/admin/reload",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Reloaded MAC mappings and fingerprint bundle", body = Object),
+        (status = 422, description = "Invalid data; the previous data is kept", body = ApiError),
+    )
+)]
+pub async fn reload_detection_data() -> Response {
+    match crate::fingerprint::reload() {
+        Ok(summary) => {
+            info!(
+                "Reloaded {} MAC mappings, {} classification rules and {} bundle signatures",
+                summary.mac_mappings, summary.classification_rules, summary.bundle_signatures
+            );
+            Json(summary).into_response()
+        }
+        Err(e) => {
+            error!("Reload failed: {}", e);
+            ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Reload failed, keeping current data: {}", e),
+            )
+            .into_response()
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct ProbeCacheResponse {
+    pub entries: usize,
+    pub expired: usize,
+    pub sources: Vec<crate::hybrid_detection::CacheStats>,
+}
+
+// Cached SMB and HTTP probe results and failures
+#[utoipa::path(
+    get,
+    path = "/api/admin/probe-cache",
+    tag = "admin",
+    responses((status = 200, description = "Cache entries per probing source", body = Object))
+)]
+pub async fn get_probe_cache(State(state): State<Arc<AppState>>) -> Response {
+    let (entries, expired) = state.hybrid_detector.cache_stats().await;
+    Json(ProbeCacheResponse {
+        entries,
+        expired,
+        sources: state.hybrid_detector.source_cache_stats(),
+    })
+    .into_response()
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ProbeCacheQuery {
+    /// Only entries for this MAC address
+    mac: Option<String>,
+    /// Only entries for this IP address
+    ip: Option<String>,
+}
+
+// Drop cached probe results so devices are probed afresh on their next request,
+// e.g. after an OS upgrade: all of them, or those of a MAC and/or IP
+#[utoipa::path(
+    delete,
+    path = "/api/admin/probe-cache",
+    tag = "admin",
+    params(ProbeCacheQuery),
+    responses(
+        (status = 200, description = "Number of entries removed", body = Object),
+        (status = 400, description = "Invalid MAC or IP address", body = ApiError),
+    )
+)]
+pub async fn delete_probe_cache(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ProbeCacheQuery>,
+) -> Response {
+    let mac = match params.mac.as_deref() {
+        Some(mac) => match normalize_mac(mac) {
+            Some(mac) => Some(mac),
+            None => {
+                return ApiError::new(StatusCode::BAD_REQUEST, format!("Invalid MAC address '{}'", mac)).into_response()
+            }
+        },
+        None => None,
+    };
+    if let Some(ip) = &params.ip {
+        if ip.parse::<std::net::IpAddr>().is_err() {
+            return ApiError::new(StatusCode::BAD_REQUEST, format!("Invalid IP address '{}'", ip)).into_response();
+        }
+    }
+
+    let removed = if mac.is_none() && params.ip.is_none() {
+        let (entries, _) = state.hybrid_detector.cache_stats().await;
+        state.hybrid_detector.clear_cache().await;
+        entries
+    } else {
+        let removed = state.hybrid_detector.forget_cached(mac.as_deref(), params.ip.as_deref());
+        let target = [mac.as_deref(), params.ip.as_deref()].into_iter().flatten().collect::<Vec<_>>();
+        info!("Removed {} cached probe results for {}", removed, target.join(" at "));
+        removed
+    };
+    // Keep a restart from bringing the removed entries back
+    if let Err(e) = state.save_probe_cache().await {
+        warn!("Failed to save cached probe results: {}", e);
+    }
+    Json(serde_json::json!({ "removed": removed })).into_response()
+}
+
+// Apply the runtime-tunable settings of the posted TOML, or of config.toml when the
+// body is empty (same as SIGHUP); posted settings last until the next reload or restart
+#[utoipa::path(
+    post,
+    path = "/api/admin/config",
+    tag = "admin",
+    request_body(content = String, description = "config.toml content (empty: re-read config.toml)", content_type = "application/toml"),
+    responses(
+        (status = 200, description = "Settings applied and those needing a restart", body = Object),
+        (status = 415, description = "A body sent as anything but application/toml", body = ApiError),
+        (status = 422, description = "Invalid configuration; the current settings are kept", body = ApiError),
+    )
+)]
+pub async fn reload_config(State(state): State<Arc<AppState>>, headers: HeaderMap, body: String) -> Response {
+    let reloaded = if body.trim().is_empty() {
+        crate::reload::reload_file(&state)
+    } else {
+        // A form or text/plain post can come from any page; TOML can't without a CORS preflight
+        if !content_type(&headers).is_some_and(|content_type| content_type.eq_ignore_ascii_case("application/toml")) {
+            return ApiError::new(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Send the configuration as application/toml")
+                .into_response();
+        }
+        crate::reload::reload(&state, &body)
+    };
+    match reloaded {
+        Ok(summary) => {
+            summary.log("Config reload");
+            Json(summary).into_response()
+        }
+        Err(e) => {
+            error!("Config reload failed: {}", e);
+            ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Invalid configuration, keeping current settings: {}", e),
+            )
+            .into_response()
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NoisyClientsQuery {
+    /// Window length in hours back from now (default: 1)
+    hours: Option<u64>,
+    /// Expected seconds between renewals for a healthy client (default: 1800, half a 1h lease)
+    renewal_secs: Option<u64>,
+    /// Minimum requests-vs-expected ratio to report (default: 5)
+    min_score: Option<f64>,
+    limit: Option<usize>,
+}
+
+#[derive(serde::Serialize)]
+pub struct NoisyClientsResponse {
+    pub since: String,
+    /// Requests a well-behaved client would send in the window
+    pub expected_requests: f64,
+    pub clients: Vec<crate::chattiness::NoisyClient>,
+}
+
+// Devices sending far more requests than their renewal rate explains
+#[utoipa::path(
+    get,
+    path = "/api/devices/noisy",
+    tag = "devices",
+    params(NoisyClientsQuery),
+    responses(
+        (status = 200, body = Object),
+        (status = 400, description = "Invalid hours", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn get_noisy_clients(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<NoisyClientsQuery>,
+) -> Response {
+    let hours = params.hours.unwrap_or(1).max(1);
+    if hours > (MAX_WINDOW_SECS / 3600) as u64 {
+        return ApiError::new(StatusCode::BAD_REQUEST, "hours may span at most ten years").into_response();
+    }
+    let window = std::time::Duration::from_secs(hours * 3600);
+    let renewal = std::time::Duration::from_secs(params.renewal_secs.unwrap_or(1800).max(1));
+    let min_score = params.min_score.unwrap_or(5.0);
+    let expected = crate::chattiness::expected_requests(window, renewal);
+    let since = crate::chattiness::window_start(window);
+
+    // Nothing below min_score can qualify, so let SQLite drop quiet devices
+    let min_requests = (expected * min_score).floor() as i64;
+    match crate::db::queries::device_activity(&state.db_pool, &since, min_requests).await {
+        Ok(activity) => {
+            let mut clients = crate::chattiness::rank_noisy_clients(activity, window, renewal, min_score);
+            clients.truncate(params.limit.unwrap_or(50));
+            Json(NoisyClientsResponse {
+                since,
+                expected_requests: expected,
+                clients,
+            })
+            .into_response()
+        }
+        Err(e) => {
+            error!("Failed to compute noisy clients: {}", e);
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to compute noisy clients",
+            )
+            .into_response()
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SnapshotQuery {
+    /// RFC 3339 point in time (default: now)
+    as_of: Option<String>,
+}
+
+// What was known about a device at a point in time
+#[utoipa::path(
+    get,
+    path = "/api/devices/{mac}",
+    tag = "devices",
+    params(("mac" = String, Path, description = "Device MAC address"), SnapshotQuery),
+    responses(
+        (status = 200, body = crate::db::snapshot::DeviceSnapshot),
+        (status = 400, description = "Invalid as_of timestamp", body = ApiError),
+        (status = 404, description = "Device not seen by then", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn get_device_snapshot(
+    State(state): State<Arc<AppState>>,
+    Path(mac): Path<String>,
+    Query(params): Query<SnapshotQuery>,
+) -> Response {
+    let mac = crate::mac::canonical(&mac);
+    // Normalise to the UTC form requests are stored in so string comparison orders correctly
+    let as_of = match params.as_of.as_deref().map(chrono::DateTime::parse_from_rfc3339) {
+        None => chrono::Utc::now().to_rfc3339(),
+        Some(Ok(time)) => time.with_timezone(&chrono::Utc).to_rfc3339(),
+        Some(Err(e)) => {
+            return ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid as_of timestamp: {}", e),
+            )
+            .into_response();
+        }
+    };
+
+    match crate::db::snapshot::device_snapshot(&state.db_pool, &mac, &as_of).await {
+        Ok(Some(snapshot)) => Json(snapshot).into_response(),
+        Ok(None) => ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("No requests seen from {} as of {}", mac, as_of),
+        )
+        .into_response(),
+        Err(e) => {
+            error!("Failed to build snapshot for {}: {}", mac, e);
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to build device snapshot",
+            )
+            .into_response()
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct EvidenceResponse {
+    pub mac_address: String,
+    pub evidence: Vec<crate::db::evidence::StoredEvidence>,
+}
+
+// Each detection source's latest verdict for a device
+#[utoipa::path(
+    get,
+    path = "/api/devices/{mac}/evidence",
+    tag = "devices",
+    params(("mac" = String, Path, description = "Device MAC address")),
+    responses(
+        (status = 200, body = Object),
+        (status = 404, description = "No evidence stored", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn get_device_evidence(
+    State(state): State<Arc<AppState>>,
+    Path(mac): Path<String>,
+) -> Response {
+    let mac = crate::mac::canonical(&mac);
+    match crate::db::evidence::evidence_for_mac(&state.db_pool, &mac).await {
+        Ok(evidence) if evidence.is_empty() => ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("No detection evidence stored for {}", mac),
+        )
+        .into_response(),
+        Ok(evidence) => Json(EvidenceResponse { mac_address: mac, evidence }).into_response(),
+        Err(e) => {
+            error!("Failed to load detection evidence for {}: {}", mac, e);
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to load detection evidence",
+            )
+            .into_response()
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TimelineQuery {
+    #[serde(default = "default_timeline_limit")]
+    limit: i64,
+    #[serde(default)]
+    offset: i64,
+}
+
+fn default_timeline_limit() -> i64 {
+    100
+}
+
+#[derive(serde::Serialize)]
+pub struct TimelineResponse {
+    pub mac_address: String,
+    /// All requests from the device (the page is `requests`)
+    pub total: i64,
+    pub offset: i64,
+    pub limit: i64,
+    pub requests: Vec<crate::dhcp::DhcpRequest>,
+    /// Changes over the device's whole history, not just this page
+    pub os_changes: Vec<crate::db::queries::ValueChange>,
+    pub ip_changes: Vec<crate::db::queries::ValueChange>,
+}
+
+// Chronological request history for one device, with its OS and IP changes
+#[utoipa::path(
+    get,
+    path = "/api/devices/{mac}/timeline",
+    tag = "devices",
+    params(("mac" = String, Path, description = "Device MAC address"), TimelineQuery),
+    responses(
+        (status = 200, body = Object),
+        (status = 404, description = "Device never seen", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn get_device_timeline(
+    State(state): State<Arc<AppState>>,
+    Path(mac): Path<String>,
+    Query(params): Query<TimelineQuery>,
+) -> Response {
+    use crate::db::queries;
+
+    let mac = crate::mac::canonical(&mac);
+    let limit = params.limit.clamp(1, 1000);
+    let offset = params.offset.max(0);
+
+    let result = async {
+        let total = queries::count_for_mac(&state.db_pool, &mac).await?;
+        let requests = queries::requests_for_mac(&state.db_pool, &mac, limit, offset).await?;
+        let os_changes = queries::os_changes_for_mac(&state.db_pool, &mac).await?;
+        let ip_changes = queries::ip_changes_for_mac(&state.db_pool, &mac).await?;
+        Ok::<_, sqlx::Error>((total, requests, os_changes, ip_changes))
+    }
+    .await;
+
+    match result {
+        Ok((0, ..)) => ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("No requests seen from {}", mac),
+        )
+        .into_response(),
+        Ok((total, requests, os_changes, ip_changes)) => Json(TimelineResponse {
+            mac_address: mac,
+            total,
+            offset,
+            limit,
+            requests,
+            os_changes,
+            ip_changes,
+        })
+        .into_response(),
+        Err(e) => {
+            error!("Failed to load timeline for {}: {}", mac, e);
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to load device timeline",
+            )
+            .into_response()
+        }
+    }
+}
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct DeviceIpsResponse {
+    pub mac_address: String,
+    pub current: Option<String>,
+    /// Times the address changed
+    pub changes: usize,
+    /// Most recent first
+    pub history: Vec<crate::db::ip_history::IpAssignment>,
+}
+
+// Addresses a device has held, from its REQUEST/INFORM/ACK exchanges
+#[utoipa::path(
+    get,
+    path = "/api/devices/{mac}/ips",
+    tag = "devices",
+    params(("mac" = String, Path, description = "Device MAC address")),
+    responses(
+        (status = 200, body = DeviceIpsResponse),
+        (status = 404, description = "No addresses recorded", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn get_device_ips(State(state): State<Arc<AppState>>, Path(mac): Path<String>) -> Response {
+    let mac = crate::mac::canonical(&mac);
+    match crate::db::ip_history::ip_history(&state.db_pool, &mac).await {
+        Ok(history) if history.is_empty() => ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("No addresses recorded for {}", mac),
+        )
+        .into_response(),
+        Ok(history) => Json(DeviceIpsResponse {
+            mac_address: mac,
+            current: history.first().map(|assignment| assignment.ip_address.clone()),
+            changes: history.len() - 1,
+            history,
+        })
+        .into_response(),
+        Err(e) => {
+            error!("Failed to load address history for {}: {}", mac, e);
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to load address history",
+            )
+            .into_response()
+        }
+    }
+}
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct DeviceNamesResponse {
+    pub mac_address: String,
+    /// Most recently resolved first
+    pub names: Vec<crate::db::dns_names::DnsName>,
+}
+
+// Reverse DNS and mDNS names found for a device's addresses
+#[utoipa::path(
+    get,
+    path = "/api/devices/{mac}/names",
+    tag = "devices",
+    params(("mac" = String, Path, description = "Device MAC address")),
+    responses(
+        (status = 200, body = DeviceNamesResponse),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn get_device_names(State(state): State<Arc<AppState>>, Path(mac): Path<String>) -> Response {
+    let mac = crate::mac::canonical(&mac);
+    match crate::db::dns_names::names_for_mac(&state.db_pool, &mac).await {
+        Ok(names) => Json(DeviceNamesResponse { mac_address: mac, names }).into_response(),
+        Err(e) => {
+            error!("Failed to load names for {}: {}", mac, e);
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to load device names",
+            )
+            .into_response()
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct RedetectResponse {
+    pub mac_address: String,
+    /// Last non-zero source IP seen for the device (None = fingerprint-only detection)
+    pub ip_address: Option<String>,
+    pub result: DetectionResult,
+    /// What each source concluded on this run
+    pub evidence: Vec<crate::hybrid_detection::Evidence>,
+}
+
+// Re-run detection for a device against its last known IP, bypassing the probe cache
+// but not the probe scheduler's limits (admin only)
+#[utoipa::path(
+    post,
+    path = "/api/devices/{mac}/redetect",
+    tag = "devices",
+    params(("mac" = String, Path, description = "Device MAC address")),
+    responses(
+        (status = 200, body = Object),
+        (status = 404, description = "Device never seen", body = ApiError),
+        (status = 503, description = "Standby cluster node", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn redetect_device(
+    State(state): State<Arc<AppState>>,
+    Path(mac): Path<String>,
+) -> Response {
+    // Probing is the leader's job
+    if !state.is_active() {
+        return ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "This node is on standby; send the request to the cluster leader",
+        )
+        .into_response();
+    }
+
+    let mac = crate::mac::canonical(&mac);
+    let (latest, ip_address) = match crate::db::queries::latest_for_mac(&state.db_pool, &mac).await {
+        Ok(Some(found)) => found,
+        Ok(None) => {
+            return ApiError::new(
+                StatusCode::NOT_FOUND,
+                format!("No requests seen from {}", mac),
+            )
+            .into_response();
+        }
+        Err(e) => {
+            error!("Failed to look up device {}: {}", mac, e);
+            return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Device lookup failed").into_response();
+        }
+    };
+
+    info!("Re-running detection for {} (last IP: {:?})", mac, ip_address);
+    let device = crate::hybrid_detection::DeviceContext {
+        mac_address: &mac,
+        ip_address: ip_address.as_deref().unwrap_or("0.0.0.0"),
+        fingerprint: &latest.fingerprint,
+        vendor_class: latest.vendor_class.as_deref(),
+        hostname: latest.hostname.as_deref(),
+        vendor_specific: latest.option_data(43),
+        client_arch: latest.client_arch,
+        vendor_options: &latest.vendor_options,
+    };
+    let detection = state.hybrid_detector.redetect(&device).await;
+    let now = chrono::Utc::now().to_rfc3339();
+    if let Err(e) = crate::db::evidence::record_evidence(&state.db_pool, &mac, &now, &detection.evidence).await {
+        error!("Failed to store detection evidence for {}: {}", mac, e);
+    }
+
+    Json(RedetectResponse {
+        mac_address: mac,
+        ip_address,
+        result: detection.result,
+        evidence: detection.evidence,
+    })
+    .into_response()
+}
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct DhcpServersResponse {
+    /// "monitor" (passive only) or "active" (periodic probes enabled)
+    pub mode: &'static str,
+    /// Probe sent in active mode: "discover" or "inform"
+    pub probe: Option<&'static str>,
+    /// Servers that answered a probe, most recently seen first
+    pub servers: Vec<crate::db::dhcp_servers::DhcpServer>,
+}
+
+// DHCP servers found by active scanning, with the options they hand out
+#[utoipa::path(
+    get,
+    path = "/api/servers",
+    tag = "servers",
+    responses(
+        (status = 200, body = DhcpServersResponse),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn get_dhcp_servers(State(state): State<Arc<AppState>>) -> Response {
+    match crate::db::dhcp_servers::list_servers(&state.db_pool).await {
+        Ok(servers) => Json(DhcpServersResponse {
+            mode: if state.active_scan.is_some() { "active" } else { "monitor" },
+            probe: state.active_scan.as_ref().map(|scan| scan.probe.as_str()),
+            servers,
+        })
+        .into_response(),
+        Err(e) => {
+            error!("Failed to list DHCP servers: {}", e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to list DHCP servers").into_response()
+        }
+    }
+}
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct AlertListResponse {
+    /// Alerts matching the filter (ignoring limit/offset)
+    pub total: i64,
+    /// Unacknowledged alerts overall, for the UI badge
+    pub unacknowledged: i64,
+    pub alerts: Vec<crate::db::alerts::StoredAlert>,
+}
+
+// List stored alerts, filtered by severity, type, time and acknowledgement
+#[utoipa::path(
+    get,
+    path = "/api/alerts",
+    tag = "alerts",
+    params(crate::db::alerts::AlertFilter),
+    responses(
+        (status = 200, body = AlertListResponse),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn list_alerts(
+    State(state): State<Arc<AppState>>,
+    Query(filter): Query<crate::db::alerts::AlertFilter>,
+) -> Response {
+    let open = crate::db::alerts::AlertFilter {
+        acknowledged: Some(false),
+        ..Default::default()
+    };
+
+    let result = async {
+        let alerts = crate::db::alerts::list_alerts(&state.db_pool, &filter).await?;
+        let total = crate::db::alerts::count_alerts(&state.db_pool, &filter).await?;
+        let unacknowledged = crate::db::alerts::count_alerts(&state.db_pool, &open).await?;
+        Ok::<_, sqlx::Error>(AlertListResponse { total, unacknowledged, alerts })
+    }
+    .await;
+
+    match result {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => {
+            error!("Failed to list alerts: {}", e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to list alerts").into_response()
+        }
+    }
+}
+
+// Acknowledge a single alert
+#[utoipa::path(
+    post,
+    path = "/api/alerts/{id}/ack",
+    tag = "alerts",
+    params(("id" = i64, Path, description = "Alert id")),
+    responses(
+        (status = 204, description = "Acknowledged"),
+        (status = 404, description = "Alert not found", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn acknowledge_alert(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Response {
+    match crate::db::alerts::acknowledge_alert(&state.db_pool, id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => ApiError::new(StatusCode::NOT_FOUND, "Alert not found").into_response(),
+        Err(e) => {
+            error!("Failed to acknowledge alert {}: {}", id, e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to acknowledge alert").into_response()
+        }
+    }
+}
+
+// Acknowledge every open alert
+#[utoipa::path(
+    post,
+    path = "/api/alerts/ack",
+    tag = "alerts",
+    responses(
+        (status = 200, description = "Number of alerts acknowledged", body = Object),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn acknowledge_all_alerts(State(state): State<Arc<AppState>>) -> Response {
+    match crate::db::alerts::acknowledge_all(&state.db_pool).await {
+        Ok(acknowledged) => Json(serde_json::json!({ "acknowledged": acknowledged })).into_response(),
+        Err(e) => {
+            error!("Failed to acknowledge alerts: {}", e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to acknowledge alerts").into_response()
+        }
+    }
+}
+
+// Delete a stored alert
+#[utoipa::path(
+    delete,
+    path = "/api/alerts/{id}",
+    tag = "alerts",
+    params(("id" = i64, Path, description = "Alert id")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 404, description = "Alert not found", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn delete_alert(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Response {
+    match crate::db::alerts::delete_alert(&state.db_pool, id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => ApiError::new(StatusCode::NOT_FOUND, "Alert not found").into_response(),
+        Err(e) => {
+            error!("Failed to delete alert {}: {}", id, e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete alert").into_response()
+        }
+    }
+}
+
+/// A MAC in any notation as lower-case, colon-separated
+fn normalize_mac(mac: &str) -> Option<String> {
+    mac.parse::<crate::mac::Mac>().ok().map(|mac| mac.to_string())
+}
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct MappingEntry {
+    mac_address: String,
+    os_name: String,
+    device_class: String,
+    vendor: String,
+    /// "override" (managed through the API) or "file" (mac_os_mapping.toml)
+    source: &'static str,
+    updated_at: Option<String>,
+}
+
+// MAC to OS mappings: API overrides, then mac_os_mapping.toml entries they don't shadow
+#[utoipa::path(
+    get,
+    path = "/api/mappings",
+    tag = "mappings",
+    responses(
+        (status = 200, description = "API overrides and file mappings", body = Vec<MappingEntry>),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn list_mappings(State(state): State<Arc<AppState>>) -> Response {
+    let overrides = match crate::db::overrides::list_overrides(&state.db_pool).await {
+        Ok(overrides) => overrides,
+        Err(e) => {
+            error!("Failed to list MAC overrides: {}", e);
+            return ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to list mappings",
+            )
+            .into_response();
+        }
+    };
+
+    let mut file: Vec<_> = crate::fingerprint::file_mac_mappings()
+        .iter()
+        .filter(|(mac, _)| !overrides.iter().any(|entry| &entry.mac_address == *mac))
+        .map(|(mac, info)| MappingEntry {
+            mac_address: mac.clone(),
+            os_name: info.os_name.clone(),
+            device_class: info.device_class.clone(),
+            vendor: info.vendor.clone(),
+            source: "file",
+            updated_at: None,
+        })
+        .collect();
+    file.sort_by(|a, b| a.mac_address.cmp(&b.mac_address));
+
+    let mut mappings: Vec<MappingEntry> = overrides
+        .into_iter()
+        .map(|entry| MappingEntry {
+            mac_address: entry.mac_address,
+            os_name: entry.os_name,
+            device_class: entry.device_class,
+            vendor: entry.vendor,
+            source: "override",
+            updated_at: Some(entry.updated_at),
+        })
+        .collect();
+    mappings.extend(file);
+    Json(mappings).into_response()
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MappingRequest {
+    mac_address: String,
+    os_name: String,
+    #[serde(default)]
+    device_class: String,
+    #[serde(default)]
+    vendor: String,
+}
+
+// Create or replace an override; applies to detections from now on
+#[utoipa::path(
+    post,
+    path = "/api/mappings",
+    tag = "mappings",
+    request_body = MappingRequest,
+    responses(
+        (status = 200, body = crate::db::overrides::MacOverride),
+        (status = 400, description = "Invalid MAC address or missing os_name", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn save_mapping(
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<MappingRequest>,
+) -> Response {
+    let Some(mac_address) = normalize_mac(&body.mac_address) else {
+        return ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid MAC address '{}'", body.mac_address),
+        )
+        .into_response();
+    };
+    if body.os_name.trim().is_empty() {
+        return ApiError::new(StatusCode::BAD_REQUEST, "os_name is required").into_response();
+    }
+
+    let entry = crate::db::overrides::MacOverride {
+        mac_address,
+        os_name: body.os_name.trim().to_string(),
+        device_class: body.device_class.trim().to_string(),
+        vendor: body.vendor.trim().to_string(),
+        updated_at: chrono::Utc::now().to_rfc3339(),
+    };
+    match crate::db::overrides::upsert_override(&state.db_pool, &entry).await {
+        Ok(()) => {
+            info!("MAC override for {}: {}", entry.mac_address, entry.os_name);
+            crate::fingerprint::set_mac_override(&entry.mac_address, entry.info());
+            Json(entry).into_response()
+        }
+        Err(e) => {
+            error!("Failed to save MAC override for {}: {}", entry.mac_address, e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to save mapping").into_response()
+        }
+    }
+}
+
+// Remove an override; mac_os_mapping.toml entries can only be changed in the file
+#[utoipa::path(
+    delete,
+    path = "/api/mappings/{mac}",
+    tag = "mappings",
+    params(("mac" = String, Path, description = "Device MAC address")),
+    responses(
+        (status = 204, description = "Override removed"),
+        (status = 400, description = "Invalid MAC address", body = ApiError),
+        (status = 404, description = "Mapping not found", body = ApiError),
+        (status = 409, description = "Mapped in mac_os_mapping.toml", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn delete_mapping(
+    State(state): State<Arc<AppState>>,
+    Path(mac): Path<String>,
+) -> Response {
+    let Some(mac) = normalize_mac(&mac) else {
+        return ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid MAC address '{}'", mac),
+        )
+        .into_response();
+    };
+    match crate::db::overrides::delete_override(&state.db_pool, &mac).await {
+        Ok(true) => {
+            info!("Removed MAC override for {}", mac);
+            crate::fingerprint::remove_mac_override(&mac);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) if crate::fingerprint::file_mac_mappings().contains_key(&mac) => ApiError::new(
+            StatusCode::CONFLICT,
+            format!("{} is mapped in mac_os_mapping.toml, not through the API", mac),
+        )
+        .into_response(),
+        Ok(false) => ApiError::new(StatusCode::NOT_FOUND, "Mapping not found").into_response(),
+        Err(e) => {
+            error!("Failed to delete MAC override for {}: {}", mac, e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete mapping").into_response()
+        }
+    }
+}
+
+// Classification rules in evaluation order
+#[utoipa::path(
+    get,
+    path = "/api/rules",
+    tag = "mappings",
+    responses((status = 200, body = Vec<crate::rules::ClassificationRule>))
+)]
+pub async fn list_rules() -> Response {
+    Json(crate::rules::rules()).into_response()
+}
+
+// Replace every rule; written to classification_rules.toml and applied to detections from now on
+#[utoipa::path(
+    put,
+    path = "/api/rules",
+    tag = "mappings",
+    request_body = Vec<crate::rules::ClassificationRule>,
+    responses(
+        (status = 200, body = Vec<crate::rules::ClassificationRule>),
+        (status = 400, description = "Invalid pattern or missing os_name; the current rules are kept", body = ApiError),
+        (status = 500, description = "Failed to write the rules file", body = ApiError),
+    )
+)]
+pub async fn put_rules(Json(body): Json<Vec<crate::rules::ClassificationRule>>) -> Response {
+    let rules = match crate::rules::compile(body) {
+        Ok(rules) => rules,
+        Err(e) => return ApiError::new(StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    if let Err(e) = crate::rules::write_rules(crate::rules::RULES_PATH, &rules) {
+        error!("Failed to save classification rules: {}", e);
+        return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to save rules").into_response();
+    }
+    info!("Saved {} classification rules", rules.len());
+    crate::rules::install(rules);
+    Json(crate::rules::rules()).into_response()
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct DeviceMetaRequest {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    location: Option<String>,
+    #[serde(default)]
+    owner: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Trimmed, with blank values treated as unset
+fn non_blank(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+// A device's name, location, owner and tags
+#[utoipa::path(
+    get,
+    path = "/api/devices/{mac}/meta",
+    tag = "devices",
+    params(("mac" = String, Path, description = "Device MAC address")),
+    responses(
+        (status = 200, body = crate::db::device_meta::DeviceMeta),
+        (status = 400, description = "Invalid MAC address", body = ApiError),
+        (status = 404, description = "No metadata set", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn get_device_meta(State(state): State<Arc<AppState>>, Path(mac): Path<String>) -> Response {
+    let Some(mac) = normalize_mac(&mac) else {
+        return ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid MAC address '{}'", mac),
+        )
+        .into_response();
+    };
+    match crate::db::device_meta::get_meta(&state.db_pool, &mac).await {
+        Ok(Some(meta)) => Json(meta).into_response(),
+        Ok(None) => ApiError::new(StatusCode::NOT_FOUND, "No metadata for this device").into_response(),
+        Err(e) => {
+            error!("Failed to load device metadata for {}: {}", mac, e);
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to load device metadata",
+            )
+            .into_response()
+        }
+    }
+}
+
+// Replace a device's name, location, owner and tags; all blank clears them
+#[utoipa::path(
+    put,
+    path = "/api/devices/{mac}/meta",
+    tag = "devices",
+    params(("mac" = String, Path, description = "Device MAC address")),
+    request_body = DeviceMetaRequest,
+    responses(
+        (status = 200, body = crate::db::device_meta::DeviceMeta),
+        (status = 204, description = "All fields empty; metadata removed"),
+        (status = 400, description = "Invalid MAC address", body = ApiError),
+        (status = 500, description = "Database error", body = ApiError),
+    )
+)]
+pub async fn put_device_meta(
+    State(state): State<Arc<AppState>>,
+    Path(mac): Path<String>,
+    Json(body): Json<DeviceMetaRequest>,
+) -> Response {
+    let Some(mac) = normalize_mac(&mac) else {
+        return ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid MAC address '{}'", mac),
+        )
+        .into_response();
+    };
+
+    let mut tags: Vec<String> = Vec::new();
+    for tag in body.tags.iter().map(|tag| tag.trim()).filter(|tag| !tag.is_empty()) {
+        if !tags.iter().any(|existing| existing == tag) {
+            tags.push(tag.to_string());
+        }
+    }
+    let meta = crate::db::device_meta::DeviceMeta {
+        mac_address: mac,
+        name: non_blank(body.name),
+        location: non_blank(body.location),
+        owner: non_blank(body.owner),
+        tags,
+        updated_at: chrono::Utc::now().to_rfc3339(),
+    };
+
+    match crate::db::device_meta::put_meta(&state.db_pool, &meta).await {
+        Ok(()) if meta.is_empty() => StatusCode::NO_CONTENT.into_response(),
+        Ok(()) => Json(meta).into_response(),
+        Err(e) => {
+            error!("Failed to save device metadata for {}: {}", meta.mac_address, e);
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to save device metadata",
+            )
+            .into_response()
+        }
+    }
+}
+
+// Run a GraphQL query against the stored data
+pub async fn graphql(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    Json(super::graphql::SCHEMA.execute(request.data(state)).await)
+}
+
+// GraphiQL explorer for the GraphQL endpoint
+pub async fn graphiql() -> Html<String> {
+    Html(async_graphql::http::GraphiQLSource::build().endpoint("graphql").finish())
 }
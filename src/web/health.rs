@@ -0,0 +1,132 @@
+//! Health of the pieces /healthz, /readyz and the systemd watchdog look at: the DHCP
+//! listener, the database write path and the broadcast channels feeding live clients.
+
+use crate::db::health::{DbCondition, DbHealth};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+
+/// State of the DHCP listener (or the demo feed standing in for it)
+#[derive(Default)]
+pub struct ListenerHealth {
+    bound: AtomicBool,
+    failed: AtomicBool,
+    /// Milliseconds since the epoch, 0 before the first packet
+    last_packet_ms: AtomicI64,
+    receive_errors: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ListenerStatus {
+    pub bound: bool,
+    /// The listener stopped and no longer receives requests
+    pub failed: bool,
+    pub last_packet: Option<DateTime<Utc>>,
+    pub receive_errors: u64,
+}
+
+impl ListenerHealth {
+    pub fn mark_bound(&self) {
+        self.bound.store(true, Ordering::Relaxed);
+    }
+
+    pub fn mark_failed(&self) {
+        self.failed.store(true, Ordering::Relaxed);
+    }
+
+    pub fn record_packet(&self) {
+        self.last_packet_ms.store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    pub fn record_error(&self) {
+        self.receive_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Bound and still running
+    pub fn is_up(&self) -> bool {
+        self.bound.load(Ordering::Relaxed) && !self.failed.load(Ordering::Relaxed)
+    }
+
+    pub fn status(&self) -> ListenerStatus {
+        let last_packet_ms = self.last_packet_ms.load(Ordering::Relaxed);
+        ListenerStatus {
+            bound: self.bound.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            last_packet: (last_packet_ms > 0)
+                .then(|| Utc.timestamp_millis_opt(last_packet_ms).single())
+                .flatten(),
+            receive_errors: self.receive_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Subscribers to the live feeds (WebSocket clients, MQTT, email, ...)
+#[derive(Debug, Clone, Serialize)]
+pub struct BroadcastStatus {
+    pub request_subscribers: usize,
+    pub alert_subscribers: usize,
+    pub device_subscribers: usize,
+    pub update_subscribers: usize,
+    pub presence_subscribers: usize,
+}
+
+/// Whether the monitor can do its job, and if not, why
+#[derive(Debug, Clone, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub reasons: Vec<String>,
+}
+
+impl Readiness {
+    pub fn evaluate(listener: &ListenerStatus, database: &DbHealth) -> Self {
+        let mut reasons = Vec::new();
+        if listener.failed {
+            reasons.push("DHCP listener stopped".to_string());
+        } else if !listener.bound {
+            reasons.push("DHCP listener not bound yet".to_string());
+        }
+        match database.condition {
+            DbCondition::Ok => {}
+            DbCondition::DiskFull => reasons.push("database disk full, requests buffered in memory".to_string()),
+            DbCondition::Corrupt => reasons.push("database corrupt, requests buffered in memory".to_string()),
+        }
+        Self {
+            ready: reasons.is_empty(),
+            reasons,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readiness_follows_listener_and_database() {
+        let listener = ListenerHealth::default();
+        let mut database = DbHealth {
+            condition: DbCondition::Ok,
+            buffered: 0,
+            dropped: 0,
+            last_error: None,
+        };
+
+        let starting = Readiness::evaluate(&listener.status(), &database);
+        assert!(!starting.ready);
+        assert_eq!(starting.reasons, ["DHCP listener not bound yet"]);
+
+        listener.mark_bound();
+        listener.record_packet();
+        assert!(listener.status().last_packet.is_some());
+        assert!(Readiness::evaluate(&listener.status(), &database).ready);
+
+        database.condition = DbCondition::DiskFull;
+        assert_eq!(
+            Readiness::evaluate(&listener.status(), &database).reasons,
+            ["database disk full, requests buffered in memory"]
+        );
+
+        listener.mark_failed();
+        assert!(!listener.is_up());
+    }
+}
@@ -0,0 +1,96 @@
+//! Prometheus text exposition of the live counters, served at /metrics for setups
+//! that collect by scraping. Covers request totals and the capture counters that
+//! show whether the monitor itself is dropping data.
+
+use super::stats::Statistics;
+use std::fmt::Write;
+
+/// Labels are quoted; backslashes, quotes and newlines in values are escaped
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: impl std::fmt::Display) {
+    if labels.is_empty() {
+        let _ = writeln!(out, "{} {}", name, value);
+        return;
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+        .collect();
+    let _ = writeln!(out, "{}{{{}}} {}", name, labels.join(","), value);
+}
+
+pub fn render(stats: &Statistics) -> String {
+    let mut out = String::new();
+
+    header(&mut out, "dhcpmon_requests_total", "counter", "DHCP requests processed, by message type");
+    let mut request_types: Vec<_> = stats.request_types.iter().collect();
+    request_types.sort();
+    for (message_type, count) in request_types {
+        sample(&mut out, "dhcpmon_requests_total", &[("message_type", message_type)], count);
+    }
+
+    header(&mut out, "dhcpmon_unique_macs", "gauge", "Distinct client MAC addresses seen");
+    sample(&mut out, "dhcpmon_unique_macs", &[], stats.unique_macs);
+    header(&mut out, "dhcpmon_uptime_seconds", "gauge", "Seconds since the monitor started");
+    sample(&mut out, "dhcpmon_uptime_seconds", &[], stats.uptime_seconds);
+
+    let capture = &stats.capture;
+    header(&mut out, "dhcpmon_packets_received_total", "counter", "Datagrams read from the DHCP socket");
+    sample(&mut out, "dhcpmon_packets_received_total", &[], capture.packets_received);
+    header(&mut out, "dhcpmon_receive_errors_total", "counter", "Errors reading from the DHCP socket");
+    sample(&mut out, "dhcpmon_receive_errors_total", &[], capture.receive_errors);
+    header(&mut out, "dhcpmon_packets_dropped_total", "counter", "Packets discarded before processing, by reason");
+    sample(&mut out, "dhcpmon_packets_dropped_total", &[("reason", "malformed")], capture.malformed_packets);
+    sample(&mut out, "dhcpmon_packets_dropped_total", &[("reason", "oversized")], capture.oversized_packets);
+    sample(&mut out, "dhcpmon_packets_dropped_total", &[("reason", "shed")], capture.shed_packets);
+    header(
+        &mut out,
+        "dhcpmon_requests_deduplicated_total",
+        "counter",
+        "Requests folded into an earlier identical request instead of being stored",
+    );
+    sample(&mut out, "dhcpmon_requests_deduplicated_total", &[], capture.deduplicated_requests);
+    header(
+        &mut out,
+        "dhcpmon_broadcast_lagged_total",
+        "counter",
+        "Messages live subscribers missed by falling behind",
+    );
+    sample(&mut out, "dhcpmon_broadcast_lagged_total", &[], capture.broadcast_lagged);
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web::stats::StatsCollector;
+
+    #[test]
+    fn test_render() {
+        let collector = StatsCollector::new(chrono::Utc::now());
+        collector.record(&crate::dhcp::DhcpRequest {
+            mac_address: "aa:bb:cc:dd:ee:ff".to_string(),
+            message_type: "DISCOVER".to_string(),
+            ..Default::default()
+        });
+        let mut stats = collector.snapshot();
+        stats.capture.oversized_packets = 2;
+        stats.capture.shed_packets = 5;
+
+        let text = render(&stats);
+        assert!(text.contains("# TYPE dhcpmon_requests_total counter\n"));
+        assert!(text.contains("dhcpmon_requests_total{message_type=\"DISCOVER\"} 1\n"));
+        assert!(text.contains("dhcpmon_packets_dropped_total{reason=\"oversized\"} 2\n"));
+        assert!(text.contains("dhcpmon_packets_dropped_total{reason=\"shed\"} 5\n"));
+        assert_eq!(escape("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
@@ -1,3 +1,17 @@
+pub mod access_log;
+pub mod admin;
+pub mod assets;
+pub mod error;
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 pub mod handlers;
+pub mod health;
+pub mod metrics;
+pub mod openapi;
+pub mod proxy;
+pub mod rate_limit;
 pub mod server;
 pub mod state;
+pub mod stats;
+pub mod websocket;
@@ -0,0 +1,100 @@
+use super::error::ApiError;
+use super::handlers;
+use utoipa::OpenApi;
+
+/// OpenAPI description of the REST API, served at /api/openapi.json
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "ks-dhcpmon", description = "DHCP monitoring and device identification API"),
+    paths(
+        handlers::healthz,
+        handlers::readyz,
+        handlers::get_cluster_status,
+        handlers::get_version,
+        handlers::get_history,
+        handlers::search_requests,
+        handlers::get_statistics,
+        handlers::get_metrics,
+        handlers::get_option_statistics,
+        handlers::get_stats_timeseries,
+        handlers::get_lint_report,
+        handlers::get_request,
+        handlers::get_decoded_request,
+        handlers::get_request_hexdump,
+        handlers::get_logs,
+        handlers::get_logs_count,
+        handlers::export_logs,
+        handlers::import_logs,
+        handlers::ingest_requests,
+        handlers::get_sites,
+        handlers::check_fingerprint_update,
+        handlers::apply_fingerprint_update,
+        handlers::rollback_fingerprint_update,
+        handlers::reload_detection_data,
+        handlers::reload_config,
+        handlers::get_probe_cache,
+        handlers::delete_probe_cache,
+        handlers::get_devices,
+        handlers::get_noisy_clients,
+        handlers::get_device_snapshot,
+        handlers::get_device_timeline,
+        handlers::get_device_ips,
+        handlers::get_device_names,
+        handlers::get_device_evidence,
+        handlers::redetect_device,
+        handlers::get_device_meta,
+        handlers::put_device_meta,
+        handlers::get_dhcp_servers,
+        handlers::list_alerts,
+        handlers::acknowledge_all_alerts,
+        handlers::delete_alert,
+        handlers::acknowledge_alert,
+        handlers::list_mappings,
+        handlers::save_mapping,
+        handlers::delete_mapping,
+        handlers::list_rules,
+        handlers::put_rules,
+    ),
+    components(schemas(ApiError, handlers::LogsPage)),
+    tags(
+        (name = "requests", description = "Live requests held in memory"),
+        (name = "logs", description = "Stored requests: query, export and import"),
+        (name = "stats", description = "Counters, time series and lint findings"),
+        (name = "devices", description = "Per-device inventory, history and metadata"),
+        (name = "servers", description = "DHCP servers found by active scanning"),
+        (name = "alerts", description = "Stored alerts"),
+        (name = "mappings", description = "MAC to OS overrides and classification rules"),
+        (name = "sites", description = "Requests forwarded by branch-site agents"),
+        (name = "admin", description = "Fingerprint bundle updates, data and config reloads, probe cache; changes need [web] admin_token or a local client"),
+        (name = "system", description = "Health, cluster state and version"),
+    )
+)]
+pub struct ApiDoc;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_api_route_is_documented() {
+        let spec = ApiDoc::openapi();
+        let routes = include_str!("server.rs")
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix(".route(\""))
+            .filter_map(|rest| rest.split('"').next())
+            .filter(|path| path.starts_with("/api/") && *path != "/api/graphql");
+
+        for route in routes {
+            // axum's :param segments are {param} in OpenAPI
+            let path = route
+                .split('/')
+                .map(|segment| match segment.strip_prefix(':') {
+                    Some(name) => format!("{{{}}}", name),
+                    None => segment.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+            assert!(spec.paths.paths.contains_key(&path), "{} is not in the OpenAPI spec", path);
+        }
+    }
+}
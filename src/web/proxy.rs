@@ -0,0 +1,138 @@
+//! Running behind a reverse proxy, possibly at a sub-path such as `/dhcpmon/`.
+//!
+//! Pages and scripts use URLs relative to a `<base href>` injected when a page is
+//! served, so the UI works whatever prefix it is mounted under. The prefix is the
+//! configured base path, plus `X-Forwarded-Prefix` when the proxy strips it itself.
+//! Forwarded headers are only honored when `trust_forwarded_headers` is set, since
+//! anyone can send them to a directly exposed port.
+
+use axum::extract::ConnectInfo;
+use axum::http::{header, HeaderMap, HeaderValue, Method};
+use std::net::{IpAddr, SocketAddr};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+#[derive(Debug, Clone, Default)]
+pub struct ProxyConfig {
+    /// Path the app is served under, "" for the root, otherwise "/dhcpmon" style
+    pub base_path: String,
+    /// Honor X-Forwarded-For and X-Forwarded-Prefix
+    pub trust_forwarded_headers: bool,
+    /// Origins allowed to call the API from a browser ("*" for any)
+    pub cors_origins: Vec<String>,
+}
+
+/// "/dhcpmon/", "dhcpmon" and "/dhcpmon" all become "/dhcpmon"; "/" becomes ""
+pub fn normalize_base_path(path: &str) -> String {
+    let trimmed = path.trim().trim_matches('/');
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", trimmed)
+    }
+}
+
+// Prefixes end up in HTML, so only accept plain path characters
+fn is_safe_prefix(prefix: &str) -> bool {
+    prefix.starts_with('/')
+        && prefix
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '-' | '_' | '.' | '~'))
+}
+
+impl ProxyConfig {
+    pub fn new(base_path: &str, trust_forwarded_headers: bool, cors_origins: Vec<String>) -> Self {
+        Self {
+            base_path: normalize_base_path(base_path),
+            trust_forwarded_headers,
+            cors_origins,
+        }
+    }
+
+    /// The URL path the browser reaches the app under, always ending in '/'
+    pub fn external_base(&self, headers: &HeaderMap) -> String {
+        let forwarded = if self.trust_forwarded_headers {
+            headers
+                .get("x-forwarded-prefix")
+                .and_then(|value| value.to_str().ok())
+                .map(normalize_base_path)
+                .filter(|prefix| is_safe_prefix(prefix))
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+        format!("{}{}/", forwarded, self.base_path)
+    }
+
+    /// The requesting client: the address the proxy saw when trusted, else the peer
+    pub fn client_ip(&self, headers: &HeaderMap, connect_info: Option<&ConnectInfo<SocketAddr>>) -> Option<IpAddr> {
+        if self.trust_forwarded_headers {
+            // The last entry was appended by our proxy; earlier ones are client-supplied
+            let forwarded = headers
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .next_back()
+                .and_then(|entry| entry.trim().parse::<IpAddr>().ok());
+            if forwarded.is_some() {
+                return forwarded;
+            }
+        }
+        connect_info.map(|ConnectInfo(addr)| addr.ip())
+    }
+
+    /// CORS headers for the configured origins, None when cross-origin use is off
+    pub fn cors_layer(&self) -> Option<CorsLayer> {
+        if self.cors_origins.is_empty() {
+            return None;
+        }
+        let origins = if self.cors_origins.iter().any(|origin| origin == "*") {
+            AllowOrigin::any()
+        } else {
+            AllowOrigin::list(
+                self.cors_origins
+                    .iter()
+                    .filter_map(|origin| HeaderValue::from_str(origin.trim_end_matches('/')).ok()),
+            )
+        };
+        Some(
+            CorsLayer::new()
+                .allow_origin(origins)
+                .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+                .allow_headers([header::CONTENT_TYPE])
+                .expose_headers([header::RETRY_AFTER]),
+        )
+    }
+}
+
+/// Page HTML with a `<base href>` pointing at the external base
+pub fn with_base_href(html: &str, base: &str) -> String {
+    html.replacen("<head>", &format!("<head>\n    <base href=\"{}\">", base), 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_external_base_and_client_ip() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-prefix", HeaderValue::from_static("/dhcpmon/"));
+        headers.insert("x-forwarded-for", HeaderValue::from_static("203.0.113.9, 192.168.1.20"));
+        let peer = ConnectInfo("127.0.0.1:40000".parse::<SocketAddr>().unwrap());
+
+        let direct = ProxyConfig::new("/", false, Vec::new());
+        assert_eq!(direct.external_base(&headers), "/");
+        assert_eq!(direct.client_ip(&headers, Some(&peer)), Some("127.0.0.1".parse().unwrap()));
+
+        let proxied = ProxyConfig::new("monitor/", true, Vec::new());
+        assert_eq!(proxied.base_path, "/monitor");
+        assert_eq!(proxied.external_base(&headers), "/dhcpmon/monitor/");
+        assert_eq!(proxied.client_ip(&headers, Some(&peer)), Some("192.168.1.20".parse().unwrap()));
+
+        headers.insert("x-forwarded-prefix", HeaderValue::from_static("/\"><script>"));
+        assert_eq!(proxied.external_base(&headers), "/monitor/");
+
+        assert!(with_base_href("<html>\n<head>\n", "/monitor/").contains("<base href=\"/monitor/\">"));
+    }
+}
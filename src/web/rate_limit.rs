@@ -0,0 +1,218 @@
+//! Per-client rate limits on expensive endpoints and a cap on WebSocket clients, so
+//! a misbehaving dashboard can't starve capture of CPU and database time.
+
+use super::error::ApiError;
+use super::proxy::ProxyConfig;
+use super::state::AppState;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Buckets kept before idle ones are swept
+const MAX_BUCKETS: usize = 10_000;
+/// A bucket untouched for this long is full again and can be forgotten
+const BUCKET_IDLE: Duration = Duration::from_secs(600);
+
+/// Token bucket for requests whose path starts with `path`
+#[derive(Debug, Clone, PartialEq)]
+pub struct EndpointLimit {
+    pub path: String,
+    pub per_minute: u32,
+    /// Requests allowed back to back before the rate applies
+    pub burst: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RateLimitConfig {
+    pub endpoints: Vec<EndpointLimit>,
+    /// Concurrent WebSocket connections overall and per client IP (0 = unlimited)
+    pub max_websockets: usize,
+    pub max_websockets_per_client: usize,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Default)]
+struct WebSocketCounts {
+    total: usize,
+    per_client: HashMap<IpAddr, usize>,
+}
+
+#[derive(Default)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<(IpAddr, usize), Bucket>>,
+    websockets: Arc<Mutex<WebSocketCounts>>,
+}
+
+/// Held for the life of a WebSocket connection; frees its slot on drop
+pub struct WebSocketPermit {
+    client: IpAddr,
+    counts: Arc<Mutex<WebSocketCounts>>,
+}
+
+impl Drop for WebSocketPermit {
+    fn drop(&mut self) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.total = counts.total.saturating_sub(1);
+        if let Some(count) = counts.per_client.get_mut(&self.client) {
+            *count -= 1;
+            if *count == 0 {
+                counts.per_client.remove(&self.client);
+            }
+        }
+    }
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    // The most specific limit covering this path
+    fn rule_for(&self, path: &str) -> Option<(usize, &EndpointLimit)> {
+        self.config
+            .endpoints
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| rule.per_minute > 0 && path.starts_with(&rule.path))
+            .max_by_key(|(_, rule)| rule.path.len())
+    }
+
+    /// Take a token for this request; Err carries the wait until one is available
+    pub fn check(&self, client: IpAddr, path: &str) -> Result<(), Duration> {
+        self.check_at(client, path, Instant::now())
+    }
+
+    fn check_at(&self, client: IpAddr, path: &str, now: Instant) -> Result<(), Duration> {
+        let Some((index, rule)) = self.rule_for(path) else {
+            return Ok(());
+        };
+        let rate = rule.per_minute as f64 / 60.0;
+        let capacity = rule.burst.max(1) as f64;
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_BUCKETS {
+            buckets.retain(|_, bucket| now.duration_since(bucket.updated) < BUCKET_IDLE);
+        }
+        let bucket = buckets.entry((client, index)).or_insert(Bucket { tokens: capacity, updated: now });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+
+    /// Claim a WebSocket slot, or None when the client or server is at its limit
+    pub fn try_websocket(&self, client: IpAddr) -> Option<WebSocketPermit> {
+        let mut counts = self.websockets.lock().unwrap();
+        let for_client = counts.per_client.get(&client).copied().unwrap_or(0);
+        if (self.config.max_websockets > 0 && counts.total >= self.config.max_websockets)
+            || (self.config.max_websockets_per_client > 0 && for_client >= self.config.max_websockets_per_client)
+        {
+            return None;
+        }
+        counts.total += 1;
+        *counts.per_client.entry(client).or_insert(0) += 1;
+        Some(WebSocketPermit {
+            client,
+            counts: self.websockets.clone(),
+        })
+    }
+}
+
+/// Client address for limiting; requests without connection info share one bucket
+pub fn client_ip(proxy: &ProxyConfig, headers: &HeaderMap, connect_info: Option<ConnectInfo<SocketAddr>>) -> IpAddr {
+    proxy
+        .client_ip(headers, connect_info.as_ref())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+}
+
+/// Middleware answering 429 with Retry-After once a client exceeds an endpoint's limit
+pub async fn limit_requests(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    let client = client_ip(&state.proxy, request.headers(), connect_info);
+    if let Err(wait) = state.rate_limiter.check(client, path) {
+        let retry_after = wait.as_secs() + 1;
+        let mut response = ApiError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            format!("Rate limit exceeded for {}; retry in {}s", path, retry_after),
+        )
+        .into_response();
+        response.headers_mut().insert("retry-after", retry_after.into());
+        return response;
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            endpoints: vec![
+                EndpointLimit { path: "/api/logs".to_string(), per_minute: 120, burst: 10 },
+                EndpointLimit { path: "/api/logs/export".to_string(), per_minute: 6, burst: 2 },
+            ],
+            max_websockets: 3,
+            max_websockets_per_client: 2,
+        })
+    }
+
+    #[test]
+    fn test_most_specific_limit_applies_per_client() {
+        let limiter = limiter();
+        let client: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        let start = Instant::now();
+
+        assert!(limiter.check_at(client, "/api/logs/export", start).is_ok());
+        assert!(limiter.check_at(client, "/api/logs/export", start).is_ok());
+        let wait = limiter.check_at(client, "/api/logs/export", start).unwrap_err();
+        assert_eq!(wait.as_secs(), 10);
+
+        // Other endpoints and other clients have their own buckets
+        assert!(limiter.check_at(client, "/api/logs", start).is_ok());
+        assert!(limiter.check_at(other, "/api/logs/export", start).is_ok());
+        assert!(limiter.check_at(client, "/api/stats", start).is_ok());
+
+        // One export token every 10s
+        assert!(limiter.check_at(client, "/api/logs/export", start + Duration::from_secs(10)).is_ok());
+    }
+
+    #[test]
+    fn test_websocket_slots() {
+        let limiter = limiter();
+        let client: IpAddr = "10.0.0.1".parse().unwrap();
+        let first = limiter.try_websocket(client).unwrap();
+        let _second = limiter.try_websocket(client).unwrap();
+        assert!(limiter.try_websocket(client).is_none());
+
+        let _other = limiter.try_websocket("10.0.0.2".parse().unwrap()).unwrap();
+        assert!(limiter.try_websocket("10.0.0.3".parse().unwrap()).is_none());
+
+        drop(first);
+        assert!(limiter.try_websocket(client).is_some());
+    }
+}
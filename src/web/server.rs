@@ -1,52 +1,222 @@
+use super::access_log::{self, AccessLogger};
+use super::admin;
+use super::assets;
 use super::handlers;
+use super::openapi::ApiDoc;
+use super::rate_limit;
 use super::state::AppState;
 use axum::{
-    routing::get,
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{delete, get, post, put},
     Router,
 };
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tower_http::trace::TraceLayer;
-use tracing::info;
+use tracing::{info, warn};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::{Config, SwaggerUi};
+
+// Maximum accepted body size for log imports (request.json can grow large)
+const IMPORT_BODY_LIMIT: usize = 256 * 1024 * 1024;
+
+// Maximum accepted body size for agent batches (up to MAX_INGEST_BATCH requests with packets)
+const INGEST_BODY_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Where the web server listens
+#[derive(Debug, Clone, PartialEq)]
+pub enum Listen {
+    Tcp(SocketAddr),
+    /// Unix domain socket, for a reverse proxy on the same host
+    Unix(PathBuf),
+}
+
+pub async fn run_server(
+    state: Arc<AppState>,
+    listen: Listen,
+    access_logger: Option<Arc<AccessLogger>>,
+) -> anyhow::Result<()> {
+    let proxy = state.proxy.clone();
+
+    // Endpoints that change how the monitor runs: admin token or local clients only
+    let admin_routes = Router::new()
+        .route("/api/fingerprints/update", post(handlers::apply_fingerprint_update))
+        .route("/api/fingerprints/rollback", post(handlers::rollback_fingerprint_update))
+        .route("/api/admin/reload", post(handlers::reload_detection_data))
+        .route("/api/admin/config", post(handlers::reload_config))
+        .route("/api/admin/probe-cache", get(handlers::get_probe_cache).delete(handlers::delete_probe_cache))
+        .route("/api/rules", put(handlers::put_rules))
+        // Overrides take precedence over detection, like the rules
+        .route("/api/mappings", post(handlers::save_mapping))
+        .route("/api/mappings/:mac", delete(handlers::delete_mapping))
+        .route(
+            "/api/logs/import",
+            post(handlers::import_logs).layer(DefaultBodyLimit::max(IMPORT_BODY_LIMIT)),
+        )
+        // Acknowledging or deleting alerts hides them from the dashboard
+        .route("/api/alerts/ack", post(handlers::acknowledge_all_alerts))
+        .route("/api/alerts/:id", delete(handlers::delete_alert))
+        .route("/api/alerts/:id/ack", post(handlers::acknowledge_alert))
+        // Device names, owners and tags, which alerts and exports carry
+        .route("/api/devices/:mac/meta", put(handlers::put_device_meta))
+        // Sends SMB and HTTP probes into the network
+        .route("/api/devices/:mac/redetect", post(handlers::redetect_device))
+        .route_layer(middleware::from_fn_with_state(state.clone(), admin::require_admin));
 
-pub async fn run_server(state: Arc<AppState>, port: u16) -> anyhow::Result<()> {
     // Build router with all endpoints
-    let app = Router::new()
+    let mut app = Router::new()
         // Serve static HTML page
         .route("/", get(handlers::serve_index))
 
         // WebSocket endpoint for real-time updates
         .route("/ws", get(handlers::websocket_handler))
 
+        // Health check
+        .route("/healthz", get(handlers::healthz))
+        .route("/readyz", get(handlers::readyz))
+        .route("/metrics", get(handlers::get_metrics))
+        .route("/api/cluster", get(handlers::get_cluster_status))
+
         // REST API endpoints
         .route("/api/history", get(handlers::get_history))
         .route("/api/stats", get(handlers::get_statistics))
+        .route("/api/stats/options", get(handlers::get_option_statistics))
+        .route("/api/stats/timeseries", get(handlers::get_stats_timeseries))
+        .route("/api/lint/report", get(handlers::get_lint_report))
         .route("/api/search", get(handlers::search_requests))
+        .route("/api/version", get(handlers::get_version))
 
-        // Static assets (CSS, JS)
+        // Static assets (CSS, JS), under both content-hashed and plain URLs
         .route("/app.js", get(handlers::serve_js))
+        .route(assets::APP_JS_PATH, get(handlers::serve_js))
         .route("/styles.css", get(handlers::serve_css))
+        .route(assets::STYLES_CSS_PATH, get(handlers::serve_css))
 
         // Historical logs page
         .route("/logs", get(handlers::serve_logs_page))
         .route("/logs.js", get(handlers::serve_logs_js))
+        .route(assets::LOGS_JS_PATH, get(handlers::serve_logs_js))
         .route("/logs.css", get(handlers::serve_logs_css))
+        .route(assets::LOGS_CSS_PATH, get(handlers::serve_logs_css))
+
+        // Device mappings page
+        .route("/mappings", get(handlers::serve_mappings_page))
+        .route("/mappings.js", get(handlers::serve_mappings_js))
+        .route(assets::MAPPINGS_JS_PATH, get(handlers::serve_mappings_js))
 
         // Historical logs API endpoints
         .route("/api/logs", get(handlers::get_logs))
         .route("/api/logs/count", get(handlers::get_logs_count))
         .route("/api/logs/export", get(handlers::export_logs))
+        .route("/api/requests/:id", get(handlers::get_request))
+        .route("/api/requests/:id/decoded", get(handlers::get_decoded_request))
+        .route("/api/requests/:id/hexdump", get(handlers::get_request_hexdump))
+
+        // Requests forwarded by branch-site agents, and per-site totals
+        .route("/api/ingest", post(handlers::ingest_requests).layer(DefaultBodyLimit::max(INGEST_BODY_LIMIT)))
+        .route("/api/sites", get(handlers::get_sites))
+
+        // Upstream fingerprint bundle updates (applying one is an admin route)
+        .route("/api/fingerprints/update", get(handlers::check_fingerprint_update))
+
+        // DHCP servers answering active scans
+        .route("/api/servers", get(handlers::get_dhcp_servers))
+
+        // Stored alerts
+        .route("/api/alerts", get(handlers::list_alerts))
+
+        // Devices
+        .route("/api/devices", get(handlers::get_devices))
+        .route("/api/devices/noisy", get(handlers::get_noisy_clients))
+        .route("/api/devices/:mac", get(handlers::get_device_snapshot))
+        .route("/api/devices/:mac/timeline", get(handlers::get_device_timeline))
+        .route("/api/devices/:mac/ips", get(handlers::get_device_ips))
+        .route("/api/devices/:mac/names", get(handlers::get_device_names))
+        .route("/api/devices/:mac/evidence", get(handlers::get_device_evidence))
+        .route("/api/devices/:mac/meta", get(handlers::get_device_meta))
+
+        // MAC -> OS mapping overrides
+        .route("/api/mappings", get(handlers::list_mappings))
+        .route("/api/rules", get(handlers::list_rules))
+        .merge(admin_routes)
+
+        // GraphQL queries and the GraphiQL explorer
+        .route("/api/graphql", get(handlers::graphiql).post(handlers::graphql))
+
+        // OpenAPI spec and Swagger UI (the UI fetches the spec relative to itself)
+        .merge(
+            SwaggerUi::new("/api/docs")
+                .url("/api/openapi.json", ApiDoc::openapi())
+                .config(Config::new(["../openapi.json"])),
+        )
+
+        // Per-client limits on expensive endpoints
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit::limit_requests))
 
         // Add application state
-        .with_state(state)
+        .with_state(state.clone());
+
+    // Mount under the configured base path, e.g. /dhcpmon behind nginx; the nested "/"
+    // only matches the bare prefix, so the page under the trailing slash is added here
+    if !proxy.base_path.is_empty() {
+        app = Router::new()
+            .route(&format!("{}/", proxy.base_path), get(handlers::serve_index))
+            .with_state(state)
+            .nest(&proxy.base_path, app);
+    }
 
-        // Add tracing middleware
-        .layer(TraceLayer::new_for_http());
+    // Browser access from other origins (e.g. a Grafana dashboard)
+    if let Some(cors) = proxy.cors_layer() {
+        app = app.layer(cors);
+    }
 
-    let addr = format!("0.0.0.0:{}", port);
-    info!("Web UI available at http://{}", addr);
+    // Add tracing middleware
+    app = app.layer(TraceLayer::new_for_http());
 
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+    // Access log goes to its own file, separate from application logs
+    if let Some(logger) = access_logger {
+        app = app.layer(middleware::from_fn_with_state(logger, access_log::log_access));
+    }
+
+    match listen {
+        Listen::Tcp(addr) => {
+            info!("Web UI available at http://{}{}/", addr, proxy.base_path);
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
+        }
+        Listen::Unix(path) => serve_unix(app, &path).await?,
+    }
 
     Ok(())
 }
+
+/// Serve on a Unix domain socket. axum::serve only takes TCP listeners, so
+/// connections are handed to hyper directly; requests carry no ConnectInfo.
+async fn serve_unix(app: Router, path: &std::path::Path) -> anyhow::Result<()> {
+    use hyper_util::rt::{TokioExecutor, TokioIo};
+    use hyper_util::server::conn::auto;
+    use hyper_util::service::TowerToHyperService;
+    use std::os::unix::fs::FileTypeExt;
+
+    // A socket left behind by an earlier run would make bind fail
+    if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    info!("Web UI available on Unix socket {}", path.display());
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                warn!("Unix socket connection error: {}", e);
+            }
+        });
+    }
+}
@@ -1,11 +1,31 @@
+use crate::active_scan::ActiveScanConfig;
+use crate::agent::AgentRegistry;
+use crate::alerts::{Alert, AlertManager, Severity};
+use crate::anomaly::AnomalyDetector;
+use crate::arp::NeighborTable;
+use crate::rdns::ReverseResolver;
+use crate::scripting::{RequestScript, ScriptActions};
+use crate::capture_filter::CaptureFilter;
+use crate::subnets::SubnetMap;
+use crate::dedup::RequestDeduplicator;
+use crate::cluster::LeaderElection;
+use crate::db::health::{WriteGuard, WriteOutcome};
 use crate::dhcp::DhcpRequest;
 use crate::logger::RequestLogger;
-use crate::hybrid_detection::HybridDetector;
+use crate::new_device::{NewDeviceEvent, NewDeviceTracker};
+use crate::presence::{PresenceChange, PresenceEngine};
+use crate::reverify::ReverifyConfig;
+use crate::notify::email::EmailConfig;
+use crate::fingerprint_update::FingerprintUpdater;
+use crate::hybrid_detection::{DetectionResult, DeviceContext, HybridDetector};
+use super::health::{BroadcastStatus, ListenerHealth};
+use super::proxy::ProxyConfig;
+use super::rate_limit::RateLimiter;
+use super::stats::{CaptureCounters, Statistics, StatsCollector};
 use std::sync::Arc;
-use tokio::sync::{broadcast, RwLock};
+use tokio::sync::{broadcast, watch, RwLock};
 use ringbuf::{HeapRb, Rb};
 use chrono::{DateTime, Utc};
-use std::collections::{HashMap, HashSet};
 use sqlx::SqlitePool;
 
 // Configuration constants
@@ -13,94 +33,391 @@ pub const HISTORY_BUFFER_SIZE: usize = 1000;
 pub const BROADCAST_CHANNEL_SIZE: usize = 100;
 pub const WEB_SERVER_PORT: u16 = 8080;
 
-// Statistics structure
-#[derive(Debug, Clone, serde::Serialize)]
-pub struct Statistics {
-    pub total_requests: u64,
-    pub request_types: HashMap<String, u64>,
-    pub unique_macs: u64,
-    pub requests_per_minute: f64,
-    pub last_updated: DateTime<Utc>,
-    pub uptime_seconds: u64,
-    pub vendor_classes: HashMap<String, u64>,
-}
-
-impl Default for Statistics {
-    fn default() -> Self {
-        Self {
-            total_requests: 0,
-            request_types: HashMap::new(),
-            unique_macs: 0,
-            requests_per_minute: 0.0,
-            last_updated: Utc::now(),
-            uptime_seconds: 0,
-            vendor_classes: HashMap::new(),
-        }
-    }
-}
-
 // Application state shared across all tasks
 pub struct AppState {
     // Broadcast channel for real-time updates to WebSocket clients
     pub broadcast_tx: broadcast::Sender<Arc<DhcpRequest>>,
 
+    // Notified alerts, pushed to WebSocket clients
+    pub alert_tx: broadcast::Sender<Alert>,
+
+    // Devices joining (or returning to) the network, pushed to WebSocket clients
+    pub device_tx: broadcast::Sender<NewDeviceEvent>,
+
+    // Requests whose detection changed after they were broadcast, once probes finished
+    pub update_tx: broadcast::Sender<Arc<DhcpRequest>>,
+
+    // Devices going online, idle or offline, pushed to WebSocket clients
+    pub presence_tx: broadcast::Sender<PresenceChange>,
+
     // File logger (existing)
     pub logger: Arc<RequestLogger>,
 
     // Database pool
     pub db_pool: SqlitePool,
 
+    // Buffers writes in memory while the database is full or corrupt
+    pub db_guard: WriteGuard,
+
     // Circular buffer for recent requests (thread-safe)
     pub history: Arc<RwLock<HeapRb<Arc<DhcpRequest>>>>,
 
-    // Statistics (thread-safe)
-    pub stats: Arc<RwLock<Statistics>>,
+    // Statistics (atomic counters, no global lock)
+    pub stats: StatsCollector,
 
-    // Set of unique MAC addresses (for stats)
-    pub unique_macs: Arc<RwLock<HashSet<String>>>,
+    // Packets and messages the monitor lost or discarded
+    pub capture: Arc<CaptureCounters>,
 
     // Hybrid detector for OS detection
     pub hybrid_detector: Arc<HybridDetector>,
 
-    // Application start time
-    pub start_time: DateTime<Utc>,
+    // Upstream fingerprint bundle updater (None when not configured)
+    pub fingerprint_updater: Option<Arc<FingerprintUpdater>>,
+
+    // Alert deduplication and flap suppression
+    pub alerts: Arc<AlertManager>,
+
+    // Starvation/flood/fingerprint-change detection (None when disabled)
+    pub anomaly_detector: Option<AnomalyDetector>,
+
+    // Leader election against the shared database (None when running standalone)
+    pub cluster: Option<Arc<LeaderElection>>,
+
+    // Packets dropped before logging and storage (replaced on config reload)
+    capture_filter: std::sync::RwLock<Arc<CaptureFilter>>,
+
+    // Subnets requests are attributed to
+    pub subnets: SubnetMap,
+
+    // Folds repeated identical requests into one stored row (None when disabled)
+    pub dedup: Option<RequestDeduplicator>,
+
+    // Announces devices seen for the first time (None when disabled)
+    pub new_devices: Option<NewDeviceTracker>,
+
+    // Per-client limits on expensive endpoints and WebSocket connections
+    pub rate_limiter: RateLimiter,
+
+    // Base path, forwarded-header trust and CORS origins for reverse-proxy setups
+    pub proxy: ProxyConfig,
+
+    // Bearer token for the admin endpoints (None: local clients only)
+    pub admin_token: Option<String>,
+
+    // DHCP listener (or demo feed) state for /healthz, /readyz and the systemd watchdog
+    pub listener: ListenerHealth,
+
+    // Host ARP table sightings for device presence (None when disabled)
+    pub neighbors: Option<Arc<NeighborTable>>,
+
+    // Online/idle/offline state per device (None when disabled)
+    pub presence: Option<Arc<PresenceEngine>>,
+
+    // PTR/mDNS lookups of device addresses (None when disabled)
+    pub reverse_dns: Option<Arc<ReverseResolver>>,
+
+    // Branch-site agents allowed to forward requests to /api/ingest
+    pub agents: AgentRegistry,
+
+    // Site policy script run on each request (None when not configured)
+    pub script: Option<Arc<RequestScript>>,
+
+    // Periodic probes for DHCP servers (None in the default monitor-only mode)
+    pub active_scan: Option<ActiveScanConfig>,
+
+    // Confidence decay and re-verification of stored detections (None when disabled)
+    pub reverify: Option<ReverifyConfig>,
+
+    // Alert email settings, sent to the notifier on config reload (None when not configured)
+    pub email: Option<watch::Sender<EmailConfig>>,
 }
 
 impl AppState {
-    pub fn new(logger: Arc<RequestLogger>, db_pool: SqlitePool, hybrid_detector: Arc<HybridDetector>) -> Self {
+    pub fn new(
+        logger: Arc<RequestLogger>,
+        db_pool: SqlitePool,
+        hybrid_detector: Arc<HybridDetector>,
+        fingerprint_updater: Option<Arc<FingerprintUpdater>>,
+        alerts: Arc<AlertManager>,
+        anomaly_detector: Option<AnomalyDetector>,
+        cluster: Option<Arc<LeaderElection>>,
+    ) -> Self {
         let (broadcast_tx, _) = broadcast::channel(BROADCAST_CHANNEL_SIZE);
+        let (alert_tx, _) = broadcast::channel(BROADCAST_CHANNEL_SIZE);
+        let (device_tx, _) = broadcast::channel(BROADCAST_CHANNEL_SIZE);
+        let (update_tx, _) = broadcast::channel(BROADCAST_CHANNEL_SIZE);
+        let (presence_tx, _) = broadcast::channel(BROADCAST_CHANNEL_SIZE);
 
         Self {
             broadcast_tx,
+            alert_tx,
+            device_tx,
+            update_tx,
+            presence_tx,
             logger,
             db_pool,
+            db_guard: WriteGuard::default(),
             history: Arc::new(RwLock::new(HeapRb::new(HISTORY_BUFFER_SIZE))),
-            stats: Arc::new(RwLock::new(Statistics::default())),
-            unique_macs: Arc::new(RwLock::new(HashSet::new())),
+            stats: StatsCollector::new(Utc::now()),
+            capture: Arc::new(CaptureCounters::default()),
             hybrid_detector,
-            start_time: Utc::now(),
+            fingerprint_updater,
+            alerts,
+            anomaly_detector,
+            cluster,
+            capture_filter: std::sync::RwLock::new(Arc::new(CaptureFilter::default())),
+            subnets: SubnetMap::default(),
+            dedup: None,
+            new_devices: None,
+            rate_limiter: RateLimiter::default(),
+            proxy: ProxyConfig::default(),
+            admin_token: None,
+            listener: ListenerHealth::default(),
+            neighbors: None,
+            presence: None,
+            reverse_dns: None,
+            agents: AgentRegistry::default(),
+            script: None,
+            active_scan: None,
+            reverify: None,
+            email: None,
         }
     }
 
-    // Process a new DHCP request (called from UDP handler)
-    pub async fn process_request(&self, mut request: DhcpRequest) -> anyhow::Result<()> {
-        // 0. Run hybrid detection to enhance OS detection
-        let detection_result = self.hybrid_detector.detect(
+    pub fn with_capture_filter(self, capture_filter: CaptureFilter) -> Self {
+        self.set_capture_filter(capture_filter);
+        self
+    }
+
+    pub fn with_subnets(mut self, subnets: SubnetMap) -> Self {
+        self.subnets = subnets;
+        self
+    }
+
+    pub fn with_dedup(mut self, dedup: Option<RequestDeduplicator>) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    pub fn with_new_devices(mut self, new_devices: Option<NewDeviceTracker>) -> Self {
+        self.new_devices = new_devices;
+        self
+    }
+
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = rate_limiter;
+        self
+    }
+
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    pub fn with_admin_token(mut self, admin_token: Option<String>) -> Self {
+        self.admin_token = admin_token;
+        self
+    }
+
+    pub fn with_neighbors(mut self, neighbors: Option<Arc<NeighborTable>>) -> Self {
+        self.neighbors = neighbors;
+        self
+    }
+
+    pub fn with_presence(mut self, presence: Option<Arc<PresenceEngine>>) -> Self {
+        self.presence = presence;
+        self
+    }
+
+    pub fn with_reverse_dns(mut self, reverse_dns: Option<Arc<ReverseResolver>>) -> Self {
+        self.reverse_dns = reverse_dns;
+        self
+    }
+
+    pub fn with_agents(mut self, agents: AgentRegistry) -> Self {
+        self.agents = agents;
+        self
+    }
+
+    pub fn with_script(mut self, script: Option<Arc<RequestScript>>) -> Self {
+        self.script = script;
+        self
+    }
+
+    pub fn with_active_scan(mut self, active_scan: Option<ActiveScanConfig>) -> Self {
+        self.active_scan = active_scan;
+        self
+    }
+
+    pub fn with_reverify(mut self, reverify: Option<ReverifyConfig>) -> Self {
+        self.reverify = reverify;
+        self
+    }
+
+    pub fn with_email(mut self, email: Option<EmailConfig>) -> Self {
+        self.email = email.map(|config| watch::channel(config).0);
+        self
+    }
+
+    // The capture filter in force; a reload swaps in a new one between packets
+    pub fn capture_filter(&self) -> Arc<CaptureFilter> {
+        self.capture_filter.read().unwrap().clone()
+    }
+
+    pub fn set_capture_filter(&self, capture_filter: CaptureFilter) {
+        *self.capture_filter.write().unwrap() = Arc::new(capture_filter);
+    }
+
+    // Subscribers to the live feeds
+    pub fn broadcast_status(&self) -> BroadcastStatus {
+        BroadcastStatus {
+            request_subscribers: self.broadcast_tx.receiver_count(),
+            alert_subscribers: self.alert_tx.receiver_count(),
+            device_subscribers: self.device_tx.receiver_count(),
+            update_subscribers: self.update_tx.receiver_count(),
+            presence_subscribers: self.presence_tx.receiver_count(),
+        }
+    }
+
+    // Whether this instance should capture and probe (always true when standalone)
+    pub fn is_active(&self) -> bool {
+        self.cluster.as_ref().is_none_or(|cluster| cluster.is_leader())
+    }
+
+    // Run hybrid detection and record the result on the request
+    pub async fn classify(&self, request: &mut DhcpRequest) {
+        let detection = self.hybrid_detector.detect(&device_context(request)).await;
+        if let Err(e) = crate::db::evidence::record_evidence(
+            &self.db_pool,
             &request.mac_address,
-            &request.source_ip,
-            &request.fingerprint,
-            request.vendor_class.as_deref()
-        ).await;
+            &request.timestamp,
+            &detection.evidence,
+        )
+        .await
+        {
+            tracing::debug!("Failed to store detection evidence for {}: {}", request.mac_address, e);
+        }
+        apply_detection(request, detection.result);
+    }
+
+    // Run detection for a device's latest stored request again with fresh probes,
+    // at its last known address, and store the verdict as re-verified now
+    pub async fn reverify(&self, mac_address: &str) -> Result<Option<DhcpRequest>, sqlx::Error> {
+        let Some((mut latest, ip_address)) = crate::db::queries::latest_for_mac(&self.db_pool, mac_address).await? else {
+            return Ok(None);
+        };
+        let detection = {
+            let mut device = device_context(&latest);
+            if latest.site_id.is_none() {
+                device.ip_address = ip_address.as_deref().unwrap_or("");
+            }
+            self.hybrid_detector.reverify(&device).await
+        };
+        let now = chrono::Utc::now().to_rfc3339();
+        if let Err(e) = crate::db::evidence::record_evidence(&self.db_pool, mac_address, &now, &detection.evidence).await {
+            tracing::debug!("Failed to store detection evidence for {}: {}", mac_address, e);
+        }
+        apply_detection(&mut latest, detection.result);
+        crate::db::queries::record_verification(&self.db_pool, &latest, &now).await?;
+        Ok(Some(latest))
+    }
+
+    // Probe the device after the request went out with its DHCP-only detection; when
+    // that changes the verdict, update the stored row and history entry and announce it.
+    // A new device is announced here too, once the probes have had their say.
+    fn enrich_in_background(self: &Arc<Self>, request: Arc<DhcpRequest>, new_device: Option<NewDeviceEvent>) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            let mut enriched = (*request).clone();
+            state.classify(&mut enriched).await;
+            if let (Some(tracker), Some(mut event)) = (&state.new_devices, new_device) {
+                event.os_name = enriched.os_name.clone();
+                event.device_class = enriched.device_class.clone();
+                state.announce_device(tracker, event).await;
+            }
+            let verdict = |r: &DhcpRequest| {
+                (r.os_name.clone(), r.device_class.clone(), r.detection_method.clone(), r.confidence, r.smb_dialect.clone(), r.smb_build)
+            };
+            if verdict(&enriched) == verdict(&request) {
+                return;
+            }
+            tracing::debug!(
+                "Probes changed detection of {} to {:?} ({:?})",
+                enriched.mac_address, enriched.os_name, enriched.detection_method
+            );
+
+            match crate::db::queries::update_detection(&state.db_pool, &enriched).await {
+                Ok(true) => {}
+                // Held in the write buffer; the stored row keeps the DHCP-only detection
+                Ok(false) => tracing::debug!("No stored row to update for {}", enriched.mac_address),
+                Err(e) => tracing::warn!("Failed to update detection of {}: {}", enriched.mac_address, e),
+            }
+            let enriched = Arc::new(enriched);
+            {
+                let mut history = state.history.write().await;
+                if let Some(entry) = history.iter_mut().find(|entry| Arc::ptr_eq(entry, &request)) {
+                    *entry = enriched.clone();
+                }
+            }
+            let _ = state.update_tx.send(enriched);
+        });
+    }
+
+    // Process a new DHCP request (called from UDP handler)
+    pub async fn process_request(self: &Arc<Self>, mut request: DhcpRequest) -> anyhow::Result<()> {
+        // Repeats of a recent identical request are only counted, on the stored row
+        if let Some(dedup) = &self.dedup {
+            if dedup.is_repeat(&request) {
+                match crate::db::queries::record_repeat(&self.db_pool, &request).await {
+                    Ok(true) => {
+                        self.capture.record_deduplicated();
+                        self.observe(&request).await;
+                        return Ok(());
+                    }
+                    // The first request is still being classified or stored; keep this one
+                    Ok(false) => dedup.restart(&request),
+                    Err(e) => {
+                        tracing::debug!("Failed to count repeat from {}: {}", request.mac_address, e);
+                        dedup.restart(&request);
+                    }
+                }
+            }
+        }
 
-        // Update request with hybrid detection results
-        request.os_name = Some(detection_result.os_name);
-        request.device_class = Some(detection_result.device_class);
-        request.detection_method = Some(detection_result.detection_method);
-        request.confidence = Some(detection_result.confidence);
-        request.smb_dialect = detection_result.smb_dialect;
-        request.smb_build = detection_result.smb_build;
+        // 0. Run hybrid detection to enhance OS detection. Probes can take seconds, so when
+        // the device would be probed it goes out with what DHCP says and is updated later.
+        // A policy script decides on the final verdict whether the request is kept at all,
+        // so with one configured the request waits for its probes.
+        let probing = self.script.is_none() && self.hybrid_detector.probes(&device_context(&request));
+        if probing {
+            let detection = self.hybrid_detector.detect_passive(&device_context(&request)).await;
+            apply_detection(&mut request, detection.result);
+        } else {
+            self.classify(&mut request).await;
+        }
 
-        let request_arc = Arc::new(request);
+        // The site policy script sees the classified request and may drop it
+        if let Some(script) = &self.script {
+            match script.run(&request) {
+                Ok(actions) if actions.suppress => return Ok(()),
+                Ok(actions) => self.apply_script_actions(&request, actions).await,
+                Err(e) => tracing::warn!("Script failed on request from {}: {}", request.mac_address, e),
+            }
+        }
+
+        // Announce new devices, with what detection found out about them; a device still
+        // to be probed is announced when the probes are done
+        let mut new_device = None;
+        if let Some(tracker) = &self.new_devices {
+            match tracker.observe(&self.db_pool, &request).await {
+                Ok(Some(event)) if probing => new_device = Some(event),
+                Ok(Some(event)) => self.announce_device(tracker, event).await,
+                Ok(None) => {}
+                Err(e) => tracing::debug!("Failed to check whether {} is new: {}", request.mac_address, e),
+            }
+        }
+
+        let mut request_arc = Arc::new(request);
 
         // 1. Log to file (existing functionality)
         if let Err(e) = self.logger.log(&request_arc) {
@@ -108,8 +425,54 @@ impl AppState {
         }
 
         // 2. Insert to database
-        if let Err(e) = crate::db::queries::insert_request(&self.db_pool, &request_arc).await {
-            tracing::error!("Failed to insert to database: {}", e);
+        match self.db_guard.insert(&self.db_pool, request_arc.clone()).await {
+            WriteOutcome::Stored(id) => {
+                // Nothing else holds the request yet, so this doesn't copy it
+                Arc::make_mut(&mut request_arc).id = id;
+                self.alerts.resolve("db_write_failed", "dhcp_requests");
+                self.alerts.resolve("db_disk_full", "dhcp_requests");
+                self.alerts.resolve("db_corrupt", "dhcp_requests");
+            }
+            WriteOutcome::Buffered(condition, e) => {
+                self.raise_alert(
+                    condition.alert_type(),
+                    "dhcp_requests",
+                    Severity::Critical,
+                    format!("Database unwritable ({:?}), buffering requests in memory: {}", condition, e),
+                ).await;
+            }
+            WriteOutcome::Failed(e) => {
+                tracing::error!("Failed to insert to database: {}", e);
+                self.raise_alert(
+                    "db_write_failed",
+                    "dhcp_requests",
+                    Severity::Critical,
+                    format!("Failed to store DHCP requests: {}", e),
+                ).await;
+            }
+        }
+
+        // 2b. Track the device's address
+        if let Some((ip_address, source)) = &request_arc.assigned_ip {
+            match crate::db::ip_history::record_ip(
+                &self.db_pool,
+                &request_arc.mac_address,
+                ip_address,
+                source,
+                &request_arc.timestamp,
+            )
+            .await
+            {
+                Ok(Some(previous)) => tracing::info!(
+                    "Address of {} changed from {} to {}",
+                    request_arc.mac_address, previous, ip_address
+                ),
+                Ok(None) => {}
+                Err(e) => tracing::debug!("Failed to record address of {}: {}", request_arc.mac_address, e),
+            }
+            if let Some(resolver) = self.reverse_dns.as_ref().filter(|_| request_arc.site_id.is_none()) {
+                resolver.lookup_in_background(self.db_pool.clone(), request_arc.mac_address.clone(), ip_address);
+            }
         }
 
         // 3. Add to history buffer
@@ -118,42 +481,95 @@ impl AppState {
             history.push_overwrite(request_arc.clone());
         }
 
-        // 4. Update statistics
-        self.update_statistics(&request_arc).await;
+        // 4. Update statistics and look for anomalous patterns
+        self.observe(&request_arc).await;
 
         // 5. Broadcast to WebSocket clients (don't wait for receivers)
-        let _ = self.broadcast_tx.send(request_arc);
+        let _ = self.broadcast_tx.send(request_arc.clone());
+
+        // 6. Fill in what the probes find
+        if probing {
+            self.enrich_in_background(request_arc, new_device);
+        }
 
         Ok(())
     }
 
-    async fn update_statistics(&self, request: &DhcpRequest) {
-        let mut stats = self.stats.write().await;
-        let mut macs = self.unique_macs.write().await;
-
-        // Increment total
-        stats.total_requests += 1;
-
-        // Track message types
-        *stats.request_types.entry(request.message_type.clone()).or_insert(0) += 1;
-
-        // Track unique MACs
-        macs.insert(request.mac_address.clone());
-        stats.unique_macs = macs.len() as u64;
-
-        // Track vendor classes
-        if let Some(ref vendor) = request.vendor_class {
-            *stats.vendor_classes.entry(vendor.clone()).or_insert(0) += 1;
+    // Update the device's metadata and raise the alert a script asked for
+    async fn apply_script_actions(&self, request: &DhcpRequest, actions: ScriptActions) {
+        if actions.updates_meta() {
+            let mac = &request.mac_address;
+            let updated = async {
+                let mut meta = crate::db::device_meta::get_meta(&self.db_pool, mac)
+                    .await?
+                    .unwrap_or_else(|| crate::db::device_meta::DeviceMeta {
+                        mac_address: mac.clone(),
+                        ..Default::default()
+                    });
+                if actions.apply_to(&mut meta) {
+                    meta.updated_at = Utc::now().to_rfc3339();
+                    crate::db::device_meta::put_meta(&self.db_pool, &meta).await?;
+                }
+                Ok::<_, sqlx::Error>(())
+            };
+            if let Err(e) = updated.await {
+                tracing::warn!("Failed to update metadata of {} from script: {}", mac, e);
+            }
+        }
+        if let Some(message) = actions.notify.clone() {
+            self.raise_alert("script", &request.mac_address, actions.severity(), message).await;
         }
+    }
 
-        // Calculate requests per minute
-        let elapsed = (Utc::now() - self.start_time).num_seconds() as f64;
-        if elapsed > 0.0 {
-            stats.requests_per_minute = (stats.total_requests as f64) / (elapsed / 60.0);
+    // Count the request and look for anomalous patterns (deduplicated repeats included)
+    async fn observe(&self, request: &DhcpRequest) {
+        self.stats.record(request);
+        if let Some(change) = self.presence.as_ref().and_then(|presence| presence.record_request(request)) {
+            crate::presence::announce(self, change);
+        }
+        if let Some(detector) = &self.anomaly_detector {
+            let report = detector.observe(request);
+            for anomaly in report.raised {
+                self.raise_alert(anomaly.kind, &anomaly.key, anomaly.severity, anomaly.message).await;
+            }
+            for (kind, key) in report.cleared {
+                self.alerts.resolve(kind, &key);
+            }
         }
+    }
+
+    // Alert on, push and post a new device; each arrival is its own episode
+    async fn announce_device(&self, tracker: &NewDeviceTracker, event: NewDeviceEvent) {
+        self.raise_alert("new_device", &event.mac_address, Severity::Info, event.message()).await;
+        self.alerts.resolve("new_device", &event.mac_address);
+        tracker.notify_webhook(&event);
+        let _ = self.device_tx.send(event);
+    }
 
-        stats.uptime_seconds = elapsed as u64;
-        stats.last_updated = Utc::now();
+    // Raise an alert; unless the alert manager suppresses it, log, store and push it
+    pub async fn raise_alert(&self, alert_type: &str, key: &str, severity: Severity, message: String) {
+        if let Some(alert) = self.alerts.raise(alert_type, key, severity, message) {
+            let flapping = if alert.flapping { " (flapping)" } else { "" };
+            match alert.severity {
+                Severity::Critical => tracing::error!(
+                    "ALERT [{}] {}{} - seen {} times",
+                    alert.alert_type, alert.message, flapping, alert.occurrences
+                ),
+                Severity::Warning => tracing::warn!(
+                    "ALERT [{}] {}{} - seen {} times",
+                    alert.alert_type, alert.message, flapping, alert.occurrences
+                ),
+                Severity::Info => tracing::info!(
+                    "ALERT [{}] {}{} - seen {} times",
+                    alert.alert_type, alert.message, flapping, alert.occurrences
+                ),
+            }
+
+            if let Err(e) = crate::db::alerts::insert_alert(&self.db_pool, &alert).await {
+                tracing::debug!("Failed to store alert: {}", e);
+            }
+            let _ = self.alert_tx.send(alert);
+        }
     }
 
     // Get recent history (for API endpoint)
@@ -163,30 +579,200 @@ impl AppState {
     }
 
     // Search history (for filtering)
-    pub async fn search_history(
-        &self,
-        mac: Option<&str>,
-        vendor: Option<&str>,
-        msg_type: Option<&str>,
-    ) -> Vec<Arc<DhcpRequest>> {
+    pub async fn search_history(&self, filter: &HistoryFilter) -> Vec<Arc<DhcpRequest>> {
+        let filter = filter.normalized();
         let history = self.history.read().await;
 
         history.iter()
-            .filter(|req| {
-                let mac_match = mac.map_or(true, |m| req.mac_address.contains(m));
-                let vendor_match = vendor.map_or(true, |v| {
-                    req.vendor_class.as_ref().map_or(false, |vc| vc.contains(v))
-                });
-                let type_match = msg_type.map_or(true, |t| req.message_type.eq_ignore_ascii_case(t));
-
-                mac_match && vendor_match && type_match
-            })
+            .filter(|req| filter.matches(req))
             .cloned()
             .collect()
     }
 
+    // Seed statistics with counts from previous runs
+    pub async fn restore_stats(&self) -> Result<(), sqlx::Error> {
+        let totals = crate::db::stats::load_totals(&self.db_pool).await?;
+        tracing::info!(
+            "Restored statistics: {} requests from {} devices",
+            totals.total_requests,
+            totals.macs.len()
+        );
+        self.stats.seed(totals);
+        Ok(())
+    }
+
+    // Teach the anomaly detector the devices, fingerprints and vendor classes stored earlier
+    pub async fn restore_identities(&self) -> Result<(), sqlx::Error> {
+        let Some(detector) = &self.anomaly_detector else {
+            return Ok(());
+        };
+        let known = crate::db::known_devices::all_known(&self.db_pool).await?;
+        for mac in &known {
+            detector.seed_known(mac);
+        }
+        let identities = crate::db::queries::device_identities(&self.db_pool).await?;
+        for (mac, fingerprint, vendor_class) in &identities {
+            detector.seed_identity(mac, fingerprint, vendor_class.as_deref());
+        }
+        tracing::info!(
+            "Restored {} known devices and {} device fingerprints for anomaly detection",
+            known.len(),
+            identities.len()
+        );
+        Ok(())
+    }
+
+    // Pick up probe results from before a restart, so renewing hosts aren't all re-probed at once
+    pub async fn restore_probe_cache(&self) -> Result<(), sqlx::Error> {
+        let entries = crate::db::probe_cache::load(&self.db_pool).await?;
+        let restored = self.hybrid_detector.import_cache(&entries);
+        tracing::info!("Restored {} cached probe results", restored);
+        Ok(())
+    }
+
+    pub async fn save_probe_cache(&self) -> Result<(), sqlx::Error> {
+        crate::db::probe_cache::save(&self.db_pool, &self.hybrid_detector.export_cache()).await
+    }
+
     // Get current statistics
-    pub async fn get_stats(&self) -> Statistics {
-        self.stats.read().await.clone()
+    pub fn get_stats(&self) -> Statistics {
+        let mut stats = self.stats.snapshot();
+        stats.capture = self.capture.snapshot(self.listener.status().receive_errors);
+        stats
+    }
+}
+
+// What the detection sources get to see of a request
+fn device_context(request: &DhcpRequest) -> DeviceContext<'_> {
+    DeviceContext {
+        mac_address: &request.mac_address,
+        // Devices at agent sites can't be reached by active probes from here
+        ip_address: if request.site_id.is_some() { "" } else { &request.source_ip },
+        fingerprint: &request.fingerprint,
+        vendor_class: request.vendor_class.as_deref(),
+        hostname: request.hostname.as_deref(),
+        vendor_specific: request.option_data(43),
+        client_arch: request.client_arch,
+        vendor_options: &request.vendor_options,
+    }
+}
+
+fn apply_detection(request: &mut DhcpRequest, detection: DetectionResult) {
+    request.os_name = Some(detection.os_name);
+    request.device_class = Some(detection.device_class);
+    request.detection_method = Some(detection.detection_method);
+    request.confidence = Some(detection.confidence);
+    request.smb_dialect = detection.smb_dialect;
+    request.smb_build = detection.smb_build;
+}
+
+/// Filters over the in-memory history, matching what the stored-log filters offer.
+/// Text filters are case-insensitive substrings; the time range is inclusive.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    pub mac: Option<String>,
+    pub vendor: Option<String>,
+    pub msg_type: Option<String>,
+    pub hostname: Option<String>,
+    pub fingerprint: Option<String>,
+    pub os_name: Option<String>,
+    /// Exact site, or "local" for requests captured by this instance
+    pub site: Option<String>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+// Some(needle) must occur in the haystack; needles are already lowercase
+fn contains_lower(haystack: Option<&str>, needle: &Option<String>) -> bool {
+    needle
+        .as_deref()
+        .is_none_or(|needle| haystack.is_some_and(|value| value.to_lowercase().contains(needle)))
+}
+
+impl HistoryFilter {
+    /// Lowercase text filters once, MACs with ':' separators, and drop empty ones
+    pub fn normalized(&self) -> Self {
+        let lower = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_lowercase)
+        };
+        Self {
+            mac: lower(&self.mac).map(|mac| crate::mac::canonical_fragment(&mac)),
+            vendor: lower(&self.vendor),
+            msg_type: lower(&self.msg_type),
+            hostname: lower(&self.hostname),
+            fingerprint: lower(&self.fingerprint),
+            os_name: lower(&self.os_name),
+            site: lower(&self.site),
+            start: self.start,
+            end: self.end,
+        }
+    }
+
+    /// Whether the request passes; call on a `normalized` filter
+    pub fn matches(&self, req: &DhcpRequest) -> bool {
+        if self.start.is_some() || self.end.is_some() {
+            let Ok(timestamp) = DateTime::parse_from_rfc3339(&req.timestamp) else {
+                return false;
+            };
+            if self.start.is_some_and(|start| timestamp < start) || self.end.is_some_and(|end| timestamp > end) {
+                return false;
+            }
+        }
+
+        contains_lower(Some(&req.mac_address), &self.mac)
+            && contains_lower(req.vendor_class.as_deref(), &self.vendor)
+            && self.msg_type.as_deref().is_none_or(|t| req.message_type.eq_ignore_ascii_case(t))
+            && contains_lower(req.hostname.as_deref(), &self.hostname)
+            && contains_lower(Some(&req.fingerprint), &self.fingerprint)
+            && contains_lower(req.os_name.as_deref(), &self.os_name)
+            && self.site.as_deref().is_none_or(|site| match req.site_id.as_deref() {
+                None => site == crate::db::queries::LOCAL_SITE,
+                Some(id) => id.eq_ignore_ascii_case(site),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(mac: &str, timestamp: &str, os_name: Option<&str>) -> DhcpRequest {
+        DhcpRequest {
+            timestamp: timestamp.to_string(),
+            mac_address: mac.to_string(),
+            message_type: "DISCOVER".to_string(),
+            fingerprint: "1,3,6,15,31,33".to_string(),
+            os_name: os_name.map(str::to_string),
+            hostname: Some("Kitchen-PC".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_history_filter() {
+        let windows = request("aa:bb:cc:dd:ee:ff", "2025-01-01T10:00:00+00:00", Some("Windows 11"));
+        let unknown = request("11:22:33:44:55:66", "2025-01-01T12:00:00+00:00", None);
+
+        let filter = |filter: HistoryFilter| {
+            let filter = filter.normalized();
+            [&windows, &unknown].into_iter().filter(|req| filter.matches(req)).count()
+        };
+
+        assert_eq!(filter(HistoryFilter::default()), 2);
+        assert_eq!(filter(HistoryFilter { mac: Some("AA-BB-CC".to_string()), ..Default::default() }), 1);
+        assert_eq!(filter(HistoryFilter { os_name: Some("windows".to_string()), ..Default::default() }), 1);
+        assert_eq!(filter(HistoryFilter { fingerprint: Some("15,31".to_string()), ..Default::default() }), 2);
+        assert_eq!(filter(HistoryFilter { hostname: Some("kitchen".to_string()), ..Default::default() }), 2);
+        assert_eq!(filter(HistoryFilter { msg_type: Some("discover".to_string()), ..Default::default() }), 2);
+        assert_eq!(filter(HistoryFilter { site: Some("local".to_string()), ..Default::default() }), 2);
+        assert_eq!(filter(HistoryFilter { site: Some("branch-1".to_string()), ..Default::default() }), 0);
+
+        let start = DateTime::parse_from_rfc3339("2025-01-01T11:00:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(filter(HistoryFilter { start: Some(start), ..Default::default() }), 1);
+        assert_eq!(filter(HistoryFilter { end: Some(start), ..Default::default() }), 1);
     }
 }
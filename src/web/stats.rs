@@ -0,0 +1,330 @@
+use crate::dhcp::DhcpRequest;
+use chrono::{DateTime, Utc};
+use dashmap::{DashMap, DashSet};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+// Statistics snapshot served by /api/stats
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Statistics {
+    pub total_requests: u64,
+    pub request_types: HashMap<String, u64>,
+    pub unique_macs: u64,
+    /// Average rate over the last minute
+    pub requests_per_minute: f64,
+    /// Average per-minute rate over the last 5 and 15 minutes
+    pub requests_per_minute_5m: f64,
+    pub requests_per_minute_15m: f64,
+    pub last_updated: DateTime<Utc>,
+    pub uptime_seconds: u64,
+    pub vendor_classes: HashMap<String, u64>,
+    pub vlans: HashMap<u16, u64>,
+    /// Requests per attributed subnet (CIDR)
+    pub subnets: HashMap<String, u64>,
+    /// What the monitor itself lost or discarded since startup
+    pub capture: CaptureStatistics,
+}
+
+/// Packets and messages lost or discarded by the monitor, so operators can tell
+/// whether it keeps up
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct CaptureStatistics {
+    /// Datagrams read from the DHCP socket
+    pub packets_received: u64,
+    /// Errors reading from the socket
+    pub receive_errors: u64,
+    /// Packets that didn't parse as DHCP
+    pub malformed_packets: u64,
+    /// Packets that filled the receive buffer and may have been truncated; dropped
+    pub oversized_packets: u64,
+    /// Packets discarded by the `[receive] overflow` policy while the queue was full
+    pub shed_packets: u64,
+    /// Requests folded into an earlier identical one instead of being stored
+    pub deduplicated_requests: u64,
+    /// Messages live subscribers (WebSocket, gRPC, MQTT, email) missed by falling behind
+    pub broadcast_lagged: u64,
+}
+
+/// Counters behind `CaptureStatistics`; receive errors are kept by `ListenerHealth`
+#[derive(Default)]
+pub struct CaptureCounters {
+    received: AtomicU64,
+    malformed: AtomicU64,
+    oversized: AtomicU64,
+    shed: AtomicU64,
+    deduplicated: AtomicU64,
+    broadcast_lagged: AtomicU64,
+}
+
+impl CaptureCounters {
+    pub fn record_received(&self) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_malformed(&self) {
+        self.malformed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_oversized(&self) {
+        self.oversized.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_shed(&self, count: u64) {
+        self.shed.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn record_deduplicated(&self) {
+        self.deduplicated.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_lagged(&self, skipped: u64) {
+        self.broadcast_lagged.fetch_add(skipped, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self, receive_errors: u64) -> CaptureStatistics {
+        CaptureStatistics {
+            packets_received: self.received.load(Ordering::Relaxed),
+            receive_errors,
+            malformed_packets: self.malformed.load(Ordering::Relaxed),
+            oversized_packets: self.oversized.load(Ordering::Relaxed),
+            shed_packets: self.shed.load(Ordering::Relaxed),
+            deduplicated_requests: self.deduplicated.load(Ordering::Relaxed),
+            broadcast_lagged: self.broadcast_lagged.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Lock-free request counters; concurrent packets only contend on a map shard
+pub struct StatsCollector {
+    total_requests: AtomicU64,
+    rate: RateWindow,
+    request_types: DashMap<String, u64>,
+    unique_macs: DashSet<String>,
+    vendor_classes: DashMap<String, u64>,
+    vlans: DashMap<u16, u64>,
+    subnets: DashMap<String, u64>,
+    // Milliseconds since the epoch of the last recorded request
+    last_updated_ms: AtomicI64,
+    start_time: DateTime<Utc>,
+}
+
+// Increment a counter without allocating a key when it already exists
+fn increment<K: Hash + Eq + Clone>(map: &DashMap<K, u64>, key: &K) {
+    if let Some(mut count) = map.get_mut(key) {
+        *count += 1;
+        return;
+    }
+    *map.entry(key.clone()).or_insert(0) += 1;
+}
+
+/// Longest window a rate is reported over, in minutes
+const RATE_WINDOW_MINUTES: usize = 15;
+
+/// Per-minute request counts for the last RATE_WINDOW_MINUTES minutes. Each slot
+/// packs the minute (high 32 bits) and its count (low 32 bits) into one atomic,
+/// so a slot is reset for a new minute and incremented in the same update.
+struct RateWindow {
+    slots: [AtomicU64; RATE_WINDOW_MINUTES + 1],
+}
+
+impl RateWindow {
+    fn new() -> Self {
+        Self {
+            slots: std::array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+
+    fn record(&self, now_secs: i64) {
+        let minute = (now_secs / 60) as u64;
+        let slot = &self.slots[minute as usize % self.slots.len()];
+        let _ = slot.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |packed| {
+            Some(if packed >> 32 == minute & 0xffff_ffff {
+                packed.saturating_add(1)
+            } else {
+                (minute << 32) | 1
+            })
+        });
+    }
+
+    // Requests recorded in the given minute, 0 if its slot has been reused
+    fn count(&self, minute: i64) -> u64 {
+        let packed = self.slots[minute as usize % self.slots.len()].load(Ordering::Relaxed);
+        if packed >> 32 == minute as u64 & 0xffff_ffff {
+            packed & 0xffff_ffff
+        } else {
+            0
+        }
+    }
+
+    /// Average requests per minute over the last `minutes` minutes (at most
+    /// `uptime_secs`). The current partial minute counts in full and the oldest
+    /// minute in proportion to how much of it is still inside the window.
+    fn per_minute(&self, minutes: usize, now_secs: i64, uptime_secs: i64) -> f64 {
+        let minutes = minutes.min(RATE_WINDOW_MINUTES) as i64;
+        let current = now_secs / 60;
+        let elapsed = (now_secs % 60) as f64 / 60.0;
+
+        let mut total: f64 = (0..minutes).map(|back| self.count(current - back) as f64).sum();
+        total += self.count(current - minutes) as f64 * (1.0 - elapsed);
+
+        let span = (minutes as f64).min(uptime_secs as f64 / 60.0);
+        if span > 0.0 { total / span } else { 0.0 }
+    }
+}
+
+fn to_hash_map<K: Hash + Eq + Clone>(map: &DashMap<K, u64>) -> HashMap<K, u64> {
+    map.iter().map(|entry| (entry.key().clone(), *entry.value())).collect()
+}
+
+impl StatsCollector {
+    pub fn new(start_time: DateTime<Utc>) -> Self {
+        Self {
+            total_requests: AtomicU64::new(0),
+            rate: RateWindow::new(),
+            request_types: DashMap::new(),
+            unique_macs: DashSet::new(),
+            vendor_classes: DashMap::new(),
+            vlans: DashMap::new(),
+            subnets: DashMap::new(),
+            last_updated_ms: AtomicI64::new(start_time.timestamp_millis()),
+            start_time,
+        }
+    }
+
+    /// Add counts from previous runs
+    pub fn seed(&self, totals: crate::db::stats::StatsTotals) {
+        self.total_requests.fetch_add(totals.total_requests, Ordering::Relaxed);
+        for (message_type, count) in totals.request_types {
+            *self.request_types.entry(message_type).or_insert(0) += count;
+        }
+        for (vendor, count) in totals.vendor_classes {
+            *self.vendor_classes.entry(vendor).or_insert(0) += count;
+        }
+        for (vlan_id, count) in totals.vlans {
+            *self.vlans.entry(vlan_id).or_insert(0) += count;
+        }
+        for (subnet, count) in totals.subnets {
+            *self.subnets.entry(subnet).or_insert(0) += count;
+        }
+        for mac in totals.macs {
+            self.unique_macs.insert(mac);
+        }
+    }
+
+    pub fn record(&self, request: &DhcpRequest) {
+        self.total_requests.fetch_add(1, Ordering::Relaxed);
+        self.rate.record(Utc::now().timestamp());
+        increment(&self.request_types, &request.message_type);
+
+        if !self.unique_macs.contains(&request.mac_address) {
+            self.unique_macs.insert(request.mac_address.clone());
+        }
+
+        if let Some(ref vendor) = request.vendor_class {
+            increment(&self.vendor_classes, vendor);
+        }
+
+        // Only known when the capture backend sees 802.1Q tags
+        if let Some(vlan_id) = request.vlan_id {
+            increment(&self.vlans, &vlan_id);
+        }
+
+        if let Some(ref subnet) = request.subnet {
+            increment(&self.subnets, subnet);
+        }
+
+        self.last_updated_ms.fetch_max(Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    /// Point-in-time copy of the counters for serialization
+    pub fn snapshot(&self) -> Statistics {
+        let total_requests = self.total_requests.load(Ordering::Relaxed);
+        let now = Utc::now();
+        let elapsed = (now - self.start_time).num_seconds();
+        let rate = |minutes| self.rate.per_minute(minutes, now.timestamp(), elapsed);
+
+        Statistics {
+            total_requests,
+            request_types: to_hash_map(&self.request_types),
+            unique_macs: self.unique_macs.len() as u64,
+            requests_per_minute: rate(1),
+            requests_per_minute_5m: rate(5),
+            requests_per_minute_15m: rate(15),
+            last_updated: DateTime::from_timestamp_millis(self.last_updated_ms.load(Ordering::Relaxed))
+                .unwrap_or(self.start_time),
+            uptime_seconds: elapsed.max(0) as u64,
+            vendor_classes: to_hash_map(&self.vendor_classes),
+            vlans: to_hash_map(&self.vlans),
+            subnets: to_hash_map(&self.subnets),
+            capture: CaptureStatistics::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_concurrent_records() {
+        let stats = Arc::new(StatsCollector::new(Utc::now()));
+
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let stats = stats.clone();
+                std::thread::spawn(move || {
+                    for i in 0..1000 {
+                        stats.record(&DhcpRequest {
+                            mac_address: format!("00:00:00:00:{:02x}:{:02x}", t, i % 10),
+                            message_type: if i % 2 == 0 { "DISCOVER" } else { "REQUEST" }.to_string(),
+                            vendor_class: Some("MSFT 5.0".to_string()),
+                            vlan_id: Some(10),
+                            subnet: Some("10.1.20.0/24".to_string()),
+                            ..Default::default()
+                        });
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.total_requests, 8000);
+        assert_eq!(snapshot.unique_macs, 80);
+        assert_eq!(snapshot.request_types["DISCOVER"], 4000);
+        assert_eq!(snapshot.vendor_classes["MSFT 5.0"], 8000);
+        assert_eq!(snapshot.vlans[&10], 8000);
+        assert_eq!(snapshot.subnets["10.1.20.0/24"], 8000);
+    }
+
+    #[test]
+    fn test_rate_window() {
+        let window = RateWindow::new();
+        let start = 1_700_000_000 / 60 * 60;
+        // 10 requests a minute for 20 minutes, then 60 in the last minute
+        for minute in 0..20 {
+            for _ in 0..10 {
+                window.record(start + minute * 60);
+            }
+        }
+        for _ in 0..60 {
+            window.record(start + 20 * 60 + 5);
+        }
+
+        // Late in the minute, the minute before the window has almost dropped out
+        let now = start + 20 * 60 + 59;
+        let one = window.per_minute(1, now, 3600);
+        assert!((60.0..=61.0).contains(&one), "1m rate {}", one);
+        let fifteen = window.per_minute(15, now, 3600);
+        assert!((13.0..=14.5).contains(&fifteen), "15m rate {}", fifteen);
+
+        // Minutes whose slots were reused no longer count
+        assert_eq!(window.count(start), 0);
+        // Short uptime shrinks the window instead of diluting the rate
+        assert!(window.per_minute(15, now, 60) > 60.0);
+    }
+}
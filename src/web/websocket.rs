@@ -0,0 +1,262 @@
+//! Live feed to dashboard WebSocket clients.
+//!
+//! Each client gets its own bounded queue between the broadcast channels and the
+//! socket, so one slow client can't make the others lag. When a queue overflows the
+//! oldest messages are dropped and the client is told how many with
+//! `{"type": "dropped", "count": n, "total": n}`. Requests whose detection changed
+//! after they were sent, once active probes finished, are sent again as
+//! `{"type": "request_updated", "request": {...}}`, and devices going online, idle
+//! or offline as `{"type": "presence_changed", "presence": {...}}`. The server
+//! pings idle connections and closes those that stop answering.
+
+use super::state::AppState;
+use axum::extract::ws::{Message, WebSocket};
+use futures::{sink::SinkExt, stream::StreamExt};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Notify;
+use tracing::{debug, error, info, warn};
+
+/// Messages queued per client before the oldest are dropped
+pub const CLIENT_QUEUE_SIZE: usize = 512;
+/// How often the server pings the client
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+/// Clients silent (no pong or other frame) for this long are disconnected
+const IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Bounded per-client outbox that drops the oldest messages when full
+pub struct ClientQueue {
+    capacity: usize,
+    inner: Mutex<QueueState>,
+    notify: Notify,
+}
+
+#[derive(Default)]
+struct QueueState {
+    messages: VecDeque<String>,
+    /// Dropped since the client was last told
+    dropped: u64,
+    /// Dropped over the whole connection
+    total_dropped: u64,
+    closed: bool,
+}
+
+impl ClientQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::new(QueueState::default()),
+            notify: Notify::new(),
+        }
+    }
+
+    pub fn push(&self, message: String) {
+        {
+            let mut state = self.inner.lock().unwrap();
+            if state.messages.len() >= self.capacity {
+                state.messages.pop_front();
+                state.dropped += 1;
+                state.total_dropped += 1;
+            }
+            state.messages.push_back(message);
+        }
+        self.notify.notify_one();
+    }
+
+    /// Count messages lost before they reached the queue (broadcast lag)
+    pub fn record_dropped(&self, count: u64) {
+        {
+            let mut state = self.inner.lock().unwrap();
+            state.dropped += count;
+            state.total_dropped += count;
+        }
+        self.notify.notify_one();
+    }
+
+    pub fn close(&self) {
+        self.inner.lock().unwrap().closed = true;
+        self.notify.notify_one();
+    }
+
+    /// Everything queued, preceded by a drop notice when messages were lost.
+    /// None once the queue is closed and drained.
+    pub async fn next_batch(&self) -> Option<Vec<String>> {
+        loop {
+            {
+                let mut state = self.inner.lock().unwrap();
+                let mut batch = Vec::with_capacity(state.messages.len() + 1);
+                if state.dropped > 0 {
+                    batch.push(
+                        serde_json::json!({ "type": "dropped", "count": state.dropped, "total": state.total_dropped })
+                            .to_string(),
+                    );
+                    state.dropped = 0;
+                }
+                batch.extend(state.messages.drain(..));
+                if !batch.is_empty() {
+                    return Some(batch);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+pub async fn handle_websocket(socket: WebSocket, state: Arc<AppState>) {
+    let (mut sender, mut receiver) = socket.split();
+
+    // Subscribe before sending history so nothing falls in between
+    let mut rx = state.broadcast_tx.subscribe();
+    // Alerts are wrapped as {"type": "alert", "alert": {...}} to tell them apart from requests,
+    // new devices as {"type": "new_device", "device": {...}}
+    let mut alert_rx = state.alert_tx.subscribe();
+    let mut device_rx = state.device_tx.subscribe();
+    let mut update_rx = state.update_tx.subscribe();
+    let mut presence_rx = state.presence_tx.subscribe();
+
+    info!("WebSocket client connected");
+
+    // Send initial history on connection
+    let history = state.get_history(50).await;
+    for request in history {
+        let json = match serde_json::to_string(&*request) {
+            Ok(j) => j,
+            Err(e) => {
+                error!("Failed to serialize request: {}", e);
+                continue;
+            }
+        };
+
+        if sender.send(Message::Text(json)).await.is_err() {
+            warn!("Failed to send initial history to client");
+            return;
+        }
+    }
+
+    let queue = Arc::new(ClientQueue::new(CLIENT_QUEUE_SIZE));
+    let last_heard = Arc::new(AtomicI64::new(now_ms()));
+
+    // Any frame from the client (pongs included) shows it is alive
+    let heard = last_heard.clone();
+    let mut recv_task = tokio::spawn(async move {
+        while let Some(Ok(msg)) = receiver.next().await {
+            heard.store(now_ms(), Ordering::Relaxed);
+            if matches!(msg, Message::Close(_)) {
+                break;
+            }
+        }
+    });
+
+    // Move broadcasts into the client's queue; lag is counted, not fatal
+    let outbox = queue.clone();
+    let capture = state.capture.clone();
+    let mut forward_task = tokio::spawn(async move {
+        loop {
+            let received = tokio::select! {
+                request = rx.recv() => request.map(|request| serde_json::to_string(&*request)),
+                alert = alert_rx.recv() => alert.map(|alert| {
+                    serde_json::to_string(&serde_json::json!({ "type": "alert", "alert": alert }))
+                }),
+                device = device_rx.recv() => device.map(|device| {
+                    serde_json::to_string(&serde_json::json!({ "type": "new_device", "device": device }))
+                }),
+                request = update_rx.recv() => request.map(|request| {
+                    serde_json::to_string(&serde_json::json!({ "type": "request_updated", "request": request }))
+                }),
+                change = presence_rx.recv() => change.map(|change| {
+                    serde_json::to_string(&serde_json::json!({ "type": "presence_changed", "presence": change }))
+                }),
+            };
+            match received {
+                Ok(Ok(json)) => outbox.push(json),
+                Ok(Err(e)) => error!("Failed to serialize message: {}", e),
+                Err(RecvError::Lagged(skipped)) => {
+                    capture.record_lagged(skipped);
+                    outbox.record_dropped(skipped);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+        outbox.close();
+    });
+
+    // Drain the queue to the socket and keep the connection alive
+    let outbox = queue.clone();
+    let mut send_task = tokio::spawn(async move {
+        let mut ping = tokio::time::interval(PING_INTERVAL);
+        ping.tick().await;
+        loop {
+            tokio::select! {
+                batch = outbox.next_batch() => {
+                    let Some(batch) = batch else { break };
+                    for json in batch {
+                        if sender.send(Message::Text(json)).await.is_err() {
+                            // Client disconnected
+                            return;
+                        }
+                    }
+                }
+                _ = ping.tick() => {
+                    let idle = now_ms() - last_heard.load(Ordering::Relaxed);
+                    if idle > IDLE_TIMEOUT.as_millis() as i64 {
+                        debug!("Closing WebSocket client idle for {}s", idle / 1000);
+                        let _ = sender.send(Message::Close(None)).await;
+                        return;
+                    }
+                    if sender.send(Message::Ping(Vec::new())).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+
+    // Wait for either end to finish
+    tokio::select! {
+        _ = (&mut send_task) => {}
+        _ = (&mut recv_task) => {}
+        _ = (&mut forward_task) => {
+            let _ = (&mut send_task).await;
+        }
+    }
+    send_task.abort();
+    recv_task.abort();
+    forward_task.abort();
+
+    info!("WebSocket client disconnected");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_queue_drops_oldest_and_reports() {
+        let queue = ClientQueue::new(2);
+        queue.push("a".to_string());
+        queue.push("b".to_string());
+        queue.push("c".to_string());
+        queue.record_dropped(3);
+
+        let batch = queue.next_batch().await.unwrap();
+        let notice: serde_json::Value = serde_json::from_str(&batch[0]).unwrap();
+        assert_eq!(notice["type"], "dropped");
+        assert_eq!(notice["count"], 4);
+        assert_eq!(&batch[1..], ["b", "c"]);
+
+        queue.push("d".to_string());
+        queue.close();
+        assert_eq!(queue.next_batch().await.unwrap(), ["d"]);
+        assert!(queue.next_batch().await.is_none());
+    }
+}
@@ -0,0 +1,143 @@
+//! Windows hints in option 60 and option 43.
+//!
+//! Windows clients send the vendor class "MSFT 5.0" (2000 and later) or
+//! "MSFT 98" (98/ME), which says Windows but not which one. Deployment setups
+//! (task sequences, WDS and PXE boot images) often append the NT version or a
+//! build number to option 60 or carry it in option 43 sub-options, e.g.
+//! "MSFT 5.0 10.0.22631" or "PXEClient build=19045". Those builds name the
+//! release outright, which a DHCP fingerprint can't.
+//!
+//! Printers, phones and embedded DHCP clients put firmware builds in the same
+//! options, so a build only counts when the vendor class is a Microsoft or PXE
+//! one, or the text itself names WinPE.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// "10.0.22631" style NT versions, or "build 19045" / "build=19045"
+static BUILD: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)(?:\b(?:10\.0|6\.[0-3])\.|\bbuild\s*[:=]?\s*)(\d{4,5})\b").unwrap());
+
+/// Where a hint came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HintSource {
+    VendorClass,
+    VendorSpecific,
+}
+
+impl HintSource {
+    pub fn option(self) -> u8 {
+        match self {
+            Self::VendorClass => 60,
+            Self::VendorSpecific => 43,
+        }
+    }
+}
+
+/// What the client said about its Windows version
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowsHint {
+    /// A build number, naming the release
+    Build { build: u32, source: HintSource },
+    /// A Microsoft vendor class without a build: Windows, version unknown
+    Family { vendor_class: String },
+}
+
+/// Decode a hint from the option 60 vendor class and option 43 data; builds
+/// win over the bare vendor class, and option 60 over option 43
+pub fn decode(vendor_class: Option<&str>, vendor_specific: Option<&[u8]>) -> Option<WindowsHint> {
+    let windows_client = vendor_class.is_some_and(|vendor_class| {
+        vendor_class.starts_with("MSFT") || vendor_class.starts_with("PXEClient")
+    });
+    let windows_build = |text: &str| {
+        (windows_client || text.to_ascii_lowercase().contains("winpe"))
+            .then(|| find_build(text))
+            .flatten()
+    };
+
+    if let Some(build) = vendor_class.and_then(windows_build) {
+        return Some(WindowsHint::Build { build, source: HintSource::VendorClass });
+    }
+    if let Some(build) = vendor_specific.and_then(|data| texts(data).into_iter().find_map(|text| windows_build(&text))) {
+        return Some(WindowsHint::Build { build, source: HintSource::VendorSpecific });
+    }
+    vendor_class
+        .filter(|vendor_class| vendor_class.starts_with("MSFT"))
+        .map(|vendor_class| WindowsHint::Family { vendor_class: vendor_class.to_string() })
+}
+
+fn find_build(text: &str) -> Option<u32> {
+    let build: u32 = BUILD.captures(text)?[1].parse().ok()?;
+    // NT 4 ended at build 1381; anything below is a version number, not a build
+    (build >= 2195).then_some(build)
+}
+
+// Text of each option 43 sub-option, or of the whole option when it isn't
+// code-length-value encoded
+fn texts(data: &[u8]) -> Vec<String> {
+    let mut texts = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        match data[i] {
+            0 => i += 1,
+            255 => break,
+            _ => {
+                let Some(&len) = data.get(i + 1) else { break };
+                let Some(value) = data.get(i + 2..i + 2 + len as usize) else { break };
+                texts.push(String::from_utf8_lossy(value).into_owned());
+                i += 2 + len as usize;
+            }
+        }
+    }
+    if i < data.len() && data[i] != 255 || texts.is_empty() {
+        return vec![String::from_utf8_lossy(data).into_owned()];
+    }
+    texts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_hints() {
+        assert_eq!(
+            decode(Some("MSFT 5.0 10.0.22631"), None),
+            Some(WindowsHint::Build { build: 22631, source: HintSource::VendorClass })
+        );
+        assert_eq!(
+            decode(Some("MSFT 5.0"), None),
+            Some(WindowsHint::Family { vendor_class: "MSFT 5.0".to_string() })
+        );
+        assert_eq!(decode(Some("android-dhcp-14"), None), None);
+        assert!(matches!(decode(Some("MSFT 5.0 build 7"), None), Some(WindowsHint::Family { .. })));
+
+        // Sub-option 1 of a PXE boot image's option 43
+        let mut option_43 = vec![1, 11];
+        option_43.extend_from_slice(b"build=19045");
+        option_43.push(255);
+        assert_eq!(
+            decode(Some("PXEClient:Arch:00007:UNDI:003016"), Some(&option_43)),
+            Some(WindowsHint::Build { build: 19045, source: HintSource::VendorSpecific })
+        );
+        // Unstructured text
+        assert_eq!(
+            decode(None, Some(b"WinPE 10.0.26100")),
+            Some(WindowsHint::Build { build: 26100, source: HintSource::VendorSpecific })
+        );
+    }
+
+    #[test]
+    fn test_ignores_builds_from_other_clients() {
+        assert_eq!(decode(Some("udhcp 1.36.1 build 19045"), None), None);
+        assert_eq!(decode(Some("Cisco Systems, Inc. IP Phone CP-8841 build=12041"), None), None);
+        // Printer firmware in option 43
+        assert_eq!(decode(Some("Hewlett-Packard JetDirect"), Some(b"FW build=20231")), None);
+        assert_eq!(decode(None, Some(b"firmware 10.0.19045")), None);
+        // A WinPE image says what it is even without a vendor class we know
+        assert_eq!(
+            decode(Some("udhcp 1.36.1"), Some(b"WinPE build 22621")),
+            Some(WindowsHint::Build { build: 22621, source: HintSource::VendorSpecific })
+        );
+    }
+}